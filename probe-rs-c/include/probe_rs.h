@@ -0,0 +1,177 @@
+/* The C API of probe-rs. This file is generated by cbindgen, do not edit it. */
+
+#ifndef PROBE_RS_H
+#define PROBE_RS_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of a call.
+typedef enum ProbeRsStatus {
+  // The call succeeded.
+  PROBE_RS_STATUS_OK = 0,
+  // The call failed, [`probe_rs_last_error`] describes why.
+  PROBE_RS_STATUS_ERROR = -1,
+  // A pointer argument was null.
+  PROBE_RS_STATUS_INVALID_ARGUMENT = -2,
+} ProbeRsStatus;
+
+// The format of a file which is flashed with [`probe_rs_download_file`].
+typedef enum ProbeRsFormat {
+  PROBE_RS_FORMAT_ELF = 0,
+  // Intel HEX.
+  PROBE_RS_FORMAT_HEX = 1,
+  // A binary, which is flashed to the given base address.
+  PROBE_RS_FORMAT_BIN = 2,
+} ProbeRsFormat;
+
+// A list of the connected debug probes.
+typedef struct ProbeRsProbeList ProbeRsProbeList;
+
+// A session on a target, which is closed with [`probe_rs_session_close`].
+typedef struct ProbeRsSession ProbeRsSession;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Copy the description of the last failure of the calling thread into `buffer`, as a null-terminated string which is
+// truncated to `length` bytes.
+//
+// Returns the length of the whole description without the null byte, or zero if no call failed yet. `buffer` may be
+// null to query the length.
+//
+// # Safety
+//
+// `buffer` must be null or point to at least `length` writable bytes.
+size_t probe_rs_last_error(char *buffer, size_t length);
+
+// List the connected debug probes. The list is freed with [`probe_rs_probe_list_free`].
+ProbeRsProbeList *probe_rs_probe_list_new(void);
+
+// The number of probes in the list.
+//
+// # Safety
+//
+// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+size_t probe_rs_probe_list_len(const ProbeRsProbeList *list);
+
+// The name of the probe at `index`, or null if `index` is out of range. The string is valid until the list is freed.
+//
+// # Safety
+//
+// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+const char *probe_rs_probe_list_identifier(const ProbeRsProbeList *list,
+                                           size_t index);
+
+// The selector of the probe at `index`, `VID:PID[:SERIAL]`, which [`probe_rs_session_open`] opens. Returns null if
+// `index` is out of range. The string is valid until the list is freed.
+//
+// # Safety
+//
+// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+const char *probe_rs_probe_list_selector(const ProbeRsProbeList *list,
+                                         size_t index);
+
+// Free a list of probes.
+//
+// # Safety
+//
+// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+void probe_rs_probe_list_free(ProbeRsProbeList *list);
+
+// Open the probe with the selector `probe`, or the first probe if it is null, and attach to the target `chip`, e.g.
+// `nRF52840_xxAA`.
+//
+// `speed_khz` sets the speed of the probe, zero keeps its default. Returns null on failure.
+//
+// # Safety
+//
+// `probe` must be null or point to a null-terminated string, and `chip` must point to a null-terminated string.
+ProbeRsSession *probe_rs_session_open(const char *probe,
+                                      const char *chip,
+                                      uint32_t speed_khz);
+
+// Close a session, which detaches from the target and closes the probe.
+//
+// # Safety
+//
+// `session` must be null or a session from [`probe_rs_session_open`] which was not closed.
+void probe_rs_session_close(ProbeRsSession *session);
+
+// Read `length` bytes at `address` of the memory of core `core` into `data`.
+//
+// # Safety
+//
+// `session` must be a session from [`probe_rs_session_open`] which was not closed, and `data` must point to at least
+// `length` writable bytes.
+ProbeRsStatus probe_rs_read_memory(ProbeRsSession *session,
+                                   uint32_t core,
+                                   uint64_t address,
+                                   uint8_t *data,
+                                   size_t length);
+
+// Write `length` bytes from `data` to `address` of the memory of core `core`.
+//
+// # Safety
+//
+// `session` must be a session from [`probe_rs_session_open`] which was not closed, and `data` must point to at least
+// `length` readable bytes.
+ProbeRsStatus probe_rs_write_memory(ProbeRsSession *session,
+                                    uint32_t core,
+                                    uint64_t address,
+                                    const uint8_t *data,
+                                    size_t length);
+
+// Flash the file at `path`. `base_address` is the address which a binary is flashed to, it is ignored for the other
+// formats.
+//
+// # Safety
+//
+// `session` must be a session from [`probe_rs_session_open`] which was not closed, and `path` must point to a
+// null-terminated string.
+ProbeRsStatus probe_rs_download_file(ProbeRsSession *session,
+                                     const char *path,
+                                     ProbeRsFormat format,
+                                     uint64_t base_address);
+
+// Reset core `core`, and let it run.
+//
+// # Safety
+//
+// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+ProbeRsStatus probe_rs_reset(ProbeRsSession *session, uint32_t core);
+
+// Reset core `core`, and halt it before it runs the first instruction.
+//
+// # Safety
+//
+// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+ProbeRsStatus probe_rs_reset_and_halt(ProbeRsSession *session,
+                                      uint32_t core,
+                                      uint32_t timeout_ms);
+
+// Halt core `core`, waiting up to `timeout_ms` for it to halt.
+//
+// # Safety
+//
+// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+ProbeRsStatus probe_rs_halt(ProbeRsSession *session,
+                            uint32_t core,
+                            uint32_t timeout_ms);
+
+// Resume core `core`.
+//
+// # Safety
+//
+// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+ProbeRsStatus probe_rs_run(ProbeRsSession *session, uint32_t core);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif // __cplusplus
+
+#endif /* PROBE_RS_H */
@@ -0,0 +1,432 @@
+//! C bindings for probe-rs.
+//!
+//! The functions list the probes, open a session on a target, access its memory, flash it, and reset, halt and run
+//! its cores. They return a [`ProbeRsStatus`] or a null pointer on failure, and [`probe_rs_last_error`] describes the
+//! last failure of the calling thread. Sessions and probe lists are opaque handles, which are freed by the functions
+//! ending in `_close` and `_free`.
+//!
+//! The header `include/probe_rs.h` is generated from this file with `cbindgen`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use probe_rs::flashing::{self, BinOptions, Format};
+use probe_rs::{DebugProbeSelector, MemoryInterface, Permissions, Probe, Session};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The result of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeRsStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// The call failed, [`probe_rs_last_error`] describes why.
+    Error = -1,
+    /// A pointer argument was null.
+    InvalidArgument = -2,
+}
+
+/// The format of a file which is flashed with [`probe_rs_download_file`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeRsFormat {
+    Elf = 0,
+    /// Intel HEX.
+    Hex = 1,
+    /// A binary, which is flashed to the given base address.
+    Bin = 2,
+}
+
+/// A list of the connected debug probes.
+pub struct ProbeRsProbeList {
+    probes: Vec<(CString, CString)>,
+}
+
+/// A session on a target, which is closed with [`probe_rs_session_close`].
+pub struct ProbeRsSession {
+    session: Session,
+}
+
+fn set_last_error(error: &anyhow::Error) {
+    // The messages of the error chain cannot contain a null byte.
+    let message = format!("{error:#}").replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+/// Run `f`, storing its error or panic for [`probe_rs_last_error`].
+fn call<T>(f: impl FnOnce() -> anyhow::Result<T>) -> Option<T> {
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(anyhow!("probe-rs panicked")));
+    result.map_err(|error| set_last_error(&error)).ok()
+}
+
+fn status(f: impl FnOnce() -> anyhow::Result<()>) -> ProbeRsStatus {
+    match call(f) {
+        Some(()) => ProbeRsStatus::Ok,
+        None => ProbeRsStatus::Error,
+    }
+}
+
+/// # Safety
+///
+/// `string` must be null or point to a null-terminated string.
+unsafe fn optional_str<'a>(string: *const c_char) -> anyhow::Result<Option<&'a str>> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    let string = CStr::from_ptr(string)
+        .to_str()
+        .context("The string is not valid UTF-8")?;
+    Ok(Some(string))
+}
+
+/// Copy the description of the last failure of the calling thread into `buffer`, as a null-terminated string which is
+/// truncated to `length` bytes.
+///
+/// Returns the length of the whole description without the null byte, or zero if no call failed yet. `buffer` may be
+/// null to query the length.
+///
+/// # Safety
+///
+/// `buffer` must be null or point to at least `length` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_last_error(buffer: *mut c_char, length: usize) -> usize {
+    LAST_ERROR.with(|last| {
+        let last = last.borrow();
+        let Some(message) = last.as_ref() else {
+            return 0;
+        };
+        let message = message.as_bytes();
+        if !buffer.is_null() && length > 0 {
+            let copied = message.len().min(length - 1);
+            ptr::copy_nonoverlapping(message.as_ptr().cast(), buffer, copied);
+            *buffer.add(copied) = 0;
+        }
+        message.len()
+    })
+}
+
+/// List the connected debug probes. The list is freed with [`probe_rs_probe_list_free`].
+#[no_mangle]
+pub extern "C" fn probe_rs_probe_list_new() -> *mut ProbeRsProbeList {
+    let probes = call(|| {
+        Ok(Probe::list_all()
+            .iter()
+            .filter_map(|info| {
+                let identifier = CString::new(info.identifier.clone()).ok()?;
+                let selector = CString::new(DebugProbeSelector::from(info).to_string()).ok()?;
+                Some((identifier, selector))
+            })
+            .collect())
+    })
+    .unwrap_or_default();
+    Box::into_raw(Box::new(ProbeRsProbeList { probes }))
+}
+
+/// The number of probes in the list.
+///
+/// # Safety
+///
+/// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_probe_list_len(list: *const ProbeRsProbeList) -> usize {
+    list.as_ref().map_or(0, |list| list.probes.len())
+}
+
+/// The name of the probe at `index`, or null if `index` is out of range. The string is valid until the list is freed.
+///
+/// # Safety
+///
+/// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_probe_list_identifier(
+    list: *const ProbeRsProbeList,
+    index: usize,
+) -> *const c_char {
+    list.as_ref()
+        .and_then(|list| list.probes.get(index))
+        .map_or(ptr::null(), |(identifier, _)| identifier.as_ptr())
+}
+
+/// The selector of the probe at `index`, `VID:PID[:SERIAL]`, which [`probe_rs_session_open`] opens. Returns null if
+/// `index` is out of range. The string is valid until the list is freed.
+///
+/// # Safety
+///
+/// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_probe_list_selector(
+    list: *const ProbeRsProbeList,
+    index: usize,
+) -> *const c_char {
+    list.as_ref()
+        .and_then(|list| list.probes.get(index))
+        .map_or(ptr::null(), |(_, selector)| selector.as_ptr())
+}
+
+/// Free a list of probes.
+///
+/// # Safety
+///
+/// `list` must be null or a list from [`probe_rs_probe_list_new`] which was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_probe_list_free(list: *mut ProbeRsProbeList) {
+    if !list.is_null() {
+        drop(Box::from_raw(list));
+    }
+}
+
+/// Open the probe with the selector `probe`, or the first probe if it is null, and attach to the target `chip`, e.g.
+/// `nRF52840_xxAA`.
+///
+/// `speed_khz` sets the speed of the probe, zero keeps its default. Returns null on failure.
+///
+/// # Safety
+///
+/// `probe` must be null or point to a null-terminated string, and `chip` must point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_session_open(
+    probe: *const c_char,
+    chip: *const c_char,
+    speed_khz: u32,
+) -> *mut ProbeRsSession {
+    let session = call(|| {
+        let chip = optional_str(chip)?.context("No chip was given")?;
+        let mut probe = match optional_str(probe)? {
+            Some(selector) => Probe::open(DebugProbeSelector::try_from(selector)?)?,
+            None => Probe::list_all()
+                .first()
+                .context("No probe was found")?
+                .open()?,
+        };
+        if speed_khz != 0 {
+            probe.set_speed(speed_khz)?;
+        }
+        Ok(probe.attach(chip, Permissions::default())?)
+    });
+
+    session.map_or(ptr::null_mut(), |session| {
+        Box::into_raw(Box::new(ProbeRsSession { session }))
+    })
+}
+
+/// Close a session, which detaches from the target and closes the probe.
+///
+/// # Safety
+///
+/// `session` must be null or a session from [`probe_rs_session_open`] which was not closed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_session_close(session: *mut ProbeRsSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// # Safety
+///
+/// `session` must be null or a session from [`probe_rs_session_open`] which was not closed.
+unsafe fn with_session(
+    session: *mut ProbeRsSession,
+    f: impl FnOnce(&mut Session) -> anyhow::Result<()>,
+) -> ProbeRsStatus {
+    match session.as_mut() {
+        Some(session) => status(|| f(&mut session.session)),
+        None => ProbeRsStatus::InvalidArgument,
+    }
+}
+
+/// Read `length` bytes at `address` of the memory of core `core` into `data`.
+///
+/// # Safety
+///
+/// `session` must be a session from [`probe_rs_session_open`] which was not closed, and `data` must point to at least
+/// `length` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_read_memory(
+    session: *mut ProbeRsSession,
+    core: u32,
+    address: u64,
+    data: *mut u8,
+    length: usize,
+) -> ProbeRsStatus {
+    if data.is_null() && length > 0 {
+        return ProbeRsStatus::InvalidArgument;
+    }
+    with_session(session, |session| {
+        if length == 0 {
+            return Ok(());
+        }
+        let data = std::slice::from_raw_parts_mut(data, length);
+        Ok(session.core(core as usize)?.read(address, data)?)
+    })
+}
+
+/// Write `length` bytes from `data` to `address` of the memory of core `core`.
+///
+/// # Safety
+///
+/// `session` must be a session from [`probe_rs_session_open`] which was not closed, and `data` must point to at least
+/// `length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_write_memory(
+    session: *mut ProbeRsSession,
+    core: u32,
+    address: u64,
+    data: *const u8,
+    length: usize,
+) -> ProbeRsStatus {
+    if data.is_null() && length > 0 {
+        return ProbeRsStatus::InvalidArgument;
+    }
+    with_session(session, |session| {
+        if length == 0 {
+            return Ok(());
+        }
+        let data = std::slice::from_raw_parts(data, length);
+        let mut core = session.core(core as usize)?;
+        core.write_8(address, data)?;
+        Ok(core.flush()?)
+    })
+}
+
+/// Flash the file at `path`. `base_address` is the address which a binary is flashed to, it is ignored for the other
+/// formats.
+///
+/// # Safety
+///
+/// `session` must be a session from [`probe_rs_session_open`] which was not closed, and `path` must point to a
+/// null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_download_file(
+    session: *mut ProbeRsSession,
+    path: *const c_char,
+    format: ProbeRsFormat,
+    base_address: u64,
+) -> ProbeRsStatus {
+    if path.is_null() {
+        return ProbeRsStatus::InvalidArgument;
+    }
+    with_session(session, |session| {
+        let path = optional_str(path)?.context("No path was given")?;
+        let format = match format {
+            ProbeRsFormat::Elf => Format::default(),
+            ProbeRsFormat::Hex => Format::Hex,
+            ProbeRsFormat::Bin => Format::Bin(BinOptions {
+                base_address: Some(base_address),
+                skip: 0,
+            }),
+        };
+        Ok(flashing::download_file(session, Path::new(path), format)?)
+    })
+}
+
+/// Reset core `core`, and let it run.
+///
+/// # Safety
+///
+/// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_reset(session: *mut ProbeRsSession, core: u32) -> ProbeRsStatus {
+    with_session(session, |session| {
+        Ok(session.core(core as usize)?.reset()?)
+    })
+}
+
+/// Reset core `core`, and halt it before it runs the first instruction.
+///
+/// # Safety
+///
+/// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_reset_and_halt(
+    session: *mut ProbeRsSession,
+    core: u32,
+    timeout_ms: u32,
+) -> ProbeRsStatus {
+    with_session(session, |session| {
+        let timeout = Duration::from_millis(timeout_ms.into());
+        session.core(core as usize)?.reset_and_halt(timeout)?;
+        Ok(())
+    })
+}
+
+/// Halt core `core`, waiting up to `timeout_ms` for it to halt.
+///
+/// # Safety
+///
+/// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_halt(
+    session: *mut ProbeRsSession,
+    core: u32,
+    timeout_ms: u32,
+) -> ProbeRsStatus {
+    with_session(session, |session| {
+        let timeout = Duration::from_millis(timeout_ms.into());
+        session.core(core as usize)?.halt(timeout)?;
+        Ok(())
+    })
+}
+
+/// Resume core `core`.
+///
+/// # Safety
+///
+/// `session` must be a session from [`probe_rs_session_open`] which was not closed.
+#[no_mangle]
+pub unsafe extern "C" fn probe_rs_run(session: *mut ProbeRsSession, core: u32) -> ProbeRsStatus {
+    with_session(session, |session| Ok(session.core(core as usize)?.run()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe {
+            let length = probe_rs_last_error(ptr::null_mut(), 0);
+            let mut buffer = vec![0 as c_char; length + 1];
+            probe_rs_last_error(buffer.as_mut_ptr(), buffer.len());
+            CStr::from_ptr(buffer.as_ptr()).to_str().unwrap().to_owned()
+        }
+    }
+
+    #[test]
+    fn failures_are_described_by_the_last_error() {
+        let session = unsafe { probe_rs_session_open(ptr::null(), ptr::null(), 0) };
+        assert!(session.is_null());
+        assert_eq!(last_error(), "No chip was given");
+
+        // The description is truncated to the buffer.
+        let mut buffer = [1 as c_char; 3];
+        let length = unsafe { probe_rs_last_error(buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(length, "No chip was given".len());
+        assert_eq!(buffer, [b'N' as c_char, b'o' as c_char, 0]);
+    }
+
+    #[test]
+    fn null_handles_are_invalid_arguments() {
+        let mut data = [0; 4];
+        unsafe {
+            assert_eq!(
+                probe_rs_read_memory(ptr::null_mut(), 0, 0x2000_0000, data.as_mut_ptr(), 4),
+                ProbeRsStatus::InvalidArgument
+            );
+            assert_eq!(
+                probe_rs_run(ptr::null_mut(), 0),
+                ProbeRsStatus::InvalidArgument
+            );
+            assert_eq!(probe_rs_probe_list_len(ptr::null()), 0);
+            assert!(probe_rs_probe_list_selector(ptr::null(), 0).is_null());
+            probe_rs_session_close(ptr::null_mut());
+        }
+    }
+}
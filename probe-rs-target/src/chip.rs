@@ -1,12 +1,15 @@
-use super::memory::MemoryRegion;
-use crate::{serialize::hex_option, CoreType};
+use super::memory::{MemoryAlias, MemoryRegion, Partition};
+use crate::{
+    serialize::{hex_option, hex_u_int},
+    CoreType,
+};
 use serde::{Deserialize, Serialize};
 /// A single chip variant.
 ///
 /// This describes an exact chip variant, including the cores, flash and memory size. For example,
 /// the `nRF52832` chip has two variants, `nRF52832_xxAA` and `nRF52832_xxBB`. For this case,
 /// the struct will correspond to one of the variants, e.g. `nRF52832_xxAA`.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Chip {
     /// This is the name of the chip in base form.
     /// E.g. `nRF52832`.
@@ -27,6 +30,25 @@ pub struct Chip {
     /// [`ChipFamily::flash_algorithms`]: crate::ChipFamily::flash_algorithms
     #[serde(default)]
     pub flash_algorithms: Vec<String>,
+    /// Where the unique device ID of the chip can be read from memory, if it has one.
+    #[serde(default)]
+    pub unique_id: Option<UniqueIdLocation>,
+    /// Named partitions of the memory map, like the bootloader and the slots of the firmware images.
+    #[serde(default)]
+    pub partitions: Vec<Partition>,
+    /// Address ranges which mirror parts of the memory map, like flash which is also mapped at address 0.
+    #[serde(default)]
+    pub aliases: Vec<MemoryAlias>,
+}
+
+/// The location of the unique device ID in the memory of a chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UniqueIdLocation {
+    /// The address of the first byte of the ID.
+    #[serde(serialize_with = "hex_u_int")]
+    pub address: u64,
+    /// The length of the ID in bytes.
+    pub size: u32,
 }
 
 impl Chip {
@@ -44,12 +66,15 @@ impl Chip {
             }],
             memory_map: vec![],
             flash_algorithms: vec![],
+            unique_id: None,
+            partitions: vec![],
+            aliases: vec![],
         }
     }
 }
 
 /// An individual core inside a chip
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Core {
     /// The core name.
     pub name: String,
@@ -64,7 +89,7 @@ pub struct Core {
 }
 
 /// The data required to access a core
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CoreAccessOptions {
     /// Arm specific options
     Arm(ArmCoreAccessOptions),
@@ -73,7 +98,7 @@ pub enum CoreAccessOptions {
 }
 
 /// The data required to access an ARM core
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct ArmCoreAccessOptions {
     /// The access port number to access the core
     pub ap: u8,
@@ -90,5 +115,5 @@ pub struct ArmCoreAccessOptions {
 }
 
 /// The data required to access a Risc-V core
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RiscvCoreAccessOptions {}
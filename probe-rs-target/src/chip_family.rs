@@ -1,4 +1,4 @@
-use crate::CoreAccessOptions;
+use crate::{CoreAccessOptions, MemoryRange};
 
 use super::chip::Chip;
 use super::flash_algorithm::RawFlashAlgorithm;
@@ -185,6 +185,75 @@ impl ChipFamily {
                 ));
             }
 
+            for (i, partition) in variant.partitions.iter().enumerate() {
+                if partition.range.is_empty() {
+                    return Err(format!(
+                        "partition `{}` of variant `{}` is empty",
+                        partition.name, variant.name
+                    ));
+                }
+                for other in &variant.partitions[..i] {
+                    if other.name == partition.name {
+                        return Err(format!(
+                            "partition `{}` of variant `{}` is defined twice",
+                            partition.name, variant.name
+                        ));
+                    }
+                    if other.range.intersects_range(&partition.range) {
+                        return Err(format!(
+                            "partitions `{}` and `{}` of variant `{}` overlap",
+                            other.name, partition.name, variant.name
+                        ));
+                    }
+                }
+            }
+
+            for alias in &variant.aliases {
+                let name = alias.name.as_deref().unwrap_or("unnamed");
+                if alias.range.is_empty() {
+                    return Err(format!(
+                        "alias `{name}` of variant `{}` is empty",
+                        variant.name
+                    ));
+                }
+                if variant
+                    .memory_map
+                    .iter()
+                    .any(|region| region.range().intersects_range(&alias.range))
+                {
+                    return Err(format!(
+                        "alias `{name}` of variant `{}` overlaps the memory map",
+                        variant.name
+                    ));
+                }
+                let target = alias.target_range();
+                if !variant
+                    .memory_map
+                    .iter()
+                    .any(|region| region.range().contains_range(&target))
+                {
+                    return Err(format!(
+                        "alias `{name}` of variant `{}` mirrors {:#010x}..{:#010x}, which is not part of a memory region",
+                        variant.name, target.start, target.end
+                    ));
+                }
+            }
+
+            // Every core sees its own memory map, which is made of the regions it can access.
+            for region in &variant.memory_map {
+                if let Some(core) = region
+                    .cores()
+                    .iter()
+                    .find(|core| !variant.cores.iter().any(|c| &c.name == *core))
+                {
+                    let range = region.range();
+                    return Err(format!(
+                        "memory region {:#010x}..{:#010x} of variant `{}` is accessed by the unknown core `{core}`",
+                        range.start, range.end, variant.name
+                    ));
+                }
+            }
+
             // Core specific validation logic based on type
             for core in variant.cores.iter() {
                 // The core access options must match the core type specified
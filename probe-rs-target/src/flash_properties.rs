@@ -1,5 +1,5 @@
 use super::memory::SectorDescription;
-use crate::serialize::{hex_range, hex_u_int};
+use crate::serialize::{hex_option, hex_range, hex_u_int};
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
 
@@ -26,6 +26,13 @@ pub struct FlashProperties {
     /// The available sectors of the device flash.
     #[serde(default)]
     pub sectors: Vec<SectorDescription>,
+    /// The size of the words in which the flash is programmed, if it is wider than a few bytes or protected by
+    /// ECC, e.g. 32 bytes on the STM32H7 and 8 bytes on the STM32L4.
+    ///
+    /// Every word is programmed once and as a whole, because programming it in parts or again before it is erased
+    /// fails or causes ECC errors.
+    #[serde(default, serialize_with = "hex_option")]
+    pub program_granularity: Option<u32>,
 }
 
 impl Default for FlashProperties {
@@ -38,6 +45,7 @@ impl Default for FlashProperties {
             program_page_timeout: 0,
             erase_sector_timeout: 0,
             sectors: vec![],
+            program_granularity: None,
         }
     }
 }
@@ -18,13 +18,15 @@ mod flash_properties;
 mod memory;
 pub(crate) mod serialize;
 
-pub use chip::{ArmCoreAccessOptions, Chip, Core, CoreAccessOptions, RiscvCoreAccessOptions};
+pub use chip::{
+    ArmCoreAccessOptions, Chip, Core, CoreAccessOptions, RiscvCoreAccessOptions, UniqueIdLocation,
+};
 pub use chip_family::{
     Architecture, ChipFamily, CoreType, InstructionSet, TargetDescriptionSource,
 };
 pub use flash_algorithm::RawFlashAlgorithm;
 pub use flash_properties::FlashProperties;
 pub use memory::{
-    GenericRegion, MemoryRange, MemoryRegion, NvmRegion, PageInfo, RamRegion, SectorDescription,
-    SectorInfo,
+    write_protected_region, GenericRegion, MemoryAlias, MemoryAliasKind, MemoryRange, MemoryRegion,
+    NvmRegion, PageInfo, Partition, PartitionKind, RamRegion, SectorDescription, SectorInfo,
 };
@@ -15,6 +15,11 @@ pub struct NvmRegion {
     pub is_boot_memory: bool,
     /// List of cores that can access this region
     pub cores: Vec<String>,
+    /// True if the region is one-time programmable (OTP), so that every bit can only be programmed once.
+    ///
+    /// Such regions are never erased, and are only written when this is explicitly allowed.
+    #[serde(default)]
+    pub is_otp: bool,
 }
 
 impl NvmRegion {
@@ -51,6 +56,106 @@ pub struct GenericRegion {
     pub range: Range<u64>,
     /// List of cores that can access this region
     pub cores: Vec<String>,
+    /// True if the region can only be read, e.g. a ROM.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// The use of a [`Partition`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionKind {
+    /// A bootloader, which starts the image in a slot.
+    Bootloader,
+    /// A slot of a firmware image. The first slot is the primary slot (A), from which the image is started, and the
+    /// second one the secondary slot (B), into which updates are written.
+    Slot,
+    /// Data of the firmware, e.g. a filesystem or a key-value store.
+    Storage,
+    /// Any other use.
+    #[default]
+    Other,
+}
+
+/// A named part of the memory map, e.g. the bootloader, an image slot or a storage area, which overlays the memory
+/// regions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Partition {
+    /// The name of the partition, e.g. `slot0`.
+    pub name: String,
+    /// Address range of the partition
+    #[serde(serialize_with = "hex_range")]
+    pub range: Range<u64>,
+    /// The use of the partition.
+    #[serde(default)]
+    pub kind: PartitionKind,
+}
+
+/// How the addresses of a [`MemoryAlias`] map to the memory they mirror.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryAliasKind {
+    /// Every byte of the alias mirrors one byte of the memory, e.g. flash which is also mapped at address 0.
+    #[default]
+    Mirror,
+    /// Every word of the alias mirrors one bit of the memory, like the bit-band aliases of Cortex-M3 and Cortex-M4.
+    BitBand,
+}
+
+/// A range of addresses which mirrors a part of the memory map under another address.
+///
+/// Data and code can be linked at either address, so addresses are translated to the memory map with
+/// [`MemoryAlias::canonical_address`] before they are compared.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MemoryAlias {
+    /// A name to describe the alias
+    pub name: Option<String>,
+    /// Address range of the alias
+    #[serde(serialize_with = "hex_range")]
+    pub range: Range<u64>,
+    /// The address in the memory map which the start of the alias mirrors.
+    #[serde(serialize_with = "hex_u_int")]
+    pub target: u64,
+    /// How the alias maps to the memory.
+    #[serde(default)]
+    pub kind: MemoryAliasKind,
+}
+
+impl MemoryAlias {
+    /// The range of the memory map which the alias mirrors.
+    pub fn target_range(&self) -> Range<u64> {
+        let size = self.range.end - self.range.start;
+        match self.kind {
+            MemoryAliasKind::Mirror => self.target..self.target + size,
+            MemoryAliasKind::BitBand => self.target..self.target + size / 32,
+        }
+    }
+
+    /// The address in the memory map which `address` of the alias mirrors, or `None` if the address is not part
+    /// of the alias. For a bit-band alias, this is the address of the byte which contains the bit.
+    pub fn canonical_address(&self, address: u64) -> Option<u64> {
+        if !self.range.contains(&address) {
+            return None;
+        }
+        let offset = address - self.range.start;
+        Some(match self.kind {
+            MemoryAliasKind::Mirror => self.target + offset,
+            MemoryAliasKind::BitBand => self.target + offset / 32,
+        })
+    }
+
+    /// The address of the alias which mirrors `address` of the memory map, or `None` if the address is not
+    /// mirrored. For a bit-band alias, this is the address of bit 0 of the byte.
+    pub fn alias_address(&self, address: u64) -> Option<u64> {
+        if !self.target_range().contains(&address) {
+            return None;
+        }
+        let offset = address - self.target;
+        Some(match self.kind {
+            MemoryAliasKind::Mirror => self.range.start + offset,
+            MemoryAliasKind::BitBand => self.range.start + offset * 32,
+        })
+    }
 }
 
 /// Holds information about a specific, individual flash
@@ -142,10 +247,91 @@ pub enum MemoryRegion {
     Nvm(NvmRegion),
 }
 
+impl MemoryRegion {
+    /// The address range of the region.
+    pub fn range(&self) -> &Range<u64> {
+        match self {
+            MemoryRegion::Ram(region) => &region.range,
+            MemoryRegion::Generic(region) => &region.range,
+            MemoryRegion::Nvm(region) => &region.range,
+        }
+    }
+
+    /// The names of the cores which can access the region.
+    pub fn cores(&self) -> &[String] {
+        match self {
+            MemoryRegion::Ram(region) => &region.cores,
+            MemoryRegion::Generic(region) => &region.cores,
+            MemoryRegion::Nvm(region) => &region.cores,
+        }
+    }
+
+    /// True if the region must not be written with plain memory writes: non-volatile memory, which is only written
+    /// by flashing, and regions which are marked as read-only.
+    pub fn is_write_protected(&self) -> bool {
+        match self {
+            MemoryRegion::Ram(_) => false,
+            MemoryRegion::Generic(region) => region.read_only,
+            MemoryRegion::Nvm(_) => true,
+        }
+    }
+}
+
+/// The first region of `memory_map` which protects a part of `range` from memory writes, see
+/// [`MemoryRegion::is_write_protected`].
+pub fn write_protected_region<'a>(
+    memory_map: &'a [MemoryRegion],
+    range: &Range<u64>,
+) -> Option<&'a MemoryRegion> {
+    memory_map.iter().find(|region| {
+        region.is_write_protected()
+            && region.range().start < range.end
+            && range.start < region.range().end
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn flash_and_read_only_regions_are_write_protected() {
+        let memory_map = [
+            MemoryRegion::Nvm(NvmRegion {
+                name: Some("FLASH".to_string()),
+                range: 0x0800_0000..0x0810_0000,
+                is_boot_memory: true,
+                cores: vec!["main".to_string()],
+                is_otp: false,
+            }),
+            MemoryRegion::Ram(RamRegion {
+                name: Some("SRAM".to_string()),
+                range: 0x2000_0000..0x2002_0000,
+                is_boot_memory: false,
+                cores: vec!["main".to_string()],
+            }),
+            MemoryRegion::Generic(GenericRegion {
+                name: Some("ROM".to_string()),
+                range: 0x1fff_0000..0x1fff_8000,
+                cores: vec!["main".to_string()],
+                read_only: true,
+            }),
+        ];
+
+        assert_eq!(
+            write_protected_region(&memory_map, &(0x0800_0100..0x0800_0104)),
+            Some(&memory_map[0])
+        );
+        assert_eq!(
+            write_protected_region(&memory_map, &(0x1fff_7ffe..0x1fff_8002)),
+            Some(&memory_map[2])
+        );
+        assert_eq!(
+            write_protected_region(&memory_map, &(0x2000_0000..0x2000_0004)),
+            None
+        );
+    }
+
     #[test]
     fn contains_range1() {
         let range1 = 0..1;
@@ -250,4 +436,32 @@ mod test {
         let range2 = 6..8;
         assert!(!range1.intersects_range(&range2));
     }
+
+    #[test]
+    fn mirrored_addresses() {
+        let alias = MemoryAlias {
+            name: None,
+            range: 0x0000_0000..0x0010_0000,
+            target: 0x0800_0000,
+            kind: MemoryAliasKind::Mirror,
+        };
+        assert_eq!(alias.canonical_address(0x1234), Some(0x0800_1234));
+        assert_eq!(alias.canonical_address(0x0010_0000), None);
+        assert_eq!(alias.alias_address(0x0800_1234), Some(0x1234));
+        assert_eq!(alias.alias_address(0x0810_0000), None);
+    }
+
+    #[test]
+    fn bit_band_addresses() {
+        let alias = MemoryAlias {
+            name: None,
+            range: 0x2200_0000..0x2400_0000,
+            target: 0x2000_0000,
+            kind: MemoryAliasKind::BitBand,
+        };
+        assert_eq!(alias.target_range(), 0x2000_0000..0x2010_0000);
+        // Bit 3 of the byte at 0x2000_0010.
+        assert_eq!(alias.canonical_address(0x2200_020C), Some(0x2000_0010));
+        assert_eq!(alias.alias_address(0x2000_0010), Some(0x2200_0200));
+    }
 }
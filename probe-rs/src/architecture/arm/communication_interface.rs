@@ -3,7 +3,9 @@ use super::{
         valid_access_ports, AccessPort, ApAccess, ApClass, BaseaddrFormat, GenericAp, MemoryAp,
         BASE, BASE2, CFG, CSW, IDR,
     },
-    dp::{Abort, Ctrl, DebugPortVersion, DpAccess, Select, DPIDR},
+    dp::{
+        adi_v6, Abort, Ctrl, DebugPortVersion, DpAccess, Select, Select1, BASEPTR0, BASEPTR1, DPIDR,
+    },
     memory::{
         adi_v5_memory_interface::{ADIMemoryInterface, ArmProbe},
         Component,
@@ -12,8 +14,9 @@ use super::{
     ApAddress, ArmError, DapAccess, DpAddress, PortType, RawDapAccess, SwoAccess, SwoConfig,
 };
 use crate::{
-    architecture::arm::ap::DataSize, CoreStatus, DebugProbe, DebugProbeError,
-    Error as ProbeRsError, Probe,
+    architecture::arm::ap::DataSize,
+    probe::transaction_log::{self, DapPort},
+    CoreStatus, DebugProbe, DebugProbeError, Error as ProbeRsError, Probe,
 };
 use jep106::JEP106Code;
 
@@ -21,7 +24,7 @@ use std::{
     collections::{hash_map, HashMap},
     fmt::Debug,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 /// An error in the communication with an access port or
@@ -159,27 +162,51 @@ impl ArmDebugState for Initialized {}
 
 #[derive(Debug)]
 pub(crate) struct DpState {
-    pub _debug_port_version: DebugPortVersion,
+    pub debug_port_version: DebugPortVersion,
 
     pub current_dpbanksel: u8,
 
     pub current_apsel: u8,
     pub current_apbanksel: u8,
 
+    /// The address in the access port address space which is selected on a DPv3, without the lowest 4 bits.
+    /// `None` if the contents of SELECT and SELECT1 are not known.
+    pub current_ap_address: Option<u64>,
+
     /// Information about the APs of the target.
     /// APs are identified by a number, starting from zero.
     pub ap_information: Vec<ApInformation>,
+
+    /// On a DPv3, the base addresses of the APs, in the order in which they were found in the ROM tables.
+    /// The AP number is the index into this list.
+    pub ap_base_addresses: Vec<u64>,
 }
 
 impl DpState {
     pub fn new() -> Self {
         Self {
-            _debug_port_version: DebugPortVersion::Unsupported(0xFF),
+            debug_port_version: DebugPortVersion::Unsupported(0xFF),
             current_dpbanksel: 0,
             current_apsel: 0,
             current_apbanksel: 0,
+            current_ap_address: None,
             ap_information: Vec::new(),
+            ap_base_addresses: Vec::new(),
+        }
+    }
+
+    /// The value of the SELECT register for the current selection.
+    fn select(&self) -> Select {
+        if self.debug_port_version == DebugPortVersion::DPv3 {
+            let address = self.current_ap_address.unwrap_or(0) as u32 & 0xFFFF_FFF0;
+            return Select(address | u32::from(self.current_dpbanksel));
         }
+
+        let mut select = Select(0);
+        select.set_ap_sel(self.current_apsel);
+        select.set_ap_bank_sel(self.current_apbanksel);
+        select.set_dp_bank_sel(self.current_dpbanksel);
+        select
     }
 }
 
@@ -509,10 +536,22 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
             ctrl_reg.set_orun_detect(self.state.use_overrun_detect);
             self.write_dp_register(dp, ctrl_reg)?;
 
+            let dpidr: DPIDR = self.read_dp_register(dp)?;
+            let debug_port_version = DebugPortVersion::from(dpidr.version());
+            tracing::debug!("Debug port version: {}", debug_port_version);
+            // note(unwrap): we have inserted the state above, it must exist.
+            self.state.dps.get_mut(&dp).unwrap().debug_port_version = debug_port_version;
+
             /* determine the number and type of available APs */
             tracing::trace!("Searching valid APs");
 
             let ap_span = tracing::debug_span!("AP discovery").entered();
+            if debug_port_version == DebugPortVersion::DPv3 {
+                let ap_base_addresses = self.discover_address_mapped_access_ports(dp)?;
+                // note(unwrap): we have inserted the state above, it must exist.
+                self.state.dps.get_mut(&dp).unwrap().ap_base_addresses = ap_base_addresses;
+            }
+
             for ap in valid_access_ports(self, dp) {
                 let ap_state = ApInformation::read_from_target(self, ap)?;
                 tracing::debug!("AP {:x?}: {:?}", ap, ap_state);
@@ -537,26 +576,24 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
 
         // DP register addresses are 4 bank bits, 4 address bits. Lowest 2 address bits are
         // always 0, so this leaves only 4 possible addresses: 0x0, 0x4, 0x8, 0xC.
-        // Only address 0x4 is banked, the rest are don't care.
+        // Address 0x4 is banked, and on a DPv3 address 0x0 is banked as well. The rest are don't care.
 
         let bank = dp_register_address >> 4;
         let addr = dp_register_address & 0xF;
 
-        if addr != 4 {
+        let banked =
+            addr == 4 || (addr == 0 && dp_state.debug_port_version == DebugPortVersion::DPv3);
+
+        if !banked {
             return Ok(());
         }
 
         if bank != dp_state.current_dpbanksel {
             dp_state.current_dpbanksel = bank;
 
-            let mut select = Select(0);
-
             tracing::debug!("Changing DP_BANK_SEL to {}", dp_state.current_dpbanksel);
 
-            select.set_ap_sel(dp_state.current_apsel);
-            select.set_ap_bank_sel(dp_state.current_apbanksel);
-            select.set_dp_bank_sel(dp_state.current_dpbanksel);
-
+            let select = dp_state.select();
             self.write_dp_register(dp, select)?;
         }
 
@@ -570,6 +607,18 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
     ) -> Result<(), ArmError> {
         let dp_state = self.select_dp(ap.dp)?;
 
+        if dp_state.debug_port_version == DebugPortVersion::DPv3 {
+            let base = *dp_state
+                .ap_base_addresses
+                .get(ap.ap as usize)
+                .ok_or(ArmError::ApDoesNotExist(ap))?;
+
+            return self.select_ap_space_address(
+                ap.dp,
+                adi_v6::ap_register_address(base, ap_register_address),
+            );
+        }
+
         let port = ap.ap;
         let ap_bank = ap_register_address >> 4;
 
@@ -586,24 +635,74 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
         }
 
         if cache_changed {
-            let mut select = Select(0);
-
             tracing::debug!(
                 "Changing AP to {}, AP_BANK_SEL to {}",
                 dp_state.current_apsel,
                 dp_state.current_apbanksel
             );
 
-            select.set_ap_sel(dp_state.current_apsel);
-            select.set_ap_bank_sel(dp_state.current_apbanksel);
-            select.set_dp_bank_sel(dp_state.current_dpbanksel);
-
+            let select = dp_state.select();
             self.write_dp_register(ap.dp, select)?;
         }
 
         Ok(())
     }
 
+    /// Select an address in the access port address space of a DPv3, using SELECT and SELECT1.
+    ///
+    /// The lowest 4 bits of the address are not selected, they are part of the AP access itself.
+    fn select_ap_space_address(&mut self, dp: DpAddress, address: u64) -> Result<(), ArmError> {
+        let dp_state = self.select_dp(dp)?;
+
+        let address = address & !0xF;
+        let previous_address = dp_state.current_ap_address.replace(address);
+
+        if previous_address == Some(address) {
+            return Ok(());
+        }
+
+        tracing::debug!("Changing AP address to {:#x}", address);
+
+        if previous_address.map(|previous| previous >> 32) != Some(address >> 32) {
+            self.write_dp_register(dp, Select1((address >> 32) as u32))?;
+        }
+
+        // Writing SELECT1 can change the DP bank, so the state has to be fetched again.
+        let select = self.select_dp(dp)?.select();
+        self.write_dp_register(dp, select)?;
+
+        Ok(())
+    }
+
+    /// Read a word from the access port address space of a DPv3.
+    fn read_ap_space(&mut self, dp: DpAddress, address: u64) -> Result<u32, ArmError> {
+        self.select_ap_space_address(dp, address)?;
+
+        let value = self
+            .probe
+            .raw_read_register(PortType::AccessPort, (address & 0xC) as u8)?;
+
+        Ok(value)
+    }
+
+    /// Find the APs of a DPv3, by walking the ROM tables which the base pointer points to.
+    fn discover_address_mapped_access_ports(
+        &mut self,
+        dp: DpAddress,
+    ) -> Result<Vec<u64>, ArmError> {
+        let baseptr0: BASEPTR0 = self.read_dp_register(dp)?;
+
+        if !baseptr0.valid() {
+            tracing::warn!("The debug port does not provide a base pointer, no APs can be found.");
+            return Ok(Vec::new());
+        }
+
+        let baseptr1: BASEPTR1 = self.read_dp_register(dp)?;
+        let base = u64::from(baseptr1.0) << 32 | u64::from(baseptr0.ptr() << 12);
+
+        adi_v6::discover_access_ports(&mut |address| self.read_ap_space(dp, address), base)
+    }
+
     /// Determine the type and additional information about an AP.
     ///
     /// If the AP doesn't exist, None is returned.
@@ -663,8 +762,17 @@ impl SwoAccess for ArmCommunicationInterface<Initialized> {
 impl DapAccess for ArmCommunicationInterface<Initialized> {
     fn read_raw_dp_register(&mut self, dp: DpAddress, address: u8) -> Result<u32, ArmError> {
         self.select_dp_and_dp_bank(dp, address)?;
-        let result = self.probe.raw_read_register(PortType::DebugPort, address)?;
-        Ok(result)
+        let start = Instant::now();
+        let result = self.probe.raw_read_register(PortType::DebugPort, address);
+        transaction_log::record_dap(
+            start,
+            DapPort::Dp(dp),
+            address,
+            false,
+            result.as_ref().map_or(&[][..], std::slice::from_ref),
+            result.as_ref().err(),
+        );
+        result
     }
 
     fn write_raw_dp_register(
@@ -674,9 +782,19 @@ impl DapAccess for ArmCommunicationInterface<Initialized> {
         value: u32,
     ) -> Result<(), ArmError> {
         self.select_dp_and_dp_bank(dp, address)?;
-        self.probe
-            .raw_write_register(PortType::DebugPort, address, value)?;
-        Ok(())
+        let start = Instant::now();
+        let result = self
+            .probe
+            .raw_write_register(PortType::DebugPort, address, value);
+        transaction_log::record_dap(
+            start,
+            DapPort::Dp(dp),
+            address,
+            true,
+            &[value],
+            result.as_ref().err(),
+        );
+        result
     }
 
     fn read_raw_ap_register(
@@ -686,11 +804,17 @@ impl DapAccess for ArmCommunicationInterface<Initialized> {
     ) -> std::result::Result<u32, ArmError> {
         self.select_ap_and_ap_bank(ap, address)?;
 
-        let result = self
-            .probe
-            .raw_read_register(PortType::AccessPort, address)?;
-
-        Ok(result)
+        let start = Instant::now();
+        let result = self.probe.raw_read_register(PortType::AccessPort, address);
+        transaction_log::record_dap(
+            start,
+            DapPort::Ap(ap),
+            address,
+            false,
+            result.as_ref().map_or(&[][..], std::slice::from_ref),
+            result.as_ref().err(),
+        );
+        result
     }
 
     fn read_raw_ap_register_repeated(
@@ -701,9 +825,19 @@ impl DapAccess for ArmCommunicationInterface<Initialized> {
     ) -> Result<(), ArmError> {
         self.select_ap_and_ap_bank(ap, address)?;
 
-        self.probe
-            .raw_read_block(PortType::AccessPort, address, values)?;
-        Ok(())
+        let start = Instant::now();
+        let result = self
+            .probe
+            .raw_read_block(PortType::AccessPort, address, values);
+        transaction_log::record_dap(
+            start,
+            DapPort::Ap(ap),
+            address,
+            false,
+            values,
+            result.as_ref().err(),
+        );
+        result
     }
 
     fn write_raw_ap_register(
@@ -714,10 +848,19 @@ impl DapAccess for ArmCommunicationInterface<Initialized> {
     ) -> Result<(), ArmError> {
         self.select_ap_and_ap_bank(ap, address)?;
 
-        self.probe
-            .raw_write_register(PortType::AccessPort, address, value)?;
-
-        Ok(())
+        let start = Instant::now();
+        let result = self
+            .probe
+            .raw_write_register(PortType::AccessPort, address, value);
+        transaction_log::record_dap(
+            start,
+            DapPort::Ap(ap),
+            address,
+            true,
+            &[value],
+            result.as_ref().err(),
+        );
+        result
     }
 
     fn write_raw_ap_register_repeated(
@@ -728,9 +871,19 @@ impl DapAccess for ArmCommunicationInterface<Initialized> {
     ) -> Result<(), ArmError> {
         self.select_ap_and_ap_bank(ap, address)?;
 
-        self.probe
-            .raw_write_block(PortType::AccessPort, address, values)?;
-        Ok(())
+        let start = Instant::now();
+        let result = self
+            .probe
+            .raw_write_block(PortType::AccessPort, address, values);
+        transaction_log::record_dap(
+            start,
+            DapPort::Ap(ap),
+            address,
+            true,
+            values,
+            result.as_ref().err(),
+        );
+        result
     }
 }
 
@@ -779,7 +932,9 @@ impl ArmCommunicationInterface<Initialized> {
 
                 let component = Component::try_parse(&mut *memory, baseaddr)?;
 
-                if let Component::Class1RomTable(component_id, _) = component {
+                if let Component::Class1RomTable(component_id, _)
+                | Component::Class9RomTable(component_id, _) = component
+                {
                     if let Some(jep106) = component_id.peripheral_id().jep106() {
                         return Ok(Some(ArmChipInfo {
                             manufacturer: jep106,
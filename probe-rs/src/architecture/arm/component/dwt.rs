@@ -11,6 +11,15 @@ use super::DebugComponentInterface;
 use crate::architecture::arm::{ArmError, ArmProbeInterface};
 use crate::{memory_mapped_bitfield_register, Error};
 
+/// The accesses for which a DWT comparator emits data trace packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataTraceAccess {
+    /// Only writes.
+    Write,
+    /// Reads and writes.
+    ReadWrite,
+}
+
 /// A struct representing a DWT unit on target.
 pub struct Dwt<'a> {
     component: &'a CoresightComponent,
@@ -71,6 +80,49 @@ impl<'a> Dwt<'a> {
         function.store_unit(self.component, self.interface, unit)
     }
 
+    /// The number of comparators which are implemented.
+    pub fn num_comparators(&mut self) -> Result<usize, ArmError> {
+        let ctrl = Ctrl::load(self.component, self.interface)?;
+        Ok(ctrl.numcomp() as usize)
+    }
+
+    /// Enables data value tracing of the `size` bytes at `address` on a specific DWT unit.
+    ///
+    /// Every matching access emits the PC of the instruction and the value which was read or written, `size` has
+    /// to be 1, 2 or 4, and `address` aligned to it.
+    pub fn enable_data_value_trace(
+        &mut self,
+        unit: usize,
+        address: u32,
+        size: u32,
+        access: DataTraceAccess,
+    ) -> Result<(), ArmError> {
+        let size_bits = size.trailing_zeros();
+
+        let mut comp = Comp::load_unit(self.component, self.interface, unit)?;
+        comp.set_comp(address);
+        comp.store_unit(self.component, self.interface, unit)?;
+
+        // Accesses to every byte of the value match.
+        let mut mask = Mask::load_unit(self.component, self.interface, unit)?;
+        mask.set_mask(size_bits);
+        mask.store_unit(self.component, self.interface, unit)?;
+
+        let mut function = Function::load_unit(self.component, self.interface, unit)?;
+        function.set_datavsize(size_bits as u8);
+        function.set_emitrange(false);
+        function.set_datavmatch(false);
+        function.set_cycmatch(false);
+        function.set_function(match access {
+            // Sample PC and data value for writes.
+            DataTraceAccess::Write => 0b1111,
+            // Sample PC and data value for reads and writes.
+            DataTraceAccess::ReadWrite => 0b0011,
+        });
+
+        function.store_unit(self.component, self.interface, unit)
+    }
+
     /// Disables data tracing on the given unit.
     pub fn disable_data_trace(&mut self, unit: usize) -> Result<(), ArmError> {
         let mut function = Function::load_unit(self.component, self.interface, unit)?;
@@ -2,6 +2,7 @@
 
 mod dwt;
 mod itm;
+mod mtb;
 mod scs;
 mod swo;
 mod tmc;
@@ -18,7 +19,8 @@ use crate::architecture::arm::{ArmProbeInterface, SwoConfig, SwoMode};
 use crate::{Core, Error, MemoryInterface, MemoryMappedRegister};
 
 pub use self::itm::Itm;
-pub use dwt::Dwt;
+pub use dwt::{DataTraceAccess, Dwt};
+pub use mtb::{Mtb, MtbPacket};
 pub use scs::Scs;
 pub use swo::Swo;
 pub use tmc::TraceMemoryController;
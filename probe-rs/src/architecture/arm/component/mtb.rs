@@ -0,0 +1,257 @@
+//! Interface with the MTB (Micro Trace Buffer) of the Cortex-M0+.
+//!
+//! The MTB records every non-sequential change of the program flow, i.e. the taken branches, the exception entries
+//! and the exception returns, as a packet of two words into a circular buffer in the SRAM. The Cortex-M0+ has no ETM,
+//! so this is its only instruction trace. The buffer is a part of the SRAM which the firmware doesn't use, and the
+//! trace is read from it through the debug port, usually when the core halted, e.g. on a fault.
+//!
+//! See the CoreSight MTB-M0+ technical reference manual for the registers.
+
+use anyhow::anyhow;
+
+use super::super::memory::romtable::CoresightComponent;
+use super::DebugComponentInterface;
+use crate::architecture::arm::{ArmError, ArmProbeInterface};
+use crate::memory_mapped_bitfield_register;
+
+/// The smallest buffer, of two packets.
+const MIN_BUFFER_SIZE: u32 = 16;
+
+/// A change of the program flow, which the MTB recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MtbPacket {
+    /// The address of the branch instruction, or of the instruction at which an exception was taken.
+    pub source: u32,
+    /// The address of the next instruction which was executed.
+    pub destination: u32,
+    /// Whether the packet is an exception entry, or the entry into the debug state.
+    pub exception: bool,
+    /// Whether the recording (re)started with this packet, so the program flow between the previous packet and the
+    /// source of this one wasn't recorded.
+    pub start: bool,
+}
+
+impl MtbPacket {
+    /// Decode a packet from its two words. Bit 0 of each address, which is always 0 for Thumb code, is a flag.
+    pub fn from_words(source: u32, destination: u32) -> Self {
+        Self {
+            source: source & !1,
+            destination: destination & !1,
+            exception: source & 1 != 0,
+            start: destination & 1 != 0,
+        }
+    }
+}
+
+/// The packets of a circular buffer, the oldest first.
+///
+/// `words` is the content of the buffer, `next` the index of the word where the next packet is written, and the
+/// buffer `wrapped` if the packets after `next` are older ones, which weren't overwritten yet.
+fn packets_in_order(words: &[u32], next: usize, wrapped: bool) -> Vec<MtbPacket> {
+    let (newer, older) = words.split_at(next.min(words.len()));
+    let older = if wrapped { older } else { &[] };
+
+    older
+        .chunks_exact(2)
+        .chain(newer.chunks_exact(2))
+        .map(|packet| MtbPacket::from_words(packet[0], packet[1]))
+        .collect()
+}
+
+/// The MTB of a Cortex-M0+.
+pub struct Mtb<'a> {
+    component: &'a CoresightComponent,
+    interface: &'a mut dyn ArmProbeInterface,
+}
+
+impl<'a> Mtb<'a> {
+    /// Creates a new MTB component representation.
+    pub fn new(
+        interface: &'a mut dyn ArmProbeInterface,
+        component: &'a CoresightComponent,
+    ) -> Self {
+        Mtb {
+            component,
+            interface,
+        }
+    }
+
+    /// The address of the SRAM, to which the position of the buffer is relative.
+    pub fn sram_base(&mut self) -> Result<u32, ArmError> {
+        Ok(Base::load(self.component, self.interface)?.base())
+    }
+
+    /// Whether the MTB records.
+    pub fn is_enabled(&mut self) -> Result<bool, ArmError> {
+        Ok(Master::load(self.component, self.interface)?.en())
+    }
+
+    /// Start recording into the buffer of `size` bytes at `address`, which the firmware must not use.
+    ///
+    /// The size is a power of two of at least 16 bytes, and the buffer has to be aligned to its size, and be in the
+    /// SRAM of the MTB. Each packet takes 8 bytes. With `stop_when_full`, the recording stops when the buffer is
+    /// full, so it keeps the oldest packets. Otherwise the newest packets overwrite the oldest ones.
+    pub fn enable(
+        &mut self,
+        address: u32,
+        size: u32,
+        stop_when_full: bool,
+    ) -> Result<(), ArmError> {
+        if !size.is_power_of_two() || size < MIN_BUFFER_SIZE {
+            return Err(anyhow!(
+                "The size of the MTB buffer has to be a power of two of at least {MIN_BUFFER_SIZE} bytes, not {size}"
+            )
+            .into());
+        }
+        if address % size != 0 {
+            return Err(anyhow!(
+                "The MTB buffer at {address:#010x} is not aligned to its size of {size} bytes"
+            )
+            .into());
+        }
+        let offset = address
+            .checked_sub(self.sram_base()?)
+            .ok_or_else(|| anyhow!("The MTB buffer at {address:#010x} is below the SRAM"))?;
+
+        self.disable()?;
+
+        let mut position = Position(0);
+        position.set_pointer(offset >> 3);
+        position.set_wrap(false);
+        position.store(self.component, self.interface)?;
+
+        // The recording stops when the pointer reaches the watermark, at the last packet of the buffer.
+        let mut flow = Flow(0);
+        if stop_when_full {
+            flow.set_watermark((offset + size - 8) >> 3);
+            flow.set_autostop(true);
+        }
+        flow.store(self.component, self.interface)?;
+
+        let mut master = Master(0);
+        master.set_mask(size.trailing_zeros() as u8 - 4);
+        master.set_en(true);
+        master.store(self.component, self.interface)?;
+
+        // The implemented size of the buffer is limited by the size of the SRAM.
+        let mask = Master::load(self.component, self.interface)?.mask();
+        if 1u64 << (mask + 4) != u64::from(size) {
+            self.disable()?;
+            return Err(anyhow!("The MTB doesn't support a buffer of {size} bytes").into());
+        }
+
+        Ok(())
+    }
+
+    /// Stop recording. The recorded packets remain in the buffer.
+    pub fn disable(&mut self) -> Result<(), ArmError> {
+        let mut master = Master::load(self.component, self.interface)?;
+        master.set_en(false);
+        master.store(self.component, self.interface)
+    }
+
+    /// Stop recording, and read the recorded packets from the buffer, the oldest first.
+    pub fn read_trace(&mut self) -> Result<Vec<MtbPacket>, ArmError> {
+        self.disable()?;
+
+        let master = Master::load(self.component, self.interface)?;
+        let position = Position::load(self.component, self.interface)?;
+        let size = 1u32 << (master.mask() + 4);
+        let offset = position.pointer() << 3;
+        let start = self.sram_base()? + (offset & !(size - 1));
+
+        let mut words = vec![0; size as usize / 4];
+        let mut memory = self.interface.memory_interface(self.component.ap)?;
+        memory.read_32(start as u64, &mut words)?;
+
+        let next = (offset & (size - 1)) as usize / 4;
+        Ok(packets_in_order(&words, next, position.wrap()))
+    }
+}
+
+memory_mapped_bitfield_register! {
+    pub struct Position(u32);
+    0x000, "MTB_POSITION",
+    impl From;
+
+    /// The offset of the next packet from the SRAM base, in units of 8 bytes.
+    pub u32, pointer, set_pointer: 31, 3;
+    /// The pointer wrapped around the end of the buffer.
+    pub wrap, set_wrap: 2;
+}
+
+impl DebugComponentInterface for Position {}
+
+memory_mapped_bitfield_register! {
+    pub struct Master(u32);
+    0x004, "MTB_MASTER",
+    impl From;
+
+    pub en, set_en: 31;
+    pub haltreq, set_haltreq: 9;
+    pub rampriv, set_rampriv: 8;
+    pub sfrwpriv, set_sfrwpriv: 7;
+    pub tstopen, set_tstopen: 6;
+    pub tstarten, set_tstarten: 5;
+    /// The size of the buffer is `2^(mask + 4)` bytes.
+    pub u8, mask, set_mask: 4, 0;
+}
+
+impl DebugComponentInterface for Master {}
+
+memory_mapped_bitfield_register! {
+    pub struct Flow(u32);
+    0x008, "MTB_FLOW",
+    impl From;
+
+    /// The pointer at which the recording stops, or the core halts.
+    pub u32, watermark, set_watermark: 31, 3;
+    pub autohalt, set_autohalt: 1;
+    pub autostop, set_autostop: 0;
+}
+
+impl DebugComponentInterface for Flow {}
+
+memory_mapped_bitfield_register! {
+    pub struct Base(u32);
+    0x00C, "MTB_BASE",
+    impl From;
+
+    pub u32, base, _: 31, 0;
+}
+
+impl DebugComponentInterface for Base {}
+
+#[cfg(test)]
+mod test {
+    use super::{packets_in_order, MtbPacket};
+
+    #[test]
+    fn packets_are_read_oldest_first() {
+        let packet = |source, destination| MtbPacket::from_words(source, destination);
+        let words = [0x100, 0x200, 0x301, 0x401, 0x500, 0x600, 0x700, 0x800];
+
+        assert_eq!(
+            packet(0x301, 0x401),
+            MtbPacket {
+                source: 0x300,
+                destination: 0x400,
+                exception: true,
+                start: true,
+            }
+        );
+        assert_eq!(
+            packets_in_order(&words, 4, false),
+            [packet(0x100, 0x200), packet(0x301, 0x401)]
+        );
+        assert_eq!(
+            packets_in_order(&words, 4, true),
+            [
+                packet(0x500, 0x600),
+                packet(0x700, 0x800),
+                packet(0x100, 0x200),
+                packet(0x301, 0x401),
+            ]
+        );
+    }
+}
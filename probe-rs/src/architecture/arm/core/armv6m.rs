@@ -5,7 +5,7 @@ use crate::{
     architecture::arm::{
         memory::adi_v5_memory_interface::ArmProbe, sequences::ArmDebugSequence, ArmError,
     },
-    core::{CoreRegisters, RegisterId, RegisterValue},
+    core::{CoreRegisters, RegisterId, RegisterValue, VectorCatchCondition},
     error::Error,
     memory::valid_32bit_address,
     Architecture, CoreInformation, CoreInterface, CoreRegister, CoreStatus, CoreType,
@@ -462,6 +462,22 @@ impl<'probe> Armv6m<'probe> {
     fn set_core_status(&mut self, new_status: CoreStatus) {
         super::update_core_status(&mut self.memory, &mut self.state.current_state, new_status);
     }
+
+    /// Enable or disable the vector catch for `condition` in DEMCR.
+    fn set_vector_catch(
+        &mut self,
+        condition: VectorCatchCondition,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let mut demcr = Demcr(self.memory.read_word_32(Demcr::get_mmio_address())?);
+        match condition {
+            VectorCatchCondition::HardFault => demcr.set_vc_harderr(enabled),
+            VectorCatchCondition::CoreReset => demcr.set_vc_corereset(enabled),
+        }
+        self.memory
+            .write_word_32(Demcr::get_mmio_address(), demcr.into())?;
+        Ok(())
+    }
 }
 
 impl<'probe> CoreInterface for Armv6m<'probe> {
@@ -822,6 +838,14 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         self.id
     }
 
+    fn enable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.set_vector_catch(condition, true)
+    }
+
+    fn disable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.set_vector_catch(condition, false)
+    }
+
     #[tracing::instrument(skip(self))]
     fn reset_catch_set(&mut self) -> Result<(), Error> {
         // Set the reset_catch bit.
@@ -1,6 +1,7 @@
 //! Register types and the core interface for armv7-M
 
 use super::{
+    cache::{CacheAccess, Caches},
     cortex_m::Mvfr0,
     registers::cortex_m::{
         CORTEX_M_CORE_REGSISTERS, CORTEX_M_WITH_FP_CORE_REGSISTERS, FP, PC, RA, SP,
@@ -14,7 +15,7 @@ use crate::{
     },
     core::{
         Architecture, CoreInformation, CoreInterface, CoreRegisters, CoreStatus, HaltReason,
-        MemoryMappedRegister, RegisterId, RegisterValue,
+        MemoryMappedRegister, RegisterId, RegisterValue, VectorCatchCondition,
     },
     error::Error,
     memory::valid_32bit_address,
@@ -643,8 +644,47 @@ impl<'probe> Armv7m<'probe> {
     }
 
     fn set_core_status(&mut self, new_status: CoreStatus) {
+        if self.state.current_state != new_status {
+            // The firmware can change the cache configuration while the core runs.
+            self.state.caches = None;
+        }
         super::update_core_status(&mut self.memory, &mut self.state.current_state, new_status);
     }
+
+    /// Keep the caches in sync with the memory around an access of the debugger, see [`super::cache`].
+    fn maintain_caches(
+        &mut self,
+        address: u64,
+        length: usize,
+        access: CacheAccess,
+    ) -> Result<(), ArmError> {
+        if !self.state.current_state.is_halted() {
+            return Ok(());
+        }
+        if self.state.caches.is_none() {
+            self.state.caches = Some(Caches::read(&mut *self.memory, true)?);
+        }
+        if let Some(caches) = &self.state.caches {
+            caches.maintain(&mut *self.memory, address..address + length as u64, access)?;
+        }
+        Ok(())
+    }
+
+    /// Enable or disable the vector catch for `condition` in DEMCR.
+    fn set_vector_catch(
+        &mut self,
+        condition: VectorCatchCondition,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let mut demcr = Demcr(self.memory.read_word_32(Demcr::get_mmio_address())?);
+        match condition {
+            VectorCatchCondition::HardFault => demcr.set_vc_harderr(enabled),
+            VectorCatchCondition::CoreReset => demcr.set_vc_corereset(enabled),
+        }
+        self.memory
+            .write_word_32(Demcr::get_mmio_address(), demcr.into())?;
+        Ok(())
+    }
 }
 
 impl<'probe> CoreInterface for Armv7m<'probe> {
@@ -1051,6 +1091,14 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
         self.id
     }
 
+    fn enable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.set_vector_catch(condition, true)
+    }
+
+    fn disable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.set_vector_catch(condition, false)
+    }
+
     #[tracing::instrument(skip(self))]
     fn reset_catch_set(&mut self) -> Result<(), Error> {
         self.sequence
@@ -1082,81 +1130,94 @@ impl<'probe> MemoryInterface for Armv7m<'probe> {
     }
 
     fn read_word_64(&mut self, address: u64) -> Result<u64, crate::error::Error> {
+        self.maintain_caches(address, 8, CacheAccess::BeforeRead)?;
         self.memory
             .read_word_64(address)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+        self.maintain_caches(address, 4, CacheAccess::BeforeRead)?;
         self.memory
             .read_word_32(address)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
+        self.maintain_caches(address, 1, CacheAccess::BeforeRead)?;
         self.memory
             .read_word_8(address)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), crate::error::Error> {
+        self.maintain_caches(address, data.len() * 8, CacheAccess::BeforeRead)?;
         self.memory
             .read_64(address, data)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
+        self.maintain_caches(address, data.len() * 4, CacheAccess::BeforeRead)?;
         self.memory
             .read_32(address, data)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        self.maintain_caches(address, data.len(), CacheAccess::BeforeRead)?;
         self.memory
             .read_8(address, data)
             .map_err(From::<ArmError>::from)
     }
 
     fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), crate::error::Error> {
-        self.memory
-            .write_word_64(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, 8, CacheAccess::BeforeWrite)?;
+        self.memory.write_word_64(address, data)?;
+        self.maintain_caches(address, 8, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
-        self.memory
-            .write_word_32(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, 4, CacheAccess::BeforeWrite)?;
+        self.memory.write_word_32(address, data)?;
+        self.maintain_caches(address, 4, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), Error> {
-        self.memory
-            .write_word_8(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, 1, CacheAccess::BeforeWrite)?;
+        self.memory.write_word_8(address, data)?;
+        self.maintain_caches(address, 1, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), crate::error::Error> {
-        self.memory
-            .write_64(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len() * 8, CacheAccess::BeforeWrite)?;
+        self.memory.write_64(address, data)?;
+        self.maintain_caches(address, data.len() * 8, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), Error> {
-        self.memory
-            .write_32(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len() * 4, CacheAccess::BeforeWrite)?;
+        self.memory.write_32(address, data)?;
+        self.maintain_caches(address, data.len() * 4, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
-        self.memory
-            .write_8(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len(), CacheAccess::BeforeWrite)?;
+        self.memory.write_8(address, data)?;
+        self.maintain_caches(address, data.len(), CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
-        self.memory
-            .write(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len(), CacheAccess::BeforeWrite)?;
+        self.memory.write(address, data)?;
+        self.maintain_caches(address, data.len(), CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn supports_8bit_transfers(&self) -> Result<bool, Error> {
@@ -1,9 +1,12 @@
 //! Register types and the core interface for armv8-M
 
 use super::{
+    cache::{CacheAccess, Caches},
     cortex_m::Mvfr0,
     registers::cortex_m::{
-        CORTEX_M_CORE_REGSISTERS, CORTEX_M_WITH_FP_CORE_REGSISTERS, FP, PC, RA, SP,
+        is_secure_register, CORTEX_M_CORE_REGSISTERS,
+        CORTEX_M_WITH_FP_AND_SECURITY_CORE_REGSISTERS, CORTEX_M_WITH_FP_CORE_REGSISTERS,
+        CORTEX_M_WITH_SECURITY_CORE_REGSISTERS, FP, PC, RA, SP,
     },
     CortexMState, Dfsr,
 };
@@ -12,13 +15,14 @@ use crate::{
         core::registers::cortex_m::XPSR, memory::adi_v5_memory_interface::ArmProbe,
         sequences::ArmDebugSequence, ArmError,
     },
-    core::{CoreRegisters, RegisterId, RegisterValue},
+    core::{CoreRegisters, RegisterId, RegisterValue, VectorCatchCondition},
     error::Error,
     memory::valid_32bit_address,
-    Architecture, CoreInformation, CoreInterface, CoreRegister, CoreStatus, CoreType, HaltReason,
-    InstructionSet, MemoryInterface, MemoryMappedRegister,
+    memory_mapped_bitfield_register, Architecture, CoreInformation, CoreInterface, CoreRegister,
+    CoreStatus, CoreType, HaltReason, InstructionSet, MemoryInterface, MemoryMappedRegister,
+    SecurityAttribution, SecurityState, SecurityStatus,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bitfield::bitfield;
 use std::{
     mem::size_of,
@@ -72,6 +76,9 @@ impl<'probe> Armv8m<'probe> {
 
             state.current_state = core_state;
             state.fp_present = Mvfr0(memory.read_word_32(Mvfr0::get_mmio_address())?).fp_present();
+            state.security_extension_present =
+                IdPfr1(memory.read_word_32(IdPfr1::get_mmio_address())?).security() != 0;
+            state.security_registers_present = state.security_extension_present && dhcsr.s_sde();
 
             state.initialize();
         }
@@ -85,8 +92,127 @@ impl<'probe> Armv8m<'probe> {
     }
 
     fn set_core_status(&mut self, new_status: CoreStatus) {
+        if self.state.current_state != new_status {
+            // The firmware can change the cache configuration while the core runs.
+            self.state.caches = None;
+        }
         super::update_core_status(&mut self.memory, &mut self.state.current_state, new_status);
     }
+
+    /// Keep the caches in sync with the memory around an access of the debugger, see [`super::cache`].
+    fn maintain_caches(
+        &mut self,
+        address: u64,
+        length: usize,
+        access: CacheAccess,
+    ) -> Result<(), ArmError> {
+        if !self.state.current_state.is_halted() {
+            return Ok(());
+        }
+        if self.state.caches.is_none() {
+            self.state.caches = Some(Caches::read(&mut *self.memory, false)?);
+        }
+        if let Some(caches) = &self.state.caches {
+            caches.maintain(&mut *self.memory, address..address + length as u64, access)?;
+        }
+        Ok(())
+    }
+
+    fn secure_debug_enabled(&mut self) -> Result<bool, Error> {
+        let dhcsr = Dhcsr(self.memory.read_word_32(Dhcsr::get_mmio_address())?);
+        Ok(dhcsr.s_sde())
+    }
+
+    /// Ensure that Secure registers are only accessed when secure debug is enabled.
+    fn check_register_access(&mut self, address: RegisterId) -> Result<(), Error> {
+        if is_secure_register(address) && !self.secure_debug_enabled()? {
+            return Err(Error::Other(anyhow!(
+                "Register {:?} is a Secure register, which can only be accessed when secure debug is enabled.",
+                address
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Read the enabled regions of the SAU. The region number register is restored afterwards.
+    fn sau_regions(&mut self) -> Result<Vec<SauRegion>, Error> {
+        let sau_type = SauType(self.memory.read_word_32(SauType::get_mmio_address())?);
+        let old_rnr = self.memory.read_word_32(SauRnr::get_mmio_address())?;
+
+        let mut regions = Vec::new();
+        for region in 0..sau_type.sregion() {
+            self.memory
+                .write_word_32(SauRnr::get_mmio_address(), region)?;
+
+            let rbar = SauRbar(self.memory.read_word_32(SauRbar::get_mmio_address())?);
+            let rlar = SauRlar(self.memory.read_word_32(SauRlar::get_mmio_address())?);
+
+            if rlar.enable() {
+                regions.push(SauRegion {
+                    start: rbar.baddr() << 5,
+                    end: rlar.laddr() << 5 | 0x1F,
+                    non_secure_callable: rlar.nsc(),
+                });
+            }
+        }
+
+        self.memory
+            .write_word_32(SauRnr::get_mmio_address(), old_rnr)?;
+
+        Ok(regions)
+    }
+
+    /// Enable or disable the vector catch for `condition` in DEMCR.
+    fn set_vector_catch(
+        &mut self,
+        condition: VectorCatchCondition,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let mut demcr = Demcr(self.memory.read_word_32(Demcr::get_mmio_address())?);
+        match condition {
+            VectorCatchCondition::HardFault => demcr.set_vc_harderr(enabled),
+            VectorCatchCondition::CoreReset => demcr.set_vc_corereset(enabled),
+        }
+        self.memory
+            .write_word_32(Demcr::get_mmio_address(), demcr.into())?;
+        Ok(())
+    }
+}
+
+/// An enabled region of the Security Attribution Unit, with inclusive bounds.
+#[derive(Debug, Clone, Copy)]
+struct SauRegion {
+    start: u32,
+    end: u32,
+    non_secure_callable: bool,
+}
+
+/// The security attribution of `address`, as configured in the SAU.
+///
+/// The IMPLEMENTATION DEFINED attribution unit (IDAU) can mark an address as more secure than the SAU does,
+/// but it cannot be read by a debugger, so only the SAU is taken into account.
+fn sau_attribution(ctrl: SauCtrl, regions: &[SauRegion], address: u64) -> SecurityAttribution {
+    if !ctrl.enable() {
+        return if ctrl.allns() {
+            SecurityAttribution::NonSecure
+        } else {
+            SecurityAttribution::Secure
+        };
+    }
+
+    let mut matching = regions
+        .iter()
+        .filter(|region| (u64::from(region.start)..=u64::from(region.end)).contains(&address));
+
+    match (matching.next(), matching.next()) {
+        // An address which matches multiple regions is Secure.
+        (Some(region), None) if region.non_secure_callable => {
+            SecurityAttribution::NonSecureCallable
+        }
+        (Some(_), None) => SecurityAttribution::NonSecure,
+        _ => SecurityAttribution::Secure,
+    }
 }
 
 impl<'probe> CoreInterface for Armv8m<'probe> {
@@ -307,6 +433,7 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
 
     fn read_core_reg(&mut self, address: RegisterId) -> Result<RegisterValue, Error> {
         if self.state.current_state.is_halted() {
+            self.check_register_access(address)?;
             let value = super::cortex_m::read_core_reg(&mut *self.memory, address)?;
             Ok(value.into())
         } else {
@@ -316,6 +443,7 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
 
     fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<(), Error> {
         if self.state.current_state.is_halted() {
+            self.check_register_access(address)?;
             super::cortex_m::write_core_reg(&mut *self.memory, address, value.try_into()?)
         } else {
             Err(Error::Arm(ArmError::CoreNotHalted))
@@ -394,10 +522,11 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
     }
 
     fn registers(&self) -> &'static CoreRegisters {
-        if self.state.fp_present {
-            &CORTEX_M_WITH_FP_CORE_REGSISTERS
-        } else {
-            &CORTEX_M_CORE_REGSISTERS
+        match (self.state.fp_present, self.state.security_registers_present) {
+            (false, false) => &CORTEX_M_CORE_REGSISTERS,
+            (true, false) => &CORTEX_M_WITH_FP_CORE_REGSISTERS,
+            (false, true) => &CORTEX_M_WITH_SECURITY_CORE_REGSISTERS,
+            (true, true) => &CORTEX_M_WITH_FP_AND_SECURITY_CORE_REGSISTERS,
         }
     }
 
@@ -437,10 +566,51 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
         Ok(self.state.fp_present)
     }
 
+    fn security_status(&mut self) -> Result<Option<SecurityStatus>, Error> {
+        if !self.state.security_extension_present {
+            return Ok(None);
+        }
+
+        let dscsr = Dscsr(self.memory.read_word_32(Dscsr::get_mmio_address())?);
+
+        Ok(Some(SecurityStatus {
+            state: if dscsr.cds() {
+                SecurityState::Secure
+            } else {
+                SecurityState::NonSecure
+            },
+            secure_debug_enabled: self.secure_debug_enabled()?,
+        }))
+    }
+
+    fn security_attribution(&mut self, address: u64) -> Result<Option<SecurityAttribution>, Error> {
+        // The SAU registers are RAZ/WI for Non-secure accesses, so it can only be read with secure debug.
+        if !self.state.security_extension_present || !self.secure_debug_enabled()? {
+            return Ok(None);
+        }
+
+        let ctrl = SauCtrl(self.memory.read_word_32(SauCtrl::get_mmio_address())?);
+        let regions = if ctrl.enable() {
+            self.sau_regions()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Some(sau_attribution(ctrl, &regions, address)))
+    }
+
     fn id(&self) -> usize {
         self.id
     }
 
+    fn enable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.set_vector_catch(condition, true)
+    }
+
+    fn disable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.set_vector_catch(condition, false)
+    }
+
     #[tracing::instrument(skip(self))]
     fn reset_catch_set(&mut self) -> Result<(), Error> {
         self.sequence
@@ -471,80 +641,93 @@ impl<'probe> MemoryInterface for Armv8m<'probe> {
         self.memory.supports_native_64bit_access()
     }
     fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+        self.maintain_caches(address, 4, CacheAccess::BeforeRead)?;
         self.memory
             .read_word_32(address)
             .map_err(From::<ArmError>::from)
     }
     fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
+        self.maintain_caches(address, 1, CacheAccess::BeforeRead)?;
         self.memory
             .read_word_8(address)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), crate::error::Error> {
+        self.maintain_caches(address, data.len() * 8, CacheAccess::BeforeRead)?;
         self.memory
             .read_64(address, data)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
+        self.maintain_caches(address, data.len() * 4, CacheAccess::BeforeRead)?;
         self.memory
             .read_32(address, data)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        self.maintain_caches(address, data.len(), CacheAccess::BeforeRead)?;
         self.memory
             .read_8(address, data)
             .map_err(From::<ArmError>::from)
     }
 
     fn read_word_64(&mut self, address: u64) -> Result<u64, crate::error::Error> {
+        self.maintain_caches(address, 8, CacheAccess::BeforeRead)?;
         self.memory
             .read_word_64(address)
             .map_err(From::<ArmError>::from)
     }
 
     fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), crate::error::Error> {
-        self.memory
-            .write_word_64(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, 8, CacheAccess::BeforeWrite)?;
+        self.memory.write_word_64(address, data)?;
+        self.maintain_caches(address, 8, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
-        self.memory
-            .write_word_32(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, 4, CacheAccess::BeforeWrite)?;
+        self.memory.write_word_32(address, data)?;
+        self.maintain_caches(address, 4, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), Error> {
-        self.memory
-            .write_word_8(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, 1, CacheAccess::BeforeWrite)?;
+        self.memory.write_word_8(address, data)?;
+        self.maintain_caches(address, 1, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), crate::error::Error> {
-        self.memory
-            .write_64(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len() * 8, CacheAccess::BeforeWrite)?;
+        self.memory.write_64(address, data)?;
+        self.maintain_caches(address, data.len() * 8, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), Error> {
-        self.memory
-            .write_32(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len() * 4, CacheAccess::BeforeWrite)?;
+        self.memory.write_32(address, data)?;
+        self.maintain_caches(address, data.len() * 4, CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
-        self.memory
-            .write_8(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len(), CacheAccess::BeforeWrite)?;
+        self.memory.write_8(address, data)?;
+        self.maintain_caches(address, data.len(), CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn write(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
-        self.memory
-            .write(address, data)
-            .map_err(From::<ArmError>::from)
+        self.maintain_caches(address, data.len(), CacheAccess::BeforeWrite)?;
+        self.memory.write(address, data)?;
+        self.maintain_caches(address, data.len(), CacheAccess::AfterWrite)?;
+        Ok(())
     }
 
     fn supports_8bit_transfers(&self) -> Result<bool, Error> {
@@ -1080,3 +1263,134 @@ impl From<FpCompN> for u32 {
         value.0
     }
 }
+
+memory_mapped_bitfield_register! {
+    /// ID_PFR1, Processor Feature Register 1 (see armv8-M Architecture Reference Manual D1.2.90)
+    pub struct IdPfr1(u32);
+    0xE000_ED44, "ID_PFR1",
+    impl From;
+    /// Identifies whether the Security Extension is implemented:
+    ///
+    /// `0b0000`: Security Extension not implemented.\
+    /// `0b0001`: Security Extension implemented.\
+    /// `0b0011`: Security Extension implemented, with state handling instructions.
+    pub security, _: 7, 4;
+}
+
+memory_mapped_bitfield_register! {
+    /// DSCSR, Debug Security Control and Status Register (see armv8-M Architecture Reference Manual D1.2.39)
+    pub struct Dscsr(u32);
+    0xE000_EE08, "DSCSR",
+    impl From;
+    /// Current domain Secure. Indicates the current Security state of the PE:
+    ///
+    /// `0`: PE is in Non-secure state.\
+    /// `1`: PE is in Secure state.
+    pub cds, _: 16;
+    /// Secure banked register select. If SBRSELEN is `1`, selects whether DCRSR accesses the Secure (`0b0`: Non-secure, `0b1`: Secure)
+    /// version of the banked stack pointer registers.
+    pub sbrsel, set_sbrsel: 1;
+    /// Secure banked register select enable. If this is `0`, the current Security state selects the banked stack pointer registers.
+    pub sbrselen, set_sbrselen: 0;
+}
+
+memory_mapped_bitfield_register! {
+    /// SAU_CTRL, SAU Control Register (see armv8-M Architecture Reference Manual D1.2.204)
+    pub struct SauCtrl(u32);
+    0xE000_EDD0, "SAU_CTRL",
+    impl From;
+    /// All Non-secure. When SAU_CTRL.ENABLE is `0`, this bit controls if the memory is marked as Non-secure or Secure.
+    pub allns, _: 1;
+    /// Enables the SAU.
+    pub enable, _: 0;
+}
+
+memory_mapped_bitfield_register! {
+    /// SAU_TYPE, SAU Type Register (see armv8-M Architecture Reference Manual D1.2.208)
+    pub struct SauType(u32);
+    0xE000_EDD4, "SAU_TYPE",
+    impl From;
+    /// The number of implemented SAU regions.
+    pub sregion, _: 7, 0;
+}
+
+memory_mapped_bitfield_register! {
+    /// SAU_RNR, SAU Region Number Register (see armv8-M Architecture Reference Manual D1.2.207)
+    pub struct SauRnr(u32);
+    0xE000_EDD8, "SAU_RNR",
+    impl From;
+    /// Selects the region which is accessed through SAU_RBAR and SAU_RLAR.
+    pub region, set_region: 7, 0;
+}
+
+memory_mapped_bitfield_register! {
+    /// SAU_RBAR, SAU Region Base Address Register (see armv8-M Architecture Reference Manual D1.2.205)
+    pub struct SauRbar(u32);
+    0xE000_EDDC, "SAU_RBAR",
+    impl From;
+    /// Bits `[31:5]` of the base address of the selected region.
+    pub baddr, _: 31, 5;
+}
+
+memory_mapped_bitfield_register! {
+    /// SAU_RLAR, SAU Region Limit Address Register (see armv8-M Architecture Reference Manual D1.2.206)
+    pub struct SauRlar(u32);
+    0xE000_EDE0, "SAU_RLAR",
+    impl From;
+    /// Bits `[31:5]` of the limit address of the selected region. Bits `[4:0]` of the limit address are `0b11111`.
+    pub laddr, _: 31, 5;
+    /// Non-secure callable. Indicates if the region is Non-secure callable (`1`) or Non-secure (`0`).
+    pub nsc, _: 1;
+    /// Enables the selected region.
+    pub enable, _: 0;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn regions() -> Vec<SauRegion> {
+        vec![
+            SauRegion {
+                start: 0x0800_0000,
+                end: 0x0800_01FF,
+                non_secure_callable: true,
+            },
+            SauRegion {
+                start: 0x0804_0000,
+                end: 0x080F_FFFF,
+                non_secure_callable: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn sau_regions_determine_attribution() {
+        let ctrl = SauCtrl(0b01);
+
+        assert_eq!(
+            sau_attribution(ctrl, &regions(), 0x0800_0100),
+            SecurityAttribution::NonSecureCallable
+        );
+        assert_eq!(
+            sau_attribution(ctrl, &regions(), 0x080F_FFFF),
+            SecurityAttribution::NonSecure
+        );
+        assert_eq!(
+            sau_attribution(ctrl, &regions(), 0x0802_0000),
+            SecurityAttribution::Secure
+        );
+    }
+
+    #[test]
+    fn disabled_sau_uses_allns() {
+        assert_eq!(
+            sau_attribution(SauCtrl(0b00), &regions(), 0x0804_0000),
+            SecurityAttribution::Secure
+        );
+        assert_eq!(
+            sau_attribution(SauCtrl(0b10), &[], 0x0000_0000),
+            SecurityAttribution::NonSecure
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Maintenance of the L1 caches of Cortex-M7, M55 and M85 cores around the memory accesses of the debugger.
+//!
+//! The debugger accesses the memory through the AHB-AP, behind the caches of the core. When the core is halted, the
+//! D-cache may hold data which was not written back to the memory yet, and both caches may hold old copies of memory
+//! which the debugger overwrites, e.g. when it loads a flash algorithm or patches code. So the dirty lines are cleaned
+//! before the debugger reads a range, they are cleaned and invalidated before it writes a range, and the I-cache is
+//! invalidated after a write, so the core fetches the new instructions.
+//!
+//! The TCMs, the peripherals and the device regions are never cached, so accesses to them need no maintenance.
+
+use std::ops::Range;
+
+use crate::{
+    architecture::arm::{memory::adi_v5_memory_interface::ArmProbe, ArmError},
+    memory_mapped_bitfield_register, MemoryMappedRegister,
+};
+
+memory_mapped_bitfield_register! {
+    /// Configuration and Control Register
+    pub struct Ccr(u32);
+    0xE000_ED14, "CCR",
+    impl From;
+    pub ic, _: 17;
+    pub dc, _: 16;
+}
+
+memory_mapped_bitfield_register! {
+    /// Cache Size ID Register, of the cache selected with CSSELR
+    pub struct Ccsidr(u32);
+    0xE000_ED80, "CCSIDR",
+    impl From;
+    pub num_sets, _: 27, 13;
+    pub associativity, _: 12, 3;
+    pub line_size, _: 2, 0;
+}
+
+memory_mapped_bitfield_register! {
+    /// Cache Size Selection Register
+    pub struct Csselr(u32);
+    0xE000_ED84, "CSSELR",
+    impl From;
+    pub level, set_level: 3, 1;
+    pub ind, set_ind: 0;
+}
+
+/// Instruction cache invalidate all to the Point of Unification.
+const ICIALLU: u64 = 0xE000_EF50;
+/// Data cache clean by address to the Point of Coherency.
+const DCCMVAC: u64 = 0xE000_EF68;
+/// Data cache clean by set/way.
+const DCCSW: u64 = 0xE000_EF6C;
+/// Data cache clean and invalidate by address to the Point of Coherency.
+const DCCIMVAC: u64 = 0xE000_EF70;
+/// Data cache clean and invalidate by set/way.
+const DCCISW: u64 = 0xE000_EF74;
+
+/// Instruction and Data TCM Control Registers of the Cortex-M7.
+const ITCMCR: u64 = 0xE000_EF90;
+const DTCMCR: u64 = 0xE000_EF94;
+const ITCM_BASE: u64 = 0x0000_0000;
+const DTCM_BASE: u64 = 0x2000_0000;
+
+/// The access of the debugger which the caches are maintained for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CacheAccess {
+    BeforeRead,
+    BeforeWrite,
+    AfterWrite,
+}
+
+/// The size and organization of the D-cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CacheGeometry {
+    line_size: u64,
+    sets: u32,
+    ways: u32,
+}
+
+impl CacheGeometry {
+    fn from_ccsidr(ccsidr: Ccsidr) -> Self {
+        Self {
+            line_size: 16 << ccsidr.line_size(),
+            sets: ccsidr.num_sets() + 1,
+            ways: ccsidr.associativity() + 1,
+        }
+    }
+
+    fn lines(&self) -> u64 {
+        self.sets as u64 * self.ways as u64
+    }
+
+    /// The values for the set/way operations, which together cover every line of the cache.
+    fn set_way_values(&self) -> impl Iterator<Item = u32> + '_ {
+        let way_shift = 32 - self.ways.next_power_of_two().trailing_zeros();
+        let set_shift = self.line_size.trailing_zeros();
+        (0..self.ways).flat_map(move |way| {
+            // With a single way, the way field has no bits.
+            let way = way.checked_shl(way_shift).unwrap_or(0);
+            (0..self.sets).map(move |set| way | set << set_shift)
+        })
+    }
+
+    /// The addresses of the lines which hold `range`.
+    fn line_addresses(&self, range: Range<u64>) -> impl Iterator<Item = u64> {
+        let first = range.start & !(self.line_size - 1);
+        (first..range.end).step_by(self.line_size as usize)
+    }
+}
+
+/// The state of the caches while the core is halted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Caches {
+    /// The enabled D-cache.
+    dcache: Option<CacheGeometry>,
+    icache_enabled: bool,
+    /// The enabled TCMs, which are not cached.
+    tcms: Vec<Range<u64>>,
+}
+
+impl Caches {
+    /// Read which caches are enabled. `tcm_control` is set for cores with the TCM control registers of the
+    /// Cortex-M7.
+    pub(crate) fn read(memory: &mut dyn ArmProbe, tcm_control: bool) -> Result<Self, ArmError> {
+        // The cache bits of CCR read as zero on cores without caches.
+        let ccr = Ccr(memory.read_word_32(Ccr::get_mmio_address())?);
+        if !ccr.dc() && !ccr.ic() {
+            return Ok(Self::default());
+        }
+
+        let dcache = if ccr.dc() {
+            // Select the level 1 data cache.
+            memory.write_word_32(Csselr::get_mmio_address(), 0)?;
+            let ccsidr = Ccsidr(memory.read_word_32(Ccsidr::get_mmio_address())?);
+            Some(CacheGeometry::from_ccsidr(ccsidr))
+        } else {
+            None
+        };
+
+        let mut tcms = Vec::new();
+        if tcm_control {
+            for (register, base) in [(ITCMCR, ITCM_BASE), (DTCMCR, DTCM_BASE)] {
+                if let Some(size) = tcm_size(memory.read_word_32(register)?) {
+                    tcms.push(base..base + size);
+                }
+            }
+        }
+
+        let caches = Self {
+            dcache,
+            icache_enabled: ccr.ic(),
+            tcms,
+        };
+        tracing::debug!("Caches of the halted core: {:?}", caches);
+        Ok(caches)
+    }
+
+    fn is_cached(&self, range: &Range<u64>) -> bool {
+        is_cacheable(range.start)
+            && !self
+                .tcms
+                .iter()
+                .any(|tcm| tcm.contains(&range.start) && range.end <= tcm.end)
+    }
+
+    /// Bring the caches in sync with the memory for an access of the debugger to `range`.
+    pub(crate) fn maintain(
+        &self,
+        memory: &mut dyn ArmProbe,
+        range: Range<u64>,
+        access: CacheAccess,
+    ) -> Result<(), ArmError> {
+        if range.is_empty() || !self.is_cached(&range) {
+            return Ok(());
+        }
+
+        match (access, self.dcache) {
+            (CacheAccess::BeforeRead, Some(dcache)) => {
+                clean_dcache(memory, &dcache, range, DCCMVAC, DCCSW)
+            }
+            (CacheAccess::BeforeWrite, Some(dcache)) => {
+                clean_dcache(memory, &dcache, range, DCCIMVAC, DCCISW)
+            }
+            (CacheAccess::AfterWrite, _) if self.icache_enabled => memory.write_word_32(ICIALLU, 0),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Run a D-cache operation for every line of `range`, or for the whole cache if that takes fewer operations.
+fn clean_dcache(
+    memory: &mut dyn ArmProbe,
+    dcache: &CacheGeometry,
+    range: Range<u64>,
+    by_address: u64,
+    by_set_way: u64,
+) -> Result<(), ArmError> {
+    let lines = (range.end - range.start) / dcache.line_size + 2;
+    if lines > dcache.lines() {
+        for value in dcache.set_way_values() {
+            memory.write_word_32(by_set_way, value)?;
+        }
+    } else {
+        for address in dcache.line_addresses(range) {
+            memory.write_word_32(by_address, address as u32)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether an address is in a region of the default memory map which can be cached: the code, SRAM and external
+/// RAM regions.
+fn is_cacheable(address: u64) -> bool {
+    matches!(address, 0x0000_0000..=0x3FFF_FFFF | 0x6000_0000..=0x9FFF_FFFF)
+}
+
+/// The size of a TCM from the value of its control register, if it is enabled.
+fn tcm_size(tcmcr: u32) -> Option<u64> {
+    let enabled = tcmcr & 1 != 0;
+    let size = (tcmcr >> 3) & 0xF;
+    // The size is encoded as 1 KB << (SZ - 1), from 4 KB upwards.
+    if enabled && size >= 3 {
+        Some(1024 << (size - 1))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The 16 KB D-cache of a Cortex-M7: four ways of 128 sets of 32 byte lines.
+    const M7_DCACHE: CacheGeometry = CacheGeometry {
+        line_size: 32,
+        sets: 128,
+        ways: 4,
+    };
+
+    #[test]
+    fn geometry_is_decoded() {
+        // The CCSIDR value of the D-cache above.
+        assert_eq!(CacheGeometry::from_ccsidr(Ccsidr(0xF00F_E019)), M7_DCACHE);
+    }
+
+    #[test]
+    fn set_way_values_cover_the_cache() {
+        let values = M7_DCACHE.set_way_values().collect::<Vec<_>>();
+        assert_eq!(values.len(), 512);
+        assert_eq!(values[1], 1 << 5);
+        assert_eq!(values[128], 1 << 30);
+        assert_eq!(values[511], 3 << 30 | 127 << 5);
+    }
+
+    #[test]
+    fn unaligned_ranges_cover_all_their_lines() {
+        assert_eq!(
+            M7_DCACHE
+                .line_addresses(0x2400_001C..0x2400_0024)
+                .collect::<Vec<_>>(),
+            [0x2400_0000, 0x2400_0020]
+        );
+    }
+
+    #[test]
+    fn tcms_and_peripherals_are_not_cached() {
+        let caches = Caches {
+            dcache: Some(M7_DCACHE),
+            icache_enabled: true,
+            tcms: vec![0..tcm_size(0x39).unwrap(), DTCM_BASE..DTCM_BASE + 0x2_0000],
+        };
+
+        assert_eq!(tcm_size(0x39), Some(64 * 1024));
+        assert_eq!(tcm_size(0x38), None);
+        assert!(!caches.is_cached(&(0x100..0x104)));
+        assert!(!caches.is_cached(&(0x2000_0000..0x2000_0100)));
+        assert!(!caches.is_cached(&(0x4000_0000..0x4000_0004)));
+        assert!(caches.is_cached(&(0x0800_0000..0x0800_0002)));
+        assert!(caches.is_cached(&(0x2400_0000..0x2400_0004)));
+    }
+}
@@ -11,6 +11,7 @@ pub mod armv8m;
 
 pub(crate) mod armv7a_debug_regs;
 pub(crate) mod armv8a_debug_regs;
+pub(crate) mod cache;
 pub(crate) mod cortex_m;
 pub(crate) mod exception_handling;
 pub(crate) mod instructions;
@@ -113,6 +114,15 @@ pub struct CortexMState {
     current_state: CoreStatus,
 
     fp_present: bool,
+
+    /// The core implements the ARMv8-M security extension (TrustZone-M).
+    security_extension_present: bool,
+
+    /// The banked Secure and Non-secure registers are accessible, because secure debug was enabled when attaching.
+    security_registers_present: bool,
+
+    /// The caches of the halted core, which are read on the first memory access after the core halted.
+    caches: Option<cache::Caches>,
 }
 
 impl CortexMState {
@@ -122,6 +132,9 @@ impl CortexMState {
             hw_breakpoints_enabled: false,
             current_state: CoreStatus::Unknown,
             fp_present: false,
+            security_extension_present: false,
+            security_registers_present: false,
+            caches: None,
         }
     }
 
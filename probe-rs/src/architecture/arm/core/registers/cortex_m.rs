@@ -58,6 +58,33 @@ pub(crate) static CORTEX_M_WITH_FP_CORE_REGSISTERS: Lazy<CoreRegisters> = Lazy::
     )
 });
 
+pub(crate) static CORTEX_M_WITH_SECURITY_CORE_REGSISTERS: Lazy<CoreRegisters> = Lazy::new(|| {
+    CoreRegisters::new(
+        ARM32_COMMON_REGS_SET
+            .iter()
+            .chain(CORTEX_M_COMMON_REGS_SET)
+            .chain(CORTEX_M_SECURITY_REGS_SET)
+            .collect(),
+    )
+});
+
+pub(crate) static CORTEX_M_WITH_FP_AND_SECURITY_CORE_REGSISTERS: Lazy<CoreRegisters> =
+    Lazy::new(|| {
+        CoreRegisters::new(
+            ARM32_COMMON_REGS_SET
+                .iter()
+                .chain(CORTEX_M_COMMON_REGS_SET)
+                .chain(CORTEX_M_WITH_FP_REGS_SET)
+                .chain(CORTEX_M_SECURITY_REGS_SET)
+                .collect(),
+        )
+    });
+
+/// Returns `true` for the registers of the ARMv8-M security extension which can only be accessed when secure debug is enabled.
+pub(crate) fn is_secure_register(id: RegisterId) -> bool {
+    matches!(id.0, 0x1A..=0x1D | 0x22)
+}
+
 pub(super) static ARM32_COMMON_REGS_SET: &[CoreRegister] = &[
     CoreRegister {
         roles: &[
@@ -169,6 +196,80 @@ static CORTEX_M_COMMON_REGS_SET: &[CoreRegister] = &[
     },
 ];
 
+/// The banked stack pointers and special registers of the ARMv8-M security extension.
+static CORTEX_M_SECURITY_REGS_SET: &[CoreRegister] = &[
+    CoreRegister {
+        roles: &[RegisterRole::Core("MSP_NS")],
+        id: RegisterId(0x18),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    CoreRegister {
+        roles: &[RegisterRole::Core("PSP_NS")],
+        id: RegisterId(0x19),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    CoreRegister {
+        roles: &[RegisterRole::Core("MSP_S")],
+        id: RegisterId(0x1A),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    CoreRegister {
+        roles: &[RegisterRole::Core("PSP_S")],
+        id: RegisterId(0x1B),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    CoreRegister {
+        roles: &[RegisterRole::Core("MSPLIM_S")],
+        id: RegisterId(0x1C),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    CoreRegister {
+        roles: &[RegisterRole::Core("PSPLIM_S")],
+        id: RegisterId(0x1D),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    CoreRegister {
+        roles: &[RegisterRole::Core("MSPLIM_NS")],
+        id: RegisterId(0x1E),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    CoreRegister {
+        roles: &[RegisterRole::Core("PSPLIM_NS")],
+        id: RegisterId(0x1F),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    // CONTROL_S bits [31:24], FAULTMASK_S bits [23:16],
+    // BASEPRI_S bits [15:8], and PRIMASK_S bits [7:0]
+    CoreRegister {
+        roles: &[
+            RegisterRole::Core("EXTRA_S"),
+            RegisterRole::Other("EXTRA_S"),
+        ],
+        id: RegisterId(0x22),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+    // CONTROL_NS bits [31:24], FAULTMASK_NS bits [23:16],
+    // BASEPRI_NS bits [15:8], and PRIMASK_NS bits [7:0]
+    CoreRegister {
+        roles: &[
+            RegisterRole::Core("EXTRA_NS"),
+            RegisterRole::Other("EXTRA_NS"),
+        ],
+        id: RegisterId(0x23),
+        data_type: RegisterDataType::UnsignedInteger(32),
+        unwind_rule: UnwindRule::Preserve,
+    },
+];
+
 static CORTEX_M_WITH_FP_REGS_SET: &[CoreRegister] = &[
     CoreRegister {
         roles: &[
@@ -0,0 +1,216 @@
+//! Access ports of ADIv6 debug ports.
+//!
+//! On a DPv3, access ports are not selected by an 8-bit number. Instead, every access port is a component
+//! with a 4 KB register space in the access port address space of the debug port, and is selected by its address.
+//! The debug port provides a pointer to the first component (BASEPTR0/BASEPTR1), which is usually a ROM table,
+//! and the access ports are found by walking this ROM table and all the ROM tables nested in it.
+//!
+//! probe-rs keeps numbering the access ports, in the order in which they are found in the ROM tables, so that
+//! an [`ApAddress`](crate::architecture::arm::ApAddress) refers to an ADIv6 access port in the same way as to an ADIv5 one.
+
+use crate::architecture::arm::ArmError;
+
+/// The offset of the ADIv5 compatible registers (CSW, TAR, ..., IDR) in the register space of an access port.
+const AP_REGISTER_OFFSET: u64 = 0xD00;
+
+/// Offset of the CIDR1 register of a component, which contains the component class.
+const CIDR1_OFFSET: u64 = 0xFF4;
+
+/// Offset of the DEVARCH register of a component.
+const DEVARCH_OFFSET: u64 = 0xFBC;
+
+/// Component class of a Class 0x1 ROM table.
+const CLASS_ROM_TABLE: u32 = 0x1;
+
+/// Component class of a CoreSight component, which includes Class 0x9 ROM tables and access ports.
+const CLASS_CORESIGHT: u32 = 0x9;
+
+/// JEP106 code of Arm, as used in the ARCHITECT field of DEVARCH.
+const ARCHITECT_ARM: u32 = 0x23B;
+
+/// DEVARCH.ARCHID of a Class 0x9 ROM table.
+const ARCHID_ROM_TABLE: u32 = 0x0AF7;
+
+/// The maximum number of entries of a Class 0x1 ROM table.
+const MAX_ENTRIES_CLASS_1: u64 = 960;
+
+/// The maximum number of 32-bit entries of a Class 0x9 ROM table.
+const MAX_ENTRIES_CLASS_9: u64 = 512;
+
+/// ROM tables can point to each other, so the recursion is limited to protect against broken tables.
+const MAX_NESTING: usize = 8;
+
+/// The address of an ADIv5 AP register (e.g. `IDR` at `0xFC`) in the access port at `base`.
+pub(crate) fn ap_register_address(base: u64, register: u8) -> u64 {
+    base + AP_REGISTER_OFFSET + u64::from(register)
+}
+
+/// Find the base addresses of all access ports, starting at the component at `base`.
+///
+/// `read` reads a 32-bit word from the access port address space of the debug port.
+pub(crate) fn discover_access_ports(
+    read: &mut dyn FnMut(u64) -> Result<u32, ArmError>,
+    base: u64,
+) -> Result<Vec<u64>, ArmError> {
+    let mut access_ports = Vec::new();
+    visit_component(read, base, 0, &mut access_ports)?;
+    Ok(access_ports)
+}
+
+fn visit_component(
+    read: &mut dyn FnMut(u64) -> Result<u32, ArmError>,
+    base: u64,
+    depth: usize,
+    access_ports: &mut Vec<u64>,
+) -> Result<(), ArmError> {
+    if depth > MAX_NESTING {
+        tracing::warn!(
+            "ROM tables are nested too deeply, ignoring component at {:#x}",
+            base
+        );
+        return Ok(());
+    }
+
+    let class = (read(base + CIDR1_OFFSET)? >> 4) & 0xF;
+
+    match class {
+        CLASS_ROM_TABLE => visit_rom_table(read, base, MAX_ENTRIES_CLASS_1, depth, access_ports),
+        CLASS_CORESIGHT => {
+            let arch_id = arch_id(read(base + DEVARCH_OFFSET)?);
+
+            if arch_id == Some(ARCHID_ROM_TABLE) {
+                visit_rom_table(read, base, MAX_ENTRIES_CLASS_9, depth, access_ports)
+            } else if arch_id.map(is_access_port).unwrap_or(false) {
+                tracing::debug!("Found access port at {:#x}", base);
+                access_ports.push(base);
+                Ok(())
+            } else {
+                tracing::debug!("Ignoring component at {:#x} ({:x?})", base, arch_id);
+                Ok(())
+            }
+        }
+        class => {
+            tracing::debug!("Ignoring component of class {:#x} at {:#x}", class, base);
+            Ok(())
+        }
+    }
+}
+
+fn visit_rom_table(
+    read: &mut dyn FnMut(u64) -> Result<u32, ArmError>,
+    base: u64,
+    max_entries: u64,
+    depth: usize,
+    access_ports: &mut Vec<u64>,
+) -> Result<(), ArmError> {
+    tracing::debug!("Parsing ROM table at {:#x}", base);
+
+    for index in 0..max_entries {
+        let entry = read(base + index * 4)?;
+
+        // The end of the table is marked by an all zero entry.
+        if entry == 0 {
+            break;
+        }
+
+        if let Some(address) = rom_table_entry_address(base, entry) {
+            visit_component(read, address, depth + 1, access_ports)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The address of the component a ROM table entry points to, or `None` if the component is not present.
+fn rom_table_entry_address(base: u64, entry: u32) -> Option<u64> {
+    // Bit 0 is set for present entries, both in Class 0x1 and Class 0x9 ROM tables.
+    if entry & 1 == 0 {
+        return None;
+    }
+
+    // The offset is a signed value, in bits [31:12].
+    let offset = i64::from((entry & 0xFFFF_F000) as i32);
+    Some((base as i64 + offset) as u64)
+}
+
+/// The architecture ID from a DEVARCH value, if an Arm architecture is present.
+fn arch_id(devarch: u32) -> Option<u32> {
+    let present = devarch & (1 << 20) != 0;
+    let architect = devarch >> 21;
+
+    (present && architect == ARCHITECT_ARM).then_some(devarch & 0xFFFF)
+}
+
+/// Access ports have an architecture ID of the form `0x0Ax7`, e.g. `0x0A17` for a MEM-AP and `0x0A27` for a JTAG-AP.
+fn is_access_port(arch_id: u32) -> bool {
+    arch_id != ARCHID_ROM_TABLE && arch_id & 0xFF0F == 0x0A07
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    const DEVARCH_MEM_AP: u32 = ARCHITECT_ARM << 21 | 1 << 20 | 0x0A17;
+    const DEVARCH_ROM_TABLE: u32 = ARCHITECT_ARM << 21 | 1 << 20 | ARCHID_ROM_TABLE;
+
+    /// An access port address space, all registers which are not set read as zero.
+    struct Components(HashMap<u64, u32>);
+
+    impl Components {
+        fn component(&mut self, base: u64, class: u32, devarch: u32) -> &mut Self {
+            self.0.insert(base + CIDR1_OFFSET, class << 4);
+            self.0.insert(base + DEVARCH_OFFSET, devarch);
+            self
+        }
+
+        fn entries(&mut self, base: u64, entries: &[u32]) -> &mut Self {
+            for (index, entry) in entries.iter().enumerate() {
+                self.0.insert(base + index as u64 * 4, *entry);
+            }
+            self
+        }
+
+        fn discover(&self, base: u64) -> Vec<u64> {
+            discover_access_ports(
+                &mut |address| Ok(self.0.get(&address).copied().unwrap_or(0)),
+                base,
+            )
+            .unwrap()
+        }
+    }
+
+    #[test]
+    fn access_ports_in_nested_rom_tables_are_found() {
+        let mut components = Components(HashMap::new());
+        components
+            // Class 0x9 ROM table, with two MEM-APs and a nested Class 0x1 ROM table.
+            .component(0x0, CLASS_CORESIGHT, DEVARCH_ROM_TABLE)
+            .entries(0x0, &[0x0000_1003, 0x0000_2002, 0x0000_3003, 0x0000_4003])
+            .component(0x1000, CLASS_CORESIGHT, DEVARCH_MEM_AP)
+            .component(0x3000, CLASS_ROM_TABLE, 0)
+            // The nested table points back, with a negative offset.
+            .entries(0x3000, &[0xFFFF_F003])
+            .component(0x2000, CLASS_CORESIGHT, DEVARCH_MEM_AP)
+            .component(0x4000, CLASS_CORESIGHT, DEVARCH_MEM_AP);
+
+        // The entry at 0x2000 is not present in the top-level table, but is found through the nested one.
+        assert_eq!(components.discover(0x0), vec![0x1000, 0x2000, 0x4000]);
+    }
+
+    #[test]
+    fn base_pointer_to_access_port() {
+        let mut components = Components(HashMap::new());
+        components.component(0x8000, CLASS_CORESIGHT, DEVARCH_MEM_AP);
+
+        assert_eq!(components.discover(0x8000), vec![0x8000]);
+    }
+
+    #[test]
+    fn ap_register_addresses() {
+        // IDR of the MEM-AP at 0x2000
+        assert_eq!(ap_register_address(0x2000, 0xFC), 0x2DFC);
+        // CSW of an AP above 4 GB
+        assert_eq!(ap_register_address(0x1_0000_0000, 0x00), 0x1_0000_0D00);
+    }
+}
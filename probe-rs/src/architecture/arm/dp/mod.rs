@@ -2,6 +2,8 @@
 
 #[macro_use]
 mod register_generation;
+pub(crate) mod adi_v6;
+pub mod swj;
 
 use super::{
     communication_interface::RegisterParseError, ArmError, DapAccess, DapError, DpAddress, Register,
@@ -307,6 +309,9 @@ impl Register for Ctrl {
 
 bitfield! {
     /// SELECT, AP Select register (see ADI v5.2 B2.2.9)
+    ///
+    /// On a DPv3 (ADIv6), bits `[31:4]` hold bits `[31:4]` of the selected address in the access port address space
+    /// instead of APSEL and APBANKSEL, see [`Select1`].
     #[derive(Clone)]
     pub struct Select(u32);
     impl Debug;
@@ -383,7 +388,8 @@ bitfield! {
     ///
     /// `0x0`: Reserved. Implementations of DPv0 do not implement DPIDR.\
     /// `0x1`: DPv1 is implemented.\
-    /// `0x2`: DPv2 is implemented.
+    /// `0x2`: DPv2 is implemented.\
+    /// `0x3`: DPv3 is implemented (ADIv6).
     ///
     /// All remaining values are reserved.
     pub u8, version, _: 15, 12;
@@ -482,6 +488,102 @@ impl Register for TARGETID {
     const NAME: &'static str = "TARGETID";
 }
 
+bitfield! {
+    /// BASEPTR0, Base Pointer register 0 (see ADI v6.0 B2.2.2)
+    ///
+    /// BASEPTR0 holds the lower half of the address of the first component in the
+    /// access port address space of a DPv3, which is usually a ROM table.
+    #[derive(Clone)]
+    pub struct BASEPTR0(u32);
+    impl Debug;
+    /// Bits `[31:12]` of the address of the first component.
+    pub u32, ptr, _: 31, 12;
+    /// Indicates if the base pointer is valid. If this is `0b0`, no base address is provided.
+    pub valid, _: 0;
+}
+
+impl TryFrom<u32> for BASEPTR0 {
+    type Error = RegisterParseError;
+
+    fn try_from(raw: u32) -> Result<Self, Self::Error> {
+        Ok(Self(raw))
+    }
+}
+
+impl From<BASEPTR0> for u32 {
+    fn from(raw: BASEPTR0) -> Self {
+        raw.0
+    }
+}
+
+impl DpRegister for BASEPTR0 {
+    const VERSION: DebugPortVersion = DebugPortVersion::DPv3;
+}
+
+impl Register for BASEPTR0 {
+    const ADDRESS: u8 = 0x20;
+    const NAME: &'static str = "BASEPTR0";
+}
+
+/// BASEPTR1, Base Pointer register 1 (see ADI v6.0 B2.2.3)
+///
+/// BASEPTR1 holds bits `[63:32]` of the address of the first component in the access port address space.
+#[derive(Debug, Clone)]
+pub struct BASEPTR1(pub u32);
+
+impl TryFrom<u32> for BASEPTR1 {
+    type Error = RegisterParseError;
+
+    fn try_from(raw: u32) -> Result<Self, Self::Error> {
+        Ok(Self(raw))
+    }
+}
+
+impl From<BASEPTR1> for u32 {
+    fn from(raw: BASEPTR1) -> Self {
+        raw.0
+    }
+}
+
+impl DpRegister for BASEPTR1 {
+    const VERSION: DebugPortVersion = DebugPortVersion::DPv3;
+}
+
+impl Register for BASEPTR1 {
+    const ADDRESS: u8 = 0x30;
+    const NAME: &'static str = "BASEPTR1";
+}
+
+/// SELECT1, AP Select register 1 (see ADI v6.0 B2.2.11)
+///
+/// On a DPv3, SELECT1 holds bits `[63:32]` of the access port address space address which is used
+/// for AP accesses. The lower bits are held by [`Select`].
+#[derive(Debug, Clone)]
+pub struct Select1(pub u32);
+
+impl TryFrom<u32> for Select1 {
+    type Error = RegisterParseError;
+
+    fn try_from(raw: u32) -> Result<Self, Self::Error> {
+        Ok(Self(raw))
+    }
+}
+
+impl From<Select1> for u32 {
+    fn from(raw: Select1) -> Self {
+        raw.0
+    }
+}
+
+impl DpRegister for Select1 {
+    const VERSION: DebugPortVersion = DebugPortVersion::DPv3;
+}
+
+impl Register for Select1 {
+    const ADDRESS: u8 = 0x54;
+    const NAME: &'static str = "SELECT1";
+}
+
 /// The ID of a debug port. Can be used to detect and select devices in a multidrop setup.
 #[derive(Debug)]
 pub struct DebugPortId {
@@ -581,6 +683,8 @@ pub enum DebugPortVersion {
     DPv1,
     /// Version 2 (**very** rare (only known example is the RP2040))
     DPv2,
+    /// Version 3, which is part of ADIv6 and uses address-mapped access ports.
+    DPv3,
     /// Some unsupported value was encountered!
     Unsupported(u8),
 }
@@ -593,6 +697,7 @@ impl From<DebugPortVersion> for u8 {
             DPv0 => 0,
             DPv1 => 1,
             DPv2 => 2,
+            DPv3 => 3,
             Unsupported(val) => val,
         }
     }
@@ -615,6 +720,7 @@ impl Display for DebugPortVersion {
             DPv0 => write!(f, "DPv0"),
             DPv1 => write!(f, "DPv1"),
             DPv2 => write!(f, "DPv2"),
+            DPv3 => write!(f, "DPv3"),
             Unsupported(version) => write!(f, "<unsupported Debugport Version {version}>"),
         }
     }
@@ -626,6 +732,7 @@ impl From<u8> for DebugPortVersion {
             0 => DebugPortVersion::DPv0,
             1 => DebugPortVersion::DPv1,
             2 => DebugPortVersion::DPv2,
+            3 => DebugPortVersion::DPv3,
             value => DebugPortVersion::Unsupported(value),
         }
     }
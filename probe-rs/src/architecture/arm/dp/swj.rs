@@ -0,0 +1,193 @@
+//! Sequences to switch the protocol of a SWJ-DP, and to enter and leave the dormant state.
+//!
+//! A SWJ-DP can be in one of three states: SWD, JTAG, or (since ADIv5.2) dormant. Depending on the
+//! part, and on what the previous tool left behind, the debug port can be in any of them when we connect.
+//! The legacy switch sequences (0xE79E and 0xE73C) only work from JTAG or SWD, while a debug port in the
+//! dormant state only reacts to the selection alert sequence followed by an activation code.
+//!
+//! See the "SWJ-DP" chapter of the ARM Debug Interface Architecture Specification (ADIv5.2 / ADIv6).
+
+use crate::{architecture::arm::RawDapAccess, DebugProbeError, WireProtocol};
+
+/// A sequence of bits, as `(bit_len, bits)` pairs, which are sent LSB first on SWDIO/TMS.
+pub type SwjSequence = Vec<(u8, u64)>;
+
+/// The 128-bit selection alert sequence, which wakes up a debug port in the dormant state.
+const SELECTION_ALERT: u128 = 0x19BC_0EA2_E3DD_AFE9_8685_2D95_6209_F392;
+
+/// The activation code which selects SWD after the selection alert sequence.
+const ACTIVATION_CODE_SWD: u64 = 0x1A;
+
+/// The activation code which selects JTAG after the selection alert sequence.
+const ACTIVATION_CODE_JTAG: u64 = 0x0A;
+
+/// How the debug port is switched to the protocol selected by the probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SwjSwitchMode {
+    /// Send only the legacy JTAG to SWD or SWD to JTAG switch sequence.
+    ///
+    /// This works for all SWJ-DPs which are in the SWD or JTAG state, but not in the dormant state.
+    #[default]
+    Legacy,
+    /// Put the debug port into the dormant state first, and then wake it up with the activation code
+    /// of the selected protocol.
+    ///
+    /// This works regardless of whether the debug port is in the SWD, JTAG or dormant state, but requires
+    /// a debug port which supports the dormant state (SWJ-DPv2, ADIv5.2 and later).
+    Dormant,
+}
+
+/// At least 50 cycles with SWDIO/TMS high, which is a line reset for SWD, and resets the JTAG TAP.
+pub fn line_reset() -> SwjSequence {
+    vec![(51, 0x0007_FFFF_FFFF_FFFF)]
+}
+
+/// The legacy switch sequence from JTAG to SWD.
+pub fn jtag_to_swd() -> SwjSequence {
+    let mut sequence = line_reset();
+    sequence.push((16, 0xE79E));
+    sequence.extend(line_reset());
+    sequence
+}
+
+/// The legacy switch sequence from SWD to JTAG.
+pub fn swd_to_jtag() -> SwjSequence {
+    let mut sequence = line_reset();
+    sequence.push((16, 0xE73C));
+    // At least 5 cycles with TMS high, to reset the TAP.
+    sequence.push((5, 0x1F));
+    sequence
+}
+
+/// The sequence which puts a debug port in the SWD state into the dormant state.
+pub fn swd_to_dormant() -> SwjSequence {
+    let mut sequence = line_reset();
+    sequence.push((16, 0xE3BC));
+    sequence
+}
+
+/// The sequence which puts a debug port in the JTAG state into the dormant state.
+pub fn jtag_to_dormant() -> SwjSequence {
+    vec![
+        // At least 5 cycles with TMS high, to get the TAP into the Test-Logic-Reset state.
+        (8, 0xFF),
+        // The 31-bit JTAG to dormant select sequence, followed by one cycle with TMS low.
+        (32, 0x33BB_BBBA),
+    ]
+}
+
+/// The sequence which wakes up a debug port in the dormant state, and activates the given protocol.
+pub fn dormant_to(protocol: WireProtocol) -> SwjSequence {
+    let activation_code = match protocol {
+        WireProtocol::Swd => ACTIVATION_CODE_SWD,
+        WireProtocol::Jtag => ACTIVATION_CODE_JTAG,
+    };
+
+    let mut sequence = vec![
+        // At least 8 cycles with SWDIO/TMS high.
+        (8, 0xFF),
+        (64, SELECTION_ALERT as u64),
+        (64, (SELECTION_ALERT >> 64) as u64),
+        // 4 cycles with SWDIO/TMS low, followed by the activation code.
+        (12, activation_code << 4),
+    ];
+
+    match protocol {
+        // The debug port is in the reset state after activation, and needs a line reset before it can be used.
+        WireProtocol::Swd => sequence.extend(line_reset()),
+        WireProtocol::Jtag => sequence.push((5, 0x1F)),
+    }
+
+    sequence
+}
+
+/// The sequence which puts the debug port into the dormant state, no matter in which state it is.
+///
+/// A debug port which is in the dormant state already ignores the whole sequence.
+pub fn any_to_dormant() -> SwjSequence {
+    // From SWD, this is a line reset followed by the SWD to dormant sequence. From JTAG, these bits
+    // end with the TAP in the Test-Logic-Reset state, from which the JTAG to dormant sequence works.
+    let mut sequence = swd_to_dormant();
+    sequence.extend(line_reset());
+    sequence.extend(jtag_to_dormant());
+    sequence
+}
+
+/// The sequence which switches the debug port to `protocol`, using the given `mode`.
+pub fn switch_to(protocol: WireProtocol, mode: SwjSwitchMode) -> SwjSequence {
+    match (mode, protocol) {
+        (SwjSwitchMode::Legacy, WireProtocol::Swd) => jtag_to_swd(),
+        (SwjSwitchMode::Legacy, WireProtocol::Jtag) => swd_to_jtag(),
+        (SwjSwitchMode::Dormant, protocol) => {
+            let mut sequence = any_to_dormant();
+            sequence.extend(dormant_to(protocol));
+            sequence
+        }
+    }
+}
+
+/// Send a sequence to the debug port.
+pub fn send(
+    interface: &mut (impl RawDapAccess + ?Sized),
+    sequence: &[(u8, u64)],
+) -> Result<(), DebugProbeError> {
+    for &(bit_len, bits) in sequence {
+        interface.swj_sequence(bit_len, bits)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Flatten a sequence into the bytes which are sent, LSB first.
+    fn to_bytes(sequence: &[(u8, u64)]) -> (usize, Vec<u8>) {
+        let mut bits = Vec::new();
+        for &(bit_len, value) in sequence {
+            bits.extend((0..bit_len).map(|bit| (value >> bit) & 1 == 1));
+        }
+
+        let bytes = bits
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (index, &bit)| byte | (bit as u8) << index)
+            })
+            .collect();
+        (bits.len(), bytes)
+    }
+
+    #[test]
+    fn dormant_to_swd_matches_specification() {
+        let (len, bytes) = to_bytes(&dormant_to(WireProtocol::Swd)[..4]);
+
+        assert_eq!(len, 148);
+        assert_eq!(
+            bytes,
+            [
+                0xff, 0x92, 0xf3, 0x09, 0x62, 0x95, 0x2d, 0x85, 0x86, 0xe9, 0xaf, 0xdd, 0xe3, 0xa2,
+                0x0e, 0xbc, 0x19, 0xa0, 0x01
+            ]
+        );
+    }
+
+    #[test]
+    fn swd_to_dormant_matches_specification() {
+        let (len, bytes) = to_bytes(&swd_to_dormant());
+
+        assert_eq!(len, 67);
+        assert_eq!(&bytes[6..], [0xe7, 0x1d, 0x07]);
+    }
+
+    #[test]
+    fn jtag_to_dormant_matches_specification() {
+        let (len, bytes) = to_bytes(&jtag_to_dormant());
+
+        assert_eq!(len, 40);
+        assert_eq!(bytes, [0xff, 0xba, 0xbb, 0xbb, 0x33]);
+    }
+}
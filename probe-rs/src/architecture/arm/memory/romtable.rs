@@ -104,7 +104,7 @@ impl<'probe, 'memory, 'reader> Iterator for RomTableIterator<'probe, 'memory, 'r
     }
 }
 
-/// Encapsulates information about a CoreSight ROM table (class 1).
+/// Encapsulates information about a CoreSight ROM table (class 1 or class 9).
 #[derive(Clone, Debug, PartialEq)]
 pub struct RomTable {
     /// ALL the entries in the romtable in flattened fashion.
@@ -393,6 +393,9 @@ enum RawComponent {
     CoreLinkOrPrimeCellOrSystemComponent = 0xF,
 }
 
+/// DEVARCH.ARCHID of a Class 0x9 ROM table.
+const CLASS_9_ROM_TABLE_ARCH_ID: u16 = 0x0AF7;
+
 /// This enum describes a CoreSight component.
 /// Described in table D1-2 in the ADIv5.2 spec.
 #[derive(Clone, Debug, PartialEq)]
@@ -406,6 +409,9 @@ pub enum Component {
 
     /// A CoreSight component can be a Class 0x9 ROM Table, which can be identified from the DEVARCH.ARCHID having the value 0x0AF7. See also _ROM Table Types on page D2-237_. For detailed information about Class 0x9 ROM Tables, see _Chapter D4 Class 0x9 ROM Tables_.
     CoresightComponent(ComponentId),
+    /// Class 0x9 ROM Table, a CoreSight component with a DEVARCH.ARCHID of 0x0AF7.
+    /// For detailed information about Class 0x9 ROM Tables, see _Chapter D4 Class 0x9 ROM Tables_.
+    Class9RomTable(ComponentId, RomTable),
     /// Peripheral Test Block.
     PeripheralTestBlock(ComponentId),
     /// Generic IP component.
@@ -446,6 +452,14 @@ impl Component {
 
                 Component::Class1RomTable(component_id, rom_table)
             }
+            RawComponent::CoreSightComponent
+                if component_id.peripheral_id.arch_id() == CLASS_9_ROM_TABLE_ARCH_ID =>
+            {
+                // Class 0x9 entries have the same layout as Class 0x1 entries, as far as the parsing is concerned.
+                let rom_table = RomTable::try_parse(memory, component_id.component_address)?;
+
+                Component::Class9RomTable(component_id, rom_table)
+            }
             RawComponent::CoreSightComponent => Component::CoresightComponent(component_id),
             RawComponent::PeripheralTestBlock => Component::PeripheralTestBlock(component_id),
             RawComponent::GenericIPComponent => Component::GenericIPComponent(component_id),
@@ -463,6 +477,7 @@ impl Component {
             Component::GenericVerificationComponent(component_id) => component_id,
             Component::Class1RomTable(component_id, ..) => component_id,
             Component::CoresightComponent(component_id, ..) => component_id,
+            Component::Class9RomTable(component_id, ..) => component_id,
             Component::PeripheralTestBlock(component_id) => component_id,
             Component::GenericIPComponent(component_id) => component_id,
             Component::CoreLinkOrPrimeCellOrSystemComponent(component_id) => component_id,
@@ -570,9 +585,11 @@ impl<'a> Iterator for CoresightComponentIter<'a> {
         if let Some(component) = self.components.get(self.current) {
             // If it has children, remember to iterate them next.
             self.children = match &component.component {
-                Component::Class1RomTable(_, v) => Some(Box::new(CoresightComponentIter::new(
-                    v.entries.iter().map(|v| &v.component).collect(),
-                ))),
+                Component::Class1RomTable(_, v) | Component::Class9RomTable(_, v) => {
+                    Some(Box::new(CoresightComponentIter::new(
+                        v.entries.iter().map(|v| &v.component).collect(),
+                    )))
+                }
                 _ => None,
             };
             // Advance the pointer by one.
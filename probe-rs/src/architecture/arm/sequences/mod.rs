@@ -21,7 +21,11 @@ use std::{
 
 use probe_rs_target::CoreType;
 
-use crate::{architecture::arm::ArmProbeInterface, DebugProbeError, MemoryMappedRegister};
+use crate::{
+    architecture::arm::ArmProbeInterface,
+    probe::transaction_log::{self, DapPort},
+    DebugProbeError, MemoryMappedRegister,
+};
 
 use super::{
     ap::{AccessPortError, MemoryAp},
@@ -29,7 +33,10 @@ use super::{
     communication_interface::{DapProbe, Initialized},
     component::{TraceFunnel, TraceSink},
     core::cortex_m::Dhcsr,
-    dp::{Abort, Ctrl, DebugPortError, DpAccess, Select, DPIDR},
+    dp::{
+        swj::{self, SwjSwitchMode},
+        Abort, Ctrl, DebugPortError, DpAccess, Select, DPIDR,
+    },
     memory::{
         adi_v5_memory_interface::ArmProbe,
         romtable::{CoresightComponent, PeripheralType},
@@ -457,28 +464,19 @@ pub trait ArmDebugSequence: Send + Sync {
 
         // TODO: Use atomic block
 
-        // Ensure current debug interface is in reset state.
-        interface.swj_sequence(51, 0x0007_FFFF_FFFF_FFFF)?;
-
         // Make sure the debug port is in the correct mode based on what the probe
-        // has selected via active_protocol
-        match interface.active_protocol() {
-            Some(crate::WireProtocol::Jtag) => {
-                // Execute SWJ-DP Switch Sequence SWD to JTAG (0xE73C).
-                interface.swj_sequence(16, 0xE73C)?;
-            }
-            Some(crate::WireProtocol::Swd) => {
-                // Execute SWJ-DP Switch Sequence JTAG to SWD (0xE79E).
-                // Change if SWJ-DP uses deprecated switch code (0xEDB6).
-                interface.swj_sequence(16, 0xE79E)?;
-            }
-            _ => {
+        // has selected via active_protocol. All switch sequences start by putting the
+        // current debug interface into the reset state.
+        let protocol = match interface.active_protocol() {
+            Some(protocol) => protocol,
+            None => {
                 return Err(ArmDebugSequenceError::SequenceSpecific(
                     "Cannot detect current protocol".into(),
                 )
                 .into());
             }
-        }
+        };
+        swj::send(interface, &swj::switch_to(protocol, self.swj_switch_mode()))?;
 
         interface.swj_sequence(51, 0x0007_FFFF_FFFF_FFFF)?; // > 50 cycles SWDIO/TMS High.
         interface.swj_sequence(3, 0x00)?; // At least 2 idle cycles (SWDIO/TMS Low).
@@ -486,7 +484,16 @@ pub trait ArmDebugSequence: Send + Sync {
         // End of atomic block.
 
         // Read DPIDR to enable SWD interface.
-        let _ = interface.raw_read_register(PortType::DebugPort, DPIDR::ADDRESS);
+        let start = Instant::now();
+        let result = interface.raw_read_register(PortType::DebugPort, DPIDR::ADDRESS);
+        transaction_log::record_dap(
+            start,
+            DapPort::Dp(DpAddress::Default),
+            DPIDR::ADDRESS,
+            false,
+            result.as_ref().map_or(&[][..], std::slice::from_ref),
+            result.as_ref().err(),
+        );
 
         // TODO: Figure a way how to do this.
         // interface.read_dpidr()?;
@@ -494,6 +501,14 @@ pub trait ArmDebugSequence: Send + Sync {
         Ok(())
     }
 
+    /// How [`ArmDebugSequence::debug_port_setup`] switches the debug port to the protocol selected by the probe.
+    ///
+    /// Parts which power up in the dormant state, or which might have been left in it by another tool,
+    /// should return [`SwjSwitchMode::Dormant`].
+    fn swj_switch_mode(&self) -> SwjSwitchMode {
+        SwjSwitchMode::Legacy
+    }
+
     /// Connect to the target debug port and power it up. This is based on the
     /// `DebugPortStart` function from the [ARM SVD Debug Description].
     ///
@@ -705,6 +720,23 @@ pub trait ArmDebugSequence: Send + Sync {
         Ok(())
     }
 
+    /// Authenticate to a device which only allows debug access after answering a challenge,
+    /// using the [`DebugAuthenticator`](crate::DebugAuthenticator) given in the [`Permissions`](crate::Permissions).
+    ///
+    /// Executed after having powered up the debug port, before [`ArmDebugSequence::debug_device_unlock`],
+    /// and only if an authenticator was given.
+    fn debug_authenticate(
+        &self,
+        _interface: &mut dyn ArmProbeInterface,
+        _default_ap: MemoryAp,
+        _authenticator: &dyn crate::DebugAuthenticator,
+    ) -> Result<(), ArmError> {
+        tracing::warn!(
+            "Debug authentication is not supported for this target, ignoring the authenticator."
+        );
+        Ok(())
+    }
+
     /// Executed before step or run command to support recovery from a lost target connection, e.g. after a low power mode.
     /// This is based on the `RecoverSupportStart` function from the [ARM SVD Debug Description].
     ///
@@ -13,9 +13,10 @@ use crate::{
         core::armv7m::{Aircr, Demcr, Dhcsr},
         dp::{Abort, Ctrl, DpAccess, Select, DPIDR},
         memory::adi_v5_memory_interface::ArmProbe,
-        ApAddress, ArmCommunicationInterface, ArmError, DapAccess, DpAddress,
+        ApAddress, ArmCommunicationInterface, ArmError, ArmProbeInterface, DapAccess, DpAddress,
     },
     core::MemoryMappedRegister,
+    AuthenticationChallenge, AuthenticationProtocol, DebugAuthenticator,
 };
 
 use super::ArmDebugSequence;
@@ -232,6 +233,132 @@ impl ArmDebugSequence for LPC55Sxx {
 
         wait_for_stop_after_reset(interface)
     }
+
+    fn debug_authenticate(
+        &self,
+        interface: &mut dyn ArmProbeInterface,
+        default_ap: MemoryAp,
+        authenticator: &dyn DebugAuthenticator,
+    ) -> Result<(), ArmError> {
+        let ap = ApAddress {
+            dp: default_ap.ap_address().dp,
+            ap: DEBUG_MAILBOX_AP,
+        };
+
+        // Resynchronize the mailbox, in case a previous command was aborted.
+        interface.write_raw_ap_register(ap, DM_CSW, 0x0000_0021)?;
+        thread::sleep(Duration::from_micros(30000));
+
+        let challenge = mailbox_command(interface, ap, DM_CMD_DBG_AUTH_START, &[])?;
+        tracing::debug!(
+            "Received debug credential challenge of {} words",
+            challenge.len()
+        );
+
+        let response = authenticator
+            .respond(&AuthenticationChallenge {
+                protocol: AuthenticationProtocol::NxpDebugMailbox,
+                data: challenge
+                    .iter()
+                    .flat_map(|word| word.to_le_bytes())
+                    .collect(),
+            })
+            .map_err(ArmError::Other)?;
+
+        if response.len() % 4 != 0 {
+            return Err(ArmError::Other(anyhow::anyhow!(
+                "The debug authentication response has to be a multiple of 4 bytes long, but is {} bytes long",
+                response.len()
+            )));
+        }
+
+        let response: Vec<u32> = response
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+
+        mailbox_command(interface, ap, DM_CMD_DBG_AUTH_RESP, &response)?;
+        tracing::info!("Debug authentication succeeded");
+
+        // The access ports of the cores only become accessible now, so they have to be discovered again.
+        Err(ArmError::ReAttachRequired)
+    }
+}
+
+/// The access port of the debug mailbox.
+const DEBUG_MAILBOX_AP: u8 = 2;
+
+/// Control and status register of the debug mailbox.
+const DM_CSW: u8 = 0x0;
+/// Request register of the debug mailbox, which takes the command and the parameters.
+const DM_REQUEST: u8 = 0x4;
+/// Return register of the debug mailbox, which contains the status and the response.
+const DM_RETURN: u8 = 0x8;
+
+/// Start debug authentication, the response is the debug credential challenge.
+const DM_CMD_DBG_AUTH_START: u16 = 0x10;
+/// Send the debug authentication response.
+const DM_CMD_DBG_AUTH_RESP: u16 = 0x11;
+
+/// Upper half of the word which acknowledges a response word.
+const DM_ACK_TOKEN: u32 = 0xA5A5 << 16;
+
+/// Read the return register of the debug mailbox, which fails while the mailbox is still busy.
+fn mailbox_read(interface: &mut dyn ArmProbeInterface, ap: ApAddress) -> Result<u32, ArmError> {
+    let start = Instant::now();
+
+    loop {
+        match interface.read_raw_ap_register(ap, DM_RETURN) {
+            Ok(value) => return Ok(value),
+            Err(_) if start.elapsed() < Duration::from_secs(1) => {
+                thread::sleep(Duration::from_millis(1))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Run a command of the debug mailbox, and return the response words.
+fn mailbox_command(
+    interface: &mut dyn ArmProbeInterface,
+    ap: ApAddress,
+    command: u16,
+    parameters: &[u32],
+) -> Result<Vec<u32>, ArmError> {
+    interface.write_raw_ap_register(
+        ap,
+        DM_REQUEST,
+        u32::from(command) | (parameters.len() as u32) << 16,
+    )?;
+
+    for parameter in parameters {
+        // Every word of the request is acknowledged before the next one is sent.
+        mailbox_read(interface, ap)?;
+        interface.write_raw_ap_register(ap, DM_REQUEST, *parameter)?;
+    }
+
+    let status = mailbox_read(interface, ap)?;
+    if status & 0xFFFF != 0 {
+        return Err(ArmError::Other(anyhow::anyhow!(
+            "Debug mailbox command {:#x} failed with status {:#x}",
+            command,
+            status & 0xFFFF
+        )));
+    }
+
+    let response_len = (status >> 16) & 0x7FFF;
+    let mut response = Vec::with_capacity(response_len as usize);
+
+    for index in 0..response_len {
+        interface.write_raw_ap_register(ap, DM_REQUEST, DM_ACK_TOKEN | index)?;
+        response.push(mailbox_read(interface, ap)?);
+    }
+
+    if response_len > 0 {
+        interface.write_raw_ap_register(ap, DM_REQUEST, DM_ACK_TOKEN | response_len)?;
+    }
+
+    Ok(response)
 }
 
 fn wait_for_stop_after_reset(memory: &mut dyn ArmProbe) -> Result<(), ArmError> {
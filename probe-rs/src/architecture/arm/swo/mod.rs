@@ -33,6 +33,9 @@ pub struct SwoConfig {
     /// This is required to use ETM over SWO, but otherwise
     /// adds overhead if only DWT/ITM data is used.
     tpiu_continuous_formatting: bool,
+
+    /// Whether the SWO output is received by an external serial adapter instead of the probe.
+    external_capture: bool,
 }
 
 impl SwoConfig {
@@ -46,6 +49,7 @@ impl SwoConfig {
             baud: 1_000_000,
             tpiu_clk,
             tpiu_continuous_formatting: false,
+            external_capture: false,
         }
     }
 
@@ -79,6 +83,14 @@ impl SwoConfig {
         self
     }
 
+    /// Set whether the SWO output is received by an external serial adapter, e.g. a USB-UART connected to the
+    /// SWO pin, instead of the probe. The SWO capture of the probe is not enabled then, and the data is read
+    /// with a [`SerialSwoReader`].
+    pub fn set_external_capture(mut self, enabled: bool) -> Self {
+        self.external_capture = enabled;
+        self
+    }
+
     /// The SWO mode.
     pub fn mode(&self) -> SwoMode {
         self.mode
@@ -103,6 +115,11 @@ impl SwoConfig {
     pub fn tpiu_continuous_formatting(&self) -> bool {
         self.tpiu_continuous_formatting
     }
+
+    /// Whether the SWO output is received by an external serial adapter instead of the probe.
+    pub fn external_capture(&self) -> bool {
+        self.external_capture
+    }
 }
 
 /// An interface to operate SWO to be implemented on drivers that support SWO.
@@ -210,3 +227,45 @@ impl<'a> std::io::Read for SwoReader<'a> {
         Ok(swo.len())
     }
 }
+
+/// A reader for SWO data which is received by a serial adapter connected to the SWO pin, for probes without SWO
+/// capture. The SWO output has to use the UART mode, with the baud rate of the serial port.
+#[cfg(feature = "serial")]
+pub struct SerialSwoReader {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+#[cfg(feature = "serial")]
+impl SerialSwoReader {
+    /// Open the serial port at `path` with the given baud rate.
+    pub fn open(path: &str, baud: u32) -> Result<Self, serialport::Error> {
+        let port = serialport::new(path, baud)
+            .timeout(std::time::Duration::from_millis(10))
+            .open()?;
+
+        // Discard whatever was received before the trace was configured.
+        port.clear(serialport::ClearBuffer::Input)?;
+
+        Ok(Self { port })
+    }
+}
+
+#[cfg(feature = "serial")]
+impl std::fmt::Debug for SerialSwoReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerialSwoReader")
+            .field("port", &self.port.name())
+            .finish()
+    }
+}
+
+#[cfg(feature = "serial")]
+impl std::io::Read for SerialSwoReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match std::io::Read::read(&mut self.port, buf) {
+            // Like the SWO capture of a probe, no data is not the end of the stream.
+            Err(error) if error.kind() == std::io::ErrorKind::TimedOut => Ok(0),
+            result => result,
+        }
+    }
+}
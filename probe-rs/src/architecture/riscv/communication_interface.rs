@@ -294,6 +294,11 @@ impl RiscvCommunicationInterface {
         Ok(s)
     }
 
+    /// Assert the target reset.
+    pub fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        self.dtm.target_reset_assert()
+    }
+
     /// Deassert the target reset.
     pub fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
         self.dtm.target_reset_deassert()
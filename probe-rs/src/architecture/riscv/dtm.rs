@@ -11,7 +11,10 @@ use bitfield::bitfield;
 
 use super::communication_interface::RiscvError;
 use crate::{
-    probe::{CommandResult, DeferredResultIndex, JTAGAccess, JtagWriteCommand},
+    probe::{
+        transaction_log::{self, Ack},
+        BatchExecutionError, CommandResult, DeferredResultIndex, JTAGAccess, JtagWriteCommand,
+    },
     DebugProbeError,
 };
 
@@ -32,7 +35,11 @@ impl Dtm {
         // Always 5 bits for now
         probe.set_ir_len(5);
 
-        let dtmcs_raw = match probe.read_register(DTMCS_ADDRESS, DTMCS_WIDTH) {
+        let start = Instant::now();
+        let result = probe.read_register(DTMCS_ADDRESS, DTMCS_WIDTH);
+        record_scan(start, DTMCS_ADDRESS, DTMCS_WIDTH, &[], &result);
+
+        let dtmcs_raw = match result {
             Ok(value) => value,
             Err(e) => return Err((probe, e.into())),
         };
@@ -67,12 +74,19 @@ impl Dtm {
         })
     }
 
+    pub fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        self.probe.target_reset_assert()
+    }
+
     pub fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
         self.probe.target_reset_deassert()
     }
 
     pub fn read_idcode(&mut self) -> Result<u32, DebugProbeError> {
-        let value = self.probe.read_register(0x1, 32)?;
+        let start = Instant::now();
+        let result = self.probe.read_register(IDCODE_ADDRESS, 32);
+        record_scan(start, IDCODE_ADDRESS, 32, &[], &result);
+        let value = result?;
 
         Ok(u32::from_le_bytes((&value[..]).try_into().unwrap()))
     }
@@ -87,8 +101,12 @@ impl Dtm {
 
         let bytes = reg_value.to_le_bytes();
 
-        self.probe
-            .write_register(DTMCS_ADDRESS, &bytes, DTMCS_WIDTH)?;
+        let start = Instant::now();
+        let result = self
+            .probe
+            .write_register(DTMCS_ADDRESS, &bytes, DTMCS_WIDTH);
+        record_scan(start, DTMCS_ADDRESS, DTMCS_WIDTH, &bytes, &result);
+        result?;
 
         Ok(())
     }
@@ -97,7 +115,11 @@ impl Dtm {
         let cmds = self.queued_commands.clone();
         self.queued_commands = Vec::new();
 
-        match self.probe.write_register_batch(&cmds) {
+        let start = Instant::now();
+        let result = self.probe.write_register_batch(&cmds);
+        record_batch(start, &cmds, &result);
+
+        match result {
             Ok(r) => Ok(r),
             Err(e) => match e.error {
                 crate::Error::Riscv(ae) => {
@@ -182,7 +204,10 @@ impl Dtm {
 
         let bit_size = self.abits + DMI_ADDRESS_BIT_OFFSET;
 
-        let response_bytes = self.probe.write_register(DMI_ADDRESS, &bytes, bit_size)?;
+        let start = Instant::now();
+        let result = self.probe.write_register(DMI_ADDRESS, &bytes, bit_size);
+        record_scan(start, DMI_ADDRESS, bit_size, &bytes, &result);
+        let response_bytes = result?;
 
         let response_value: u128 = response_bytes.iter().enumerate().fold(0, |acc, elem| {
             let (byte_offset, value) = elem;
@@ -231,6 +256,70 @@ impl Dtm {
     }
 }
 
+/// Record a scan of a JTAG register in the transaction log. The acknowledgement of a `dmi` scan is its `op` field.
+fn record_scan(
+    start: Instant,
+    instruction: u32,
+    length: u32,
+    data: &[u8],
+    result: &Result<Vec<u8>, DebugProbeError>,
+) {
+    let (captured, ack) = match result {
+        Ok(captured) if instruction == DMI_ADDRESS => (
+            captured.as_slice(),
+            dmi_ack(captured.first().map_or(0, |op| op & DMI_OP_MASK as u8)),
+        ),
+        Ok(captured) => (captured.as_slice(), Ack::Ok),
+        Err(_) => (&[][..], Ack::Error),
+    };
+    transaction_log::record_jtag(start, instruction, length, data, captured, ack);
+}
+
+/// Record the scans of a batch in the transaction log. The scans after a failed one were not executed.
+fn record_batch(
+    start: Instant,
+    commands: &[JtagWriteCommand],
+    result: &Result<Vec<CommandResult>, BatchExecutionError>,
+) {
+    if !transaction_log::is_enabled() {
+        return;
+    }
+
+    let (executed, failure) = match result {
+        Ok(results) => (results.len(), None),
+        Err(error) => {
+            let ack = match &error.error {
+                crate::Error::Riscv(RiscvError::DmiTransfer(status)) => dmi_ack(*status as u8),
+                _ => Ack::Error,
+            };
+            (error.results.len(), Some(ack))
+        }
+    };
+
+    for command in &commands[..executed.min(commands.len())] {
+        transaction_log::record_jtag(
+            start,
+            command.address,
+            command.len,
+            &command.data,
+            &[],
+            Ack::Ok,
+        );
+    }
+    if let (Some(ack), Some(command)) = (failure, commands.get(executed)) {
+        transaction_log::record_jtag(start, command.address, command.len, &command.data, &[], ack);
+    }
+}
+
+/// The acknowledgement of the `op` field of the `dmi` register.
+fn dmi_ack(op: u8) -> Ack {
+    match op {
+        2 => Ack::Fault,
+        3 => Ack::Wait,
+        _ => Ack::Ok,
+    }
+}
+
 bitfield! {
     /// The `dtmcs` register is
     struct Dtmcs(u32);
@@ -247,6 +336,9 @@ bitfield! {
 /// Address of the `dtmcs` JTAG register.
 const DTMCS_ADDRESS: u32 = 0x10;
 
+/// Address of the `idcode` JTAG register.
+const IDCODE_ADDRESS: u32 = 0x1;
+
 /// Width of the `dtmcs` JTAG register.
 const DTMCS_WIDTH: u32 = 32;
 
@@ -271,7 +363,7 @@ pub enum DmiOperation {
 
 /// Possible return values in the op field of
 /// the dmi register.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum DmiOperationStatus {
     Ok = 0,
     Reserved = 1,
@@ -0,0 +1,255 @@
+//! An async facade for [`Session`], for host applications which drive many probes from one async runtime.
+//!
+//! All probe operations are blocking, so they are executed on the threads of a [`BlockingExecutor`]. The executor
+//! is shared between all sessions, which means that hundreds of probes can be driven by a handful of threads.
+//! The returned futures do not depend on a specific runtime, and can be awaited from `tokio`, `async-std`, or any other executor.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), probe_rs::Error> {
+//! use std::time::Duration;
+//! use probe_rs::{AsyncSession, BlockingExecutor, Permissions};
+//!
+//! let executor = BlockingExecutor::new(4);
+//! let session = AsyncSession::auto_attach("nrf52840_xxAA", Permissions::default(), executor).await?;
+//!
+//! session.halt(0, Duration::from_millis(100)).await?;
+//! let value = session.read_word_32(0, 0x2000_0000).await?;
+//! session.run(0).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::config::TargetSelector;
+use crate::{
+    Core, CoreInformation, CoreStatus, Error, MemoryInterface, Permissions, Probe, Session,
+    SharedSession,
+};
+use futures_channel::oneshot;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed pool of threads, which runs the blocking probe operations of [`AsyncSession`]s.
+pub struct BlockingExecutor {
+    sender: Mutex<mpsc::Sender<Job>>,
+}
+
+impl BlockingExecutor {
+    /// Start a new executor with the given number of worker threads.
+    ///
+    /// The threads are stopped once the executor, and all sessions using it, are dropped.
+    pub fn new(threads: usize) -> Arc<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for index in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("probe-rs-executor-{index}"))
+                .spawn(move || loop {
+                    // The lock is released before the job runs, so that other workers can pick up jobs in the meantime.
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => break,
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("Failed to spawn probe-rs executor thread");
+        }
+
+        Arc::new(Self {
+            sender: Mutex::new(sender),
+        })
+    }
+
+    /// Run a blocking function on one of the worker threads, and wait for its result.
+    pub async fn spawn<F, R>(&self, function: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            // The receiver is gone if the future was dropped, in which case nobody is interested in the result.
+            let _ = result_sender.send(function());
+        });
+
+        self.sender
+            .lock()
+            .map_err(|_| anyhow::anyhow!("The probe-rs executor is poisoned."))?
+            .send(job)
+            .map_err(|_| anyhow::anyhow!("The probe-rs executor has been stopped."))?;
+
+        result_receiver
+            .await
+            .map_err(|_| Error::Other(anyhow::anyhow!("A blocking probe-rs operation panicked.")))
+    }
+}
+
+/// An async handle to a [`Session`].
+///
+/// The handle can be cloned, and all clones refer to the same session. Operations on the same session are
+/// executed one after the other, operations on different sessions run in parallel.
+///
+/// The session can also be used by blocking code at the same time, with [`AsyncSession::shared`].
+#[derive(Clone)]
+pub struct AsyncSession {
+    session: Arc<SharedSession>,
+    executor: Arc<BlockingExecutor>,
+}
+
+impl AsyncSession {
+    /// Wrap an existing session.
+    pub fn new(session: Session, executor: Arc<BlockingExecutor>) -> Self {
+        Self {
+            session: Arc::new(SharedSession::new(session)),
+            executor,
+        }
+    }
+
+    /// Open the first probe which is found, and attach to the target. See [`Session::auto_attach`].
+    pub async fn auto_attach(
+        target: impl Into<TargetSelector>,
+        permissions: Permissions,
+        executor: Arc<BlockingExecutor>,
+    ) -> Result<Self, Error> {
+        let target = target.into();
+        let session = executor
+            .spawn(move || Session::auto_attach(target, permissions))
+            .await??;
+        Ok(Self::new(session, executor))
+    }
+
+    /// Attach to the target with the given probe. See [`Probe::attach`].
+    pub async fn attach(
+        probe: Probe,
+        target: impl Into<TargetSelector>,
+        permissions: Permissions,
+        executor: Arc<BlockingExecutor>,
+    ) -> Result<Self, Error> {
+        let target = target.into();
+        let session = executor
+            .spawn(move || probe.attach(target, permissions))
+            .await??;
+        Ok(Self::new(session, executor))
+    }
+
+    /// Run a blocking function with exclusive access to the session.
+    pub async fn with_session<F, R>(&self, function: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Session) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        let session = self.session.clone();
+        self.executor
+            .spawn(move || {
+                let mut session = session.lock();
+                function(&mut session)
+            })
+            .await?
+    }
+
+    /// Run a blocking function with exclusive access to the core with the given index.
+    pub async fn with_core<F, R>(&self, core_index: usize, function: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Core) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.with_session(move |session| function(&mut session.core(core_index)?))
+            .await
+    }
+
+    /// See [`Core::status`].
+    pub async fn status(&self, core_index: usize) -> Result<CoreStatus, Error> {
+        self.with_core(core_index, |core| core.status()).await
+    }
+
+    /// See [`Core::halt`].
+    pub async fn halt(
+        &self,
+        core_index: usize,
+        timeout: Duration,
+    ) -> Result<CoreInformation, Error> {
+        self.with_core(core_index, move |core| core.halt(timeout))
+            .await
+    }
+
+    /// See [`Core::run`].
+    pub async fn run(&self, core_index: usize) -> Result<(), Error> {
+        self.with_core(core_index, |core| core.run()).await
+    }
+
+    /// See [`Core::reset`].
+    pub async fn reset(&self, core_index: usize) -> Result<(), Error> {
+        self.with_core(core_index, |core| core.reset()).await
+    }
+
+    /// See [`Core::reset_and_halt`].
+    pub async fn reset_and_halt(
+        &self,
+        core_index: usize,
+        timeout: Duration,
+    ) -> Result<CoreInformation, Error> {
+        self.with_core(core_index, move |core| core.reset_and_halt(timeout))
+            .await
+    }
+
+    /// See [`MemoryInterface::read_word_32`].
+    pub async fn read_word_32(&self, core_index: usize, address: u64) -> Result<u32, Error> {
+        self.with_core(core_index, move |core| core.read_word_32(address))
+            .await
+    }
+
+    /// See [`MemoryInterface::write_word_32`].
+    pub async fn write_word_32(
+        &self,
+        core_index: usize,
+        address: u64,
+        data: u32,
+    ) -> Result<(), Error> {
+        self.with_core(core_index, move |core| core.write_word_32(address, data))
+            .await
+    }
+
+    /// Read `length` bytes, starting at `address`. See [`MemoryInterface::read_8`].
+    pub async fn read(
+        &self,
+        core_index: usize,
+        address: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, Error> {
+        self.with_core(core_index, move |core| {
+            let mut data = vec![0; length];
+            core.read_8(address, &mut data)?;
+            Ok(data)
+        })
+        .await
+    }
+
+    /// Write `data`, starting at `address`. See [`MemoryInterface::write_8`].
+    pub async fn write(&self, core_index: usize, address: u64, data: Vec<u8>) -> Result<(), Error> {
+        self.with_core(core_index, move |core| core.write_8(address, &data))
+            .await
+    }
+
+    /// The underlying [`SharedSession`], e.g. to run the GDB stub on it.
+    pub fn shared(&self) -> Arc<SharedSession> {
+        self.session.clone()
+    }
+
+    /// Take back the session, if this is the last handle to it.
+    ///
+    /// Otherwise, the handle is returned unchanged.
+    pub fn into_inner(self) -> Result<Session, Self> {
+        let executor = self.executor;
+        match Arc::try_unwrap(self.session) {
+            Ok(session) => Ok(session.into_inner()),
+            Err(session) => Err(Self { session, executor }),
+        }
+    }
+}
@@ -2,16 +2,35 @@ pub mod benchmark;
 pub mod cargo_embed;
 pub mod cargo_flash;
 pub mod chip;
+pub mod clocks;
+pub mod compare;
+pub mod dap;
 pub mod dap_server;
+pub mod data_trace;
 pub mod debug;
 pub mod download;
 pub mod dump;
 pub mod erase;
+pub mod flash_stats;
+pub mod fpga;
+#[cfg(feature = "filesystem")]
+pub mod fs;
 pub mod gdb;
 pub mod info;
 pub mod itm;
+pub mod jtag;
 pub mod list;
+pub mod mcuboot;
+pub mod mtb;
+pub mod probe;
 pub mod profile;
 pub mod reset;
 pub mod run;
+pub mod script;
+pub mod stack_usage;
+pub mod symbol;
+pub mod systemview;
+pub mod test;
+pub mod test_algorithm;
 pub mod trace;
+pub mod transaction_log;
@@ -35,8 +35,12 @@ pub struct Probe {
     pub usb_vid: Option<String>,
     pub usb_pid: Option<String>,
     pub serial: Option<String>,
+    pub usb_path: Option<String>,
     pub protocol: WireProtocol,
     pub speed: Option<u32>,
+    pub target_power: bool,
+    pub power_cycle_ms: Option<u64>,
+    pub power_settle_ms: u64,
 }
 
 /// The flashing config struct holding all the possible flashing options.
@@ -11,7 +11,8 @@ use probe_rs::rtt::{Rtt, ScanRegion};
 use probe_rs::{
     config::TargetSelector,
     flashing::{download_file_with_options, DownloadOptions, FlashProgress, Format, ProgressEvent},
-    DebugProbeSelector, Permissions, Probe, Session,
+    messages::{self, MessageId},
+    DebugProbeSelector, Permissions, Probe, SharedSession,
 };
 use std::ffi::OsString;
 use std::{
@@ -21,13 +22,14 @@ use std::{
     panic,
     path::{Path, PathBuf},
     process,
-    sync::{Arc, Mutex},
+    sync::Arc,
     time::{Duration, Instant},
 };
 use time::{OffsetDateTime, UtcOffset};
 
 use self::rttui::channel::DataFormat;
-use crate::util::{build_artifact, common_options::CargoOptions, logging};
+use crate::util::flash::{finished_message, progress_label};
+use crate::util::{build_artifact, common_options::CargoOptions, logging, termination};
 
 #[derive(Debug, clap::Parser)]
 struct Opt {
@@ -38,7 +40,9 @@ struct Opt {
     #[clap(
         long = "probe",
         help = "Use this flag to select a specific probe in the list.\n\
-        Use '--probe VID:PID' or '--probe VID:PID:Serial' if you have more than one probe with the same VID:PID."
+        Use '--probe VID:PID' or '--probe VID:PID:Serial' if you have more than one probe with the same VID:PID.\n\
+        Use '--probe VID:PID@Path' to select the probe by its USB port, or the name of a probe alias.",
+        value_parser = crate::util::probe_aliases::parse_probe_selector
     )]
     probe_selector: Option<DebugProbeSelector>,
     #[clap(name = "disable-progressbars", long = "disable-progressbars")]
@@ -62,7 +66,10 @@ pub fn main(args: Vec<OsString>) {
         }
     };
 
-    match main_try(args, offset) {
+    let result = main_try(args, offset);
+    termination::exit_if_requested();
+
+    match result {
         Ok(_) => (),
         Err(e) => {
             // Ensure stderr is flushed before calling proces::exit,
@@ -180,6 +187,7 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
                     vendor_id: u16::from_str_radix(vid, 16)?,
                     product_id: u16::from_str_radix(pid, 16)?,
                     serial_number: config.probe.serial.clone(),
+                    usb_path: config.probe.usb_path.clone(),
                 };
                 // if two probes with the same VID:PID pair exist we just choose one
                 Probe::open(selector)?
@@ -236,6 +244,20 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
 
     log::info!("Protocol speed {} kHz", protocol_speed);
 
+    if let Some(off_time) = config.probe.power_cycle_ms {
+        probe
+            .power_cycle_target(
+                Duration::from_millis(off_time),
+                Duration::from_millis(config.probe.power_settle_ms),
+            )
+            .context("failed to power cycle the target")?;
+    } else if config.probe.target_power {
+        probe
+            .set_target_power(true)
+            .context("failed to switch on the target power")?;
+        std::thread::sleep(Duration::from_millis(config.probe.power_settle_ms));
+    }
+
     let permissions = if config.flashing.enabled || config.gdb.enabled {
         Permissions::new().allow_erase_all()
     } else {
@@ -263,6 +285,11 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
 
     if config.flashing.enabled {
         flash(&config, &mut session, path, opt.disable_progressbars)?;
+
+        // Ctrl-C is deferred until the flashing finished. Don't go on to reset and run the target.
+        if termination::requested() {
+            return Ok(());
+        }
     }
 
     if config.reset.enabled {
@@ -282,7 +309,7 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
         }
     }
 
-    let session = Arc::new(Mutex::new(session));
+    let session = Arc::new(SharedSession::new(session));
 
     let mut gdb_thread_handle = None;
 
@@ -295,18 +322,23 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
                 gdb_connection_string.as_deref().unwrap_or("127.0.0.1:1337");
 
             logging::println(format!(
-                "    {} listening at {}",
-                "GDB stub".green().bold(),
-                gdb_connection_string,
+                "    {}",
+                messages::format(
+                    MessageId::GdbStubListening,
+                    &[
+                        ("gdb_stub", &"GDB stub".green().bold()),
+                        ("address", &gdb_connection_string),
+                    ],
+                )
             ));
 
             let instances = {
-                let session = session.lock().unwrap();
+                let session = session.lock();
                 GdbInstanceConfiguration::from_session(&session, Some(gdb_connection_string))
             };
 
             if let Err(e) = probe_rs::gdb_server::run(&session, instances.iter()) {
-                logging::eprintln("During the execution of GDB an error was encountered:");
+                logging::eprintln(MessageId::GdbStubFailed.to_string());
                 logging::eprintln(format!("{e:?}"));
             }
         }));
@@ -375,13 +407,13 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
         let mut app = rttui::app::App::new(rtt, &config, logname)?;
         loop {
             {
-                let mut session_handle = session.lock().unwrap();
+                let mut session_handle = session.lock();
                 let mut core = session_handle.core(0)?;
 
                 app.poll_rtt(&mut core, offset)?;
 
                 app.render(defmt_state.as_ref());
-                if app.handle_event(&mut core) {
+                if app.handle_event(&mut core) || termination::requested() {
                     logging::println("Shutting down.");
                     return Ok(());
                 };
@@ -392,6 +424,12 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
     }
 
     if let Some(gdb_thread_handle) = gdb_thread_handle {
+        while !gdb_thread_handle.is_finished() {
+            if termination::requested() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
         let _ = gdb_thread_handle.join();
     }
 
@@ -405,11 +443,11 @@ fn main_try(mut args: Vec<OsString>, offset: UtcOffset) -> Result<()> {
 }
 
 fn rtt_config(
-    session: Arc<Mutex<Session>>,
+    session: Arc<SharedSession>,
     config: &config::Config,
     rtt: &mut Rtt,
 ) -> Result<(), anyhow::Error> {
-    let mut session_handle = session.lock().unwrap();
+    let mut session_handle = session.lock();
     let mut core = session_handle.core(0)?;
     let default_up_mode = config.rtt.up_mode;
 
@@ -492,7 +530,7 @@ impl DefmtInformation {
 
 /// Try to attach to RTT, with the given timeout
 fn rtt_attach(
-    session: Arc<Mutex<Session>>,
+    session: Arc<SharedSession>,
     timeout: Duration,
     rtt_region: &ScanRegion,
 ) -> Result<Rtt> {
@@ -510,7 +548,7 @@ fn rtt_attach(
         //
         // GDB is also using the session
         {
-            let mut session_handle = session.lock().unwrap();
+            let mut session_handle = session.lock();
             let memory_map = session_handle.target().memory_map.clone();
             let mut core = session_handle.core(0)?;
 
@@ -551,7 +589,7 @@ fn flash(
         let fill_progress = if config.flashing.restore_unwritten_bytes {
             let fill_progress = Arc::new(multi_progress.add(ProgressBar::new(0)));
             fill_progress.set_style(style.clone());
-            fill_progress.set_message("     Reading flash  ");
+            fill_progress.set_message(progress_label(MessageId::ProgressReadingFlash));
             Some(fill_progress)
         } else {
             None
@@ -563,12 +601,12 @@ fn flash(
             logging::set_progress_bar(erase_progress.clone());
         }
         erase_progress.set_style(style.clone());
-        erase_progress.set_message("     Erasing sectors");
+        erase_progress.set_message(progress_label(MessageId::ProgressErasingSectors));
 
         // Create a new progress bar for the program progress.
         let program_progress = multi_progress.add(ProgressBar::new(0));
         program_progress.set_style(style);
-        program_progress.set_message(" Programming pages  ");
+        program_progress.set_message(progress_label(MessageId::ProgressProgrammingPages));
 
         let flash_layout_output_path = config.flashing.flash_layout_output_path.clone();
         // Register callback to update the progress.
@@ -640,6 +678,19 @@ fn flash(
                         fp.finish()
                     };
                 }
+                ProgrammerProgress {
+                    name,
+                    programmed,
+                    total,
+                } => {
+                    let phase =
+                        messages::format(MessageId::ProgressProgramming, &[("name", &name)]);
+                    let percent = programmed * 100 / total;
+                    program_progress.set_message(progress_label(messages::format(
+                        MessageId::ProgressPercent,
+                        &[("phase", &phase), ("percent", &percent)],
+                    )));
+                }
                 DiagnosticMessage { .. } => todo!(),
             }
         });
@@ -650,7 +701,7 @@ fn flash(
         options.keep_unwritten_bytes = config.flashing.restore_unwritten_bytes;
         options.do_chip_erase = config.flashing.do_chip_erase;
 
-        download_file_with_options(session, path, Format::Elf, options)
+        download_file_with_options(session, path, Format::default(), options)
             .with_context(|| format!("failed to flash {}", path.display()))?;
 
         // If we don't do this, the inactive progress bars will swallow log
@@ -661,14 +712,10 @@ fn flash(
         options.keep_unwritten_bytes = config.flashing.restore_unwritten_bytes;
         options.do_chip_erase = config.flashing.do_chip_erase;
 
-        download_file_with_options(session, path, Format::Elf, options)
+        download_file_with_options(session, path, Format::default(), options)
             .with_context(|| format!("failed to flash {}", path.display()))?;
     }
     let elapsed = instant.elapsed();
-    logging::println(format!(
-        "    {} flashing in {}s",
-        "Finished".green().bold(),
-        elapsed.as_millis() as f32 / 1000.0,
-    ));
+    logging::println(finished_message(elapsed));
     Ok(())
 }
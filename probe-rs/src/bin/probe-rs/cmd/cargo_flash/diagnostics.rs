@@ -9,6 +9,7 @@ use probe_rs::{
     config::MemoryRegion,
     config::{RegistryError, TargetDescriptionSource},
     flashing::{FileDownloadError, FlashError},
+    transaction_log::TransactionLogError,
     Error as ProbeRsError, Target,
 };
 
@@ -29,6 +30,24 @@ pub(crate) fn render_diagnostics(error: OperationError) {
                 "For a guide on how to set up your probes, see https://probe.rs/docs/getting-started/probe-setup".into(),
             ],
         ),
+        OperationError::NoProbesFoundInWsl => (
+            error.to_string(),
+            vec![
+                "Share the probe with 'usbipd bind --busid <BUSID>' on Windows, and attach it with 'probe-rs probe wsl --auto-attach'.".into(),
+                "udev doesn't run in WSL by default. Start it with 'sudo service udev restart', so the udev rules of the probe apply.".into(),
+            ],
+        ),
+        OperationError::FailedToReadTransactionLog { source, path } => (
+            error.to_string(),
+            match source {
+                TransactionLogError::Io(_) => vec![
+                    format!("Make sure the path '{}' is the location of a transaction log.", path.display())
+                ],
+                TransactionLogError::Malformed { .. } => vec![
+                    "Transaction logs are recorded with '--transaction-log <PATH>'. Make sure the file is a complete log, which was not edited.".into()
+                ],
+            },
+        ),
         OperationError::FailedToOpenElf { source, path } => (
             error.to_string(),
             match source.kind() {
@@ -71,6 +90,13 @@ pub(crate) fn render_diagnostics(error: OperationError) {
                 "This could be a permission issue. Check our guide on how to make all probes work properly on your system: https://probe.rs/docs/getting-started/probe-setup".into()
             ],
         ),
+        OperationError::FailedToControlTargetPower(_e) => (
+            error.to_string(),
+            vec![
+                "Not all probes can power the target. Currently, this is only supported by J-Link probes.".into(),
+                "Check that the power supply of the probe is connected to the target.".into(),
+            ],
+        ),
         OperationError::MultipleProbesFound { .. } => (
             error.to_string(),
             vec![
@@ -139,20 +165,42 @@ pub(crate) fn render_diagnostics(error: OperationError) {
             ),
         },
         OperationError::ChipNotFound { source, .. } => match source {
-            RegistryError::ChipNotFound(_) => (
-                error.to_string(),
-                vec![
-                    "Did you spell the name of your chip correctly? Capitalization does not matter."
-                        .into(),
-                    "Maybe your chip is not supported yet. You could add it yourself with our tool here: https://github.com/probe-rs/target-gen.".into(),
-                    "You can list all the available chips by running `probe-rs chip list`.".into(),
-                ],
-            ),
+            RegistryError::ChipNotFound(name) => {
+                let suggestions = probe_rs::config::suggest_chips(name);
+                (
+                    error.to_string(),
+                    if suggestions.is_empty() {
+                        vec![
+                            "Did you spell the name of your chip correctly? Capitalization does not matter."
+                                .into(),
+                            "Maybe your chip is not supported yet. You could add it yourself with our tool here: https://github.com/probe-rs/target-gen.".into(),
+                            "You can list all the available chips by running `probe-rs chip list`.".into(),
+                        ]
+                    } else {
+                        vec![
+                            format!("The following chips have similar names: {}", suggestions.join(", ")),
+                            "You can list all the available chips by running `probe-rs chip list`.".into(),
+                        ]
+                    },
+                )
+            }
             _ => (
                 error.to_string(),
                 vec![],
             ),
         },
+        OperationError::FailedBoardDescriptionParsing { .. } => (
+            error.to_string(),
+            vec![],
+        ),
+        OperationError::BoardNotFound { .. } => (
+            error.to_string(),
+            vec![
+                "Did you spell the name of your board correctly? Capitalization does not matter.".into(),
+                "You can list all the available boards by running `probe-rs chip boards`.".into(),
+                "Boards which are not built in can be added to the `boards.yaml` file in the probe-rs configuration directory.".into(),
+            ],
+        ),
         OperationError::FailedToSelectProtocol { .. } => (
             error.to_string(),
             vec![],
@@ -201,6 +249,16 @@ pub(crate) fn render_diagnostics(error: OperationError) {
             error.to_string(),
             vec![],
         ),
+        OperationError::ProvisioningFailed(_e) => (
+            error.to_string(),
+            vec![
+                "The unique ID of the connected chip is printed by `probe-rs info --chip <CHIP>`.".into(),
+            ],
+        ),
+        OperationError::Interrupted => (
+            error.to_string(),
+            vec![],
+        ),
     };
 
     use std::io::Write;
@@ -9,10 +9,13 @@ use crate::util::common_options::{CargoOptions, FlashOptions, OperationError};
 use crate::util::flash;
 use clap::{CommandFactory, FromArgMatches};
 
-use crate::util::{build_artifact, logging};
+use crate::util::{build_artifact, logging, termination};
 
 pub fn main(args: Vec<OsString>) {
-    match main_try(args) {
+    let result = main_try(args);
+    termination::exit_if_requested();
+
+    match result {
         Ok(_) => (),
         Err(e) => {
             // Ensure stderr is flushed before calling process::exit,
@@ -1,5 +1,7 @@
 use byte_unit::Byte;
 
+use crate::util::common_options::load_user_boards;
+
 #[derive(clap::Parser)]
 pub struct Cmd {
     #[clap(subcommand)]
@@ -18,6 +20,9 @@ enum Subcommand {
         /// The name of the chip to display.
         name: String,
     },
+    /// Lists all the available boards and their chips
+    #[clap(name = "boards")]
+    Boards,
 }
 
 impl Cmd {
@@ -25,8 +30,29 @@ impl Cmd {
         match self.subcommand {
             Subcommand::List => print_families().map_err(Into::into),
             Subcommand::Info { name } => print_chip_info(name),
+            Subcommand::Boards => print_boards(),
+        }
+    }
+}
+
+/// Print all the available boards, with the chips on them, to the commandline.
+pub fn print_boards() -> anyhow::Result<()> {
+    load_user_boards(None)?;
+
+    println!("Available boards:");
+    for board in probe_rs::config::boards() {
+        if board.aliases.is_empty() {
+            println!("    {}: {}", board.name, board.chip);
+        } else {
+            println!(
+                "    {} ({}): {}",
+                board.name,
+                board.aliases.join(", "),
+                board.chip
+            );
         }
     }
+    Ok(())
 }
 
 /// Print all the available families and their contained chips to the
@@ -80,5 +106,19 @@ pub fn print_chip_info(name: impl AsRef<str>) -> anyhow::Result<()> {
             ),
         };
     }
+
+    if !target.partitions.is_empty() {
+        println!("Partitions:");
+        for partition in target.partitions {
+            println!(
+                "    - {} ({:?}): {:#010x?} ({})",
+                partition.name,
+                partition.kind,
+                &partition.range,
+                Byte::from_bytes(get_range_len(&partition.range) as u128)
+                    .get_appropriate_unit(true)
+            );
+        }
+    }
     Ok(())
 }
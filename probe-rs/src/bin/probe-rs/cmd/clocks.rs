@@ -0,0 +1,89 @@
+//! Show the core clock frequency and the SysTick configuration of the target.
+
+use anyhow::Result;
+
+use crate::util::clocks::{read_core_clock, read_systick};
+use crate::util::common_options::ProbeOptions;
+use crate::util::parse_u64;
+use crate::CoreOptions;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    shared: CoreOptions,
+
+    #[clap(flatten)]
+    common: ProbeOptions,
+
+    /// The frequency of the external oscillator (HSE) in Hz, which can not be read from the chip
+    #[clap(long, value_parser = parse_u64)]
+    hse: Option<u64>,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let mut session = self.common.simple_attach()?;
+        let target_name = session.target().name.clone();
+        let mut core = session.core(self.shared.core)?;
+
+        let clock = read_core_clock(&target_name, &mut core, self.hse)?;
+        match &clock {
+            Some(clock) => match clock.frequency {
+                Some(frequency) => println!(
+                    "Core clock: {} ({})",
+                    format_frequency(frequency as f64),
+                    clock.source
+                ),
+                None => println!(
+                    "Core clock: unknown, {} depends on the external oscillator, whose frequency can be given with --hse",
+                    clock.source
+                ),
+            },
+            None => println!("Core clock: the clock tree of {target_name} is not known"),
+        }
+
+        if !core.core_type().is_cortex_m() {
+            return Ok(());
+        }
+
+        let systick = read_systick(&mut core, clock.as_ref())?;
+        let clock_source = if systick.core_clock_source {
+            "core clock"
+        } else {
+            "reference clock"
+        };
+        println!(
+            "SysTick: {}, interrupt {}, counting the {clock_source}, reload {}, current {}",
+            if systick.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            if systick.interrupt_enabled {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            systick.reload,
+            systick.current
+        );
+        if let Some(tick_frequency) = systick.tick_frequency {
+            println!(
+                "SysTick interrupt rate: {}",
+                format_frequency(tick_frequency)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn format_frequency(frequency: f64) -> String {
+    if frequency >= 1_000_000.0 {
+        format!("{:.3} MHz", frequency / 1_000_000.0)
+    } else if frequency >= 1_000.0 {
+        format!("{:.3} kHz", frequency / 1_000.0)
+    } else {
+        format!("{frequency:.3} Hz")
+    }
+}
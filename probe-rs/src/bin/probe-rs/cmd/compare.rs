@@ -0,0 +1,252 @@
+//! Compare the memory of the target with a firmware file.
+//!
+//! The file is loaded like it would be for flashing, and every loaded byte is read back from the target.
+//! Differing ranges are reported, and for ELF files they are attributed to the section and the symbol
+//! they belong to, so that it can be seen which parts of a build are not on the board.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use probe_rs::{
+    flashing::{FileDownloadError, Format},
+    MemoryInterface,
+};
+
+use crate::util::{common_options::ProbeOptions, symbol_address};
+use crate::{CoreOptions, FormatOptions};
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    shared: CoreOptions,
+
+    #[clap(flatten)]
+    common: ProbeOptions,
+
+    /// The path to the file to compare the memory of the target with
+    path: PathBuf,
+
+    #[clap(flatten)]
+    format_options: FormatOptions,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let mut session = self.common.simple_attach()?;
+
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) => return Err(FileDownloadError::IO(e)).context("Failed to open binary file."),
+        };
+
+        let mut loader = session.target().flash_loader();
+
+        let format = self.format_options.into_format()?;
+        let attribution = match format {
+            Format::Elf(_) => Some(ElfAttribution::load(&self.path)?),
+            _ => None,
+        };
+        match format {
+            Format::Bin(options) => loader.load_bin_data(&mut file, options),
+            Format::Elf(options) => loader.load_elf_data(&mut file, &options),
+            Format::Hex => loader.load_hex_data(&mut file),
+            Format::Idf(options) => loader.load_idf_data(&mut session, &mut file, options),
+        }?;
+
+        let mut core = session.core(self.shared.core)?;
+
+        let mut compared = 0;
+        let mut differing = Vec::new();
+        for (address, expected) in loader.data() {
+            let mut actual = vec![0; expected.len()];
+            core.read(address, &mut actual)
+                .with_context(|| format!("Failed to read the memory at {address:#010x}"))?;
+
+            compared += expected.len();
+            differing.extend(differences(address, expected, &actual));
+        }
+
+        for range in &differing {
+            let description = attribution
+                .as_ref()
+                .and_then(|attribution| attribution.describe(range.start))
+                .map(|description| format!(": {description}"))
+                .unwrap_or_default();
+            println!(
+                "{:#010x}..{:#010x} ({} bytes){description}",
+                range.start,
+                range.end,
+                range.end - range.start
+            );
+        }
+
+        let differing_bytes: u64 = differing.iter().map(|range| range.end - range.start).sum();
+        if differing.is_empty() {
+            println!(
+                "The memory of the target matches {} ({compared} bytes).",
+                self.path.display()
+            );
+            Ok(())
+        } else {
+            bail!(
+                "{differing_bytes} of {compared} bytes in {} ranges differ from {}",
+                differing.len(),
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// The address ranges in which `expected` and `actual`, which are both located at `address`, differ.
+fn differences(address: u64, expected: &[u8], actual: &[u8]) -> Vec<Range<u64>> {
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+
+    for (offset, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        if expected == actual {
+            continue;
+        }
+
+        let byte_address = address + offset as u64;
+        match ranges.last_mut() {
+            Some(range) if range.end == byte_address => range.end += 1,
+            _ => ranges.push(byte_address..byte_address + 1),
+        }
+    }
+
+    ranges
+}
+
+/// Finds the section and the symbol of a loaded address in an ELF file.
+struct ElfAttribution {
+    /// The load segments, as (physical range, virtual start address) pairs.
+    ///
+    /// Initialized data is loaded at a different address than it is used at, so the sections and symbols
+    /// are found by the virtual address.
+    segments: Vec<(Range<u64>, u64)>,
+    sections: Vec<(String, Range<u64>)>,
+    symbols: Vec<(String, Range<u64>)>,
+}
+
+impl ElfAttribution {
+    fn load(path: &Path) -> Result<Self> {
+        let buffer = std::fs::read(path)
+            .with_context(|| format!("Failed to read ELF file {}", path.display()))?;
+        let binary = goblin::elf::Elf::parse(buffer.as_slice())?;
+
+        let segments = binary
+            .program_headers
+            .iter()
+            .filter(|header| header.p_type == goblin::elf::program_header::PT_LOAD)
+            .map(|header| {
+                (
+                    header.p_paddr..header.p_paddr + header.p_filesz,
+                    header.p_vaddr,
+                )
+            })
+            .collect();
+
+        let sections = binary
+            .section_headers
+            .iter()
+            .filter(|header| header.is_alloc() && header.sh_size > 0)
+            .filter_map(|header| {
+                let name = binary.shdr_strtab.get_at(header.sh_name)?;
+                Some((
+                    name.to_string(),
+                    header.sh_addr..header.sh_addr + header.sh_size,
+                ))
+            })
+            .collect();
+
+        let symbols = binary
+            .syms
+            .iter()
+            .filter(|sym| sym.is_function() || sym.st_type() == goblin::elf::sym::STT_OBJECT)
+            .filter(|sym| sym.st_size > 0)
+            .filter_map(|sym| {
+                let name = binary.strtab.get_at(sym.st_name)?;
+                let address = symbol_address(&sym);
+                let name = addr2line::demangle_auto(Cow::from(name), None).into_owned();
+                Some((name, address..address + sym.st_size))
+            })
+            .collect();
+
+        Ok(Self {
+            segments,
+            sections,
+            symbols,
+        })
+    }
+
+    /// Describe where the loaded `address` belongs to, e.g. `.text, main+0x10`.
+    fn describe(&self, address: u64) -> Option<String> {
+        let address = self
+            .segments
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(range, virtual_start)| address - range.start + virtual_start)
+            .unwrap_or(address);
+
+        let (section, _) = self
+            .sections
+            .iter()
+            .find(|(_, range)| range.contains(&address))?;
+
+        match self
+            .symbols
+            .iter()
+            .find(|(_, range)| range.contains(&address))
+        {
+            Some((symbol, range)) => {
+                Some(format!("{section}, {symbol}+{:#x}", address - range.start))
+            }
+            None => Some(section.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differing_bytes_are_merged_into_ranges() {
+        let expected = [0, 1, 2, 3, 4, 5, 6, 7];
+        let actual = [0, 0xFF, 0xFF, 3, 4, 0xFF, 6, 0xFF];
+
+        assert_eq!(
+            differences(0x1000, &expected, &actual),
+            vec![0x1001..0x1003, 0x1005..0x1006, 0x1007..0x1008]
+        );
+        assert!(differences(0x1000, &expected, &expected).is_empty());
+    }
+
+    #[test]
+    fn initialized_data_is_attributed_by_its_virtual_address() {
+        let attribution = ElfAttribution {
+            segments: vec![(0x0..0x100, 0x0), (0x100..0x110, 0x2000_0000)],
+            sections: vec![
+                (".text".to_string(), 0x0..0x100),
+                (".data".to_string(), 0x2000_0000..0x2000_0010),
+            ],
+            symbols: vec![
+                ("main".to_string(), 0x40..0x80),
+                ("COUNTER".to_string(), 0x2000_0008..0x2000_000C),
+            ],
+        };
+
+        assert_eq!(
+            attribution.describe(0x50).as_deref(),
+            Some(".text, main+0x10")
+        );
+        assert_eq!(attribution.describe(0x10).as_deref(), Some(".text"));
+        assert_eq!(
+            attribution.describe(0x10A).as_deref(),
+            Some(".data, COUNTER+0x2")
+        );
+        assert_eq!(attribution.describe(0x200), None);
+    }
+}
@@ -0,0 +1,358 @@
+use anyhow::{Context, Result};
+use probe_rs::architecture::arm::{
+    sequences::DefaultArmSequence, ApAddress, ArmProbeInterface, DpAddress,
+};
+
+use crate::util::common_options::ProbeOptions;
+use crate::util::parse_u32;
+
+/// The registers of an ADIv5 AP.
+const AP_CSW: u8 = 0x00;
+const AP_TAR: u8 = 0x04;
+const AP_DRW: u8 = 0x0C;
+const AP_BASE: u8 = 0xF8;
+const AP_IDR: u8 = 0xFC;
+
+/// The class of a MEM-AP in the IDR of an AP.
+const CLASS_MEM_AP: u32 = 0x8;
+
+/// The TAR of a MEM-AP only increments within blocks of this size.
+const AUTO_INCREMENT_BLOCK: u32 = 0x400;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Read the IDR of every AP of the debug port, including the APs after an unused one
+    ScanAps {
+        /// The highest AP to scan.
+        #[clap(long, value_parser = parse_u32, default_value = "255")]
+        last: u32,
+        #[clap(flatten)]
+        dap: DapOptions,
+    },
+    /// Read a register of the debug port
+    ReadDp {
+        /// The address of the register, with the bank in bits 4 to 7, e.g. 0x24 for TARGETID.
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+        #[clap(flatten)]
+        dap: DapOptions,
+    },
+    /// Write a register of the debug port
+    WriteDp {
+        /// The address of the register, with the bank in bits 4 to 7.
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+        #[clap(value_parser = parse_u32)]
+        value: u32,
+        #[clap(flatten)]
+        dap: DapOptions,
+    },
+    /// Read a register of an AP
+    ReadAp {
+        /// The number of the AP.
+        #[clap(value_parser = parse_u32)]
+        ap: u32,
+        /// The address of the register, e.g. 0xFC for the IDR.
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+        #[clap(flatten)]
+        dap: DapOptions,
+    },
+    /// Write a register of an AP
+    WriteAp {
+        /// The number of the AP.
+        #[clap(value_parser = parse_u32)]
+        ap: u32,
+        /// The address of the register.
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+        #[clap(value_parser = parse_u32)]
+        value: u32,
+        #[clap(flatten)]
+        dap: DapOptions,
+    },
+    /// Read 32 bit words of memory through a MEM-AP
+    ReadMem {
+        /// The number of the MEM-AP.
+        #[clap(value_parser = parse_u32)]
+        ap: u32,
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+        /// The number of words to read.
+        #[clap(value_parser = parse_u32, default_value = "1")]
+        words: u32,
+        #[clap(flatten)]
+        dap: DapOptions,
+    },
+    /// Write 32 bit words to memory through a MEM-AP
+    WriteMem {
+        /// The number of the MEM-AP.
+        #[clap(value_parser = parse_u32)]
+        ap: u32,
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+        /// The words to write.
+        #[clap(value_parser = parse_u32, required = true)]
+        values: Vec<u32>,
+        #[clap(flatten)]
+        dap: DapOptions,
+    },
+}
+
+#[derive(clap::Args)]
+struct DapOptions {
+    /// The TARGETSEL value of the debug port on an SWD multidrop bus. By default, there is a single debug port.
+    #[clap(long, value_parser = parse_u32)]
+    targetsel: Option<u32>,
+    #[clap(flatten)]
+    common: ProbeOptions,
+}
+
+impl DapOptions {
+    fn dp(&self) -> DpAddress {
+        match self.targetsel {
+            Some(targetsel) => DpAddress::Multidrop(targetsel),
+            None => DpAddress::Default,
+        }
+    }
+
+    fn ap(&self, ap: u32) -> Result<ApAddress> {
+        Ok(ApAddress {
+            dp: self.dp(),
+            ap: ap.try_into().context("APs are numbered from 0 to 255")?,
+        })
+    }
+
+    /// Attach to the debug port without a target, and run `operation` with its interface.
+    fn run(&self, operation: impl FnOnce(&mut dyn ArmProbeInterface) -> Result<()>) -> Result<()> {
+        let mut probe = self.common.attach_probe()?;
+        if self.common.connect_under_reset {
+            probe.attach_to_unspecified_under_reset()?;
+        } else {
+            probe.attach_to_unspecified()?;
+        }
+        let interface = probe
+            .try_into_arm_interface()
+            .map_err(|(_, error)| error)
+            .context("The probe has no interface for ARM targets")?;
+        let mut interface = interface
+            .initialize(DefaultArmSequence::create())
+            .map_err(|(_, error)| error)?;
+        let result = operation(&mut *interface);
+        interface.close();
+        result
+    }
+}
+
+/// The fields of the IDR of an AP.
+#[derive(Debug, PartialEq, Eq)]
+struct ApIdentity {
+    revision: u32,
+    designer: u32,
+    class: u32,
+    variant: u32,
+    ap_type: u32,
+}
+
+impl ApIdentity {
+    fn parse(idr: u32) -> Self {
+        Self {
+            revision: idr >> 28,
+            designer: (idr >> 17) & 0x7ff,
+            class: (idr >> 13) & 0xf,
+            variant: (idr >> 4) & 0xf,
+            ap_type: idr & 0xf,
+        }
+    }
+
+    fn class_name(&self) -> &'static str {
+        match (self.class, self.ap_type) {
+            (CLASS_MEM_AP, 0x1) => "MEM-AP (AMBA AHB3)",
+            (CLASS_MEM_AP, 0x2) => "MEM-AP (AMBA APB2 or APB3)",
+            (CLASS_MEM_AP, 0x4) => "MEM-AP (AMBA AXI3 or AXI4)",
+            (CLASS_MEM_AP, 0x5) => "MEM-AP (AMBA AHB5)",
+            (CLASS_MEM_AP, 0x6) => "MEM-AP (AMBA APB4 or APB5)",
+            (CLASS_MEM_AP, 0x7) => "MEM-AP (AMBA AXI5)",
+            (CLASS_MEM_AP, 0x8) => "MEM-AP (AMBA AHB5 with enhanced HPROT)",
+            (CLASS_MEM_AP, _) => "MEM-AP",
+            (0x0, 0x0) => "JTAG-AP",
+            (0x1, _) => "COM-AP",
+            _ => "unknown AP",
+        }
+    }
+
+    fn designer_name(&self) -> &'static str {
+        let cc = (self.designer >> 7) as u8;
+        let id = (self.designer & 0x7f) as u8;
+        jep106::JEP106Code::new(cc, id).get().unwrap_or("<unknown>")
+    }
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        match self.subcommand {
+            Subcommand::ScanAps { last, dap } => {
+                let last = u8::try_from(last).context("APs are numbered from 0 to 255")?;
+                dap.run(|interface| {
+                    for ap in 0..=last {
+                        let address = dap.ap(ap.into())?;
+                        let idr = match interface.read_raw_ap_register(address, AP_IDR) {
+                            Ok(0) => continue,
+                            Ok(idr) => idr,
+                            Err(error) => {
+                                println!("AP {ap:3}: reading the IDR failed: {error}");
+                                continue;
+                            }
+                        };
+                        let identity = ApIdentity::parse(idr);
+                        print!(
+                            "AP {ap:3}: IDR {idr:#010x}, {}, designer {}, variant {:#x}, revision {:#x}",
+                            identity.class_name(),
+                            identity.designer_name(),
+                            identity.variant,
+                            identity.revision
+                        );
+                        if identity.class == CLASS_MEM_AP {
+                            let csw = interface.read_raw_ap_register(address, AP_CSW)?;
+                            let base = interface.read_raw_ap_register(address, AP_BASE)?;
+                            print!(", CSW {csw:#010x}, BASE {base:#010x}");
+                        }
+                        println!();
+                    }
+                    Ok(())
+                })?;
+            }
+            Subcommand::ReadDp { address, dap } => {
+                let address = register_address(address)?;
+                dap.run(|interface| {
+                    let value = interface.read_raw_dp_register(dap.dp(), address)?;
+                    println!("{value:#010x}");
+                    Ok(())
+                })?;
+            }
+            Subcommand::WriteDp {
+                address,
+                value,
+                dap,
+            } => {
+                let address = register_address(address)?;
+                dap.run(|interface| {
+                    interface.write_raw_dp_register(dap.dp(), address, value)?;
+                    Ok(())
+                })?;
+            }
+            Subcommand::ReadAp { ap, address, dap } => {
+                let ap = dap.ap(ap)?;
+                let address = register_address(address)?;
+                dap.run(|interface| {
+                    let value = interface.read_raw_ap_register(ap, address)?;
+                    println!("{value:#010x}");
+                    Ok(())
+                })?;
+            }
+            Subcommand::WriteAp {
+                ap,
+                address,
+                value,
+                dap,
+            } => {
+                let ap = dap.ap(ap)?;
+                let address = register_address(address)?;
+                dap.run(|interface| {
+                    interface.write_raw_ap_register(ap, address, value)?;
+                    Ok(())
+                })?;
+            }
+            Subcommand::ReadMem {
+                ap,
+                address,
+                words,
+                dap,
+            } => {
+                let ap = dap.ap(ap)?;
+                dap.run(|interface| {
+                    select_word_access(interface, ap)?;
+                    for (index, word_address) in word_addresses(address, words).enumerate() {
+                        if index == 0 || word_address % AUTO_INCREMENT_BLOCK == 0 {
+                            interface.write_raw_ap_register(ap, AP_TAR, word_address)?;
+                        }
+                        let value = interface.read_raw_ap_register(ap, AP_DRW)?;
+                        println!("{word_address:#010x}: {value:#010x}");
+                    }
+                    Ok(())
+                })?;
+            }
+            Subcommand::WriteMem {
+                ap,
+                address,
+                values,
+                dap,
+            } => {
+                let ap = dap.ap(ap)?;
+                dap.run(|interface| {
+                    select_word_access(interface, ap)?;
+                    let addresses = word_addresses(address, values.len() as u32);
+                    for (index, (word_address, value)) in addresses.zip(&values).enumerate() {
+                        if index == 0 || word_address % AUTO_INCREMENT_BLOCK == 0 {
+                            interface.write_raw_ap_register(ap, AP_TAR, word_address)?;
+                        }
+                        interface.write_raw_ap_register(ap, AP_DRW, *value)?;
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The address of a DP or AP register, which has to be word aligned.
+fn register_address(address: u32) -> Result<u8> {
+    anyhow::ensure!(
+        address % 4 == 0 && address <= 0xFC,
+        "The address of a register has to be word aligned, and at most 0xFC"
+    );
+    Ok(address as u8)
+}
+
+/// The addresses of `count` words from `start`, which is rounded down to a word.
+fn word_addresses(start: u32, count: u32) -> impl Iterator<Item = u32> {
+    (0..count).map(move |index| (start & !3).wrapping_add(4 * index))
+}
+
+/// Set up the CSW of a MEM-AP for 32 bit accesses, which increment the TAR. The other fields, e.g. the protection
+/// of the bus accesses, are kept.
+fn select_word_access(interface: &mut dyn ArmProbeInterface, ap: ApAddress) -> Result<()> {
+    let csw = interface.read_raw_ap_register(ap, AP_CSW)?;
+    interface.write_raw_ap_register(ap, AP_CSW, (csw & !0x37) | 0x12)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{word_addresses, ApIdentity};
+
+    #[test]
+    fn idr_is_decoded() {
+        // The AHB-AP of a Cortex-M4.
+        let identity = ApIdentity::parse(0x2477_0011);
+        assert_eq!(identity.class_name(), "MEM-AP (AMBA AHB3)");
+        assert_eq!(identity.designer_name(), "ARM Ltd");
+        assert_eq!(identity.revision, 2);
+        assert_eq!(identity.variant, 1);
+    }
+
+    #[test]
+    fn word_addresses_are_aligned() {
+        assert_eq!(
+            word_addresses(0x2000_0002, 3).collect::<Vec<_>>(),
+            [0x2000_0000, 0x2000_0004, 0x2000_0008]
+        );
+    }
+}
@@ -3,7 +3,7 @@ use super::{
     dap_types,
     repl_commands_helpers::{build_expanded_commands, command_completions},
     request_helpers::{
-        disassemble_target_memory, get_dap_source, get_variable_reference,
+        disassemble_target_memory, function_address, get_dap_source, get_variable_reference,
         set_instruction_breakpoint,
     },
 };
@@ -12,11 +12,13 @@ use crate::cmd::dap_server::{
     server::{
         configuration::ConsoleLog,
         core_data::CoreHandle,
+        execution_history::Snapshot,
+        persistent_state::{self, SavedBreakpoint},
         session_data::{BreakpointType, SourceLocationScope},
     },
     DebuggerError,
 };
-use crate::util::rtt;
+use crate::util::{interrupts, memory_protection, rtt, rtt_plot::PlotFrame, stack_usage};
 use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use dap_types::*;
@@ -24,21 +26,37 @@ use num_traits::Zero;
 use parse_int::parse;
 use probe_rs::{
     architecture::{arm::ArmError, riscv::communication_interface::RiscvError},
+    config::MemoryRegion,
     debug::{
         ColumnType, DebugRegisters, SourceLocation, SteppingMode, VariableName, VariableNodeType,
         VerifiedBreakpoint,
     },
+    flashing::RamSnapshot,
     Architecture::Riscv,
-    CoreStatus, Error, HaltReason, MemoryInterface, RegisterValue,
+    CoreStatus, DebugProbeError, DebugProbeSelector, DisconnectAction, Error, HaltReason,
+    MemoryInterface, Probe, ProbeCreationError, ProbeEvent, ProbeWatcher, RegisterValue,
+    RunUntilOutcome, Session,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::{convert::TryInto, path::Path, str, string::ToString, time::Duration};
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    path::Path,
+    str,
+    string::ToString,
+    time::{Duration, Instant},
+};
 
 /// Progress ID used for progress reporting when the debug adapter protocol is used.
 type ProgressId = i64;
 
 pub struct DebugAdapter<P: ProtocolAdapter> {
     pub(crate) halt_after_reset: bool,
+    /// The RAM contents of `keepRam`, which were read before flashing, and are written back after the next reset.
+    pub(crate) kept_ram: Option<RamSnapshot>,
+    /// What happens to the target when the session ends: the `onDisconnect` policy, or the halt which the client
+    /// requested in its `disconnect` request.
+    pub(crate) on_disconnect: Option<DisconnectAction>,
     /// NOTE: VSCode sends a 'threads' request when it receives the response from the `ConfigurationDone` request, irrespective of target state.
     /// This can lead to duplicate `threads->stacktrace->etc.` sequences if & when the target halts and sends a 'stopped' event.
     /// See <https://github.com/golang/vscode-go/issues/940> for more info.
@@ -61,6 +79,14 @@ pub struct DebugAdapter<P: ProtocolAdapter> {
     pub(crate) lines_start_at_1: bool,
     /// DWARF spec at Sect 2.14 uses 1 based numbering, with a 0 indicating not-specified. We will follow that standard, and translate incoming requests depending on the DAP Client treatment of 0 or 1 based numbering.
     pub(crate) columns_start_at_1: bool,
+    /// Requests which the client sent while another request was processed, see [`DebugAdapter::check_cancelled`].
+    queued_requests: VecDeque<Request>,
+    /// The `seq` of the request which is processed, and when its processing started.
+    current_request: Option<(i64, Instant)>,
+    /// The current request was cancelled by the client.
+    current_request_cancelled: bool,
+    /// Requests which take longer than this are cancelled, see [`DebugAdapter::check_cancelled`].
+    pub(crate) request_timeout: Option<Duration>,
     adapter: P,
 }
 
@@ -68,12 +94,18 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
     pub fn new(adapter: P) -> DebugAdapter<P> {
         DebugAdapter {
             halt_after_reset: false,
+            kept_ram: None,
+            on_disconnect: None,
             configuration_done: false,
             all_cores_halted: true,
             progress_id: 0,
             supports_progress_reporting: false,
             lines_start_at_1: true,
             columns_start_at_1: true,
+            queued_requests: VecDeque::new(),
+            current_request: None,
+            current_request_cancelled: false,
+            request_timeout: None,
             adapter,
         }
     }
@@ -99,9 +131,11 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                         cpu_info.pc
                     ))),
                 )?;
+                let description = target_core
+                    .with_security_state(new_status.short_long_status(Some(cpu_info.pc)).1);
                 let event_body = Some(StoppedEventBody {
                     reason: "pause".to_owned(),
-                    description: Some(new_status.short_long_status(Some(cpu_info.pc)).1),
+                    description: Some(description),
                     thread_id: Some(target_core.core.id() as i64),
                     preserve_focus_hint: Some(false),
                     text: None,
@@ -131,8 +165,11 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         let must_halt_debuggee = arguments.terminate_debuggee.unwrap_or(false)
             || arguments.suspend_debuggee.unwrap_or(false);
 
-        if must_halt_debuggee {
+        // A configured `onDisconnect` policy takes precedence over the request of the client.
+        if must_halt_debuggee && self.on_disconnect.is_none() {
             let _ = target_core.core.halt(Duration::from_millis(100));
+            // Otherwise, disabling the debug logic at the end of the session would resume the core.
+            self.on_disconnect = Some(DisconnectAction::Halt);
         }
 
         self.send_response::<DisconnectResponse>(request, Ok(None))
@@ -168,6 +205,9 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         let mut fast_buff = vec![0u8; large_read_byte_count];
         // Read as many large chunks as possible.
         while num_bytes_unread > 0 {
+            if let Err(error) = self.check_cancelled() {
+                return self.send_response::<()>(request, Err(error));
+            }
             if let Ok(()) = target_core.core.read(address, &mut fast_buff) {
                 result_buffer.extend_from_slice(&fast_buff);
                 address += large_read_byte_count as u64;
@@ -178,6 +218,9 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         }
         // Read the remaining bytes one by one.
         while num_bytes_unread > 0 {
+            if let Err(error) = self.check_cancelled() {
+                return self.send_response::<()>(request, Err(error));
+            }
             if let Ok(good_byte) = target_core.core.read_word_8(address) {
                 result_buffer.push(good_byte);
                 address += 1;
@@ -302,6 +345,12 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         };
 
         if let Some(context) = &arguments.context {
+            if context == "watch" {
+                if let Some(persistent_state) = target_core.core_data.persistent_state.as_mut() {
+                    persistent_state.add_watch_expression(&arguments.expression);
+                }
+            }
+
             if context == "clipboard" {
                 response_body.result = arguments.expression;
             } else if context == "repl" {
@@ -502,6 +551,319 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         self.send_response(request, Ok(Some(response_body)))
     }
 
+    /// Custom `probe-rs/listProbes` request, which returns all the debug probes connected to the host.
+    /// This allows the DAP client to offer a probe picker, without having to use a separate CLI binary.
+    pub(crate) fn list_probes(&mut self, request: &Request) -> Result<()> {
+        let probes = Probe::list_all()
+            .into_iter()
+            .map(ProbeListEntry::from)
+            .collect();
+
+        self.send_response(request, Ok(Some(ListProbesResponseBody { probes })))
+    }
+
+    /// Wait for the probe matching `selector` to be connected, reporting every probe that is plugged in or unplugged
+    /// in the meantime with a custom `probe-rs-probe-event` event.
+    pub(crate) fn wait_for_probe(
+        &mut self,
+        selector: &DebugProbeSelector,
+        timeout: Duration,
+    ) -> Result<(), DebuggerError> {
+        let mut watcher = ProbeWatcher::new();
+        if watcher.probes().iter().any(|probe| selector.matches(probe)) {
+            return Ok(());
+        }
+
+        self.log_to_console(format!(
+            "Waiting up to {}s for the probe {} to be connected.",
+            timeout.as_secs(),
+            selector
+        ));
+        watcher
+            .wait_for(selector, timeout, Duration::from_millis(500), |event| {
+                let (event, probe_info) = match event {
+                    ProbeEvent::Attached(probe_info) => ("attached", probe_info),
+                    ProbeEvent::Detached(probe_info) => ("detached", probe_info),
+                };
+                let event_body = ProbeEventBody {
+                    event: event.to_string(),
+                    probe: ProbeListEntry::from(probe_info.clone()),
+                };
+                if let Err(error) = self.send_event("probe-rs-probe-event", Some(event_body)) {
+                    tracing::warn!("Failed to send probe event: {:?}", error);
+                }
+            })
+            .map_err(|error| match error {
+                DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound) => {
+                    DebuggerError::Other(anyhow!(
+                        "The probe {} was not connected within {}s.",
+                        selector,
+                        timeout.as_secs()
+                    ))
+                }
+                other => DebuggerError::DebugProbe(other),
+            })?;
+
+        Ok(())
+    }
+
+    /// Custom `probe-rs/listChips` request, which returns all the chip families and variants known to probe-rs.
+    pub(crate) fn list_chips(&mut self, request: &Request) -> Result<()> {
+        let arguments: ListChipsArguments = if request.arguments.is_some() {
+            get_arguments(self, request)?
+        } else {
+            ListChipsArguments::default()
+        };
+        let filter = arguments.filter.map(|filter| filter.to_ascii_lowercase());
+
+        let families = match probe_rs::config::families() {
+            Ok(families) => families,
+            Err(error) => {
+                let error = DebuggerError::ProbeRs(error.into());
+                return self.send_response::<()>(request, Err(error));
+            }
+        };
+
+        let families = families
+            .into_iter()
+            .filter_map(|family| {
+                let variants: Vec<String> = family
+                    .variants
+                    .into_iter()
+                    .map(|variant| variant.name)
+                    .filter(|name| {
+                        filter
+                            .as_ref()
+                            .map_or(true, |filter| name.to_ascii_lowercase().starts_with(filter))
+                    })
+                    .collect();
+
+                (!variants.is_empty()).then(|| ChipFamilyEntry {
+                    name: family.name,
+                    manufacturer: family
+                        .manufacturer
+                        .and_then(|code| code.get())
+                        .map(ToString::to_string),
+                    variants,
+                })
+            })
+            .collect();
+
+        self.send_response(request, Ok(Some(ListChipsResponseBody { families })))
+    }
+
+    /// Custom `probe-rs/resetLine` request, which controls the hardware reset line of the target,
+    /// independent of the cores and the debug connection.
+    pub(crate) fn reset_line(&mut self, session: &mut Session, request: &Request) -> Result<()> {
+        let arguments: ResetLineArguments = get_arguments(self, request)?;
+
+        let result = match arguments.action {
+            ResetLineAction::Assert => session.target_reset_assert(),
+            ResetLineAction::Release => session.target_reset_deassert(),
+            ResetLineAction::Pulse => session
+                .target_reset_pulse(Duration::from_millis(arguments.duration_ms.unwrap_or(100))),
+        };
+
+        match result {
+            Ok(()) => self.send_response::<()>(request, Ok(None)),
+            Err(error) => self.send_response::<()>(request, Err(DebuggerError::ProbeRs(error))),
+        }
+    }
+
+    /// Custom `probe-rs/uniqueId` request, which returns the unique device ID of the connected chip,
+    /// so that test results can be bound to a physical board.
+    pub(crate) fn unique_id(&mut self, session: &mut Session, request: &Request) -> Result<()> {
+        match session.read_unique_id() {
+            Ok(unique_id) => {
+                let response_body = UniqueIdResponseBody {
+                    chip: session.target().name.clone(),
+                    unique_id: unique_id.map(|id| {
+                        id.iter()
+                            .map(|byte| format!("{byte:02x}"))
+                            .collect::<String>()
+                    }),
+                };
+                self.send_response(request, Ok(Some(response_body)))
+            }
+            Err(error) => self.send_response::<()>(request, Err(DebuggerError::ProbeRs(error))),
+        }
+    }
+
+    /// Custom `probe-rs/interrupts` request, which reads the state of the interrupts of the core,
+    /// named from the SVD file if there is one.
+    pub(crate) fn interrupts(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let result = interrupts::read(
+            &mut target_core.core,
+            &target_core.core_data.interrupt_info(),
+        )
+        .map(|controllers| Some(InterruptsResponseBody { controllers }))
+        .map_err(DebuggerError::Other);
+        self.send_response(request, result)
+    }
+
+    /// Custom `probe-rs/memoryProtection` request, which decodes the MPU or PMP regions of the core.
+    pub(crate) fn memory_protection(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let result = memory_protection::read(&mut target_core.core)
+            .map(|memory_protection| Some(MemoryProtectionResponseBody { memory_protection }))
+            .map_err(DebuggerError::Other);
+        self.send_response(request, result)
+    }
+
+    /// Custom `probe-rs/stackUsage` request, which paints the main stack of the program,
+    /// or measures how much of it was used since it was painted.
+    pub(crate) fn stack_usage(
+        &mut self,
+        target_core: &mut CoreHandle,
+        program_binary: Option<&Path>,
+        request: &Request,
+    ) -> Result<()> {
+        let arguments: StackUsageArguments = get_arguments(self, request)?;
+
+        let stack = match program_binary
+            .ok_or_else(|| anyhow!("The stack can only be found with a `programBinary`"))
+            .and_then(stack_usage::main_stack)
+        {
+            Ok(stack) => stack,
+            Err(error) => {
+                return self.send_response::<()>(request, Err(DebuggerError::Other(error)))
+            }
+        };
+
+        match arguments.action {
+            StackUsageAction::Paint => {
+                let result =
+                    stack_usage::paint(&mut target_core.core, stack).map_err(DebuggerError::Other);
+                self.send_response::<()>(request, result.map(|()| None))
+            }
+            StackUsageAction::Measure => {
+                let result = stack_usage::measure(&mut target_core.core, stack)
+                    .map(|usage| {
+                        Some(StackUsageResponseBody {
+                            start: usage.stack.start,
+                            end: usage.stack.end,
+                            used: usage.used,
+                            overflowed: usage.overflowed,
+                        })
+                    })
+                    .map_err(DebuggerError::Other);
+                self.send_response(request, result)
+            }
+        }
+    }
+
+    /// Custom `probe-rs/runUntil` request, which runs the core until it reaches an address or a function, or the
+    /// time budget is used up, and responds with how the run ended.
+    ///
+    /// The request is answered when the run has ended, so other requests wait for it. The `stopped` event is sent
+    /// by the next poll of the core, as for any other halt.
+    pub(crate) fn run_until(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let arguments: RunUntilArguments = get_arguments(self, request)?;
+
+        let address = match (arguments.address, arguments.symbol) {
+            (Some(_), Some(_)) => Err(DebuggerError::UserMessage(
+                "Either an `address` or a `symbol` can be run to, not both.".to_string(),
+            )),
+            (Some(address), None) => Ok(Some(address)),
+            (None, Some(symbol)) => function_address(target_core, &symbol).map(Some),
+            (None, None) => Ok(None),
+        };
+        let address = match address {
+            Ok(address) => address,
+            Err(error) => return self.send_response::<()>(request, Err(error)),
+        };
+
+        target_core.reset_core_status(self);
+        let result = target_core
+            .core
+            .run_until(address, Duration::from_millis(arguments.timeout_ms))
+            .map(|outcome| {
+                let end = match outcome {
+                    RunUntilOutcome::Reached { .. } => RunUntilEnd::Reached,
+                    RunUntilOutcome::Halted { .. } => RunUntilEnd::Halted,
+                    RunUntilOutcome::LockedUp { .. } => RunUntilEnd::LockedUp,
+                    RunUntilOutcome::Timeout { .. } => RunUntilEnd::Timeout,
+                };
+                Some(RunUntilResponseBody {
+                    end,
+                    address: outcome.address(),
+                    description: outcome.to_string(),
+                })
+            })
+            .map_err(|error| DebuggerError::Other(anyhow!("{}", error)));
+        self.send_response(request, result)
+    }
+
+    /// Custom `probe-rs/chipInfo` request, which returns the cores, memory map and flash algorithms of a chip.
+    pub(crate) fn chip_info(&mut self, request: &Request) -> Result<()> {
+        let arguments: ChipInfoArguments = get_arguments(self, request)?;
+
+        let target = match probe_rs::config::get_target_by_name(arguments.chip) {
+            Ok(target) => target,
+            Err(error) => {
+                let error = DebuggerError::ProbeRs(error.into());
+                return self.send_response::<()>(request, Err(error));
+            }
+        };
+
+        let response_body = ChipInfoResponseBody {
+            name: target.name,
+            cores: target
+                .cores
+                .into_iter()
+                .map(|core| ChipCoreEntry {
+                    name: core.name,
+                    core_type: format!("{:?}", core.core_type).to_ascii_lowercase(),
+                })
+                .collect(),
+            memory_map: target
+                .memory_map
+                .into_iter()
+                .map(|region| {
+                    let (kind, name, range) = match region {
+                        MemoryRegion::Ram(region) => ("ram", region.name, region.range),
+                        MemoryRegion::Nvm(region) => ("nvm", region.name, region.range),
+                        MemoryRegion::Generic(region) => ("generic", region.name, region.range),
+                    };
+                    ChipMemoryRegionEntry {
+                        kind: kind.to_string(),
+                        name,
+                        start: range.start,
+                        end: range.end,
+                    }
+                })
+                .collect(),
+            flash_algorithms: target
+                .flash_algorithms
+                .into_iter()
+                .map(|algorithm| algorithm.name)
+                .collect(),
+            partitions: target
+                .partitions
+                .into_iter()
+                .map(|partition| ChipPartitionEntry {
+                    kind: format!("{:?}", partition.kind).to_ascii_lowercase(),
+                    name: partition.name,
+                    start: partition.range.start,
+                    end: partition.range.end,
+                })
+                .collect(),
+        };
+
+        self.send_response(request, Ok(Some(response_body)))
+    }
+
     /// Set the variable with the given name in the variable container to a new value.
     pub(crate) fn set_variable(
         &mut self,
@@ -656,6 +1018,9 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                 return self
                     .send_response::<()>(request, Err(DebuggerError::Other(anyhow!("{}", error))));
             }
+            if let Err(error) = self.restore_kept_ram(target_core) {
+                return self.send_response::<()>(request, Err(error));
+            }
 
             // Ensure ebreak enters debug mode, this is necessary for soft breakpoints to work on architectures like RISC-V.
             target_core.core.debug_on_sw_breakpoint(true)?;
@@ -725,6 +1090,9 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                     return self.show_error_message(&DebuggerError::Other(anyhow!("{}", error)));
                 }
             };
+            if let Err(error) = self.restore_kept_ram(target_core) {
+                return self.show_error_message(&error);
+            }
 
             // Ensure ebreak enters debug mode, this is necessary for soft breakpoints to work on architectures like RISC-V.
             target_core.core.debug_on_sw_breakpoint(true)?;
@@ -750,14 +1118,217 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         }
     }
 
+    /// Write back the RAM contents of `keepRam` after the core was reset and halted, before the program runs.
+    fn restore_kept_ram(&mut self, target_core: &mut CoreHandle) -> Result<(), DebuggerError> {
+        if let Some(kept_ram) = self.kept_ram.take() {
+            kept_ram.restore(&mut target_core.core)?;
+            self.log_to_console(format!(
+                "Restored {} bytes of RAM from `keepRam` after the reset",
+                kept_ram.len()
+            ));
+        }
+        Ok(())
+    }
+
     pub(crate) fn configuration_done(
         &mut self,
-        _core_data: &mut CoreHandle,
+        target_core: &mut CoreHandle,
         request: &Request,
     ) -> Result<()> {
+        self.restore_breakpoints(target_core)?;
         self.send_response::<()>(request, Ok(None))
     }
 
+    /// Set the stored breakpoints of the sources the client did not set breakpoints for,
+    /// and notify the client of the ones which could be set.
+    fn restore_breakpoints(&mut self, target_core: &mut CoreHandle) -> Result<()> {
+        let Some(to_restore) = target_core
+            .core_data
+            .persistent_state
+            .as_mut()
+            .map(|persistent_state| persistent_state.take_breakpoints_to_restore())
+        else {
+            return Ok(());
+        };
+
+        for (source_path, saved_breakpoints) in to_restore {
+            let source = Source {
+                name: source_path
+                    .file_name()
+                    .map(|file_name| file_name.to_string_lossy().to_string()),
+                path: Some(source_path.to_string_lossy().to_string()),
+                source_reference: None,
+                presentation_hint: None,
+                origin: None,
+                sources: None,
+                adapter_data: None,
+                checksums: None,
+            };
+
+            for saved_breakpoint in saved_breakpoints {
+                let line = persistent_state::rebind_line(
+                    &target_core.core_data.debug_info,
+                    &source_path,
+                    &saved_breakpoint,
+                );
+                match target_core.verify_and_set_breakpoint(
+                    &source_path,
+                    line,
+                    saved_breakpoint.column,
+                    &source,
+                ) {
+                    Ok(VerifiedBreakpoint {
+                        address,
+                        source_location,
+                    }) => {
+                        self.send_event(
+                            "breakpoint",
+                            Some(BreakpointEventBody {
+                                breakpoint: Breakpoint {
+                                    column: source_location.column.map(|col| match col {
+                                        ColumnType::LeftEdge => 0_i64,
+                                        ColumnType::Column(c) => c as i64,
+                                    }),
+                                    end_column: None,
+                                    end_line: None,
+                                    id: None,
+                                    line: source_location.line.map(|line| line as i64),
+                                    message: Some(format!(
+                                        "Restored source breakpoint at memory address: {address:#010X}"
+                                    )),
+                                    source: Some(source.clone()),
+                                    instruction_reference: Some(format!("{address:#010X}")),
+                                    offset: None,
+                                    verified: true,
+                                },
+                                reason: "new".to_string(),
+                            }),
+                        )?;
+                    }
+                    Err(error) => {
+                        self.log_to_console(format!(
+                            "Failed to restore the breakpoint at {}:{line}: {error}",
+                            source_path.display()
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Custom `probe-rs/savedState` request, which returns the breakpoints and watch expressions
+    /// which are kept across debug sessions.
+    ///
+    /// The protocol has no way to add watch expressions to the client, so it has to restore them from this.
+    pub(crate) fn saved_state(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let result = target_core
+            .core_data
+            .persistent_state
+            .as_ref()
+            .map(|persistent_state| {
+                Some(SavedStateResponseBody {
+                    saved_state: persistent_state.state.clone(),
+                })
+            })
+            .ok_or_else(|| {
+                DebuggerError::Other(anyhow!(
+                    "The debug state is only kept with `persistDebugState` and a `programBinary`"
+                ))
+            });
+        self.send_response(request, result)
+    }
+
+    /// Custom `probe-rs/setSnapshotBreakpoints` request, which replaces the breakpoints which capture a snapshot of
+    /// the core at each hit.
+    pub(crate) fn set_snapshot_breakpoints(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let arguments: SetSnapshotBreakpointsArguments = get_arguments(self, request)?;
+
+        let snapshot_breakpoints = target_core
+            .core_data
+            .breakpoints
+            .iter()
+            .filter(|breakpoint| {
+                matches!(
+                    breakpoint.breakpoint_type,
+                    BreakpointType::SnapshotBreakpoint { .. }
+                )
+            })
+            .map(|breakpoint| breakpoint.address)
+            .collect::<Vec<_>>();
+        for address in snapshot_breakpoints {
+            if let Err(error) = target_core.clear_breakpoint(address) {
+                return self.send_response::<()>(request, Err(DebuggerError::Other(error)));
+            }
+        }
+
+        for breakpoint in arguments.breakpoints {
+            let breakpoint_type = BreakpointType::SnapshotBreakpoint {
+                name: breakpoint.name,
+                ranges: breakpoint
+                    .ranges
+                    .iter()
+                    .map(|range| range.start..range.end)
+                    .collect(),
+            };
+            if let Err(error) = target_core.set_breakpoint(breakpoint.address, breakpoint_type) {
+                return self.send_response::<()>(request, Err(error));
+            }
+        }
+        self.send_response::<()>(request, Ok(None))
+    }
+
+    /// Custom `probe-rs/snapshots` request, which lists the snapshots that snapshot breakpoints captured.
+    pub(crate) fn snapshots(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let snapshots = target_core
+            .core_data
+            .snapshots
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.send_response(request, Ok(Some(SnapshotsResponseBody { snapshots })))
+    }
+
+    /// Custom `probe-rs/diffSnapshots` request, which compares the registers and the memory of two snapshots.
+    pub(crate) fn diff_snapshots(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let arguments: DiffSnapshotsArguments = get_arguments(self, request)?;
+
+        let find = |name: &str| {
+            target_core
+                .core_data
+                .snapshots
+                .iter()
+                .find(|(snapshot_name, _)| snapshot_name == name)
+                .map(|(_, snapshot)| snapshot)
+                .ok_or_else(|| {
+                    DebuggerError::UserMessage(format!("There is no snapshot {name:?}."))
+                })
+        };
+        let result = find(&arguments.from).and_then(|from| {
+            let to = find(&arguments.to)?;
+            Ok(Some(DiffSnapshotsResponseBody {
+                diff: from.diff(to),
+            }))
+        });
+        self.send_response(request, result)
+    }
+
     pub(crate) fn set_breakpoints(
         &mut self,
         target_core: &mut CoreHandle,
@@ -767,6 +1338,8 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
 
         let mut created_breakpoints: Vec<Breakpoint> = Vec::new(); // For returning in the Response
 
+        let mut saved_breakpoints = Vec::new();
+
         if let Some(source_path) = args.source.path.as_ref().map(Path::new) {
             // Always clear existing breakpoints for the specified `[crate::debug_adapter::dap_types::Source]` before setting new ones.
             // The DAP Specification doesn't make allowances for deleting and setting individual breakpoints for a specific `Source`.
@@ -813,23 +1386,45 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                         Ok(VerifiedBreakpoint {
                             address,
                             source_location,
-                        }) => created_breakpoints.push(Breakpoint {
-                            column: source_location.column.map(|col| match col {
-                                ColumnType::LeftEdge => 0_i64,
-                                ColumnType::Column(c) => c as i64,
-                            }),
-                            end_column: None,
-                            end_line: None,
-                            id: None,
-                            line: source_location.line.map(|line| line as i64),
-                            message: Some(format!(
-                                "Source breakpoint at memory address: {address:#010X}"
-                            )),
-                            source: Some(args.source.clone()),
-                            instruction_reference: Some(format!("{address:#010X}")),
-                            offset: None,
-                            verified: true,
-                        }),
+                        }) => {
+                            if target_core.core_data.persistent_state.is_some() {
+                                let anchor = persistent_state::function_anchor(
+                                    &target_core.core_data.debug_info,
+                                    address,
+                                );
+                                saved_breakpoints.push(SavedBreakpoint {
+                                    line: requested_breakpoint_line,
+                                    column: requested_breakpoint_column,
+                                    condition: bp.condition.clone(),
+                                    hit_condition: bp.hit_condition.clone(),
+                                    log_message: bp.log_message.clone(),
+                                    function_line: anchor.as_ref().map(|(_, line)| *line),
+                                    function: anchor.map(|(function, _)| function),
+                                });
+                            }
+
+                            let mut message =
+                                format!("Source breakpoint at memory address: {address:#010X}");
+                            if let Some(note) = target_core.security_note(address) {
+                                message = format!("{message} ({note})");
+                            }
+
+                            created_breakpoints.push(Breakpoint {
+                                column: source_location.column.map(|col| match col {
+                                    ColumnType::LeftEdge => 0_i64,
+                                    ColumnType::Column(c) => c as i64,
+                                }),
+                                end_column: None,
+                                end_line: None,
+                                id: None,
+                                line: source_location.line.map(|line| line as i64),
+                                message: Some(message),
+                                source: Some(args.source.clone()),
+                                instruction_reference: Some(format!("{address:#010X}")),
+                                offset: None,
+                                verified: true,
+                            })
+                        }
                         Err(error) => created_breakpoints.push(Breakpoint {
                             column: None,
                             end_column: None,
@@ -846,6 +1441,10 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                 }
             }
 
+            if let Some(persistent_state) = target_core.core_data.persistent_state.as_mut() {
+                persistent_state.set_breakpoints(source_path, saved_breakpoints);
+            }
+
             let breakpoint_body = SetBreakpointsResponseBody {
                 breakpoints: created_breakpoints,
             };
@@ -930,12 +1529,11 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                         .core
                         .read_core_reg(target_core.core.program_counter())
                         .ok();
+                    let (reason, description) =
+                        target_core.stopped_reason(current_core_status, program_counter);
                     let event_body = Some(StoppedEventBody {
-                        reason: current_core_status
-                            .short_long_status(program_counter)
-                            .0
-                            .to_owned(),
-                        description: Some(current_core_status.short_long_status(program_counter).1),
+                        reason,
+                        description: Some(description),
                         thread_id: Some(target_core.core.id() as i64),
                         preserve_focus_hint: None,
                         text: None,
@@ -1007,6 +1605,7 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                 "Updating the stack frame data for core #{}",
                 target_core.core.id()
             );
+            target_core.select_symbol_file(self);
 
             target_core.core_data.stack_frames = target_core
                 .core_data
@@ -1339,6 +1938,7 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
     ) -> Result<()> {
         let arguments: VariablesArguments = get_arguments(self, request)?;
 
+        let value_history = &mut target_core.core_data.value_history;
         if let Some(core_peripherals) = &mut target_core.core_data.core_peripherals {
             // First we check the SVD VariableCache, we do this first because it is the lowest computational overhead.
             if let Some(search_variable) = core_peripherals
@@ -1359,6 +1959,12 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                             variable,
                             &mut core_peripherals.svd_variable_cache,
                         );
+                        // The SVD cache is not automatically refreshed on every stack trace, and we only need to refresh the field values.
+                        variable.extract_value(
+                            &mut target_core.core,
+                            &core_peripherals.svd_variable_cache,
+                        );
+                        let value = variable.get_value(&core_peripherals.svd_variable_cache);
                         Variable {
                             name: if let VariableName::Named(variable_name) = &variable.name {
                                 if let Some(last_part) = variable_name.split_terminator('.').last()
@@ -1378,16 +1984,10 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                                 .map_or_else(|_| None, |address| Some(format!("{address:#010x}"))),
                             indexed_variables: Some(indexed_child_variables_cnt),
                             named_variables: Some(named_child_variables_cnt),
-                            presentation_hint: None,
+                            presentation_hint: value_history
+                                .presentation_hint(&variable.name.to_string(), &value),
                             type_: Some(variable.type_name.to_string()),
-                            value: {
-                                // The SVD cache is not automatically refreshed on every stack trace, and we only need to refresh the field values.
-                                variable.extract_value(
-                                    &mut target_core.core,
-                                    &core_peripherals.svd_variable_cache,
-                                );
-                                variable.get_value(&core_peripherals.svd_variable_cache)
-                            },
+                            value,
                             variables_reference,
                         }
                     })
@@ -1406,6 +2006,12 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
             let mut variable_cache: Option<&mut probe_rs::debug::VariableCache> = None;
             let mut stack_frame_registers: Option<&DebugRegisters> = None;
             let mut frame_base: Option<u64> = None;
+            // Only the registers of the frame the core halted in are compared with the previous stop.
+            let top_frame_id = target_core
+                .core_data
+                .stack_frames
+                .first()
+                .map(|frame| frame.id);
             for stack_frame in target_core.core_data.stack_frames.iter_mut() {
                 if let Some(search_cache) = &mut stack_frame.local_variables {
                     if let Some(search_variable) =
@@ -1437,16 +2043,26 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                         .registers
                         .0
                         .iter()
-                        .map(|register| Variable {
-                            name: register.get_register_name(),
-                            evaluate_name: Some(register.get_register_name()),
-                            memory_reference: None,
-                            indexed_variables: None,
-                            named_variables: None,
-                            presentation_hint: None, // TODO: Implement hint as Hex for registers
-                            type_: Some(format!("{}", VariableName::RegistersRoot)),
-                            value: register.value.unwrap_or_default().to_string(),
-                            variables_reference: 0,
+                        .map(|register| {
+                            let name = register.get_register_name();
+                            let value = register.value.unwrap_or_default().to_string();
+                            // TODO: Implement hint as Hex for registers
+                            let presentation_hint = if top_frame_id == Some(stack_frame.id) {
+                                value_history.presentation_hint(&name, &value)
+                            } else {
+                                None
+                            };
+                            Variable {
+                                evaluate_name: Some(name.clone()),
+                                name,
+                                memory_reference: None,
+                                indexed_variables: None,
+                                named_variables: None,
+                                presentation_hint,
+                                type_: Some(format!("{}", VariableName::RegistersRoot)),
+                                value,
+                                variables_reference: 0,
+                            }
                         })
                         .collect();
                     return self.send_response(
@@ -1671,6 +2287,14 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         // We override the halt reason because our implementation of stepping uses breakpoints and results in a "BreakPoint" halt reason, which is not appropriate here.
         target_core.core_data.last_known_status = CoreStatus::Halted(HaltReason::Step);
         if matches!(new_status, CoreStatus::Halted(_)) {
+            let record_steps = target_core
+                .core_data
+                .execution_history
+                .as_ref()
+                .map_or(false, |history| history.record_steps);
+            if record_steps {
+                target_core.record_history();
+            }
             let event_body = Some(StoppedEventBody {
                 reason: target_core
                     .core_data
@@ -1679,9 +2303,11 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                     .0
                     .to_string(),
                 description: Some(
-                    CoreStatus::Halted(HaltReason::Step)
-                        .short_long_status(Some(program_counter))
-                        .1,
+                    target_core.with_security_state(
+                        CoreStatus::Halted(HaltReason::Step)
+                            .short_long_status(Some(program_counter))
+                            .1,
+                    ),
                 ),
                 thread_id: Some(target_core.core.id() as i64),
                 preserve_focus_hint: None,
@@ -1694,10 +2320,177 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
         Ok(())
     }
 
+    /// Restores the snapshot of the previous stop of the core, see
+    /// [`CoreConfig::record_history`](crate::cmd::dap_server::server::configuration::CoreConfig::record_history).
+    pub(crate) fn step_back(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        self.rewind(target_core, request, |_| true)
+    }
+
+    /// Restores the newest snapshot of a stop at a breakpoint, or the oldest snapshot if no stop was at a breakpoint.
+    pub(crate) fn reverse_continue(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+    ) -> Result<()> {
+        let breakpoints = target_core
+            .core_data
+            .breakpoints
+            .iter()
+            .map(|breakpoint| breakpoint.address)
+            .collect::<Vec<_>>();
+        self.rewind(target_core, request, |snapshot| {
+            breakpoints.contains(&snapshot.program_counter)
+        })
+    }
+
+    /// Common code for the `stepBack` and `reverseContinue` methods.
+    fn rewind(
+        &mut self,
+        target_core: &mut CoreHandle,
+        request: &Request,
+        stop_at: impl Fn(&Snapshot) -> bool,
+    ) -> Result<()> {
+        let Some(history) = target_core.core_data.execution_history.as_mut() else {
+            return self.send_response::<()>(
+                request,
+                Err(DebuggerError::UserMessage(
+                    "Stepping back requires a recorded history. Enable `recordHistory` in the core configuration."
+                        .to_string(),
+                )),
+            );
+        };
+        if !target_core.core.core_halted()? {
+            return self.send_response::<()>(
+                request,
+                Err(DebuggerError::UserMessage(
+                    "The core has to be halted to step back.".to_string(),
+                )),
+            );
+        }
+
+        let (program_counter, at_breakpoint) =
+            match history.step_back(&mut target_core.core, &stop_at) {
+                Ok(Some(snapshot)) => (snapshot.program_counter, stop_at(snapshot)),
+                Ok(None) => {
+                    return self.send_response::<()>(
+                        request,
+                        Err(DebuggerError::UserMessage(
+                            "There is no older recorded snapshot.".to_string(),
+                        )),
+                    )
+                }
+                Err(error) => {
+                    return self.send_response::<()>(request, Err(DebuggerError::Other(error)))
+                }
+            };
+        self.send_response::<()>(request, Ok(None))?;
+        target_core.core_data.value_history.next_stop();
+
+        let reason = if request.command == "stepBack" {
+            "step"
+        } else if at_breakpoint {
+            "breakpoint"
+        } else {
+            "entry"
+        };
+        let description = target_core.with_security_state(format!(
+            "Restored the recorded snapshot @{program_counter:#010x}."
+        ));
+        let event_body = Some(StoppedEventBody {
+            reason: reason.to_owned(),
+            description: Some(description),
+            thread_id: Some(target_core.core.id() as i64),
+            preserve_focus_hint: None,
+            text: None,
+            all_threads_stopped: Some(self.all_cores_halted),
+            hit_breakpoint_ids: None,
+        });
+        self.send_event("stopped", event_body)
+    }
+
     /// Returns one of the standard DAP Requests if all goes well, or a "error" request, which should indicate that the calling function should return.
     /// When preparing to return an "error" request, we will send a Response containing the DebuggerError encountered.
     pub fn listen_for_request(&mut self) -> anyhow::Result<Option<Request>> {
-        self.adapter.listen_for_request()
+        loop {
+            let request = match self.queued_requests.pop_front() {
+                Some(request) => request,
+                None => match self.adapter.listen_for_request()? {
+                    Some(request) => request,
+                    None => return Ok(None),
+                },
+            };
+
+            if request.command == "cancel" {
+                self.cancel(&request)?;
+                continue;
+            }
+
+            self.current_request = Some((request.seq, Instant::now()));
+            self.current_request_cancelled = false;
+            return Ok(Some(request));
+        }
+    }
+
+    /// Handle a `cancel` request. A queued request is answered with the `cancelled` error right away, while the
+    /// request which is processed is only flagged, and stops at its next [`DebugAdapter::check_cancelled`].
+    /// Requests which are already answered are ignored, as the DAP specification allows.
+    fn cancel(&mut self, request: &Request) -> anyhow::Result<()> {
+        // The arguments of a `cancel` request are optional.
+        let request_id = match request.arguments {
+            Some(_) => get_arguments::<CancelArguments, _>(self, request)?.request_id,
+            None => None,
+        };
+
+        if let Some(request_id) = request_id {
+            if let Some(position) = self
+                .queued_requests
+                .iter()
+                .position(|queued| queued.seq == request_id)
+            {
+                if let Some(cancelled) = self.queued_requests.remove(position) {
+                    self.send_response::<()>(&cancelled, Err(DebuggerError::Cancelled))?;
+                }
+            } else if matches!(self.current_request, Some((current, _)) if current == request_id) {
+                self.current_request_cancelled = true;
+            }
+        }
+
+        self.send_response::<()>(request, Ok(None))
+    }
+
+    /// Check whether the request which is processed was cancelled by the client, or took longer than the
+    /// `requestTimeout`. Long running requests call this between accesses to the target, and stop with the returned
+    /// error, which is the response to the request, so a stalled target does not block the whole session.
+    ///
+    /// Requests which the client sent in the meantime are queued, and returned by [`DebugAdapter::listen_for_request`]
+    /// later.
+    pub(crate) fn check_cancelled(&mut self) -> Result<(), DebuggerError> {
+        while let Some(request) = self.adapter.listen_for_request()? {
+            if request.command == "cancel" {
+                self.cancel(&request)?;
+            } else {
+                self.queued_requests.push_back(request);
+            }
+        }
+
+        if self.current_request_cancelled {
+            return Err(DebuggerError::Cancelled);
+        }
+        match (self.current_request, self.request_timeout) {
+            (Some((_, started)), Some(timeout)) if started.elapsed() > timeout => {
+                Err(DebuggerError::RequestTimeout(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait until the client sent a request, or `timeout` passed.
+    pub(crate) fn wait_for_input(&mut self, timeout: Duration) {
+        self.adapter.wait_for_input(timeout)
     }
 
     /// Sends either the success response or an error response if passed a
@@ -1732,6 +2525,9 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                 );
                 child_error = std::error::Error::source(source_error);
             }
+            if let Some(hint) = response.code().hint() {
+                response_message = format!("{response_message}\n\n{hint}");
+            }
             response_message
         };
         if self
@@ -1798,6 +2594,28 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
             .is_ok()
     }
 
+    /// Send a custom `probe-rs-rtt-plot` event to the MS DAP Client, with a decoded frame of an RTT channel in the
+    /// [`DataFormat::Plot`](rtt::DataFormat::Plot) format.
+    pub fn rtt_plot(&mut self, channel_number: usize, frame: PlotFrame) -> bool {
+        let event_body = match serde_json::to_value(RttPlotEventBody {
+            channel_number,
+            frame,
+        }) {
+            Ok(event_body) => event_body,
+            Err(_) => {
+                return false;
+            }
+        };
+        self.send_event("probe-rs-rtt-plot", Some(event_body))
+            .is_ok()
+    }
+
+    /// Send a custom `probe-rs-heap-stats` event to the MS DAP Client, with the current usage of the heap.
+    pub fn heap_stats(&mut self, event_body: HeapStatsEventBody) -> bool {
+        self.send_event("probe-rs-heap-stats", Some(event_body))
+            .is_ok()
+    }
+
     fn new_progress_id(&mut self) -> ProgressId {
         let id = self.progress_id;
 
@@ -1,4 +1,4 @@
-use probe_rs::{CoreStatus, HaltReason};
+use probe_rs::{CoreStatus, HaltReason, StopReason};
 
 pub(crate) trait DapStatus {
     fn short_long_status(&self, program_counter: Option<u64>) -> (&'static str, String);
@@ -62,3 +62,22 @@ impl DapStatus for CoreStatus {
         }
     }
 }
+
+impl DapStatus for StopReason {
+    /// The short status is the `reason` of a DAP stopped event, and the long status describes the details of the halt.
+    fn short_long_status(&self, _program_counter: Option<u64>) -> (&'static str, String) {
+        let reason = match self {
+            StopReason::HardwareBreakpoint { .. }
+            | StopReason::SoftwareBreakpoint { .. }
+            | StopReason::Breakpoint { .. } => "breakpoint",
+            StopReason::Semihosting { .. } => "semihosting",
+            StopReason::Watchpoint { .. } => "data breakpoint",
+            StopReason::Step => "step",
+            StopReason::Request => "pause",
+            StopReason::External => "external",
+            StopReason::Exception { .. } => "exception",
+            _ => "unrecognized",
+        };
+        (reason, format!("{self}."))
+    }
+}
@@ -2,8 +2,11 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
 
 // use crate::dap_types2 as debugserver_types;
-use crate::cmd::dap_server::DebuggerError;
-use crate::util::rtt;
+use crate::cmd::dap_server::{
+    server::{configuration::RecordedRange, execution_history, persistent_state},
+    DebuggerError,
+};
+use crate::util::{interrupts, memory_protection, rtt, rtt_plot::PlotFrame};
 use num_traits::Num;
 use parse_int::parse;
 use schemafy::schemafy;
@@ -92,6 +95,33 @@ pub struct RttDataEventBody {
     pub data: String,
 }
 
+/// Body of the custom `probe-rs-rtt-plot` event, with a decoded frame of the plotting protocol.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RttPlotEventBody {
+    pub channel_number: usize,
+    #[serde(flatten)]
+    pub frame: PlotFrame,
+}
+
+/// Body of the custom `probe-rs-heap-stats` event.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeapStatsEventBody {
+    /// The size of the heap in bytes.
+    pub size: u64,
+    /// The number of allocated bytes.
+    pub used: u64,
+    /// The highest number of allocated bytes. For allocators which do not track this, it is the highest sampled usage.
+    pub peak: u64,
+    /// The number of free blocks.
+    pub free_blocks: u64,
+    /// The size of the largest free block, which is the largest allocation that can succeed.
+    pub largest_free_block: u64,
+    /// The fragmentation of the free memory, from 0 (all free memory is in one block) towards 1.
+    pub fragmentation: f64,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "lowercase", deserialize = "PascalCase"))]
 pub enum MessageSeverity {
@@ -106,6 +136,297 @@ pub struct ShowMessageEventBody {
     pub message: String,
 }
 
+/// A single debug probe, as returned by the custom `probe-rs/listProbes` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeListEntry {
+    /// The name of the debug probe.
+    pub identifier: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+    /// The type of the probe, e.g. `CmsisDap` or `StLink`.
+    pub probe_type: String,
+    /// The selector string (`VID:PID[:SERIAL]`) which can be used as the `probe` value in `launch.json`.
+    pub probe_selector: String,
+}
+
+impl From<probe_rs::DebugProbeInfo> for ProbeListEntry {
+    fn from(probe_info: probe_rs::DebugProbeInfo) -> Self {
+        ProbeListEntry {
+            probe_selector: probe_rs::DebugProbeSelector::from(&probe_info).to_string(),
+            identifier: probe_info.identifier,
+            vendor_id: probe_info.vendor_id,
+            product_id: probe_info.product_id,
+            serial_number: probe_info.serial_number,
+            probe_type: format!("{:?}", probe_info.probe_type),
+        }
+    }
+}
+
+/// Response body for the custom `probe-rs/listProbes` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListProbesResponseBody {
+    pub probes: Vec<ProbeListEntry>,
+}
+
+/// Body of the custom `probe-rs-probe-event` event, which is sent when a debug probe is plugged in or unplugged.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeEventBody {
+    /// Either `attached` or `detached`.
+    pub event: String,
+    pub probe: ProbeListEntry,
+}
+
+/// Arguments for the custom `probe-rs/listChips` request.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListChipsArguments {
+    /// Only return chips whose name starts with this (case insensitive) string.
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+/// A chip family, as returned by the custom `probe-rs/listChips` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChipFamilyEntry {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    /// The names of all the chip variants in this family.
+    pub variants: Vec<String>,
+}
+
+/// Response body for the custom `probe-rs/listChips` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListChipsResponseBody {
+    pub families: Vec<ChipFamilyEntry>,
+}
+
+/// What to do with the reset line, in the custom `probe-rs/resetLine` request.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResetLineAction {
+    /// Assert the reset line, and keep it asserted until it is released.
+    Assert,
+    /// Release the reset line.
+    Release,
+    /// Assert the reset line for `durationMs`, and release it again.
+    Pulse,
+}
+
+/// Arguments for the custom `probe-rs/resetLine` request, which controls the hardware reset line (nRESET) of the target.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetLineArguments {
+    pub action: ResetLineAction,
+    /// The pulse duration in milliseconds. Defaults to 100 ms.
+    pub duration_ms: Option<u64>,
+}
+
+/// Arguments for the custom `probe-rs/chipInfo` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChipInfoArguments {
+    /// The name of the chip, as it would be used for the `chip` value in `launch.json`.
+    pub chip: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChipCoreEntry {
+    pub name: String,
+    /// The core type, e.g. `armv7em` or `riscv`.
+    pub core_type: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChipMemoryRegionEntry {
+    /// One of `ram`, `nvm` or `generic`.
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChipPartitionEntry {
+    pub name: String,
+    /// One of `bootloader`, `slot`, `storage` or `other`.
+    pub kind: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Response body for the custom `probe-rs/chipInfo` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChipInfoResponseBody {
+    pub name: String,
+    pub cores: Vec<ChipCoreEntry>,
+    pub memory_map: Vec<ChipMemoryRegionEntry>,
+    /// The names of the flash algorithms available for this chip.
+    pub flash_algorithms: Vec<String>,
+    /// The named partitions of the memory map, like the bootloader and the slots of the firmware images.
+    pub partitions: Vec<ChipPartitionEntry>,
+}
+
+/// What to do with the stack, in the custom `probe-rs/stackUsage` request.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StackUsageAction {
+    /// Paint the unused part of the stack with a pattern. Best done while the core is halted after a reset.
+    Paint,
+    /// Measure how much of the stack was used since it was painted.
+    Measure,
+}
+
+/// Arguments for the custom `probe-rs/stackUsage` request, which analyzes the usage of the main stack of the program.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackUsageArguments {
+    pub action: StackUsageAction,
+}
+
+/// Response body for the custom `probe-rs/stackUsage` request, when the usage was measured.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackUsageResponseBody {
+    /// The lowest address of the stack.
+    pub start: u64,
+    /// The end of the stack, where it starts to grow down from.
+    pub end: u64,
+    /// The maximum number of bytes which were used since the stack was painted.
+    pub used: u64,
+    /// The lowest word of the stack was overwritten, so the stack most likely overflowed.
+    pub overflowed: bool,
+}
+
+/// Arguments for the custom `probe-rs/runUntil` request, which runs the core until it reaches an address or a
+/// function, or the time budget is used up.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunUntilArguments {
+    /// The address to run to.
+    pub address: Option<u64>,
+    /// The name of a function to run to, instead of an `address`.
+    pub symbol: Option<String>,
+    /// The time budget of the run, after which the core is halted.
+    pub timeout_ms: u64,
+}
+
+/// How the run of the custom `probe-rs/runUntil` request ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunUntilEnd {
+    /// The core reached the address.
+    Reached,
+    /// The core halted for another reason first.
+    Halted,
+    /// The core locked up.
+    LockedUp,
+    /// The time budget was used up.
+    Timeout,
+}
+
+/// Response body for the custom `probe-rs/runUntil` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunUntilResponseBody {
+    pub end: RunUntilEnd,
+    /// The program counter at which the core is halted.
+    pub address: u64,
+    /// A description of how the run ended, e.g. why the core halted.
+    pub description: String,
+}
+
+/// Response body for the custom `probe-rs/interrupts` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterruptsResponseBody {
+    pub controllers: Vec<interrupts::InterruptController>,
+}
+
+/// Response body for the custom `probe-rs/memoryProtection` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryProtectionResponseBody {
+    #[serde(flatten)]
+    pub memory_protection: memory_protection::MemoryProtection,
+}
+
+/// Response body for the custom `probe-rs/savedState` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedStateResponseBody {
+    #[serde(flatten)]
+    pub saved_state: persistent_state::SavedState,
+}
+
+/// A breakpoint of the custom `probe-rs/setSnapshotBreakpoints` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotBreakpoint {
+    /// The address of the instruction to break at.
+    pub address: u64,
+    /// The name of the snapshots, which are numbered by the hit, e.g. `loop#3`.
+    pub name: String,
+    /// The memory ranges which are captured with the registers.
+    #[serde(default)]
+    pub ranges: Vec<RecordedRange>,
+}
+
+/// Arguments for the custom `probe-rs/setSnapshotBreakpoints` request, which replaces all snapshot breakpoints.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetSnapshotBreakpointsArguments {
+    pub breakpoints: Vec<SnapshotBreakpoint>,
+}
+
+/// Response body for the custom `probe-rs/snapshots` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotsResponseBody {
+    /// The names of the captured snapshots, oldest first.
+    pub snapshots: Vec<String>,
+}
+
+/// Arguments for the custom `probe-rs/diffSnapshots` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSnapshotsArguments {
+    /// The name of the older snapshot.
+    pub from: String,
+    /// The name of the newer snapshot.
+    pub to: String,
+}
+
+/// Response body for the custom `probe-rs/diffSnapshots` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSnapshotsResponseBody {
+    #[serde(flatten)]
+    pub diff: execution_history::SnapshotDiff,
+}
+
+/// Response body for the custom `probe-rs/uniqueId` request.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UniqueIdResponseBody {
+    /// The name of the connected chip.
+    pub chip: String,
+    /// The unique device ID as a hex string, or `None` if the location of the ID is not known for this chip.
+    pub unique_id: Option<String>,
+}
+
 impl TryFrom<&serde_json::Value> for ReadMemoryArguments {
     fn try_from(arguments: &serde_json::Value) -> Result<Self, Self::Error> {
         let count = get_int_argument(Some(arguments), "count", 1)?;
@@ -5,12 +5,17 @@ use super::{
     },
     repl_commands_helpers::*,
     repl_types::*,
-    request_helpers::set_instruction_breakpoint,
+    request_helpers::{function_address, set_instruction_breakpoint},
 };
 use crate::cmd::dap_server::{server::core_data::CoreHandle, DebuggerError};
+use crate::util::interrupts;
+use parse_int::parse;
 use probe_rs::{debug::VariableName, CoreStatus, HaltReason};
 use std::{fmt::Display, str::FromStr, time::Duration};
 
+/// The time a function which is called with the `call` command has to return.
+const FUNCTION_CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// The handler is a function that takes a reference to the target core, and a reference to the response body.
 /// The response body is used to populate the response to the client.
 /// The handler returns a Result<[`Response`], [`DebuggerError`]>.
@@ -236,6 +241,106 @@ pub(crate) static REPL_COMMANDS: &[ReplCommand<ReplHandler>] = &[
                 // TODO: This is easy to implement ... just requires deciding how to format the output.
                 handler: |_, _, _| Err(DebuggerError::Unimplemented),
             },
+            ReplCommand {
+                command: "interrupts",
+                help_text: "Show the enabled, pending and active interrupts, and their priorities.",
+                sub_commands: None,
+                args: None,
+                handler: |target_core, _, _| {
+                    let controllers = interrupts::read(
+                        &mut target_core.core,
+                        &target_core.core_data.interrupt_info(),
+                    )
+                    .map_err(DebuggerError::Other)?;
+
+                    Ok(Response {
+                        command: "info".to_string(),
+                        success: true,
+                        message: Some(
+                            controllers
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join("\n\n"),
+                        ),
+                        type_: "response".to_string(),
+                        request_seq: 0,
+                        seq: 0,
+                        body: None,
+                    })
+                },
+            },
+            ReplCommand {
+                command: "symbol",
+                help_text:
+                    "Describe the function and source location at the specified (hex) address.",
+                sub_commands: None,
+                args: Some(&[ReplCommandArgs::Required("address (hex)")]),
+                handler: |target_core, command_arguments, _| {
+                    let MemoryAddress(address) = command_arguments.trim().try_into()?;
+                    let resolved = target_core
+                        .core_data
+                        .debug_info
+                        .resolve_address(address)
+                        .map_err(|error| DebuggerError::Other(error.into()))?;
+
+                    let mut message = format!(
+                        "{address:#010x} is in {}",
+                        resolved.function.as_deref().unwrap_or("<unknown function>")
+                    );
+                    if let Some(location) = resolved.source_location {
+                        if let (Some(file), Some(line)) = (location.file, location.line) {
+                            message.push_str(&format!(" at {file}:{line}"));
+                        }
+                    }
+
+                    Ok(Response {
+                        command: "info".to_string(),
+                        success: true,
+                        message: Some(message),
+                        type_: "response".to_string(),
+                        request_seq: 0,
+                        seq: 0,
+                        body: None,
+                    })
+                },
+            },
+            ReplCommand {
+                command: "address",
+                help_text: "List the addresses of the functions with the specified name.",
+                sub_commands: None,
+                args: Some(&[ReplCommandArgs::Required("function name")]),
+                handler: |target_core, command_arguments, _| {
+                    let name = command_arguments.trim();
+                    let ranges = target_core
+                        .core_data
+                        .debug_info
+                        .function_ranges(name)
+                        .map_err(|error| DebuggerError::Other(error.into()))?;
+
+                    let message = if ranges.is_empty() {
+                        format!("No function named '{name}' was found.")
+                    } else {
+                        ranges
+                            .iter()
+                            .map(|range| {
+                                format!("{name} is at {:#010x}..{:#010x}", range.start, range.end)
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    };
+
+                    Ok(Response {
+                        command: "info".to_string(),
+                        success: true,
+                        message: Some(message),
+                        type_: "response".to_string(),
+                        request_seq: 0,
+                        seq: 0,
+                        body: None,
+                    })
+                },
+            },
         ]),
         args: None,
         handler: |_, _, _| {
@@ -361,4 +466,47 @@ pub(crate) static REPL_COMMANDS: &[ReplCommand<ReplHandler>] = &[
             memory_read(input_address, gdb_nuf, target_core)
         },
     },
+    ReplCommand {
+        command: "call",
+        help_text: "Call a function of the program on the halted core, and print its result. The registers are restored afterwards.",
+        sub_commands: None,
+        args: Some(&[
+            ReplCommandArgs::Required("function name or *address"),
+            ReplCommandArgs::Optional("arguments (integers)"),
+        ]),
+        handler: |target_core, command_arguments, _| {
+            let mut input_arguments = command_arguments.split_whitespace();
+            let Some(function) = input_arguments.next() else {
+                return Err(DebuggerError::UserMessage(
+                    "Please provide the function to call. See the `help` command for more information."
+                        .to_string(),
+                ));
+            };
+            let parse_integer = |input: &str| {
+                parse::<u64>(input).map_err(|error| {
+                    DebuggerError::UserMessage(format!("Invalid integer {input:?}: {error}"))
+                })
+            };
+            let address = match function.strip_prefix('*') {
+                Some(address) => parse_integer(address)?,
+                None => function_address(target_core, function)?,
+            };
+            let arguments = input_arguments
+                .map(parse_integer)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let result = target_core
+                .core
+                .call_function(address, &arguments, FUNCTION_CALL_TIMEOUT)?;
+            Ok(Response {
+                command: "call".to_string(),
+                success: true,
+                message: Some(format!("{function} returned {result:#010x} ({result})")),
+                type_: "response".to_string(),
+                request_seq: 0,
+                seq: 0,
+                body: None,
+            })
+        },
+    },
 ];
@@ -11,7 +11,7 @@ use capstone::{
 use num_traits::Zero;
 use probe_rs::{
     debug::{ColumnType, SourceLocation},
-    CoreType, InstructionSet, MemoryInterface,
+    CoreType, InstructionSet, MemoryInterface, SecurityStatus,
 };
 use std::time::Duration;
 
@@ -75,6 +75,14 @@ pub(crate) fn disassemble_target_memory(
         return Err(DebuggerError::Other(anyhow!(error_message)));
     };
     let mut stored_source_location = None;
+    // Without secure debug, the Secure memory of a TrustZone-M core is not readable.
+    let secure_memory_hidden = matches!(
+        target_core.core.security_status(),
+        Ok(Some(SecurityStatus {
+            secure_debug_enabled: false,
+            ..
+        }))
+    );
     while assembly_lines.len() < instruction_count as usize {
         if read_more_bytes {
             if let Some(current_read_pointer) = read_pointer {
@@ -102,9 +110,13 @@ pub(crate) fn disassemble_target_memory(
                             column: None,
                             end_column: None,
                             end_line: None,
-                            instruction: format!(
-                                "<instruction address not readable : {memory_read_error:?}>"
-                            ),
+                            instruction: if secure_memory_hidden {
+                                format!("<instruction address not readable, it may be Secure memory : {memory_read_error:?}>")
+                            } else {
+                                format!(
+                                    "<instruction address not readable : {memory_read_error:?}>"
+                                )
+                            },
                             instruction_bytes: None,
                             line: None,
                             location: None,
@@ -222,6 +234,29 @@ pub(crate) fn disassemble_target_memory(
     Ok(assembly_lines)
 }
 
+/// The entry address of the function `name`, which has to be unambiguous.
+pub(crate) fn function_address(target_core: &CoreHandle, name: &str) -> Result<u64, DebuggerError> {
+    let ranges = target_core
+        .core_data
+        .debug_info
+        .function_ranges(name)
+        .map_err(|error| DebuggerError::Other(error.into()))?;
+    let mut addresses = ranges.iter().map(|range| range.start).collect::<Vec<_>>();
+    addresses.sort_unstable();
+    addresses.dedup();
+
+    match addresses[..] {
+        [address] => Ok(address),
+        [] => Err(DebuggerError::UserMessage(format!(
+            "No function named '{name}' was found."
+        ))),
+        _ => Err(DebuggerError::UserMessage(format!(
+            "There are {} functions named '{name}', run to the address of one of them instead.",
+            addresses.len()
+        ))),
+    }
+}
+
 pub(crate) fn get_capstone(target_core: &mut CoreHandle) -> Result<Capstone, DebuggerError> {
     let mut cs = match target_core.core.instruction_set()? {
         InstructionSet::Thumb2 => {
@@ -411,6 +446,11 @@ pub(crate) fn set_instruction_breakpoint(
                         breakpoint_response.message = Some(format!("Instruction breakpoint set @:{memory_reference:#010x}, but could not resolve a source location."));
                     }
                 }
+                if let Some(note) = target_core.security_note(memory_reference) {
+                    breakpoint_response.message = breakpoint_response
+                        .message
+                        .map(|message| format!("{message} ({note})"));
+                }
             }
             Err(error) => {
                 breakpoint_response.instruction_reference =
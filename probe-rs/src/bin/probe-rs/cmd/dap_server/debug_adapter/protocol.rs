@@ -13,6 +13,7 @@ use std::{
     io::{BufRead, BufReader, Read, Write},
     str,
     string::ToString,
+    time::{Duration, Instant},
 };
 
 pub trait ProtocolAdapter {
@@ -20,6 +21,9 @@ pub trait ProtocolAdapter {
     /// return None.
     fn listen_for_request(&mut self) -> anyhow::Result<Option<Request>>;
 
+    /// Wait until the client sent data, or `timeout` passed, whichever is first. The data is not consumed.
+    fn wait_for_input(&mut self, timeout: Duration);
+
     fn send_event<S: Serialize>(
         &mut self,
         event_type: &str,
@@ -230,6 +234,24 @@ impl<R: Read, W: Write> ProtocolAdapter for DapAdapter<R, W> {
         self.listen_for_request_and_respond()
     }
 
+    fn wait_for_input(&mut self, timeout: Duration) {
+        let start = Instant::now();
+        loop {
+            match self.input.fill_buf() {
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {}
+                // Data, the end of the input or an error, which `listen_for_request` handles.
+                _ => return,
+            }
+
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return;
+            }
+            // Checking the socket is cheap, unlike polling the probe.
+            std::thread::sleep(remaining.min(Duration::from_millis(10)));
+        }
+    }
+
     fn send_event<S: Serialize>(
         &mut self,
         event_type: &str,
@@ -344,11 +366,18 @@ impl<R: Read, W: Write> ProtocolAdapter for DapAdapter<R, W> {
                     );
                     child_error = std::error::Error::source(source_error);
                 }
+                let code = debugger_error.code();
+                if let Some(hint) = code.hint() {
+                    response_message = format!("{response_message}\n\n{hint}");
+                }
                 // We have to send log messages on error conditions to the DAP Client now, because
                 // if this error happens during the 'launch' or 'attach' request, the DAP Client
                 // will not initiate a session, and will not be listening for 'output' events.
-                self.log_to_console(&response_message);
-                self.show_message(MessageSeverity::Error, &response_message);
+                // A cancelled request is expected by the client, and is not reported to the user.
+                if !matches!(debugger_error, DebuggerError::Cancelled) {
+                    self.log_to_console(&response_message);
+                    self.show_message(MessageSeverity::Error, &response_message);
+                }
 
                 let error_resp = ErrorResponse {
                     command: request.command.clone(),
@@ -360,12 +389,13 @@ impl<R: Read, W: Write> ProtocolAdapter for DapAdapter<R, W> {
                     body: ErrorResponseBody {
                         error: Some(Message {
                             format: "{response_message}".to_string(),
-                            variables: Some(BTreeMap::from([(
-                                "response_message".to_string(),
-                                response_message,
-                            )])),
-                            // TODO: Implement unique error codes, that can index into the documentation for more information and suggested actions.
-                            id: 0,
+                            // The code and its category allow clients to handle errors without parsing the message.
+                            variables: Some(BTreeMap::from([
+                                ("response_message".to_string(), response_message),
+                                ("code".to_string(), code.name().to_string()),
+                                ("category".to_string(), code.category().name().to_string()),
+                            ])),
+                            id: code.value() as i64,
                             send_telemetry: Some(false),
                             show_user: Some(true),
                             url_label: Some("Documentation".to_string()),
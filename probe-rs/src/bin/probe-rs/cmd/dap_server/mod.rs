@@ -8,9 +8,10 @@ mod server;
 use anyhow::{Context, Result};
 use probe_rs::{
     architecture::arm::ap::AccessPortError, flashing::FileDownloadError, DebugProbeError, Error,
+    ErrorCode,
 };
 use server::startup::debug;
-use std::{env::var, fs::File, io::stderr};
+use std::{env::var, fs::File, io::stderr, time::Duration};
 use time::{OffsetDateTime, UtcOffset};
 use tracing::metadata::LevelFilter;
 use tracing_subscriber::{
@@ -28,12 +29,21 @@ pub enum DebuggerError {
         argument: String,
         source: anyhow::Error,
     },
+    /// Attaching to the target failed, with the target voltage which the probe measured before.
+    #[error("Failed to attach to the target")]
+    Attach {
+        source: Error,
+        target_voltage: Option<f32>,
+    },
     #[error(transparent)]
     DebugProbe(#[from] DebugProbeError),
     #[error(transparent)]
     FileDownload(#[from] FileDownloadError),
     #[error("Received an invalid requeset")]
     InvalidRequest,
+    /// The client cancelled the request with a `cancel` request.
+    #[error("The request was cancelled")]
+    Cancelled,
     #[error("Command requires a value for argument '{argument_name}'")]
     MissingArgument { argument_name: String },
     #[error(transparent)]
@@ -54,6 +64,28 @@ pub enum DebuggerError {
     UnableToOpenProbe(Option<&'static str>),
     #[error("Request not implemented")]
     Unimplemented,
+    #[error("The request did not complete within {0:?}, and was cancelled")]
+    RequestTimeout(Duration),
+}
+
+impl DebuggerError {
+    /// The classification of the error, which is reported to the DAP client with the error.
+    pub(crate) fn code(&self) -> ErrorCode {
+        // Transparent variants don't report the error they contain as their source.
+        match self {
+            DebuggerError::AccessPort(error) => ErrorCode::of(error),
+            DebuggerError::Attach {
+                source,
+                target_voltage,
+            } => ErrorCode::of(source).with_target_voltage(*target_voltage),
+            DebuggerError::DebugProbe(error) => ErrorCode::of(error),
+            DebuggerError::FileDownload(error) => ErrorCode::of(error),
+            DebuggerError::Other(error) => ErrorCode::of(error.as_ref()),
+            DebuggerError::ProbeRs(error) => ErrorCode::of(error),
+            DebuggerError::UnableToOpenProbe(_) => ErrorCode::ProbeNotFound,
+            other => ErrorCode::of(other),
+        }
+    }
 }
 
 /// Open target in debug mode and accept debug commands.
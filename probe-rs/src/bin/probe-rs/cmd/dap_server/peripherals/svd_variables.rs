@@ -2,6 +2,7 @@ use crate::cmd::dap_server::{
     debug_adapter::{dap::adapter::DebugAdapter, protocol::ProtocolAdapter},
     DebuggerError,
 };
+use crate::util::interrupts::InterruptInfo;
 use probe_rs::{
     debug::{
         Variable, VariableCache, VariableLocation, VariableName, VariableNodeType, VariableType,
@@ -22,6 +23,8 @@ pub(crate) struct SvdCache {
     /// Unlike other VariableCache instances, it will only be built once per DebugSession.
     /// After that, only the SVD fields values change values, and the data for these will be re-read everytime they are queried by the debugger.
     pub(crate) svd_variable_cache: VariableCache,
+    /// The names of the interrupts, and the configuration of the interrupt controller.
+    pub(crate) interrupt_info: InterruptInfo,
 }
 
 impl SvdCache {
@@ -53,7 +56,9 @@ impl SvdCache {
                             )
                             .ok();
 
+                        let interrupt_info = interrupt_info_from_svd(&peripheral_device);
                         Ok(SvdCache {
+                            interrupt_info,
                             svd_variable_cache: variable_cache_from_svd(
                                 peripheral_device,
                                 core,
@@ -76,6 +81,28 @@ impl SvdCache {
     }
 }
 
+/// Collect the names of the interrupts, the number of NVIC priority bits, and the base address of the PLIC.
+fn interrupt_info_from_svd(peripheral_device: &Device) -> InterruptInfo {
+    let mut interrupt_info = InterruptInfo {
+        nvic_priority_bits: peripheral_device
+            .cpu
+            .as_ref()
+            .map(|cpu| cpu.nvic_priority_bits),
+        ..Default::default()
+    };
+    for peripheral in &peripheral_device.peripherals {
+        for interrupt in &peripheral.interrupt {
+            interrupt_info
+                .names
+                .insert(interrupt.value, interrupt.name.clone());
+        }
+        if peripheral.name.eq_ignore_ascii_case("PLIC") {
+            interrupt_info.plic_base_address = Some(peripheral.base_address);
+        }
+    }
+    interrupt_info
+}
+
 /// Create a [`probe_rs::debug::VariableCache`] from a Device that was parsed from a CMSIS-SVD file.
 pub(crate) fn variable_cache_from_svd<P: ProtocolAdapter>(
     peripheral_device: Device,
@@ -2,11 +2,31 @@
 pub(crate) mod configuration;
 /// The data structures borrowed from the [`session_data::SessionData`], that applies to a specific core.
 pub(crate) mod core_data;
+/// The debugger support for heap statistics.
+pub(crate) mod debug_heap;
+/// The debugger support for detecting Rust panics.
+pub(crate) mod debug_panic;
 /// The debugger support for rtt.
 pub(crate) mod debug_rtt;
 /// Implements the part of the debug server that processes incoming requests from the [`crate::debug_adapter::dap::adapter::DebugAdapter`]
 pub(crate) mod debugger;
+/// The snapshots of the registers and the memory of a core at its last stops, to step back.
+pub(crate) mod execution_history;
+/// External commands which are run at points of the session lifecycle.
+pub(crate) mod lifecycle_commands;
+/// The breakpoints and watch expressions which are kept across debug sessions.
+pub(crate) mod persistent_state;
+/// The interval between polls of running cores.
+pub(crate) mod poll_interval;
 /// The data structures needed to keep track of a session status in the debugger.
 pub(crate) mod session_data;
 /// This is where the primary processing for the debugger is driven from.
 pub(crate) mod startup;
+/// Register write sequences which emulate external inputs.
+pub(crate) mod stimulus;
+/// The debugger support for more than one binary on a core.
+pub(crate) mod symbol_files;
+/// The output which is pulsed on selected debug events, to arm external instruments.
+pub(crate) mod trigger;
+/// The values of registers across halts, to show which of them changed since the previous stop.
+pub(crate) mod value_history;
@@ -1,9 +1,14 @@
+use crate::util::common_options::load_user_boards;
 use crate::util::rtt;
 use crate::{cmd::dap_server::DebuggerError, FormatOptions};
 use anyhow::{anyhow, Result};
-use probe_rs::{DebugProbeSelector, WireProtocol};
+use probe_rs::{
+    config::{get_board_by_name, BoardProbe},
+    flashing::{self, BinOptions, Format, ImagePatch},
+    DebugProbeSelector, DisconnectAction, WireProtocol,
+};
 use serde::Deserialize;
-use std::{env::current_dir, path::PathBuf};
+use std::{env::current_dir, path::PathBuf, str::FromStr};
 
 /// Shared options for all session level configuration.
 #[derive(Clone, Deserialize, Debug, Default)]
@@ -20,9 +25,27 @@ pub struct SessionConfig {
     #[serde(alias = "probe")]
     pub(crate) probe_selector: Option<DebugProbeSelector>,
 
+    /// Use the target of a GDB server at this address, e.g. `localhost:3333` for OpenOCD, instead of a debug probe.
+    pub(crate) gdb_remote: Option<String>,
+
+    /// The GDB server given by `gdb_remote` is the GDB stub of an emulator, e.g. QEMU, whose debug registers are emulated.
+    #[serde(default)]
+    pub(crate) gdb_remote_emulator: bool,
+
+    /// Wait up to this many seconds for the probe given by `probe_selector` to be connected, instead of failing immediately.
+    pub(crate) wait_for_probe: Option<u64>,
+
     /// The target to be selected.
     pub(crate) chip: Option<String>,
 
+    /// The board to connect to, e.g. `nucleo-h743zi`. It selects the chip, and the protocol, speed, reset and RTT
+    /// options of the board, unless they are given.
+    pub(crate) board: Option<String>,
+
+    /// The debug probes of the `board`, one of which is used if more than one probe is connected.
+    #[serde(skip)]
+    pub(crate) board_probes: Vec<BoardProbe>,
+
     /// Assert target's reset during connect
     #[serde(default)]
     pub(crate) connect_under_reset: bool,
@@ -41,6 +64,47 @@ pub struct SessionConfig {
     #[serde(default)]
     pub(crate) flashing_config: FlashingConfig,
 
+    /// Start a GDB server on this address (e.g. `127.0.0.1:1337`), which shares the session with the debugger.
+    pub(crate) gdb_connection_string: Option<String>,
+
+    /// The bounds of the interval between polls of the running target, while its RTT channels are idle.
+    #[serde(default)]
+    pub(crate) polling: PollingConfig,
+
+    /// Cancel a `readMemory` request which did not complete after this many milliseconds, e.g. because the bus of the
+    /// target stalls, and respond with an error instead of blocking the session.
+    pub(crate) request_timeout: Option<u64>,
+
+    /// Periodically report the usage of the heap of the program, with the custom `probe-rs-heap-stats` event.
+    pub(crate) heap_config: Option<HeapConfig>,
+
+    /// Keep the breakpoints and watch expressions of the program across debug sessions, and restore the breakpoints
+    /// the debug client does not set itself.
+    #[serde(default)]
+    pub(crate) persist_debug_state: bool,
+
+    /// RAM ranges whose contents are kept across flashing and the reset after it, e.g. a region which the program
+    /// does not initialize and which holds crash logs or boot flags. They are read before flashing, and written back
+    /// after the reset, before the program runs.
+    #[serde(default)]
+    pub(crate) keep_ram: Vec<KeptRamRange>,
+
+    /// What happens to the target when the debug client disconnects, or the debugger exits. Without it, the core is
+    /// halted if the client asks to terminate or suspend the program, and left running otherwise.
+    pub(crate) on_disconnect: Option<OnDisconnect>,
+
+    /// External commands which are run at points of the session lifecycle, e.g. to sign the image or to switch the power of the target.
+    #[serde(default)]
+    pub(crate) lifecycle_commands: LifecycleCommands,
+
+    /// An output which is pulsed when selected debug events occur, to arm an external oscilloscope or logic analyzer.
+    pub(crate) trigger: Option<TriggerConfig>,
+
+    /// Register write sequences which emulate external inputs, e.g. a GPIO input which toggles every 10 ms while the
+    /// program runs.
+    #[serde(default)]
+    pub(crate) stimuli: Vec<StimulusConfig>,
+
     /// Every core on the target has certain configuration.
     ///
     /// NOTE: Although we allow specifying multiple core configurations, this is a work in progress, and probe-rs-debugger currently only supports debugging a single core.
@@ -53,6 +117,8 @@ impl SessionConfig {
         // Update the `cwd`.
         self.cwd = self.resolve_cwd()?;
 
+        self.apply_board()?;
+
         for target_core_config in &mut self.core_configs {
             // Update the `program_binary` and validate that the file exists.
             target_core_config.program_binary = match get_absolute_path(
@@ -74,6 +140,17 @@ impl SessionConfig {
                         )));
                 }
             };
+            // Update the `symbol_files` and validate that the files exist.
+            for symbol_file in &mut target_core_config.symbol_files {
+                symbol_file.program_binary =
+                    get_absolute_path(self.cwd.clone(), Some(&symbol_file.program_binary))?;
+                if !symbol_file.program_binary.is_file() {
+                    return Err(DebuggerError::Other(anyhow!(
+                        "Invalid symbol file specified '{:?}'",
+                        symbol_file.program_binary
+                    )));
+                }
+            }
             // Update the `svd_file` and validate that the file exists, or else return an error.
             target_core_config.svd_file =
                 match get_absolute_path(self.cwd.clone(), target_core_config.svd_file.as_ref()) {
@@ -95,6 +172,42 @@ impl SessionConfig {
                 };
         }
 
+        // Update the `additional_images` and validate that the files exist.
+        for image in &mut self.flashing_config.additional_images {
+            image.path = get_absolute_path(self.cwd.clone(), Some(&image.path))?;
+            if !image.path.is_file() {
+                return Err(DebuggerError::Other(anyhow!(
+                    "Invalid additional image specified '{:?}'",
+                    image.path
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fill in the options which are not given from the `board`.
+    fn apply_board(&mut self) -> Result<(), DebuggerError> {
+        let Some(name) = &self.board else {
+            return Ok(());
+        };
+        load_user_boards(None).map_err(|error| DebuggerError::Other(error.into()))?;
+        let board = get_board_by_name(name).map_err(|error| DebuggerError::Other(error.into()))?;
+
+        self.chip.get_or_insert(board.chip);
+        if self.wire_protocol.is_none() {
+            self.wire_protocol = board.protocol;
+        }
+        if self.speed.is_none() {
+            self.speed = board.speed;
+        }
+        self.connect_under_reset |= board.connect_under_reset;
+        for core_config in &mut self.core_configs {
+            if core_config.rtt_config.scan_region.is_none() {
+                core_config.rtt_config.scan_region = board.rtt_scan_region.clone();
+            }
+        }
+        self.board_probes = board.probes;
         Ok(())
     }
 
@@ -148,6 +261,66 @@ fn get_absolute_path(
     }
 }
 
+/// Configuration options for the polling of running cores.
+///
+/// The interval starts at `min_interval_ms`, and doubles up to `max_interval_ms` while the RTT channels are idle. A
+/// request of the client or new RTT data resets it.
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PollingConfig {
+    #[serde(default = "default_min_poll_interval")]
+    pub(crate) min_interval_ms: u64,
+    #[serde(default = "default_max_poll_interval")]
+    pub(crate) max_interval_ms: u64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self {
+            min_interval_ms: default_min_poll_interval(),
+            max_interval_ms: default_max_poll_interval(),
+        }
+    }
+}
+
+fn default_min_poll_interval() -> u64 {
+    50
+}
+
+fn default_max_poll_interval() -> u64 {
+    500
+}
+
+/// The allocators whose heap statistics can be reported.
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HeapAllocator {
+    /// The `Heap` of `embedded-alloc` 0.5.
+    EmbeddedAlloc,
+    /// The `heap_4.c` memory management of FreeRTOS.
+    FreeRtosHeap4,
+}
+
+/// Configuration options for the heap statistics.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HeapConfig {
+    /// The allocator used by the program.
+    pub(crate) allocator: HeapAllocator,
+
+    /// The symbol of the heap. For `embeddedAlloc`, this is the `#[global_allocator]` static, which defaults to `HEAP`.
+    /// For `freeRtosHeap4`, this is the memory of the heap, which defaults to `ucHeap`.
+    pub(crate) symbol: Option<String>,
+
+    /// How often the heap is sampled, in milliseconds.
+    #[serde(default = "default_heap_interval")]
+    pub(crate) interval_ms: u64,
+}
+
+fn default_heap_interval() -> u64 {
+    1000
+}
+
 /// Configuration options to control flashing.
 #[derive(Clone, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
@@ -171,6 +344,230 @@ pub struct FlashingConfig {
     /// [`FormatOptions`] to control the flashing operation, depending on the type of binary ( [`probe_rs::flashing::Format`] ) to be flashed.
     #[serde(default)]
     pub(crate) format_options: FormatOptions,
+
+    /// Patches which are applied to the image before flashing, e.g. to fill in a checksum, a CRC or a serial number.
+    #[serde(default)]
+    pub(crate) patches: Vec<ImagePatch>,
+
+    /// Allow writing to one-time programmable (OTP) memory
+    #[serde(default)]
+    pub(crate) allow_otp_write: bool,
+
+    /// Images which are flashed together with the `program_binary`, e.g. a Nordic SoftDevice next to the application.
+    #[serde(default)]
+    pub(crate) additional_images: Vec<AdditionalImage>,
+}
+
+/// An image of [`FlashingConfig::additional_images`].
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AdditionalImage {
+    /// The image as a path. Relative to `cwd`, or fully qualified.
+    pub(crate) path: PathBuf,
+
+    /// The format of the image, `elf`, `hex` or `bin`. By default, it is taken from the extension of the file.
+    pub(crate) format: Option<String>,
+
+    /// The address at which a `bin` image is flashed.
+    pub(crate) base_address: Option<u64>,
+}
+
+impl AdditionalImage {
+    /// The image, as it is passed to [`DownloadOptions::additional_images`](probe_rs::flashing::DownloadOptions).
+    pub(crate) fn to_image(&self) -> Result<flashing::AdditionalImage> {
+        let format = match &self.format {
+            Some(format) => Format::from_str(format).map_err(|error| anyhow!(error))?,
+            // Files with other extensions, like `.axf`, are ELF files.
+            None => self
+                .path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .and_then(|extension| Format::from_str(extension).ok())
+                .unwrap_or_default(),
+        };
+        let format = match format {
+            Format::Bin(_) => Format::Bin(BinOptions {
+                base_address: self.base_address,
+                skip: 0,
+            }),
+            Format::Idf(_) => {
+                return Err(anyhow!(
+                    "The esp-idf format is not supported for additional images"
+                ))
+            }
+            format => format,
+        };
+        Ok(flashing::AdditionalImage {
+            path: self.path.clone(),
+            format,
+        })
+    }
+}
+
+/// The policies of [`SessionConfig::on_disconnect`]. The hardware breakpoints are always removed.
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OnDisconnect {
+    /// Resume the core if it is halted, and disable its debug logic.
+    LeaveRunning,
+    /// Halt the core.
+    Halt,
+    /// Reset the target and let it run.
+    ResetAndRun,
+    /// Reset the target and halt the core at the first instruction.
+    ResetAndHalt,
+}
+
+impl From<OnDisconnect> for DisconnectAction {
+    fn from(on_disconnect: OnDisconnect) -> Self {
+        match on_disconnect {
+            OnDisconnect::LeaveRunning => DisconnectAction::Run,
+            OnDisconnect::Halt => DisconnectAction::Halt,
+            OnDisconnect::ResetAndRun => DisconnectAction::ResetAndRun,
+            OnDisconnect::ResetAndHalt => DisconnectAction::ResetAndHalt,
+        }
+    }
+}
+
+/// A RAM range of [`SessionConfig::keep_ram`].
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeptRamRange {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+}
+
+/// External commands which are run at points of the session lifecycle.
+///
+/// The commands are run in the `cwd` of the session, with the environment variables `PROBE_RS_CHIP`,
+/// `PROBE_RS_PROBE` (if a `probe` is selected) and `PROBE_RS_PROGRAM_BINARY`. Their output is shown in the debug console.
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleCommands {
+    /// Run before the binary is flashed, when the session is launched or restarted. If one of them fails, the binary is not flashed.
+    #[serde(default)]
+    pub(crate) before_flash: Vec<ExternalCommand>,
+
+    /// Run when the debug session ends. Failures are only reported.
+    #[serde(default)]
+    pub(crate) after_session: Vec<ExternalCommand>,
+}
+
+/// An output which is pulsed when selected debug events occur, see [`SessionConfig::trigger`].
+#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerConfig {
+    /// The events which fire the trigger.
+    pub(crate) events: Vec<TriggerEvent>,
+
+    /// Pulse this pin of the debug probe. The pins are not used by SWD, so this requires an SWD connection, and is
+    /// supported by CMSIS-DAP probes and J-Links.
+    pub(crate) probe_pin: Option<TriggerPin>,
+
+    /// The pin is low during the pulse, instead of high.
+    #[serde(default)]
+    pub(crate) active_low: bool,
+
+    /// The length of the pulse of `probePin`, in microseconds.
+    #[serde(default = "default_pulse_length")]
+    pub(crate) pulse_length: u64,
+
+    /// Start this command without waiting for it to exit, e.g. `sigrok-cli` to start a capture. It is run like the
+    /// [`LifecycleCommands`].
+    pub(crate) command: Option<ExternalCommand>,
+}
+
+fn default_pulse_length() -> u64 {
+    10
+}
+
+/// A debug event which fires the trigger, see [`TriggerConfig`].
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TriggerEvent {
+    /// A core halted on a breakpoint.
+    BreakpointHit,
+    /// Flashing the program starts.
+    FlashStart,
+}
+
+/// A pin of the debug probe which can be pulsed, see [`TriggerConfig::probe_pin`].
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TriggerPin {
+    /// The TDI pin of the JTAG connector.
+    Tdi,
+    /// The nTRST pin of the JTAG connector.
+    Ntrst,
+}
+
+/// A sequence of register writes, which is written on a schedule or on debug events, see
+/// [`SessionConfig::stimuli`].
+#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StimulusConfig {
+    /// The name of the stimulus in messages.
+    pub(crate) name: Option<String>,
+
+    /// The core whose memory interface writes the registers.
+    #[serde(default)]
+    pub(crate) core_index: usize,
+
+    /// The writes, in order.
+    pub(crate) writes: Vec<RegisterWrite>,
+
+    /// Write the sequence every `intervalMs` milliseconds while the target runs. The interval starts again when the
+    /// target resumes.
+    pub(crate) interval_ms: Option<u64>,
+
+    /// Write the sequence when one of these events occurs.
+    #[serde(default)]
+    pub(crate) events: Vec<StimulusEvent>,
+
+    /// Stop after the sequence was written this many times.
+    pub(crate) count: Option<u32>,
+}
+
+/// A write of a 32-bit register, see [`StimulusConfig`].
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterWrite {
+    pub(crate) address: u64,
+    #[serde(flatten)]
+    pub(crate) operation: RegisterOperation,
+}
+
+/// How a [`RegisterWrite`] changes the register. All but `value` read the register first.
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RegisterOperation {
+    /// Write this value.
+    Value(u32),
+    /// Set these bits.
+    Set(u32),
+    /// Clear these bits.
+    Clear(u32),
+    /// Invert these bits.
+    Toggle(u32),
+}
+
+/// A debug event on which a stimulus is written, see [`StimulusConfig`].
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StimulusEvent {
+    /// The target starts running, or resumes after a halt.
+    Resumed,
+    /// A core halted on a breakpoint.
+    BreakpointHit,
+}
+
+/// A program and its arguments.
+#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalCommand {
+    pub(crate) command: String,
+    #[serde(default)]
+    pub(crate) args: Vec<String>,
 }
 
 /// Configuration options for all core level configuration.
@@ -184,13 +581,82 @@ pub struct CoreConfig {
     /// Binary to debug as a path. Relative to `cwd`, or fully qualified.
     pub(crate) program_binary: Option<PathBuf>,
 
+    /// The offset from the address `program_binary` is linked at to the address it runs at, e.g. when it is copied
+    /// to RAM. It moves the addresses of the code and the constants in the debug information, but not of variables.
+    #[serde(default)]
+    pub(crate) load_offset: i64,
+
+    /// More binaries which run on the core, e.g. the bootloader which starts `program_binary`.
+    /// The symbols of the binary whose code the core halts in are used. These binaries are not flashed.
+    #[serde(default)]
+    pub(crate) symbol_files: Vec<SymbolFile>,
+
     /// CMSIS-SVD file for the target. Relative to `cwd`, or fully qualified.
     pub(crate) svd_file: Option<PathBuf>,
 
+    /// Halt the core when the program panics, and report the panic message and location.
+    #[serde(default)]
+    pub(crate) break_on_panic: bool,
+
+    /// Log the decoded MPU or PMP regions to the console when the core halts because of an exception.
+    #[serde(default)]
+    pub(crate) decode_memory_protection: bool,
+
+    /// Allow writes to flash and to the regions of the memory map which are marked as read-only, e.g. from the
+    /// memory view. By default they are refused, to protect against a mistyped address.
+    #[serde(default)]
+    pub(crate) allow_protected_memory_writes: bool,
+
+    /// Record a snapshot of the registers and the memory of the core at each stop, so the debug client can step back
+    /// and continue in reverse through the recorded stops.
+    pub(crate) record_history: Option<RecordConfig>,
+
     #[serde(flatten)]
     pub(crate) rtt_config: rtt::RttConfig,
 }
 
+/// Configuration options of [`CoreConfig::record_history`].
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordConfig {
+    /// The number of snapshots which are kept. The oldest snapshot is dropped when a new one is recorded.
+    #[serde(default = "default_max_snapshots")]
+    pub(crate) max_snapshots: usize,
+
+    /// Also record a snapshot after each step, not only when the core halts on its own.
+    #[serde(default)]
+    pub(crate) record_steps: bool,
+
+    /// The memory ranges which are recorded. Defaults to the RAM regions of the memory map of the core.
+    #[serde(default)]
+    pub(crate) ranges: Vec<RecordedRange>,
+}
+
+fn default_max_snapshots() -> usize {
+    100
+}
+
+/// A memory range which is recorded, see [`RecordConfig::ranges`] and the custom `probe-rs/setSnapshotBreakpoints`
+/// request.
+#[derive(Clone, Copy, serde::Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedRange {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+}
+
+/// A binary which runs on a core besides its `program_binary`.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolFile {
+    /// Binary as a path. Relative to `cwd`, or fully qualified.
+    pub(crate) program_binary: PathBuf,
+
+    /// The address at which the code of the binary starts, if it runs at another address than it is linked at.
+    /// The debug information is moved by the difference, like with `load_offset`.
+    pub(crate) load_address: Option<u64>,
+}
+
 fn default_console_log() -> Option<ConsoleLog> {
     Some(ConsoleLog::Console)
 }
@@ -11,10 +11,19 @@ use crate::cmd::dap_server::{
         protocol::ProtocolAdapter,
     },
     peripherals::svd_variables::SvdCache,
-    server::debug_rtt,
+    server::{
+        debug_heap,
+        debug_panic::PanicReport,
+        debug_rtt,
+        execution_history::{ExecutionHistory, Snapshot},
+        persistent_state::PersistentState,
+        symbol_files::SymbolFiles,
+        value_history::ValueHistory,
+    },
     DebuggerError,
 };
 use crate::util::rtt::{self, ChannelMode, DataFormat, RttActiveTarget};
+use crate::util::{function_address, interrupts, memory_protection, PANIC_HANDLER_SYMBOL};
 use anyhow::{anyhow, Result};
 use probe_rs::{
     debug::{debug_info::DebugInfo, ColumnType, VerifiedBreakpoint},
@@ -36,11 +45,25 @@ pub struct CoreData {
     ///   These 'implicit' updates of `last_known_status` will not(and should not) result in a notification to the client.
     pub(crate) last_known_status: CoreStatus,
     pub(crate) target_name: String,
+    /// The debug information of the binary whose code the core runs.
     pub(crate) debug_info: DebugInfo,
+    /// The other binaries of the core, if there are any.
+    pub(crate) symbol_files: Option<SymbolFiles>,
     pub(crate) core_peripherals: Option<SvdCache>,
     pub(crate) stack_frames: Vec<probe_rs::debug::stack_frame::StackFrame>,
     pub(crate) breakpoints: Vec<session_data::ActiveBreakpoint>,
     pub(crate) rtt_connection: Option<debug_rtt::RttConnection>,
+    pub(crate) heap_monitor: Option<debug_heap::HeapMonitor>,
+    /// Log the memory protection regions when the core halts because of an exception.
+    pub(crate) decode_memory_protection: bool,
+    /// The breakpoints and watch expressions which are kept across debug sessions, if enabled.
+    pub(crate) persistent_state: Option<PersistentState>,
+    /// The values of the registers at the previous stop, to mark the registers which changed.
+    pub(crate) value_history: ValueHistory,
+    /// The snapshots of the last stops, if the history of the core is recorded.
+    pub(crate) execution_history: Option<ExecutionHistory>,
+    /// The snapshots which snapshot breakpoints captured, by their name.
+    pub(crate) snapshots: Vec<(String, Snapshot)>,
 }
 
 /// [CoreHandle] provides handles to various data structures required to debug a single instance of a core. The actual state is stored in [session_data::SessionData].
@@ -51,7 +74,37 @@ pub struct CoreHandle<'p> {
     pub(crate) core_data: &'p mut CoreData,
 }
 
+impl CoreData {
+    /// The names of the interrupts and the configuration of the interrupt controller, from the SVD file if it is loaded.
+    pub(crate) fn interrupt_info(&self) -> interrupts::InterruptInfo {
+        self.core_peripherals
+            .as_ref()
+            .map(|core_peripherals| core_peripherals.interrupt_info.clone())
+            .unwrap_or_default()
+    }
+}
+
 impl<'p> CoreHandle<'p> {
+    /// Use the symbols of the binary whose code the core is halted in, and tell the user when it changed.
+    pub(crate) fn select_symbol_file<P: ProtocolAdapter>(
+        &mut self,
+        debug_adapter: &mut DebugAdapter<P>,
+    ) {
+        let Some(symbol_files) = self.core_data.symbol_files.as_mut() else {
+            return;
+        };
+        let Ok(program_counter) = self.core.read_core_reg::<u64>(self.core.program_counter())
+        else {
+            return;
+        };
+        if let Some(image) = symbol_files.select(&mut self.core_data.debug_info, program_counter) {
+            debug_adapter.log_to_console(format!(
+                "Using the symbols of {:?}, which contains the program counter {program_counter:#010x}",
+                image.path
+            ));
+        }
+    }
+
     /// Some MS DAP requests (e.g. `step`) implicitly expect the core to resume processing and then to optionally halt again, before the request completes.
     ///
     /// This method is used to set the `last_known_status` to [`CoreStatus::Unknown`] (because we cannot verify that it will indeed resume running until we have polled it again),
@@ -89,22 +142,50 @@ impl<'p> CoreHandle<'p> {
                                 );
                             }
                             CoreStatus::Halted(_) => {
+                                self.core_data.value_history.next_stop();
+                                self.record_history();
                                 // HaltReason::Step is a special case, where we have to send a custome event to the client that the core halted.
                                 // In this case, we don't re-send the "stopped" event, but further down, we will
                                 // update the `last_known_status` to the actual HaltReason returned by the core.
+                                self.select_symbol_file(debug_adapter);
                                 if self.core_data.last_known_status
                                     != CoreStatus::Halted(HaltReason::Step)
                                 {
                                     let program_counter =
                                         self.core.read_core_reg(self.core.program_counter()).ok();
+                                    if let Some(program_counter) = program_counter {
+                                        self.capture_snapshot(debug_adapter, program_counter);
+                                    }
+                                    let (reason, description) = match self
+                                        .panic_report(status, program_counter)
+                                    {
+                                        Some(panic_report) => {
+                                            let description = format!("Core {panic_report}");
+                                            debug_adapter.log_to_console(&description);
+                                            debug_adapter
+                                                .show_message(MessageSeverity::Error, &description);
+                                            ("exception".to_owned(), description)
+                                        }
+                                        None => self.stopped_reason(status, program_counter),
+                                    };
+                                    if self.core_data.decode_memory_protection
+                                        && status == CoreStatus::Halted(HaltReason::Exception)
+                                    {
+                                        match memory_protection::read(&mut self.core) {
+                                            Ok(memory_protection) => {
+                                                debug_adapter.log_to_console(format!(
+                                                    "Memory protection at the exception:\n{memory_protection}"
+                                                ));
+                                            }
+                                            Err(error) => tracing::debug!(
+                                                "Failed to read the memory protection: {error:?}"
+                                            ),
+                                        }
+                                    }
+                                    let description = self.with_security_state(description);
                                     let event_body = Some(StoppedEventBody {
-                                        reason: status
-                                            .short_long_status(program_counter)
-                                            .0
-                                            .to_owned(),
-                                        description: Some(
-                                            status.short_long_status(program_counter).1,
-                                        ),
+                                        reason,
+                                        description: Some(description),
                                         thread_id: Some(self.core.id() as i64),
                                         preserve_focus_hint: Some(false),
                                         text: None,
@@ -156,6 +237,102 @@ impl<'p> CoreHandle<'p> {
         }
     }
 
+    /// Record a snapshot of the halted core, if the history of the core is recorded.
+    pub(crate) fn record_history(&mut self) {
+        if let Some(history) = self.core_data.execution_history.as_mut() {
+            if let Err(error) = history.record(&mut self.core) {
+                tracing::warn!("Failed to record a snapshot of the core: {error:?}");
+            }
+        }
+    }
+
+    /// Capture a snapshot, if the core halted on a snapshot breakpoint. The snapshot is named after the breakpoint and
+    /// the number of its hit, e.g. `loop#3`.
+    fn capture_snapshot<P: ProtocolAdapter>(
+        &mut self,
+        debug_adapter: &mut DebugAdapter<P>,
+        program_counter: u64,
+    ) {
+        let Some((_, breakpoint)) = self.find_breakpoint_in_cache(program_counter) else {
+            return;
+        };
+        let BreakpointType::SnapshotBreakpoint { name, ranges } =
+            breakpoint.breakpoint_type.clone()
+        else {
+            return;
+        };
+
+        let hit = 1 + self
+            .core_data
+            .snapshots
+            .iter()
+            .filter(|(snapshot_name, _)| {
+                snapshot_name.rsplit_once('#').map(|(tag, _)| tag) == Some(&*name)
+            })
+            .count();
+        let name = format!("{name}#{hit}");
+        match Snapshot::capture(&mut self.core, &ranges) {
+            Ok(snapshot) => {
+                debug_adapter.log_to_console(format!("Captured snapshot {name}"));
+                self.core_data.snapshots.push((name, snapshot));
+            }
+            Err(error) => {
+                debug_adapter
+                    .log_to_console(format!("Failed to capture snapshot {name}: {error:?}"));
+            }
+        }
+    }
+
+    /// The `reason` and the description of a stopped event for the core, which halted with `status`.
+    ///
+    /// The halt is classified with [`Core::stop_reason`], e.g. to name the breakpoint or the fault, and falls back to
+    /// the halt reason of `status` if that fails.
+    pub(crate) fn stopped_reason(
+        &mut self,
+        status: CoreStatus,
+        program_counter: Option<u64>,
+    ) -> (String, String) {
+        let (reason, description) = match self.core.stop_reason() {
+            Ok(Some(stop_reason)) => stop_reason.short_long_status(program_counter),
+            _ => status.short_long_status(program_counter),
+        };
+        (reason.to_owned(), description)
+    }
+
+    /// If the core halted on the panic breakpoint, extract the details of the panic.
+    fn panic_report(
+        &mut self,
+        status: CoreStatus,
+        program_counter: Option<u64>,
+    ) -> Option<PanicReport> {
+        if !matches!(status, CoreStatus::Halted(HaltReason::Breakpoint(_))) {
+            return None;
+        }
+        let (_, breakpoint) = self.find_breakpoint_in_cache(program_counter?)?;
+        if breakpoint.breakpoint_type != BreakpointType::PanicHandler {
+            return None;
+        }
+        PanicReport::from_halted_core(self)
+    }
+
+    /// Set a breakpoint on the panic handler of the program, so that the core halts as soon as it panics.
+    pub(crate) fn set_panic_breakpoint(
+        &mut self,
+        program_binary: &Path,
+    ) -> Result<(), DebuggerError> {
+        self.clear_breakpoints(BreakpointType::PanicHandler)?;
+
+        let Ok(Some(address)) = function_address(program_binary, PANIC_HANDLER_SYMBOL) else {
+            return Err(DebuggerError::UserMessage(format!(
+                "Cannot break on panic: the panic handler was not found in {}.",
+                program_binary.display()
+            )));
+        };
+        tracing::debug!("Setting panic breakpoint at {:#010x}", address);
+
+        self.set_breakpoint(address, BreakpointType::PanicHandler)
+    }
+
     /// Search available [`probe_rs::debug::StackFrame`]'s for the given `id`
     pub(crate) fn get_stackframe(
         &'p self,
@@ -177,12 +354,17 @@ impl<'p> CoreHandle<'p> {
         timestamp_offset: UtcOffset,
     ) -> Result<()> {
         let mut debugger_rtt_channels: Vec<debug_rtt::DebuggerRttChannel> = vec![];
-        // Attach to RTT by using the RTT control block address from the ELF file. Do not scan the memory for the control block.
+        // Attach to RTT by using the RTT control block address from the ELF file. Do not scan the memory for the control
+        // block, unless a scan region is configured.
         match File::open(program_binary)
             .map_err(|error| anyhow!("Error attempting to attach to RTT: {}", error))
             .and_then(|mut open_file| {
                 RttActiveTarget::get_rtt_symbol(&mut open_file).map_or_else(
-                    || Err(anyhow!("No RTT control block found in ELF file")),
+                    || {
+                        rtt_config
+                            .scan_region()
+                            .ok_or_else(|| anyhow!("No RTT control block found in ELF file"))
+                    },
                     |rtt_header_address| Ok(ScanRegion::Exact(rtt_header_address as u32)),
                 )
             })
@@ -194,11 +376,16 @@ impl<'p> CoreHandle<'p> {
                 tracing::info!("RTT initialized.");
                 RttActiveTarget::new(rtt, program_binary, rtt_config, timestamp_offset)
             }) {
-            Ok(target_rtt) => {
+            Ok(mut target_rtt) => {
+                target_rtt.record_plot_frames();
                 for any_channel in target_rtt.active_channels.iter() {
                     if let Some(up_channel) = &any_channel.up_channel {
-                        if any_channel.data_format == DataFormat::Defmt {
-                            // For defmt, we set the channel to be blocking when full.
+                        if matches!(
+                            any_channel.data_format,
+                            DataFormat::Defmt | DataFormat::Printf
+                        ) {
+                            // For defmt and printf, we set the channel to be blocking when full, because a lost byte
+                            // corrupts the following messages.
                             up_channel.set_mode(&mut self.core, ChannelMode::BlockIfFull)?;
                         }
                         debugger_rtt_channels.push(debug_rtt::DebuggerRttChannel {
@@ -227,12 +414,42 @@ impl<'p> CoreHandle<'p> {
 
     /// Check if a breakpoint address is already cached in [`CoreData::breakpoints`].
     /// Use this to avoid duplicate breakpoint entries, and also to help with clearing existing breakpoints on request.
+    /// Addresses at different aliases of the same memory match.
     fn find_breakpoint_in_cache(&self, address: u64) -> Option<(usize, &ActiveBreakpoint)> {
+        let debug_info = &self.core_data.debug_info;
+        let address = debug_info.program_address(address);
         self.core_data
             .breakpoints
             .iter()
             .enumerate()
-            .find(|(_, breakpoint)| breakpoint.address == address)
+            .find(|(_, breakpoint)| debug_info.program_address(breakpoint.address) == address)
+    }
+
+    /// Add the security state to a description of a halted core, for cores with TrustZone-M.
+    pub(crate) fn with_security_state(&mut self, description: String) -> String {
+        match self.core.security_status() {
+            Ok(Some(security)) => format!("{description} ({} state)", security.state),
+            _ => description,
+        }
+    }
+
+    /// A note about the TrustZone-M security attribution of `address`, e.g. for breakpoint messages.
+    ///
+    /// Returns `None` for cores without TrustZone-M.
+    pub(crate) fn security_note(&mut self, address: u64) -> Option<String> {
+        let security = self.core.security_status().ok().flatten()?;
+
+        if !security.secure_debug_enabled {
+            return Some(
+                "Secure code cannot be debugged, because secure debug is not enabled".to_string(),
+            );
+        }
+
+        self.core
+            .security_attribution(address)
+            .ok()
+            .flatten()
+            .map(|attribution| format!("{attribution} memory"))
     }
 
     /// Set a single breakpoint in target configuration as well as [`super::core_data::CoreHandle`]
@@ -321,6 +538,20 @@ impl<'p> CoreHandle<'p> {
                 requested_breakpoint_line,
                 requested_breakpoint_column,
             )
+            .or_else(|debug_error| {
+                // The source can belong to another binary of the core.
+                self.core_data
+                    .symbol_files
+                    .as_ref()
+                    .and_then(|symbol_files| {
+                        symbol_files.breakpoint_location(
+                            source_path,
+                            requested_breakpoint_line,
+                            requested_breakpoint_column,
+                        )
+                    })
+                    .ok_or(debug_error)
+            })
             .map_err(|debug_error|
                 DebuggerError::Other(anyhow!("Cannot set breakpoint here. Try reducing compile time-, and link time-, optimization in your build configuration, or choose a different source location: {debug_error}")))?;
         self.set_breakpoint(
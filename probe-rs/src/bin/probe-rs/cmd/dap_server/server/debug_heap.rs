@@ -0,0 +1,382 @@
+//! Heap statistics, read from the data structures of the allocator of the firmware.
+//!
+//! The allocator is configured with [`HeapConfig`], and its data structures are found with the symbols of the
+//! program binary. The heap is sampled periodically, and whenever the statistics change, they are sent to the
+//! client with the custom `probe-rs-heap-stats` event.
+//!
+//! Only 32-bit targets are supported.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use probe_rs::{Core, MemoryInterface};
+
+use super::configuration::{HeapAllocator, HeapConfig};
+use crate::cmd::dap_server::debug_adapter::{
+    dap::{
+        adapter::DebugAdapter,
+        dap_types::{HeapStatsEventBody, MessageSeverity},
+    },
+    protocol::ProtocolAdapter,
+};
+
+/// The maximum number of free blocks which are followed, so that a corrupted free list can not loop forever.
+const MAX_FREE_BLOCKS: usize = 4096;
+
+/// The usage of the heap at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HeapStats {
+    /// The size of the heap in bytes.
+    pub(crate) size: u64,
+    /// The number of allocated bytes.
+    pub(crate) used: u64,
+    /// The highest number of allocated bytes.
+    pub(crate) peak: u64,
+    /// The number of free blocks.
+    pub(crate) free_blocks: u64,
+    /// The size of the largest free block in bytes, which is the largest allocation that can succeed.
+    pub(crate) largest_free_block: u64,
+}
+
+impl HeapStats {
+    /// The fragmentation of the free memory, from 0 (all free memory is in one block) towards 1.
+    pub(crate) fn fragmentation(&self) -> f64 {
+        let free = self.size.saturating_sub(self.used);
+        if free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f64 / free as f64
+        }
+    }
+}
+
+/// The addresses of the data structures of an allocator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HeapLayout {
+    /// The `Heap` static of `embedded-alloc` 0.5, which wraps the heap of `linked_list_allocator` 0.10.
+    ///
+    /// The layout of Rust types is not stable, so this relies on the layout which the compiler currently uses:
+    /// the `RefCell` borrow flag, `used`, the dummy first hole (`size`, `next`), `bottom` and `top`.
+    EmbeddedAlloc { heap: u64 },
+    /// The statics of the FreeRTOS `heap_4.c`.
+    FreeRtosHeap4 {
+        /// The size of the `ucHeap` array.
+        heap_size: u64,
+        /// `xStart`, whose first member points to the first free block.
+        start: u64,
+        /// `pxEnd`, the pointer to the marker block at the end of the free list.
+        end_pointer: u64,
+        /// `xFreeBytesRemaining`.
+        free_bytes: u64,
+        /// `xMinimumEverFreeBytesRemaining`.
+        minimum_ever_free_bytes: u64,
+    },
+}
+
+impl HeapLayout {
+    /// Find the data structures of the configured allocator in the program binary.
+    fn find(config: &HeapConfig, program_binary: &Path) -> Result<Self> {
+        let buffer = std::fs::read(program_binary)
+            .with_context(|| format!("Failed to read {}", program_binary.display()))?;
+        let binary = goblin::elf::Elf::parse(buffer.as_slice())?;
+
+        let symbol = |name: &str| {
+            binary
+                .syms
+                .iter()
+                .find(|sym| {
+                    binary.strtab.get_at(sym.st_name).map_or(false, |raw_name| {
+                        let demangled = addr2line::demangle_auto(Cow::from(raw_name), None);
+                        raw_name == name
+                            || demangled == name
+                            || demangled.ends_with(&format!("::{name}"))
+                    })
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        "The symbol `{name}` of the heap was not found in {}",
+                        program_binary.display()
+                    )
+                })
+        };
+
+        Ok(match config.allocator {
+            HeapAllocator::EmbeddedAlloc => HeapLayout::EmbeddedAlloc {
+                heap: symbol(config.symbol.as_deref().unwrap_or("HEAP"))?.st_value,
+            },
+            HeapAllocator::FreeRtosHeap4 => HeapLayout::FreeRtosHeap4 {
+                heap_size: symbol(config.symbol.as_deref().unwrap_or("ucHeap"))?.st_size,
+                start: symbol("xStart")?.st_value,
+                end_pointer: symbol("pxEnd")?.st_value,
+                free_bytes: symbol("xFreeBytesRemaining")?.st_value,
+                minimum_ever_free_bytes: symbol("xMinimumEverFreeBytesRemaining")?.st_value,
+            },
+        })
+    }
+
+    /// Read the statistics of the heap, or `None` if the heap was not initialized yet.
+    ///
+    /// The statistics of `embedded-alloc` do not include the peak usage, so it is set to the current usage.
+    fn read(&self, mut read_word: impl FnMut(u64) -> Result<u32>) -> Result<Option<HeapStats>> {
+        match *self {
+            HeapLayout::EmbeddedAlloc { heap } => {
+                let used = read_word(heap + 4)? as u64;
+                let first_hole = read_word(heap + 12)? as u64;
+                let bottom = read_word(heap + 16)? as u64;
+                let top = read_word(heap + 20)? as u64;
+
+                if bottom == 0 {
+                    return Ok(None);
+                }
+                if top < bottom || used > top - bottom {
+                    bail!("The heap at {heap:#010x} does not look like the heap of embedded-alloc 0.5");
+                }
+
+                let (free_blocks, largest_free_block) = walk_free_list(first_hole, 0, |hole| {
+                    if !(bottom..top).contains(&hole) {
+                        bail!("The free block at {hole:#010x} is outside of the heap");
+                    }
+                    Ok((read_word(hole)? as u64, read_word(hole + 4)? as u64))
+                })?;
+
+                Ok(Some(HeapStats {
+                    size: top - bottom,
+                    used,
+                    peak: used,
+                    free_blocks,
+                    largest_free_block,
+                }))
+            }
+            HeapLayout::FreeRtosHeap4 {
+                heap_size,
+                start,
+                end_pointer,
+                free_bytes,
+                minimum_ever_free_bytes,
+            } => {
+                let end = read_word(end_pointer)? as u64;
+                if end == 0 {
+                    return Ok(None);
+                }
+
+                let free = read_word(free_bytes)? as u64;
+                let minimum_ever_free = read_word(minimum_ever_free_bytes)? as u64;
+                let first_block = read_word(start)? as u64;
+
+                let (free_blocks, largest_free_block) =
+                    walk_free_list(first_block, end, |block| {
+                        // The highest bit of the size marks allocated blocks, and is never set in the free list.
+                        let size = read_word(block + 4)? & 0x7FFF_FFFF;
+                        Ok((size as u64, read_word(block)? as u64))
+                    })?;
+
+                Ok(Some(HeapStats {
+                    size: heap_size,
+                    used: heap_size.saturating_sub(free),
+                    peak: heap_size.saturating_sub(minimum_ever_free),
+                    free_blocks,
+                    largest_free_block,
+                }))
+            }
+        }
+    }
+}
+
+/// Follow a free list from `first` until `end`, and return the number of blocks and the size of the largest one.
+///
+/// `read_block` returns the size of a block and the address of the next one.
+fn walk_free_list(
+    first: u64,
+    end: u64,
+    mut read_block: impl FnMut(u64) -> Result<(u64, u64)>,
+) -> Result<(u64, u64)> {
+    let mut blocks = 0;
+    let mut largest = 0;
+
+    let mut block = first;
+    while block != end && block != 0 {
+        if blocks == MAX_FREE_BLOCKS as u64 {
+            bail!(
+                "The free list has more than {MAX_FREE_BLOCKS} blocks, it is most likely corrupted"
+            );
+        }
+        let (size, next) = read_block(block)?;
+        blocks += 1;
+        largest = largest.max(size);
+        block = next;
+    }
+
+    Ok((blocks, largest))
+}
+
+/// Samples the heap of a core, and reports changes of the statistics to the client.
+pub(crate) struct HeapMonitor {
+    layout: HeapLayout,
+    interval: Duration,
+    last_sample: Option<Instant>,
+    last_stats: Option<HeapStats>,
+    /// The highest usage which was sampled, for allocators which do not track it themselves.
+    sampled_peak: u64,
+    /// Failures are only shown to the user once, because they are most likely to repeat on every sample.
+    has_failed: bool,
+}
+
+impl HeapMonitor {
+    pub(crate) fn new(config: &HeapConfig, program_binary: &Path) -> Result<Self> {
+        Ok(HeapMonitor {
+            layout: HeapLayout::find(config, program_binary)?,
+            interval: Duration::from_millis(config.interval_ms),
+            last_sample: None,
+            last_stats: None,
+            sampled_peak: 0,
+            has_failed: false,
+        })
+    }
+
+    /// Sample the heap if the interval has passed, and send the statistics to the client if they changed.
+    pub(crate) fn poll<P: ProtocolAdapter>(
+        &mut self,
+        debug_adapter: &mut DebugAdapter<P>,
+        core: &mut Core,
+    ) {
+        if self
+            .last_sample
+            .map_or(false, |last_sample| last_sample.elapsed() < self.interval)
+        {
+            return;
+        }
+        self.last_sample = Some(Instant::now());
+
+        let mut stats = match self.layout.read(|address| Ok(core.read_word_32(address)?)) {
+            Ok(Some(stats)) => stats,
+            Ok(None) => return,
+            Err(error) => {
+                if !self.has_failed {
+                    self.has_failed = true;
+                    debug_adapter.show_message(
+                        MessageSeverity::Warning,
+                        format!("Failed to read the heap statistics: {error:?}"),
+                    );
+                } else {
+                    tracing::debug!("Failed to read the heap statistics: {error:?}");
+                }
+                return;
+            }
+        };
+
+        self.sampled_peak = self.sampled_peak.max(stats.peak);
+        stats.peak = self.sampled_peak;
+
+        if self.last_stats.as_ref() == Some(&stats) {
+            return;
+        }
+
+        debug_adapter.heap_stats(HeapStatsEventBody {
+            size: stats.size,
+            used: stats.used,
+            peak: stats.peak,
+            free_blocks: stats.free_blocks,
+            largest_free_block: stats.largest_free_block,
+            fragmentation: stats.fragmentation(),
+        });
+        self.last_stats = Some(stats);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn memory(words: &[(u64, u32)]) -> impl FnMut(u64) -> Result<u32> + '_ {
+        let words: HashMap<u64, u32> = words.iter().copied().collect();
+        move |address| {
+            words
+                .get(&address)
+                .copied()
+                .ok_or_else(|| anyhow!("{address:#x} is not mapped"))
+        }
+    }
+
+    #[test]
+    fn embedded_alloc_heap() {
+        let layout = HeapLayout::EmbeddedAlloc { heap: 0x100 };
+
+        let stats = layout
+            .read(memory(&[
+                (0x104, 0x300),
+                (0x10C, 0x1000),
+                (0x110, 0x1000),
+                (0x114, 0x2000),
+                // Two holes, of 0x800 and 0x500 bytes.
+                (0x1000, 0x800),
+                (0x1004, 0x1A00),
+                (0x1A00, 0x500),
+                (0x1A04, 0),
+            ]))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            HeapStats {
+                size: 0x1000,
+                used: 0x300,
+                peak: 0x300,
+                free_blocks: 2,
+                largest_free_block: 0x800,
+            }
+        );
+        assert!((stats.fragmentation() - (1.0 - 0x800 as f64 / 0xD00 as f64)).abs() < 1e-9);
+
+        // The heap was not initialized yet.
+        let stats = layout
+            .read(memory(&[(0x104, 0), (0x10C, 0), (0x110, 0), (0x114, 0)]))
+            .unwrap();
+        assert_eq!(stats, None);
+    }
+
+    #[test]
+    fn freertos_heap_4() {
+        let layout = HeapLayout::FreeRtosHeap4 {
+            heap_size: 0x1000,
+            start: 0x100,
+            end_pointer: 0x108,
+            free_bytes: 0x10C,
+            minimum_ever_free_bytes: 0x110,
+        };
+
+        let stats = layout
+            .read(memory(&[
+                (0x100, 0x2000),
+                (0x108, 0x2FF0),
+                (0x10C, 0x900),
+                (0x110, 0x400),
+                (0x2000, 0x2800),
+                (0x2004, 0x100),
+                (0x2800, 0x2FF0),
+                (0x2804, 0x800),
+            ]))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            HeapStats {
+                size: 0x1000,
+                used: 0x700,
+                peak: 0xC00,
+                free_blocks: 2,
+                largest_free_block: 0x800,
+            }
+        );
+    }
+
+    #[test]
+    fn looping_free_list_is_rejected() {
+        assert!(walk_free_list(0x100, 0, |block| Ok((0x10, block))).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use super::core_data::CoreHandle;
+use probe_rs::{
+    debug::{DebugInfo, StackFrame, Variable, VariableCache, VariableName},
+    Core,
+};
+use std::fmt::Display;
+
+/// The maximum depth that we will search through the children of `core::panic::PanicInfo` to find the message and location.
+const MAX_SEARCH_DEPTH: usize = 6;
+
+/// Stands in for a formatted argument of the panic message, whose value is not available.
+const ARGUMENT_PLACEHOLDER: &str = "{..}";
+
+/// The details of a panic, extracted from the `core::panic::PanicInfo` argument of the panic handler.
+#[derive(Debug, Default)]
+pub(crate) struct PanicReport {
+    /// The panic message.
+    ///
+    /// The formatted arguments are not available on the target, so only the literal pieces of the message are reported,
+    /// and each argument is replaced by `{..}`.
+    pub(crate) message: Option<String>,
+    /// The `file:line:column` of the code that panicked.
+    pub(crate) location: Option<String>,
+}
+
+impl Display for PanicReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "panicked at '{}'",
+            self.message.as_deref().unwrap_or("<unknown message>")
+        )?;
+        if let Some(location) = &self.location {
+            write!(f, ", {location}")?;
+        }
+        Ok(())
+    }
+}
+
+impl PanicReport {
+    /// Extract the panic message and location, while the core is halted at the panic handler.
+    pub(crate) fn from_halted_core(target_core: &mut CoreHandle) -> Option<Self> {
+        let program_counter = target_core
+            .core
+            .read_core_reg(target_core.core.program_counter())
+            .ok()?;
+        let mut stack_frames = target_core
+            .core_data
+            .debug_info
+            .unwind(&mut target_core.core, program_counter)
+            .ok()?;
+
+        let mut report = PanicReport::default();
+
+        if let Some(panic_frame) = stack_frames.first_mut() {
+            let debug_info = &target_core.core_data.debug_info;
+            let core = &mut target_core.core;
+
+            // Temporarily take the cache, so that we can pass the frame registers alongside it.
+            if let Some(mut local_variables) = panic_frame.local_variables.take() {
+                let mut resolver = VariableResolver {
+                    debug_info,
+                    core,
+                    stack_frame: panic_frame,
+                    cache: &mut local_variables,
+                };
+
+                if let Some(mut location) = resolver.find("location") {
+                    let file = resolver.find_value_of(&mut location, "file");
+                    let line = resolver.find_value_of(&mut location, "line");
+                    let column = resolver.find_value_of(&mut location, "col");
+                    report.location = file.map(|file| {
+                        format!(
+                            "{}:{}:{}",
+                            file,
+                            line.as_deref().unwrap_or("?"),
+                            column.as_deref().unwrap_or("?")
+                        )
+                    });
+                }
+
+                if let Some(mut pieces) = resolver.find("pieces") {
+                    let pieces = resolver.string_values(&mut pieces, MAX_SEARCH_DEPTH);
+                    // `args` is a slice, whose `length` is the number of formatted arguments.
+                    let argument_count = resolver
+                        .find("args")
+                        .and_then(|mut args| resolver.find_value_of(&mut args, "length"))
+                        .and_then(|length| length.parse().ok())
+                        .unwrap_or(0);
+                    report.message = render_message(&pieces, argument_count);
+                }
+
+                panic_frame.local_variables = Some(local_variables);
+            }
+        }
+
+        // If the location could not be resolved from the `PanicInfo`, use the first frame outside of the panic machinery.
+        if report.location.is_none() {
+            report.location = stack_frames
+                .iter()
+                .find(|frame| {
+                    !frame.function_name.contains("panic")
+                        && !frame.function_name.contains("unwind")
+                })
+                .and_then(|frame| frame.source_location.as_ref())
+                .and_then(|source_location| {
+                    source_location.combined_path().ok().map(|path| {
+                        format!(
+                            "{}:{}",
+                            path.display(),
+                            source_location
+                                .line
+                                .map_or_else(|| "?".to_string(), |line| line.to_string())
+                        )
+                    })
+                });
+        }
+
+        Some(report)
+    }
+}
+
+/// Interleave the static `pieces` of `fmt::Arguments` with a placeholder for each of its `argument_count` arguments,
+/// in the order in which `fmt::write` puts them together.
+fn render_message(pieces: &[String], argument_count: usize) -> Option<String> {
+    let mut message = String::new();
+    for index in 0..pieces.len().max(argument_count) {
+        if let Some(piece) = pieces.get(index) {
+            message.push_str(piece);
+        }
+        if index < argument_count {
+            message.push_str(ARGUMENT_PLACEHOLDER);
+        }
+    }
+    (!message.is_empty()).then_some(message)
+}
+
+/// Helper to search through the lazily resolved variables of a single stack frame.
+struct VariableResolver<'a, 'c, 'p> {
+    debug_info: &'a DebugInfo,
+    core: &'a mut Core<'c>,
+    stack_frame: &'a StackFrame,
+    cache: &'p mut VariableCache,
+}
+
+impl VariableResolver<'_, '_, '_> {
+    /// Search the whole variable cache for the first variable with the given name.
+    fn find(&mut self, name: &str) -> Option<Variable> {
+        let mut roots = self.cache.get_children(None).ok()?;
+        roots
+            .iter_mut()
+            .find_map(|root| self.find_child(root, name, MAX_SEARCH_DEPTH))
+    }
+
+    /// Search the children of `parent` for a variable with the given name, and return its value.
+    fn find_value_of(&mut self, parent: &mut Variable, name: &str) -> Option<String> {
+        self.find_child(parent, name, MAX_SEARCH_DEPTH)
+            .map(|variable| trim_quotes(&variable.get_value(self.cache)).to_string())
+    }
+
+    /// Return the values of all the string (`&str`) variables below `parent`, in order.
+    fn string_values(&mut self, parent: &mut Variable, depth: usize) -> Vec<String> {
+        let mut values = Vec::new();
+        for mut child in self.resolve_children(parent) {
+            if child.type_name.to_string() == "&str" {
+                values.push(trim_quotes(&child.get_value(self.cache)).to_string());
+            } else if depth > 0 {
+                values.extend(self.string_values(&mut child, depth - 1));
+            }
+        }
+        values
+    }
+
+    fn find_child(&mut self, parent: &mut Variable, name: &str, depth: usize) -> Option<Variable> {
+        let mut children = self.resolve_children(parent);
+
+        if let Some(found) = children
+            .iter()
+            .find(|child| child.name == VariableName::Named(name.to_string()))
+        {
+            return Some(found.clone());
+        }
+
+        if depth == 0 {
+            return None;
+        }
+
+        children
+            .iter_mut()
+            .find_map(|child| self.find_child(child, name, depth - 1))
+    }
+
+    /// Return the children of `parent`, resolving them first if they have been deferred.
+    fn resolve_children(&mut self, parent: &mut Variable) -> Vec<Variable> {
+        if parent.variable_node_type.is_deferred()
+            && !self.cache.has_children(parent).unwrap_or(true)
+        {
+            if let Err(error) = self.debug_info.cache_deferred_variables(
+                self.cache,
+                self.core,
+                parent,
+                &self.stack_frame.registers,
+                self.stack_frame.frame_base,
+            ) {
+                tracing::debug!("Failed to resolve children of {}: {:?}", parent.name, error);
+            }
+        }
+
+        self.cache
+            .get_children(Some(parent.variable_key))
+            .unwrap_or_default()
+    }
+}
+
+fn trim_quotes(value: &str) -> &str {
+    value.trim_matches('"')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pieces(pieces: &[&str]) -> Vec<String> {
+        pieces.iter().map(|piece| piece.to_string()).collect()
+    }
+
+    #[test]
+    fn render_message_without_arguments() {
+        assert_eq!(
+            render_message(&pieces(&["explicit panic"]), 0).as_deref(),
+            Some("explicit panic")
+        );
+    }
+
+    #[test]
+    fn render_message_marks_arguments() {
+        // panic!("index {} out of range for length {}", index, len)
+        assert_eq!(
+            render_message(&pieces(&["index ", " out of range for length "]), 2).as_deref(),
+            Some("index {..} out of range for length {..}")
+        );
+        // panic!("{} is not {}!", a, b)
+        assert_eq!(
+            render_message(&pieces(&["", " is not ", "!"]), 2).as_deref(),
+            Some("{..} is not {..}!")
+        );
+    }
+
+    #[test]
+    fn render_message_of_only_an_argument() {
+        // panic!("{}", message)
+        assert_eq!(render_message(&[], 1).as_deref(), Some("{..}"));
+    }
+
+    #[test]
+    fn render_message_without_anything() {
+        assert_eq!(render_message(&[], 0), None);
+    }
+}
@@ -58,15 +58,20 @@ impl DebuggerRttChannel {
                     }
                 })
                 .and_then(|rtt_channel| {
-                    match rtt_channel.get_rtt_data(core, rtt_target.defmt_state.as_ref()) {
-                        Ok(data_result) => data_result,
-                        Err(rtt_error) => {
-                            debug_adapter
-                                .show_error_message(&DebuggerError::Other(rtt_error))
-                                .ok();
-                            None
-                        }
+                    let data_result =
+                        match rtt_channel.get_rtt_data(core, rtt_target.defmt_state.as_ref()) {
+                            Ok(data_result) => data_result,
+                            Err(rtt_error) => {
+                                debug_adapter
+                                    .show_error_message(&DebuggerError::Other(rtt_error))
+                                    .ok();
+                                None
+                            }
+                        };
+                    for frame in rtt_channel.take_plot_frames() {
+                        debug_adapter.rtt_plot(self.channel_number, frame);
                     }
+                    data_result
                 })
                 .and_then(|(channel_number, channel_data)| {
                     if debug_adapter
@@ -1,7 +1,9 @@
 use super::{
-    configuration::{self, ConsoleLog},
+    configuration::{self, ConsoleLog, ExternalCommand, TriggerEvent},
+    lifecycle_commands::{command_environment, run_commands},
     session_data::SessionData,
-    startup::{get_file_timestamp, TargetSessionType},
+    startup::{get_file_hash, get_file_timestamp, TargetSessionType},
+    trigger::fire_trigger,
 };
 use crate::cmd::dap_server::{
     debug_adapter::{
@@ -20,18 +22,11 @@ use crate::cmd::dap_server::{
 };
 use anyhow::{anyhow, Context};
 use probe_rs::{
-    flashing::{download_file_with_options, DownloadOptions, FlashProgress},
-    Architecture, CoreStatus,
-};
-use std::{
-    cell::RefCell,
-    fs,
-    ops::Mul,
-    path::Path,
-    rc::Rc,
-    thread,
-    time::{Duration, UNIX_EPOCH},
+    flashing::{download_file_with_options, DownloadOptions, FlashProgress, RamSnapshot},
+    messages::{self, MessageId},
+    Architecture, CoreStatus, DisconnectAction,
 };
+use std::{cell::RefCell, ops::Mul, path::Path, rc::Rc, thread, time::Duration};
 use time::UtcOffset;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -57,8 +52,8 @@ pub struct Debugger {
     timestamp_offset: UtcOffset,
 
     // TODO: Store somewhere else
-    // Timestamp of the flashed binary
-    binary_timestamp: Option<Duration>,
+    /// The binary which was flashed last.
+    flashed_binary: Option<FlashedBinary>,
 }
 
 impl Debugger {
@@ -67,7 +62,7 @@ impl Debugger {
         Self {
             config: configuration::SessionConfig::default(),
             timestamp_offset,
-            binary_timestamp: None,
+            flashed_binary: None,
         }
     }
 
@@ -88,20 +83,25 @@ impl Debugger {
                 if debug_adapter.all_cores_halted {
                     // Once all cores are halted, then we can skip polling the core for status, and just wait for the next DAP Client request.
                     tracing::trace!(
-                        "Sleeping (all cores are halted) for 100ms to reduce polling overheaads."
+                        "Waiting (all cores are halted) for up to 100ms for the next request."
                     );
-                    thread::sleep(Duration::from_millis(100)); // Medium delay to reduce fast looping costs.
+                    debug_adapter.wait_for_input(Duration::from_millis(100));
                 } else {
                     // Poll ALL target cores for status, which includes synching status with the DAP client, and handling RTT data.
                     let (_, suggest_delay_required) =
                         session_data.poll_cores(&self.config, debug_adapter)?;
-                    // If there are no requests from the DAP Client, and there was no RTT data in the last poll, then we can sleep for a short period of time to reduce CPU usage.
+                    // If there are no requests from the DAP Client, and there was no RTT data in the last poll, then we can wait before polling again, and wait longer the longer the target is idle.
                     if debug_adapter.configuration_is_done() && suggest_delay_required {
+                        let mut interval = session_data.poll_interval.idle();
+                        if let Some(until_stimulus) = session_data.stimuli.until_next() {
+                            interval = interval.min(until_stimulus);
+                        }
                         tracing::trace!(
-                            "Sleeping (core is running) for 50ms to reduce polling overheads."
+                            "Waiting (core is running) for up to {interval:?} to reduce polling overheads."
                         );
-                        thread::sleep(Duration::from_millis(50)); // Small delay to reduce fast looping costs.
+                        debug_adapter.wait_for_input(interval);
                     } else {
+                        session_data.poll_interval.reset();
                         tracing::trace!("Retrieving data from the core, no delay required between iterations of polling the core.");
                     };
                 }
@@ -109,6 +109,7 @@ impl Debugger {
                 Ok(DebugSessionStatus::Continue)
             }
             Some(request) => {
+                session_data.poll_interval.reset();
                 // Poll ALL target cores for status, which includes synching status with the DAP client, and handling RTT data.
                 let (core_statuses, _) = session_data.poll_cores(&self.config, debug_adapter)?;
 
@@ -138,7 +139,22 @@ impl Debugger {
                         )));
                     };
 
-                let Ok(mut target_core) = session_data.attach_core(target_core_config.core_index) else {
+                let shared_session = session_data.session.clone();
+                let mut session = shared_session.lock();
+
+                // The reset line belongs to the whole target, so it is controlled without attaching to a core.
+                if request.command == "probe-rs/resetLine" {
+                    debug_adapter.reset_line(&mut session, &request)?;
+                    return Ok(DebugSessionStatus::Continue);
+                }
+
+                // The same goes for the unique ID of the chip.
+                if request.command == "probe-rs/uniqueId" {
+                    debug_adapter.unique_id(&mut session, &request)?;
+                    return Ok(DebugSessionStatus::Continue);
+                }
+
+                let Ok(mut target_core) = session_data.attach_core(&mut session, target_core_config.core_index) else {
                         return Err(DebuggerError::Other(anyhow!(
                             "Unable to connect to target core"
                         )));
@@ -161,7 +177,10 @@ impl Debugger {
                     | "variables"
                     | "readMemory"
                     | "writeMemory"
-                    | "disassemble" => {
+                    | "disassemble"
+                    | "probe-rs/stackUsage"
+                    | "probe-rs/memoryProtection"
+                    | "probe-rs/interrupts" => {
                         if new_status == &CoreStatus::Sleeping {
                             match target_core.core.halt(Duration::from_millis(100)) {
                                 Ok(_) => {
@@ -211,6 +230,27 @@ impl Debugger {
                         }
                         Ok(())
                     }
+                    "probe-rs/listProbes" => debug_adapter.list_probes(&request),
+                    "probe-rs/listChips" => debug_adapter.list_chips(&request),
+                    "probe-rs/chipInfo" => debug_adapter.chip_info(&request),
+                    "probe-rs/interrupts" => debug_adapter.interrupts(&mut target_core, &request),
+                    "probe-rs/memoryProtection" => {
+                        debug_adapter.memory_protection(&mut target_core, &request)
+                    }
+                    "probe-rs/savedState" => debug_adapter.saved_state(&mut target_core, &request),
+                    "probe-rs/setSnapshotBreakpoints" => {
+                        debug_adapter.set_snapshot_breakpoints(&mut target_core, &request)
+                    }
+                    "probe-rs/snapshots" => debug_adapter.snapshots(&mut target_core, &request),
+                    "probe-rs/diffSnapshots" => {
+                        debug_adapter.diff_snapshots(&mut target_core, &request)
+                    }
+                    "probe-rs/runUntil" => debug_adapter.run_until(&mut target_core, &request),
+                    "probe-rs/stackUsage" => debug_adapter.stack_usage(
+                        &mut target_core,
+                        target_core_config.program_binary.as_deref(),
+                        &request,
+                    ),
                     "disconnect" => {
                         let result = debug_adapter.disconnect(&mut target_core, &request);
                         debug_session = DebugSessionStatus::Terminate;
@@ -219,6 +259,8 @@ impl Debugger {
                     "next" => debug_adapter.next(&mut target_core, &request),
                     "stepIn" => debug_adapter.step_in(&mut target_core, &request),
                     "stepOut" => debug_adapter.step_out(&mut target_core, &request),
+                    "stepBack" => debug_adapter.step_back(&mut target_core, &request),
+                    "reverseContinue" => debug_adapter.reverse_continue(&mut target_core, &request),
                     "pause" => debug_adapter.pause(&mut target_core, &request),
                     "readMemory" => debug_adapter.read_memory(&mut target_core, &request),
                     "writeMemory" => debug_adapter.write_memory(&mut target_core, &request),
@@ -342,6 +384,7 @@ impl Debugger {
             let debug_session_status = self
                 .process_next_request(&mut session_data, &mut debug_adapter)
                 .or_else(|e| {
+                    self.run_after_session_commands(&session_data, &mut debug_adapter);
                     debug_adapter.show_message(
                         MessageSeverity::Error,
                         format!("Debug Adapter terminated unexpectedly with an error: {e:?}"),
@@ -364,12 +407,60 @@ impl Debugger {
                     debug_adapter = self.restart(debug_adapter, &mut session_data, &request)?;
                 }
                 DebugSessionStatus::Terminate => {
+                    if let Some(action) = debug_adapter.on_disconnect {
+                        session_data.session.lock().set_disconnect_action(action);
+                    }
+                    self.run_after_session_commands(&session_data, &mut debug_adapter);
                     return Ok(DebugSessionStatus::Terminate);
                 }
             };
         }
     }
 
+    /// Run external commands, with the environment of the session.
+    fn run_lifecycle_commands<P: ProtocolAdapter + 'static>(
+        &self,
+        commands: &[ExternalCommand],
+        session_data: &SessionData,
+        debug_adapter: &mut DebugAdapter<P>,
+    ) -> anyhow::Result<()> {
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        let chip = session_data.session.lock().target().name.clone();
+        let program_binary = self
+            .config
+            .core_configs
+            .first()
+            .and_then(|core_config| core_config.program_binary.as_deref());
+        let environment =
+            command_environment(&chip, self.config.probe_selector.as_ref(), program_binary);
+        run_commands(
+            commands,
+            &environment,
+            self.config.cwd.as_deref(),
+            debug_adapter,
+        )
+    }
+
+    /// Run the `afterSession` commands. A failure is only reported, because the session has already ended.
+    fn run_after_session_commands<P: ProtocolAdapter + 'static>(
+        &self,
+        session_data: &SessionData,
+        debug_adapter: &mut DebugAdapter<P>,
+    ) {
+        if let Err(error) = self.run_lifecycle_commands(
+            &self.config.lifecycle_commands.after_session,
+            session_data,
+            debug_adapter,
+        ) {
+            debug_adapter.log_to_console(format!(
+                "Warning: An `afterSession` command failed: {error:?}"
+            ));
+        }
+    }
+
     /// Process launch or attach request
     fn handle_launch_attach<P: ProtocolAdapter + 'static>(
         &mut self,
@@ -377,7 +468,14 @@ impl Debugger {
     ) -> Result<(DebugAdapter<P>, SessionData), DebuggerError> {
         let launch_attach_request = loop {
             if let Some(request) = debug_adapter.listen_for_request()? {
-                break request;
+                // The probe and chip information requests do not require a target session,
+                // so that DAP clients can use them to help the user fill in the launch configuration.
+                match request.command.as_str() {
+                    "probe-rs/listProbes" => debug_adapter.list_probes(&request)?,
+                    "probe-rs/listChips" => debug_adapter.list_chips(&request)?,
+                    "probe-rs/chipInfo" => debug_adapter.chip_info(&request)?,
+                    _ => break request,
+                }
             }
         };
 
@@ -428,13 +526,30 @@ impl Debugger {
             return Err(err.into());
         }
 
+        if let (Some(selector), Some(timeout)) =
+            (&self.config.probe_selector, self.config.wait_for_probe)
+        {
+            debug_adapter
+                .wait_for_probe(selector, Duration::from_secs(timeout))
+                .or_else(|error| {
+                    debug_adapter.show_error_message(&error)?;
+                    Err(error)
+                })?;
+        }
+
         let mut session_data =
             SessionData::new(&mut self.config, self.timestamp_offset).or_else(|error| {
                 debug_adapter.show_error_message(&error)?;
                 Err(error)
             })?;
 
+        for address in session_data.gdb_server_addresses() {
+            debug_adapter.log_to_console(format!("GDB server listening at {address}"));
+        }
+
         debug_adapter.halt_after_reset = self.config.flashing_config.halt_after_reset;
+        debug_adapter.on_disconnect = self.config.on_disconnect.map(DisconnectAction::from);
+        debug_adapter.request_timeout = self.config.request_timeout.map(Duration::from_millis);
 
         if self.config.flashing_config.flashing_enabled {
             let target_core_config = self.config.core_configs.first_mut().ok_or_else(|| {
@@ -449,8 +564,18 @@ impl Debugger {
                     return Err(err);
                 };
 
-            // Store timestamp of flashed binary
-            self.binary_timestamp = get_file_timestamp(&path_to_elf);
+            if let Err(error) = self.run_lifecycle_commands(
+                &self.config.lifecycle_commands.before_flash,
+                &session_data,
+                &mut debug_adapter,
+            ) {
+                let error = DebuggerError::Other(error.context("A `beforeFlash` command failed"));
+                debug_adapter.show_error_message(&error)?;
+                return Err(error);
+            }
+
+            // Store the timestamp and the hash of the flashed binary
+            self.flashed_binary = Some(FlashedBinary::of(&path_to_elf));
 
             debug_adapter = self.flash(
                 &path_to_elf,
@@ -467,8 +592,10 @@ impl Debugger {
         })?;
 
         // First, attach to the core
+        let shared_session = session_data.session.clone();
+        let mut session = shared_session.lock();
         let mut target_core = session_data
-            .attach_core(target_core_config.core_index)
+            .attach_core(&mut session, target_core_config.core_index)
             .or_else(|error| {
                 debug_adapter.show_error_message(&error)?;
                 Err(error)
@@ -498,6 +625,14 @@ impl Debugger {
             };
         }
 
+        if target_core_config.break_on_panic {
+            if let Some(program_binary) = &target_core_config.program_binary {
+                if let Err(error) = target_core.set_panic_breakpoint(program_binary) {
+                    debug_adapter.show_error_message(&error)?;
+                }
+            }
+        }
+
         if requested_target_session_type == TargetSessionType::LaunchRequest {
             // This will effectively do a `reset` and `halt` of the core, which is what we want until after the `configuration_done` request.
             debug_adapter
@@ -510,6 +645,7 @@ impl Debugger {
         }
 
         drop(target_core);
+        drop(session);
 
         debug_adapter.send_response::<()>(&launch_attach_request, Ok(None))?;
 
@@ -523,6 +659,17 @@ impl Debugger {
         request: &Request,
     ) -> Result<DebugAdapter<P>, DebuggerError> {
         if self.config.flashing_config.flashing_enabled {
+            // The commands run first, because they may build or sign the binary.
+            if let Err(error) = self.run_lifecycle_commands(
+                &self.config.lifecycle_commands.before_flash,
+                session_data,
+                &mut debug_adapter,
+            ) {
+                let error = DebuggerError::Other(error.context("A `beforeFlash` command failed"));
+                debug_adapter.show_error_message(&error)?;
+                return Err(error);
+            }
+
             let target_core_config = self.config.core_configs.first_mut().ok_or_else(|| {
                 DebuggerError::Other(anyhow!(
                     "Cannot continue unless one target core configuration is defined."
@@ -535,16 +682,27 @@ impl Debugger {
                     return Err(err);
                 };
 
-            if is_file_newer(&mut self.binary_timestamp, &path_to_elf) {
+            if is_binary_changed(&mut self.flashed_binary, &path_to_elf) {
                 // If there is a new binary as part of a restart, there are some key things that
                 // need to be 'reset' for things to work properly.
                 session_data.load_debug_info_for_core(target_core_config)?;
-                session_data
-                    .attach_core(target_core_config.core_index)
-                    .map(|mut target_core| target_core.recompute_breakpoints())??;
+                let shared_session = session_data.session.clone();
+                let mut session = shared_session.lock();
+                let mut target_core =
+                    session_data.attach_core(&mut session, target_core_config.core_index)?;
+                target_core.recompute_breakpoints()?;
+                if target_core_config.break_on_panic {
+                    target_core.set_panic_breakpoint(&path_to_elf)?;
+                }
+                drop(target_core);
+                drop(session);
 
                 debug_adapter =
                     self.flash(&path_to_elf, debug_adapter, request.seq, session_data)?;
+            } else {
+                debug_adapter.log_to_console(format!(
+                    "FLASHING: Skipped, because {path_to_elf:?} did not change since it was flashed"
+                ));
             }
         }
 
@@ -555,8 +713,10 @@ impl Debugger {
         })?;
 
         // First, attach to the core
+        let shared_session = session_data.session.clone();
+        let mut session = shared_session.lock();
         let mut target_core = session_data
-            .attach_core(target_core_config.core_index)
+            .attach_core(&mut session, target_core_config.core_index)
             .or_else(|error| {
                 debug_adapter.show_error_message(&error)?;
                 Err(error)
@@ -591,13 +751,78 @@ impl Debugger {
             "FLASHING: Starting write of {:?} to device memory",
             &path_to_elf
         ));
+        if let Err(error) = fire_trigger(
+            &self.config,
+            TriggerEvent::FlashStart,
+            &mut session_data.session.lock(),
+        ) {
+            debug_adapter.show_error_message(&DebuggerError::Other(
+                error.context("Failed to fire the trigger"),
+            ))?;
+        }
         let progress_id = debug_adapter
-            .start_progress("Flashing device", Some(request_id))
+            .start_progress(
+                &MessageId::ProgressFlashingDevice.to_string(),
+                Some(request_id),
+            )
             .ok();
 
         let mut download_options = DownloadOptions::default();
         download_options.keep_unwritten_bytes = self.config.flashing_config.restore_unwritten_bytes;
         download_options.do_chip_erase = self.config.flashing_config.full_chip_erase;
+        download_options.patches = self.config.flashing_config.patches.clone();
+        download_options.allow_otp_write = self.config.flashing_config.allow_otp_write;
+        download_options.partition = self
+            .config
+            .flashing_config
+            .format_options
+            .partition
+            .clone();
+        download_options.additional_images = match self
+            .config
+            .flashing_config
+            .additional_images
+            .iter()
+            .map(|image| image.to_image())
+            .collect::<Result<_, _>>()
+        {
+            Ok(images) => images,
+            Err(error) => {
+                let error = DebuggerError::Other(error);
+                debug_adapter.show_error_message(&error)?;
+                return Err(error);
+            }
+        };
+        download_options.keep_ram = self
+            .config
+            .keep_ram
+            .iter()
+            .map(|range| range.start..range.end)
+            .collect();
+
+        if !download_options.keep_ram.is_empty() {
+            let core_index = self
+                .config
+                .core_configs
+                .first()
+                .map_or(0, |core_config| core_config.core_index);
+            let snapshot = session_data
+                .session
+                .lock()
+                .core(core_index)
+                .and_then(|mut core| RamSnapshot::read(&mut core, &download_options.keep_ram));
+            match snapshot {
+                // The contents are restored after the reset which follows the flashing.
+                Ok(snapshot) => debug_adapter.kept_ram = Some(snapshot),
+                Err(error) => {
+                    let error = DebuggerError::Other(
+                        anyhow!(error).context("Failed to read the RAM ranges of `keepRam`"),
+                    );
+                    debug_adapter.show_error_message(&error)?;
+                    return Err(error);
+                }
+            }
+        }
 
         let rc_debug_adapter = Rc::new(RefCell::new(debug_adapter));
         let rc_debug_adapter_clone = rc_debug_adapter.clone();
@@ -640,7 +865,14 @@ impl Debugger {
                     }
                     probe_rs::flashing::ProgressEvent::StartedFilling => {
                         debug_adapter
-                            .update_progress(Some(0.0), Some("Reading Old Pages ..."), id)
+                            .update_progress(
+                                Some(0.0),
+                                Some(phase_message(
+                                    MessageId::ProgressStarted,
+                                    MessageId::ProgressReadingFlash,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::PageFilled { size, .. } => {
@@ -651,24 +883,45 @@ impl Debugger {
                         debug_adapter
                             .update_progress(
                                 Some(progress),
-                                Some(format!("Reading Old Pages ({progress})")),
+                                Some(percent_message(MessageId::ProgressReadingFlash, progress)),
                                 id,
                             )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::FailedFilling => {
                         debug_adapter
-                            .update_progress(Some(1.0), Some("Reading Old Pages Failed!"), id)
+                            .update_progress(
+                                Some(1.0),
+                                Some(phase_message(
+                                    MessageId::ProgressFailed,
+                                    MessageId::ProgressReadingFlash,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::FinishedFilling => {
                         debug_adapter
-                            .update_progress(Some(1.0), Some("Reading Old Pages Complete!"), id)
+                            .update_progress(
+                                Some(1.0),
+                                Some(phase_message(
+                                    MessageId::ProgressComplete,
+                                    MessageId::ProgressReadingFlash,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::StartedErasing => {
                         debug_adapter
-                            .update_progress(Some(0.0), Some("Erasing Sectors ..."), id)
+                            .update_progress(
+                                Some(0.0),
+                                Some(phase_message(
+                                    MessageId::ProgressStarted,
+                                    MessageId::ProgressErasingSectors,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::SectorErased { size, .. } => {
@@ -678,24 +931,45 @@ impl Debugger {
                         debug_adapter
                             .update_progress(
                                 Some(progress),
-                                Some(format!("Erasing Sectors ({progress})")),
+                                Some(percent_message(MessageId::ProgressErasingSectors, progress)),
                                 id,
                             )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::FailedErasing => {
                         debug_adapter
-                            .update_progress(Some(1.0), Some("Erasing Sectors Failed!"), id)
+                            .update_progress(
+                                Some(1.0),
+                                Some(phase_message(
+                                    MessageId::ProgressFailed,
+                                    MessageId::ProgressErasingSectors,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::FinishedErasing => {
                         debug_adapter
-                            .update_progress(Some(1.0), Some("Erasing Sectors Complete!"), id)
+                            .update_progress(
+                                Some(1.0),
+                                Some(phase_message(
+                                    MessageId::ProgressComplete,
+                                    MessageId::ProgressErasingSectors,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::StartedProgramming => {
                         debug_adapter
-                            .update_progress(Some(0.0), Some("Programming Pages ..."), id)
+                            .update_progress(
+                                Some(0.0),
+                                Some(phase_message(
+                                    MessageId::ProgressStarted,
+                                    MessageId::ProgressProgrammingPages,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::PageProgrammed { size, .. } => {
@@ -705,9 +979,9 @@ impl Debugger {
                         debug_adapter
                             .update_progress(
                                 Some(progress),
-                                Some(format!(
-                                    "Programming Pages ({:02.0}%)",
-                                    progress.mul(100_f64)
+                                Some(percent_message(
+                                    MessageId::ProgressProgrammingPages,
+                                    progress,
                                 )),
                                 id,
                             )
@@ -715,12 +989,42 @@ impl Debugger {
                     }
                     probe_rs::flashing::ProgressEvent::FailedProgramming => {
                         debug_adapter
-                            .update_progress(Some(1.0), Some("Flashing Pages Failed!"), id)
+                            .update_progress(
+                                Some(1.0),
+                                Some(phase_message(
+                                    MessageId::ProgressFailed,
+                                    MessageId::ProgressProgrammingPages,
+                                )),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::FinishedProgramming => {
                         debug_adapter
-                            .update_progress(Some(1.0), Some("Flashing Pages Complete!"), id)
+                            .update_progress(
+                                Some(1.0),
+                                Some(phase_message(
+                                    MessageId::ProgressComplete,
+                                    MessageId::ProgressProgrammingPages,
+                                )),
+                                id,
+                            )
+                            .ok();
+                    }
+                    probe_rs::flashing::ProgressEvent::ProgrammerProgress {
+                        name,
+                        programmed,
+                        total,
+                    } => {
+                        let progress = programmed as f64 / total as f64;
+                        let phase =
+                            messages::format(MessageId::ProgressProgramming, &[("name", &name)]);
+                        debug_adapter
+                            .update_progress(
+                                Some(progress),
+                                Some(percent_message(phase, progress)),
+                                id,
+                            )
                             .ok();
                     }
                     probe_rs::flashing::ProgressEvent::DiagnosticMessage { .. } => (),
@@ -731,7 +1035,7 @@ impl Debugger {
         download_options.progress = flash_progress;
 
         let flash_result = download_file_with_options(
-            &mut session_data.session,
+            &mut session_data.session.lock(),
             path_to_elf,
             self.config
                 .flashing_config
@@ -819,6 +1123,8 @@ impl Debugger {
             supports_instruction_breakpoints: Some(true),
             supports_stepping_granularity: Some(true),
             supports_completions_request: Some(true),
+            supports_cancel_request: Some(true),
+            supports_step_back: Some(true),
             // supports_value_formatting_options: Some(true),
             // supports_function_breakpoints: Some(true),
             // TODO: Use DEMCR register to implement exception breakpoints
@@ -867,32 +1173,86 @@ fn expect_request<P: ProtocolAdapter>(
     }
 }
 
-pub(crate) fn is_file_newer(
-    saved_binary_timestamp: &mut Option<Duration>,
+/// The timestamp and the hash of a flashed binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FlashedBinary {
+    timestamp: Option<Duration>,
+    hash: Option<u64>,
+}
+
+impl FlashedBinary {
+    pub(crate) fn of(path_to_elf: &Path) -> Self {
+        Self {
+            timestamp: get_file_timestamp(path_to_elf),
+            hash: get_file_hash(path_to_elf),
+        }
+    }
+}
+
+/// Check if the binary differs from the one that was flashed last, and remember it if it does.
+///
+/// The binary is only hashed when its timestamp changed, so a rebuild without changes, or a binary that was
+/// replaced by an older build, is still detected correctly.
+pub(crate) fn is_binary_changed(
+    flashed_binary: &mut Option<FlashedBinary>,
     path_to_elf: &Path,
 ) -> bool {
-    if let Some(check_current_binary_timestamp) = *saved_binary_timestamp {
-        // We have a timestamp for the binary that is currently on the device, so we need to compare it with the new binary.
-        if let Some(new_binary_timestamp) = get_file_timestamp(path_to_elf) {
-            // If it is newer, we can flash it. Otherwise just skip flashing.
-            if new_binary_timestamp > check_current_binary_timestamp {
-                *saved_binary_timestamp = Some(new_binary_timestamp);
-                true
-            } else {
-                false
-            }
-        } else {
-            // For some reason we couldn't get a timestamp for the new binary. Warn and assume it is new.
-            tracing::warn!("Could not get timestamp for new binary. Assuming it is new.");
-            true
-        }
-    } else {
-        // We don't have a timestamp for the binary that is currently on the device, so we can flash the binary.
-        *saved_binary_timestamp = fs::metadata(path_to_elf)
-            .and_then(|metadata| metadata.modified())
-            .map(|modified| modified.duration_since(UNIX_EPOCH).ok())
-            .ok()
-            .flatten();
-        true
+    let Some(flashed) = flashed_binary else {
+        // We don't know the binary that is currently on the device, so we have to flash the binary.
+        *flashed_binary = Some(FlashedBinary::of(path_to_elf));
+        return true;
+    };
+
+    let timestamp = get_file_timestamp(path_to_elf);
+    if timestamp.is_some() && timestamp == flashed.timestamp {
+        return false;
+    }
+
+    let hash = get_file_hash(path_to_elf);
+    if hash.is_none() {
+        // For some reason we couldn't read the new binary. Warn and assume it is new.
+        tracing::warn!("Could not read the new binary. Assuming it is new.");
+    }
+    let changed = hash.is_none() || hash != flashed.hash;
+    *flashed = FlashedBinary { timestamp, hash };
+    changed
+}
+
+/// The message of a phase of flashing, e.g. [`MessageId::ProgressFailed`] for [`MessageId::ProgressErasingSectors`].
+fn phase_message(message: MessageId, phase: MessageId) -> String {
+    messages::format(message, &[("phase", &phase)])
+}
+
+/// The message of the progress of a phase of flashing, as a percentage.
+fn percent_message(phase: impl std::fmt::Display, progress: f64) -> String {
+    let percent = format!("{:02.0}", progress.mul(100_f64));
+    messages::format(
+        MessageId::ProgressPercent,
+        &[("phase", &phase), ("percent", &percent)],
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unchanged_binary_is_not_flashed_again() {
+        let path = std::env::temp_dir().join(format!("probe-rs-binary-{}", std::process::id()));
+        std::fs::write(&path, b"first build").unwrap();
+        let mut flashed_binary = None;
+        assert!(is_binary_changed(&mut flashed_binary, &path));
+        assert!(!is_binary_changed(&mut flashed_binary, &path));
+
+        // A rebuild with the same contents only changes the timestamp.
+        flashed_binary.as_mut().unwrap().timestamp = None;
+        assert!(!is_binary_changed(&mut flashed_binary, &path));
+
+        std::fs::write(&path, b"second build").unwrap();
+        flashed_binary.as_mut().unwrap().timestamp = None;
+        let changed = is_binary_changed(&mut flashed_binary, &path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(changed);
     }
 }
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::Result;
+use probe_rs::{Core, CoreRegister, MemoryInterface, RegisterValue};
+use serde::{Deserialize, Serialize};
+
+use super::configuration::RecordConfig;
+
+/// The size of the pages in which the recorded memory is compared and stored.
+const PAGE_SIZE: u64 = 1024;
+
+/// The registers and the memory of a core at one stop.
+#[derive(Debug, Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) program_counter: u64,
+    registers: Vec<(&'static CoreRegister, RegisterValue)>,
+    /// The contents of the recorded memory, by the address of the page. A page which did not change since the
+    /// previous snapshot shares its contents with it.
+    pages: BTreeMap<u64, Arc<[u8]>>,
+}
+
+impl Snapshot {
+    /// Capture the registers of the halted core, and the memory in `ranges`.
+    pub(crate) fn capture(core: &mut Core, ranges: &[Range<u64>]) -> Result<Self> {
+        let program_counter = core.read_core_reg(core.program_counter())?;
+        let registers = core
+            .registers()
+            .all_registers()
+            .filter_map(|register| {
+                // Registers which can't be read, e.g. of a disabled FPU, are not restored either.
+                core.read_core_reg::<RegisterValue>(register.id())
+                    .ok()
+                    .map(|value| (register, value))
+            })
+            .collect();
+
+        let mut pages = BTreeMap::new();
+        for range in ranges {
+            for address in (range.start..range.end).step_by(PAGE_SIZE as usize) {
+                let mut page = vec![0; (range.end - address).min(PAGE_SIZE) as usize];
+                core.read(address, &mut page)?;
+                pages.insert(address, Arc::from(page));
+            }
+        }
+
+        Ok(Self {
+            program_counter,
+            registers,
+            pages,
+        })
+    }
+
+    /// The registers and the bytes of memory which differ between this snapshot and a `newer` one. Memory which
+    /// only one of the snapshots captured is not compared.
+    pub(crate) fn diff(&self, newer: &Snapshot) -> SnapshotDiff {
+        let registers = self
+            .registers
+            .iter()
+            .filter_map(|(register, from)| {
+                let (_, to) = newer
+                    .registers
+                    .iter()
+                    .find(|(newer_register, _)| newer_register.id() == register.id())?;
+                (from != to).then(|| RegisterChange {
+                    name: register.name().to_string(),
+                    from: from.to_string(),
+                    to: to.to_string(),
+                })
+            })
+            .collect();
+
+        let mut memory = Vec::new();
+        for (address, from) in &self.pages {
+            let Some(to) = newer.pages.get(address) else {
+                continue;
+            };
+            if Arc::ptr_eq(from, to) {
+                continue;
+            }
+            let mut offset = 0;
+            while offset < from.len().min(to.len()) {
+                if from[offset] == to[offset] {
+                    offset += 1;
+                    continue;
+                }
+                let start = offset;
+                while offset < from.len().min(to.len()) && from[offset] != to[offset] {
+                    offset += 1;
+                }
+                memory.push(MemoryChange {
+                    address: address + start as u64,
+                    from: hex(&from[start..offset]),
+                    to: hex(&to[start..offset]),
+                });
+            }
+        }
+
+        SnapshotDiff { registers, memory }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The differences between two snapshots, see [`Snapshot::diff`].
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotDiff {
+    pub(crate) registers: Vec<RegisterChange>,
+    pub(crate) memory: Vec<MemoryChange>,
+}
+
+/// A register whose value differs between two snapshots.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterChange {
+    pub(crate) name: String,
+    pub(crate) from: String,
+    pub(crate) to: String,
+}
+
+/// A run of bytes which differ between two snapshots, with the bytes as hex strings.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryChange {
+    pub(crate) address: u64,
+    pub(crate) from: String,
+    pub(crate) to: String,
+}
+
+/// The snapshots of a core which were recorded at its last stops, oldest first, see
+/// [`CoreConfig::record_history`](super::configuration::CoreConfig::record_history).
+///
+/// Stepping back restores the registers and the recorded memory of an older snapshot on the target, and drops the
+/// newer snapshots, because the program executes again from there. Peripherals are not restored.
+#[derive(Debug)]
+pub(crate) struct ExecutionHistory {
+    max_snapshots: usize,
+    pub(crate) record_steps: bool,
+    ranges: Vec<Range<u64>>,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl ExecutionHistory {
+    /// Record the `ranges` of the configuration, or `ram` if it has none.
+    pub(crate) fn new(config: &RecordConfig, ram: Vec<Range<u64>>) -> Self {
+        let ranges = if config.ranges.is_empty() {
+            ram
+        } else {
+            config
+                .ranges
+                .iter()
+                .map(|range| range.start..range.end)
+                .collect()
+        };
+        Self {
+            max_snapshots: config.max_snapshots.max(1),
+            record_steps: config.record_steps,
+            ranges,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Record a snapshot of the halted core.
+    pub(crate) fn record(&mut self, core: &mut Core) -> Result<()> {
+        let snapshot = Snapshot::capture(core, &self.ranges)?;
+        self.push(snapshot);
+        Ok(())
+    }
+
+    fn push(&mut self, mut snapshot: Snapshot) {
+        if let Some(previous) = self.snapshots.back() {
+            for (address, page) in snapshot.pages.iter_mut() {
+                if let Some(previous_page) = previous.pages.get(address) {
+                    if previous_page == page {
+                        *page = previous_page.clone();
+                    }
+                }
+            }
+        }
+        if self.snapshots.len() == self.max_snapshots {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Drop the newest snapshot, which is the current state of the core, and then the older snapshots until
+    /// `stop_at` matches one or only the oldest is left. Returns the pages which differ between the current state and
+    /// the snapshot which is now the newest, or `None` if there is no older snapshot.
+    fn rewind(&mut self, stop_at: impl Fn(&Snapshot) -> bool) -> Option<Vec<(u64, Arc<[u8]>)>> {
+        if self.snapshots.len() < 2 {
+            return None;
+        }
+        let current = self.snapshots.pop_back()?;
+        while self.snapshots.len() > 1 && !stop_at(self.snapshots.back()?) {
+            self.snapshots.pop_back();
+        }
+
+        let target = self.snapshots.back()?;
+        Some(
+            target
+                .pages
+                .iter()
+                .filter(|(address, page)| {
+                    current
+                        .pages
+                        .get(*address)
+                        .map_or(true, |current_page| !Arc::ptr_eq(current_page, *page))
+                })
+                .map(|(address, page)| (*address, page.clone()))
+                .collect(),
+        )
+    }
+
+    /// Restore the snapshot before the current one, or the newest older snapshot for which `stop_at` is true, on the
+    /// halted core. Returns the restored snapshot, or `None` if there is no older snapshot.
+    pub(crate) fn step_back(
+        &mut self,
+        core: &mut Core,
+        stop_at: impl Fn(&Snapshot) -> bool,
+    ) -> Result<Option<&Snapshot>> {
+        let Some(pages) = self.rewind(stop_at) else {
+            return Ok(None);
+        };
+        for (address, page) in pages {
+            core.write_8(address, &page)?;
+        }
+        let Some(snapshot) = self.snapshots.back() else {
+            return Ok(None);
+        };
+        for (register, value) in &snapshot.registers {
+            core.write_core_reg(register.id(), *value)?;
+        }
+        Ok(Some(snapshot))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use super::{ExecutionHistory, MemoryChange, Snapshot};
+
+    fn snapshot(program_counter: u64, pages: &[(u64, u8)]) -> Snapshot {
+        Snapshot {
+            program_counter,
+            registers: Vec::new(),
+            pages: pages
+                .iter()
+                .map(|(address, value)| (*address, Arc::from(vec![*value; 4])))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    fn history(max_snapshots: usize) -> ExecutionHistory {
+        ExecutionHistory {
+            max_snapshots,
+            record_steps: false,
+            ranges: Vec::new(),
+            snapshots: Default::default(),
+        }
+    }
+
+    #[test]
+    fn only_changed_pages_are_written_back() {
+        let mut history = history(10);
+        history.push(snapshot(0x100, &[(0, 1), (0x400, 2)]));
+        history.push(snapshot(0x200, &[(0, 1), (0x400, 3)]));
+
+        let pages = history.rewind(|_| true).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].0, 0x400);
+        assert_eq!(&*pages[0].1, &[2; 4]);
+        assert_eq!(history.snapshots.len(), 1);
+        // The oldest snapshot can't be rewound.
+        assert!(history.rewind(|_| true).is_none());
+    }
+
+    #[test]
+    fn rewind_stops_at_the_matching_snapshot() {
+        let mut history = history(3);
+        for program_counter in [0x100, 0x200, 0x300, 0x400] {
+            history.push(snapshot(program_counter, &[(0, program_counter as u8)]));
+        }
+        assert_eq!(history.snapshots.len(), 3);
+
+        history.rewind(|snapshot| snapshot.program_counter == 0x100);
+        // The snapshot at 0x100 was dropped to keep three, so the oldest one is restored.
+        assert_eq!(history.snapshots.back().unwrap().program_counter, 0x200);
+    }
+
+    #[test]
+    fn diff_reports_the_runs_of_changed_bytes() {
+        let mut older = snapshot(0x100, &[(0x2000_0000, 0)]);
+        older.pages.insert(0x2000_0400, Arc::from(vec![0; 4]));
+        let mut newer = older.clone();
+        newer.pages.insert(0x2000_0000, Arc::from(vec![0, 1, 2, 0]));
+
+        let diff = older.diff(&newer);
+        assert!(diff.registers.is_empty());
+        assert_eq!(
+            diff.memory,
+            [MemoryChange {
+                address: 0x2000_0001,
+                from: "0000".to_string(),
+                to: "0102".to_string(),
+            }]
+        );
+    }
+}
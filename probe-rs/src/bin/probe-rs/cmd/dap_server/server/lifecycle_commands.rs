@@ -0,0 +1,133 @@
+//! External commands which are run at points of the session lifecycle, see [`LifecycleCommands`].
+//!
+//! [`LifecycleCommands`]: super::configuration::LifecycleCommands
+
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+use probe_rs::DebugProbeSelector;
+
+use super::configuration::ExternalCommand;
+use crate::cmd::dap_server::debug_adapter::{
+    dap::adapter::DebugAdapter, protocol::ProtocolAdapter,
+};
+
+/// The environment variables which describe the session to the commands.
+pub(crate) fn command_environment(
+    chip: &str,
+    probe_selector: Option<&DebugProbeSelector>,
+    program_binary: Option<&Path>,
+) -> Vec<(&'static str, String)> {
+    let mut environment = vec![("PROBE_RS_CHIP", chip.to_string())];
+    if let Some(probe_selector) = probe_selector {
+        environment.push(("PROBE_RS_PROBE", probe_selector.to_string()));
+    }
+    if let Some(program_binary) = program_binary {
+        environment.push((
+            "PROBE_RS_PROGRAM_BINARY",
+            program_binary.display().to_string(),
+        ));
+    }
+    environment
+}
+
+/// Run the commands one after the other, and show their output in the debug console.
+///
+/// Returns an error for the first command which can not be started or does not exit successfully.
+pub(crate) fn run_commands<P: ProtocolAdapter>(
+    commands: &[ExternalCommand],
+    environment: &[(&'static str, String)],
+    cwd: Option<&Path>,
+    debug_adapter: &mut DebugAdapter<P>,
+) -> Result<()> {
+    for command in commands {
+        let command_line = command_line(command);
+        debug_adapter.log_to_console(format!("Running `{command_line}`"));
+
+        let output = run_command(command, environment, cwd)?;
+        for line in String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .chain(String::from_utf8_lossy(&output.stderr).lines())
+        {
+            debug_adapter.log_to_console(line);
+        }
+
+        if !output.status.success() {
+            return Err(anyhow!("`{command_line}` failed with {}", output.status));
+        }
+    }
+    Ok(())
+}
+
+/// Start the command, without waiting for it to exit. Its output is discarded.
+pub(crate) fn spawn_command(
+    command: &ExternalCommand,
+    environment: &[(&'static str, String)],
+    cwd: Option<&Path>,
+) -> Result<()> {
+    process(command, environment, cwd)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to start `{}`", command_line(command)))?;
+    Ok(())
+}
+
+fn run_command(
+    command: &ExternalCommand,
+    environment: &[(&'static str, String)],
+    cwd: Option<&Path>,
+) -> Result<Output> {
+    process(command, environment, cwd)
+        .output()
+        .with_context(|| format!("Failed to run `{}`", command_line(command)))
+}
+
+fn process(
+    command: &ExternalCommand,
+    environment: &[(&'static str, String)],
+    cwd: Option<&Path>,
+) -> Command {
+    let mut process = Command::new(&command.command);
+    process
+        .args(&command.args)
+        .envs(environment.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null());
+    if let Some(cwd) = cwd {
+        process.current_dir(cwd);
+    }
+    process
+}
+
+fn command_line(command: &ExternalCommand) -> String {
+    std::iter::once(command.command.as_str())
+        .chain(command.args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(all(test, unix))]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn commands_see_the_session_environment() {
+        let command = ExternalCommand {
+            command: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "echo $PROBE_RS_CHIP $PROBE_RS_PROGRAM_BINARY; exit 3".to_string(),
+            ],
+        };
+        let environment = command_environment("nRF52840_xxAA", None, Some(Path::new("firmware")));
+
+        let output = run_command(&command, &environment, None).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "nRF52840_xxAA firmware\n"
+        );
+        assert_eq!(output.status.code(), Some(3));
+    }
+}
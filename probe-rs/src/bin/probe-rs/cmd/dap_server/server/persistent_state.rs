@@ -0,0 +1,275 @@
+//! Breakpoints and watch expressions which are kept across debug sessions.
+//!
+//! The state is stored per program binary, in the data directory of probe-rs. When a new session starts, the
+//! breakpoints of every source which the debug client did not set before `configurationDone` are restored. A
+//! breakpoint remembers the function it was set in, and the line offset into it, so it moves with the function when
+//! lines are added or removed above it before the next build.
+//!
+//! Conditions, hit conditions and log messages are stored and reported with the `probe-rs/savedState` request, but
+//! they are not evaluated by the debugger.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use probe_rs::debug::debug_info::DebugInfo;
+use serde::{Deserialize, Serialize};
+
+/// A source breakpoint as it was requested by the debug client.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedBreakpoint {
+    /// The requested line, starting at 1.
+    pub line: u64,
+    /// The requested column, starting at 1.
+    pub column: Option<u64>,
+    pub condition: Option<String>,
+    pub hit_condition: Option<String>,
+    pub log_message: Option<String>,
+    /// The function the breakpoint was verified in.
+    pub function: Option<String>,
+    /// The first line of `function` when the breakpoint was set.
+    pub function_line: Option<u64>,
+}
+
+/// The breakpoints and watch expressions of a program.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SavedState {
+    /// The breakpoints by the path of their source file.
+    pub breakpoints: BTreeMap<PathBuf, Vec<SavedBreakpoint>>,
+    pub watch_expressions: Vec<String>,
+}
+
+/// The [`SavedState`] of the program of a core, and the file it is stored in.
+#[derive(Debug, Default)]
+pub(crate) struct PersistentState {
+    /// The state file, or `None` if the state is not persisted.
+    path: Option<PathBuf>,
+    pub(crate) state: SavedState,
+    /// The sources whose breakpoints were set by the client in this session, and are not restored.
+    sources_set_by_client: HashSet<PathBuf>,
+    /// The watch expressions evaluated in this session, which replace the stored ones.
+    session_watch_expressions: Vec<String>,
+}
+
+impl PersistentState {
+    /// Load the state of a program binary. Without a data directory the state is only kept for this session.
+    pub(crate) fn load(program_binary: &Path) -> Result<Self> {
+        let path = state_dir().map(|dir| state_file(&dir, program_binary));
+        let state = match &path {
+            Some(path) if path.exists() => {
+                let contents = std::fs::read(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                serde_json::from_slice(&contents)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?
+            }
+            _ => SavedState::default(),
+        };
+
+        Ok(Self {
+            path,
+            state,
+            ..Default::default()
+        })
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| {
+                std::fs::write(
+                    path,
+                    serde_json::to_vec_pretty(&self.state).unwrap_or_default(),
+                )
+            });
+        if let Err(error) = result {
+            tracing::warn!(
+                "Failed to save the debug state to {}: {error}",
+                path.display()
+            );
+        }
+    }
+
+    /// Replace the breakpoints of a source with the ones the client requested.
+    pub(crate) fn set_breakpoints(
+        &mut self,
+        source_path: &Path,
+        breakpoints: Vec<SavedBreakpoint>,
+    ) {
+        self.sources_set_by_client.insert(source_path.to_path_buf());
+        if breakpoints.is_empty() {
+            self.state.breakpoints.remove(source_path);
+        } else {
+            self.state
+                .breakpoints
+                .insert(source_path.to_path_buf(), breakpoints);
+        }
+        self.save();
+    }
+
+    /// Remember a watch expression. The first one of a session replaces the watch expressions of earlier sessions.
+    pub(crate) fn add_watch_expression(&mut self, expression: &str) {
+        if self
+            .session_watch_expressions
+            .iter()
+            .any(|known| known == expression)
+        {
+            return;
+        }
+        self.session_watch_expressions.push(expression.to_string());
+        self.state.watch_expressions = self.session_watch_expressions.clone();
+        self.save();
+    }
+
+    /// The stored breakpoints of the sources the client did not set in this session.
+    ///
+    /// They are only returned once, because they are set on the core afterwards.
+    pub(crate) fn take_breakpoints_to_restore(&mut self) -> Vec<(PathBuf, Vec<SavedBreakpoint>)> {
+        let to_restore = self
+            .state
+            .breakpoints
+            .iter()
+            .filter(|(source_path, _)| !self.sources_set_by_client.contains(*source_path))
+            .map(|(source_path, breakpoints)| (source_path.clone(), breakpoints.clone()))
+            .collect::<Vec<_>>();
+        self.sources_set_by_client.extend(
+            to_restore
+                .iter()
+                .map(|(source_path, _)| source_path.clone()),
+        );
+        to_restore
+    }
+}
+
+/// The directory in which the state files are stored.
+fn state_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("rs", "probe-rs", "probe-rs")
+        .map(|dirs| dirs.data_dir().join("debug-state"))
+}
+
+fn state_file(dir: &Path, program_binary: &Path) -> PathBuf {
+    let program_binary = program_binary
+        .canonicalize()
+        .unwrap_or_else(|_| program_binary.to_path_buf());
+    let name = format!("{}.json", program_binary.display()).replace(['/', '\\'], "_");
+    dir.join(sanitize_filename::sanitize(name))
+}
+
+/// Find the function which contains the address, and its first line, to anchor a breakpoint to.
+pub(crate) fn function_anchor(debug_info: &DebugInfo, address: u64) -> Option<(String, u64)> {
+    let function = debug_info.resolve_address(address).ok()?.function?;
+    let range = debug_info
+        .function_ranges(&function)
+        .ok()?
+        .into_iter()
+        .find(|range| range.contains(&address))?;
+    let line = debug_info.get_source_location(range.start)?.line?;
+    Some((function, line))
+}
+
+/// The line of a stored breakpoint in the current program, which moves with the function it was set in.
+pub(crate) fn rebind_line(
+    debug_info: &DebugInfo,
+    source_path: &Path,
+    breakpoint: &SavedBreakpoint,
+) -> u64 {
+    let function_line = breakpoint.function.as_deref().and_then(|function| {
+        let lines = debug_info
+            .function_ranges(function)
+            .ok()?
+            .into_iter()
+            .filter_map(|range| debug_info.get_source_location(range.start))
+            .filter(|location| {
+                location
+                    .combined_path()
+                    .map(|path| path == source_path)
+                    .unwrap_or(false)
+            })
+            .filter_map(|location| location.line)
+            .collect::<Vec<_>>();
+        // A function name which is found more than once in the same file can not be matched.
+        match lines.as_slice() {
+            [line] => Some(*line),
+            _ => None,
+        }
+    });
+    moved_line(breakpoint, function_line)
+}
+
+fn moved_line(breakpoint: &SavedBreakpoint, new_function_line: Option<u64>) -> u64 {
+    match (breakpoint.function_line, new_function_line) {
+        (Some(old), Some(new)) if breakpoint.line >= old => new + (breakpoint.line - old),
+        _ => breakpoint.line,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    fn breakpoint(line: u64, function_line: Option<u64>) -> SavedBreakpoint {
+        SavedBreakpoint {
+            line,
+            column: None,
+            condition: None,
+            hit_condition: None,
+            log_message: Some("x = {x}".to_string()),
+            function: function_line.map(|_| "main".to_string()),
+            function_line,
+        }
+    }
+
+    #[test]
+    fn breakpoints_move_with_their_function() {
+        assert_eq!(moved_line(&breakpoint(25, Some(20)), Some(23)), 28);
+        assert_eq!(moved_line(&breakpoint(25, Some(20)), Some(10)), 15);
+        // Without the function in the new program, the line is kept.
+        assert_eq!(moved_line(&breakpoint(25, Some(20)), None), 25);
+        assert_eq!(moved_line(&breakpoint(25, None), Some(23)), 25);
+    }
+
+    #[test]
+    fn state_is_restored_from_file() {
+        let dir = std::env::temp_dir().join(format!("probe-rs-debug-state-{}", std::process::id()));
+        let path = state_file(&dir, Path::new("/project/target/firmware"));
+        let mut persistent_state = PersistentState {
+            path: Some(path.clone()),
+            ..Default::default()
+        };
+        persistent_state.set_breakpoints(
+            Path::new("/project/src/main.rs"),
+            vec![breakpoint(25, Some(20))],
+        );
+        persistent_state
+            .set_breakpoints(Path::new("/project/src/lib.rs"), vec![breakpoint(3, None)]);
+        persistent_state.set_breakpoints(Path::new("/project/src/lib.rs"), vec![]);
+        persistent_state.add_watch_expression("counter");
+
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut restored = PersistentState {
+            state: serde_json::from_slice(&contents).unwrap(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            restored.state.watch_expressions,
+            vec!["counter".to_string()]
+        );
+        assert_eq!(
+            restored.take_breakpoints_to_restore(),
+            vec![(
+                PathBuf::from("/project/src/main.rs"),
+                vec![breakpoint(25, Some(20))]
+            )]
+        );
+        assert!(restored.take_breakpoints_to_restore().is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use super::configuration::PollingConfig;
+
+/// The interval between polls of running cores, which backs off exponentially while nothing happens.
+///
+/// Every poll accesses the probe over USB, which keeps the host from suspending the USB port. So while the RTT
+/// channels are idle, the interval doubles up to [`PollingConfig::max_interval_ms`], and it drops back to
+/// [`PollingConfig::min_interval_ms`] when data arrives or the client sends a request.
+#[derive(Debug, Clone)]
+pub(crate) struct PollInterval {
+    interval: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl PollInterval {
+    pub(crate) fn new(config: &PollingConfig) -> Self {
+        let min = Duration::from_millis(config.min_interval_ms);
+        Self {
+            interval: min,
+            min,
+            max: Duration::from_millis(config.max_interval_ms).max(min),
+        }
+    }
+
+    /// The time to wait after a poll which found nothing to do.
+    pub(crate) fn idle(&mut self) -> Duration {
+        let interval = self.interval;
+        self.interval = (interval * 2).min(self.max);
+        interval
+    }
+
+    /// Poll quickly again, after activity of the target or the client.
+    pub(crate) fn reset(&mut self) {
+        self.interval = self.min;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn the_interval_backs_off_until_activity() {
+        let mut interval = PollInterval::new(&PollingConfig {
+            min_interval_ms: 50,
+            max_interval_ms: 300,
+        });
+
+        let idle = (0..5)
+            .map(|_| interval.idle().as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(idle, [50, 100, 200, 300, 300]);
+
+        interval.reset();
+        assert_eq!(interval.idle(), Duration::from_millis(50));
+    }
+}
@@ -1,6 +1,14 @@
 use super::{
-    configuration::{self, CoreConfig, SessionConfig},
+    configuration::{self, CoreConfig, SessionConfig, TriggerEvent},
     core_data::{CoreData, CoreHandle},
+    debug_heap::HeapMonitor,
+    execution_history::ExecutionHistory,
+    persistent_state::PersistentState,
+    poll_interval::PollInterval,
+    stimulus::Stimuli,
+    symbol_files::SymbolFiles,
+    trigger::fire_trigger,
+    value_history::ValueHistory,
 };
 use crate::cmd::dap_server::{
     debug_adapter::{
@@ -10,11 +18,21 @@ use crate::cmd::dap_server::{
 };
 use anyhow::{anyhow, Result};
 use probe_rs::{
-    config::TargetSelector,
+    config::{MemoryAlias, MemoryRegion, TargetSelector},
     debug::{debug_info::DebugInfo, SourceLocation},
-    CoreStatus, DebugProbeError, Permissions, Probe, ProbeCreationError, Session,
+    gdb_server::{self, GdbInstanceConfiguration},
+    messages::{self, MessageId},
+    CoreStatus, DebugProbeError, GdbRemote, HaltReason, Permissions, Probe, ProbeCreationError,
+    Session, SharedSession,
 };
 use std::env::set_current_dir;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::thread::JoinHandle;
 use time::UtcOffset;
 
 /// The supported breakpoint types
@@ -28,6 +46,14 @@ pub(crate) enum BreakpointType {
         source: Source,
         location: SourceLocationScope,
     },
+    /// A breakpoint on the panic handler of a Rust program, set when the `break_on_panic` option is enabled.
+    PanicHandler,
+    /// A breakpoint which captures a snapshot of the registers and the memory in `ranges` at each hit, set with the
+    /// custom `probe-rs/setSnapshotBreakpoints` request.
+    SnapshotBreakpoint {
+        name: String,
+        ranges: Vec<Range<u64>>,
+    },
 }
 
 /// Breakpoint requests will either be refer to a specific SourceLcoation, or unspecified, in which case it will refer to
@@ -49,7 +75,8 @@ pub(crate) struct ActiveBreakpoint {
 /// To get access to the [CoreHandle] for a specific [probe_rs::Core], the
 /// TODO: Adjust [SessionConfig] to allow multiple cores (and if appropriate, their binaries) to be specified.
 pub(crate) struct SessionData {
-    pub(crate) session: Session,
+    /// The session is shared with the (optional) GDB server, which runs in its own thread.
+    pub(crate) session: Arc<SharedSession>,
     /// [SessionData] will manage one [CoreData] per target core, that is also present in [SessionConfig::core_configs]
     pub(crate) core_data: Vec<CoreData>,
 
@@ -57,6 +84,15 @@ pub(crate) struct SessionData {
     ///
     /// Getting the offset can fail, so it's better to store it.
     timestamp_offset: UtcOffset,
+
+    /// The GDB server which runs alongside the debug adapter, if one was requested.
+    gdb_server: Option<GdbServer>,
+
+    /// The interval between polls of the running cores.
+    pub(crate) poll_interval: PollInterval,
+
+    /// The register write sequences which emulate external inputs.
+    pub(crate) stimuli: Stimuli,
 }
 
 impl SessionData {
@@ -65,34 +101,55 @@ impl SessionData {
         timestamp_offset: UtcOffset,
     ) -> Result<Self, DebuggerError> {
         // `SessionConfig` Probe/Session level configurations initialization.
-        let mut target_probe = match config.probe_selector.clone() {
-            Some(selector) => Probe::open(selector.clone()).map_err(|e| match e {
-                DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound) => {
-                    DebuggerError::Other(anyhow!(
+        let mut target_probe = if let Some(address) = &config.gdb_remote {
+            if config.gdb_remote_emulator {
+                GdbRemote::connect_emulator(address)
+            } else {
+                GdbRemote::connect(address)
+            }
+            .map(|probe| Probe::from_specific_probe(probe))
+            .map_err(DebuggerError::DebugProbe)
+        } else {
+            match config.probe_selector.clone() {
+                Some(selector) => Probe::open(selector.clone()).map_err(|e| match e {
+                    error @ DebugProbeError::ProbeCouldNotBeCreated(
+                        ProbeCreationError::NotFound,
+                    ) => DebuggerError::Other(anyhow!(error).context(format!(
                         "Could not find the probe_selector specified as {:04x}:{:04x}:{:?}",
-                        selector.vendor_id,
-                        selector.product_id,
-                        selector.serial_number
-                    ))
-                }
-                other_error => DebuggerError::DebugProbe(other_error),
-            }),
-            None => {
-                // Only automatically select a probe if there is only a single probe detected.
-                let list = Probe::list_all();
-                if list.len() > 1 {
-                    return Err(DebuggerError::Other(anyhow!(
-                        "Found multiple ({}) probes",
-                        list.len()
-                    )));
-                }
+                        selector.vendor_id, selector.product_id, selector.serial_number
+                    ))),
+                    other_error => DebuggerError::DebugProbe(other_error),
+                }),
+                None => {
+                    // Only automatically select a probe if there is only a single probe detected, or of the board.
+                    let mut list = Probe::list_all();
+                    let board_probes = list
+                        .iter()
+                        .filter(|info| {
+                            config.board_probes.iter().any(|probe| {
+                                probe.vendor_id == info.vendor_id
+                                    && probe.product_id == info.product_id
+                            })
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if !board_probes.is_empty() {
+                        list = board_probes;
+                    }
+                    if list.len() > 1 {
+                        return Err(DebuggerError::Other(anyhow!(messages::format(
+                            MessageId::ProbeMultipleFound,
+                            &[("count", &list.len())],
+                        ))));
+                    }
 
-                if let Some(info) = list.first() {
-                    Probe::open(info).map_err(DebuggerError::DebugProbe)
-                } else {
-                    return Err(DebuggerError::Other(anyhow!(
-                        "No probes found. Please check your USB connections."
-                    )));
+                    if let Some(info) = list.first() {
+                        Probe::open(info).map_err(DebuggerError::DebugProbe)
+                    } else {
+                        return Err(DebuggerError::UnableToOpenProbe(Some(
+                            "No probes found. Please check your USB connections.",
+                        )));
+                    }
                 }
             }
         }?;
@@ -124,14 +181,23 @@ impl SessionData {
             permissions = permissions.allow_erase_all();
         }
 
+        // A target which does not respond is most likely not powered, if the probe measures a low voltage.
+        let target_voltage = target_probe.get_target_voltage().ok().flatten();
+
         // Attach to the probe.
-        let target_session = if config.connect_under_reset {
-            target_probe.attach_under_reset(target_selector, permissions)?
+        let mut target_session = if config.connect_under_reset {
+            target_probe.attach_under_reset(target_selector, permissions)
         } else {
-            target_probe
-                .attach(target_selector, permissions)
-                .map_err(|err| anyhow!("Error attaching to the probe: {:?}.", err))?
-        };
+            target_probe.attach(target_selector, permissions)
+        }
+        .map_err(|source| DebuggerError::Attach {
+            source,
+            target_voltage,
+        })?;
+        // The policy also applies when the debugger exits without a `disconnect` request.
+        if let Some(on_disconnect) = config.on_disconnect {
+            target_session.set_disconnect_action(on_disconnect.into());
+        }
 
         // Change the current working directory if `config.cwd` is `Some(T)`.
         if let Some(new_cwd) = config.cwd.clone() {
@@ -168,6 +234,23 @@ impl SessionData {
         let mut core_data_vec = vec![];
 
         for core_configuration in &valid_core_configs {
+            let heap_monitor = match (&config.heap_config, &core_configuration.program_binary) {
+                (Some(heap_config), Some(program_binary)) => Some(
+                    HeapMonitor::new(heap_config, program_binary).map_err(DebuggerError::Other)?,
+                ),
+                _ => None,
+            };
+            let persistent_state = match &core_configuration.program_binary {
+                Some(program_binary) if config.persist_debug_state => {
+                    Some(PersistentState::load(program_binary).map_err(DebuggerError::Other)?)
+                }
+                _ => None,
+            };
+            target_session.protect_memory_writes(
+                core_configuration.core_index,
+                !core_configuration.allow_protected_memory_writes,
+            )?;
+
             core_data_vec.push(CoreData {
                 core_index: core_configuration.core_index,
                 last_known_status: CoreStatus::Unknown,
@@ -176,21 +259,59 @@ impl SessionData {
                     core_configuration.core_index,
                     target_session.target().name
                 ),
-                debug_info: debug_info_from_binary(core_configuration)?,
+                debug_info: debug_info_from_binary(
+                    core_configuration,
+                    target_session.target().aliases.clone(),
+                )?,
+                symbol_files: SymbolFiles::load(core_configuration)
+                    .map_err(DebuggerError::Other)?,
                 core_peripherals: None,
                 stack_frames: Vec::<probe_rs::debug::stack_frame::StackFrame>::new(),
                 breakpoints: Vec::<ActiveBreakpoint>::new(),
                 rtt_connection: None,
+                heap_monitor,
+                decode_memory_protection: core_configuration.decode_memory_protection,
+                persistent_state,
+                value_history: ValueHistory::default(),
+                snapshots: Vec::new(),
+                execution_history: core_configuration.record_history.as_ref().map(|config| {
+                    let ram = target_session
+                        .target()
+                        .core_memory_map(core_configuration.core_index)
+                        .filter_map(|region| match region {
+                            MemoryRegion::Ram(region) => Some(region.range.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    ExecutionHistory::new(config, ram)
+                }),
             })
         }
 
+        let session = Arc::new(SharedSession::new(target_session));
+        let gdb_server = config
+            .gdb_connection_string
+            .as_ref()
+            .map(|connection_string| GdbServer::start(session.clone(), connection_string));
+
         Ok(SessionData {
-            session: target_session,
+            session,
             core_data: core_data_vec,
             timestamp_offset,
+            gdb_server,
+            poll_interval: PollInterval::new(&config.polling),
+            stimuli: Stimuli::new(&config.stimuli).map_err(DebuggerError::Other)?,
         })
     }
 
+    /// The addresses the GDB server is listening on, if it is running.
+    pub(crate) fn gdb_server_addresses(&self) -> Vec<SocketAddr> {
+        self.gdb_server
+            .as_ref()
+            .map(|gdb_server| gdb_server.addresses.clone())
+            .unwrap_or_default()
+    }
+
     /// Reload the a specific core's debug info from the binary file.
     pub(crate) fn load_debug_info_for_core(
         &mut self,
@@ -201,7 +322,10 @@ impl SessionData {
             .iter_mut()
             .find(|core_data| core_data.core_index == core_configuration.core_index)
         {
-            core_data.debug_info = debug_info_from_binary(core_configuration)?;
+            let memory_aliases = core_data.debug_info.memory_aliases().to_vec();
+            core_data.debug_info = debug_info_from_binary(core_configuration, memory_aliases)?;
+            core_data.symbol_files =
+                SymbolFiles::load(core_configuration).map_err(DebuggerError::Other)?;
             Ok(())
         } else {
             Err(DebuggerError::UnableToOpenProbe(Some(
@@ -211,9 +335,15 @@ impl SessionData {
     }
 
     /// Do a 'light weight'(just get references to existing data structures) attach to the core and return relevant debug data.
-    pub(crate) fn attach_core(&mut self, core_index: usize) -> Result<CoreHandle, DebuggerError> {
+    ///
+    /// The `session` has to be locked by the caller, using [`SessionData::session`], for as long as the [`CoreHandle`] is used.
+    pub(crate) fn attach_core<'p>(
+        &'p mut self,
+        session: &'p mut Session,
+        core_index: usize,
+    ) -> Result<CoreHandle<'p>, DebuggerError> {
         if let (Ok(target_core), Some(core_data)) = (
-            self.session.core(core_index),
+            session.core(core_index),
             self.core_data
                 .iter_mut()
                 .find(|core_data| core_data.core_index == core_index),
@@ -253,14 +383,18 @@ impl SessionData {
         // By default, we will have a small delay between polls, and will disable it if we know the last poll returned data, on the assumption that there might be at least one more batch of data.
         let mut suggest_delay_required = true;
         let mut status_of_cores: Vec<CoreStatus> = vec![];
-        let target_memory_map = &self.session.target().memory_map.clone();
+        // Keep the session locked while polling, and release it afterwards so that the GDB server can take its turn.
+        let shared_session = self.session.clone();
+        let mut session = shared_session.lock();
+        let target_memory_map = &session.target().memory_map.clone();
 
         let timestamp_offset = self.timestamp_offset;
 
         // Always set `all_cores_halted` to true, until one core is found to be running.
         debug_adapter.all_cores_halted = true;
+        let mut breakpoint_hit = false;
         for core_config in session_config.core_configs.iter() {
-            let Ok(mut target_core) = self.attach_core(core_config.core_index) else {
+            let Ok(mut target_core) = self.attach_core(&mut session, core_config.core_index) else {
                 tracing::debug!(
                     "Failed to attach to target core #{}. Cannot poll for RTT data.",
                     core_config.core_index
@@ -269,11 +403,17 @@ impl SessionData {
             };
 
             // We need to poll the core to determine its status.
+            let was_halted = target_core.core_data.last_known_status.is_halted();
             let current_core_status = target_core.poll_core(debug_adapter).map_err(|error| {
                 let error = DebuggerError::ProbeRs(error);
                 let _ = debug_adapter.show_error_message(&error);
                 error
             })?;
+            breakpoint_hit |= !was_halted
+                && matches!(
+                    current_core_status,
+                    CoreStatus::Halted(HaltReason::Breakpoint(_))
+                );
 
             // If appropriate, check for RTT data.
             if core_config.rtt_config.enabled {
@@ -307,6 +447,10 @@ impl SessionData {
                 }
             }
 
+            if let Some(heap_monitor) = &mut target_core.core_data.heap_monitor {
+                heap_monitor.poll(debug_adapter, &mut target_core.core);
+            }
+
             // If the core is running, we set the flag to indicate that at least one core is not halted.
             // By setting it here, we ensure that RTT will be checked at least once after the core has halted.
             if !current_core_status.is_halted() {
@@ -314,15 +458,90 @@ impl SessionData {
             }
             status_of_cores.push(current_core_status);
         }
+
+        if let Err(error) = self.stimuli.poll(
+            &mut session,
+            !debug_adapter.all_cores_halted,
+            breakpoint_hit,
+        ) {
+            let _ = debug_adapter.show_error_message(&DebuggerError::Other(error));
+        }
+
+        if breakpoint_hit {
+            if let Err(error) =
+                fire_trigger(session_config, TriggerEvent::BreakpointHit, &mut session)
+            {
+                let _ = debug_adapter.show_error_message(&DebuggerError::Other(
+                    error.context("Failed to fire the trigger"),
+                ));
+            }
+        }
         Ok((status_of_cores, suggest_delay_required))
     }
 }
 
+/// A GDB server, which shares the session with the debug adapter.
+///
+/// Both the debug adapter and the GDB server poll the core status, so a halt or resume by one of them
+/// is reported to the clients of the other. The hardware breakpoints are shared between the two as well.
+struct GdbServer {
+    addresses: Vec<SocketAddr>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl GdbServer {
+    fn start(session: Arc<SharedSession>, connection_string: &str) -> Self {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let instances =
+            GdbInstanceConfiguration::from_session(&session.lock(), Some(connection_string));
+        for instance in &instances {
+            tracing::info!(
+                "Starting GDB server for {:?} cores at {:?}",
+                instance.core_type,
+                instance.socket_addrs
+            );
+        }
+
+        let addresses = instances
+            .iter()
+            .flat_map(|instance| instance.socket_addrs.iter().copied())
+            .collect();
+
+        let thread_shutdown = shutdown.clone();
+        let thread = std::thread::spawn(move || {
+            if let Err(error) = gdb_server::run_until(&session, instances.iter(), &thread_shutdown)
+            {
+                tracing::error!("The GDB server stopped with an error: {:?}", error);
+            }
+        });
+
+        Self {
+            addresses,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for GdbServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Load the debug info of the program of a core, which looks up addresses at the `memory_aliases` of the target
+/// at the address of the program.
 pub(crate) fn debug_info_from_binary(
     core_configuration: &CoreConfig,
+    memory_aliases: Vec<MemoryAlias>,
 ) -> Result<DebugInfo, DebuggerError> {
-    let debug_info = if let Some(binary_path) = &core_configuration.program_binary {
-        DebugInfo::from_file(binary_path).map_err(|error| DebuggerError::Other(anyhow!(error)))?
+    let mut debug_info = if let Some(binary_path) = &core_configuration.program_binary {
+        DebugInfo::from_file_with_load_offset(binary_path, core_configuration.load_offset)
+            .map_err(|error| DebuggerError::Other(anyhow!(error)))?
     } else {
         return Err(anyhow!(
             "Please provide a valid `program_binary` for debug core: {:?}",
@@ -330,5 +549,6 @@ pub(crate) fn debug_info_from_binary(
         )
         .into());
     };
+    debug_info.set_memory_aliases(memory_aliases);
     Ok(debug_info)
 }
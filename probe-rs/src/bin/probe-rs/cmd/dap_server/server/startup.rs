@@ -3,7 +3,9 @@ use crate::cmd::dap_server::debug_adapter::{dap::adapter::*, protocol::DapAdapte
 use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::{
+    collections::hash_map::DefaultHasher,
     fs,
+    hash::{Hash, Hasher},
     net::{Ipv4Addr, TcpListener},
     path::Path,
     time::{Duration, UNIX_EPOCH},
@@ -113,3 +115,13 @@ pub(crate) fn get_file_timestamp(path_to_elf: &Path) -> Option<Duration> {
         .ok()
         .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
 }
+
+/// Try to get a hash of the contents of a file.
+///
+/// If an error occurs, None is returned.
+pub(crate) fn get_file_hash(path_to_elf: &Path) -> Option<u64> {
+    let contents = fs::read(path_to_elf).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
@@ -0,0 +1,216 @@
+//! Register write sequences which emulate external inputs during debugging, see [`StimulusConfig`].
+//!
+//! The stimuli are written while the cores are polled, so the interval of a stimulus also limits the time between
+//! two polls of the running target. Its accuracy is that of the polling, a few milliseconds.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use probe_rs::{MemoryInterface, Session};
+
+use super::configuration::{RegisterOperation, StimulusConfig, StimulusEvent};
+
+/// A stimulus, and when it is written next.
+#[derive(Debug)]
+struct ScheduledStimulus {
+    config: StimulusConfig,
+    /// The next write of an interval stimulus, while the target runs.
+    next: Option<Instant>,
+    /// How often the sequence was written.
+    written: u32,
+    /// Writing the stimulus failed, so it is not written again.
+    failed: bool,
+}
+
+impl ScheduledStimulus {
+    fn name(&self, index: usize) -> String {
+        match &self.config.name {
+            Some(name) => format!("'{name}'"),
+            None => format!("#{index}"),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.failed || matches!(self.config.count, Some(count) if self.written >= count)
+    }
+}
+
+/// The stimuli of the session.
+#[derive(Debug)]
+pub(crate) struct Stimuli {
+    stimuli: Vec<ScheduledStimulus>,
+    /// Whether the target was running at the previous poll.
+    running: bool,
+}
+
+impl Stimuli {
+    pub(crate) fn new(configs: &[StimulusConfig]) -> Result<Self> {
+        for (index, config) in configs.iter().enumerate() {
+            anyhow::ensure!(
+                config.interval_ms.is_some() || !config.events.is_empty(),
+                "The stimulus #{index} has neither an `intervalMs` nor `events`, so it is never written"
+            );
+            anyhow::ensure!(
+                config.interval_ms != Some(0),
+                "The `intervalMs` of the stimulus #{index} is 0"
+            );
+        }
+
+        Ok(Self {
+            stimuli: configs
+                .iter()
+                .map(|config| ScheduledStimulus {
+                    config: config.clone(),
+                    next: None,
+                    written: 0,
+                    failed: false,
+                })
+                .collect(),
+            running: false,
+        })
+    }
+
+    /// The time until the next interval stimulus is due, to limit the wait before the next poll.
+    pub(crate) fn until_next(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.stimuli
+            .iter()
+            .filter(|stimulus| !stimulus.is_done())
+            .filter_map(|stimulus| stimulus.next)
+            .min()
+            .map(|next| next.saturating_duration_since(now))
+    }
+
+    /// Write the stimuli which are due, after a poll found the target `running`, or a core halted on a breakpoint.
+    /// A stimulus which fails is reported, and not written again.
+    pub(crate) fn poll(
+        &mut self,
+        session: &mut Session,
+        running: bool,
+        breakpoint_hit: bool,
+    ) -> Result<()> {
+        let mut result = Ok(());
+        for index in self.due(Instant::now(), running, breakpoint_hit) {
+            let stimulus = &mut self.stimuli[index];
+            if let Err(error) = write_stimulus(session, &stimulus.config) {
+                stimulus.failed = true;
+                result = Err(error.context(format!(
+                    "Failed to write the stimulus {}",
+                    stimulus.name(index)
+                )));
+            }
+        }
+        result
+    }
+
+    /// The stimuli which are due `now`, which are counted as written.
+    fn due(&mut self, now: Instant, running: bool, breakpoint_hit: bool) -> Vec<usize> {
+        let resumed = running && !self.running;
+        self.running = running;
+
+        let mut due = Vec::new();
+        for (index, stimulus) in self.stimuli.iter_mut().enumerate() {
+            if stimulus.is_done() {
+                continue;
+            }
+
+            let interval = stimulus.config.interval_ms.map(Duration::from_millis);
+            let on_event = (resumed && stimulus.config.events.contains(&StimulusEvent::Resumed))
+                || (breakpoint_hit
+                    && stimulus
+                        .config
+                        .events
+                        .contains(&StimulusEvent::BreakpointHit));
+            let on_interval = match (interval, stimulus.next) {
+                (Some(_), Some(next)) if running => next <= now,
+                _ => false,
+            };
+
+            // The interval restarts when the target resumes, and pauses while it is halted. A late poll doesn't
+            // write the missed stimuli at once.
+            stimulus.next = match interval {
+                Some(interval) if running => Some(match stimulus.next {
+                    Some(next) if !resumed && !on_interval => next,
+                    Some(next) if !resumed && next + interval > now => next + interval,
+                    _ => now + interval,
+                }),
+                _ => None,
+            };
+
+            if on_event || on_interval {
+                stimulus.written += 1;
+                due.push(index);
+            }
+        }
+        due
+    }
+}
+
+fn write_stimulus(session: &mut Session, config: &StimulusConfig) -> Result<()> {
+    let mut core = session
+        .core(config.core_index)
+        .with_context(|| format!("Failed to attach to the core #{}", config.core_index))?;
+    for write in &config.writes {
+        let value = match write.operation {
+            RegisterOperation::Value(value) => value,
+            RegisterOperation::Set(bits) => core.read_word_32(write.address)? | bits,
+            RegisterOperation::Clear(bits) => core.read_word_32(write.address)? & !bits,
+            RegisterOperation::Toggle(bits) => core.read_word_32(write.address)? ^ bits,
+        };
+        core.write_word_32(write.address, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::Stimuli;
+    use crate::cmd::dap_server::server::configuration::{
+        RegisterOperation, RegisterWrite, StimulusConfig, StimulusEvent,
+    };
+
+    fn stimulus(interval_ms: Option<u64>, events: Vec<StimulusEvent>) -> StimulusConfig {
+        StimulusConfig {
+            name: None,
+            core_index: 0,
+            writes: vec![RegisterWrite {
+                address: 0x4800_0014,
+                operation: RegisterOperation::Toggle(1 << 5),
+            }],
+            interval_ms,
+            events,
+            count: None,
+        }
+    }
+
+    #[test]
+    fn stimuli_are_written_on_their_interval_and_events() {
+        let mut configs = vec![
+            stimulus(Some(10), Vec::new()),
+            stimulus(None, vec![StimulusEvent::Resumed]),
+            stimulus(None, vec![StimulusEvent::BreakpointHit]),
+        ];
+        configs[1].count = Some(1);
+        let mut stimuli = Stimuli::new(&configs).unwrap();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // Resuming starts the interval.
+        assert_eq!(stimuli.due(at(0), true, false), [1]);
+        assert!(stimuli.due(at(5), true, false).is_empty());
+        assert_eq!(stimuli.due(at(10), true, false), [0]);
+        // A late poll writes the stimulus once.
+        assert_eq!(stimuli.due(at(45), true, false), [0]);
+        assert!(stimuli.due(at(46), true, false).is_empty());
+
+        // The interval pauses while halted, and the resume stimulus was written its count already.
+        assert_eq!(stimuli.due(at(60), false, true), [2]);
+        assert!(stimuli.due(at(100), true, false).is_empty());
+        assert_eq!(stimuli.due(at(110), true, false), [0]);
+
+        assert!(Stimuli::new(&[stimulus(None, Vec::new())]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Debugging more than one binary on a core, e.g. a bootloader which starts the application at a different address.
+//!
+//! Every binary covers the address range of its code, where it runs. When the core halts in the code of another binary than the
+//! active one, its [`DebugInfo`] becomes the active one in [`super::core_data::CoreData::debug_info`], so the stack
+//! frames and variables are resolved with the symbols of the code that is running.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use goblin::elf::section_header::{SHF_ALLOC, SHF_EXECINSTR};
+use probe_rs::debug::{debug_info::DebugInfo, VerifiedBreakpoint};
+
+use super::configuration::CoreConfig;
+
+/// A binary, and the addresses of its code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Image {
+    pub(crate) path: PathBuf,
+    pub(crate) address_range: Range<u64>,
+}
+
+/// The binaries of a core, besides the active one.
+pub(crate) struct SymbolFiles {
+    /// The binary of the active [`DebugInfo`].
+    active: Image,
+    inactive: Vec<(Image, DebugInfo)>,
+}
+
+impl SymbolFiles {
+    /// Load the `symbol_files` of a core, or `None` if it only has the `program_binary`.
+    pub(crate) fn load(core_configuration: &CoreConfig) -> Result<Option<Self>> {
+        let Some(program_binary) = &core_configuration.program_binary else {
+            return Ok(None);
+        };
+        if core_configuration.symbol_files.is_empty() {
+            return Ok(None);
+        }
+
+        let active = Image {
+            path: program_binary.clone(),
+            address_range: move_range(code_range(program_binary)?, core_configuration.load_offset),
+        };
+        let mut inactive = Vec::new();
+        for symbol_file in &core_configuration.symbol_files {
+            let code_range = code_range(&symbol_file.program_binary)?;
+            let load_offset = symbol_file.load_address.map_or(0, |load_address| {
+                load_address.wrapping_sub(code_range.start) as i64
+            });
+            let image = Image {
+                path: symbol_file.program_binary.clone(),
+                address_range: move_range(code_range, load_offset),
+            };
+            let debug_info =
+                DebugInfo::from_file_with_load_offset(&symbol_file.program_binary, load_offset)
+                    .map_err(|error| anyhow!(error))?;
+            inactive.push((image, debug_info));
+        }
+
+        Ok(Some(Self { active, inactive }))
+    }
+
+    /// Make the binary which contains the program counter the active one, by swapping its [`DebugInfo`] with
+    /// `debug_info`. Returns the binary if it changed.
+    pub(crate) fn select(
+        &mut self,
+        debug_info: &mut DebugInfo,
+        program_counter: u64,
+    ) -> Option<&Image> {
+        if self.active.address_range.contains(&program_counter) {
+            return None;
+        }
+
+        let (image, inactive_debug_info) = self
+            .inactive
+            .iter_mut()
+            .find(|(image, _)| image.address_range.contains(&program_counter))?;
+        std::mem::swap(debug_info, inactive_debug_info);
+        std::mem::swap(&mut self.active, image);
+        Some(&self.active)
+    }
+
+    /// Find the location of a breakpoint in the binaries which are not active.
+    pub(crate) fn breakpoint_location(
+        &self,
+        path: &Path,
+        line: u64,
+        column: Option<u64>,
+    ) -> Option<VerifiedBreakpoint> {
+        self.inactive
+            .iter()
+            .find_map(|(_, debug_info)| debug_info.get_breakpoint_location(path, line, column).ok())
+    }
+}
+
+/// The range of the link addresses of the code of a binary.
+fn code_range(path: &Path) -> Result<Range<u64>> {
+    let buffer = std::fs::read(path)
+        .with_context(|| format!("Failed to read ELF file {}", path.display()))?;
+    let binary = goblin::elf::Elf::parse(buffer.as_slice())?;
+
+    binary
+        .section_headers
+        .iter()
+        .filter(|section| {
+            let flags = section.sh_flags as u32;
+            flags & SHF_ALLOC != 0 && flags & SHF_EXECINSTR != 0 && section.sh_size > 0
+        })
+        .map(|section| section.sh_addr..section.sh_addr + section.sh_size)
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+        .ok_or_else(|| anyhow!("{} does not contain any code", path.display()))
+}
+
+/// The range of addresses at which the code runs, `load_offset` from where it is linked.
+fn move_range(range: Range<u64>, load_offset: i64) -> Range<u64> {
+    let offset = load_offset as u64;
+    range.start.wrapping_add(offset)..range.end.wrapping_add(offset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn code_range_is_moved_by_load_offset() {
+        assert_eq!(move_range(0x100..0x400, 0), 0x100..0x400);
+        assert_eq!(move_range(0x1100..0x1400, 0x2000), 0x3100..0x3400);
+        assert_eq!(move_range(0x1100..0x1400, -0x1000), 0x100..0x400);
+    }
+}
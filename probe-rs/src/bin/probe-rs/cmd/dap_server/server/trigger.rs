@@ -0,0 +1,80 @@
+//! The output which is pulsed when selected debug events occur, see [`TriggerConfig`].
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use probe_rs::Session;
+
+use super::configuration::{SessionConfig, TriggerConfig, TriggerEvent, TriggerPin};
+use super::lifecycle_commands::{command_environment, spawn_command};
+
+/// The bits of the pins in the `DAP_SWJ_Pins` command.
+const PIN_TDI: u32 = 1 << 2;
+const PIN_NTRST: u32 = 1 << 5;
+
+/// Fire the trigger of the session, if it is configured for `event`: pulse the pin of the probe, and start the
+/// command.
+pub(crate) fn fire_trigger(
+    session_config: &SessionConfig,
+    event: TriggerEvent,
+    session: &mut Session,
+) -> Result<()> {
+    let Some(trigger) = &session_config.trigger else {
+        return Ok(());
+    };
+    if !trigger.events.contains(&event) {
+        return Ok(());
+    }
+
+    if let Some(pin) = trigger.probe_pin {
+        pulse(session, trigger, pin)?;
+    }
+    if let Some(command) = &trigger.command {
+        let program_binary = session_config
+            .core_configs
+            .first()
+            .and_then(|core_config| core_config.program_binary.as_deref());
+        let environment = command_environment(
+            &session.target().name,
+            session_config.probe_selector.as_ref(),
+            program_binary,
+        );
+        spawn_command(command, &environment, session_config.cwd.as_deref())?;
+    }
+    Ok(())
+}
+
+fn pulse(session: &mut Session, trigger: &TriggerConfig, pin: TriggerPin) -> Result<()> {
+    let (active, idle, select) = pin_levels(pin, trigger.active_low);
+    let interface = session
+        .get_arm_interface()
+        .context("Only the probes of ARM targets can pulse a trigger pin")?;
+    interface.swj_pins(active, select, 0)?;
+    std::thread::sleep(Duration::from_micros(trigger.pulse_length));
+    interface.swj_pins(idle, select, 0)?;
+    Ok(())
+}
+
+/// The values of the pins during and after the pulse, and the mask which selects the pin.
+fn pin_levels(pin: TriggerPin, active_low: bool) -> (u32, u32, u32) {
+    let select = match pin {
+        TriggerPin::Tdi => PIN_TDI,
+        TriggerPin::Ntrst => PIN_NTRST,
+    };
+    if active_low {
+        (0, select, select)
+    } else {
+        (select, 0, select)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pin_levels, TriggerPin};
+
+    #[test]
+    fn pins_are_pulsed_to_their_active_level() {
+        assert_eq!(pin_levels(TriggerPin::Tdi, false), (0x04, 0, 0x04));
+        assert_eq!(pin_levels(TriggerPin::Ntrst, true), (0, 0x20, 0x20));
+    }
+}
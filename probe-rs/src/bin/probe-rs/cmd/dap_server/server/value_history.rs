@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use crate::cmd::dap_server::debug_adapter::dap::dap_types::VariablePresentationHint;
+
+/// The attribute of the presentation hint of a register whose value changed since the previous stop.
+///
+/// The DAP specification has no attribute for this, so clients have to know it to highlight the registers.
+pub(crate) const CHANGED_ATTRIBUTE: &str = "changed";
+
+/// The values of the core registers and the peripheral registers, as they were shown at the previous stop and at
+/// this stop, by their qualified names.
+#[derive(Debug, Default)]
+pub(crate) struct ValueHistory {
+    previous_stop: HashMap<String, String>,
+    this_stop: HashMap<String, String>,
+}
+
+impl ValueHistory {
+    /// The core halted again, so the values shown so far are the values of the previous stop. Registers which were
+    /// not shown at this stop keep their older value.
+    pub(crate) fn next_stop(&mut self) {
+        self.previous_stop.extend(self.this_stop.drain());
+    }
+
+    /// Record the value of a register at this stop, and tell whether it changed since the previous stop.
+    pub(crate) fn update(&mut self, name: &str, value: &str) -> bool {
+        self.this_stop.insert(name.to_string(), value.to_string());
+        self.previous_stop
+            .get(name)
+            .map_or(false, |previous| previous != value)
+    }
+
+    /// The presentation hint of a register, which marks it if its value changed since the previous stop.
+    pub(crate) fn presentation_hint(
+        &mut self,
+        name: &str,
+        value: &str,
+    ) -> Option<VariablePresentationHint> {
+        self.update(name, value).then(|| VariablePresentationHint {
+            attributes: Some(vec![CHANGED_ATTRIBUTE.to_string()]),
+            kind: Some("data".to_string()),
+            lazy: None,
+            visibility: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values_are_compared_with_the_previous_stop() {
+        let mut history = ValueHistory::default();
+        assert!(!history.update("r0", "1"));
+        assert!(!history.update("GPIOA.ODR", "0x0"));
+
+        history.next_stop();
+        assert!(history.update("r0", "2"));
+        // Showing the register again at the same stop does not hide the change.
+        assert!(history.update("r0", "2"));
+
+        history.next_stop();
+        assert!(!history.update("r0", "2"));
+        // The peripheral was not shown at the previous stop, so it is compared with the stop before.
+        assert!(history.update("GPIOA.ODR", "0x20"));
+    }
+}
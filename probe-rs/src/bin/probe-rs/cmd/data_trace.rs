@@ -0,0 +1,424 @@
+//! Log the values of variables over time with the data trace of the DWT, without halting the core.
+//!
+//! Every variable is traced by one DWT comparator, which emits the PC of the instruction and the value for every
+//! access to it. The packets are received over SWO, timestamped with the timestamps of the ITM, and written as
+//! CSV or NDJSON with the columns `time`, `variable`, `access`, `value` and `pc`. The time is given in seconds since the
+//! start of the trace.
+
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use itm::{MemoryAccessType, TracePacket};
+use probe_rs::architecture::arm::{
+    component::{find_component, DataTraceAccess, Dwt, TraceSink},
+    memory::PeripheralType,
+    DpAddress, SwoConfig,
+};
+
+use super::itm::{swo_reader, TpiuClock};
+use crate::util::itm_events::{ItmEvent, ItmEvents, TimestampConfig};
+use crate::util::telemetry::{TelemetryFormat, TelemetryRecord, TelemetrySink};
+use crate::util::{common_options::ProbeOptions, parse_u64, termination};
+use crate::CoreOptions;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    shared: CoreOptions,
+
+    #[clap(flatten)]
+    common: ProbeOptions,
+
+    /// The speed of the clock feeding the TPIU/SWO module in Hz, or `auto` to compute it from the clock configuration of the target.
+    clk: TpiuClock,
+
+    /// The desired baud rate of the SWO output.
+    baud: u32,
+
+    /// The variables to trace, as `<address>[:<size>]`, or `<symbol>[:<size>]` with `--elf`. The size is 1, 2 or 4
+    /// bytes, and defaults to 4 for addresses and to the size of the symbol.
+    #[clap(required = true)]
+    variables: Vec<String>,
+
+    /// The ELF file with the symbols of the variables.
+    #[clap(long)]
+    elf: Option<PathBuf>,
+
+    /// Receive the SWO output with the serial adapter at this port, e.g. `/dev/ttyUSB0`, for probes without SWO
+    /// capture.
+    #[clap(long)]
+    swo_serial: Option<String>,
+
+    /// Trace reads of the variables, not only writes.
+    #[clap(long)]
+    reads: bool,
+
+    /// Stop tracing after this many milliseconds, instead of tracing until probe-rs is stopped.
+    #[clap(long, value_parser = parse_u64)]
+    duration_ms: Option<u64>,
+
+    /// Write the log to this file, instead of stdout.
+    #[clap(long)]
+    output: Option<PathBuf>,
+
+    /// The format of the log, `csv` or `ndjson` with one JSON object per line.
+    #[clap(long, default_value = "csv")]
+    format: TelemetryFormat,
+
+    /// Also send every access as a JSON object in a UDP datagram to this address, e.g. for the UDP server of
+    /// PlotJuggler.
+    #[clap(long, value_name = "ADDRESS")]
+    udp: Option<SocketAddr>,
+}
+
+/// A variable which is traced by a DWT comparator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Variable {
+    name: String,
+    address: u32,
+    size: u32,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let symbols = self.elf.as_deref().map(read_symbols).transpose()?;
+        let variables = self
+            .variables
+            .iter()
+            .map(|variable| parse_variable(variable, symbols.as_deref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut session = self.common.simple_attach()?;
+
+        let clk = self.clk.frequency(&mut session, self.shared.core)?;
+        let sink = TraceSink::Swo(
+            SwoConfig::new(clk)
+                .set_baud(self.baud)
+                .set_external_capture(self.swo_serial.is_some()),
+        );
+        session.setup_tracing(self.shared.core, sink)?;
+
+        let access = if self.reads {
+            DataTraceAccess::ReadWrite
+        } else {
+            DataTraceAccess::Write
+        };
+        {
+            let components = session.get_arm_components(DpAddress::Default)?;
+            let component = find_component(&components, PeripheralType::Dwt)?;
+            let interface = session.get_arm_interface()?;
+            let mut dwt = Dwt::new(interface, component);
+
+            let num_comparators = dwt.num_comparators()?;
+            if variables.len() > num_comparators {
+                bail!(
+                    "The DWT of the core has {num_comparators} comparators, so at most {num_comparators} variables can be traced"
+                );
+            }
+
+            // Only the data trace is of interest.
+            dwt.disable_exception_trace()?;
+            for (unit, variable) in variables.iter().enumerate() {
+                dwt.enable_data_value_trace(unit, variable.address, variable.size, access)?;
+            }
+        }
+
+        let output: Box<dyn Write> = match &self.output {
+            Some(path) => {
+                Box::new(LineWriter::new(File::create(path).with_context(|| {
+                    format!("Failed to create {}", path.display())
+                })?))
+            }
+            None => Box::new(LineWriter::new(std::io::stdout())),
+        };
+        let mut log_sink = TelemetrySink::new(self.format, output);
+        if let Some(addr) = self.udp {
+            log_sink
+                .send_udp(addr)
+                .with_context(|| format!("Failed to send to {addr}"))?;
+        }
+        log_sink.header::<NamedRecord>()?;
+
+        let decoder = itm::Decoder::new(
+            swo_reader(&mut session, self.swo_serial.as_deref(), sink)?,
+            itm::DecoderOptions { ignore_eof: true },
+        );
+        let events = ItmEvents::new(
+            decoder,
+            TimestampConfig {
+                clock_frequency: clk,
+                prescaler: 1,
+                global_clock_frequency: None,
+            },
+        );
+
+        let duration = self.duration_ms.map(Duration::from_millis);
+        let start = Instant::now();
+        let mut log = DataTraceLog::default();
+        for event in events {
+            if let Some(record) = log.record(&event?) {
+                if let Some(variable) = variables.get(record.comparator as usize) {
+                    log_sink.record(&NamedRecord {
+                        record: &record,
+                        variable: &variable.name,
+                    })?;
+                }
+            }
+
+            if duration.map_or(false, |duration| start.elapsed() > duration)
+                || termination::requested()
+            {
+                break;
+            }
+        }
+
+        for unit in 0..variables.len() {
+            session.remove_swv_data_trace(unit)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a variable as `<address>[:<size>]` or `<symbol>[:<size>]`.
+fn parse_variable(variable: &str, symbols: Option<&[Symbol]>) -> Result<Variable> {
+    // Only a number after the last colon is a size, symbols can contain colons.
+    let (target, size) = match variable.rsplit_once(':') {
+        Some((target, size)) => match size.parse::<u32>() {
+            Ok(size) => (target, Some(size)),
+            Err(_) => (variable, None),
+        },
+        None => (variable, None),
+    };
+
+    let (address, default_size) = if target.starts_with(|c: char| c.is_ascii_digit()) {
+        let address = parse_u64(target).with_context(|| format!("Invalid address '{target}'"))?;
+        (address, 4)
+    } else {
+        let symbols = symbols
+            .ok_or_else(|| anyhow!("The address of '{target}' can only be looked up with --elf"))?;
+        let symbol = symbols
+            .iter()
+            .find(|symbol| symbol.name == target)
+            .ok_or_else(|| anyhow!("The ELF file has no variable '{target}'"))?;
+        (symbol.address, symbol.size)
+    };
+
+    let size = size.map_or(default_size, u64::from);
+    if ![1, 2, 4].contains(&size) {
+        bail!("'{target}' has a size of {size} bytes, only variables of 1, 2 or 4 bytes can be traced");
+    }
+    let size = size as u32;
+    let address = u32::try_from(address)
+        .ok()
+        .filter(|address| address % size == 0)
+        .ok_or_else(|| anyhow!("'{target}' is not at a 32 bit address aligned to its size"))?;
+
+    Ok(Variable {
+        name: target.to_string(),
+        address,
+        size,
+    })
+}
+
+/// A variable in the symbol table of an ELF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Symbol {
+    name: String,
+    address: u64,
+    size: u64,
+}
+
+fn read_symbols(elf: &Path) -> Result<Vec<Symbol>> {
+    let buffer = std::fs::read(elf).with_context(|| format!("Failed to read {}", elf.display()))?;
+    let binary = goblin::elf::Elf::parse(buffer.as_slice())?;
+
+    Ok(binary
+        .syms
+        .iter()
+        .filter(|sym| sym.st_type() == goblin::elf::sym::STT_OBJECT)
+        .filter_map(|sym| {
+            Some(Symbol {
+                name: binary.strtab.get_at(sym.st_name)?.to_string(),
+                address: sym.st_value,
+                size: sym.st_size,
+            })
+        })
+        .collect())
+}
+
+/// A traced access to a variable.
+#[derive(Debug, Clone, PartialEq)]
+struct Record {
+    time: Duration,
+    comparator: u8,
+    access: MemoryAccessType,
+    value: u32,
+    /// The PC of the instruction which accessed the variable, if its packet was not lost.
+    pc: Option<u32>,
+}
+
+impl Record {
+    fn access(&self) -> &'static str {
+        match self.access {
+            MemoryAccessType::Read => "read",
+            MemoryAccessType::Write => "write",
+        }
+    }
+
+    fn to_csv(&self, variable: &str) -> String {
+        let access = self.access();
+        let pc = self.pc.map(|pc| format!("{pc:#010x}")).unwrap_or_default();
+        format!(
+            "{:.6},{variable},{access},{},{pc}",
+            self.time.as_secs_f64(),
+            self.value
+        )
+    }
+}
+
+/// A record with the name of its variable, as it is logged.
+struct NamedRecord<'a> {
+    record: &'a Record,
+    variable: &'a str,
+}
+
+impl TelemetryRecord for NamedRecord<'_> {
+    fn columns() -> &'static [&'static str] {
+        &["time", "variable", "access", "value", "pc"]
+    }
+
+    fn to_csv(&self) -> String {
+        self.record.to_csv(self.variable)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "time": self.record.time.as_secs_f64(),
+            "variable": self.variable,
+            "access": self.record.access(),
+            "value": self.record.value,
+            "pc": self.record.pc,
+        })
+    }
+}
+
+/// Turns the data trace packets into records of the accesses.
+#[derive(Debug, Default)]
+struct DataTraceLog {
+    /// The PC packet of every comparator, which precedes its data value packet.
+    pcs: [Option<u32>; 16],
+}
+
+impl DataTraceLog {
+    /// The access of a data value packet, with the PC of the packet before it.
+    fn record(&mut self, event: &ItmEvent) -> Option<Record> {
+        match &event.packet {
+            TracePacket::DataTracePC { comparator, pc } => {
+                if let Some(slot) = self.pcs.get_mut(*comparator as usize) {
+                    *slot = Some(*pc);
+                }
+                None
+            }
+            TracePacket::DataTraceValue {
+                comparator,
+                access_type,
+                value,
+            } => {
+                // The value is sent with the least significant byte first.
+                let value = value
+                    .iter()
+                    .rev()
+                    .fold(0, |word, byte| (word << 8) | *byte as u32);
+                Some(Record {
+                    time: event.timestamp.target,
+                    comparator: *comparator,
+                    access: access_type.clone(),
+                    value,
+                    pc: self
+                        .pcs
+                        .get_mut(*comparator as usize)
+                        .and_then(Option::take),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::itm_events::{EventTimestamp, TimestampQuality};
+
+    #[test]
+    fn variables_are_parsed() {
+        let symbols = [Symbol {
+            name: "COUNTER".to_string(),
+            address: 0x2000_0010,
+            size: 2,
+        }];
+
+        assert_eq!(
+            parse_variable("0x20000004", None).unwrap(),
+            Variable {
+                name: "0x20000004".to_string(),
+                address: 0x2000_0004,
+                size: 4
+            }
+        );
+        assert_eq!(
+            parse_variable("COUNTER", Some(&symbols)).unwrap(),
+            Variable {
+                name: "COUNTER".to_string(),
+                address: 0x2000_0010,
+                size: 2
+            }
+        );
+        assert_eq!(parse_variable("0x20000011:1", None).unwrap().size, 1);
+        assert!(parse_variable("0x20000002:4", None).is_err());
+        assert!(parse_variable("COUNTER", None).is_err());
+    }
+
+    #[test]
+    fn values_are_recorded_with_the_pc() {
+        let timestamp = EventTimestamp {
+            target: Duration::from_micros(1500),
+            quality: TimestampQuality::Exact,
+            host: std::time::SystemTime::UNIX_EPOCH,
+        };
+        let packets = [
+            TracePacket::DataTracePC {
+                comparator: 1,
+                pc: 0x0800_0420,
+            },
+            TracePacket::DataTraceValue {
+                comparator: 1,
+                access_type: MemoryAccessType::Write,
+                value: vec![0x2a, 0x01],
+            },
+            TracePacket::DataTraceValue {
+                comparator: 0,
+                access_type: MemoryAccessType::Read,
+                value: vec![0x07],
+            },
+        ];
+
+        let mut log = DataTraceLog::default();
+        assert_eq!(
+            packets
+                .into_iter()
+                .filter_map(|packet| log.record(&ItmEvent { packet, timestamp }))
+                .map(|record| record.to_csv("COUNTER"))
+                .collect::<Vec<_>>(),
+            [
+                "0.001500,COUNTER,write,298,0x08000420",
+                "0.001500,COUNTER,read,7,"
+            ]
+        );
+    }
+}
@@ -1,15 +1,30 @@
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use probe_rs::flashing::FileDownloadError;
 use probe_rs::flashing::Format;
+use probe_rs::flashing::{Bootloader, McubootSerial, Stm32Bootloader, Stm32Dfu, Uf2Drive};
 
 use crate::util::common_options::ProbeOptions;
 use crate::util::common_options::{CargoOptions, FlashOptions};
-use crate::util::flash::run_flash_download;
+use crate::util::flash::{flash_region_end, run_bootloader_download, run_flash_download};
+use crate::util::{parse_u32, parse_u64};
 use crate::FormatOptions;
 
+/// The bootloaders of chips which can flash them without a debug probe.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum BootloaderKind {
+    /// The STM32 system bootloader on a USART
+    Stm32Uart,
+    /// The STM32 system bootloader in USB DFU mode
+    Stm32Dfu,
+    /// The serial recovery of MCUboot
+    Mcuboot,
+    /// The mass storage drive of a UF2 bootloader
+    Uf2,
+}
+
 #[derive(clap::Parser)]
 pub struct Cmd {
     #[clap(flatten)]
@@ -30,12 +45,52 @@ pub struct Cmd {
     #[clap(long = "disable-double-buffering")]
     disable_double_buffering: bool,
 
+    /// Flash the per-device data of the connected chip from the given CSV or JSON manifest, which is keyed by the unique device ID
+    #[clap(long, value_name = "MANIFEST")]
+    provision: Option<PathBuf>,
+
+    /// The file to which the provisioned data is appended. Defaults to `<MANIFEST>.record.jsonl`
+    #[clap(long, value_name = "RECORD")]
+    provision_record: Option<PathBuf>,
+
+    /// Allow writing to one-time programmable (OTP) memory. Use `--dry-run` first to see which OTP words would change
+    #[clap(long)]
+    allow_otp_write: bool,
+
     #[clap(flatten)]
     format_options: FormatOptions,
+
+    /// Flash through a bootloader of the chip instead of a debug probe. The chip has to be selected with `--chip`
+    #[clap(long, value_enum)]
+    bootloader: Option<BootloaderKind>,
+
+    /// The serial port of the `stm32-uart` and `mcuboot` bootloaders
+    #[clap(long)]
+    bootloader_port: Option<String>,
+
+    /// The baud rate of the serial port of the bootloader
+    #[clap(long, default_value = "115200")]
+    bootloader_baud_rate: u32,
+
+    /// The start of the slot which the `mcuboot` bootloader programs. The slot reaches to the end of its flash region
+    #[clap(long, value_parser = parse_u64)]
+    mcuboot_slot: Option<u64>,
+
+    /// The directory at which the drive of the `uf2` bootloader is mounted. Defaults to the first UF2 drive which is found
+    #[clap(long)]
+    uf2_drive: Option<PathBuf>,
+
+    /// The family ID of the blocks for the `uf2` bootloader
+    #[clap(long, value_parser = parse_u32)]
+    uf2_family_id: Option<u32>,
 }
 
 impl Cmd {
-    pub fn run(self) -> anyhow::Result<()> {
+    pub fn run(mut self) -> anyhow::Result<()> {
+        if let Some(kind) = self.bootloader {
+            return self.run_with_bootloader(kind);
+        }
+
         let mut session = self.common.simple_attach()?;
 
         let mut file = match File::open(&self.path) {
@@ -45,13 +100,17 @@ impl Cmd {
 
         let mut loader = session.target().flash_loader();
 
+        let partition = self.format_options.resolve_partition(session.target())?;
         let format = self.format_options.into_format()?;
         match format {
             Format::Bin(options) => loader.load_bin_data(&mut file, options),
-            Format::Elf => loader.load_elf_data(&mut file),
+            Format::Elf(options) => loader.load_elf_data(&mut file, &options),
             Format::Hex => loader.load_hex_data(&mut file),
             Format::Idf(options) => loader.load_idf_data(&mut session, &mut file, options),
         }?;
+        if let Some(partition) = &partition {
+            loader.check_partition(partition)?;
+        }
 
         run_flash_download(
             &mut session,
@@ -65,6 +124,95 @@ impl Cmd {
                 flash_layout_output_path: None,
                 elf: None,
                 work_dir: None,
+                provision: self.provision,
+                provision_record: self.provision_record,
+                allow_otp_write: self.allow_otp_write,
+                cargo_options: CargoOptions::default(),
+                probe_options: self.common,
+            },
+            loader,
+            self.chip_erase,
+        )?;
+
+        Ok(())
+    }
+
+    fn run_with_bootloader(mut self, kind: BootloaderKind) -> anyhow::Result<()> {
+        if self.provision.is_some() {
+            bail!("Provisioning needs a debug probe, to read the unique device ID.");
+        }
+        self.common.maybe_load_chip_desc()?;
+        let Some(chip) = self.common.chip_name()? else {
+            bail!("Flashing through a bootloader needs the chip to be selected with `--chip` or `--board`.");
+        };
+        let target = probe_rs::config::get_target_by_name(chip)?;
+
+        let port = || {
+            self.bootloader_port.as_deref().context(
+                "The serial port of the bootloader has to be selected with `--bootloader-port`.",
+            )
+        };
+        let mut bootloader: Box<dyn Bootloader> = match kind {
+            BootloaderKind::Stm32Uart => Box::new(Stm32Bootloader::open_serial(
+                port()?,
+                self.bootloader_baud_rate,
+            )?),
+            BootloaderKind::Stm32Dfu => Box::new(Stm32Dfu::open()?),
+            BootloaderKind::Mcuboot => {
+                let start = self
+                    .mcuboot_slot
+                    .context("The slot of MCUboot has to be selected with `--mcuboot-slot`.")?;
+                let end = flash_region_end(&target, start)?;
+                Box::new(McubootSerial::open_serial(
+                    port()?,
+                    self.bootloader_baud_rate,
+                    start..end,
+                )?)
+            }
+            BootloaderKind::Uf2 => {
+                let drive = match &self.uf2_drive {
+                    Some(drive) => drive.clone(),
+                    None => Uf2Drive::find_drives().into_iter().next().context(
+                        "No UF2 drive was found, select its directory with `--uf2-drive`.",
+                    )?,
+                };
+                Box::new(Uf2Drive::new(drive, self.uf2_family_id))
+            }
+        };
+
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) => return Err(FileDownloadError::IO(e)).context("Failed to open binary file."),
+        };
+
+        let mut loader = target.flash_loader();
+        let partition = self.format_options.resolve_partition(&target)?;
+        match self.format_options.into_format()? {
+            Format::Bin(options) => loader.load_bin_data(&mut file, options),
+            Format::Elf(options) => loader.load_elf_data(&mut file, &options),
+            Format::Hex => loader.load_hex_data(&mut file),
+            Format::Idf(_) => bail!("The esp-idf format can not be flashed through a bootloader."),
+        }?;
+        if let Some(partition) = &partition {
+            loader.check_partition(partition)?;
+        }
+
+        run_bootloader_download(
+            bootloader.as_mut(),
+            &target,
+            Path::new(&self.path),
+            &FlashOptions {
+                disable_progressbars: self.disable_progressbars,
+                disable_double_buffering: self.disable_double_buffering,
+                reset_halt: false,
+                log: None,
+                restore_unwritten: false,
+                flash_layout_output_path: None,
+                elf: None,
+                work_dir: None,
+                provision: None,
+                provision_record: None,
+                allow_otp_write: self.allow_otp_write,
                 cargo_options: CargoOptions::default(),
                 probe_options: self.common,
             },
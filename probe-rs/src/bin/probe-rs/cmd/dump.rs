@@ -33,7 +33,16 @@ impl Cmd {
 
         // let loc = 220 * 1024;
 
-        let mut core = session.core(self.shared.core)?;
+        // Cores of heterogeneous chips see different memory, so use one which can access the address.
+        let core_index = if session.target().core_can_access(self.shared.core, self.loc) {
+            self.shared.core
+        } else {
+            session
+                .target()
+                .core_index_for_address(self.loc)
+                .unwrap_or(self.shared.core)
+        };
+        let mut core = session.core(core_index)?;
 
         core.read_32(self.loc, data.as_mut_slice())?;
         // Stop timer.
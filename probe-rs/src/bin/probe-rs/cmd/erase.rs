@@ -1,19 +1,88 @@
-use probe_rs::flashing::erase_all;
+use std::ops::Range;
+
+use anyhow::anyhow;
+use colored::Colorize;
+use probe_rs::config::MemoryRegion;
+use probe_rs::flashing::{erase_all, erase_range, sectors_in_range};
+use probe_rs::Target;
 
 use crate::util::common_options::ProbeOptions;
+use crate::util::parse_range;
 
 #[derive(clap::Parser)]
 pub struct Cmd {
     #[clap(flatten)]
     common: ProbeOptions,
+
+    /// Only erase the sectors which contain this address range, given as `<start>..<end>`.
+    #[clap(long, value_parser = parse_range, conflicts_with = "region")]
+    range: Option<Range<u64>>,
+
+    /// Only erase the sectors of this partition or named flash region.
+    #[clap(long)]
+    region: Option<String>,
 }
 
 impl Cmd {
     pub fn run(self) -> anyhow::Result<()> {
         let mut session = self.common.simple_attach()?;
 
-        erase_all(&mut session, None)?;
+        let range = match (&self.range, &self.region) {
+            (Some(range), _) => range.clone(),
+            (None, Some(name)) => named_range(session.target(), name)?,
+            (None, None) => {
+                if self.common.dry_run {
+                    println!("{} all nonvolatile memory", "Would erase".yellow().bold());
+                } else {
+                    erase_all(&mut session, None)?;
+                }
+                return Ok(());
+            }
+        };
+
+        // Print what is erased, as it can be more than the range if it does not match the sectors.
+        for group in sectors_in_range(session.target(), range.clone())? {
+            let (Some(first), Some(last)) = (group.sectors.first(), group.sectors.last()) else {
+                continue;
+            };
+            println!(
+                "{} {} sector(s) at {:#010x}..{:#010x} with '{}'",
+                if self.common.dry_run {
+                    "Would erase"
+                } else {
+                    "Erasing"
+                }
+                .yellow()
+                .bold(),
+                group.sectors.len(),
+                first.base_address,
+                last.base_address + last.size,
+                group.algorithm
+            );
+        }
+
+        if !self.common.dry_run {
+            erase_range(&mut session, None, range)?;
+        }
 
         Ok(())
     }
 }
+
+/// The address range of a partition, or of a flash region, with the name.
+fn named_range(target: &Target, name: &str) -> anyhow::Result<Range<u64>> {
+    if let Some(partition) = target.partition(name) {
+        return Ok(partition.range.clone());
+    }
+
+    target
+        .memory_map
+        .iter()
+        .find_map(|region| match region {
+            MemoryRegion::Nvm(region) if region.name.as_deref() == Some(name) => {
+                Some(region.range.clone())
+            }
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("The target has no partition or flash region named `{name}`"))
+}
@@ -0,0 +1,50 @@
+use byte_unit::Byte;
+
+use crate::util::flash_stats;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    /// Show the erase and program counts of every sector.
+    #[clap(long)]
+    sectors: bool,
+}
+
+impl Cmd {
+    pub fn run(self) -> anyhow::Result<()> {
+        let all_stats = flash_stats::load_all()?;
+
+        if all_stats.is_empty() {
+            println!("No flash statistics were recorded yet.");
+            return Ok(());
+        }
+
+        for stats in all_stats {
+            println!("{} ({})", stats.chip, stats.unique_id);
+            println!("    Flash operations: {}", stats.flash_operations);
+            println!("    Sector erases:    {}", stats.total_erases());
+            println!("    Sector programs:  {}", stats.total_programs());
+
+            if let Some((address, sector)) = stats.most_erased() {
+                println!(
+                    "    Most erased:      {:#010x} ({} erases)",
+                    address, sector.erase_count
+                );
+            }
+
+            if self.sectors {
+                println!("    Sectors:");
+                for (address, sector) in &stats.sectors {
+                    println!(
+                        "        {:#010x} ({}): {} erases, {} programs",
+                        address,
+                        Byte::from_bytes(sector.size as u128).get_appropriate_unit(true),
+                        sector.erase_count,
+                        sector.program_count
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
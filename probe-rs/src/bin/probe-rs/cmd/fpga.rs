@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use probe_rs::fpga::{ChainPosition, Fpga, FpgaFamily};
+use probe_rs::WireProtocol;
+
+use crate::util::common_options::ProbeOptions;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Read the IDCODE of the FPGA, to check its position in the scan chain
+    Idcode {
+        #[clap(flatten)]
+        fpga: FpgaOptions,
+    },
+    /// Load a bitstream into the configuration memory of the FPGA, and start it
+    Load {
+        /// The bitstream, a '.bit' file, or a '.bin' file of the Xilinx tools.
+        bitstream: PathBuf,
+        #[clap(flatten)]
+        fpga: FpgaOptions,
+    },
+}
+
+#[derive(clap::Args)]
+struct FpgaOptions {
+    /// The family of the FPGA: ecp5 for the Lattice ECP5, or xilinx7 for the Xilinx 7-series.
+    #[clap(long)]
+    family: FpgaFamily,
+    /// The sum of the IR lengths of the TAPs between the FPGA and TDO, e.g. 4 for the ARM DAP of a Zynq-7000.
+    #[clap(long, default_value = "0")]
+    ir_before: usize,
+    /// The sum of the IR lengths of the TAPs between TDI and the FPGA.
+    #[clap(long, default_value = "0")]
+    ir_after: usize,
+    /// The number of TAPs between the FPGA and TDO.
+    #[clap(long, default_value = "0")]
+    taps_before: usize,
+    /// The number of TAPs between TDI and the FPGA.
+    #[clap(long, default_value = "0")]
+    taps_after: usize,
+    #[clap(flatten)]
+    common: ProbeOptions,
+}
+
+impl FpgaOptions {
+    /// Attach to the scan chain, and run `operation` with the FPGA.
+    fn run(self, operation: impl FnOnce(&mut Fpga) -> Result<()>) -> Result<()> {
+        let mut common = self.common;
+        if *common.protocol.get_or_insert(WireProtocol::Jtag) != WireProtocol::Jtag {
+            bail!("FPGAs can only be configured through JTAG");
+        }
+
+        let mut probe = common.attach_probe()?;
+        probe.attach_to_unspecified()?;
+        let jtag = probe
+            .try_as_raw_jtag()
+            .context("The probe has no raw access to the JTAG scan chain")?;
+        let position = ChainPosition {
+            ir_before: self.ir_before,
+            ir_after: self.ir_after,
+            taps_before: self.taps_before,
+            taps_after: self.taps_after,
+        };
+        let mut fpga = Fpga::new(jtag, self.family, position)?;
+        operation(&mut fpga)?;
+
+        probe.detach()?;
+        Ok(())
+    }
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        match self.subcommand {
+            Subcommand::Idcode { fpga } => fpga.run(|fpga| {
+                println!("{} FPGA, IDCODE {:#010x}", fpga.family(), fpga.idcode());
+                Ok(())
+            }),
+            Subcommand::Load { bitstream, fpga } => {
+                let data = std::fs::read(&bitstream)
+                    .with_context(|| format!("Failed to read {}", bitstream.display()))?;
+                fpga.run(|fpga| {
+                    println!(
+                        "Loading {} into the {} FPGA with IDCODE {:#010x}",
+                        bitstream.display(),
+                        fpga.family(),
+                        fpga.idcode()
+                    );
+                    fpga.load(&data)?;
+                    println!("The FPGA is configured");
+                    Ok(())
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use probe_rs::flashing::{Fat, FileSystem, FlashStorage, LittleFs};
+
+use crate::util::{common_options::ProbeOptions, flash::flash_region_end, parse_u32, parse_u64};
+use crate::CoreOptions;
+
+/// The kinds of filesystems.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum FileSystemKind {
+    /// FAT12, FAT16 or FAT32, at the address or in the first partition of a partition table
+    Fat,
+    /// LittleFS
+    Littlefs,
+}
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    shared: CoreOptions,
+
+    #[clap(flatten)]
+    common: ProbeOptions,
+
+    /// The address of the filesystem in the flash of the target.
+    #[clap(long, value_parser = parse_u64)]
+    address: u64,
+
+    /// The size of the filesystem in bytes. By default, it reaches to the end of the flash region.
+    #[clap(long, value_parser = parse_u64)]
+    size: Option<u64>,
+
+    /// The kind of the filesystem.
+    #[clap(long, value_enum, default_value = "fat")]
+    kind: FileSystemKind,
+
+    /// The block size with which the LittleFS filesystem was formatted, usually the erase size of the flash.
+    #[clap(long, value_parser = parse_u32, default_value = "4096")]
+    block_size: u32,
+
+    /// The size of the units in which the flash is read and rewritten, which should be a multiple of its sector size.
+    #[clap(long, value_parser = parse_u32, default_value = "4096")]
+    unit_size: u32,
+
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// List the entries of a directory
+    Ls {
+        /// The path of the directory.
+        #[clap(default_value = "/")]
+        path: String,
+    },
+    /// Read a file, and print it or save it
+    Read {
+        /// The path of the file in the filesystem.
+        path: String,
+        /// The file to save it to, instead of printing it.
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a file, and replace it if it exists
+    Write {
+        /// The file to write.
+        file: PathBuf,
+        /// The path of the file in the filesystem.
+        path: String,
+    },
+    /// Remove a file
+    Rm {
+        /// The path of the file.
+        path: String,
+    },
+    /// Create a directory
+    Mkdir {
+        /// The path of the directory.
+        path: String,
+    },
+}
+
+impl Cmd {
+    pub fn run(self) -> anyhow::Result<()> {
+        let mut session = self.common.simple_attach()?;
+
+        let end = match self.size {
+            Some(size) => self.address + size,
+            None => flash_region_end(session.target(), self.address)?,
+        };
+        let storage = FlashStorage::nvm(self.address..end, self.shared.core, self.unit_size);
+        let mounted: Result<Box<dyn FileSystem>, _> = match self.kind {
+            FileSystemKind::Fat => Fat::mount(&mut session, storage).map(|fs| Box::new(fs) as _),
+            FileSystemKind::Littlefs => {
+                LittleFs::mount(&mut session, storage, self.block_size).map(|fs| Box::new(fs) as _)
+            }
+        };
+        let mut fs = mounted
+            .with_context(|| format!("Mounting the filesystem at {:#010x} failed", self.address))?;
+
+        match self.subcommand {
+            Subcommand::Ls { path } => {
+                for entry in fs.list(&mut session, &path)? {
+                    if entry.is_directory {
+                        println!("{:>10}  {}/", "", entry.name);
+                    } else {
+                        println!("{:>10}  {}", entry.size, entry.name);
+                    }
+                }
+            }
+            Subcommand::Read { path, output } => {
+                let data = fs.read_file(&mut session, &path)?;
+                match output {
+                    Some(output) => std::fs::write(&output, data)
+                        .with_context(|| format!("Writing {} failed", output.display()))?,
+                    None => print!("{}", String::from_utf8_lossy(&data)),
+                }
+            }
+            Subcommand::Write { file, path } => {
+                let data = std::fs::read(&file)
+                    .with_context(|| format!("Reading {} failed", file.display()))?;
+                fs.write_file(&mut session, &path, &data)?;
+            }
+            Subcommand::Rm { path } => fs.remove_file(&mut session, &path)?,
+            Subcommand::Mkdir { path } => fs.create_directory(&mut session, &path)?,
+        }
+
+        fs.flush(&mut session)?;
+        Ok(())
+    }
+}
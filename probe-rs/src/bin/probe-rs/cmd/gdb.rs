@@ -1,6 +1,8 @@
-use std::sync::Mutex;
 use std::time::Duration;
 
+use probe_rs::messages::{self, MessageId};
+use probe_rs::SharedSession;
+
 use crate::util::common_options::ProbeOptions;
 
 #[derive(clap::Parser)]
@@ -43,15 +45,21 @@ impl Cmd {
 
         for instance in instances.iter() {
             println!(
-                "Firing up GDB stub for {:?} cores at {:?}",
-                instance.core_type, instance.socket_addrs
+                "{}",
+                messages::format(
+                    MessageId::GdbStubStarting,
+                    &[
+                        ("core_type", &format!("{:?}", instance.core_type)),
+                        ("addresses", &format!("{:?}", instance.socket_addrs)),
+                    ],
+                )
             );
         }
 
-        let session = Mutex::new(session);
+        let session = SharedSession::new(session);
 
         if let Err(e) = probe_rs::gdb_server::run(&session, instances.iter()) {
-            eprintln!("During the execution of GDB an error was encountered:");
+            eprintln!("{}", MessageId::GdbStubFailed);
             eprintln!("{e:?}");
         }
 
@@ -25,10 +25,34 @@ use crate::util::common_options::ProbeOptions;
 pub struct Cmd {
     #[clap(flatten)]
     common: ProbeOptions,
+
+    /// Only print the chip and its unique device ID, as JSON. Requires `--chip`.
+    #[clap(long)]
+    json: bool,
+}
+
+/// The structured output of `probe-rs info --json`.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChipIdentity {
+    chip: String,
+    /// The unique device ID as a hex string, if its location is known for the chip.
+    unique_id: Option<String>,
 }
 
 impl Cmd {
     pub fn run(self) -> anyhow::Result<()> {
+        if self.json {
+            anyhow::ensure!(
+                self.common.chip.is_some(),
+                "The chip has to be given with `--chip` to read its unique ID"
+            );
+
+            let identity = read_chip_identity(&self.common)?;
+            println!("{}", serde_json::to_string_pretty(&identity)?);
+            return Ok(());
+        }
+
         let mut probe = self.common.attach_probe()?;
 
         let protocols = if let Some(protocol) = self.common.protocol {
@@ -55,10 +79,36 @@ impl Cmd {
             println!();
         }
 
+        if self.common.chip.is_some() {
+            // The probe has to be released before the session attaches to it again.
+            drop(probe);
+
+            let identity = read_chip_identity(&self.common)?;
+            match identity.unique_id {
+                Some(unique_id) => println!("Unique ID of {}: {unique_id}", identity.chip),
+                None => println!(
+                    "The location of the unique ID of {} is not known",
+                    identity.chip
+                ),
+            }
+        }
+
         Ok(())
     }
 }
 
+fn read_chip_identity(common: &ProbeOptions) -> Result<ChipIdentity> {
+    let mut session = common.simple_attach()?;
+    let unique_id = session
+        .read_unique_id()?
+        .map(|id| id.iter().map(|byte| format!("{byte:02x}")).collect());
+
+    Ok(ChipIdentity {
+        chip: session.target().name.clone(),
+        unique_id,
+    })
+}
+
 fn try_show_info(
     mut probe: Probe,
     protocol: WireProtocol,
@@ -279,8 +329,13 @@ fn coresight_component_tree(
 ) -> Result<Tree<String>> {
     let tree = match &component {
         Component::GenericVerificationComponent(_) => Tree::new("Generic".to_string()),
-        Component::Class1RomTable(_, table) => {
-            let mut rom_table = Tree::new("ROM Table (Class 1)".to_string());
+        Component::Class1RomTable(_, table) | Component::Class9RomTable(_, table) => {
+            let class = if matches!(component, Component::Class1RomTable(..)) {
+                1
+            } else {
+                9
+            };
+            let mut rom_table = Tree::new(format!("ROM Table (Class {class})"));
 
             for entry in table.entries() {
                 let component = entry.component().clone();
@@ -1,10 +1,71 @@
 //! Provides ITM tracing capabilities.
 
-use probe_rs::architecture::arm::{component::TraceSink, swo::SwoConfig};
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-use crate::util::{common_options::ProbeOptions, parse_u64};
+use anyhow::{anyhow, Context};
+use itm::TracePacket;
+use probe_rs::architecture::arm::{
+    component::TraceSink,
+    swo::{SerialSwoReader, SwoConfig},
+};
+use probe_rs::Session;
+
+use crate::util::chrome_trace::ChromeTrace;
+use crate::util::itm_events::{ItmEvent, ItmEvents, TimestampConfig, TimestampQuality};
+use crate::util::orbuculum::{OrbuculumServer, OrbuculumTee};
+use crate::util::printf::{load_format_table, FormatTable, PrintfDecoder};
+use crate::util::{clocks::read_core_clock, common_options::ProbeOptions, parse_u64, termination};
 use crate::CoreOptions;
 
+/// The clock feeding the TPIU/SWO module, in Hz or computed from the clock configuration of the target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TpiuClock {
+    Auto,
+    Hz(u32),
+}
+
+impl FromStr for TpiuClock {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            Ok(TpiuClock::Auto)
+        } else {
+            Ok(TpiuClock::Hz(u32::try_from(parse_u64(s)?)?))
+        }
+    }
+}
+
+impl TpiuClock {
+    /// The frequency of the clock in Hz. The TPIU is clocked by the core clock, so this is computed for `auto`.
+    pub(crate) fn frequency(self, session: &mut Session, core_index: usize) -> anyhow::Result<u32> {
+        match self {
+            TpiuClock::Hz(frequency) => Ok(frequency),
+            TpiuClock::Auto => {
+                let target_name = session.target().name.clone();
+                let clock = read_core_clock(&target_name, &mut session.core(core_index)?, None)?
+                    .ok_or_else(|| {
+                        anyhow!("The clock tree of {target_name} is not known, please give the clock in Hz")
+                    })?;
+                let frequency = clock.frequency.ok_or_else(|| {
+                    anyhow!(
+                        "The core clock ({}) depends on the external oscillator, please give the clock in Hz",
+                        clock.source
+                    )
+                })?;
+                tracing::info!(
+                    "Using the core clock of {frequency} Hz ({}) for SWO",
+                    clock.source
+                );
+                u32::try_from(frequency).context("The core clock is too fast")
+            }
+        }
+    }
+}
+
 #[derive(clap::Subcommand)]
 pub(crate) enum ItmSource {
     /// Direct ITM data to internal trace memory for extraction.
@@ -15,12 +76,26 @@ pub(crate) enum ItmSource {
     /// Direct ITM traffic out the TRACESWO pin for reception by the probe.
     #[clap(name = "swo")]
     Swo {
-        /// The speed of the clock feeding the TPIU/SWO module in Hz.
-        clk: u32,
+        /// The speed of the clock feeding the TPIU/SWO module in Hz, or `auto` to compute it from the clock configuration of the target.
+        clk: TpiuClock,
 
         /// The desired baud rate of the SWO output.
         baud: u32,
     },
+
+    /// Direct ITM traffic out the TRACESWO pin for reception by a serial adapter connected to it, for probes
+    /// without SWO capture.
+    #[clap(name = "swo-serial")]
+    SwoSerial {
+        /// The speed of the clock feeding the TPIU/SWO module in Hz, or `auto` to compute it from the clock configuration of the target.
+        clk: TpiuClock,
+
+        /// The desired baud rate of the SWO output, which the serial adapter has to support.
+        baud: u32,
+
+        /// The serial port of the adapter, e.g. `/dev/ttyUSB0` or `COM3`.
+        port: String,
+    },
 }
 
 #[derive(clap::Parser)]
@@ -34,21 +109,76 @@ pub struct Cmd {
     #[clap(value_parser = parse_u64)]
     duration_ms: u64,
 
+    /// The frequency of the clock of the local timestamps in Hz, or `auto` to compute it from the clock configuration of the target. Defaults to the clock of the SWO output.
+    #[clap(long)]
+    timestamp_clk: Option<TpiuClock>,
+
+    /// The frequency of the clock of the global timestamps in Hz, for targets with a global timestamp generator. Global timestamps correct the time after packets were lost.
+    #[clap(long)]
+    global_timestamp_clk: Option<u32>,
+
+    /// Expand the instrumentation packets of this stimulus port as printf messages with format string IDs, whose
+    /// format strings are read from `--printf-elf`.
+    #[clap(long, requires = "printf_elf")]
+    printf_port: Option<u8>,
+
+    /// The ELF file of the firmware, whose `.printf_formats` section holds the format strings of the printf
+    /// messages.
+    #[clap(long, requires = "printf_port")]
+    printf_elf: Option<PathBuf>,
+
+    #[clap(flatten)]
+    outputs: ItmOutputs,
+
     #[clap(subcommand)]
     source: ItmSource,
 }
 
+/// The stimulus port with printf messages, and their format strings.
+struct PrintfPort {
+    port: u8,
+    formats: FormatTable,
+    decoder: PrintfDecoder,
+}
+
+/// Where the trace is sent to, in addition to stdout.
+#[derive(clap::Args)]
+struct ItmOutputs {
+    /// Republish the raw trace data on this TCP address for Orbuculum clients like `orbtop` and `orbstat`, which
+    /// connect to `127.0.0.1:3402` by default.
+    #[clap(long, value_name = "ADDRESS")]
+    orbuculum: Option<SocketAddr>,
+
+    /// Write the ITM events as a timeline in the Chrome trace event format to this file, which can be opened with
+    /// Perfetto.
+    #[clap(long, value_name = "PATH")]
+    chrome_trace: Option<PathBuf>,
+}
+
 impl Cmd {
     pub fn run(self) -> anyhow::Result<()> {
-        let sink = match self.source {
-            ItmSource::TraceMemory => TraceSink::TraceMemory,
-            ItmSource::Swo { clk, baud } => TraceSink::Swo(SwoConfig::new(clk).set_baud(baud)),
+        let printf = match (self.printf_port, &self.printf_elf) {
+            (Some(port), Some(path)) => {
+                let elf = std::fs::read(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Some(PrintfPort {
+                    port,
+                    formats: load_format_table(&elf)?,
+                    decoder: PrintfDecoder::default(),
+                })
+            }
+            _ => None,
         };
+
         itm_trace(
             &self.shared,
             &self.common,
-            sink,
+            self.source,
             std::time::Duration::from_millis(self.duration_ms),
+            self.timestamp_clk,
+            self.global_timestamp_clk,
+            printf,
+            &self.outputs,
         )
     }
 }
@@ -58,35 +188,154 @@ impl Cmd {
 /// # Args
 /// * `shared_options` - Specifies information about which core to trace.
 /// * `common` - Specifies information about the probe to use for tracing.
-/// * `sink` - Specifies the destination for trace data.
+/// * `source` - Specifies the destination for trace data.
 /// * `duration` - Specifies the duration to trace for.
-/// * `output_file` - An optionally specified filename to write ITM binary data into.
+/// * `timestamp_clk` - The clock of the local timestamps, if it is not the SWO clock.
+/// * `global_timestamp_clk` - The frequency of the clock of the global timestamps, if it is known.
+/// * `printf` - The stimulus port whose packets are expanded as printf messages, if there is one.
+/// * `outputs` - Specifies where the trace is sent to, in addition to stdout.
+#[allow(clippy::too_many_arguments)]
 fn itm_trace(
     shared_options: &CoreOptions,
     common: &ProbeOptions,
-    sink: TraceSink,
+    source: ItmSource,
     duration: std::time::Duration,
+    timestamp_clk: Option<TpiuClock>,
+    global_timestamp_clk: Option<u32>,
+    mut printf: Option<PrintfPort>,
+    outputs: &ItmOutputs,
 ) -> anyhow::Result<()> {
     let mut session = common.simple_attach()?;
 
+    let (sink, swo_clk, serial_port) = match source {
+        ItmSource::TraceMemory => (TraceSink::TraceMemory, None, None),
+        ItmSource::Swo { clk, baud } => {
+            let clk = clk.frequency(&mut session, shared_options.core)?;
+            (
+                TraceSink::Swo(SwoConfig::new(clk).set_baud(baud)),
+                Some(clk),
+                None,
+            )
+        }
+        ItmSource::SwoSerial { clk, baud, port } => {
+            let clk = clk.frequency(&mut session, shared_options.core)?;
+            (
+                TraceSink::Swo(
+                    SwoConfig::new(clk)
+                        .set_baud(baud)
+                        .set_external_capture(true),
+                ),
+                Some(clk),
+                Some(port),
+            )
+        }
+    };
+    // The local timestamps are clocked by the core clock, which also feeds the TPIU on most targets.
+    let clock_frequency = match (timestamp_clk, swo_clk) {
+        (Some(timestamp_clk), _) => timestamp_clk.frequency(&mut session, shared_options.core)?,
+        (None, Some(swo_clk)) => swo_clk,
+        (None, None) => TpiuClock::Auto.frequency(&mut session, shared_options.core)?,
+    };
+
     session.setup_tracing(shared_options.core, sink)?;
 
-    let decoder = itm::Decoder::new(
-        session.swo_reader()?,
-        itm::DecoderOptions { ignore_eof: true },
+    let mut reader = swo_reader(&mut session, serial_port.as_deref(), sink)?;
+    if let Some(addr) = outputs.orbuculum {
+        let server = OrbuculumServer::bind(addr)
+            .with_context(|| format!("Failed to listen for Orbuculum clients on {addr}"))?;
+        reader = Box::new(OrbuculumTee::new(reader, server));
+    }
+    let decoder = itm::Decoder::new(reader, itm::DecoderOptions { ignore_eof: true });
+
+    let mut events = ItmEvents::new(
+        decoder,
+        TimestampConfig {
+            clock_frequency,
+            prescaler: 1,
+            global_clock_frequency: global_timestamp_clk,
+        },
     );
 
     let start = std::time::Instant::now();
-    let iter = decoder.singles();
+    let mut trace = outputs.chrome_trace.as_ref().map(|_| ChromeTrace::new());
 
     // Decode and print the ITM data for display.
-    for packet in iter {
-        if start.elapsed() > duration {
-            return Ok(());
+    for event in &mut events {
+        if start.elapsed() > duration || termination::requested() {
+            break;
+        }
+
+        let event = event?;
+        match (&mut printf, &event.packet) {
+            (Some(printf), TracePacket::Instrumentation { port, payload })
+                if *port == printf.port =>
+            {
+                printf.decoder.received(payload);
+                loop {
+                    match printf.decoder.decode(&printf.formats) {
+                        Ok(Some(message)) => println!("{}", format_message(&event, &message)),
+                        Ok(None) => break,
+                        // The decoder drops the malformed data, and continues with the next message.
+                        Err(error) => tracing::warn!("Failed to expand a printf message: {error}"),
+                    }
+                }
+            }
+            _ => println!("{}", format_event(&event)),
+        }
+        if let Some(trace) = &mut trace {
+            trace.itm_event(&event);
         }
+    }
+
+    if let (Some(trace), Some(path)) = (&trace, &outputs.chrome_trace) {
+        trace.write(path)?;
+    }
 
-        println!("{packet:?}");
+    if events.overflows() > 0 {
+        tracing::warn!(
+            "{} overflow packets were received, the timestamps marked with `?` are unreliable",
+            events.overflows()
+        );
     }
 
     Ok(())
 }
+
+/// The reader for the trace data, from the probe or from a serial adapter at `serial_port`.
+pub(crate) fn swo_reader<'a>(
+    session: &'a mut Session,
+    serial_port: Option<&str>,
+    sink: TraceSink,
+) -> anyhow::Result<Box<dyn Read + 'a>> {
+    match (serial_port, sink) {
+        (Some(port), TraceSink::Swo(config)) => {
+            let reader = SerialSwoReader::open(port, config.baud())
+                .with_context(|| format!("Failed to open the serial port {port}"))?;
+            Ok(Box::new(reader))
+        }
+        _ => Ok(Box::new(session.swo_reader()?)),
+    }
+}
+
+/// Show an event with the time of the target and of the host.
+///
+/// Timestamps which are only an upper bound are marked with `~`, unreliable ones with `?`.
+fn format_event(event: &ItmEvent) -> String {
+    format_message(event, &format!("{:?}", event.packet))
+}
+
+/// Show a `message` with the time of the target and of the host of its `event`.
+fn format_message(event: &ItmEvent, message: &str) -> String {
+    let marker = match event.timestamp.quality {
+        TimestampQuality::Exact => ' ',
+        TimestampQuality::Delayed => '~',
+        TimestampQuality::Unreliable => '?',
+    };
+    let host_time = time::OffsetDateTime::from(event.timestamp.host)
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default();
+    format!(
+        "{:>12.6}{marker} {host_time} {message}",
+        event.timestamp.target.as_secs_f64()
+    )
+}
@@ -0,0 +1,167 @@
+use anyhow::{bail, Context, Result};
+use probe_rs::WireProtocol;
+
+use crate::util::common_options::ProbeOptions;
+
+/// Shift raw bits through the JTAG scan chain, e.g. to talk to an FPGA or a vendor specific TAP next to the MCU.
+///
+/// The operations are run in order, in a single session, starting from Run-Test/Idle. The registers are shifted
+/// through the whole chain, so the other TAPs have to be put into BYPASS, by shifting ones into their IRs. The bits
+/// which were shifted out of each register are printed in hex.
+#[derive(clap::Parser)]
+pub struct Cmd {
+    /// The operations: 'ir:<hex>:<bits>' and 'dr:<hex>:<bits>' shift the lowest bits of the hex value into the
+    /// instruction or data registers, and 'idle:<cycles>' stays in Run-Test/Idle, e.g. 'ir:0x3e:6 dr:0:32'.
+    #[clap(value_parser = parse_operation, required = true)]
+    operations: Vec<Operation>,
+    #[clap(flatten)]
+    common: ProbeOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operation {
+    ShiftIr { data: Vec<u8>, bits: usize },
+    ShiftDr { data: Vec<u8>, bits: usize },
+    Idle { cycles: usize },
+}
+
+fn parse_operation(input: &str) -> Result<Operation> {
+    let mut parts = input.split(':');
+    let operation = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("ir"), Some(value), Some(bits), None) => {
+            let (data, bits) = parse_bits(value, bits)?;
+            Operation::ShiftIr { data, bits }
+        }
+        (Some("dr"), Some(value), Some(bits), None) => {
+            let (data, bits) = parse_bits(value, bits)?;
+            Operation::ShiftDr { data, bits }
+        }
+        (Some("idle"), Some(cycles), None, None) => Operation::Idle {
+            cycles: cycles.parse().context("Invalid number of cycles")?,
+        },
+        _ => bail!("Expected 'ir:<hex>:<bits>', 'dr:<hex>:<bits>' or 'idle:<cycles>'"),
+    };
+    Ok(operation)
+}
+
+/// Parse a hex `value` of any length into bytes, LSB first, which hold at least `bits` bits.
+fn parse_bits(value: &str, bits: &str) -> Result<(Vec<u8>, usize)> {
+    let bits: usize = bits.parse().context("Invalid number of bits")?;
+    if bits == 0 {
+        bail!("At least one bit has to be shifted");
+    }
+
+    let digits = value.strip_prefix("0x").unwrap_or(value).replace('_', "");
+    let mut data = vec![0u8; (bits + 7) / 8];
+    for (index, digit) in digits.chars().rev().enumerate() {
+        let nibble = digit
+            .to_digit(16)
+            .with_context(|| format!("'{value}' is not a hex value"))? as u8;
+        if index / 2 >= data.len() {
+            if nibble != 0 {
+                bail!("'{value}' has more than {bits} bits");
+            }
+            continue;
+        }
+        data[index / 2] |= nibble << (4 * (index % 2));
+    }
+    if bits % 8 != 0 && data[bits / 8] >> (bits % 8) != 0 {
+        bail!("'{value}' has more than {bits} bits");
+    }
+    Ok((data, bits))
+}
+
+/// Format the `bits` of `data`, LSB first, as a hex value.
+fn format_bits(data: &[u8], bits: usize) -> String {
+    let digits = (bits + 3) / 4;
+    let mut value = String::from("0x");
+    for digit in (0..digits).rev() {
+        let mut nibble = (data.get(digit / 2).copied().unwrap_or(0) >> (4 * (digit % 2))) & 0xf;
+        if digit == digits - 1 && bits % 4 != 0 {
+            nibble &= (1 << (bits % 4)) - 1;
+        }
+        value.push(char::from_digit(nibble.into(), 16).unwrap());
+    }
+    value
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let mut common = self.common;
+        if *common.protocol.get_or_insert(WireProtocol::Jtag) != WireProtocol::Jtag {
+            bail!("The scan chain can only be accessed with JTAG");
+        }
+
+        let mut probe = common.attach_probe()?;
+        probe.attach_to_unspecified()?;
+        let jtag = probe
+            .try_as_raw_jtag()
+            .context("The probe has no raw access to the JTAG scan chain")?;
+
+        for operation in &self.operations {
+            match operation {
+                Operation::ShiftIr { data, bits } => {
+                    let response = jtag.shift_ir(data, *bits)?;
+                    println!(
+                        "IR {}: {}",
+                        format_bits(data, *bits),
+                        format_bits(&response, *bits)
+                    );
+                }
+                Operation::ShiftDr { data, bits } => {
+                    let response = jtag.shift_dr(data, *bits)?;
+                    println!(
+                        "DR {}: {}",
+                        format_bits(data, *bits),
+                        format_bits(&response, *bits)
+                    );
+                }
+                Operation::Idle { cycles } => jtag.run_test_idle(*cycles)?,
+            }
+        }
+
+        probe.detach()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_bits, parse_operation, Operation};
+
+    #[test]
+    fn operations_are_parsed() {
+        assert_eq!(
+            parse_operation("ir:0x3e:6").unwrap(),
+            Operation::ShiftIr {
+                data: vec![0x3e],
+                bits: 6
+            }
+        );
+        assert_eq!(
+            parse_operation("dr:1_2345_6789:36").unwrap(),
+            Operation::ShiftDr {
+                data: vec![0x89, 0x67, 0x45, 0x23, 0x01],
+                bits: 36
+            }
+        );
+        assert_eq!(
+            parse_operation("idle:10").unwrap(),
+            Operation::Idle { cycles: 10 }
+        );
+
+        assert!(parse_operation("ir:0x1ff:8").is_err());
+        assert!(parse_operation("ir:0x7f:6").is_err());
+        assert!(parse_operation("dr:0x1:0").is_err());
+        assert!(parse_operation("ir:0x1").is_err());
+    }
+
+    #[test]
+    fn bits_are_formatted() {
+        assert_eq!(
+            format_bits(&[0x89, 0x67, 0x45, 0x23, 0xf1], 36),
+            "0x123456789"
+        );
+        assert_eq!(format_bits(&[0xff], 6), "0x3f");
+    }
+}
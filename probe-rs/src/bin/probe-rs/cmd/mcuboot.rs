@@ -0,0 +1,314 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use probe_rs::flashing::{
+    create_mcuboot_image, find_partition, FlashStorage, ImageVersion, McubootError, McubootImage,
+    McubootImageOptions, McubootKey, McubootSlot,
+};
+use probe_rs::Session;
+
+use crate::util::common_options::{CargoOptions, FlashOptions, ProbeOptions};
+use crate::util::flash::{flash_region_end, run_flash_download};
+use crate::util::{parse_u32, parse_u64};
+use crate::CoreOptions;
+
+/// The size of the units in which the trailer of a slot is rewritten.
+const UNIT_SIZE: u32 = 4096;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Create an MCUboot image from a binary, like `imgtool sign`
+    Create {
+        /// The binary, linked to start after the header of the image.
+        input: PathBuf,
+        /// The file to save the image to.
+        output: PathBuf,
+        #[clap(flatten)]
+        image: ImageArgs,
+        /// Pad the image to this size of the slot, and add a trailer which marks it to be swapped in.
+        #[clap(long, value_parser = parse_u64)]
+        pad: Option<u64>,
+        /// Mark the padded image as confirmed, so MCUboot does not revert it.
+        #[clap(long, requires = "pad")]
+        confirm: bool,
+    },
+    /// Validate an MCUboot image, and show its header
+    Verify {
+        /// The image.
+        file: PathBuf,
+    },
+    /// Flash an MCUboot image into a slot. A binary is turned into an image first
+    Flash {
+        /// The image, or a binary linked to start after the header of the image.
+        input: PathBuf,
+        #[clap(flatten)]
+        image: ImageArgs,
+        #[clap(flatten)]
+        slot: SlotArgs,
+        /// Mark the image to be swapped in at the next reset, for a test.
+        #[clap(long)]
+        test: bool,
+        /// Mark the image to be swapped in at the next reset permanently.
+        #[clap(long, conflicts_with = "test")]
+        permanent: bool,
+        #[clap(long)]
+        disable_progressbars: bool,
+        #[clap(flatten)]
+        common: ProbeOptions,
+    },
+    /// Show the image and the trailer of a slot
+    Info {
+        #[clap(flatten)]
+        slot: SlotArgs,
+        #[clap(flatten)]
+        shared: CoreOptions,
+        #[clap(flatten)]
+        common: ProbeOptions,
+    },
+    /// Mark the image in the secondary slot to be swapped in at the next reset
+    Test {
+        #[clap(flatten)]
+        slot: SlotArgs,
+        /// Swap the image in permanently, instead of for a test.
+        #[clap(long)]
+        permanent: bool,
+        #[clap(flatten)]
+        shared: CoreOptions,
+        #[clap(flatten)]
+        common: ProbeOptions,
+    },
+    /// Confirm the image in the primary slot, so MCUboot does not revert it
+    Confirm {
+        #[clap(flatten)]
+        slot: SlotArgs,
+        #[clap(flatten)]
+        shared: CoreOptions,
+        #[clap(flatten)]
+        common: ProbeOptions,
+    },
+}
+
+#[derive(clap::Args)]
+struct ImageArgs {
+    /// The version of the image, as `major.minor.revision+build`.
+    #[clap(long = "image-version", default_value = "0.0.0")]
+    version: String,
+    /// The size of the header, which the binary was linked for.
+    #[clap(long, value_parser = parse_u32, default_value = "0x200")]
+    header_size: u32,
+    /// The PEM file of the key which signs the image. Without it, the image only carries its hash.
+    #[clap(long)]
+    key: Option<PathBuf>,
+    /// The maximum number of sectors of a slot, for whose swap status MCUboot reserves space in the trailer.
+    #[clap(long, default_value = "128")]
+    max_sectors: u64,
+    /// The write size of the flash, which is the size of an entry of the swap status in the trailer.
+    #[clap(long, value_parser = parse_u64, default_value = "8")]
+    write_size: u64,
+}
+
+impl ImageArgs {
+    fn options(&self) -> anyhow::Result<McubootImageOptions> {
+        let mut options = McubootImageOptions::default();
+        options.version = self.version.parse::<ImageVersion>()?;
+        options.header_size = self
+            .header_size
+            .try_into()
+            .context("The header size has to be smaller than 64 KiB")?;
+        options.key = match &self.key {
+            Some(path) => {
+                let pem = std::fs::read_to_string(path)
+                    .with_context(|| format!("Reading {} failed", path.display()))?;
+                Some(McubootKey::from_pem(&pem)?)
+            }
+            None => None,
+        };
+        options.max_sectors = self.max_sectors;
+        options.write_size = self.write_size;
+        Ok(options)
+    }
+}
+
+#[derive(clap::Args)]
+struct SlotArgs {
+    /// The name of the partition of the slot, or its address in the flash of the target.
+    #[clap(long)]
+    slot: String,
+    /// The size of the slot in bytes. By default, it is the size of the partition, or the slot reaches to the end of
+    /// the flash region.
+    #[clap(long, value_parser = parse_u64)]
+    slot_size: Option<u64>,
+}
+
+impl SlotArgs {
+    fn range(&self, session: &Session) -> anyhow::Result<Range<u64>> {
+        let target = session.target();
+        let range = match parse_u64(&self.slot) {
+            Ok(start) => start..flash_region_end(target, start)?,
+            Err(_) => find_partition(target, &self.slot)?.range.clone(),
+        };
+        Ok(match self.slot_size {
+            Some(size) => range.start..range.start + size,
+            None => range,
+        })
+    }
+
+    fn open(&self, session: &Session, core: usize) -> anyhow::Result<McubootSlot<FlashStorage>> {
+        let storage = FlashStorage::nvm(self.range(session)?, core, UNIT_SIZE);
+        Ok(McubootSlot::new(storage, 0xFF))
+    }
+}
+
+impl Cmd {
+    pub fn run(self) -> anyhow::Result<()> {
+        match self.subcommand {
+            Subcommand::Create {
+                input,
+                output,
+                image,
+                pad,
+                confirm,
+            } => {
+                let mut options = image.options()?;
+                options.slot_size = pad;
+                options.confirm = confirm;
+                let binary = read(&input)?;
+                let image = create_mcuboot_image(&binary, &options)?;
+                std::fs::write(&output, image)
+                    .with_context(|| format!("Writing {} failed", output.display()))?;
+            }
+            Subcommand::Verify { file } => {
+                let image = McubootImage::parse(&read(&file)?)?;
+                print_image(&image);
+            }
+            Subcommand::Flash {
+                input,
+                image,
+                slot,
+                test,
+                permanent,
+                disable_progressbars,
+                common,
+            } => {
+                let mut options = image.options()?;
+                options.confirm = permanent;
+
+                let data = read(&input)?;
+                let data = match McubootImage::parse(&data) {
+                    Ok(_) => data,
+                    Err(McubootError::NoImage) => create_mcuboot_image(&data, &options)?,
+                    Err(error) => return Err(error.into()),
+                };
+
+                let mut session = common.simple_attach()?;
+                let range = slot.range(&session)?;
+                let trailer_size = options.trailer_size();
+                if range.start + data.len() as u64 + trailer_size > range.end {
+                    bail!(
+                        "The image of {} bytes does not fit into the slot at {:#010x}, with its trailer of {} bytes.",
+                        data.len(),
+                        range.start,
+                        trailer_size
+                    );
+                }
+
+                let mut loader = session.target().flash_loader();
+                loader.add_data(range.start, &data)?;
+                loader.add_data(
+                    range.end - trailer_size,
+                    &options.trailer(test || permanent),
+                )?;
+
+                run_flash_download(
+                    &mut session,
+                    &input,
+                    &FlashOptions {
+                        disable_progressbars,
+                        disable_double_buffering: false,
+                        reset_halt: false,
+                        log: None,
+                        restore_unwritten: false,
+                        flash_layout_output_path: None,
+                        elf: None,
+                        work_dir: None,
+                        provision: None,
+                        provision_record: None,
+                        allow_otp_write: false,
+                        cargo_options: CargoOptions::default(),
+                        probe_options: common,
+                    },
+                    loader,
+                    false,
+                )?;
+            }
+            Subcommand::Info {
+                slot,
+                shared,
+                common,
+            } => {
+                let mut session = common.simple_attach()?;
+                let mut mcuboot = slot.open(&session, shared.core)?;
+                match mcuboot.image(&mut session)? {
+                    Some(image) => print_image(&image),
+                    None => println!("The slot contains no image."),
+                }
+                let trailer = mcuboot.trailer(&mut session)?;
+                println!("Magic:     {:?}", trailer.magic);
+                println!("Image OK:  {:?}", trailer.image_ok);
+                println!("Copy done: {:?}", trailer.copy_done);
+            }
+            Subcommand::Test {
+                slot,
+                permanent,
+                shared,
+                common,
+            } => {
+                let mut session = common.simple_attach()?;
+                slot.open(&session, shared.core)?
+                    .mark_test(&mut session, permanent)?;
+            }
+            Subcommand::Confirm {
+                slot,
+                shared,
+                common,
+            } => {
+                let mut session = common.simple_attach()?;
+                slot.open(&session, shared.core)?
+                    .mark_confirmed(&mut session)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Reading {} failed", path.display()))
+}
+
+fn print_image(image: &McubootImage) {
+    println!("Version:   {}", image.version);
+    println!("Header:    {} bytes", image.header_size);
+    println!("Firmware:  {} bytes", image.image_size);
+    println!("Total:     {} bytes", image.size);
+    println!("Flags:     {:#010x}", image.flags);
+    println!("SHA-256:   {}", hex(&image.hash));
+    match (image.signature, image.key_hash) {
+        (Some(signature), Some(key_hash)) => {
+            println!("Signature: {signature:?}, key hash {}", hex(&key_hash))
+        }
+        (Some(signature), None) => println!("Signature: {signature:?}"),
+        (None, _) => println!("Signature: none"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,201 @@
+//! Record the program flow of a Cortex-M0+ with its Micro Trace Buffer (MTB), and print it when the core halted.
+//!
+//! The MTB records the taken branches and the exceptions into a buffer in the SRAM, which the firmware has to leave
+//! unused, e.g. an array which is aligned to its size. `enable` starts the recording and lets the target run, and
+//! `dump` prints the recorded branches, the newest last, e.g. after the core halted on a fault.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use probe_rs::architecture::arm::{
+    component::{find_component, Mtb, MtbPacket},
+    memory::PeripheralType,
+    DpAddress,
+};
+use probe_rs::debug::DebugInfo;
+use probe_rs::Session;
+
+use crate::util::{common_options::ProbeOptions, parse_u32, termination};
+use crate::CoreOptions;
+
+/// The time to wait for the core to halt.
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+/// The time between two reads of the status of the core, while waiting for it to halt.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Start recording the program flow into a buffer in the SRAM
+    Enable {
+        /// The address of the buffer, which has to be aligned to its size.
+        #[clap(value_parser = parse_u32)]
+        address: u32,
+        /// The size of the buffer in bytes, a power of two of at least 16 bytes. Each branch takes 8 bytes.
+        #[clap(long, default_value = "1024", value_parser = parse_u32)]
+        size: u32,
+        /// Stop recording when the buffer is full, to keep the oldest branches instead of the newest ones.
+        #[clap(long)]
+        stop_when_full: bool,
+        #[clap(flatten)]
+        target: TargetOptions,
+    },
+    /// Stop recording, and print the recorded branches, the newest last. A running core is halted first
+    Dump {
+        /// Wait until the core halts, e.g. at a breakpoint or on a fault, instead of halting it.
+        #[clap(long)]
+        wait: bool,
+        /// The ELF file with the debug information of the firmware, to show the functions of the addresses.
+        #[clap(long)]
+        elf: Option<PathBuf>,
+        #[clap(flatten)]
+        target: TargetOptions,
+    },
+    /// Stop recording
+    Disable {
+        #[clap(flatten)]
+        target: TargetOptions,
+    },
+}
+
+#[derive(clap::Args)]
+struct TargetOptions {
+    #[clap(flatten)]
+    shared: CoreOptions,
+    #[clap(flatten)]
+    common: ProbeOptions,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        match self.subcommand {
+            Subcommand::Enable {
+                address,
+                size,
+                stop_when_full,
+                target,
+            } => {
+                let mut session = target.common.simple_attach()?;
+                with_mtb(&mut session, |mtb| {
+                    Ok(mtb.enable(address, size, stop_when_full)?)
+                })?;
+                println!("The MTB records into the {size} bytes at {address:#010x}");
+                Ok(())
+            }
+            Subcommand::Dump { wait, elf, target } => {
+                let debug_info = elf
+                    .map(|elf| {
+                        DebugInfo::from_file(&elf).with_context(|| {
+                            format!("Failed to read the debug information of {}", elf.display())
+                        })
+                    })
+                    .transpose()?;
+                let describe = |address: u32| {
+                    let function = debug_info
+                        .as_ref()
+                        .and_then(|debug_info| debug_info.resolve_address(address.into()).ok())
+                        .and_then(|resolved| resolved.function);
+                    match function {
+                        Some(function) => format!("{address:#010x} ({function})"),
+                        None => format!("{address:#010x}"),
+                    }
+                };
+
+                let mut session = target.common.simple_attach()?;
+                let pc = {
+                    let mut core = session.core(target.shared.core)?;
+                    if wait {
+                        println!("Waiting for the core to halt, stop with Ctrl-C");
+                        while !core.core_halted()? {
+                            if termination::requested() {
+                                return Ok(());
+                            }
+                            std::thread::sleep(POLL_INTERVAL);
+                        }
+                    } else if !core.core_halted()? {
+                        core.halt(HALT_TIMEOUT)?;
+                    }
+                    core.read_core_reg::<u32>(core.program_counter())?
+                };
+
+                let packets = with_mtb(&mut session, |mtb| Ok(mtb.read_trace()?))?;
+                if packets.is_empty() {
+                    println!("The MTB recorded no branches");
+                }
+                for (index, line) in format_trace(&packets, describe).iter().enumerate() {
+                    println!("{index:>5}  {line}");
+                }
+                println!("Halted at {}", describe(pc));
+                Ok(())
+            }
+            Subcommand::Disable { target } => {
+                let mut session = target.common.simple_attach()?;
+                with_mtb(&mut session, |mtb| Ok(mtb.disable()?))
+            }
+        }
+    }
+}
+
+/// Run `operation` with the MTB of the target.
+fn with_mtb<T>(session: &mut Session, operation: impl FnOnce(&mut Mtb) -> Result<T>) -> Result<T> {
+    let components = session.get_arm_components(DpAddress::Default)?;
+    let component = find_component(&components, PeripheralType::Mtb)
+        .context("The target has no MTB, it is only implemented by some Cortex-M0+ chips")?;
+    let interface = session.get_arm_interface()?;
+    operation(&mut Mtb::new(interface, component))
+}
+
+/// Describe the recorded `packets`, with the addresses as given by `describe`. Where the recording restarted, the
+/// branches in between are missing.
+fn format_trace(packets: &[MtbPacket], describe: impl Fn(u32) -> String) -> Vec<String> {
+    packets
+        .iter()
+        .enumerate()
+        .map(|(index, packet)| {
+            let kind = if packet.exception {
+                "exception"
+            } else {
+                "branch"
+            };
+            let gap = if packet.start && index > 0 {
+                " (after a gap)"
+            } else {
+                ""
+            };
+            format!(
+                "{kind:<9}  {} -> {}{gap}",
+                describe(packet.source),
+                describe(packet.destination)
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::format_trace;
+    use probe_rs::architecture::arm::component::MtbPacket;
+
+    #[test]
+    fn branches_are_listed_with_gaps() {
+        let packets = [
+            MtbPacket::from_words(0x0000_0100, 0x0000_0201),
+            MtbPacket::from_words(0x0000_0212, 0x0000_00c0),
+            MtbPacket::from_words(0x0000_0301, 0x0000_0401),
+        ];
+        assert_eq!(
+            format_trace(&packets, |address| format!("{address:#x}")),
+            [
+                "branch     0x100 -> 0x200",
+                "branch     0x212 -> 0xc0",
+                "exception  0x300 -> 0x400 (after a gap)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use probe_rs::{DebugProbeInfo, DebugProbeSelector, DebugProbeType, Probe};
+
+use crate::util::probe_aliases::parse_probe_selector;
+use crate::util::wsl::{self, UsbipdState};
+
+/// The file which makes a DAPLink probe restart into its bootloader.
+const START_BOOTLOADER: &str = "START_BL.ACT";
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Update the firmware of DAPLink probes, through the drive of their bootloader
+    ///
+    /// The probe is restarted into its bootloader, and the firmware is copied onto the MAINTENANCE drive. This needs
+    /// the drives of the probe to be mounted, and the automation of the probe to be allowed, see
+    /// https://daplink.io/.
+    Update {
+        /// The interface firmware for the probe, as a .bin or .hex file.
+        firmware: PathBuf,
+        /// Only update this probe.
+        #[clap(long = "probe", value_parser = parse_probe_selector)]
+        probe_selector: Option<DebugProbeSelector>,
+        /// Update all selected probes, instead of failing if more than one is connected.
+        #[clap(long)]
+        all: bool,
+        /// The directories in which removable drives are mounted. By default, these are `/media/$USER`,
+        /// `/run/media/$USER` and `/media` on Linux, `/Volumes` on macOS, and all drive letters on Windows.
+        #[clap(long)]
+        mount_dir: Vec<PathBuf>,
+        /// How long to wait for the drive of the probe to appear after it restarted, in seconds.
+        #[clap(long, default_value = "30")]
+        timeout: u64,
+    },
+    /// Diagnose the probes in WSL2, which are passed through from Windows with usbipd
+    ///
+    /// Lists the probes which Windows shares with usbipd, whether they are attached to WSL, and whether their device
+    /// files can be opened.
+    Wsl {
+        /// Attach all shared probes to WSL.
+        #[clap(long)]
+        attach: bool,
+        /// Attach all shared probes to WSL, and keep attaching them again whenever Windows binds them again, e.g.
+        /// after a reset of the probe.
+        #[clap(long, conflicts_with = "attach")]
+        auto_attach: bool,
+    },
+}
+
+impl Cmd {
+    pub fn run(self) -> anyhow::Result<()> {
+        match self.subcommand {
+            Subcommand::Update {
+                firmware,
+                probe_selector,
+                all,
+                mount_dir,
+                timeout,
+            } => {
+                let probes = Probe::list_all()
+                    .into_iter()
+                    .filter(|probe| match &probe_selector {
+                        Some(selector) => selector.matches(probe),
+                        None => true,
+                    })
+                    .collect::<Vec<_>>();
+                match probes.len() {
+                    0 => bail!("No matching debug probe was found"),
+                    1 => {}
+                    count if !all => bail!(
+                        "{count} debug probes were found. Select one with '--probe', or update all of them with '--all'"
+                    ),
+                    _ => {}
+                }
+
+                let mount_dirs = if mount_dir.is_empty() {
+                    default_mount_dirs()
+                } else {
+                    mount_dir
+                };
+                let timeout = Duration::from_secs(timeout);
+                for probe in &probes {
+                    println!("Updating {probe:?}");
+                    update_probe(probe, &firmware, &mount_dirs, timeout)
+                        .with_context(|| format!("Updating {probe:?} failed"))?;
+                }
+            }
+            Subcommand::Wsl {
+                attach,
+                auto_attach,
+            } => diagnose_wsl(attach || auto_attach, auto_attach)?,
+        }
+        Ok(())
+    }
+}
+
+fn update_probe(
+    probe: &DebugProbeInfo,
+    firmware: &Path,
+    mount_dirs: &[PathBuf],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    match probe.probe_type {
+        DebugProbeType::CmsisDap => {}
+        _ => bail!("Only the firmware of DAPLink probes can be updated"),
+    }
+    let Some(unique_id) = &probe.serial_number else {
+        bail!("The probe has no serial number, so its drive can't be found");
+    };
+
+    let drive = find_drive(mount_dirs, unique_id).with_context(|| {
+        format!("The drive of the probe was not found in {mount_dirs:?}. Is it a DAPLink probe?")
+    })?;
+    if !drive.details.bootloader {
+        if !drive.details.automation_allowed {
+            bail!(
+                "The probe doesn't allow automation, so it can't be restarted into its bootloader. Hold its reset \
+                button while plugging it in instead, or copy an empty AUTO_ON.CFG file to its drive while holding the \
+                button"
+            );
+        }
+        std::fs::write(drive.path.join(START_BOOTLOADER), [])
+            .context("Restarting the probe into its bootloader failed")?;
+    }
+
+    let maintenance = wait_for_drive(mount_dirs, unique_id, true, timeout)?;
+    let Some(name) = firmware.file_name() else {
+        bail!("{} is not a file", firmware.display());
+    };
+    println!(
+        "    Copying {} to {}",
+        name.to_string_lossy(),
+        maintenance.path.display()
+    );
+    let target = maintenance.path.join(name);
+    std::fs::copy(firmware, &target)
+        .with_context(|| format!("Copying the firmware to {} failed", target.display()))?;
+    // The bootloader programs the firmware once the file is complete, so make sure it's not left in a cache.
+    std::fs::File::open(&target)
+        .and_then(|file| file.sync_all())
+        .ok();
+
+    let interface = wait_for_drive(mount_dirs, unique_id, false, timeout).map_err(|error| {
+        match std::fs::read_to_string(maintenance.path.join("FAIL.TXT")) {
+            Ok(reason) => {
+                anyhow::anyhow!("The bootloader rejected the firmware: {}", reason.trim())
+            }
+            Err(_) => error,
+        }
+    })?;
+    println!(
+        "    Updated to interface version {}",
+        interface
+            .details
+            .interface_version
+            .as_deref()
+            .unwrap_or("unknown")
+    );
+    Ok(())
+}
+
+fn default_mount_dirs() -> Vec<PathBuf> {
+    // On Windows, the drive letters are searched instead, see `mount_points`.
+    if cfg!(target_os = "windows") {
+        return Vec::new();
+    }
+    let mut dirs = Vec::new();
+    if let Ok(user) = std::env::var("USER") {
+        dirs.push(Path::new("/media").join(&user));
+        dirs.push(Path::new("/run/media").join(&user));
+    }
+    dirs.push("/media".into());
+    dirs.push("/Volumes".into());
+    dirs
+}
+
+/// A mounted drive of a DAPLink probe.
+struct Drive {
+    path: PathBuf,
+    details: DriveDetails,
+}
+
+/// The contents of the DETAILS.TXT file on the drive of a DAPLink probe.
+#[derive(Debug, PartialEq, Eq)]
+struct DriveDetails {
+    /// The unique ID of the probe, which is also its USB serial number.
+    unique_id: String,
+    /// Whether the probe runs its bootloader, and the drive is the MAINTENANCE drive.
+    bootloader: bool,
+    automation_allowed: bool,
+    interface_version: Option<String>,
+}
+
+impl DriveDetails {
+    /// Parse DETAILS.TXT of the drive called `volume`, `None` if it's not the file of a DAPLink probe.
+    fn parse(text: &str, volume: &str) -> Option<Self> {
+        let mut unique_id = None;
+        // Old bootloaders don't report their mode, but their drive is always called MAINTENANCE.
+        let mut bootloader = volume.eq_ignore_ascii_case("MAINTENANCE");
+        let mut automation_allowed = false;
+        let mut interface_version = None;
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "unique id" => unique_id = Some(value.to_string()),
+                "daplink mode" => bootloader = value.eq_ignore_ascii_case("bootloader"),
+                "automation allowed" => automation_allowed = value == "1",
+                "interface version" => interface_version = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Some(Self {
+            unique_id: unique_id?,
+            bootloader,
+            automation_allowed,
+            interface_version,
+        })
+    }
+}
+
+/// The paths at which drives are mounted in `mount_dirs`, or all drive letters if there are none.
+fn mount_points(mount_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    if mount_dirs.is_empty() {
+        return ('A'..='Z')
+            .map(|letter| PathBuf::from(format!("{letter}:\\")))
+            .collect();
+    }
+    mount_dirs
+        .iter()
+        .filter_map(|mount_dir| std::fs::read_dir(mount_dir).ok())
+        .flat_map(|entries| entries.flatten().map(|entry| entry.path()))
+        .collect()
+}
+
+/// Find the mounted drive of the probe with `unique_id`.
+fn find_drive(mount_dirs: &[PathBuf], unique_id: &str) -> Option<Drive> {
+    mount_points(mount_dirs).into_iter().find_map(|path| {
+        let text = std::fs::read_to_string(path.join("DETAILS.TXT")).ok()?;
+        let volume = path.file_name().unwrap_or_default().to_string_lossy();
+        let details = DriveDetails::parse(&text, &volume)?;
+        details
+            .unique_id
+            .eq_ignore_ascii_case(unique_id)
+            .then_some(Drive { path, details })
+    })
+}
+
+/// Wait until the probe with `unique_id` restarted, and its drive in the `bootloader` mode is mounted.
+fn wait_for_drive(
+    mount_dirs: &[PathBuf],
+    unique_id: &str,
+    bootloader: bool,
+    timeout: Duration,
+) -> anyhow::Result<Drive> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(drive) = find_drive(mount_dirs, unique_id) {
+            if drive.details.bootloader == bootloader {
+                return Ok(drive);
+            }
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+    bail!(
+        "The {} drive of the probe didn't appear within {}s",
+        if bootloader {
+            "MAINTENANCE"
+        } else {
+            "interface"
+        },
+        timeout.as_secs()
+    )
+}
+
+fn diagnose_wsl(attach: bool, auto_attach: bool) -> anyhow::Result<()> {
+    if !wsl::is_wsl() {
+        bail!("probe-rs doesn't run in WSL");
+    }
+
+    let devices = wsl::list_devices()?;
+    let probes = devices
+        .iter()
+        .filter(|device| device.is_probe())
+        .collect::<Vec<_>>();
+    if probes.is_empty() {
+        println!("Windows has no connected USB device which looks like a probe.");
+    }
+    for probe in &probes {
+        let state = match probe.state {
+            UsbipdState::NotShared => "not shared, share it with 'usbipd bind' on Windows",
+            UsbipdState::Shared => "shared, but not attached to WSL",
+            UsbipdState::Attached => "attached to WSL",
+        };
+        println!(
+            "{} {:04x}:{:04x} {}: {}",
+            probe.bus_id, probe.vendor_id, probe.product_id, probe.description, state
+        );
+        if attach && probe.state == UsbipdState::Shared {
+            wsl::attach(&probe.bus_id, auto_attach)?;
+            println!("    Attached to WSL");
+        }
+    }
+    if attach {
+        wsl::wait_for_probes(Duration::from_secs(5));
+    }
+
+    let inaccessible = wsl::inaccessible_probes();
+    for (device_file, vendor_id, product_id) in &inaccessible {
+        println!("The device file {device_file} of the probe {vendor_id:04x}:{product_id:04x} can't be opened.");
+    }
+    if !inaccessible.is_empty() {
+        println!(
+            "Install the udev rules, see https://probe.rs/docs/getting-started/probe-setup/. udev doesn't run in WSL \
+            by default, so start it with 'sudo service udev restart', and apply the rules to the attached probes \
+            with 'sudo udevadm trigger'."
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::DriveDetails;
+
+    #[test]
+    fn details_of_daplink_drives_are_parsed() {
+        let interface = "# DAPLink Firmware - see https://daplink.io\n\
+            Unique ID: 0240000034544e45001b00038b5e000c8d31000097969900\n\
+            HIC ID: 97969900\n\
+            Automation allowed: 1\n\
+            Daplink Mode: Interface\n\
+            Interface Version: 0254\n\
+            Bootloader Version: 0244\n";
+        assert_eq!(
+            DriveDetails::parse(interface, "DAPLINK"),
+            Some(DriveDetails {
+                unique_id: "0240000034544e45001b00038b5e000c8d31000097969900".to_string(),
+                bootloader: false,
+                automation_allowed: true,
+                interface_version: Some("0254".to_string()),
+            })
+        );
+
+        let bootloader = "Unique ID: 0240\nBootloader Version: 0244\n";
+        assert!(
+            DriveDetails::parse(bootloader, "MAINTENANCE")
+                .unwrap()
+                .bootloader
+        );
+        assert_eq!(DriveDetails::parse("Hello", "USB"), None);
+    }
+}
@@ -21,8 +21,10 @@ use addr2line::{
     Context as ObjectContext, LookupResult,
 };
 
+use crate::util::chrome_trace::ChromeTrace;
 use crate::util::common_options::{CargoOptions, FlashOptions};
 use crate::util::flash::run_flash_download;
+use crate::util::termination;
 use tracing::info;
 
 #[derive(clap::Parser)]
@@ -58,8 +60,8 @@ pub enum ProfileMethod {
     /// Use the Itm port to profile the chip (ARM only)
     #[clap(name = "itm")]
     Itm {
-        /// The speed of the clock feeding the TPIU/SWO module in Hz.
-        clk: u32,
+        /// The speed of the clock feeding the TPIU/SWO module in Hz, or `auto` to compute it from the clock configuration of the target.
+        clk: super::itm::TpiuClock,
         /// The desired baud rate of the SWO output.
         baud: u32,
     },
@@ -86,7 +88,7 @@ impl Cmd {
         let format = self.run.format_options.into_format()?;
         match format {
             Format::Bin(options) => loader.load_bin_data(&mut file, options),
-            Format::Elf => loader.load_elf_data(&mut file),
+            Format::Elf(options) => loader.load_elf_data(&mut file, &options),
             Format::Hex => loader.load_hex_data(&mut file),
             Format::Idf(options) => loader.load_idf_data(&mut session, &mut file, options),
         }?;
@@ -107,6 +109,9 @@ impl Cmd {
                     flash_layout_output_path: None,
                     elf: None,
                     work_dir: None,
+                    provision: None,
+                    provision_record: None,
+                    allow_otp_write: false,
                     cargo_options: CargoOptions::default(),
                     probe_options: self.run.common,
                 },
@@ -119,6 +124,10 @@ impl Cmd {
         let mut reads = 0;
         let mut samples: HashMap<u32, u64> = HashMap::with_capacity(256 * (self.duration as usize));
         let duration = Duration::from_secs(self.duration);
+        // The PC samples with the time they were taken at, for the timeline.
+        let mut timeline = Vec::new();
+        let record_timeline = self.run.chrome_trace.is_some();
+        let timeline_start = std::time::Instant::now();
         info!("Profiling...");
 
         match self.method {
@@ -133,13 +142,17 @@ impl Cmd {
                     let pc: u32 = core.read_core_reg(pc_reg)?;
                     *samples.entry(pc).or_insert(1) += 1;
                     reads += 1;
+                    if record_timeline {
+                        timeline.push((timeline_start.elapsed(), pc));
+                    }
                     core.run()?;
-                    if Instant::now() - start > duration {
+                    if Instant::now() - start > duration || termination::requested() {
                         break;
                     }
                 }
             }
             ProfileMethod::Itm { clk, baud } => {
+                let clk = clk.frequency(&mut session, self.core)?;
                 let sink = TraceSink::Swo(SwoConfig::new(clk).set_baud(baud));
                 session.setup_tracing(self.core, sink)?;
 
@@ -160,14 +173,32 @@ impl Cmd {
                     if let TracePacket::PCSample { pc: Some(pc) } = packet? {
                         *samples.entry(pc).or_insert(1) += 1;
                         reads += 1;
+                        if record_timeline {
+                            timeline.push((timeline_start.elapsed(), pc));
+                        }
                     }
-                    if Instant::now() - start > duration {
+                    if Instant::now() - start > duration || termination::requested() {
                         break;
                     }
                 }
             }
         }
 
+        if let Some(path) = &self.run.chrome_trace {
+            let timeline = timeline
+                .into_iter()
+                .map(|(time, pc)| {
+                    let function = symbols
+                        .get_name(pc as u64)
+                        .unwrap_or(format!("UNKNOWN - {:08X}", pc));
+                    (time, pc, function)
+                })
+                .collect::<Vec<_>>();
+            let mut trace = ChromeTrace::new();
+            trace.pc_samples(&timeline);
+            trace.write(path)?;
+        }
+
         let mut v = Vec::from_iter(samples);
         // sort by frequency
         v.sort_by(|&(_, a), &(_, b)| b.cmp(&a));
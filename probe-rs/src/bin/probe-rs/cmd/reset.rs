@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use crate::{util::common_options::ProbeOptions, CoreOptions};
 
 #[derive(clap::Parser)]
@@ -10,13 +12,24 @@ pub struct Cmd {
 
     /// Whether the reset pin should be asserted or deasserted. If left open, just pulse it
     assert: Option<bool>,
+
+    /// Pulse the reset pin for this many milliseconds, instead of resetting the core.
+    #[clap(long, value_name = "MS", conflicts_with = "assert")]
+    pulse: Option<u64>,
 }
 
 impl Cmd {
     pub fn run(self) -> anyhow::Result<()> {
         let mut session = self.common.simple_attach()?;
 
-        session.core(self.shared.core)?.reset()?;
+        match (self.assert, self.pulse) {
+            (Some(true), _) => session.target_reset_assert()?,
+            (Some(false), _) => session.target_reset_deassert()?,
+            (None, Some(duration)) => {
+                session.target_reset_pulse(Duration::from_millis(duration))?
+            }
+            (None, None) => session.core(self.shared.core)?.reset()?,
+        }
 
         Ok(())
     }
@@ -1,15 +1,20 @@
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::Context;
-use probe_rs::flashing::{FileDownloadError, Format};
+use probe_rs::flashing::{start_ram_image, FileDownloadError, Format, RamImageStart};
+use probe_rs::power::{PowerCapture, Ppk2, Ppk2Mode};
+use probe_rs::Session;
 use time::UtcOffset;
 
+use crate::util::can_capture::CanCapture;
+use crate::util::chrome_trace::{parse_defmt_timestamp, ChromeTrace};
 use crate::util::common_options::{CargoOptions, FlashOptions, ProbeOptions};
+use crate::util::exit_status::{ExitDetector, FirmwareExit};
 use crate::util::flash::run_flash_download;
-use crate::util::rtt;
+use crate::util::{rtt, termination};
 use crate::FormatOptions;
 
 #[derive(clap::Parser)]
@@ -30,52 +35,113 @@ pub struct Cmd {
 
     #[clap(flatten)]
     pub(crate) format_options: FormatOptions,
+
+    /// Exit once the firmware finishes, instead of running indefinitely.
+    ///
+    /// The exit code is 0 if the firmware signals success (with a breakpoint or a semihosting exit call),
+    /// 101 if it panics, or the exit code reported through semihosting.
+    #[clap(long)]
+    pub(crate) exit_on_completion: bool,
+
+    /// Write a timeline in the Chrome trace event format to this file, which can be opened with Perfetto.
+    ///
+    /// `run` writes the defmt log messages with their timestamps once the firmware exits, so it requires
+    /// `--exit-on-completion`. `profile` writes the PC samples.
+    #[clap(long, value_name = "PATH")]
+    pub(crate) chrome_trace: Option<PathBuf>,
+
+    /// Capture the frames of a CAN bus with an SLCAN adapter at this serial port, e.g. `/dev/ttyACM0`.
+    ///
+    /// The frames are printed with the RTT output, timestamped on the same clock, and added to the Chrome trace.
+    /// The adapter only listens, so it doesn't acknowledge frames on the bus.
+    #[clap(long, value_name = "PORT")]
+    pub(crate) can: Option<String>,
+
+    /// The bitrate of the CAN bus in bit/s.
+    #[clap(long, default_value_t = 500_000, requires = "can")]
+    pub(crate) can_bitrate: u32,
+
+    /// Measure the current of the target with a Nordic Power Profiler Kit II at this serial port.
+    ///
+    /// The measurements are added to the Chrome trace, and written to the file of `--power-csv`.
+    #[clap(long, value_name = "PORT")]
+    pub(crate) ppk2: Option<String>,
+
+    /// The supply voltage of the target in millivolts.
+    #[clap(long, default_value_t = 3300, requires = "ppk2")]
+    pub(crate) ppk2_millivolts: u16,
+
+    /// Supply the target from the PPK2 with `--ppk2-millivolts`, instead of measuring the current of its own supply.
+    #[clap(long, requires = "ppk2")]
+    pub(crate) ppk2_source_meter: bool,
+
+    /// Write the current measurements to this CSV file, with the time since the start of the firmware in seconds,
+    /// the time span of the measurement in seconds, and the average current in ampere.
+    #[clap(long, value_name = "PATH", requires = "ppk2")]
+    pub(crate) power_csv: Option<PathBuf>,
 }
 
 impl Cmd {
     pub fn run(self, timestamp_offset: UtcOffset) -> anyhow::Result<()> {
-        let mut session = self.common.simple_attach()?;
+        if self.chrome_trace.is_some() && !self.exit_on_completion {
+            anyhow::bail!("--chrome-trace requires --exit-on-completion, the trace is written when the firmware exits");
+        }
 
-        let mut file = match File::open(&self.path) {
-            Ok(file) => file,
-            Err(e) => return Err(FileDownloadError::IO(e)).context("Failed to open binary file."),
+        // The PPK2 may supply the target, so it is opened before attaching.
+        let ppk2 = match &self.ppk2 {
+            Some(port) => {
+                let millivolts = self.ppk2_millivolts;
+                let mode = if self.ppk2_source_meter {
+                    Ppk2Mode::SourceMeter { millivolts }
+                } else {
+                    Ppk2Mode::Ammeter { millivolts }
+                };
+                Some(Ppk2::open(port, mode).context("Failed to open the PPK2")?)
+            }
+            None => None,
         };
 
-        let mut loader = session.target().flash_loader();
+        let mut session = self.common.simple_attach()?;
 
+        let rtt_scan_region = self.common.board()?.and_then(|board| board.rtt_scan_region);
         let format = self.format_options.into_format()?;
-        match format {
-            Format::Bin(options) => loader.load_bin_data(&mut file, options),
-            Format::Elf => loader.load_elf_data(&mut file),
-            Format::Hex => loader.load_hex_data(&mut file),
-            Format::Idf(options) => loader.load_idf_data(&mut session, &mut file, options),
-        }?;
-
-        run_flash_download(
+        let ram_image = flash_program(
             &mut session,
             Path::new(&self.path),
-            &FlashOptions {
-                disable_progressbars: false,
-                disable_double_buffering: self.disable_double_buffering,
-                reset_halt: false,
-                log: None,
-                restore_unwritten: false,
-                flash_layout_output_path: None,
-                elf: None,
-                work_dir: None,
-                cargo_options: CargoOptions::default(),
-                probe_options: self.common,
-            },
-            loader,
+            format,
             self.chip_erase,
+            self.disable_double_buffering,
+            self.common,
         )?;
 
-        let rtt_config = rtt::RttConfig::default();
+        let rtt_config = rtt::RttConfig {
+            scan_region: rtt_scan_region,
+            ..Default::default()
+        };
 
         let memory_map = session.target().memory_map.clone();
 
         let mut core = session.core(0)?;
-        core.reset()?;
+        let exit_detector = if self.exit_on_completion {
+            // Set up the exit detection before the firmware starts, so that early panics are caught as well.
+            if ram_image.is_some() {
+                core.halt(Duration::from_millis(100))?;
+            } else {
+                core.reset_and_halt(Duration::from_millis(100))?;
+            }
+            let exit_detector = ExitDetector::new(&mut core, Path::new(&self.path))?;
+            match &ram_image {
+                Some(start) => start_ram_image(&mut core, start)?,
+                None => core.run()?,
+            }
+            Some(exit_detector)
+        } else {
+            match &ram_image {
+                Some(start) => start_ram_image(&mut core, start)?,
+                None => core.reset()?,
+            }
+            None
+        };
 
         let mut rtta = match rtt::attach_to_rtt(
             &mut core,
@@ -91,21 +157,200 @@ impl Cmd {
             }
         };
 
-        if let Some(rtta) = &mut rtta {
-            let mut stdout = std::io::stdout();
-            loop {
+        if rtta.is_none() && exit_detector.is_none() && self.can.is_none() && ppk2.is_none() {
+            return Ok(());
+        }
+
+        let mut trace = self.chrome_trace.as_ref().map(|_| ChromeTrace::new());
+        if let (Some(rtta), Some(_)) = (&mut rtta, &trace) {
+            rtta.record_defmt_frames();
+        }
+        let start = std::time::Instant::now();
+        let can = self
+            .can
+            .as_deref()
+            .map(|port| CanCapture::open(port, self.can_bitrate, start))
+            .transpose()?;
+        let power = ppk2.map(PowerCapture::start).transpose()?;
+        let mut power_csv = match &self.power_csv {
+            Some(path) => {
+                let mut file = File::create(path).with_context(|| {
+                    format!(
+                        "Failed to create the power measurement file {}",
+                        path.display()
+                    )
+                })?;
+                writeln!(file, "time,duration,current")?;
+                Some(file)
+            }
+            None => None,
+        };
+
+        let mut stdout = std::io::stdout();
+        let exit = loop {
+            if termination::requested() {
+                break None;
+            }
+
+            if let Some(rtta) = &mut rtta {
                 for (_ch, data) in rtta.poll_rtt_fallible(&mut core)? {
                     stdout.write_all(data.as_bytes())?;
                 }
+                if let Some(trace) = &mut trace {
+                    add_defmt_frames(trace, rtta, start.elapsed());
+                }
+            }
+
+            if let Some(can) = &can {
+                for frame in can.received() {
+                    writeln!(
+                        stdout,
+                        "[{:.6}] CAN {}",
+                        frame.time.as_secs_f64(),
+                        frame.frame
+                    )?;
+                    if let Some(trace) = &mut trace {
+                        trace.can_frame(&frame);
+                    }
+                }
+            }
+
+            if let Some(power) = &power {
+                for measurement in power.measurements() {
+                    let time = measurement.time.saturating_duration_since(start);
+                    if let Some(file) = &mut power_csv {
+                        writeln!(
+                            file,
+                            "{:.6},{:.6},{:e}",
+                            time.as_secs_f64(),
+                            measurement.duration.as_secs_f64(),
+                            measurement.current
+                        )?;
+                    }
+                    if let Some(trace) = &mut trace {
+                        trace.current(time, measurement.current);
+                    }
+                }
+            }
 
-                // Poll RTT with a frequency of 10 Hz
-                //
-                // If the polling frequency is too high,
-                // the USB connection to the probe can become unstable.
-                std::thread::sleep(Duration::from_millis(100));
+            if let Some(exit_detector) = &exit_detector {
+                let mut semihosting_output = String::new();
+                let exit = exit_detector.poll(&mut core, &mut semihosting_output)?;
+                stdout.write_all(semihosting_output.as_bytes())?;
+                if let Some(exit) = exit {
+                    // Drain any remaining RTT data, so that the last messages before the exit are not lost.
+                    if let Some(rtta) = &mut rtta {
+                        for (_ch, data) in rtta.poll_rtt_fallible(&mut core)? {
+                            stdout.write_all(data.as_bytes())?;
+                        }
+                        if let Some(trace) = &mut trace {
+                            add_defmt_frames(trace, rtta, start.elapsed());
+                        }
+                    }
+                    break Some(exit);
+                }
             }
+
+            // Poll RTT with a frequency of 10 Hz
+            //
+            // If the polling frequency is too high,
+            // the USB connection to the probe can become unstable.
+            std::thread::sleep(Duration::from_millis(100));
+        };
+
+        stdout.flush()?;
+        if let (Some(trace), Some(path)) = (&trace, &self.chrome_trace) {
+            trace.write(path)?;
         }
+        // Close the connection to the probe before exiting the process.
+        drop(core);
+        drop(session);
+
+        let Some(exit) = exit else {
+            return Ok(());
+        };
+        match exit {
+            FirmwareExit::Success => {
+                eprintln!("Firmware exited successfully.");
+                Ok(())
+            }
+            FirmwareExit::Failure(code) => {
+                eprintln!("Firmware exited with code {code}.");
+                std::process::exit(exit.exit_code());
+            }
+            FirmwareExit::Panic => {
+                eprintln!("Firmware panicked.");
+                std::process::exit(exit.exit_code());
+            }
+            FirmwareExit::HardFault => {
+                eprintln!("Firmware hit a HardFault.");
+                std::process::exit(exit.exit_code());
+            }
+        }
+    }
+}
+
+/// Load the program at `path` and download it to the target.
+///
+/// If the program is completely in RAM, it can not be started with a reset, so this returns how to start it.
+pub(crate) fn flash_program(
+    session: &mut Session,
+    path: &Path,
+    format: Format,
+    chip_erase: bool,
+    disable_double_buffering: bool,
+    probe_options: ProbeOptions,
+) -> anyhow::Result<Option<RamImageStart>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(FileDownloadError::IO(e)).context("Failed to open binary file."),
+    };
+
+    let mut loader = session.target().flash_loader();
+
+    match format {
+        Format::Bin(options) => loader.load_bin_data(&mut file, options),
+        Format::Elf(options) => loader.load_elf_data(&mut file, &options),
+        Format::Hex => loader.load_hex_data(&mut file),
+        Format::Idf(options) => loader.load_idf_data(session, &mut file, options),
+    }?;
+
+    let ram_image = loader.ram_image_start(session.target().cores[0].core_type);
+
+    run_flash_download(
+        session,
+        path,
+        &FlashOptions {
+            disable_progressbars: false,
+            disable_double_buffering,
+            reset_halt: false,
+            log: None,
+            restore_unwritten: false,
+            flash_layout_output_path: None,
+            elf: None,
+            work_dir: None,
+            provision: None,
+            provision_record: None,
+            allow_otp_write: false,
+            cargo_options: CargoOptions::default(),
+            probe_options,
+        },
+        loader,
+        chip_erase,
+    )?;
+
+    Ok(ram_image)
+}
 
-        Ok(())
+/// Add the defmt frames which were decoded since the last poll to the trace. Frames without a timestamp get the time
+/// they were received at.
+fn add_defmt_frames(trace: &mut ChromeTrace, rtta: &mut rtt::RttActiveTarget, received: Duration) {
+    for frame in rtta.take_defmt_frames() {
+        let time = frame
+            .timestamp
+            .as_deref()
+            .and_then(parse_defmt_timestamp)
+            .unwrap_or(received);
+        trace.defmt_frame(time, frame.level, &frame.message);
     }
 }
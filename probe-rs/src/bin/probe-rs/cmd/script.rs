@@ -0,0 +1,452 @@
+//! Run a debug scenario, described by a simple line based script, against the target.
+//!
+//! Every line of the script contains a single statement, and `#` starts a comment. Locations can either be
+//! an address (`0x2000_0000`), or the name of a symbol in the ELF file which was flashed last (or given with `--elf`).
+//!
+//! | Statement                               | Description                                                        |
+//! |-----------------------------------------|--------------------------------------------------------------------|
+//! | `flash <path>`                          | Flash an ELF file (relative to the script), and load its symbols.  |
+//! | `reset`                                 | Reset the core, and let it run.                                    |
+//! | `reset-halt`                            | Reset the core, and halt it at the first instruction.              |
+//! | `halt`                                  | Halt the core.                                                     |
+//! | `run`                                   | Resume the core.                                                   |
+//! | `break <location>`                      | Set a hardware breakpoint.                                         |
+//! | `clear <location>`                      | Clear a hardware breakpoint.                                       |
+//! | `wait-halt [timeout in ms]`             | Wait until the core halts, e.g. at a breakpoint (default: 1000ms). |
+//! | `sleep <ms>`                            | Wait for the given time.                                           |
+//! | `read <location>`                       | Print the value of a variable, or memory location.                |
+//! | `write <location> <value>`              | Write a value to a variable, or memory location.                   |
+//! | `expect <location> <==/!=/</>> <value>` | Check the value of a variable, or memory location.                 |
+//! | `expect-pc <location>`                  | Check that the core is halted at the given location.               |
+//!
+//! Variables are read and written with the size of the symbol, if it is 1, 2, 4 or 8 bytes, and as 32-bit words otherwise.
+//! A failing `expect` is reported, but does not stop the script. Any other error stops the script immediately.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use probe_rs::flashing::Format;
+use probe_rs::{Core, MemoryInterface};
+
+use crate::cmd::run::flash_program;
+use crate::util::common_options::ProbeOptions;
+use crate::util::{parse_u64, symbol_address};
+
+/// The timeout for `wait-halt`, if none is given.
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    pub(crate) common: ProbeOptions,
+
+    /// The path to the script to run
+    pub(crate) script: PathBuf,
+
+    /// The ELF file to load the symbols from, if the script does not flash one
+    #[clap(long)]
+    pub(crate) elf: Option<PathBuf>,
+
+    /// The core to run the script on
+    #[clap(long, default_value = "0")]
+    pub(crate) core: usize,
+
+    /// Whether to erase the entire chip before downloading
+    #[clap(long)]
+    pub(crate) chip_erase: bool,
+
+    /// Disable double-buffering when downloading flash.  If downloading times out, try this option.
+    #[clap(long = "disable-double-buffering")]
+    pub(crate) disable_double_buffering: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Location {
+    Address(u64),
+    Symbol(String),
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Address(address) => write!(f, "{address:#010x}"),
+            Location::Symbol(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl Comparison {
+    fn holds(self, actual: u64, expected: u64) -> bool {
+        match self {
+            Comparison::Equal => actual == expected,
+            Comparison::NotEqual => actual != expected,
+            Comparison::Less => actual < expected,
+            Comparison::Greater => actual > expected,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Statement {
+    Flash(PathBuf),
+    Reset,
+    ResetHalt,
+    Halt,
+    Run,
+    Break(Location),
+    Clear(Location),
+    WaitHalt(Duration),
+    Sleep(Duration),
+    Read(Location),
+    Write(Location, u64),
+    Expect(Location, Comparison, u64),
+    ExpectPc(Location),
+}
+
+/// A statement, together with the line of the script it was read from.
+#[derive(Debug)]
+struct Line {
+    number: usize,
+    text: String,
+    statement: Statement,
+}
+
+/// The address and size of a symbol in the ELF file.
+#[derive(Debug, Clone, Copy)]
+struct Symbol {
+    address: u64,
+    size: u64,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let source = fs::read_to_string(&self.script)
+            .with_context(|| format!("Failed to read script {}", self.script.display()))?;
+        let base_dir = self
+            .script
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let lines = parse_script(&source, &base_dir)?;
+
+        let mut session = self.common.simple_attach()?;
+        let mut symbols = match &self.elf {
+            Some(elf) => load_symbols(elf)?,
+            None => HashMap::new(),
+        };
+
+        let mut passed = 0;
+        let mut failed = 0;
+
+        for line in &lines {
+            match &line.statement {
+                Statement::Flash(path) => {
+                    println!("line {}: {}", line.number, line.text);
+                    flash_program(
+                        &mut session,
+                        path,
+                        Format::default(),
+                        self.chip_erase,
+                        self.disable_double_buffering,
+                        self.common.clone(),
+                    )?;
+                    symbols = load_symbols(path)?;
+                }
+                Statement::Expect(location, comparison, expected) => {
+                    let mut core = session.core(self.core)?;
+                    let actual = read_location(&mut core, &symbols, location)?;
+                    if comparison.holds(actual, *expected) {
+                        println!("line {}: {} ... ok", line.number, line.text);
+                        passed += 1;
+                    } else {
+                        println!(
+                            "line {}: {} ... FAILED (value is {:#x})",
+                            line.number, line.text, actual
+                        );
+                        failed += 1;
+                    }
+                }
+                Statement::ExpectPc(location) => {
+                    let mut core = session.core(self.core)?;
+                    let expected = resolve(&symbols, location)?.address;
+                    let program_counter: Option<u64> = if core.core_halted()? {
+                        Some(core.read_core_reg(core.program_counter())?)
+                    } else {
+                        None
+                    };
+                    match program_counter {
+                        Some(program_counter) if program_counter == expected => {
+                            println!("line {}: {} ... ok", line.number, line.text);
+                            passed += 1;
+                        }
+                        Some(program_counter) => {
+                            println!(
+                                "line {}: {} ... FAILED (pc is {:#010x})",
+                                line.number, line.text, program_counter
+                            );
+                            failed += 1;
+                        }
+                        None => {
+                            println!(
+                                "line {}: {} ... FAILED (core is running)",
+                                line.number, line.text
+                            );
+                            failed += 1;
+                        }
+                    }
+                }
+                statement => {
+                    let mut core = session.core(self.core)?;
+                    execute(&mut core, &symbols, statement)
+                        .with_context(|| format!("line {}: {}", line.number, line.text))?;
+                    if let Statement::Read(location) = statement {
+                        let value = read_location(&mut core, &symbols, location)?;
+                        println!("line {}: {} = {:#x}", line.number, location, value);
+                    } else {
+                        println!("line {}: {}", line.number, line.text);
+                    }
+                }
+            }
+        }
+
+        println!(
+            "\nscript result: {}. {} passed; {} failed",
+            if failed > 0 { "FAILED" } else { "ok" },
+            passed,
+            failed
+        );
+
+        if failed > 0 {
+            Err(anyhow!("{} of {} checks failed.", failed, passed + failed))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Execute a statement which does not produce a result.
+fn execute(
+    core: &mut Core,
+    symbols: &HashMap<String, Symbol>,
+    statement: &Statement,
+) -> Result<()> {
+    match statement {
+        Statement::Reset => core.reset()?,
+        Statement::ResetHalt => {
+            core.reset_and_halt(Duration::from_millis(100))?;
+        }
+        Statement::Halt => {
+            core.halt(Duration::from_millis(100))?;
+        }
+        Statement::Run => core.run()?,
+        Statement::Break(location) => {
+            core.set_hw_breakpoint(resolve(symbols, location)?.address)?
+        }
+        Statement::Clear(location) => {
+            core.clear_hw_breakpoint(resolve(symbols, location)?.address)?
+        }
+        Statement::WaitHalt(timeout) => core.wait_for_core_halted(*timeout)?,
+        Statement::Sleep(duration) => std::thread::sleep(*duration),
+        Statement::Read(_) => {}
+        Statement::Write(location, value) => {
+            let symbol = resolve(symbols, location)?;
+            match symbol.size {
+                1 => core.write_word_8(symbol.address, *value as u8)?,
+                2 => core.write_8(symbol.address, &(*value as u16).to_le_bytes())?,
+                8 => core.write_word_64(symbol.address, *value)?,
+                _ => core.write_word_32(symbol.address, *value as u32)?,
+            }
+        }
+        Statement::Flash(_) | Statement::Expect(..) | Statement::ExpectPc(_) => {
+            unreachable!("handled by the caller")
+        }
+    }
+    Ok(())
+}
+
+fn read_location(
+    core: &mut Core,
+    symbols: &HashMap<String, Symbol>,
+    location: &Location,
+) -> Result<u64> {
+    let symbol = resolve(symbols, location)?;
+    Ok(match symbol.size {
+        1 => core.read_word_8(symbol.address)? as u64,
+        2 => {
+            let mut bytes = [0u8; 2];
+            core.read_8(symbol.address, &mut bytes)?;
+            u16::from_le_bytes(bytes) as u64
+        }
+        8 => core.read_word_64(symbol.address)?,
+        _ => core.read_word_32(symbol.address)? as u64,
+    })
+}
+
+fn resolve(symbols: &HashMap<String, Symbol>, location: &Location) -> Result<Symbol> {
+    match location {
+        Location::Address(address) => Ok(Symbol {
+            address: *address,
+            size: 4,
+        }),
+        Location::Symbol(name) => symbols.get(name).copied().ok_or_else(|| {
+            anyhow!(
+                "Symbol '{}' not found. Flash an ELF file first, or use --elf.",
+                name
+            )
+        }),
+    }
+}
+
+/// Load the addresses and sizes of all functions and variables in the ELF file.
+fn load_symbols(elf_file: &Path) -> Result<HashMap<String, Symbol>> {
+    let buffer = fs::read(elf_file)
+        .with_context(|| format!("Failed to read ELF file {}", elf_file.display()))?;
+    let binary = goblin::elf::Elf::parse(buffer.as_slice())?;
+
+    Ok(binary
+        .syms
+        .iter()
+        .filter(|sym| sym.is_function() || sym.st_type() == goblin::elf::sym::STT_OBJECT)
+        .filter_map(|sym| {
+            let name = binary.strtab.get_at(sym.st_name)?;
+            let address = symbol_address(&sym);
+            Some((
+                name.to_string(),
+                Symbol {
+                    address,
+                    size: sym.st_size,
+                },
+            ))
+        })
+        .collect())
+}
+
+fn parse_script(source: &str, base_dir: &Path) -> Result<Vec<Line>> {
+    let mut lines = Vec::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let text = text.split('#').next().unwrap_or_default().trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(text, base_dir)
+            .with_context(|| format!("Invalid statement on line {}: {}", index + 1, text))?;
+        lines.push(Line {
+            number: index + 1,
+            text: text.to_string(),
+            statement,
+        });
+    }
+
+    Ok(lines)
+}
+
+fn parse_statement(text: &str, base_dir: &Path) -> Result<Statement> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    let statement = match words.as_slice() {
+        ["flash", path] => Statement::Flash(base_dir.join(path)),
+        ["reset"] => Statement::Reset,
+        ["reset-halt"] => Statement::ResetHalt,
+        ["halt"] => Statement::Halt,
+        ["run"] => Statement::Run,
+        ["break", location] => Statement::Break(parse_location(location)),
+        ["clear", location] => Statement::Clear(parse_location(location)),
+        ["wait-halt"] => Statement::WaitHalt(DEFAULT_WAIT_TIMEOUT),
+        ["wait-halt", timeout] => Statement::WaitHalt(parse_duration(timeout)?),
+        ["sleep", duration] => Statement::Sleep(parse_duration(duration)?),
+        ["read", location] => Statement::Read(parse_location(location)),
+        ["write", location, value] => Statement::Write(parse_location(location), parse_u64(value)?),
+        ["expect", location, comparison, value] => {
+            let comparison = match *comparison {
+                "==" => Comparison::Equal,
+                "!=" => Comparison::NotEqual,
+                "<" => Comparison::Less,
+                ">" => Comparison::Greater,
+                other => bail!("Unknown comparison '{}'", other),
+            };
+            Statement::Expect(parse_location(location), comparison, parse_u64(value)?)
+        }
+        ["expect-pc", location] => Statement::ExpectPc(parse_location(location)),
+        _ => bail!("Unknown statement"),
+    };
+
+    Ok(statement)
+}
+
+fn parse_location(text: &str) -> Location {
+    match parse_u64(text) {
+        Ok(address) => Location::Address(address),
+        Err(_) => Location::Symbol(text.to_string()),
+    }
+}
+
+fn parse_duration(text: &str) -> Result<Duration> {
+    Ok(Duration::from_millis(parse_u64(text)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_statements() {
+        let script = "
+            # Flash the firmware, and stop in main
+            flash firmware.elf
+            reset-halt
+            break main   # Comments can follow a statement
+            run
+            wait-halt 500
+            expect-pc main
+            write 0x2000_0000 0x10
+            expect COUNTER == 5
+        ";
+
+        let lines = parse_script(script, Path::new("scripts")).unwrap();
+        let statements: Vec<_> = lines.iter().map(|line| line.statement.clone()).collect();
+
+        assert_eq!(
+            statements,
+            vec![
+                Statement::Flash(PathBuf::from("scripts/firmware.elf")),
+                Statement::ResetHalt,
+                Statement::Break(Location::Symbol("main".to_string())),
+                Statement::Run,
+                Statement::WaitHalt(Duration::from_millis(500)),
+                Statement::ExpectPc(Location::Symbol("main".to_string())),
+                Statement::Write(Location::Address(0x2000_0000), 0x10),
+                Statement::Expect(
+                    Location::Symbol("COUNTER".to_string()),
+                    Comparison::Equal,
+                    5
+                ),
+            ]
+        );
+        assert_eq!(lines[2].number, 5);
+        assert_eq!(lines[2].text, "break main");
+    }
+
+    #[test]
+    fn parse_invalid_statement() {
+        let error = parse_script("reset\nexpect COUNTER >= 5", Path::new("")).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Invalid statement on line 2: expect COUNTER >= 5"
+        );
+    }
+}
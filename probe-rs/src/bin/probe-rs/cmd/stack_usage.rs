@@ -0,0 +1,90 @@
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use crate::util::common_options::ProbeOptions;
+use crate::util::{parse_range, stack_usage};
+use crate::CoreOptions;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    shared: CoreOptions,
+
+    #[clap(flatten)]
+    common: ProbeOptions,
+
+    /// The ELF file of the program, which is used to find the main stack
+    #[clap(long)]
+    elf: Option<PathBuf>,
+
+    /// A stack as `<start>..<end>`, e.g. the stack of an RTOS task. Can be given more than once, and replaces the main stack.
+    #[clap(long = "range", value_parser = parse_range)]
+    ranges: Vec<Range<u64>>,
+
+    /// Reset the core, paint the stacks and let the program run. Measure the usage with a later call without this flag.
+    #[clap(long)]
+    paint: bool,
+
+    /// Print the measured usage as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let stacks = match (&self.elf, self.ranges.is_empty()) {
+            (_, false) => self.ranges.clone(),
+            (Some(elf), true) => vec![stack_usage::main_stack(elf)?],
+            (None, true) => bail!(
+                "Either the ELF file has to be given with `--elf`, or the stacks with `--range`"
+            ),
+        };
+
+        let mut session = self.common.simple_attach()?;
+        let mut core = session.core(self.shared.core)?;
+
+        if self.paint {
+            core.reset_and_halt(Duration::from_millis(100))?;
+            for stack in &stacks {
+                stack_usage::paint(&mut core, stack.clone())?;
+                println!(
+                    "Painted the stack at {:#010x}..{:#010x}",
+                    stack.start, stack.end
+                );
+            }
+            core.run()?;
+            return Ok(());
+        }
+
+        let usages = stacks
+            .into_iter()
+            .map(|stack| stack_usage::measure(&mut core, stack))
+            .collect::<Result<Vec<_>>>()?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&usages)?);
+            return Ok(());
+        }
+
+        for usage in &usages {
+            println!(
+                "{:#010x}..{:#010x}: {} of {} bytes used ({:.1}%){}",
+                usage.stack.start,
+                usage.stack.end,
+                usage.used,
+                usage.size(),
+                usage.used as f64 * 100.0 / usage.size() as f64,
+                if usage.overflowed {
+                    ", the stack most likely overflowed"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! Resolve addresses to symbols and source locations, and the other way around, using the debug information of an ELF file.
+//!
+//! Every query is one of:
+//! - an address (`0x0800_0400`), which is resolved to the function and the source location of the instruction,
+//! - a source location (`src/main.rs:12`), which is resolved to the address of the first instruction on the line,
+//! - a function name (`main`), which is resolved to the address ranges of all functions with this name.
+//!
+//! This is useful to post-process raw addresses, e.g. from a trace or a fault report. If no query is given,
+//! the queries are read from stdin, one per line.
+
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use probe_rs::debug::{ColumnType, DebugInfo, SourceLocation};
+
+use crate::util::parse_u64;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    /// The ELF file with the debug information
+    elf: PathBuf,
+
+    /// The addresses, source locations (`<file>:<line>`) or function names to look up
+    queries: Vec<String>,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let debug_info = DebugInfo::from_file(&self.elf).with_context(|| {
+            format!(
+                "Failed to read the debug information of {}",
+                self.elf.display()
+            )
+        })?;
+
+        if self.queries.is_empty() {
+            for line in std::io::stdin().lock().lines() {
+                let line = line?;
+                let query = line.trim();
+                if !query.is_empty() {
+                    println!("{}", resolve(&debug_info, query)?);
+                }
+            }
+        } else {
+            for query in &self.queries {
+                println!("{}", resolve(&debug_info, query)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolve a single query, and describe the result.
+fn resolve(debug_info: &DebugInfo, query: &str) -> Result<String> {
+    if query.starts_with(|c: char| c.is_ascii_digit()) {
+        let address = parse_u64(query).with_context(|| format!("Invalid address '{query}'"))?;
+        let resolved = debug_info.resolve_address(address)?;
+
+        let function = resolved.function.as_deref().unwrap_or("<unknown>");
+        return Ok(match &resolved.source_location {
+            Some(location) => format!(
+                "{address:#010x}: {function} at {}",
+                format_location(location)
+            ),
+            None => format!("{address:#010x}: {function}"),
+        });
+    }
+
+    if let Some((file, line)) = query
+        .rsplit_once(':')
+        .and_then(|(file, line)| Some((file, line.parse::<u64>().ok()?)))
+    {
+        return Ok(
+            match debug_info.get_breakpoint_location(Path::new(file), line, None) {
+                Ok(breakpoint) => format!(
+                    "{query}: {:#010x} ({})",
+                    breakpoint.address,
+                    format_location(&breakpoint.source_location)
+                ),
+                Err(_) => format!("{query}: no instructions found"),
+            },
+        );
+    }
+
+    let ranges = debug_info.function_ranges(query)?;
+    if ranges.is_empty() {
+        return Ok(format!("{query}: no function found"));
+    }
+
+    Ok(ranges
+        .iter()
+        .map(|range| {
+            let location = debug_info
+                .get_source_location(range.start)
+                .map(|location| format!(" ({})", format_location(&location)))
+                .unwrap_or_default();
+            format!(
+                "{query}: {:#010x}..{:#010x}{location}",
+                range.start, range.end
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Format a source location as `<path>:<line>:<column>`.
+fn format_location(location: &SourceLocation) -> String {
+    let path = match (&location.directory, &location.file) {
+        (Some(directory), Some(file)) => directory.join(file).display().to_string(),
+        (None, Some(file)) => file.clone(),
+        _ => "<unknown file>".to_string(),
+    };
+
+    match (location.line, location.column) {
+        (Some(line), Some(ColumnType::Column(column))) => {
+            format!("{path}:{line}:{column}")
+        }
+        (Some(line), _) => format!("{path}:{line}"),
+        (None, _) => path,
+    }
+}
@@ -0,0 +1,127 @@
+//! Record the scheduling of an RTOS with SEGGER SystemView over RTT, without the SystemView application.
+//!
+//! The recording is started through the `SysView` down channel, and read from the `SysView` up channel until it is
+//! stopped. Then it is summarized, and optionally written as a timeline for Perfetto.
+
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use probe_rs::rtt::{Rtt, ScanRegion};
+
+use crate::util::chrome_trace::ChromeTrace;
+use crate::util::rtt::RttActiveTarget;
+use crate::util::systemview::{
+    SystemViewDecoder, Timeline, CHANNEL_NAME, COMMAND_START, COMMAND_STOP,
+};
+use crate::util::{common_options::ProbeOptions, parse_u64, termination};
+use crate::CoreOptions;
+
+/// The time between two reads of the up channel, when it was empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    shared: CoreOptions,
+
+    #[clap(flatten)]
+    common: ProbeOptions,
+
+    /// The ELF file of the firmware, to find the RTT control block by its symbol instead of scanning the RAM.
+    #[clap(long)]
+    elf: Option<PathBuf>,
+
+    /// Stop recording after this many milliseconds, instead of recording until probe-rs is stopped.
+    #[clap(long, value_parser = parse_u64)]
+    duration_ms: Option<u64>,
+
+    /// Write the tasks, interrupts, markers and log messages as a timeline in the Chrome trace event format to this
+    /// file, which can be opened with Perfetto.
+    #[clap(long, value_name = "PATH")]
+    chrome_trace: Option<PathBuf>,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let mut session = self.common.simple_attach()?;
+        let memory_map = session.target().memory_map.clone();
+        let mut core = session.core(self.shared.core)?;
+
+        let scan_region = match &self.elf {
+            Some(elf) => {
+                let mut file =
+                    File::open(elf).with_context(|| format!("Failed to open {}", elf.display()))?;
+                match RttActiveTarget::get_rtt_symbol(&mut file) {
+                    Some(address) => ScanRegion::Exact(address as u32),
+                    None => ScanRegion::Ram,
+                }
+            }
+            None => ScanRegion::Ram,
+        };
+        let mut rtt = Rtt::attach_region(&mut core, &memory_map, &scan_region)
+            .context("Failed to attach to RTT")?;
+
+        let up_channel = rtt
+            .up_channels()
+            .iter()
+            .find(|channel| channel.name() == Some(CHANNEL_NAME))
+            .map(|channel| channel.number())
+            .and_then(|number| rtt.up_channels().take(number))
+            .ok_or_else(|| anyhow!("The target has no RTT up channel '{CHANNEL_NAME}'"))?;
+        let down_channel = rtt
+            .down_channels()
+            .iter()
+            .find(|channel| channel.name() == Some(CHANNEL_NAME))
+            .map(|channel| channel.number())
+            .and_then(|number| rtt.down_channels().take(number))
+            .ok_or_else(|| anyhow!("The target has no RTT down channel '{CHANNEL_NAME}'"))?;
+
+        down_channel.write(&mut core, &[COMMAND_START])?;
+        println!("Recording, stop with Ctrl-C");
+
+        let duration = self.duration_ms.map(Duration::from_millis);
+        let start = Instant::now();
+        let mut decoder = SystemViewDecoder::default();
+        let mut events = Vec::new();
+        let mut buffer = vec![0; up_channel.buffer_size()];
+        'recording: loop {
+            let count = up_channel.read(&mut core, &mut buffer)?;
+            decoder.received(&buffer[..count]);
+            loop {
+                match decoder.decode() {
+                    Ok(Some(event)) => events.push(event),
+                    Ok(None) => break,
+                    Err(error) => {
+                        // The stream is only synchronized at the start of a recording.
+                        log::warn!("Stopped the recording at a malformed packet: {error}");
+                        break 'recording;
+                    }
+                }
+            }
+
+            if duration.map_or(false, |duration| start.elapsed() > duration)
+                || termination::requested()
+            {
+                break;
+            }
+            if count == 0 {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        down_channel.write(&mut core, &[COMMAND_STOP])?;
+
+        let timeline = Timeline::new(&events)?;
+        print!("{}", timeline.summary());
+
+        if let Some(path) = &self.chrome_trace {
+            let mut trace = ChromeTrace::new();
+            trace.systemview(&timeline);
+            trace.write(path)?;
+        }
+
+        Ok(())
+    }
+}
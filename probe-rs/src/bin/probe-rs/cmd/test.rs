@@ -0,0 +1,469 @@
+//! Run the tests in a test binary on the target, one test at a time.
+//!
+//! The firmware has to implement the `embedded-test` protocol, which uses semihosting to talk to the host:
+//! - At startup, the firmware requests the command line with `SYS_GET_CMDLINE`.
+//! - For the `list` command, it writes a JSON description of all the tests to stdout (`SYS_WRITE` / `SYS_WRITE0`),
+//!   and then exits.
+//! - For the `run <name>` command, it runs the named test, and then exits with `SYS_EXIT` (or panics).
+//!
+//! The target is reset before every test, so that each test starts from a clean state.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use probe_rs::flashing::Format;
+use probe_rs::{Core, MemoryInterface};
+use serde::Deserialize;
+
+use crate::cmd::run::flash_program;
+use crate::util::common_options::ProbeOptions;
+use crate::util::exit_status::{ExitDetector, FirmwareExit};
+use crate::util::semihosting::{
+    handle_console, SemihostingCall, SEMIHOSTING_ERROR, SYS_GET_CMDLINE,
+};
+use crate::util::termination;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    #[clap(flatten)]
+    pub(crate) common: ProbeOptions,
+
+    /// The path to the ELF file of the test binary
+    pub(crate) path: String,
+
+    /// Only run the tests whose name contains this string
+    pub(crate) filter: Option<String>,
+
+    /// Only run the test whose name exactly matches the filter
+    #[clap(long)]
+    pub(crate) exact: bool,
+
+    /// Also run the tests which are marked as ignored
+    #[clap(long)]
+    pub(crate) include_ignored: bool,
+
+    /// List all the tests in the binary, instead of running them
+    #[clap(long)]
+    pub(crate) list: bool,
+
+    /// The maximum time a single test is allowed to run, in seconds, if the test doesn't set its own timeout
+    #[clap(long, default_value = "60")]
+    pub(crate) timeout: u64,
+
+    /// Write the test results in JUnit XML format to the given file
+    #[clap(long)]
+    pub(crate) junit: Option<PathBuf>,
+
+    /// Whether to erase the entire chip before downloading
+    #[clap(long)]
+    pub(crate) chip_erase: bool,
+
+    /// Disable double-buffering when downloading flash.  If downloading times out, try this option.
+    #[clap(long = "disable-double-buffering")]
+    pub(crate) disable_double_buffering: bool,
+}
+
+/// The list of tests, as reported by the firmware for the `list` command.
+#[derive(Debug, Deserialize)]
+struct TestList {
+    tests: Vec<TestDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TestDefinition {
+    name: String,
+    #[serde(default)]
+    should_panic: bool,
+    #[serde(default)]
+    ignored: bool,
+    /// The maximum time the test is allowed to run in seconds, as set with `#[timeout(..)]`.
+    #[serde(default)]
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TestOutcome {
+    Passed,
+    Failed(String),
+    Ignored,
+}
+
+#[derive(Debug)]
+struct TestResult {
+    name: String,
+    outcome: TestOutcome,
+    duration: Duration,
+}
+
+impl Cmd {
+    pub fn run(self) -> Result<()> {
+        let mut session = self.common.simple_attach()?;
+        let path = PathBuf::from(&self.path);
+
+        flash_program(
+            &mut session,
+            &path,
+            Format::default(),
+            self.chip_erase,
+            self.disable_double_buffering,
+            self.common,
+        )?;
+
+        let mut core = session.core(0)?;
+        core.reset_and_halt(Duration::from_millis(100))?;
+        let exit_detector = ExitDetector::new(&mut core, &path)?;
+        let timeout = Duration::from_secs(self.timeout);
+
+        let (exit, output) = run_firmware(&mut core, &exit_detector, "list", timeout)?;
+        if exit != Some(FirmwareExit::Success) {
+            return Err(anyhow!(
+                "Failed to list the tests in the firmware ({:?}). Does it use embedded-test?",
+                exit
+            ));
+        }
+        let test_list: TestList = serde_json::from_str(output.trim())
+            .with_context(|| format!("Invalid test list received from the firmware: {output}"))?;
+
+        if self.list {
+            for test in &test_list.tests {
+                println!("{}: test", test.name);
+            }
+            return Ok(());
+        }
+
+        let total = test_list.tests.len();
+        let selected: Vec<_> = test_list
+            .tests
+            .into_iter()
+            .filter(|test| match &self.filter {
+                Some(filter) if self.exact => &test.name == filter,
+                Some(filter) => test.name.contains(filter.as_str()),
+                None => true,
+            })
+            .collect();
+        let filtered_out = total - selected.len();
+
+        println!();
+        println!("running {} tests", selected.len());
+
+        let start = Instant::now();
+        let mut results = Vec::with_capacity(selected.len());
+        for test in selected {
+            let result = if test.ignored && !self.include_ignored {
+                TestResult {
+                    name: test.name,
+                    outcome: TestOutcome::Ignored,
+                    duration: Duration::ZERO,
+                }
+            } else {
+                run_test(&mut core, &exit_detector, test, timeout)?
+            };
+
+            println!(
+                "test {} ... {}",
+                result.name,
+                match result.outcome {
+                    TestOutcome::Passed => "ok",
+                    TestOutcome::Failed(_) => "FAILED",
+                    TestOutcome::Ignored => "ignored",
+                }
+            );
+            results.push(result);
+        }
+        let elapsed = start.elapsed();
+
+        print!("{}", libtest_summary(&results, filtered_out, elapsed));
+
+        if let Some(junit) = &self.junit {
+            let suite_name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| self.path.clone());
+            fs::write(junit, junit_report(&suite_name, &results, elapsed))
+                .with_context(|| format!("Failed to write JUnit report to {}", junit.display()))?;
+        }
+
+        let failed = results
+            .iter()
+            .filter(|result| matches!(result.outcome, TestOutcome::Failed(_)))
+            .count();
+        if failed > 0 {
+            Err(anyhow!("{} of {} tests failed.", failed, results.len()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn run_test(
+    core: &mut Core,
+    exit_detector: &ExitDetector,
+    test: TestDefinition,
+    default_timeout: Duration,
+) -> Result<TestResult> {
+    let timeout = test.timeout.map_or(default_timeout, Duration::from_secs);
+    let start = Instant::now();
+    let (exit, output) = run_firmware(core, exit_detector, &format!("run {}", test.name), timeout)?;
+    let duration = start.elapsed();
+
+    for line in output.lines() {
+        tracing::info!("{}: {}", test.name, line);
+    }
+
+    let outcome = match (exit, test.should_panic) {
+        (Some(FirmwareExit::Success), false) | (Some(FirmwareExit::Panic), true) => {
+            TestOutcome::Passed
+        }
+        (Some(FirmwareExit::Success), true) => {
+            TestOutcome::Failed("test did not panic as expected".to_string())
+        }
+        (Some(FirmwareExit::Panic), false) => TestOutcome::Failed("test panicked".to_string()),
+        (Some(FirmwareExit::HardFault), _) => {
+            TestOutcome::Failed("test hit a HardFault".to_string())
+        }
+        (Some(FirmwareExit::Failure(code)), _) => {
+            TestOutcome::Failed(format!("test exited with code {code}"))
+        }
+        (None, _) => TestOutcome::Failed(format!("test timed out after {}s", timeout.as_secs())),
+    };
+
+    Ok(TestResult {
+        name: test.name,
+        outcome,
+        duration,
+    })
+}
+
+/// Reset the target and run the firmware with the given command line, until it exits or the timeout expires.
+///
+/// Returns how the firmware exited (`None` if it timed out), and everything it wrote to stdout.
+fn run_firmware(
+    core: &mut Core,
+    exit_detector: &ExitDetector,
+    command_line: &str,
+    timeout: Duration,
+) -> Result<(Option<FirmwareExit>, String)> {
+    let mut output = String::new();
+
+    core.reset_and_halt(Duration::from_millis(100))?;
+    core.run()?;
+
+    let start = Instant::now();
+    loop {
+        if start.elapsed() > timeout {
+            core.halt(Duration::from_millis(100))?;
+            return Ok((None, output));
+        }
+        if termination::requested() {
+            core.halt(Duration::from_millis(100))?;
+            anyhow::bail!("The test run was interrupted");
+        }
+
+        if core.core_halted()? {
+            if let Some(call) = SemihostingCall::pending(core)? {
+                if let Some(return_value) =
+                    handle_semihosting(core, &call, command_line, &mut output)?
+                {
+                    call.complete(core, return_value)?;
+                    core.run()?;
+                    continue;
+                }
+            }
+
+            if let Some(exit) = exit_detector.poll(core, &mut output)? {
+                return Ok((Some(exit), output));
+            }
+        }
+
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Handle the semihosting calls used by the test protocol.
+///
+/// Returns the return value of the call, or `None` if the call was not handled.
+fn handle_semihosting(
+    core: &mut Core,
+    call: &SemihostingCall,
+    command_line: &str,
+    output: &mut String,
+) -> Result<Option<u32>> {
+    let return_value = match call.operation {
+        SYS_GET_CMDLINE => {
+            let buffer = call.parameter_word(core, 0)?;
+            let length = call.parameter_word(core, 1)?;
+
+            let mut data = command_line.as_bytes().to_vec();
+            data.push(0);
+            if data.len() > length as usize {
+                SEMIHOSTING_ERROR
+            } else {
+                core.write_8(buffer as u64, &data)?;
+                core.write_word_32(call.parameter as u64 + 4, command_line.len() as u32)?;
+                0
+            }
+        }
+        _ => return handle_console(core, call, output),
+    };
+
+    Ok(Some(return_value))
+}
+
+/// Format the summary of a test run, in the same format as the Rust test harness (libtest).
+fn libtest_summary(results: &[TestResult], filtered_out: usize, elapsed: Duration) -> String {
+    let count =
+        |f: fn(&TestOutcome) -> bool| results.iter().filter(|result| f(&result.outcome)).count();
+    let passed = count(|outcome| *outcome == TestOutcome::Passed);
+    let failed = count(|outcome| matches!(outcome, TestOutcome::Failed(_)));
+    let ignored = count(|outcome| *outcome == TestOutcome::Ignored);
+
+    let mut summary = String::new();
+    if failed > 0 {
+        summary.push_str("\nfailures:\n");
+        for result in results {
+            if let TestOutcome::Failed(reason) = &result.outcome {
+                let _ = writeln!(summary, "    {}: {}", result.name, reason);
+            }
+        }
+    }
+
+    let _ = writeln!(
+        summary,
+        "\ntest result: {}. {} passed; {} failed; {} ignored; 0 measured; {} filtered out; finished in {:.2}s\n",
+        if failed > 0 { "FAILED" } else { "ok" },
+        passed,
+        failed,
+        ignored,
+        filtered_out,
+        elapsed.as_secs_f64()
+    );
+    summary
+}
+
+/// Format the results of a test run as a JUnit XML report.
+fn junit_report(suite_name: &str, results: &[TestResult], elapsed: Duration) -> String {
+    let failures = results
+        .iter()
+        .filter(|result| matches!(result.outcome, TestOutcome::Failed(_)))
+        .count();
+    let skipped = results
+        .iter()
+        .filter(|result| result.outcome == TestOutcome::Ignored)
+        .count();
+
+    let mut report = String::new();
+    let _ = writeln!(report, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(report, "<testsuites>");
+    let _ = writeln!(
+        report,
+        r#"  <testsuite name="{}" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+        xml_escape(suite_name),
+        results.len(),
+        failures,
+        skipped,
+        elapsed.as_secs_f64()
+    );
+    for result in results {
+        let _ = write!(
+            report,
+            r#"    <testcase name="{}" classname="{}" time="{:.3}""#,
+            xml_escape(&result.name),
+            xml_escape(suite_name),
+            result.duration.as_secs_f64()
+        );
+        match &result.outcome {
+            TestOutcome::Passed => {
+                let _ = writeln!(report, "/>");
+            }
+            TestOutcome::Failed(reason) => {
+                let _ = writeln!(report, ">");
+                let _ = writeln!(
+                    report,
+                    r#"      <failure message="{}"/>"#,
+                    xml_escape(reason)
+                );
+                let _ = writeln!(report, "    </testcase>");
+            }
+            TestOutcome::Ignored => {
+                let _ = writeln!(report, ">");
+                let _ = writeln!(report, "      <skipped/>");
+                let _ = writeln!(report, "    </testcase>");
+            }
+        }
+    }
+    let _ = writeln!(report, "  </testsuite>");
+    let _ = writeln!(report, "</testsuites>");
+    report
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results() -> Vec<TestResult> {
+        vec![
+            TestResult {
+                name: "passing".to_string(),
+                outcome: TestOutcome::Passed,
+                duration: Duration::from_millis(100),
+            },
+            TestResult {
+                name: "failing<1>".to_string(),
+                outcome: TestOutcome::Failed("test panicked".to_string()),
+                duration: Duration::from_millis(200),
+            },
+            TestResult {
+                name: "ignored".to_string(),
+                outcome: TestOutcome::Ignored,
+                duration: Duration::ZERO,
+            },
+        ]
+    }
+
+    #[test]
+    fn parse_test_list() {
+        let list: TestList = serde_json::from_str(
+            r#"{"version":1,"tests":[{"name":"a","should_panic":true,"ignored":false,"timeout":5},{"name":"b"}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(list.tests.len(), 2);
+        assert!(list.tests[0].should_panic);
+        assert_eq!(list.tests[0].timeout, Some(5));
+        assert!(!list.tests[1].ignored);
+        assert_eq!(list.tests[1].timeout, None);
+    }
+
+    #[test]
+    fn summary_counts_outcomes() {
+        let summary = libtest_summary(&results(), 2, Duration::from_secs(1));
+
+        assert!(summary.contains("    failing<1>: test panicked"));
+        assert!(summary.contains(
+            "test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 2 filtered out; finished in 1.00s"
+        ));
+    }
+
+    #[test]
+    fn junit_report_is_escaped() {
+        let report = junit_report("firmware", &results(), Duration::from_secs(1));
+
+        assert!(report.contains(
+            r#"<testsuite name="firmware" tests="3" failures="1" skipped="1" time="1.000">"#
+        ));
+        assert!(report.contains(r#"<testcase name="failing&lt;1&gt;" classname="firmware""#));
+        assert!(report.contains(r#"<failure message="test panicked"/>"#));
+        assert!(report.contains("<skipped/>"));
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::bail;
+use colored::Colorize;
+use probe_rs::flashing::{test_flash_algorithm, AlgorithmTestOptions};
+
+use crate::util::common_options::ProbeOptions;
+use crate::util::parse_u64;
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    /// The name of the flash algorithm to test. By default, the algorithm which flashes the address is tested.
+    #[clap(long)]
+    algorithm: Option<String>,
+    /// An address in the sector to test. By default, the last sector of the flash algorithm is tested.
+    #[clap(long, value_parser = parse_u64)]
+    address: Option<u64>,
+    /// Leave the test pattern in the sector, instead of restoring its original contents.
+    #[clap(long)]
+    no_restore: bool,
+    #[clap(flatten)]
+    common: ProbeOptions,
+}
+
+impl Cmd {
+    pub fn run(self) -> anyhow::Result<()> {
+        let mut session = self.common.simple_attach()?;
+
+        let mut options = AlgorithmTestOptions::default();
+        options.algorithm = self.algorithm;
+        options.address = self.address;
+        options.restore = !self.no_restore;
+
+        let mut failed = false;
+        let result = test_flash_algorithm(&mut session, &options, |result| {
+            let status = if result.passed {
+                "ok".green()
+            } else {
+                failed = true;
+                "FAILED".red()
+            };
+            println!(
+                "{:>12} {:>6} {:>10.2?}  {}",
+                result.step.to_string(),
+                status,
+                result.duration,
+                result.message
+            );
+        });
+
+        match result {
+            Ok(()) => println!("The flash algorithm works."),
+            // The failed step was printed already.
+            Err(error) if failed => bail!("The flash algorithm test failed: {error}"),
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(())
+    }
+}
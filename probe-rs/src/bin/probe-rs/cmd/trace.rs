@@ -2,7 +2,7 @@ use std::time::Instant;
 
 use probe_rs::MemoryInterface;
 
-use crate::util::{common_options::ProbeOptions, parse_u64};
+use crate::util::{common_options::ProbeOptions, parse_u64, termination};
 use crate::CoreOptions;
 
 #[derive(clap::Parser)]
@@ -35,7 +35,7 @@ impl Cmd {
 
         let mut core = session.core(self.shared.core)?;
 
-        loop {
+        while !termination::requested() {
             // Prepare read.
             let elapsed = start.elapsed();
             let instant = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
@@ -62,5 +62,7 @@ impl Cmd {
             let time_to_wait = poll_every_ms - instant % poll_every_ms;
             sleep(Duration::from_millis(time_to_wait));
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use probe_rs::transaction_log::{self, Ack};
+
+#[derive(clap::Parser)]
+pub struct Cmd {
+    /// The transaction log, which was recorded with '--transaction-log'.
+    path: PathBuf,
+    /// Only print the transfers which the target did not acknowledge with OK.
+    #[clap(long)]
+    failed: bool,
+}
+
+impl Cmd {
+    pub fn run(self) -> anyhow::Result<()> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open the transaction log {:?}", self.path))?;
+        let transactions = transaction_log::read(BufReader::new(file))?;
+
+        let mut failed = 0;
+        for transaction in &transactions {
+            if transaction.ack != Ack::Ok {
+                failed += 1;
+            } else if self.failed {
+                continue;
+            }
+            println!("{transaction}");
+        }
+
+        println!(
+            "{} transfers, {failed} of them not acknowledged with OK",
+            transactions.len()
+        );
+
+        Ok(())
+    }
+}
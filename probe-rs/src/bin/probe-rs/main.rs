@@ -3,13 +3,16 @@ mod util;
 
 include!(concat!(env!("OUT_DIR"), "/meta.rs"));
 
+use std::ops::Range;
 use std::path::Path;
 use std::str::FromStr;
 use std::{ffi::OsString, fs::File, path::PathBuf};
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use probe_rs::flashing::{BinOptions, Format, IdfOptions};
+use probe_rs::config::Partition;
+use probe_rs::flashing::{find_partition, BinOptions, ElfOptions, Format, IdfOptions};
+use probe_rs::Target;
 use serde::{de::Error, Deserialize, Deserializer};
 use serde_json::Value;
 use time::{OffsetDateTime, UtcOffset};
@@ -19,6 +22,7 @@ use tracing_subscriber::{
     EnvFilter, Layer,
 };
 
+use crate::util::parse_range;
 use crate::util::parse_u32;
 use crate::util::parse_u64;
 
@@ -35,6 +39,14 @@ struct Cli {
     /// If no location is specified, the log file will be stored in a default directory.
     #[clap(long, global = true)]
     log_file: Option<PathBuf>,
+    /// Record every transfer between the probe and the target, with its timing and acknowledgement, to this CSV
+    /// file, e.g. to attach it to an issue report. Print it with 'probe-rs transaction-log'.
+    #[clap(long, global = true, value_name = "PATH")]
+    transaction_log: Option<PathBuf>,
+    /// Show messages, like progress and error hints, in the translations of this YAML file, which maps the names of
+    /// messages to their text. Messages which are missing in the file are shown in English.
+    #[clap(long, global = true, value_name = "PATH")]
+    message_catalog: Option<PathBuf>,
     #[clap(subcommand)]
     subcommand: Subcommand,
 }
@@ -57,20 +69,63 @@ enum Subcommand {
     Dump(cmd::dump::Cmd),
     /// Download memory to attached target
     Download(cmd::download::Cmd),
-    /// Erase all nonvolatile memory of attached target
+    /// Show the core clock frequency and the SysTick configuration of the target
+    Clocks(cmd::clocks::Cmd),
+    /// Compare the memory of the attached target with a firmware file
+    Compare(cmd::compare::Cmd),
+    /// Erase all nonvolatile memory of attached target, or only the sectors of an address range or region
     Erase(cmd::erase::Cmd),
+    /// Test a flash algorithm of the target step by step on one sector: erase it, program a pattern, verify it, and
+    /// restore the sector
+    TestAlgorithm(cmd::test_algorithm::Cmd),
     /// Flash and run an ELF program
     #[clap(name = "run")]
     Run(cmd::run::Cmd),
+    /// Flash a test binary, and run the tests in it
+    #[clap(name = "test")]
+    Test(cmd::test::Cmd),
+    /// Run a debug script against the target
+    #[clap(name = "script")]
+    Script(cmd::script::Cmd),
     /// Trace a memory location on the target
     #[clap(name = "trace")]
     Trace(cmd::trace::Cmd),
     /// Configure and monitor ITM trace packets from the target.
     #[clap(name = "itm")]
     Itm(cmd::itm::Cmd),
+    /// Log the values of variables over time, with the data trace of the DWT over SWO
+    #[clap(name = "data-trace")]
+    DataTrace(cmd::data_trace::Cmd),
     Chip(cmd::chip::Cmd),
     Benchmark(cmd::benchmark::Cmd),
     Profile(cmd::profile::Cmd),
+    /// Show the recorded flash wear of all chips which were flashed with probe-rs
+    FlashStats(cmd::flash_stats::Cmd),
+    /// Read and write the files of a FAT or LittleFS filesystem in the flash of the target
+    #[cfg(feature = "filesystem")]
+    Fs(cmd::fs::Cmd),
+    /// Create, flash, test and confirm images of the MCUboot bootloader
+    Mcuboot(cmd::mcuboot::Cmd),
+    /// Resolve addresses to functions and source locations, and the other way around, using the debug information of an ELF file
+    Symbol(cmd::symbol::Cmd),
+    /// Paint the stacks of the target, and measure how much of them was used
+    StackUsage(cmd::stack_usage::Cmd),
+    /// Print a transaction log, which was recorded with '--transaction-log', with the names of the registers
+    TransactionLog(cmd::transaction_log::Cmd),
+    /// Manage the debug probes themselves, e.g. update their firmware
+    Probe(cmd::probe::Cmd),
+    /// Scan the access ports, and access the registers of the debug port and the access ports, and memory through a
+    /// specific access port, for the bring-up of undocumented chips
+    Dap(cmd::dap::Cmd),
+    /// Shift raw bits through the instruction and data registers of the JTAG scan chain, for TAPs which probe-rs
+    /// doesn't know, e.g. of an FPGA
+    Jtag(cmd::jtag::Cmd),
+    /// Load the configuration of an FPGA in the JTAG scan chain, e.g. on a board with an MCU and an FPGA
+    Fpga(cmd::fpga::Cmd),
+    /// Record the tasks and interrupts of an RTOS with SEGGER SystemView over RTT, and summarize them
+    Systemview(cmd::systemview::Cmd),
+    /// Record the program flow of a Cortex-M0+ with its Micro Trace Buffer (MTB), and print the recorded branches
+    Mtb(cmd::mtb::Cmd),
 }
 
 /// Shared options for core selection, shared between commands
@@ -103,6 +158,27 @@ pub(crate) struct FormatOptions {
     /// The number of bytes to skip at the start of the binary file. This is only considered when `bin` is selected as the format.
     #[clap(long, value_parser = parse_u32, default_value = "0")]
     pub skip: u32,
+    /// The name of the partition of the target to flash into. A binary is placed at its start, unless a base address
+    /// is given, and flashing fails if the image contains data outside of it.
+    #[clap(long)]
+    pub partition: Option<String>,
+    /// Only flash this section of an ELF file. Can be given more than once. This is only considered when `elf` is
+    /// selected as the format.
+    #[clap(long = "include-section", value_name = "SECTION")]
+    pub include_sections: Vec<String>,
+    /// Do not flash this section of an ELF file. Can be given more than once. This is only considered when `elf` is
+    /// selected as the format.
+    #[clap(long = "exclude-section", value_name = "SECTION")]
+    pub exclude_sections: Vec<String>,
+    /// Only flash the data of an ELF file inside of this range, given as `<start>..<end>`. Can be given more than once.
+    /// This is only considered when `elf` is selected as the format.
+    #[clap(long = "include-range", value_name = "RANGE", value_parser = parse_range)]
+    pub include_ranges: Vec<Range<u64>>,
+    /// Do not flash the data of an ELF file inside of this range, given as `<start>..<end>`, e.g. to leave a
+    /// bootloader or a SoftDevice untouched. Can be given more than once. This is only considered when `elf` is
+    /// selected as the format.
+    #[clap(long = "exclude-range", value_name = "RANGE", value_parser = parse_range)]
+    pub exclude_ranges: Vec<Range<u64>>,
     /// The idf bootloader path
     #[clap(long)]
     pub idf_bootloader: Option<PathBuf>,
@@ -112,6 +188,16 @@ pub(crate) struct FormatOptions {
 }
 
 impl FormatOptions {
+    /// Look up the partition of `target` which is selected with `--partition`, and place a binary at its start.
+    pub fn resolve_partition(&mut self, target: &Target) -> anyhow::Result<Option<Partition>> {
+        let Some(name) = &self.partition else {
+            return Ok(None);
+        };
+        let partition = find_partition(target, name)?.clone();
+        self.base_address.get_or_insert(partition.range.start);
+        Ok(Some(partition))
+    }
+
     pub fn into_format(self) -> anyhow::Result<Format> {
         Ok(match self.format {
             Format::Bin(_) => Format::Bin(BinOptions {
@@ -119,7 +205,12 @@ impl FormatOptions {
                 skip: self.skip,
             }),
             Format::Hex => Format::Hex,
-            Format::Elf => Format::Elf,
+            Format::Elf(_) => Format::Elf(ElfOptions {
+                include_sections: self.include_sections,
+                exclude_sections: self.exclude_sections,
+                include_ranges: self.include_ranges,
+                exclude_ranges: self.exclude_ranges,
+            }),
             Format::Idf(_) => {
                 let bootloader = if let Some(path) = self.idf_bootloader {
                     Some(std::fs::read(path)?)
@@ -189,10 +280,12 @@ fn multicall_check(args: &[OsString], want: &str) -> Option<Vec<OsString>> {
 fn main() -> Result<()> {
     let args: Vec<_> = std::env::args_os().collect();
     if let Some(args) = multicall_check(&args, "cargo-flash") {
+        util::termination::install().context("Failed to install the handler for Ctrl-C")?;
         cmd::cargo_flash::main(args);
         return Ok(());
     }
     if let Some(args) = multicall_check(&args, "cargo-embed") {
+        util::termination::install().context("Failed to install the handler for Ctrl-C")?;
         cmd::cargo_embed::main(args);
         return Ok(());
     }
@@ -203,6 +296,21 @@ fn main() -> Result<()> {
     // Parse the commandline options.
     let matches = Cli::parse_from(args);
 
+    if let Some(path) = &matches.transaction_log {
+        probe_rs::transaction_log::start(path)
+            .with_context(|| format!("Failed to create the transaction log {path:?}"))?;
+    }
+
+    if let Some(path) = &matches.message_catalog {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open the message catalog {path:?}"))?;
+        let catalog = probe_rs::messages::Catalog::from_yaml_reader(file)
+            .with_context(|| format!("Failed to load the message catalog {path:?}"))?;
+        probe_rs::messages::set_catalog(catalog);
+    }
+
+    util::usb_policy::apply()?;
+
     // the DAP server has special logging requirements. Run it before initializing logging,
     // so it can do its own special init.
     if let Subcommand::DapServer(cmd) = matches.subcommand {
@@ -240,6 +348,24 @@ fn main() -> Result<()> {
 
     tracing::info!("Writing log to {:?}", log_path);
 
+    // Only the commands which run until they are interrupted, or which flash or erase the target, check for Ctrl-C. The
+    // other ones keep the default handling, so that they can still be stopped while they wait, e.g. for a probe.
+    if matches!(
+        matches.subcommand,
+        Subcommand::Download(_)
+            | Subcommand::Erase(_)
+            | Subcommand::Run(_)
+            | Subcommand::Test(_)
+            | Subcommand::Itm(_)
+            | Subcommand::Trace(_)
+            | Subcommand::DataTrace(_)
+            | Subcommand::Profile(_)
+            | Subcommand::Mtb(_)
+            | Subcommand::Systemview(_)
+    ) {
+        util::termination::install().context("Failed to install the handler for Ctrl-C")?;
+    }
+
     let result = match matches.subcommand {
         Subcommand::DapServer { .. } => unreachable!(), // handled above.
         Subcommand::List(cmd) => cmd.run(),
@@ -249,16 +375,38 @@ fn main() -> Result<()> {
         Subcommand::Debug(cmd) => cmd.run(),
         Subcommand::Dump(cmd) => cmd.run(),
         Subcommand::Download(cmd) => cmd.run(),
+        Subcommand::Clocks(cmd) => cmd.run(),
+        Subcommand::Compare(cmd) => cmd.run(),
         Subcommand::Run(cmd) => cmd.run(utc_offset),
+        Subcommand::Test(cmd) => cmd.run(),
+        Subcommand::Script(cmd) => cmd.run(),
         Subcommand::Erase(cmd) => cmd.run(),
+        Subcommand::TestAlgorithm(cmd) => cmd.run(),
         Subcommand::Trace(cmd) => cmd.run(),
         Subcommand::Itm(cmd) => cmd.run(),
+        Subcommand::DataTrace(cmd) => cmd.run(),
         Subcommand::Chip(cmd) => cmd.run(),
         Subcommand::Benchmark(cmd) => cmd.run(),
         Subcommand::Profile(cmd) => cmd.run(),
+        Subcommand::FlashStats(cmd) => cmd.run(),
+        #[cfg(feature = "filesystem")]
+        Subcommand::Fs(cmd) => cmd.run(),
+        Subcommand::Mcuboot(cmd) => cmd.run(),
+        Subcommand::Symbol(cmd) => cmd.run(),
+        Subcommand::StackUsage(cmd) => cmd.run(),
+        Subcommand::TransactionLog(cmd) => cmd.run(),
+        Subcommand::Probe(cmd) => cmd.run(),
+        Subcommand::Dap(cmd) => cmd.run(),
+        Subcommand::Jtag(cmd) => cmd.run(),
+        Subcommand::Fpga(cmd) => cmd.run(),
+        Subcommand::Systemview(cmd) => cmd.run(),
+        Subcommand::Mtb(cmd) => cmd.run(),
     };
 
+    util::usb_policy::log_statistics();
     tracing::info!("Wrote log to {:?}", log_path);
 
+    util::termination::exit_if_requested();
+
     result
 }
@@ -0,0 +1,209 @@
+//! Capture the frames of a CAN bus alongside a debug session, with an adapter which speaks the SLCAN (LAWICEL)
+//! protocol over a serial port.
+//!
+//! The frames are timestamped when they are received, relative to the same start as the RTT messages of the
+//! session, so the bus traffic can be correlated with the state of the firmware.
+
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// The baud rate of the serial port. Adapters with a USB CDC interface ignore it.
+const SERIAL_BAUD_RATE: u32 = 115_200;
+
+/// The bitrates of the `S` command, by their index.
+const BITRATES: [u32; 9] = [
+    10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+];
+
+/// A frame of a CAN bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CanFrame {
+    pub id: u32,
+    /// Whether the frame has a 29 bit identifier.
+    pub extended: bool,
+    /// Whether the frame is a remote transmission request, which has no data.
+    pub remote: bool,
+    pub data: Vec<u8>,
+}
+
+impl fmt::Display for CanFrame {
+    /// Formats the frame like `candump`, e.g. `123#DEADBEEF` or `12345678#R`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.extended {
+            write!(f, "{:08X}#", self.id)?;
+        } else {
+            write!(f, "{:03X}#", self.id)?;
+        }
+        if self.remote {
+            return write!(f, "R");
+        }
+        for byte in &self.data {
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A frame with the time at which it was received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CapturedFrame {
+    /// The time since the start of the session.
+    pub time: Duration,
+    pub frame: CanFrame,
+}
+
+/// Parse a message of the adapter, without the terminating `\r`. Returns `None` for messages which are not frames,
+/// e.g. the acknowledgements of commands.
+pub(crate) fn parse_slcan(message: &str) -> Result<Option<CanFrame>> {
+    let (extended, remote) = match message.chars().next() {
+        Some('t') => (false, false),
+        Some('T') => (true, false),
+        Some('r') => (false, true),
+        Some('R') => (true, true),
+        _ => return Ok(None),
+    };
+    let id_length = if extended { 8 } else { 3 };
+    let field = |range: std::ops::Range<usize>| {
+        message
+            .get(range)
+            .ok_or_else(|| anyhow!("The SLCAN frame '{message}' is truncated"))
+    };
+
+    let id = u32::from_str_radix(field(1..1 + id_length)?, 16)
+        .with_context(|| format!("The SLCAN frame '{message}' has an invalid identifier"))?;
+    let length = field(1 + id_length..2 + id_length)?
+        .parse::<usize>()
+        .ok()
+        .filter(|length| *length <= 8)
+        .ok_or_else(|| anyhow!("The SLCAN frame '{message}' has an invalid length"))?;
+
+    let data = if remote {
+        Vec::new()
+    } else {
+        // The adapter may append a timestamp after the data, which is ignored.
+        let start = 2 + id_length;
+        (0..length)
+            .map(|index| {
+                u8::from_str_radix(field(start + 2 * index..start + 2 * index + 2)?, 16)
+                    .with_context(|| format!("The SLCAN frame '{message}' has invalid data"))
+            })
+            .collect::<Result<_>>()?
+    };
+
+    Ok(Some(CanFrame {
+        id,
+        extended,
+        remote,
+        data,
+    }))
+}
+
+/// A capture of the frames of a CAN bus, which are received in the background.
+pub(crate) struct CanCapture {
+    frames: Receiver<CapturedFrame>,
+    stop: Arc<AtomicBool>,
+}
+
+impl CanCapture {
+    /// Open the channel of the SLCAN adapter at the serial port `path` with `bitrate` bit/s, listening only. The
+    /// times of the frames are relative to `start`.
+    pub(crate) fn open(path: &str, bitrate: u32, start: Instant) -> Result<Self> {
+        let Some(bitrate_index) = BITRATES.iter().position(|rate| *rate == bitrate) else {
+            bail!("SLCAN adapters support the bitrates {BITRATES:?}, not {bitrate}");
+        };
+
+        let mut port = serialport::new(path, SERIAL_BAUD_RATE)
+            .timeout(Duration::from_millis(100))
+            .open()
+            .with_context(|| format!("Failed to open the CAN adapter at {path}"))?;
+        // Close the channel in case it was left open, and then open it in listen only mode, so the capture doesn't
+        // acknowledge frames on the bus.
+        port.write_all(format!("C\rS{bitrate_index}\rL\r").as_bytes())
+            .with_context(|| format!("Failed to configure the CAN adapter at {path}"))?;
+
+        let (sender, frames) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_reader = stop.clone();
+        std::thread::spawn(move || {
+            let mut message = Vec::new();
+            let mut buffer = [0; 256];
+            while !stop_reader.load(Ordering::Relaxed) {
+                let count = match port.read(&mut buffer) {
+                    Ok(count) => count,
+                    Err(error) if error.kind() == ErrorKind::TimedOut => continue,
+                    Err(error) => {
+                        log::error!("Failed to read from the CAN adapter: {error}");
+                        break;
+                    }
+                };
+                let time = start.elapsed();
+                for byte in &buffer[..count] {
+                    // Errors are answered with a bell character instead of the terminating `\r`.
+                    if *byte != b'\r' && *byte != 0x07 {
+                        message.push(*byte);
+                        continue;
+                    }
+                    match parse_slcan(&String::from_utf8_lossy(&message)) {
+                        Ok(Some(frame)) => {
+                            if sender.send(CapturedFrame { time, frame }).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(error) => log::warn!("{error}"),
+                    }
+                    message.clear();
+                }
+            }
+            port.write_all(b"C\r").ok();
+        });
+
+        Ok(Self { frames, stop })
+    }
+
+    /// The frames which were received since the previous call.
+    pub(crate) fn received(&self) -> impl Iterator<Item = CapturedFrame> + '_ {
+        self.frames.try_iter()
+    }
+}
+
+impl Drop for CanCapture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_slcan, CanFrame};
+
+    #[test]
+    fn slcan_frames_are_parsed() {
+        let frame = parse_slcan("t1232BEEF").unwrap().unwrap();
+        assert_eq!(
+            frame,
+            CanFrame {
+                id: 0x123,
+                extended: false,
+                remote: false,
+                data: vec![0xbe, 0xef],
+            }
+        );
+        assert_eq!(frame.to_string(), "123#BEEF");
+
+        // With a timestamp after the data.
+        let frame = parse_slcan("T1ABCDEF01111A2B").unwrap().unwrap();
+        assert_eq!(frame.to_string(), "1ABCDEF0#11");
+
+        assert_eq!(parse_slcan("r7FF0").unwrap().unwrap().to_string(), "7FF#R");
+        assert_eq!(parse_slcan("z").unwrap(), None);
+        assert!(parse_slcan("t12").is_err());
+        assert!(parse_slcan("t1239").is_err());
+    }
+}
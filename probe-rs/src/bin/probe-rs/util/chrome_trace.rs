@@ -0,0 +1,405 @@
+//! Export timelines of the target in the Chrome trace event format.
+//!
+//! The JSON files can be opened with `chrome://tracing` and with the Perfetto UI at <https://ui.perfetto.dev>. Every
+//! source of events, e.g. the PC samples or an ITM stimulus port, gets its own track, and the time of the events is
+//! the time since the start of the trace.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use itm::{ExceptionAction, TracePacket};
+use serde_json::{json, Value};
+
+use crate::util::can_capture::CapturedFrame;
+use crate::util::itm_events::ItmEvent;
+use crate::util::systemview::Timeline;
+
+/// All events belong to one process, the target.
+const PID: u32 = 1;
+
+/// The tracks of the timeline.
+pub(crate) mod track {
+    pub(crate) const PC_SAMPLES: u32 = 1;
+    pub(crate) const EXCEPTIONS: u32 = 2;
+    pub(crate) const ITM: u32 = 3;
+    pub(crate) const DEFMT: u32 = 4;
+    pub(crate) const CAN: u32 = 5;
+    pub(crate) const CURRENT: u32 = 6;
+    pub(crate) const INTERRUPTS: u32 = 7;
+    pub(crate) const MARKERS: u32 = 8;
+    pub(crate) const LOG: u32 = 9;
+    /// The ITM stimulus ports follow the other tracks.
+    pub(crate) const fn itm_port(port: u8) -> u32 {
+        16 + port as u32
+    }
+    /// The tasks of a SystemView recording follow the ITM stimulus ports.
+    pub(crate) const fn task(index: usize) -> u32 {
+        itm_port(u8::MAX) + 1 + index as u32
+    }
+}
+
+/// A trace in the Chrome trace event format, which is written as JSON.
+#[derive(Debug, Default)]
+pub(crate) struct ChromeTrace {
+    events: Vec<Value>,
+    named_tracks: Vec<u32>,
+}
+
+impl ChromeTrace {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give a track a name, once for every track.
+    pub(crate) fn name_track(&mut self, track: u32, name: &str) {
+        if self.named_tracks.contains(&track) {
+            return;
+        }
+        self.named_tracks.push(track);
+        self.events.push(json!({
+            "ph": "M",
+            "name": "thread_name",
+            "pid": PID,
+            "tid": track,
+            "args": { "name": name },
+        }));
+    }
+
+    /// An event without a duration.
+    pub(crate) fn instant(&mut self, track: u32, name: &str, time: Duration, args: Value) {
+        self.events.push(json!({
+            "ph": "i",
+            "s": "t",
+            "name": name,
+            "pid": PID,
+            "tid": track,
+            "ts": micros(time),
+            "args": args,
+        }));
+    }
+
+    /// An event from `start` until `end`.
+    pub(crate) fn complete(
+        &mut self,
+        track: u32,
+        name: &str,
+        start: Duration,
+        end: Duration,
+        args: Value,
+    ) {
+        self.events.push(json!({
+            "ph": "X",
+            "name": name,
+            "pid": PID,
+            "tid": track,
+            "ts": micros(start),
+            "dur": micros(end.saturating_sub(start)),
+            "args": args,
+        }));
+    }
+
+    /// A value of a counter, which is shown as a graph.
+    pub(crate) fn counter(&mut self, track: u32, name: &str, time: Duration, args: Value) {
+        self.events.push(json!({
+            "ph": "C",
+            "name": name,
+            "pid": PID,
+            "tid": track,
+            "ts": micros(time),
+            "args": args,
+        }));
+    }
+
+    /// The start of an event whose end is marked with [`ChromeTrace::end`].
+    pub(crate) fn begin(&mut self, track: u32, name: &str, time: Duration) {
+        self.events.push(json!({
+            "ph": "B",
+            "name": name,
+            "pid": PID,
+            "tid": track,
+            "ts": micros(time),
+        }));
+    }
+
+    pub(crate) fn end(&mut self, track: u32, time: Duration) {
+        self.events.push(json!({
+            "ph": "E",
+            "pid": PID,
+            "tid": track,
+            "ts": micros(time),
+        }));
+    }
+
+    /// Add an ITM event: exception traces become slices on the exception track, stimulus port writes become
+    /// events on the track of their port, and all other packets events on the ITM track.
+    pub(crate) fn itm_event(&mut self, event: &ItmEvent) {
+        let time = event.timestamp.target;
+        let quality = format!("{:?}", event.timestamp.quality);
+
+        match &event.packet {
+            TracePacket::ExceptionTrace { exception, action } => {
+                self.name_track(track::EXCEPTIONS, "Exceptions");
+                let name = format!("{exception:?}");
+                match action {
+                    ExceptionAction::Entered => self.begin(track::EXCEPTIONS, &name, time),
+                    ExceptionAction::Exited => self.end(track::EXCEPTIONS, time),
+                    // The core returned to a preempted exception, whose slice is still open.
+                    ExceptionAction::Returned => {}
+                }
+            }
+            TracePacket::Instrumentation { port, payload } => {
+                let track = track::itm_port(*port);
+                self.name_track(track, &format!("ITM port {port}"));
+                self.instant(
+                    track,
+                    &String::from_utf8_lossy(payload),
+                    time,
+                    json!({ "payload": payload, "quality": quality }),
+                );
+            }
+            packet => {
+                self.name_track(track::ITM, "ITM");
+                self.instant(
+                    track::ITM,
+                    &format!("{packet:?}"),
+                    time,
+                    json!({ "quality": quality }),
+                );
+            }
+        }
+    }
+
+    /// Add the PC samples of a profile, each as a slice with the name of its function until the next sample.
+    pub(crate) fn pc_samples(&mut self, samples: &[(Duration, u32, String)]) {
+        self.name_track(track::PC_SAMPLES, "PC samples");
+        for (index, (time, pc, function)) in samples.iter().enumerate() {
+            let end = samples.get(index + 1).map_or(*time, |(next, _, _)| *next);
+            self.complete(
+                track::PC_SAMPLES,
+                function,
+                *time,
+                end,
+                json!({ "pc": format!("{pc:#010x}") }),
+            );
+        }
+    }
+
+    /// Add a defmt log message.
+    pub(crate) fn defmt_frame(&mut self, time: Duration, level: Option<&str>, message: &str) {
+        self.name_track(track::DEFMT, "defmt");
+        self.instant(
+            track::DEFMT,
+            message,
+            time,
+            json!({ "level": level.unwrap_or("println") }),
+        );
+    }
+
+    /// Add a frame of a CAN bus, named like `candump` shows it.
+    pub(crate) fn can_frame(&mut self, frame: &CapturedFrame) {
+        self.name_track(track::CAN, "CAN");
+        self.instant(
+            track::CAN,
+            &frame.frame.to_string(),
+            frame.time,
+            json!({ "id": frame.frame.id, "data": frame.frame.data }),
+        );
+    }
+
+    /// Add a measurement of the current of the target, in ampere, which is shown in microampere.
+    pub(crate) fn current(&mut self, time: Duration, current: f64) {
+        self.counter(
+            track::CURRENT,
+            "Current",
+            time,
+            json!({ "uA": current * 1_000_000.0 }),
+        );
+    }
+
+    /// Add a SystemView recording: a track for every task and the idle time, the interrupts, the markers, and the
+    /// log messages.
+    pub(crate) fn systemview(&mut self, timeline: &Timeline) {
+        for (index, context) in timeline.context_list().into_iter().enumerate() {
+            let name = timeline.context_name(context);
+            let track = track::task(index);
+            self.name_track(track, &name);
+            for span in timeline.contexts.iter().filter(|span| span.what == context) {
+                self.complete(
+                    track,
+                    &name,
+                    timeline.time(span.start),
+                    timeline.time(span.end),
+                    json!({}),
+                );
+            }
+        }
+
+        if !timeline.interrupts.is_empty() {
+            self.name_track(track::INTERRUPTS, "Interrupts");
+        }
+        for span in &timeline.interrupts {
+            self.complete(
+                track::INTERRUPTS,
+                &timeline.interrupt_name(span.what),
+                timeline.time(span.start),
+                timeline.time(span.end),
+                json!({}),
+            );
+        }
+
+        if !timeline.markers.is_empty() || !timeline.marks.is_empty() {
+            self.name_track(track::MARKERS, "Markers");
+        }
+        for span in &timeline.markers {
+            self.complete(
+                track::MARKERS,
+                &timeline.marker_name(span.what),
+                timeline.time(span.start),
+                timeline.time(span.end),
+                json!({}),
+            );
+        }
+        for (ticks, marker) in &timeline.marks {
+            self.instant(
+                track::MARKERS,
+                &timeline.marker_name(*marker),
+                timeline.time(*ticks),
+                json!({}),
+            );
+        }
+
+        if !timeline.messages.is_empty() {
+            self.name_track(track::LOG, "Log");
+        }
+        for (ticks, level, message) in &timeline.messages {
+            let level = match level {
+                0 => "info",
+                1 => "warning",
+                _ => "error",
+            };
+            self.instant(
+                track::LOG,
+                message,
+                timeline.time(*ticks),
+                json!({ "level": level }),
+            );
+        }
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create the trace file {}", path.display()))?;
+        serde_json::to_writer(
+            BufWriter::new(file),
+            &json!({ "traceEvents": self.events, "displayTimeUnit": "ns" }),
+        )?;
+        tracing::info!("Wrote the trace to {}", path.display());
+        Ok(())
+    }
+}
+
+/// The time in microseconds, the unit of the trace event format.
+fn micros(time: Duration) -> f64 {
+    time.as_secs_f64() * 1_000_000.0
+}
+
+/// The time of a defmt timestamp. Timestamps with the `us` display hint are shown in seconds with a fraction, and
+/// ISO 8601 timestamps are the time since the Unix epoch. Timestamps without a unit are taken as microseconds.
+pub(crate) fn parse_defmt_timestamp(timestamp: &str) -> Option<Duration> {
+    let timestamp = timestamp.trim();
+    if timestamp.contains('T') {
+        let date_time = time::OffsetDateTime::parse(
+            timestamp,
+            &time::format_description::well_known::Iso8601::DEFAULT,
+        )
+        .ok()?;
+        return Duration::try_from(date_time - time::OffsetDateTime::UNIX_EPOCH).ok();
+    }
+    match timestamp.split_once('.') {
+        Some((seconds, fraction)) => {
+            if fraction.len() > 9 || !fraction.bytes().all(|digit| digit.is_ascii_digit()) {
+                return None;
+            }
+            let nanos = format!("{fraction:0<9}").parse().ok()?;
+            Some(Duration::new(seconds.parse().ok()?, nanos))
+        }
+        None => timestamp.parse().ok().map(Duration::from_micros),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::itm_events::{EventTimestamp, TimestampQuality};
+    use std::time::SystemTime;
+
+    fn event(packet: TracePacket, micros: u64) -> ItmEvent {
+        ItmEvent {
+            packet,
+            timestamp: EventTimestamp {
+                target: Duration::from_micros(micros),
+                quality: TimestampQuality::Exact,
+                host: SystemTime::UNIX_EPOCH,
+            },
+        }
+    }
+
+    #[test]
+    fn itm_events_are_put_on_tracks() {
+        let mut trace = ChromeTrace::new();
+        trace.itm_event(&event(
+            TracePacket::Instrumentation {
+                port: 2,
+                payload: b"hi".to_vec(),
+            },
+            10,
+        ));
+        trace.itm_event(&event(
+            TracePacket::Instrumentation {
+                port: 2,
+                payload: b"ho".to_vec(),
+            },
+            20,
+        ));
+
+        assert_eq!(
+            trace.events,
+            [
+                json!({ "ph": "M", "name": "thread_name", "pid": 1, "tid": 18, "args": { "name": "ITM port 2" } }),
+                json!({ "ph": "i", "s": "t", "name": "hi", "pid": 1, "tid": 18, "ts": 10.0, "args": { "payload": [104, 105], "quality": "Exact" } }),
+                json!({ "ph": "i", "s": "t", "name": "ho", "pid": 1, "tid": 18, "ts": 20.0, "args": { "payload": [104, 111], "quality": "Exact" } }),
+            ]
+        );
+    }
+
+    #[test]
+    fn pc_samples_last_until_the_next_sample() {
+        let mut trace = ChromeTrace::new();
+        trace.pc_samples(&[
+            (Duration::from_micros(0), 0x100, "main".to_string()),
+            (Duration::from_micros(5), 0x200, "idle".to_string()),
+        ]);
+
+        assert_eq!(trace.events[1]["name"], "main");
+        assert_eq!(trace.events[1]["dur"], 5.0);
+        assert_eq!(trace.events[2]["name"], "idle");
+        assert_eq!(trace.events[2]["dur"], 0.0);
+    }
+
+    #[test]
+    fn defmt_timestamps_are_parsed() {
+        assert_eq!(
+            parse_defmt_timestamp("1.000250"),
+            Some(Duration::from_micros(1_000_250))
+        );
+        assert_eq!(parse_defmt_timestamp("42"), Some(Duration::from_micros(42)));
+        assert_eq!(
+            parse_defmt_timestamp("1970-01-01T00:00:02.500Z"),
+            Some(Duration::from_millis(2500))
+        );
+        assert_eq!(parse_defmt_timestamp("boot"), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Computation of the core clock frequency and the SysTick configuration.
+//!
+//! The frequency of the core clock is computed from the clock configuration registers of the chip, e.g. the `RCC` of
+//! STM32 chips. This needs knowledge of every family, so it is only available for the families below. The frequency
+//! of an external oscillator can not be read from the chip, and has to be given if the clock is derived from it.
+//!
+//! The core clock is the reference for the SysTick timer, and for the SWO output of the TPIU.
+
+use anyhow::Result;
+use probe_rs::{Core, MemoryInterface};
+use serde::Serialize;
+
+const SYST_CSR: u64 = 0xE000_E010;
+
+/// The frequency of the HSI oscillator of the STM32F0, F1 and F3 families.
+const STM32_HSI_8MHZ: u64 = 8_000_000;
+/// The frequency of the HSI oscillator of the other STM32 families.
+const STM32_HSI_16MHZ: u64 = 16_000_000;
+/// The frequencies of the MSI ranges of the STM32L4 family.
+const STM32L4_MSI_RANGES: [u64; 12] = [
+    100_000, 200_000, 400_000, 800_000, 1_000_000, 2_000_000, 4_000_000, 8_000_000, 16_000_000,
+    24_000_000, 32_000_000, 48_000_000,
+];
+
+/// The configuration of the core clock.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoreClock {
+    /// The frequency of the core clock in Hz, or `None` if it is derived from an external oscillator of unknown frequency.
+    pub frequency: Option<u64>,
+    /// How the core clock is generated, e.g. `PLL from HSE`.
+    pub source: String,
+    /// The frequency of the external reference clock of the SysTick timer in Hz, if the chip has one.
+    pub systick_reference: Option<u64>,
+}
+
+/// The configuration of the SysTick timer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SysTick {
+    pub enabled: bool,
+    pub interrupt_enabled: bool,
+    /// Whether the timer counts the core clock, or the external reference clock.
+    pub core_clock_source: bool,
+    pub reload: u32,
+    pub current: u32,
+    /// The frequency of the SysTick interrupt in Hz, if the frequency of the clock of the timer is known.
+    pub tick_frequency: Option<f64>,
+}
+
+/// Compute the core clock of the target, or return `None` if the clock tree of the family is not known.
+///
+/// `hse_frequency` is the frequency of the external oscillator in Hz, if it is known.
+pub fn read_core_clock(
+    target_name: &str,
+    core: &mut Core,
+    hse_frequency: Option<u64>,
+) -> Result<Option<CoreClock>> {
+    let name = target_name.to_ascii_uppercase();
+
+    let clock = if name.starts_with("NRF52") || name.starts_with("NRF9160") {
+        CoreClock {
+            frequency: Some(64_000_000),
+            source: "HFCLK".to_string(),
+            systick_reference: None,
+        }
+    } else if name.starts_with("NRF5340") {
+        // Only the application core has a divider, the network core always runs at 64 MHz.
+        let divider = if core.id() == 0 {
+            (core.read_word_32(0x5000_5558)? & 0x1) as u64 + 1
+        } else {
+            2
+        };
+        CoreClock {
+            frequency: Some(128_000_000 / divider),
+            source: format!("HFCLK / {divider}"),
+            systick_reference: None,
+        }
+    } else if name.starts_with("STM32F0") {
+        let cfgr = core.read_word_32(0x4002_1004)?;
+        let cfgr2 = core.read_word_32(0x4002_102C)?;
+        stm32f0(cfgr, cfgr2, hse_frequency)
+    } else if name.starts_with("STM32F1") {
+        stm32f1(core.read_word_32(0x4002_1004)?, hse_frequency)
+    } else if name.starts_with("STM32F3") {
+        let cfgr = core.read_word_32(0x4002_1004)?;
+        let cfgr2 = core.read_word_32(0x4002_102C)?;
+        stm32f3(cfgr, cfgr2, hse_frequency)
+    } else if name.starts_with("STM32F2")
+        || name.starts_with("STM32F4")
+        || name.starts_with("STM32F7")
+    {
+        let pllcfgr = core.read_word_32(0x4002_3804)?;
+        let cfgr = core.read_word_32(0x4002_3808)?;
+        stm32f4(pllcfgr, cfgr, hse_frequency)
+    } else if name.starts_with("STM32L4") || name.starts_with("STM32G4") {
+        let cr = core.read_word_32(0x4002_1000)?;
+        let cfgr = core.read_word_32(0x4002_1008)?;
+        let pllcfgr = core.read_word_32(0x4002_100C)?;
+        let csr = core.read_word_32(0x4002_1094)?;
+        stm32l4(cr, cfgr, pllcfgr, csr, hse_frequency)
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(clock))
+}
+
+/// Read the configuration of the SysTick timer of an ARM Cortex-M core.
+pub fn read_systick(core: &mut Core, clock: Option<&CoreClock>) -> Result<SysTick> {
+    let mut registers = [0; 3];
+    core.read_32(SYST_CSR, &mut registers)?;
+    let [csr, reload, current] = registers;
+
+    let core_clock_source = csr & 0b100 != 0;
+    let clock_frequency = clock.and_then(|clock| {
+        if core_clock_source {
+            clock.frequency
+        } else {
+            clock.systick_reference
+        }
+    });
+
+    Ok(SysTick {
+        enabled: csr & 0b001 != 0,
+        interrupt_enabled: csr & 0b010 != 0,
+        core_clock_source,
+        reload: reload & 0xFF_FFFF,
+        current: current & 0xFF_FFFF,
+        tick_frequency: clock_frequency
+            .map(|frequency| frequency as f64 / ((reload & 0xFF_FFFF) as f64 + 1.0)),
+    })
+}
+
+/// The divider of the AHB prescaler from the `HPRE` field.
+fn ahb_divider(hpre: u32) -> u64 {
+    if hpre & 0b1000 == 0 {
+        1
+    } else {
+        [2, 4, 8, 16, 64, 128, 256, 512][(hpre & 0b111) as usize]
+    }
+}
+
+/// The core clock from the system clock, and the AHB prescaler in bits [7:4] of `cfgr`.
+///
+/// The SysTick reference clock of STM32 chips is the core clock divided by 8.
+fn stm32_core_clock(sysclk: Option<u64>, source: String, cfgr: u32) -> CoreClock {
+    let frequency = sysclk.map(|sysclk| sysclk / ahb_divider((cfgr >> 4) & 0xF));
+    CoreClock {
+        frequency,
+        source,
+        systick_reference: frequency.map(|frequency| frequency / 8),
+    }
+}
+
+fn stm32f0(cfgr: u32, cfgr2: u32, hse: Option<u64>) -> CoreClock {
+    let (sysclk, source) = match (cfgr >> 2) & 0b11 {
+        0b00 => (Some(STM32_HSI_8MHZ), "HSI".to_string()),
+        0b01 => (hse, "HSE".to_string()),
+        0b10 => {
+            let multiplier = (((cfgr >> 18) & 0xF) as u64 + 2).min(16);
+            let divider = (cfgr2 & 0xF) as u64 + 1;
+            let (input, input_name) = match (cfgr >> 15) & 0b11 {
+                0b00 => (Some(STM32_HSI_8MHZ / 2), "HSI / 2".to_string()),
+                0b01 => (Some(STM32_HSI_8MHZ / divider), format!("HSI / {divider}")),
+                0b10 => (hse.map(|hse| hse / divider), format!("HSE / {divider}")),
+                _ => (Some(48_000_000 / divider), format!("HSI48 / {divider}")),
+            };
+            (
+                input.map(|input| input * multiplier),
+                format!("PLL x{multiplier} from {input_name}"),
+            )
+        }
+        _ => (Some(48_000_000), "HSI48".to_string()),
+    };
+    stm32_core_clock(sysclk, source, cfgr)
+}
+
+fn stm32f1(cfgr: u32, hse: Option<u64>) -> CoreClock {
+    let (sysclk, source) = match (cfgr >> 2) & 0b11 {
+        0b00 => (Some(STM32_HSI_8MHZ), "HSI".to_string()),
+        0b01 => (hse, "HSE".to_string()),
+        _ => {
+            let multiplier = (((cfgr >> 18) & 0xF) as u64 + 2).min(16);
+            let (input, input_name) = if cfgr & (1 << 16) == 0 {
+                (Some(STM32_HSI_8MHZ / 2), "HSI / 2".to_string())
+            } else {
+                let divider = ((cfgr >> 17) & 0x1) as u64 + 1;
+                (hse.map(|hse| hse / divider), format!("HSE / {divider}"))
+            };
+            (
+                input.map(|input| input * multiplier),
+                format!("PLL x{multiplier} from {input_name}"),
+            )
+        }
+    };
+    stm32_core_clock(sysclk, source, cfgr)
+}
+
+fn stm32f3(cfgr: u32, cfgr2: u32, hse: Option<u64>) -> CoreClock {
+    let (sysclk, source) = match (cfgr >> 2) & 0b11 {
+        0b00 => (Some(STM32_HSI_8MHZ), "HSI".to_string()),
+        0b01 => (hse, "HSE".to_string()),
+        _ => {
+            let multiplier = (((cfgr >> 18) & 0xF) as u64 + 2).min(16);
+            let divider = (cfgr2 & 0xF) as u64 + 1;
+            let (input, input_name) = match (cfgr >> 15) & 0b11 {
+                0b00 => (Some(STM32_HSI_8MHZ / 2), "HSI / 2".to_string()),
+                0b01 => (Some(STM32_HSI_8MHZ / divider), format!("HSI / {divider}")),
+                _ => (hse.map(|hse| hse / divider), format!("HSE / {divider}")),
+            };
+            (
+                input.map(|input| input * multiplier),
+                format!("PLL x{multiplier} from {input_name}"),
+            )
+        }
+    };
+    stm32_core_clock(sysclk, source, cfgr)
+}
+
+fn stm32f4(pllcfgr: u32, cfgr: u32, hse: Option<u64>) -> CoreClock {
+    let (sysclk, source) = match (cfgr >> 2) & 0b11 {
+        0b00 => (Some(STM32_HSI_16MHZ), "HSI".to_string()),
+        0b01 => (hse, "HSE".to_string()),
+        pll => {
+            let (input, input_name) = if pllcfgr & (1 << 22) == 0 {
+                (Some(STM32_HSI_16MHZ), "HSI")
+            } else {
+                (hse, "HSE")
+            };
+            let m = (pllcfgr & 0x3F) as u64;
+            let n = ((pllcfgr >> 6) & 0x1FF) as u64;
+            // Some chips can run the core from the R output of the PLL instead of the P output.
+            let (output, divider) = if pll == 0b10 {
+                ("P", (((pllcfgr >> 16) & 0b11) as u64 + 1) * 2)
+            } else {
+                ("R", ((pllcfgr >> 28) & 0b111) as u64)
+            };
+            (
+                input
+                    .filter(|_| m != 0 && divider != 0)
+                    .map(|input| input / m * n / divider),
+                format!("PLL {output} from {input_name} / {m} x{n} / {divider}"),
+            )
+        }
+    };
+    stm32_core_clock(sysclk, source, cfgr)
+}
+
+fn stm32l4(cr: u32, cfgr: u32, pllcfgr: u32, csr: u32, hse: Option<u64>) -> CoreClock {
+    // The MSI range is set in `RCC_CR` once `MSIRGSEL` is set, and in `RCC_CSR` before.
+    let msi_range = if cr & (1 << 3) != 0 {
+        (cr >> 4) & 0xF
+    } else {
+        (csr >> 8) & 0xF
+    };
+    let msi = STM32L4_MSI_RANGES.get(msi_range as usize).copied();
+
+    let (sysclk, source) = match (cfgr >> 2) & 0b11 {
+        0b00 => (msi, "MSI".to_string()),
+        0b01 => (Some(STM32_HSI_16MHZ), "HSI16".to_string()),
+        0b10 => (hse, "HSE".to_string()),
+        _ => {
+            let (input, input_name) = match pllcfgr & 0b11 {
+                0b01 => (msi, "MSI"),
+                0b10 => (Some(STM32_HSI_16MHZ), "HSI16"),
+                0b11 => (hse, "HSE"),
+                _ => (None, "no source"),
+            };
+            let m = ((pllcfgr >> 4) & 0xF) as u64 + 1;
+            let n = ((pllcfgr >> 8) & 0x7F) as u64;
+            let r = (((pllcfgr >> 25) & 0b11) as u64 + 1) * 2;
+            (
+                input.map(|input| input / m * n / r),
+                format!("PLL R from {input_name} / {m} x{n} / {r}"),
+            )
+        }
+    };
+    stm32_core_clock(sysclk, source, cfgr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stm32f4_pll_from_hse() {
+        // 8 MHz HSE / 8 x336 / 2 = 168 MHz, with an AHB prescaler of 1.
+        let pllcfgr = 1 << 22 | 336 << 6 | 8;
+        let cfgr = 0b10 << 2 | 0b10;
+
+        let clock = stm32f4(pllcfgr, cfgr, Some(8_000_000));
+        assert_eq!(clock.frequency, Some(168_000_000));
+        assert_eq!(clock.systick_reference, Some(21_000_000));
+        assert_eq!(clock.source, "PLL P from HSE / 8 x336 / 2");
+
+        assert_eq!(stm32f4(pllcfgr, cfgr, None).frequency, None);
+    }
+
+    #[test]
+    fn stm32f1_pll_and_ahb_prescaler() {
+        // HSI / 2 x16 = 64 MHz, with an AHB prescaler of 2.
+        let cfgr = 0b1110 << 18 | 0b1000 << 4 | 0b10 << 2;
+        assert_eq!(stm32f1(cfgr, None).frequency, Some(32_000_000));
+    }
+
+    #[test]
+    fn stm32l4_msi_and_pll() {
+        // The reset state: MSI at 4 MHz from the range in `RCC_CSR`.
+        let clock = stm32l4(0, 0, 0, 6 << 8, None);
+        assert_eq!(clock.frequency, Some(4_000_000));
+
+        // MSI at 4 MHz / 1 x40 / 2 = 80 MHz.
+        let cr = 6 << 4 | 1 << 3;
+        let pllcfgr = 40 << 8 | 0b01;
+        let clock = stm32l4(cr, 0b11 << 2, pllcfgr, 0, None);
+        assert_eq!(clock.frequency, Some(80_000_000));
+    }
+}
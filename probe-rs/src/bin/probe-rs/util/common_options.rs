@@ -31,16 +31,17 @@
 //!     // ...
 //! }
 //! ```
-use super::ArtifactError;
+use super::{wsl, ArtifactError};
 
-use std::{fs::File, path::Path, path::PathBuf};
+use std::{fs::File, path::Path, path::PathBuf, time::Duration};
 
 use clap;
 use probe_rs::{
-    config::{RegistryError, TargetSelector},
-    flashing::{FileDownloadError, FlashError, FlashLoader},
-    DebugProbeError, DebugProbeSelector, FakeProbe, Permissions, Probe, Session, Target,
-    WireProtocol,
+    config::{Board, RegistryError, TargetSelector},
+    flashing::{ElfOptions, FileDownloadError, FlashError, FlashLoader},
+    transaction_log::TransactionLogError,
+    DebugProbeError, DebugProbeSelector, FakeProbe, GdbRemote, Permissions, Probe, ReplayProbe,
+    Session, Target, WireProtocol,
 };
 
 /// Common options when flashing a target device.
@@ -91,6 +92,25 @@ pub struct FlashOptions {
         help = "The work directory from which cargo-flash should operate from."
     )]
     pub work_dir: Option<PathBuf>,
+    #[clap(
+        name = "manifest",
+        long = "provision",
+        help = "Flash the per-device data of the connected chip from the given CSV or JSON manifest, \
+        which is keyed by the unique device ID."
+    )]
+    pub provision: Option<PathBuf>,
+    #[clap(
+        name = "record",
+        long = "provision-record",
+        help = "The file to which the provisioned data is appended. Defaults to `<manifest>.record.jsonl`."
+    )]
+    pub provision_record: Option<PathBuf>,
+    #[clap(
+        name = "allow-otp-write",
+        long = "allow-otp-write",
+        help = "Allow writing to one-time programmable (OTP) memory. Use `--dry-run` first to see which OTP words would change."
+    )]
+    pub allow_otp_write: bool,
     #[clap(flatten)]
     /// Arguments which are forwarded to 'cargo build'.
     pub cargo_options: CargoOptions,
@@ -100,12 +120,21 @@ pub struct FlashOptions {
 }
 
 /// Common options and logic when interfacing with a [Probe].
-#[derive(clap::Parser, Debug)]
+#[derive(clap::Parser, Debug, Clone)]
 pub struct ProbeOptions {
     #[structopt(long)]
     pub chip: Option<String>,
     #[structopt(name = "chip description file path", long = "chip-description-path")]
     pub chip_description_path: Option<PathBuf>,
+    /// The board to connect to, e.g. 'nucleo-h743zi'. It selects the chip, and the protocol, speed and reset options
+    /// of the board, unless they are given. If more than one probe is connected, the probe of the board is used.
+    ///
+    /// Boards are read from the 'boards.yaml' file in the probe-rs configuration directory too.
+    #[clap(long, conflicts_with = "chip")]
+    pub board: Option<String>,
+    /// A YAML file with a list of board descriptions, which add to or replace the built-in boards.
+    #[clap(long, value_name = "PATH")]
+    pub board_description_path: Option<PathBuf>,
 
     /// Protocol used to connect to chip. Possible options: [swd, jtag]
     #[structopt(long, help_heading = "PROBE CONFIGURATION")]
@@ -113,9 +142,42 @@ pub struct ProbeOptions {
 
     /// Use this flag to select a specific probe in the list.
     ///
-    /// Use '--probe VID:PID' or '--probe VID:PID:Serial' if you have more than one probe with the same VID:PID.",
-    #[structopt(long = "probe", help_heading = "PROBE CONFIGURATION")]
+    /// Use '--probe VID:PID' or '--probe VID:PID:Serial' if you have more than one probe with the same VID:PID.
+    /// Use '--probe VID:PID@Path' to select the probe by the USB port it is plugged into, e.g. '@1-4.2',
+    /// or use the name of a probe alias from the probes.toml configuration file.
+    #[structopt(
+        long = "probe",
+        value_parser = crate::util::probe_aliases::parse_probe_selector,
+        help_heading = "PROBE CONFIGURATION"
+    )]
     pub probe_selector: Option<DebugProbeSelector>,
+    /// Use the target of a GDB server at this address, e.g. 'localhost:3333' for OpenOCD, instead of a debug probe.
+    ///
+    /// The cores are controlled through their debug registers in memory, so this only works for ARM targets.
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        conflicts_with = "probe_selector",
+        help_heading = "PROBE CONFIGURATION"
+    )]
+    pub gdb_remote: Option<String>,
+    /// The GDB server given by '--gdb-remote' is the GDB stub of an emulator, e.g. QEMU, which does not implement the
+    /// debug registers of the core.
+    ///
+    /// The debug registers are emulated with the commands of the stub, so only Cortex-M cores are supported.
+    #[clap(long, requires = "gdb_remote", help_heading = "PROBE CONFIGURATION")]
+    pub gdb_remote_emulator: bool,
+    /// Replay a transaction log, which was recorded with '--transaction-log', instead of using a debug probe.
+    ///
+    /// Every transfer has to match the recorded one, so a bug report can be reproduced without its probe and target.
+    /// Only ARM targets are supported.
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["probe_selector", "gdb_remote"],
+        help_heading = "PROBE CONFIGURATION"
+    )]
+    pub replay: Option<PathBuf>,
     #[clap(
         long,
         help = "The protocol speed in kHz.",
@@ -135,8 +197,37 @@ pub struct ProbeOptions {
         even when it has read-only protection."
     )]
     pub allow_erase_all: bool,
+    /// Switch on the power supply of the target before connecting, if the probe can power the target.
+    #[clap(long, help_heading = "PROBE CONFIGURATION")]
+    pub target_power: bool,
+    /// Switch off the power supply of the target for this many milliseconds, and on again, before connecting.
+    ///
+    /// This can be used to recover a target from a latch-up. Only works if the probe can power the target.
+    #[clap(long, value_name = "MS", help_heading = "PROBE CONFIGURATION")]
+    pub power_cycle: Option<u64>,
+}
+
+/// Add the boards of the `boards.yaml` file in the probe-rs configuration directory, and of the file at `path`, to the
+/// registry of boards.
+pub fn load_user_boards(path: Option<&Path>) -> Result<(), OperationError> {
+    let config_file = directories::ProjectDirs::from("rs", "probe-rs", "probe-rs")
+        .map(|dirs| dirs.config_dir().join("boards.yaml"))
+        .filter(|path| path.exists());
+    for path in config_file.iter().map(PathBuf::as_path).chain(path) {
+        let file = File::open(path)?;
+        probe_rs::config::add_boards_from_yaml(file).map_err(|error| {
+            OperationError::FailedBoardDescriptionParsing {
+                source: error,
+                path: path.to_path_buf(),
+            }
+        })?;
+    }
+    Ok(())
 }
 
+/// The time the target is given to start up after its power supply was switched on.
+const TARGET_POWER_SETTLE_TIME: Duration = Duration::from_millis(100);
+
 impl ProbeOptions {
     /// Add targets contained in file given by --chip-description-path
     /// to probe-rs registery.
@@ -156,9 +247,32 @@ impl ProbeOptions {
         }
     }
 
+    /// Look up the board given by --board, after adding the boards of the user.
+    pub fn board(&self) -> Result<Option<Board>, OperationError> {
+        let Some(name) = &self.board else {
+            return Ok(None);
+        };
+
+        load_user_boards(self.board_description_path.as_deref())?;
+        probe_rs::config::get_board_by_name(name)
+            .map(Some)
+            .map_err(|error| OperationError::BoardNotFound {
+                source: error,
+                name: name.clone(),
+            })
+    }
+
+    /// The name of the chip given by --chip, or of the chip of the board given by --board.
+    pub fn chip_name(&self) -> Result<Option<String>, OperationError> {
+        Ok(match &self.chip {
+            Some(chip) => Some(chip.clone()),
+            None => self.board()?.map(|board| board.chip),
+        })
+    }
+
     /// Resolves a resultant target selector from passed [ProbeOptions].
     pub fn get_target_selector(&self) -> Result<TargetSelector, OperationError> {
-        let target = if let Some(chip_name) = &self.chip {
+        let target = if let Some(chip_name) = &self.chip_name()? {
             let target = probe_rs::config::get_target_by_name(chip_name).map_err(|error| {
                 OperationError::ChipNotFound {
                     source: error,
@@ -176,35 +290,74 @@ impl ProbeOptions {
 
     /// Attaches to specified probe and configures it.
     pub fn attach_probe(&self) -> Result<Probe, OperationError> {
+        let board = self.board()?;
         let mut probe = {
             if self.dry_run {
                 Probe::from_specific_probe(Box::new(FakeProbe::new()));
             }
 
-            // If we got a probe selector as an argument, open the probe
-            // matching the selector if possible.
-            match &self.probe_selector {
-                Some(selector) => {
-                    Probe::open(selector.clone()).map_err(OperationError::FailedToOpenProbe)
+            if let Some(path) = &self.replay {
+                ReplayProbe::from_log(path)
+                    .map(ReplayProbe::into_probe)
+                    .map_err(|source| OperationError::FailedToReadTransactionLog {
+                        source,
+                        path: path.clone(),
+                    })
+            } else if let Some(address) = &self.gdb_remote {
+                if self.gdb_remote_emulator {
+                    GdbRemote::connect_emulator(address)
+                } else {
+                    GdbRemote::connect(address)
                 }
-                None => {
-                    // Only automatically select a probe if there is
-                    // only a single probe detected.
-                    let list = Probe::list_all();
-                    if list.len() > 1 {
-                        return Err(OperationError::MultipleProbesFound { number: list.len() });
+                .map(|probe| Probe::from_specific_probe(probe))
+                .map_err(OperationError::FailedToOpenProbe)
+            } else {
+                // If we got a probe selector as an argument, open the probe
+                // matching the selector if possible.
+                match &self.probe_selector {
+                    Some(selector) => {
+                        Probe::open(selector.clone()).map_err(OperationError::FailedToOpenProbe)
                     }
-
-                    if let Some(info) = list.first() {
-                        Probe::open(info).map_err(OperationError::FailedToOpenProbe)
-                    } else {
-                        Err(OperationError::NoProbesFound)
+                    None => {
+                        // Only automatically select a probe if there is
+                        // only a single probe detected, or of the board.
+                        let mut list = Probe::list_all();
+                        if list.is_empty() && wsl::is_wsl() {
+                            // Windows binds a probe again when it reenumerates, which detaches it from WSL.
+                            match wsl::reattach_probes() {
+                                Ok(0) => return Err(OperationError::NoProbesFoundInWsl),
+                                Ok(_) => list = Probe::list_all(),
+                                Err(error) => tracing::warn!("{error:#}"),
+                            }
+                        }
+                        if let Some(board) = &board {
+                            let board_probes = list
+                                .iter()
+                                .filter(|info| board.has_probe(info.vendor_id, info.product_id))
+                                .cloned()
+                                .collect::<Vec<_>>();
+                            if !board_probes.is_empty() {
+                                list = board_probes;
+                            }
+                        }
+                        if list.len() > 1 {
+                            return Err(OperationError::MultipleProbesFound { number: list.len() });
+                        }
+
+                        if let Some(info) = list.first() {
+                            Probe::open(info).map_err(OperationError::FailedToOpenProbe)
+                        } else {
+                            Err(OperationError::NoProbesFound)
+                        }
                     }
                 }
             }
         }?;
 
-        if let Some(protocol) = self.protocol {
+        let protocol = self
+            .protocol
+            .or_else(|| board.as_ref().and_then(|board| board.protocol));
+        if let Some(protocol) = protocol {
             // Select protocol and speed
             probe.select_protocol(protocol).map_err(|error| {
                 OperationError::FailedToSelectProtocol {
@@ -214,7 +367,10 @@ impl ProbeOptions {
             })?;
         }
 
-        if let Some(speed) = self.speed {
+        let speed = self
+            .speed
+            .or_else(|| board.as_ref().and_then(|board| board.speed));
+        if let Some(speed) = speed {
             let _actual_speed = probe.set_speed(speed).map_err(|error| {
                 OperationError::FailedToSelectProtocolSpeed {
                     source: error,
@@ -223,6 +379,17 @@ impl ProbeOptions {
             })?;
         }
 
+        if let Some(off_time) = self.power_cycle {
+            probe
+                .power_cycle_target(Duration::from_millis(off_time), TARGET_POWER_SETTLE_TIME)
+                .map_err(OperationError::FailedToControlTargetPower)?;
+        } else if self.target_power {
+            probe
+                .set_target_power(true)
+                .map_err(OperationError::FailedToControlTargetPower)?;
+            std::thread::sleep(TARGET_POWER_SETTLE_TIME);
+        }
+
         Ok(probe)
     }
 
@@ -238,14 +405,18 @@ impl ProbeOptions {
             permissions = permissions.allow_erase_all();
         }
 
-        let session = if self.connect_under_reset {
+        let connect_under_reset = self.connect_under_reset
+            || self
+                .board()?
+                .map_or(false, |board| board.connect_under_reset);
+        let session = if connect_under_reset {
             probe.attach_under_reset(target, permissions)
         } else {
             probe.attach(target, permissions)
         }
         .map_err(|error| OperationError::AttachingFailed {
             source: error,
-            connect_under_reset: self.connect_under_reset,
+            connect_under_reset,
         })?;
 
         Ok(session)
@@ -272,7 +443,7 @@ impl ProbeOptions {
         let target = session.target();
 
         // Create the flash loader
-        let mut loader = FlashLoader::new(target.memory_map.to_vec(), target.source().clone());
+        let mut loader = target.flash_loader();
 
         // Add data from the ELF.
         let mut file = File::open(elf_path).map_err(|error| OperationError::FailedToOpenElf {
@@ -282,7 +453,7 @@ impl ProbeOptions {
 
         // Try and load the ELF data.
         loader
-            .load_elf_data(&mut file)
+            .load_elf_data(&mut file, &ElfOptions::default())
             .map_err(OperationError::FailedToLoadElfData)?;
 
         Ok(loader)
@@ -407,6 +578,16 @@ CARGO BUILD OPTIONS:
 pub enum OperationError {
     #[error("No connected probes were found.")]
     NoProbesFound,
+    #[error(
+        "No connected probes were found. In WSL, probes have to be attached with usbipd, see 'probe-rs probe wsl'."
+    )]
+    NoProbesFoundInWsl,
+    #[error("Failed to read the transaction log '{path}'.")]
+    FailedToReadTransactionLog {
+        #[source]
+        source: TransactionLogError,
+        path: PathBuf,
+    },
     #[error("Failed to open the ELF file '{path}' for flashing.")]
     FailedToOpenElf {
         #[source]
@@ -417,6 +598,8 @@ pub enum OperationError {
     FailedToLoadElfData(#[source] FileDownloadError),
     #[error("Failed to open the debug probe.")]
     FailedToOpenProbe(#[source] DebugProbeError),
+    #[error("Failed to control the target power supply of the probe.")]
+    FailedToControlTargetPower(#[source] DebugProbeError),
     #[error("{number} probes were found.")]
     MultipleProbesFound { number: usize },
     #[error("The flashing procedure failed for '{path}'.")]
@@ -453,6 +636,18 @@ pub enum OperationError {
         source: RegistryError,
         name: String,
     },
+    #[error("Failed to parse the board description '{path}'.")]
+    FailedBoardDescriptionParsing {
+        #[source]
+        source: RegistryError,
+        path: PathBuf,
+    },
+    #[error("The board '{name}' was not found in the database.")]
+    BoardNotFound {
+        #[source]
+        source: RegistryError,
+        name: String,
+    },
     #[error("The protocol '{protocol}' could not be selected.")]
     FailedToSelectProtocol {
         #[source]
@@ -479,8 +674,12 @@ pub enum OperationError {
     TargetResetHaltFailed(#[source] probe_rs::Error),
     #[error("Failed to write to file")]
     IOError(#[source] std::io::Error),
+    #[error("Provisioning the device failed.")]
+    ProvisioningFailed(#[source] anyhow::Error),
     #[error("Failed to parse CLI arguments.")]
     CliArgument(#[from] clap::Error),
+    #[error("The operation was interrupted.")]
+    Interrupted,
 }
 
 impl From<std::io::Error> for OperationError {
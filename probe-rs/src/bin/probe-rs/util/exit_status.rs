@@ -0,0 +1,236 @@
+//! Detect how the firmware on the target finished, so that the host process can exit with a
+//! matching exit code.
+//!
+//! This follows the conventions used by `panic-probe` and `defmt-test`:
+//! - A semihosting `SYS_EXIT` / `SYS_EXIT_EXTENDED` call reports the exit status of the firmware. Other semihosting
+//!   calls are serviced (console output) or skipped, and the firmware keeps running.
+//! - A call to the panic handler (`rust_begin_unwind`) means the firmware panicked.
+//! - A HardFault, which halts the core with a vector catch, or a lockup means the firmware crashed.
+//! - Any other breakpoint instruction (e.g. `cortex_m::asm::bkpt()`) means the firmware finished successfully.
+
+use super::semihosting::{
+    handle_console, SemihostingCall, ADP_STOPPED_APPLICATION_EXIT, SEMIHOSTING_ERROR, SYS_EXIT,
+    SYS_EXIT_EXTENDED,
+};
+use super::{function_address, PANIC_HANDLER_SYMBOL};
+use anyhow::{anyhow, Result};
+use probe_rs::{Core, CoreStatus, HaltReason, VectorCatchCondition};
+use std::path::Path;
+
+/// The exit code used when the firmware panicked, which matches the exit code of a panicking Rust program on the host.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// The exit code used when the firmware hit a HardFault, which matches the exit code of a host process which was
+/// aborted with `SIGABRT`.
+pub const HARD_FAULT_EXIT_CODE: i32 = 134;
+
+/// The reason the firmware stopped running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirmwareExit {
+    /// The firmware finished successfully.
+    Success,
+    /// The firmware reported a failure, with the given exit code.
+    Failure(i32),
+    /// The firmware panicked.
+    Panic,
+    /// The firmware hit a HardFault, or locked up.
+    HardFault,
+}
+
+impl FirmwareExit {
+    /// The exit code that the host process should use.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FirmwareExit::Success => 0,
+            FirmwareExit::Failure(code) => *code,
+            FirmwareExit::Panic => PANIC_EXIT_CODE,
+            FirmwareExit::HardFault => HARD_FAULT_EXIT_CODE,
+        }
+    }
+}
+
+/// Watches a core for the end of a firmware run.
+pub struct ExitDetector {
+    panic_handler: Option<u64>,
+}
+
+impl ExitDetector {
+    /// Create a new detector for the firmware in the given ELF file.
+    ///
+    /// This sets a breakpoint on the panic handler of the firmware, if it has one, makes sure that software
+    /// breakpoints halt the core, and catches HardFaults on the cores which support it.
+    pub fn new(core: &mut Core, elf_file: &Path) -> Result<Self> {
+        let panic_handler = function_address(elf_file, PANIC_HANDLER_SYMBOL)?;
+
+        if let Some(address) = panic_handler {
+            core.set_hw_breakpoint(address)?;
+        } else {
+            tracing::warn!("No panic handler found in the ELF file, panics will not be detected.");
+        }
+        core.debug_on_sw_breakpoint(true)?;
+
+        match core.enable_vector_catch(VectorCatchCondition::HardFault) {
+            Ok(()) => {}
+            Err(probe_rs::Error::NotImplemented(_)) => {
+                tracing::debug!("The core can't catch HardFaults, they will not be detected.");
+            }
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(Self { panic_handler })
+    }
+
+    /// Check if the core has halted, and if so, why.
+    ///
+    /// Semihosting calls other than an exit are serviced and the core is resumed. The text which the firmware
+    /// writes to the console with them is appended to `output`.
+    ///
+    /// Returns `None` while the firmware is still running.
+    pub fn poll(&self, core: &mut Core, output: &mut String) -> Result<Option<FirmwareExit>> {
+        let reason = match core.status()? {
+            CoreStatus::Halted(reason) => reason,
+            CoreStatus::LockedUp => return Ok(Some(FirmwareExit::HardFault)),
+            _ => return Ok(None),
+        };
+
+        let program_counter: u64 = core.read_core_reg(core.program_counter())?;
+
+        if Some(program_counter) == self.panic_handler {
+            return Ok(Some(FirmwareExit::Panic));
+        }
+
+        match reason {
+            HaltReason::Breakpoint(_) | HaltReason::Multiple | HaltReason::Unknown => {
+                match SemihostingCall::pending(core)? {
+                    Some(call) => service_semihosting(core, call, output),
+                    None => Ok(Some(FirmwareExit::Success)),
+                }
+            }
+            reason => decode_halt_reason(reason).map(Some),
+        }
+    }
+}
+
+/// Decode a halt of the core which is neither on a breakpoint nor on a semihosting call.
+fn decode_halt_reason(reason: HaltReason) -> Result<FirmwareExit> {
+    match reason {
+        // The vector catch of `ExitDetector::new`.
+        HaltReason::Exception => Ok(FirmwareExit::HardFault),
+        // The core was halted from the outside, e.g. by another debugger.
+        _ => Err(anyhow!("The core was halted unexpectedly ({:?}).", reason)),
+    }
+}
+
+/// Decode the exit status of a semihosting exit call, or service any other call and resume the core.
+fn service_semihosting(
+    core: &mut Core,
+    call: SemihostingCall,
+    output: &mut String,
+) -> Result<Option<FirmwareExit>> {
+    if let Some(exit) = exit_from_semihosting(core, &call)? {
+        return Ok(Some(exit));
+    }
+
+    let return_value = match handle_console(core, &call, output)? {
+        Some(return_value) => return_value,
+        None => {
+            tracing::warn!(
+                "Skipping unsupported semihosting call (operation {:#x}).",
+                call.operation
+            );
+            SEMIHOSTING_ERROR
+        }
+    };
+    call.complete(core, return_value)?;
+    core.run()?;
+
+    Ok(None)
+}
+
+/// Decode the exit status of a `SYS_EXIT` or `SYS_EXIT_EXTENDED` semihosting call.
+///
+/// Returns `None` for all other semihosting operations.
+pub fn exit_from_semihosting(
+    core: &mut Core,
+    call: &SemihostingCall,
+) -> Result<Option<FirmwareExit>> {
+    let exit = match call.operation {
+        // On 32-bit targets, `SYS_EXIT` only passes the reason, not the exit code.
+        SYS_EXIT => decode_exit(call.parameter, 0),
+        SYS_EXIT_EXTENDED => {
+            let reason = call.parameter_word(core, 0)?;
+            let code = call.parameter_word(core, 1)? as i32;
+            decode_exit(reason, code)
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(exit))
+}
+
+/// Decode the exit status from the `reason` and the exit `code` of a semihosting exit call.
+///
+/// Every reason other than `ADP_Stopped_ApplicationExit`, e.g. a run time error, is a failure.
+fn decode_exit(reason: u32, code: i32) -> FirmwareExit {
+    match (reason, code) {
+        (ADP_STOPPED_APPLICATION_EXIT, 0) => FirmwareExit::Success,
+        (ADP_STOPPED_APPLICATION_EXIT, code) => FirmwareExit::Failure(code),
+        _ => FirmwareExit::Failure(1),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{
+        decode_exit, decode_halt_reason, FirmwareExit, ADP_STOPPED_APPLICATION_EXIT,
+        HARD_FAULT_EXIT_CODE, PANIC_EXIT_CODE,
+    };
+    use probe_rs::HaltReason;
+
+    #[test]
+    fn application_exit_is_decoded_with_its_code() {
+        assert_eq!(
+            decode_exit(ADP_STOPPED_APPLICATION_EXIT, 0),
+            FirmwareExit::Success
+        );
+        assert_eq!(
+            decode_exit(ADP_STOPPED_APPLICATION_EXIT, 3),
+            FirmwareExit::Failure(3)
+        );
+        assert_eq!(
+            decode_exit(ADP_STOPPED_APPLICATION_EXIT, -1),
+            FirmwareExit::Failure(-1)
+        );
+    }
+
+    #[test]
+    fn other_exit_reasons_are_failures() {
+        // ADP_Stopped_RunTimeErrorUnknown
+        assert_eq!(decode_exit(0x20023, 0), FirmwareExit::Failure(1));
+        // ADP_Stopped_InternalError
+        assert_eq!(decode_exit(0x20024, 0), FirmwareExit::Failure(1));
+    }
+
+    #[test]
+    fn exit_codes_match_the_host_conventions() {
+        assert_eq!(FirmwareExit::Success.exit_code(), 0);
+        assert_eq!(FirmwareExit::Failure(2).exit_code(), 2);
+        assert_eq!(FirmwareExit::Panic.exit_code(), PANIC_EXIT_CODE);
+        assert_eq!(FirmwareExit::HardFault.exit_code(), HARD_FAULT_EXIT_CODE);
+    }
+
+    #[test]
+    fn hard_faults_are_failures() {
+        let exit = decode_halt_reason(HaltReason::Exception).unwrap();
+
+        assert_eq!(exit, FirmwareExit::HardFault);
+        assert_ne!(exit.exit_code(), 0);
+    }
+
+    #[test]
+    fn halts_from_the_outside_are_errors() {
+        assert!(decode_halt_reason(HaltReason::Request).is_err());
+        assert!(decode_halt_reason(HaltReason::Step).is_err());
+    }
+}
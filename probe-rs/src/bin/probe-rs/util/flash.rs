@@ -1,14 +1,26 @@
 use super::common_options::{FlashOptions, OperationError};
+use super::flash_stats::{self, FlashOperation};
 use super::logging;
+use super::provision;
+use super::termination;
 
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
 use std::time::Duration;
 use std::{path::Path, sync::Arc, time::Instant};
 
+use anyhow::Context;
 use colored::Colorize;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use probe_rs::{
-    flashing::{DownloadOptions, FlashLoader, FlashProgress, ProgressEvent},
-    Session,
+    config::MemoryRegion,
+    flashing::{
+        Bootloader, DownloadOptions, FlashError, FlashLoader, FlashPlan, FlashProgress,
+        ProgressEvent,
+    },
+    messages::{self, MessageId},
+    Session, Target,
 };
 
 /// Performs the flash download with the given loader. Ensure that the loader has the data to load already stored.
@@ -17,17 +29,39 @@ pub fn run_flash_download(
     session: &mut Session,
     path: &Path,
     opt: &FlashOptions,
-    loader: FlashLoader,
+    mut loader: FlashLoader,
     do_chip_erase: bool,
 ) -> Result<(), OperationError> {
     // Start timer.
     let instant = Instant::now();
 
+    let provisioning = opt
+        .provision
+        .as_ref()
+        .map(|manifest| provision::add_device_data(session, &mut loader, manifest))
+        .transpose()
+        .map_err(OperationError::ProvisioningFailed)?;
+
     let mut download_option = DownloadOptions::default();
     download_option.keep_unwritten_bytes = opt.restore_unwritten;
     download_option.dry_run = opt.probe_options.dry_run;
     download_option.do_chip_erase = do_chip_erase;
     download_option.disable_double_buffering = opt.disable_double_buffering;
+    download_option.allow_otp_write = opt.allow_otp_write;
+
+    if opt.probe_options.dry_run {
+        print_otp_changes(session, &loader)
+            .and_then(|()| loader.plan(session.target(), &download_option))
+            .map(|plan| print_flash_plan(&plan))
+            .map_err(|error| OperationError::FlashingFailed {
+                source: error,
+                target: Box::new(session.target().clone()),
+                target_spec: opt.probe_options.chip.clone(),
+                path: path.to_path_buf(),
+            })?;
+    }
+
+    let flash_operation = Rc::new(RefCell::new(FlashOperation::default()));
 
     if !opt.disable_progressbars {
         // Create progress bars.
@@ -41,7 +75,7 @@ pub fn run_flash_download(
         let fill_progress = if opt.restore_unwritten {
             let fill_progress = multi_progress.add(ProgressBar::new(0));
             fill_progress.set_style(style.clone());
-            fill_progress.set_message("     Reading flash  ");
+            fill_progress.set_message(progress_label(MessageId::ProgressReadingFlash));
             Some(fill_progress)
         } else {
             None
@@ -53,17 +87,19 @@ pub fn run_flash_download(
             logging::set_progress_bar(erase_progress.clone());
         }
         erase_progress.set_style(style.clone());
-        erase_progress.set_message("     Erasing sectors");
+        erase_progress.set_message(progress_label(MessageId::ProgressErasingSectors));
 
         // Create a new progress bar for the program progress.
         let program_progress = multi_progress.add(ProgressBar::new(0));
         program_progress.set_style(style);
-        program_progress.set_message(" Programming pages  ");
+        program_progress.set_message(progress_label(MessageId::ProgressProgrammingPages));
 
         // Register callback to update the progress.
         let flash_layout_output_path = opt.flash_layout_output_path.clone();
+        let operation = flash_operation.clone();
         let progress = FlashProgress::new(move |event| {
             use ProgressEvent::*;
+            operation.borrow_mut().record(&event);
             match event {
                 Initialized { flash_layout } => {
                     let total_page_size: u32 = flash_layout.pages().iter().map(|s| s.size()).sum();
@@ -133,6 +169,19 @@ pub fn run_flash_download(
                         fp.finish()
                     };
                 }
+                ProgrammerProgress {
+                    name,
+                    programmed,
+                    total,
+                } => {
+                    let phase =
+                        messages::format(MessageId::ProgressProgramming, &[("name", &name)]);
+                    let percent = programmed * 100 / total;
+                    program_progress.set_message(progress_label(messages::format(
+                        MessageId::ProgressPercent,
+                        &[("phase", &phase), ("percent", &percent)],
+                    )));
+                }
                 DiagnosticMessage { .. } => (),
             }
         });
@@ -148,6 +197,11 @@ pub fn run_flash_download(
             }
         })?;
     } else {
+        let operation = flash_operation.clone();
+        download_option.progress = Some(FlashProgress::new(move |event| {
+            operation.borrow_mut().record(&event)
+        }));
+
         loader.commit(session, download_option).map_err(|error| {
             OperationError::FlashingFailed {
                 source: error,
@@ -158,13 +212,240 @@ pub fn run_flash_download(
         })?;
     }
 
+    if !opt.probe_options.dry_run {
+        if let Err(error) = flash_stats::record(session, &flash_operation.borrow()) {
+            tracing::warn!("Failed to record the flash statistics: {:?}", error);
+        }
+
+        if let (Some(manifest), Some(record)) = (&opt.provision, provisioning) {
+            let record_path = opt
+                .provision_record
+                .clone()
+                .unwrap_or_else(|| provision::default_record_path(manifest));
+            provision::append_record(&record_path, &record)
+                .map_err(OperationError::ProvisioningFailed)?;
+            logging::eprintln(format!(
+                " {} {} with {} fields",
+                "Provisioned".green().bold(),
+                record.unique_id,
+                record.fields.len()
+            ));
+        }
+    }
+
+    // Ctrl-C is deferred until `commit` returns, so that the image is complete. Don't go on, e.g. to run it.
+    if termination::requested() {
+        return Err(OperationError::Interrupted);
+    }
+
     // Stop timer.
     let elapsed = instant.elapsed();
+    logging::eprintln(finished_message(elapsed));
+
+    Ok(())
+}
+
+/// Performs the flash download with the given loader through a bootloader of the chip, without a debug probe.
+pub fn run_bootloader_download(
+    bootloader: &mut dyn Bootloader,
+    target: &Target,
+    path: &Path,
+    opt: &FlashOptions,
+    loader: FlashLoader,
+    do_chip_erase: bool,
+) -> Result<(), OperationError> {
+    let instant = Instant::now();
+
+    let mut download_option = DownloadOptions::default();
+    download_option.dry_run = opt.probe_options.dry_run;
+    download_option.do_chip_erase = do_chip_erase;
+    download_option.allow_otp_write = opt.allow_otp_write;
+
+    if !opt.disable_progressbars {
+        let progress_bar = ProgressBar::new(0);
+        progress_bar.set_style(
+            ProgressStyle::default_bar()
+                .progress_chars("##-")
+                .template("{msg:.green.bold} [{elapsed_precise}] [{wide_bar}] {bytes:>8}/{total_bytes:>8} @ {bytes_per_sec:>10} (eta {eta:3})")
+                .expect("Error in progress bar creation. This is a bug, please report it."),
+        );
+        progress_bar.set_message(progress_label(messages::format(
+            MessageId::ProgressProgrammingWith,
+            &[("bootloader", &bootloader.name())],
+        )));
+        let bar = progress_bar.clone();
+        download_option.progress = Some(FlashProgress::new(move |event| {
+            if let ProgressEvent::ProgrammerProgress {
+                programmed, total, ..
+            } = event
+            {
+                bar.set_length(total);
+                bar.set_position(programmed);
+            }
+        }));
+        logging::set_progress_bar(Arc::new(progress_bar.clone()));
+
+        let result = loader.commit_with_bootloader(target, bootloader, download_option);
+        progress_bar.finish();
+        logging::clear_progress_bar();
+        result
+    } else {
+        loader.commit_with_bootloader(target, bootloader, download_option)
+    }
+    .map_err(|error| OperationError::FlashingFailed {
+        source: error,
+        target: Box::new(target.clone()),
+        target_spec: opt.probe_options.chip.clone(),
+        path: path.to_path_buf(),
+    })?;
+
+    let elapsed = instant.elapsed();
+    logging::eprintln(finished_message(elapsed));
+
+    Ok(())
+}
+
+/// The end of the flash region of `target` which contains `address`.
+pub fn flash_region_end(target: &Target, address: u64) -> anyhow::Result<u64> {
+    target
+        .memory_map
+        .iter()
+        .find_map(|region| match region {
+            MemoryRegion::Nvm(region) if region.range.contains(&address) => Some(region.range.end),
+            _ => None,
+        })
+        .with_context(|| format!("{address:#010x} is not in a flash region of the target"))
+}
+
+/// Show which words of one-time programmable memory would be changed by flashing.
+fn print_otp_changes(session: &mut Session, loader: &FlashLoader) -> Result<(), FlashError> {
+    for change in loader.otp_changes(session)? {
+        logging::eprintln(format!(
+            "{} OTP word {:#010x}: {:#010x} -> {:#010x}",
+            "Would change".yellow().bold(),
+            change.address,
+            change.old,
+            change.new
+        ));
+    }
+
+    Ok(())
+}
+
+/// Show what flashing would do: which sectors are erased, which pages are programmed and how long it takes at most.
+fn print_flash_plan(plan: &FlashPlan) {
+    let title = "Would flash".yellow().bold();
+
+    for region in &plan.regions {
+        let layout = &region.layout;
+        logging::eprintln(format!(
+            "{title} {:#010x}..{:#010x} with '{}' on core '{}', loaded at {:#010x}",
+            region.region.range.start,
+            region.region.range.end,
+            region.algorithm,
+            region.core,
+            region.algorithm_address
+        ));
+
+        if region.chip_erase {
+            logging::eprintln("    erase the whole chip");
+        } else if region.erase_sectors {
+            let sectors = layout
+                .sectors()
+                .iter()
+                .map(|sector| sector.address()..sector.address() + sector.size());
+            logging::eprintln(format!(
+                "    erase {} sector(s): {}",
+                layout.sectors().len(),
+                format_ranges(sectors)
+            ));
+        } else {
+            logging::eprintln("    erase nothing");
+        }
+
+        let pages = layout
+            .pages()
+            .iter()
+            .map(|page| page.address()..page.address() + page.size() as u64);
+        let page_bytes = layout
+            .pages()
+            .iter()
+            .map(|page| page.size() as u64)
+            .sum::<u64>();
+        logging::eprintln(format!(
+            "    program {} page(s) with {page_bytes} bytes{}: {}",
+            layout.pages().len(),
+            if region.double_buffering {
+                ", double buffered"
+            } else {
+                ""
+            },
+            format_ranges(pages)
+        ));
+
+        if region.restore_unwritten_bytes {
+            logging::eprintln(format!(
+                "    restore {} unwritten bytes",
+                region.restored_bytes()
+            ));
+        }
+        logging::eprintln(format!(
+            "    at most {:.1}s",
+            region.max_duration.as_secs_f32()
+        ));
+    }
+
+    for (name, range, bytes) in &plan.programmers {
+        logging::eprintln(format!(
+            "{title} {bytes} bytes to {:#010x}..{:#010x} with the '{name}' programmer",
+            range.start, range.end
+        ));
+    }
+    if !plan.ram.is_empty() {
+        logging::eprintln(format!(
+            "{title} to RAM: {}",
+            format_ranges(plan.ram.iter().cloned())
+        ));
+    }
+
     logging::eprintln(format!(
-        "    {} in {}s",
-        "Finished".green().bold(),
-        elapsed.as_millis() as f32 / 1000.0,
+        "{} at most {:.1}s",
+        "Flashing takes".yellow().bold(),
+        plan.max_duration().as_secs_f32()
     ));
+}
 
-    Ok(())
+/// Format ranges which follow each other as a single range.
+fn format_ranges(ranges: impl Iterator<Item = Range<u64>>) -> String {
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+        .iter()
+        .map(|range| format!("{:#010x}..{:#010x}", range.start, range.end))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The label of a progress bar, right-aligned with the labels of the other progress bars.
+pub(crate) fn progress_label(message: impl std::fmt::Display) -> String {
+    format!("{message:>19} ")
+}
+
+/// The message that an operation finished after `elapsed`.
+pub(crate) fn finished_message(elapsed: Duration) -> String {
+    let finished = MessageId::ProgressFinished.to_string().green().bold();
+    let seconds = elapsed.as_millis() as f32 / 1000.0;
+    format!(
+        "    {}",
+        messages::format(
+            MessageId::ProgressFinishedAfter,
+            &[("finished", &finished), ("seconds", &seconds)],
+        )
+    )
 }
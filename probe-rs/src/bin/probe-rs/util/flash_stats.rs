@@ -0,0 +1,286 @@
+//! Flash wear tracking.
+//!
+//! Development boards are flashed many times, and flash sectors only survive a limited number of erase cycles.
+//! After every flash operation, the number of erases and programs of each sector is added to a statistics file
+//! for the chip in the probe-rs data directory (e.g. `~/.local/share/probe-rs/flash-stats/`).
+//!
+//! Chips are told apart by their unique device ID, so only targets for which the location of this ID is known
+//! are tracked.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use probe_rs::{flashing::ProgressEvent, Session};
+use serde::{Deserialize, Serialize};
+
+/// The statistics of a single chip.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChipStats {
+    /// The name of the target.
+    pub chip: String,
+    /// The unique device ID, as a hex string.
+    pub unique_id: String,
+    /// The number of flash operations.
+    pub flash_operations: u64,
+    /// The statistics of every sector which was ever erased or programmed, by sector address.
+    pub sectors: BTreeMap<u64, SectorStats>,
+}
+
+impl ChipStats {
+    /// The total number of sector erases.
+    pub fn total_erases(&self) -> u64 {
+        self.sectors.values().map(|sector| sector.erase_count).sum()
+    }
+
+    /// The total number of sector programs.
+    pub fn total_programs(&self) -> u64 {
+        self.sectors
+            .values()
+            .map(|sector| sector.program_count)
+            .sum()
+    }
+
+    /// The sector which was erased most often.
+    pub fn most_erased(&self) -> Option<(u64, &SectorStats)> {
+        self.sectors
+            .iter()
+            .max_by_key(|(_, sector)| sector.erase_count)
+            .map(|(address, sector)| (*address, sector))
+    }
+
+    fn add(&mut self, operation: &FlashOperation) {
+        self.flash_operations += 1;
+
+        for sector in &operation.sectors {
+            let stats = self.sectors.entry(sector.address).or_default();
+            stats.size = sector.size;
+            if sector.erased {
+                stats.erase_count += 1;
+            }
+            if sector.programmed {
+                stats.program_count += 1;
+            }
+        }
+    }
+}
+
+/// The statistics of a single flash sector.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorStats {
+    /// The size of the sector in bytes.
+    pub size: u64,
+    /// How often the sector was erased.
+    pub erase_count: u64,
+    /// How often data was programmed into the sector.
+    pub program_count: u64,
+}
+
+#[derive(Debug, Default)]
+struct SectorOperation {
+    address: u64,
+    size: u64,
+    erased: bool,
+    programmed: bool,
+}
+
+/// Collects which sectors were erased and programmed, from the progress events of a flash operation.
+#[derive(Debug, Default)]
+pub struct FlashOperation {
+    sectors: Vec<SectorOperation>,
+    /// The sectors of the flash region which is currently being flashed.
+    current: Vec<SectorOperation>,
+}
+
+impl FlashOperation {
+    /// Process a progress event. Every flash region starts with an `Initialized` event.
+    pub fn record(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Initialized { flash_layout } => {
+                self.finish_region();
+                self.current = flash_layout
+                    .sectors()
+                    .iter()
+                    .map(|sector| SectorOperation {
+                        address: sector.address(),
+                        size: sector.size(),
+                        programmed: flash_layout.pages().iter().any(|page| {
+                            (sector.address()..sector.address() + sector.size())
+                                .contains(&page.address())
+                        }),
+                        erased: false,
+                    })
+                    .collect();
+            }
+            ProgressEvent::FinishedErasing => {
+                for sector in &mut self.current {
+                    sector.erased = true;
+                }
+            }
+            ProgressEvent::FinishedProgramming => self.finish_region(),
+            // Sectors are only counted as programmed once programming succeeded.
+            ProgressEvent::FailedProgramming => {
+                for sector in &mut self.current {
+                    sector.programmed = false;
+                }
+                self.finish_region();
+            }
+            _ => (),
+        }
+    }
+
+    fn finish_region(&mut self) {
+        self.sectors.extend(
+            self.current
+                .drain(..)
+                .filter(|sector| sector.erased || sector.programmed),
+        );
+    }
+}
+
+/// The directory in which the statistics files are stored.
+fn stats_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("rs", "probe-rs", "probe-rs")
+        .map(|dirs| dirs.data_dir().join("flash-stats"))
+}
+
+fn stats_file(dir: &Path, chip: &str, unique_id: &str) -> PathBuf {
+    let name = format!("{chip}-{unique_id}.json");
+    dir.join(sanitize_filename::sanitize(name))
+}
+
+fn load(path: &Path) -> Result<Option<ChipStats>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let stats = serde_json::from_slice(&contents)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(stats))
+}
+
+fn add_to_file(path: &Path, chip: &str, unique_id: &str, operation: &FlashOperation) -> Result<()> {
+    let mut stats = load(path)?.unwrap_or_else(|| ChipStats {
+        chip: chip.to_string(),
+        unique_id: unique_id.to_string(),
+        ..Default::default()
+    });
+    stats.add(operation);
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(&stats)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Add a flash operation to the statistics of the connected chip.
+///
+/// Nothing is recorded if the unique ID of the chip cannot be read.
+pub fn record(session: &mut Session, operation: &FlashOperation) -> Result<()> {
+    let unique_id = match session.read_unique_id()? {
+        Some(id) => id
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>(),
+        None => {
+            tracing::debug!(
+                "The unique ID of {} is unknown, not recording flash statistics",
+                session.target().name
+            );
+            return Ok(());
+        }
+    };
+
+    let Some(dir) = stats_dir() else {
+        return Ok(());
+    };
+
+    let chip = session.target().name.clone();
+    add_to_file(
+        &stats_file(&dir, &chip, &unique_id),
+        &chip,
+        &unique_id,
+        operation,
+    )
+}
+
+/// Read the statistics of all chips.
+pub fn load_all() -> Result<Vec<ChipStats>> {
+    let Some(dir) = stats_dir().filter(|dir| dir.is_dir()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut all_stats = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+            all_stats.extend(load(&path)?);
+        }
+    }
+
+    all_stats.sort_by(|a, b| (&a.chip, &a.unique_id).cmp(&(&b.chip, &b.unique_id)));
+    Ok(all_stats)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn operation(sectors: &[(u64, bool, bool)]) -> FlashOperation {
+        FlashOperation {
+            sectors: sectors
+                .iter()
+                .map(|&(address, erased, programmed)| SectorOperation {
+                    address,
+                    size: 0x1000,
+                    erased,
+                    programmed,
+                })
+                .collect(),
+            current: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn operations_are_accumulated_in_file() {
+        let dir = std::env::temp_dir().join(format!("probe-rs-flash-stats-{}", std::process::id()));
+        let path = stats_file(&dir, "nRF52840_xxAA", "0011223344556677");
+
+        add_to_file(
+            &path,
+            "nRF52840_xxAA",
+            "0011223344556677",
+            &operation(&[(0x0, true, true), (0x1000, true, false)]),
+        )
+        .unwrap();
+        add_to_file(
+            &path,
+            "nRF52840_xxAA",
+            "0011223344556677",
+            &operation(&[(0x0, true, true)]),
+        )
+        .unwrap();
+
+        let stats = load(&path).unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(stats.flash_operations, 2);
+        assert_eq!(stats.total_erases(), 3);
+        assert_eq!(stats.total_programs(), 2);
+        assert_eq!(
+            stats.most_erased(),
+            Some((
+                0x0,
+                &SectorStats {
+                    size: 0x1000,
+                    erase_count: 2,
+                    program_count: 2
+                }
+            ))
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Inspection of the interrupt state of a core.
+//!
+//! For ARM Cortex-M cores, the enabled, pending and active state and the priority of every interrupt are read from
+//! the NVIC, together with the interrupt masks of the core. For RISC-V cores, the machine mode interrupts are read
+//! from the `mie` and `mip` CSRs, and the external interrupts from the PLIC, if its base address is known.
+//!
+//! With the names of the interrupts, e.g. from an SVD file, this answers why an interrupt does not fire.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{bail, Result};
+use probe_rs::{Core, CoreType, MemoryInterface, RegisterId};
+use serde::{Deserialize, Serialize};
+
+/// The Interrupt Controller Type Register, which holds the number of implemented interrupt lines.
+const ICTR: u64 = 0xE000_E004;
+/// The Interrupt Control and State Register, which holds the active and the pending exception.
+const ICSR: u64 = 0xE000_ED04;
+const NVIC_ISER: u64 = 0xE000_E100;
+const NVIC_ISPR: u64 = 0xE000_E200;
+const NVIC_IABR: u64 = 0xE000_E300;
+const NVIC_IPR: u64 = 0xE000_E400;
+/// The register with CONTROL in bits [31:24], FAULTMASK in [23:16], BASEPRI in [15:8] and PRIMASK in [7:0].
+const CORTEX_M_EXTRA: RegisterId = RegisterId(0b10100);
+
+const MSTATUS: RegisterId = RegisterId(0x300);
+const MIE: RegisterId = RegisterId(0x304);
+const MIP: RegisterId = RegisterId(0x344);
+/// The bits of the machine mode interrupts in `mie` and `mip`.
+const RISCV_MACHINE_INTERRUPTS: &[(u32, &str)] = &[
+    (3, "machine software"),
+    (7, "machine timer"),
+    (11, "machine external"),
+];
+
+const PLIC_PENDING: u64 = 0x1000;
+/// The enable bits of context 0, which is machine mode of hart 0.
+const PLIC_ENABLE: u64 = 0x2000;
+/// The priority threshold of context 0.
+const PLIC_THRESHOLD: u64 = 0x20_0000;
+
+/// What is known about the interrupts of a chip, e.g. from its SVD file.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptInfo {
+    /// The names of the interrupts, by their number.
+    pub names: BTreeMap<u32, String>,
+    /// The number of implemented priority bits of the NVIC.
+    pub nvic_priority_bits: Option<u32>,
+    /// The base address of the PLIC of a RISC-V chip.
+    pub plic_base_address: Option<u64>,
+}
+
+/// The state of an interrupt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interrupt {
+    pub number: u32,
+    pub name: Option<String>,
+    pub enabled: bool,
+    pub pending: bool,
+    /// Whether the interrupt is being handled, or `None` if the controller does not show it.
+    pub active: Option<bool>,
+    /// The priority, without the unimplemented bits if their number is known.
+    pub priority: Option<u32>,
+}
+
+/// The state of an interrupt controller.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterruptController {
+    /// The name of the controller, e.g. `NVIC`.
+    pub name: String,
+    /// The state which masks or selects interrupts independent of the single interrupts, e.g. `PRIMASK set`.
+    pub state: Vec<String>,
+    /// The interrupts which have a name, or are enabled, pending or active.
+    pub interrupts: Vec<Interrupt>,
+}
+
+impl fmt::Display for InterruptController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.state.is_empty() {
+            write!(f, " ({})", self.state.join(", "))?;
+        }
+
+        if self.interrupts.is_empty() {
+            return write!(f, "\nNo interrupts are enabled, pending or active.");
+        }
+
+        write!(f, "\n  #  enabled  pending  active  priority  name")?;
+        for interrupt in &self.interrupts {
+            let flag = |flag: bool| if flag { "yes" } else { "no" };
+            write!(
+                f,
+                "\n{:>3}  {:<7}  {:<7}  {:<6}  {:<8}  {}",
+                interrupt.number,
+                flag(interrupt.enabled),
+                flag(interrupt.pending),
+                interrupt.active.map_or("-", flag),
+                interrupt
+                    .priority
+                    .map_or_else(|| "-".to_string(), |priority| priority.to_string()),
+                interrupt.name.as_deref().unwrap_or_default()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the state of the interrupt controllers of a halted core.
+pub fn read(core: &mut Core, info: &InterruptInfo) -> Result<Vec<InterruptController>> {
+    match core.core_type() {
+        CoreType::Armv6m | CoreType::Armv7m | CoreType::Armv7em | CoreType::Armv8m => {
+            Ok(vec![read_nvic(core, info)?])
+        }
+        CoreType::Riscv => {
+            let mut controllers = vec![read_riscv_local(core)?];
+            if let Some(plic_base_address) = info.plic_base_address {
+                controllers.push(read_plic(core, plic_base_address, info)?);
+            }
+            Ok(controllers)
+        }
+        core_type => bail!("The interrupts of {core_type:?} cores can not be inspected"),
+    }
+}
+
+fn read_nvic(core: &mut Core, info: &InterruptInfo) -> Result<InterruptController> {
+    // ARMv6-M implements up to 32 interrupts, and neither the ICTR nor the active bits.
+    let armv6m = core.core_type() == CoreType::Armv6m;
+    let words = if armv6m {
+        1
+    } else {
+        ((core.read_word_32(ICTR)? & 0xF) + 1) as usize
+    };
+
+    let mut enabled = vec![0; words];
+    core.read_32(NVIC_ISER, &mut enabled)?;
+    let mut pending = vec![0; words];
+    core.read_32(NVIC_ISPR, &mut pending)?;
+    let active = if armv6m {
+        None
+    } else {
+        let mut active = vec![0; words];
+        core.read_32(NVIC_IABR, &mut active)?;
+        Some(active)
+    };
+    // The priorities are read as words, because ARMv6-M does not support byte accesses to them.
+    let mut priorities = vec![0; words * 8];
+    core.read_32(NVIC_IPR, &mut priorities)?;
+    let priorities: Vec<u8> = priorities
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+
+    let mut state = Vec::new();
+    let extra: u32 = core.read_core_reg(CORTEX_M_EXTRA)?;
+    if extra & 0x1 != 0 {
+        state.push("PRIMASK set, all interrupts are masked".to_string());
+    }
+    if !armv6m {
+        if (extra >> 16) & 0x1 != 0 {
+            state.push("FAULTMASK set, all interrupts are masked".to_string());
+        }
+        let basepri = (extra >> 8) & 0xFF;
+        if basepri != 0 {
+            state.push(format!(
+                "BASEPRI {}, lower priorities are masked",
+                implemented_priority(basepri as u8, info.nvic_priority_bits)
+            ));
+        }
+    }
+    let icsr = core.read_word_32(ICSR)?;
+    let active_exception = icsr & 0x1FF;
+    if active_exception != 0 {
+        state.push(format!("handling exception {active_exception}"));
+    }
+
+    Ok(InterruptController {
+        name: "NVIC".to_string(),
+        state,
+        interrupts: decode_nvic(&enabled, &pending, active.as_deref(), &priorities, info),
+    })
+}
+
+/// Decode the NVIC registers. Every bit of `enabled`, `pending` and `active` belongs to one interrupt,
+/// and every byte of `priorities`.
+fn decode_nvic(
+    enabled: &[u32],
+    pending: &[u32],
+    active: Option<&[u32]>,
+    priorities: &[u8],
+    info: &InterruptInfo,
+) -> Vec<Interrupt> {
+    let bit = |words: &[u32], number: u32| words[number as usize / 32] & (1 << (number % 32)) != 0;
+
+    (0..enabled.len() as u32 * 32)
+        .map(|number| Interrupt {
+            number,
+            name: info.names.get(&number).cloned(),
+            enabled: bit(enabled, number),
+            pending: bit(pending, number),
+            active: active.map(|active| bit(active, number)),
+            priority: Some(implemented_priority(
+                priorities[number as usize],
+                info.nvic_priority_bits,
+            )),
+        })
+        .filter(is_relevant)
+        .collect()
+}
+
+/// Only the upper bits of an NVIC priority are implemented, so drop the lower ones if their number is known.
+fn implemented_priority(priority: u8, priority_bits: Option<u32>) -> u32 {
+    match priority_bits {
+        Some(bits @ 1..=8) => priority as u32 >> (8 - bits),
+        _ => priority as u32,
+    }
+}
+
+fn is_relevant(interrupt: &Interrupt) -> bool {
+    interrupt.name.is_some()
+        || interrupt.enabled
+        || interrupt.pending
+        || interrupt.active == Some(true)
+}
+
+fn read_riscv_local(core: &mut Core) -> Result<InterruptController> {
+    let mstatus: u32 = core.read_core_reg(MSTATUS)?;
+    let mie: u32 = core.read_core_reg(MIE)?;
+    let mip: u32 = core.read_core_reg(MIP)?;
+
+    let mut state = Vec::new();
+    if mstatus & (1 << 3) == 0 {
+        state.push("mstatus.MIE clear, all interrupts are masked".to_string());
+    }
+
+    Ok(InterruptController {
+        name: "RISC-V machine mode".to_string(),
+        state,
+        interrupts: RISCV_MACHINE_INTERRUPTS
+            .iter()
+            .map(|&(number, name)| Interrupt {
+                number,
+                name: Some(name.to_string()),
+                enabled: mie & (1 << number) != 0,
+                pending: mip & (1 << number) != 0,
+                active: None,
+                priority: None,
+            })
+            .collect(),
+    })
+}
+
+fn read_plic(
+    core: &mut Core,
+    base_address: u64,
+    info: &InterruptInfo,
+) -> Result<InterruptController> {
+    // The number of sources is not discoverable, so all named interrupts are read. Source 0 does not exist.
+    let sources = info.names.keys().next_back().map_or(32, |&last| last + 1);
+    let words = (sources as usize + 31) / 32;
+
+    let mut priorities = vec![0; sources as usize];
+    core.read_32(base_address, &mut priorities)?;
+    let mut pending = vec![0; words];
+    core.read_32(base_address + PLIC_PENDING, &mut pending)?;
+    let mut enabled = vec![0; words];
+    core.read_32(base_address + PLIC_ENABLE, &mut enabled)?;
+    let threshold = core.read_word_32(base_address + PLIC_THRESHOLD)?;
+
+    let bit = |words: &[u32], number: u32| words[number as usize / 32] & (1 << (number % 32)) != 0;
+
+    Ok(InterruptController {
+        name: "PLIC".to_string(),
+        state: if threshold != 0 {
+            vec![format!(
+                "threshold {threshold}, lower priorities are masked"
+            )]
+        } else {
+            Vec::new()
+        },
+        interrupts: (1..sources)
+            .map(|number| Interrupt {
+                number,
+                name: info.names.get(&number).cloned(),
+                enabled: bit(&enabled, number),
+                pending: bit(&pending, number),
+                active: None,
+                priority: Some(priorities[number as usize]),
+            })
+            .filter(is_relevant)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nvic_state_is_decoded() {
+        let info = InterruptInfo {
+            names: [(1, "UART0".to_string())].into_iter().collect(),
+            nvic_priority_bits: Some(3),
+            plic_base_address: None,
+        };
+        let mut priorities = vec![0; 64];
+        priorities[1] = 0xE0;
+        priorities[35] = 0x20;
+
+        let interrupts = decode_nvic(
+            &[0, 1 << 3],
+            &[1 << 1, 1 << 3],
+            Some(&[0, 0]),
+            &priorities,
+            &info,
+        );
+
+        assert_eq!(
+            interrupts,
+            vec![
+                Interrupt {
+                    number: 1,
+                    name: Some("UART0".to_string()),
+                    enabled: false,
+                    pending: true,
+                    active: Some(false),
+                    priority: Some(7),
+                },
+                Interrupt {
+                    number: 35,
+                    name: None,
+                    enabled: true,
+                    pending: true,
+                    active: Some(false),
+                    priority: Some(1),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Decode ITM packets into events with timestamps, which are correlated with the clock of the host.
+//!
+//! The ITM sends a local timestamp after the packets it applies to, with the number of timestamp clock cycles since
+//! the previous one. The packets are held back until their timestamp arrives, and the time of the target is the sum
+//! of the local timestamps. Global timestamps hold the absolute value of a counter, so if the frequency of their clock
+//! is known, they re-anchor the time of the target, which corrects the error from lost local timestamps.
+//!
+//! An overflow packet means that packets were lost, so the timestamps after it are marked as unreliable until the
+//! next global timestamp. Malformed packets are skipped.
+//!
+//! The host time of an event is the time of the target plus the smallest latency with which events were received
+//! so far: the receive time of an event is its host time plus the latency of the probe and the SWO output, so the
+//! event with the smallest difference between its receive time and its target time gives the best estimate.
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::time::{Duration, Instant, SystemTime};
+
+use itm::{TimestampDataRelation, TracePacket};
+
+/// The configuration of the ITM timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimestampConfig {
+    /// The frequency of the clock of the local timestamps, the core clock of most targets.
+    pub clock_frequency: u32,
+    /// The prescaler of the local timestamp clock, which is 1 unless `ITM_TCR.TSPrescale` is set.
+    pub prescaler: u32,
+    /// The frequency of the clock of the global timestamps, if the target has one and it is known.
+    pub global_clock_frequency: Option<u32>,
+}
+
+/// How accurate the timestamp of an event is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimestampQuality {
+    /// The timestamp is the time of the event.
+    Exact,
+    /// The event happened at some time between the previous and this timestamp.
+    Delayed,
+    /// Packets were lost, so the time of the target may have skipped some local timestamps.
+    Unreliable,
+}
+
+/// The time of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EventTimestamp {
+    /// The time since the start of the trace, according to the timestamps of the target.
+    pub target: Duration,
+    pub quality: TimestampQuality,
+    /// The estimated time of the event on the host.
+    pub host: SystemTime,
+}
+
+/// An ITM packet, and the time it was generated at.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ItmEvent {
+    pub packet: TracePacket,
+    pub timestamp: EventTimestamp,
+}
+
+/// Assigns timestamps to the ITM packets, see the [module documentation](self).
+#[derive(Debug)]
+pub(crate) struct TimestampTracker {
+    config: TimestampConfig,
+    /// The time of the target at the previous local timestamp.
+    target_time: Duration,
+    /// The packets which wait for their local timestamp.
+    pending: Vec<TracePacket>,
+    /// The low bits of the last global timestamp, which are completed by a `GlobalTimestamp2` packet.
+    global_low: u64,
+    global_high: u64,
+    /// The time of the target at the global timestamp 0.
+    global_base: Option<Duration>,
+    overflowed: bool,
+    overflows: usize,
+    host_start: SystemTime,
+    /// The smallest difference between the receive time and the target time of the events so far.
+    latency_offset: Option<i128>,
+}
+
+impl TimestampTracker {
+    pub(crate) fn new(config: TimestampConfig, host_start: SystemTime) -> Self {
+        Self {
+            config,
+            target_time: Duration::ZERO,
+            pending: Vec::new(),
+            global_low: 0,
+            global_high: 0,
+            global_base: None,
+            overflowed: false,
+            overflows: 0,
+            host_start,
+            latency_offset: None,
+        }
+    }
+
+    /// The number of overflow packets so far.
+    pub(crate) fn overflows(&self) -> usize {
+        self.overflows
+    }
+
+    /// Process a packet which was received `received` after the start of the trace, and return the events whose
+    /// timestamps are known now.
+    pub(crate) fn push(&mut self, packet: TracePacket, received: Duration) -> Vec<ItmEvent> {
+        match packet {
+            TracePacket::Sync => vec![],
+            TracePacket::Overflow => {
+                self.overflows += 1;
+                self.overflowed = true;
+                vec![]
+            }
+            TracePacket::LocalTimestamp1 { ts, data_relation } => {
+                self.local_timestamp(ts.into(), data_relation, received)
+            }
+            TracePacket::LocalTimestamp2 { ts } => {
+                self.local_timestamp(ts.into(), TimestampDataRelation::Sync, received)
+            }
+            TracePacket::GlobalTimestamp1 { ts, wrap, clkch } => {
+                if clkch {
+                    // The counter does not tick with the same clock anymore.
+                    self.global_base = None;
+                }
+                self.global_low = ts;
+                // Without a wrap, the high bits did not change, and no `GlobalTimestamp2` follows.
+                if !wrap {
+                    self.global_timestamp();
+                }
+                vec![]
+            }
+            TracePacket::GlobalTimestamp2 { ts } => {
+                self.global_high = ts;
+                self.global_timestamp();
+                vec![]
+            }
+            packet => {
+                self.pending.push(packet);
+                vec![]
+            }
+        }
+    }
+
+    fn local_timestamp(
+        &mut self,
+        ticks: u64,
+        data_relation: TimestampDataRelation,
+        received: Duration,
+    ) -> Vec<ItmEvent> {
+        let prescaled_ticks = ticks * self.config.prescaler.max(1) as u64;
+        self.target_time += ticks_to_duration(prescaled_ticks, self.config.clock_frequency);
+
+        let quality = if self.overflowed {
+            TimestampQuality::Unreliable
+        } else {
+            match data_relation {
+                TimestampDataRelation::Sync | TimestampDataRelation::AssocEventDelay => {
+                    TimestampQuality::Exact
+                }
+                TimestampDataRelation::UnknownDelay
+                | TimestampDataRelation::UnknownAssocEventDelay => TimestampQuality::Delayed,
+            }
+        };
+        let host = self.host_time(self.target_time, received);
+
+        let timestamp = EventTimestamp {
+            target: self.target_time,
+            quality,
+            host,
+        };
+        self.pending
+            .drain(..)
+            .map(|packet| ItmEvent { packet, timestamp })
+            .collect()
+    }
+
+    fn global_timestamp(&mut self) {
+        let Some(frequency) = self.config.global_clock_frequency else {
+            return;
+        };
+        let global_time = ticks_to_duration(self.global_high << 26 | self.global_low, frequency);
+
+        match self.global_base {
+            Some(base) => {
+                self.target_time = base + global_time;
+                // The time is correct again, even if local timestamps were lost.
+                self.overflowed = false;
+            }
+            None => self.global_base = Some(self.target_time.saturating_sub(global_time)),
+        }
+    }
+
+    /// Estimate the host time of an event, and improve the estimate of the latency with its receive time.
+    fn host_time(&mut self, target_time: Duration, received: Duration) -> SystemTime {
+        let offset = received.as_nanos() as i128 - target_time.as_nanos() as i128;
+        let offset = self
+            .latency_offset
+            .map_or(offset, |latency_offset| latency_offset.min(offset));
+        self.latency_offset = Some(offset);
+
+        let host_time = target_time.as_nanos() as i128 + offset;
+        if host_time >= 0 {
+            self.host_start + Duration::from_nanos(host_time as u64)
+        } else {
+            self.host_start - Duration::from_nanos(host_time.unsigned_abs() as u64)
+        }
+    }
+}
+
+fn ticks_to_duration(ticks: u64, frequency: u32) -> Duration {
+    Duration::from_nanos((ticks as u128 * 1_000_000_000 / frequency.max(1) as u128) as u64)
+}
+
+/// An iterator over the timestamped events of an ITM stream.
+pub(crate) struct ItmEvents<R: Read> {
+    packets: itm::Singles<R>,
+    tracker: TimestampTracker,
+    start: Instant,
+    ready: VecDeque<ItmEvent>,
+}
+
+impl<R: Read> ItmEvents<R> {
+    pub(crate) fn new(decoder: itm::Decoder<R>, config: TimestampConfig) -> Self {
+        Self {
+            packets: decoder.singles(),
+            tracker: TimestampTracker::new(config, SystemTime::now()),
+            start: Instant::now(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// The number of overflow packets so far.
+    pub(crate) fn overflows(&self) -> usize {
+        self.tracker.overflows()
+    }
+}
+
+impl<R: Read> Iterator for ItmEvents<R> {
+    type Item = Result<ItmEvent, itm::DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Some(Ok(event));
+            }
+
+            let packet = match self.packets.next()? {
+                Ok(packet) => packet,
+                // The decoder is in sync again after a malformed packet, e.g. from a glitch on the SWO line.
+                Err(itm::DecoderError::MalformedPacket(packet)) => {
+                    tracing::debug!("Ignoring a malformed ITM packet: {packet}");
+                    continue;
+                }
+                Err(error) => return Some(Err(error)),
+            };
+            if packet == TracePacket::Overflow {
+                tracing::warn!(
+                    "ITM packets were lost, the SWO output is too slow for the trace data"
+                );
+            }
+            let events = self.tracker.push(packet, self.start.elapsed());
+            self.ready.extend(events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: TimestampConfig = TimestampConfig {
+        clock_frequency: 1_000_000,
+        prescaler: 1,
+        global_clock_frequency: Some(1_000),
+    };
+
+    fn instrumentation(port: u8) -> TracePacket {
+        TracePacket::Instrumentation {
+            port,
+            payload: vec![0x2a],
+        }
+    }
+
+    #[test]
+    fn packets_get_the_time_of_the_following_local_timestamp() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut tracker = TimestampTracker::new(CONFIG, start);
+
+        assert!(tracker
+            .push(instrumentation(0), Duration::from_millis(5))
+            .is_empty());
+        let events = tracker.push(
+            TracePacket::LocalTimestamp1 {
+                ts: 1500,
+                data_relation: TimestampDataRelation::Sync,
+            },
+            Duration::from_millis(10),
+        );
+        assert_eq!(
+            events,
+            [ItmEvent {
+                packet: instrumentation(0),
+                timestamp: EventTimestamp {
+                    target: Duration::from_micros(1500),
+                    quality: TimestampQuality::Exact,
+                    // The first event gives the latency.
+                    host: start + Duration::from_millis(10),
+                },
+            }]
+        );
+
+        // A later event which was received with less latency improves the estimate.
+        tracker.push(instrumentation(1), Duration::from_millis(9));
+        let events = tracker.push(
+            TracePacket::LocalTimestamp2 { ts: 5 },
+            Duration::from_millis(9),
+        );
+        assert_eq!(events[0].timestamp.target, Duration::from_micros(1505));
+        assert_eq!(events[0].timestamp.host, start + Duration::from_millis(9));
+    }
+
+    #[test]
+    fn overflow_makes_timestamps_unreliable_until_the_next_global_timestamp() {
+        let mut tracker = TimestampTracker::new(CONFIG, SystemTime::UNIX_EPOCH);
+
+        // The global timestamp 0 is at the target time 0.
+        tracker.push(
+            TracePacket::GlobalTimestamp1 {
+                ts: 0,
+                wrap: false,
+                clkch: false,
+            },
+            Duration::ZERO,
+        );
+        tracker.push(TracePacket::Overflow, Duration::ZERO);
+        tracker.push(instrumentation(0), Duration::ZERO);
+        let events = tracker.push(
+            TracePacket::LocalTimestamp1 {
+                ts: 100,
+                data_relation: TimestampDataRelation::Sync,
+            },
+            Duration::ZERO,
+        );
+        assert_eq!(events[0].timestamp.quality, TimestampQuality::Unreliable);
+        assert_eq!(tracker.overflows(), 1);
+
+        // 2 ms on the global clock, even though local timestamps were lost.
+        tracker.push(
+            TracePacket::GlobalTimestamp1 {
+                ts: 2,
+                wrap: false,
+                clkch: false,
+            },
+            Duration::ZERO,
+        );
+        tracker.push(instrumentation(0), Duration::ZERO);
+        let events = tracker.push(
+            TracePacket::LocalTimestamp1 {
+                ts: 10,
+                data_relation: TimestampDataRelation::UnknownDelay,
+            },
+            Duration::ZERO,
+        );
+        assert_eq!(events[0].timestamp.target, Duration::from_micros(2010));
+        assert_eq!(events[0].timestamp.quality, TimestampQuality::Delayed);
+    }
+}
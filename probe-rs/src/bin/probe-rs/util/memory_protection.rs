@@ -0,0 +1,402 @@
+//! Decoding of the memory protection configuration of a core.
+//!
+//! The MPU of ARM Cortex-M cores (PMSAv7 for ARMv6-M and ARMv7-M, PMSAv8 for ARMv8-M) and the PMP of RISC-V cores
+//! are read from the core, and decoded into a table of the enabled regions and their access permissions. This helps
+//! to find the cause of memory protection faults.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use probe_rs::{Core, CoreType, MemoryInterface, RegisterId};
+use serde::{Deserialize, Serialize};
+
+const MPU_TYPE: u64 = 0xE000_ED90;
+const MPU_CTRL: u64 = 0xE000_ED94;
+const MPU_RNR: u64 = 0xE000_ED98;
+const MPU_RBAR: u64 = 0xE000_ED9C;
+/// `MPU_RASR` in PMSAv7, and `MPU_RLAR` in PMSAv8.
+const MPU_RASR_RLAR: u64 = 0xE000_EDA0;
+const MPU_MAIR0: u64 = 0xE000_EDC0;
+
+/// The CSR of `pmpcfg0`, which is followed by `pmpcfg1` to `pmpcfg3`.
+const PMPCFG0: u16 = 0x3A0;
+/// The CSR of `pmpaddr0`, which is followed by `pmpaddr1` to `pmpaddr15`.
+const PMPADDR0: u16 = 0x3B0;
+const PMP_ENTRIES: usize = 16;
+
+/// An enabled memory protection region.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtectionRegion {
+    /// The number of the region, or of the PMP entry.
+    pub number: usize,
+    /// The first address of the region.
+    pub start: u64,
+    /// The end address of the region (exclusive).
+    pub end: u64,
+    /// The access permitted to privileged code (or machine mode), like `rwx` or `r--`.
+    pub privileged: String,
+    /// The access permitted to unprivileged code (or supervisor and user mode).
+    pub unprivileged: String,
+    /// Further attributes, e.g. the memory type or disabled subregions.
+    pub attributes: String,
+}
+
+/// The memory protection configuration of a core.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryProtection {
+    /// The kind of memory protection unit, e.g. `ARMv7-M MPU`.
+    pub kind: String,
+    /// Whether the protection is enabled. The PMP is always enabled.
+    pub enabled: bool,
+    /// Further settings of the unit, e.g. whether the default memory map applies to privileged code.
+    pub settings: String,
+    /// The enabled regions, in the order of their numbers.
+    pub regions: Vec<ProtectionRegion>,
+}
+
+impl fmt::Display for MemoryProtection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}",
+            self.kind,
+            if self.enabled { "enabled" } else { "disabled" }
+        )?;
+        if !self.settings.is_empty() {
+            write!(f, ", {}", self.settings)?;
+        }
+        writeln!(f, ")")?;
+
+        if self.regions.is_empty() {
+            return write!(f, "No regions are enabled.");
+        }
+
+        write!(f, " #  start       end          priv  unpriv  attributes")?;
+        for region in &self.regions {
+            write!(
+                f,
+                "\n{:>2}  {:#010x}  {:#010x}   {:<4}  {:<6}  {}",
+                region.number,
+                region.start,
+                region.end,
+                region.privileged,
+                region.unprivileged,
+                region.attributes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Read and decode the memory protection configuration of a halted core.
+pub fn read(core: &mut Core) -> Result<MemoryProtection> {
+    match core.core_type() {
+        CoreType::Armv6m | CoreType::Armv7m | CoreType::Armv7em | CoreType::Armv8m => {
+            read_mpu(core)
+        }
+        CoreType::Riscv => read_pmp(core),
+        core_type => bail!("The memory protection of {core_type:?} cores can not be decoded"),
+    }
+}
+
+fn read_mpu(core: &mut Core) -> Result<MemoryProtection> {
+    let pmsav8 = core.core_type() == CoreType::Armv8m;
+    let kind = if pmsav8 { "ARMv8-M MPU" } else { "ARMv7-M MPU" };
+
+    let region_count = (core.read_word_32(MPU_TYPE)? >> 8) & 0xFF;
+    if region_count == 0 {
+        bail!("The core has no MPU");
+    }
+    let ctrl = core.read_word_32(MPU_CTRL)?;
+    let mair = if pmsav8 {
+        let mut mair = [0; 2];
+        core.read_32(MPU_MAIR0, &mut mair)?;
+        mair[0] as u64 | (mair[1] as u64) << 32
+    } else {
+        0
+    };
+
+    // The region registers are selected with `MPU_RNR`, which is restored afterwards, so that the program is not disturbed.
+    let selected_region = core.read_word_32(MPU_RNR)?;
+    let mut regions = Vec::new();
+    let mut result = Ok(());
+    for number in 0..region_count {
+        result = core.write_word_32(MPU_RNR, number).and_then(|()| {
+            let rbar = core.read_word_32(MPU_RBAR)?;
+            let rasr_rlar = core.read_word_32(MPU_RASR_RLAR)?;
+            regions.extend(if pmsav8 {
+                decode_pmsav8(number as usize, rbar, rasr_rlar, mair)
+            } else {
+                decode_pmsav7(number as usize, rbar, rasr_rlar)
+            });
+            Ok(())
+        });
+        if result.is_err() {
+            break;
+        }
+    }
+    core.write_word_32(MPU_RNR, selected_region)?;
+    result?;
+
+    let mut settings = Vec::new();
+    if ctrl & 0b100 != 0 {
+        settings.push("default memory map for privileged code");
+    }
+    if ctrl & 0b010 != 0 {
+        settings.push("enabled in HardFault and NMI");
+    }
+
+    Ok(MemoryProtection {
+        kind: kind.to_string(),
+        enabled: ctrl & 0b001 != 0,
+        settings: settings.join(", "),
+        regions,
+    })
+}
+
+/// Decode a PMSAv7 region from its `MPU_RBAR` and `MPU_RASR`.
+fn decode_pmsav7(number: usize, rbar: u32, rasr: u32) -> Option<ProtectionRegion> {
+    if rasr & 1 == 0 {
+        return None;
+    }
+
+    let size = 1u64 << (((rasr >> 1) & 0x1F) + 1);
+    let start = rbar as u64 & !0x1F & !(size - 1);
+
+    let (privileged, unprivileged) = match (rasr >> 24) & 0b111 {
+        0b000 => ("--", "--"),
+        0b001 => ("rw", "--"),
+        0b010 => ("rw", "r-"),
+        0b011 => ("rw", "rw"),
+        0b101 => ("r-", "--"),
+        0b110 | 0b111 => ("r-", "r-"),
+        _ => ("??", "??"),
+    };
+    let execute = rasr & (1 << 28) == 0;
+
+    let tex = (rasr >> 19) & 0b111;
+    let cacheable = (rasr >> 17) & 1;
+    let bufferable = (rasr >> 16) & 1;
+    let mut attributes = match (tex, cacheable, bufferable) {
+        (0b000, 0, 0) => "strongly-ordered".to_string(),
+        (0b000, 0, 1) | (0b010, 0, 0) => "device".to_string(),
+        (0b000, 1, 0) => "normal, write-through".to_string(),
+        (0b000, 1, 1) => "normal, write-back".to_string(),
+        (0b001, 0, 0) => "normal, non-cacheable".to_string(),
+        (0b001, 1, 1) => "normal, write-back, write-allocate".to_string(),
+        _ => format!("TEX={tex:#05b} C={cacheable} B={bufferable}"),
+    };
+    if rasr & (1 << 18) != 0 {
+        attributes.push_str(", shareable");
+    }
+
+    let disabled_subregions = (rasr >> 8) & 0xFF;
+    if disabled_subregions != 0 {
+        let subregions: Vec<String> = (0..8)
+            .filter(|subregion| disabled_subregions & (1 << subregion) != 0)
+            .map(|subregion| subregion.to_string())
+            .collect();
+        attributes.push_str(&format!(", subregions {} disabled", subregions.join(",")));
+    }
+
+    Some(ProtectionRegion {
+        number,
+        start,
+        end: start + size,
+        privileged: permissions(privileged, execute),
+        unprivileged: permissions(unprivileged, execute),
+        attributes,
+    })
+}
+
+/// Decode a PMSAv8 region from its `MPU_RBAR` and `MPU_RLAR`, and the memory attributes of `MPU_MAIR0` and `MPU_MAIR1`.
+fn decode_pmsav8(number: usize, rbar: u32, rlar: u32, mair: u64) -> Option<ProtectionRegion> {
+    if rlar & 1 == 0 {
+        return None;
+    }
+
+    let start = (rbar & !0x1F) as u64;
+    let end = (rlar | 0x1F) as u64 + 1;
+
+    let (privileged, unprivileged) = match (rbar >> 1) & 0b11 {
+        0b00 => ("rw", "--"),
+        0b01 => ("rw", "rw"),
+        0b10 => ("r-", "--"),
+        _ => ("r-", "r-"),
+    };
+    let execute = rbar & 1 == 0;
+
+    let attribute_index = (rlar >> 1) & 0b111;
+    let attribute = (mair >> (attribute_index * 8)) as u8;
+    let mut attributes = if attribute & 0xF0 == 0 {
+        match (attribute >> 2) & 0b11 {
+            0b00 => "device-nGnRnE",
+            0b01 => "device-nGnRE",
+            0b10 => "device-nGRE",
+            _ => "device-GRE",
+        }
+        .to_string()
+    } else if attribute == 0x44 {
+        "normal, non-cacheable".to_string()
+    } else {
+        format!("normal, attributes {attribute:#04x}")
+    };
+    match (rbar >> 3) & 0b11 {
+        0b10 => attributes.push_str(", outer shareable"),
+        0b11 => attributes.push_str(", inner shareable"),
+        _ => {}
+    }
+
+    Some(ProtectionRegion {
+        number,
+        start,
+        end,
+        privileged: permissions(privileged, execute),
+        unprivileged: permissions(unprivileged, execute),
+        attributes,
+    })
+}
+
+fn permissions(read_write: &str, execute: bool) -> String {
+    format!("{read_write}{}", if execute { "x" } else { "-" })
+}
+
+fn read_pmp(core: &mut Core) -> Result<MemoryProtection> {
+    let mut configs = [0u8; PMP_ENTRIES];
+    for (index, chunk) in configs.chunks_mut(4).enumerate() {
+        let pmpcfg: u32 = core.read_core_reg(RegisterId(PMPCFG0 + index as u16))?;
+        chunk.copy_from_slice(&pmpcfg.to_le_bytes());
+    }
+
+    let mut addresses = [0u32; PMP_ENTRIES];
+    for (index, address) in addresses.iter_mut().enumerate() {
+        *address = core.read_core_reg(RegisterId(PMPADDR0 + index as u16))?;
+    }
+
+    Ok(MemoryProtection {
+        kind: "RISC-V PMP".to_string(),
+        enabled: true,
+        settings: String::new(),
+        regions: decode_pmp(&configs, &addresses),
+    })
+}
+
+/// Decode the PMP entries from the bytes of the `pmpcfg` registers and the `pmpaddr` registers.
+fn decode_pmp(configs: &[u8], addresses: &[u32]) -> Vec<ProtectionRegion> {
+    let mut regions = Vec::new();
+
+    for (number, (&config, &address)) in configs.iter().zip(addresses).enumerate() {
+        let (mode, start, end) = match (config >> 3) & 0b11 {
+            0b01 => {
+                let start = match number {
+                    0 => 0,
+                    _ => (addresses[number - 1] as u64) << 2,
+                };
+                ("TOR", start, (address as u64) << 2)
+            }
+            0b10 => ("NA4", (address as u64) << 2, ((address as u64) << 2) + 4),
+            0b11 => {
+                let trailing_ones = address.trailing_ones();
+                let size = 8u64 << trailing_ones;
+                let start = ((address as u64) & !((1u64 << (trailing_ones + 1)) - 1)) << 2;
+                ("NAPOT", start, start + size)
+            }
+            // The entry is off.
+            _ => continue,
+        };
+
+        let access = format!(
+            "{}{}{}",
+            if config & 0b001 != 0 { "r" } else { "-" },
+            if config & 0b010 != 0 { "w" } else { "-" },
+            if config & 0b100 != 0 { "x" } else { "-" }
+        );
+        let locked = config & 0x80 != 0;
+
+        regions.push(ProtectionRegion {
+            number,
+            start,
+            end,
+            // Unless the entry is locked, it does not restrict machine mode.
+            privileged: if locked {
+                access.clone()
+            } else {
+                "rwx".to_string()
+            },
+            unprivileged: access,
+            attributes: if locked {
+                format!("{mode}, locked")
+            } else {
+                mode.to_string()
+            },
+        });
+    }
+
+    regions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pmsav7_region() {
+        // 0x2000_0000, 64 KiB, privileged read/write, unprivileged read-only, execute never,
+        // normal write-back memory with subregion 7 disabled.
+        let rasr = 1 << 28 | 0b010 << 24 | 0b011 << 16 | 0x80 << 8 | 15 << 1 | 1;
+        let region = decode_pmsav7(2, 0x2000_0012, rasr).unwrap();
+
+        assert_eq!(region.start, 0x2000_0000);
+        assert_eq!(region.end, 0x2001_0000);
+        assert_eq!(region.privileged, "rw-");
+        assert_eq!(region.unprivileged, "r--");
+        assert_eq!(
+            region.attributes,
+            "normal, write-back, subregions 7 disabled"
+        );
+
+        assert_eq!(decode_pmsav7(3, 0x2000_0000, rasr & !1), None);
+    }
+
+    #[test]
+    fn pmsav8_region() {
+        // Read-only for all, executable, with the device attributes of MAIR attribute 1.
+        let region =
+            decode_pmsav8(0, 0x0800_0000 | 0b11 << 1, 0x0803_FFE0 | 1 << 1 | 1, 0x0400).unwrap();
+
+        assert_eq!(region.start, 0x0800_0000);
+        assert_eq!(region.end, 0x0804_0000);
+        assert_eq!(region.privileged, "r-x");
+        assert_eq!(region.unprivileged, "r-x");
+        assert_eq!(region.attributes, "device-nGnRE");
+    }
+
+    #[test]
+    fn pmp_entries() {
+        let configs = [
+            // TOR, read/execute, locked.
+            0x80 | 0b01 << 3 | 0b101,
+            // NAPOT, read/write.
+            0b11 << 3 | 0b011,
+            // Off.
+            0,
+        ];
+        let addresses = [0x0800_4000 >> 2, (0x2000_0000 >> 2) | 0x3FF, 0x1234];
+
+        let regions = decode_pmp(&configs, &addresses);
+        assert_eq!(regions.len(), 2);
+
+        assert_eq!((regions[0].start, regions[0].end), (0, 0x0800_4000));
+        assert_eq!(regions[0].privileged, "r-x");
+        assert_eq!(regions[0].attributes, "TOR, locked");
+
+        assert_eq!(
+            (regions[1].start, regions[1].end),
+            (0x2000_0000, 0x2000_2000)
+        );
+        assert_eq!(regions[1].privileged, "rwx");
+        assert_eq!(regions[1].unprivileged, "rw-");
+    }
+}
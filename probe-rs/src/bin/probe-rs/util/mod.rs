@@ -1,14 +1,35 @@
+pub mod can_capture;
+pub mod chrome_trace;
+pub mod clocks;
 pub mod common_options;
+pub mod exit_status;
 pub mod flash;
+pub mod flash_stats;
+pub mod interrupts;
+pub mod itm_events;
 pub mod logging;
+pub mod memory_protection;
+pub mod orbuculum;
+pub mod printf;
+pub mod probe_aliases;
+pub mod provision;
 pub mod rtt;
+pub mod rtt_plot;
+pub mod semihosting;
+pub mod stack_usage;
+pub mod systemview;
+pub mod telemetry;
+pub mod termination;
+pub mod usb_policy;
+pub mod wsl;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 
 use cargo_metadata::Message;
 
 use std::process::{Command, Stdio};
 
+use std::ops::Range;
 use std::path::PathBuf;
 use std::{num::ParseIntError, path::Path};
 use thiserror::Error;
@@ -21,6 +42,46 @@ pub fn parse_u64(input: &str) -> Result<u64, ParseIntError> {
     parse_int::parse(input)
 }
 
+/// Parses an address range given as `<start>..<end>`.
+pub fn parse_range(input: &str) -> Result<Range<u64>> {
+    let (start, end) = input
+        .split_once("..")
+        .ok_or_else(|| anyhow!("Expected a range as `<start>..<end>`"))?;
+    let range = parse_u64(start)?..parse_u64(end)?;
+
+    if range.is_empty() {
+        bail!("The range {input} is empty");
+    }
+    Ok(range)
+}
+
+/// The symbol of the `#[panic_handler]` function in a Rust binary.
+///
+/// The compiler always exports the panic handler with this (unmangled) name, irrespective of the name the user gave it.
+pub const PANIC_HANDLER_SYMBOL: &str = "rust_begin_unwind";
+
+/// Find the address of the function with the given symbol name in the ELF file, e.g. to set a breakpoint on it.
+pub fn function_address(elf_file: &Path, name: &str) -> Result<Option<u64>> {
+    let buffer = std::fs::read(elf_file)?;
+    let binary = goblin::elf::Elf::parse(buffer.as_slice())?;
+
+    Ok(binary
+        .syms
+        .iter()
+        .find(|sym| sym.is_function() && binary.strtab.get_at(sym.st_name) == Some(name))
+        .map(|sym| symbol_address(&sym)))
+}
+
+/// The address of the function or variable of an ELF symbol.
+pub fn symbol_address(sym: &goblin::elf::Sym) -> u64 {
+    if sym.is_function() {
+        // The lowest bit marks Thumb functions, and is not part of the address.
+        sym.st_value & !1
+    } else {
+        sym.st_value
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ArtifactError {
     #[error("Failed to canonicalize path '{work_dir}'.")]
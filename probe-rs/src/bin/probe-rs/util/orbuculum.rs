@@ -0,0 +1,128 @@
+//! Republish the raw trace data on a TCP socket, like the `orbuculum` server does.
+//!
+//! The Orbuculum tools, e.g. `orbtop` and `orbstat`, connect to the server and receive the SWO byte stream as it
+//! came from the probe, including the TPIU frames if continuous formatting is enabled. This lets probe-rs be the
+//! capture backend of an existing Orbuculum pipeline.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+/// A TCP server which sends the trace data to all connected clients.
+#[derive(Debug)]
+pub(crate) struct OrbuculumServer {
+    listener: TcpListener,
+    clients: Vec<TcpStream>,
+}
+
+impl OrbuculumServer {
+    pub(crate) fn bind(addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        // New clients are accepted when data is published, so the capture is never blocked.
+        listener.set_nonblocking(true)?;
+
+        let server = Self {
+            listener,
+            clients: Vec::new(),
+        };
+        tracing::info!(
+            "Publishing the trace data for Orbuculum clients on {}",
+            server.local_addr()?
+        );
+
+        Ok(server)
+    }
+
+    pub(crate) fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Send `data` to every client. Clients which disconnected or cannot keep up with the trace are dropped.
+    pub(crate) fn publish(&mut self, data: &[u8]) {
+        self.accept_clients();
+
+        if data.is_empty() {
+            return;
+        }
+        self.clients
+            .retain_mut(|client| match client.write_all(data) {
+                Ok(()) => true,
+                Err(error) => {
+                    tracing::info!(
+                        "Disconnecting the Orbuculum client {:?}: {error}",
+                        client.peer_addr().ok()
+                    );
+                    false
+                }
+            });
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((client, addr)) => {
+                    if let Err(error) = client
+                        .set_nonblocking(true)
+                        .and_then(|()| client.set_nodelay(true))
+                    {
+                        tracing::warn!("Failed to set up the Orbuculum client {addr}: {error}");
+                        continue;
+                    }
+                    tracing::info!("Orbuculum client {addr} connected");
+                    self.clients.push(client);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    tracing::warn!("Failed to accept an Orbuculum client: {error}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// A reader which publishes all data it reads from the trace on an [`OrbuculumServer`].
+#[derive(Debug)]
+pub(crate) struct OrbuculumTee<R> {
+    reader: R,
+    server: OrbuculumServer,
+}
+
+impl<R: Read> OrbuculumTee<R> {
+    pub(crate) fn new(reader: R, server: OrbuculumServer) -> Self {
+        Self { reader, server }
+    }
+}
+
+impl<R: Read> Read for OrbuculumTee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.reader.read(buf)?;
+        self.server.publish(&buf[..count]);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    #[test]
+    fn trace_data_is_sent_to_clients() {
+        let server = OrbuculumServer::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let trace: &[u8] = &[0x01, 0x2a, 0x00, 0x00, 0x80];
+        let mut tee = OrbuculumTee::new(trace, server);
+        let mut decoded = Vec::new();
+        tee.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, trace);
+
+        let mut received = vec![0; trace.len()];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(received, trace);
+    }
+}
@@ -0,0 +1,608 @@
+//! Expand the messages of "compressed printf" logging, with which the firmware sends the ID of a format string and
+//! the arguments instead of the formatted text, over an RTT channel or an ITM stimulus port.
+//!
+//! The format strings are kept in the `.printf_formats` section of the ELF file, which doesn't have to be loaded into
+//! the target. The ID of a format string is its address in that section, and the strings are NUL-terminated. A message
+//! is the ID, followed by the argument of each conversion of the format string. Numbers are variable length integers
+//! with 7 bits per byte, LSB first, and bit 7 set in all bytes but the last, as in the SEGGER SystemView protocol:
+//!
+//! - Integers, characters and pointers are sent as their unsigned value. `%d` and `%i` sign extend it from 32 bits,
+//!   or from 64 bits with the `ll` and `j` modifiers.
+//! - Floating point numbers are sent as the bits of a `double`.
+//! - Strings are sent as their length in bytes, followed by the bytes.
+//! - A `*` width or precision is sent as an integer before the argument.
+//!
+//! The messages have no delimiter, so a message with an unknown ID or a malformed argument drops the received bytes.
+
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// The section of the ELF file which holds the format strings.
+pub const FORMAT_SECTION: &str = ".printf_formats";
+
+/// The longest variable length integer, which holds 64 bits.
+const MAX_VARINT_LENGTH: usize = 10;
+
+/// The format strings of the firmware, by their address.
+#[derive(Debug, Clone)]
+pub struct FormatTable {
+    /// The address of the section.
+    address: u64,
+    data: Vec<u8>,
+}
+
+impl FormatTable {
+    /// Read the format strings from an ELF file, `None` if it has no [`FORMAT_SECTION`].
+    pub fn parse(elf: &[u8]) -> Result<Option<Self>> {
+        let binary = goblin::elf::Elf::parse(elf)?;
+        let Some(header) = binary
+            .section_headers
+            .iter()
+            .find(|header| binary.shdr_strtab.get_at(header.sh_name) == Some(FORMAT_SECTION))
+        else {
+            return Ok(None);
+        };
+        let data = header
+            .file_range()
+            .and_then(|range| elf.get(range))
+            .with_context(|| format!("The {FORMAT_SECTION} section has no contents"))?;
+
+        Ok(Some(Self {
+            address: header.sh_addr,
+            data: data.to_vec(),
+        }))
+    }
+
+    /// The format string with the ID `id`. The linker may merge a string into the tail of a longer one, so the ID
+    /// doesn't have to point to the start of a string.
+    fn get(&self, id: u64) -> Option<&str> {
+        let offset = usize::try_from(id.checked_sub(self.address)?).ok()?;
+        let string = self.data.get(offset..)?;
+        let end = string.iter().position(|&byte| byte == 0)?;
+        std::str::from_utf8(&string[..end]).ok()
+    }
+}
+
+/// Why a message couldn't be expanded.
+#[derive(Debug)]
+enum DecodeError {
+    /// The message wasn't received completely yet.
+    Incomplete,
+    Malformed(anyhow::Error),
+}
+
+impl From<anyhow::Error> for DecodeError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Malformed(error)
+    }
+}
+
+/// Reads the arguments of a message.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for index in 0..MAX_VARINT_LENGTH {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(DecodeError::Incomplete)?;
+            self.position += 1;
+            value |= u64::from(byte & 0x7f) << (7 * index);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(anyhow!("A number is longer than {MAX_VARINT_LENGTH} bytes").into())
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        let bytes = self
+            .data
+            .get(self.position..)
+            .and_then(|data| data.get(..length))
+            .ok_or(DecodeError::Incomplete)?;
+        self.position += length;
+        Ok(bytes)
+    }
+}
+
+/// The width or the precision of a conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Count {
+    Fixed(usize),
+    /// `*`, the count is an argument.
+    Argument,
+}
+
+/// The size of an integer argument, from the length modifier of a conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Length {
+    /// `hh`
+    Char,
+    /// `h`
+    Short,
+    /// No modifier, or `l`, `z` and `t` on a 32-bit target.
+    Int,
+    /// `ll` and `j`
+    LongLong,
+}
+
+/// A conversion specification of a format string, e.g. `%-08.3lx`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Conversion {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alternate: bool,
+    width: Option<Count>,
+    precision: Option<Count>,
+    length: Length,
+    specifier: char,
+}
+
+/// A part of a format string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Piece<'a> {
+    Text(&'a str),
+    Conversion(Conversion),
+}
+
+/// Split a format string into text and conversions. Invalid conversions are kept as text.
+fn parse_format(format: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('%') {
+        if start > 0 {
+            pieces.push(Piece::Text(&rest[..start]));
+        }
+        match parse_conversion(&rest[start + 1..]) {
+            Some((Some(conversion), length)) => {
+                pieces.push(Piece::Conversion(conversion));
+                rest = &rest[start + 1 + length..];
+            }
+            // `%%`
+            Some((None, length)) => {
+                pieces.push(Piece::Text("%"));
+                rest = &rest[start + 1 + length..];
+            }
+            None => {
+                pieces.push(Piece::Text("%"));
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    pieces
+}
+
+/// Parse the conversion after a `%`, and return it with its length. `None` for an invalid conversion, and no
+/// conversion for `%%`.
+fn parse_conversion(input: &str) -> Option<(Option<Conversion>, usize)> {
+    let bytes = input.as_bytes();
+    let mut position = 0;
+    let mut conversion = Conversion {
+        left: false,
+        plus: false,
+        space: false,
+        zero: false,
+        alternate: false,
+        width: None,
+        precision: None,
+        length: Length::Int,
+        specifier: '%',
+    };
+
+    while let Some(&flag) = bytes.get(position) {
+        match flag {
+            b'-' => conversion.left = true,
+            b'+' => conversion.plus = true,
+            b' ' => conversion.space = true,
+            b'0' => conversion.zero = true,
+            b'#' => conversion.alternate = true,
+            _ => break,
+        }
+        position += 1;
+    }
+
+    let count = |position: &mut usize| {
+        if bytes.get(*position) == Some(&b'*') {
+            *position += 1;
+            return Some(Count::Argument);
+        }
+        let digits = bytes[*position..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        let value = input[*position..*position + digits].parse().ok()?;
+        *position += digits;
+        Some(Count::Fixed(value))
+    };
+    conversion.width = count(&mut position);
+    if bytes.get(position) == Some(&b'.') {
+        position += 1;
+        conversion.precision = Some(count(&mut position).unwrap_or(Count::Fixed(0)));
+    }
+
+    let modifiers: [(&str, Length); 8] = [
+        ("hh", Length::Char),
+        ("h", Length::Short),
+        ("ll", Length::LongLong),
+        ("l", Length::Int),
+        ("j", Length::LongLong),
+        ("z", Length::Int),
+        ("t", Length::Int),
+        ("L", Length::Int),
+    ];
+    if let Some((modifier, length)) = modifiers
+        .iter()
+        .find(|(modifier, _)| input[position..].starts_with(modifier))
+    {
+        conversion.length = *length;
+        position += modifier.len();
+    }
+
+    let specifier = input[position..].chars().next()?;
+    position += specifier.len_utf8();
+    match specifier {
+        '%' => Some((None, position)),
+        'd' | 'i' | 'u' | 'x' | 'X' | 'o' | 'c' | 's' | 'p' | 'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+            conversion.specifier = specifier;
+            Some((Some(conversion), position))
+        }
+        _ => None,
+    }
+}
+
+/// Expands the messages of an RTT channel or an ITM stimulus port, see the [module documentation](self).
+#[derive(Debug, Default)]
+pub struct PrintfDecoder {
+    buffer: Vec<u8>,
+}
+
+impl PrintfDecoder {
+    /// Add received data, to be expanded by [`PrintfDecoder::decode`].
+    pub fn received(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Expand the next message, `None` if it wasn't received completely yet. A malformed message drops the received
+    /// data.
+    pub fn decode(&mut self, formats: &FormatTable) -> Result<Option<String>> {
+        let mut reader = Reader {
+            data: &self.buffer,
+            position: 0,
+        };
+        match expand(formats, &mut reader) {
+            Ok(message) => {
+                let length = reader.position;
+                self.buffer.drain(..length);
+                Ok(Some(message))
+            }
+            Err(DecodeError::Incomplete) => Ok(None),
+            Err(DecodeError::Malformed(error)) => {
+                self.buffer.clear();
+                Err(error)
+            }
+        }
+    }
+}
+
+/// Expand the message at the start of the `reader`.
+fn expand(formats: &FormatTable, reader: &mut Reader) -> Result<String, DecodeError> {
+    let id = reader.varint()?;
+    let format = formats
+        .get(id)
+        .ok_or_else(|| anyhow!("There is no format string with the ID {id:#x}"))?;
+    format_message(format, reader)
+}
+
+/// Format the arguments at the start of the `reader` with the `format` string.
+fn format_message(format: &str, reader: &mut Reader) -> Result<String, DecodeError> {
+    let mut message = String::new();
+    for piece in parse_format(format) {
+        match piece {
+            Piece::Text(text) => message.push_str(text),
+            Piece::Conversion(mut conversion) => {
+                let mut read_count = |count| match count {
+                    Some(Count::Argument) => reader.varint().map(|value| Some(value as u32 as i32)),
+                    Some(Count::Fixed(value)) => Ok(Some(value as i32)),
+                    None => Ok(None),
+                };
+                let width = read_count(conversion.width)?;
+                let precision = read_count(conversion.precision)?;
+                // A negative width is a `-` flag, and a negative precision is no precision.
+                if let Some(width) = width.filter(|width| *width < 0) {
+                    conversion.left = true;
+                    conversion.width = Some(Count::Fixed(width.unsigned_abs() as usize));
+                } else {
+                    conversion.width = width.map(|width| Count::Fixed(width as usize));
+                }
+                conversion.precision = precision
+                    .filter(|precision| *precision >= 0)
+                    .map(|precision| Count::Fixed(precision as usize));
+
+                let argument = match conversion.specifier {
+                    's' => {
+                        let length = usize::try_from(reader.varint()?)
+                            .map_err(|_| anyhow!("A string is too long"))?;
+                        Argument::String(String::from_utf8_lossy(reader.bytes(length)?).into())
+                    }
+                    'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                        Argument::Float(f64::from_bits(reader.varint()?))
+                    }
+                    _ => Argument::Integer(reader.varint()?),
+                };
+                format_argument(&mut message, &conversion, argument);
+            }
+        }
+    }
+    Ok(message)
+}
+
+/// Format `arguments`, which are encoded as the arguments of a message, with the `format` string, e.g. for the
+/// messages of SystemView, which are formatted by the host.
+pub fn format_arguments(format: &str, arguments: &[u8]) -> Result<String> {
+    let mut reader = Reader {
+        data: arguments,
+        position: 0,
+    };
+    format_message(format, &mut reader).map_err(|error| match error {
+        DecodeError::Incomplete => {
+            anyhow!("The message has fewer arguments than its format string")
+        }
+        DecodeError::Malformed(error) => error,
+    })
+}
+
+/// The value of a conversion.
+enum Argument {
+    Integer(u64),
+    Float(f64),
+    String(String),
+}
+
+fn fixed(count: Option<Count>) -> Option<usize> {
+    match count {
+        Some(Count::Fixed(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Format an argument as C's `printf` would.
+fn format_argument(message: &mut String, conversion: &Conversion, argument: Argument) {
+    let precision = fixed(conversion.precision);
+    let upper = conversion.specifier.is_ascii_uppercase();
+
+    // The sign or prefix, and the digits, which the zero padding goes between.
+    let (prefix, body, numeric) = match argument {
+        Argument::String(string) => {
+            let body = match precision {
+                Some(precision) => string.chars().take(precision).collect(),
+                None => string,
+            };
+            (String::new(), body, false)
+        }
+        Argument::Integer(value) if conversion.specifier == 'c' => {
+            let character = char::from_u32(value as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+            (String::new(), character.to_string(), false)
+        }
+        Argument::Integer(value) => {
+            let (negative, magnitude) = match conversion.specifier {
+                'd' | 'i' => {
+                    let value = signed(value, conversion.length);
+                    (value < 0, value.unsigned_abs())
+                }
+                // Pointers have the width of the target.
+                'p' => (false, value),
+                _ => (false, unsigned(value, conversion.length)),
+            };
+            let mut digits = match conversion.specifier {
+                'x' | 'p' => format!("{magnitude:x}"),
+                'X' => format!("{magnitude:X}"),
+                'o' => format!("{magnitude:o}"),
+                _ => format!("{magnitude}"),
+            };
+            if let Some(precision) = precision {
+                if precision == 0 && magnitude == 0 {
+                    digits.clear();
+                }
+                digits = format!("{digits:0>precision$}");
+            }
+            let prefix = match conversion.specifier {
+                'x' if conversion.alternate && magnitude != 0 => "0x",
+                'X' if conversion.alternate && magnitude != 0 => "0X",
+                'o' if conversion.alternate && !digits.starts_with('0') => "0",
+                'p' => "0x",
+                _ => sign(negative, conversion),
+            };
+            (prefix.to_string(), digits, precision.is_none())
+        }
+        Argument::Float(value) => {
+            let digits = if value.is_nan() {
+                "nan".to_string()
+            } else if value.is_infinite() {
+                "inf".to_string()
+            } else {
+                let precision = precision.unwrap_or(6);
+                match conversion.specifier.to_ascii_lowercase() {
+                    'e' => format_exponential(value.abs(), precision),
+                    'g' => format_general(value.abs(), precision, conversion.alternate),
+                    _ => format!("{:.precision$}", value.abs()),
+                }
+            };
+            let digits = if upper {
+                digits.to_ascii_uppercase()
+            } else {
+                digits
+            };
+            let negative = value.is_sign_negative() && !value.is_nan();
+            (
+                sign(negative, conversion).to_string(),
+                digits,
+                value.is_finite(),
+            )
+        }
+    };
+
+    let length = prefix.chars().count() + body.chars().count();
+    let padding = fixed(conversion.width).unwrap_or(0).saturating_sub(length);
+    if conversion.left {
+        write!(message, "{prefix}{body}{:padding$}", "").ok();
+    } else if conversion.zero && numeric {
+        write!(message, "{prefix}{:0>padding$}{body}", "").ok();
+    } else {
+        write!(message, "{:padding$}{prefix}{body}", "").ok();
+    }
+}
+
+fn sign(negative: bool, conversion: &Conversion) -> &'static str {
+    match (negative, conversion.plus, conversion.space) {
+        (true, _, _) => "-",
+        (false, true, _) => "+",
+        (false, false, true) => " ",
+        _ => "",
+    }
+}
+
+/// Sign extend an integer argument per its length modifier.
+fn signed(value: u64, length: Length) -> i64 {
+    match length {
+        Length::Char => value as i8 as i64,
+        Length::Short => value as i16 as i64,
+        Length::Int => value as i32 as i64,
+        Length::LongLong => value as i64,
+    }
+}
+
+/// Truncate an integer argument per its length modifier.
+fn unsigned(value: u64, length: Length) -> u64 {
+    match length {
+        Length::Char => value as u8 as u64,
+        Length::Short => value as u16 as u64,
+        Length::Int => value as u32 as u64,
+        Length::LongLong => value,
+    }
+}
+
+/// Format a non-negative number as `%e` does, e.g. `1.500000e+03`.
+fn format_exponential(value: f64, precision: usize) -> String {
+    let formatted = format!("{value:.precision$e}");
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exponent.unsigned_abs())
+}
+
+/// Format a non-negative number as `%g` does: in the shorter of `%f` and `%e` with `precision` significant digits,
+/// without trailing zeros unless `alternate`.
+fn format_general(value: f64, precision: usize, alternate: bool) -> String {
+    let precision = precision.max(1);
+    let exponential = format_exponential(value, precision - 1);
+    let exponent: i32 = exponential
+        .split_once('e')
+        .and_then(|(_, exponent)| exponent.parse().ok())
+        .unwrap_or(0);
+
+    let (mantissa, suffix) = if exponent < -4 || exponent >= precision as i32 {
+        let (mantissa, exponent) = exponential.split_once('e').unwrap_or((&exponential, ""));
+        (mantissa.to_string(), format!("e{exponent}"))
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        (format!("{value:.decimals$}"), String::new())
+    };
+
+    if alternate || !mantissa.contains('.') {
+        format!("{mantissa}{suffix}")
+    } else {
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        format!("{mantissa}{suffix}")
+    }
+}
+
+/// Read the format strings from an ELF file, which has to have a [`FORMAT_SECTION`].
+pub fn load_format_table(elf: &[u8]) -> Result<FormatTable> {
+    match FormatTable::parse(elf)? {
+        Some(formats) => Ok(formats),
+        None => bail!("The program has no {FORMAT_SECTION} section with format strings"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FormatTable, PrintfDecoder};
+
+    fn formats() -> FormatTable {
+        FormatTable {
+            address: 0,
+            data: b"temp=%d.%02u%%\0%-6s|%5.1f|%#x|%c\0%*d %e %g %g\0".to_vec(),
+        }
+    }
+
+    #[test]
+    fn messages_are_expanded() {
+        let formats = formats();
+        let mut decoder = PrintfDecoder::default();
+
+        // -3 is sent as a 32-bit value.
+        decoder.received(&[0x00, 0xfd, 0xff, 0xff, 0xff, 0x0f, 0x05]);
+        assert_eq!(decoder.decode(&formats).unwrap().unwrap(), "temp=-3.05%");
+
+        let mut message = vec![0x0f, 0x02, b'o', b'k'];
+        let mut float = 2.375f64.to_bits();
+        while float >= 0x80 {
+            message.push(float as u8 | 0x80);
+            float >>= 7;
+        }
+        message.push(float as u8);
+        message.extend([0xac, 0x02, b'A']);
+        // An incomplete message waits for the rest.
+        decoder.received(&message[..5]);
+        assert_eq!(decoder.decode(&formats).unwrap(), None);
+        decoder.received(&message[5..]);
+        assert_eq!(
+            decoder.decode(&formats).unwrap().unwrap(),
+            "ok    |  2.4|0x12c|A"
+        );
+    }
+
+    #[test]
+    fn floats_and_widths_are_formatted_as_in_c() {
+        let formats = formats();
+        let mut decoder = PrintfDecoder::default();
+        let varint = |mut value: u64| {
+            let mut bytes = Vec::new();
+            while value >= 0x80 {
+                bytes.push(value as u8 | 0x80);
+                value >>= 7;
+            }
+            bytes.push(value as u8);
+            bytes
+        };
+
+        let mut message = varint(0x21);
+        message.extend(varint(4));
+        message.extend(varint(7));
+        message.extend(varint(1500.0f64.to_bits()));
+        message.extend(varint(0.0001f64.to_bits()));
+        message.extend(varint(1234567.0f64.to_bits()));
+        decoder.received(&message);
+        assert_eq!(
+            decoder.decode(&formats).unwrap().unwrap(),
+            "   7 1.500000e+03 0.0001 1.23457e+06"
+        );
+
+        // An unknown ID drops the data.
+        decoder.received(&[0x7f, 0x00]);
+        assert!(decoder.decode(&formats).is_err());
+        assert_eq!(decoder.decode(&formats).unwrap(), None);
+    }
+}
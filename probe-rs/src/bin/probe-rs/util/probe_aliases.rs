@@ -0,0 +1,105 @@
+//! User-defined names for debug probes.
+//!
+//! Racks with many identical probes are hard to address by VID and PID alone, so probes can be given a
+//! name in the `probes.toml` file in the probe-rs configuration directory (e.g. `~/.config/probe-rs/probes.toml`):
+//!
+//! ```toml
+//! [aliases]
+//! nrf-left = "1366:1015:000683000000"
+//! stm-bench-3 = "0483:374b@1-4.2"
+//! ```
+//!
+//! The name can then be used everywhere a probe selector is accepted, e.g. `probe-rs info --probe stm-bench-3`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use figment::{
+    providers::{Format, Toml},
+    Figment,
+};
+use probe_rs::DebugProbeSelector;
+use serde::Deserialize;
+
+/// The contents of the `probes.toml` file.
+#[derive(Debug, Default, Deserialize)]
+struct ProbeAliases {
+    #[serde(default)]
+    aliases: HashMap<String, DebugProbeSelector>,
+}
+
+/// The location of the `probes.toml` file.
+pub(crate) fn probes_file() -> Option<PathBuf> {
+    directories::ProjectDirs::from("rs", "probe-rs", "probe-rs")
+        .map(|dirs| dirs.config_dir().join("probes.toml"))
+}
+
+fn load_aliases(figment: Figment) -> Result<ProbeAliases> {
+    figment
+        .extract()
+        .context("Failed to parse the probe aliases")
+}
+
+fn resolve(aliases: &ProbeAliases, input: &str) -> Result<DebugProbeSelector> {
+    match input.parse::<DebugProbeSelector>() {
+        Ok(selector) => Ok(selector),
+        Err(error) => aliases.aliases.get(input).cloned().ok_or_else(|| {
+            anyhow!("'{input}' is neither a probe selector nor a known probe alias: {error}")
+        }),
+    }
+}
+
+/// Parse a probe selector, in the form `VID:PID[:Serial][@Path]`, or look up a probe alias.
+///
+/// This is used as the clap value parser for the `--probe` arguments.
+pub fn parse_probe_selector(input: &str) -> Result<DebugProbeSelector> {
+    // Only read the aliases if they are actually needed, so that a broken file does not affect explicit selectors.
+    if let Ok(selector) = input.parse::<DebugProbeSelector>() {
+        return Ok(selector);
+    }
+
+    let aliases = match probes_file() {
+        Some(path) if path.exists() => load_aliases(Figment::from(Toml::file(&path)))
+            .with_context(|| format!("Failed to read {}", path.display()))?,
+        _ => ProbeAliases::default(),
+    };
+
+    resolve(&aliases, input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn aliases() -> ProbeAliases {
+        load_aliases(Figment::from(Toml::string(
+            r#"
+            [aliases]
+            bench-3 = "0483:374b@1-4.2"
+            "#,
+        )))
+        .unwrap()
+    }
+
+    #[test]
+    fn alias_is_resolved() {
+        let selector = resolve(&aliases(), "bench-3").unwrap();
+
+        assert_eq!(selector.vendor_id, 0x0483);
+        assert_eq!(selector.product_id, 0x374b);
+        assert_eq!(selector.usb_path.as_deref(), Some("1-4.2"));
+    }
+
+    #[test]
+    fn selector_takes_precedence() {
+        let selector = resolve(&aliases(), "1234:5678:bench-3").unwrap();
+
+        assert_eq!(selector.serial_number.as_deref(), Some("bench-3"));
+    }
+
+    #[test]
+    fn unknown_alias_fails() {
+        assert!(resolve(&aliases(), "bench-4").is_err());
+    }
+}
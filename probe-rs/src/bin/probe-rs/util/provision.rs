@@ -0,0 +1,374 @@
+//! Production provisioning.
+//!
+//! In production, every device gets its own data on top of the firmware image: a serial number, keys or
+//! calibration values. This data is taken from a manifest, in which every device is identified by its unique
+//! device ID, and is flashed together with the image, e.g. into the UICR or OTP area of the chip.
+//!
+//! The manifest is a CSV file, whose first column holds the unique IDs and whose other columns are headed by a
+//! field description, or a JSON file with the same information:
+//!
+//! ```text
+//! unique_id,serial@0x10001080:u32,key@0x10001090
+//! 0011223344556677,1001,000102030405060708090a0b0c0d0e0f
+//! ```
+//!
+//! ```json
+//! {
+//!   "fields": ["serial@0x10001080:u32", "key@0x10001090"],
+//!   "devices": {
+//!     "0011223344556677": { "serial": 1001, "key": "000102030405060708090a0b0c0d0e0f" }
+//!   }
+//! }
+//! ```
+//!
+//! A field is described by `<name>@<address>`, optionally followed by `:u8`, `:u16`, `:u32` or `:u64`.
+//! Without a type, the value is a hex string of the bytes to write, in address order. With a type, the value
+//! is an integer, which is written little-endian.
+//!
+//! Every provisioned device is appended to a record file as a line of JSON, so that it can be traced later
+//! which data went onto which device.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use probe_rs::{flashing::FlashLoader, Session};
+use serde::{Deserialize, Serialize};
+
+/// A field of the per-device data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Field {
+    name: String,
+    address: u64,
+    /// The size of an integer field in bytes, or `None` for a field given as hex bytes.
+    integer_size: Option<usize>,
+}
+
+impl Field {
+    /// Parse a field description like `serial@0x10001080:u32`.
+    fn parse(description: &str) -> Result<Self> {
+        let (name, location) = description.trim().split_once('@').ok_or_else(|| {
+            anyhow!("The field '{description}' has no address, expected '<name>@<address>'")
+        })?;
+
+        let (address, integer_size) = match location.split_once(':') {
+            Some((address, kind)) => {
+                let size = match kind {
+                    "u8" => 1,
+                    "u16" => 2,
+                    "u32" => 4,
+                    "u64" => 8,
+                    _ => bail!("The field '{name}' has the unknown type '{kind}'"),
+                };
+                (address, Some(size))
+            }
+            None => (location, None),
+        };
+
+        let address = super::parse_u64(address)
+            .with_context(|| format!("The field '{name}' has the invalid address '{address}'"))?;
+
+        Ok(Field {
+            name: name.to_string(),
+            address,
+            integer_size,
+        })
+    }
+
+    /// Convert a value of the manifest into the bytes which are written.
+    fn encode(&self, value: &str) -> Result<Vec<u8>> {
+        let value = value.trim();
+        match self.integer_size {
+            Some(size) => {
+                let integer = super::parse_u64(value).with_context(|| {
+                    format!(
+                        "The value '{value}' of the field '{}' is not an integer",
+                        self.name
+                    )
+                })?;
+                if size < 8 && integer >> (size * 8) != 0 {
+                    bail!(
+                        "The value {integer} of the field '{}' does not fit into {size} bytes",
+                        self.name
+                    );
+                }
+                Ok(integer.to_le_bytes()[..size].to_vec())
+            }
+            None => {
+                let hex = value.strip_prefix("0x").unwrap_or(value);
+                if hex.len() % 2 != 0 {
+                    bail!(
+                        "The value of the field '{}' has an odd number of hex digits",
+                        self.name
+                    );
+                }
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|index| {
+                        u8::from_str_radix(&hex[index..index + 2], 16).with_context(|| {
+                            format!(
+                                "The value '{value}' of the field '{}' is not hex",
+                                self.name
+                            )
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The per-device data of a production run.
+#[derive(Debug)]
+pub struct Manifest {
+    fields: Vec<Field>,
+    /// The values of every device, in the order of the fields, by the lowercase unique ID.
+    devices: HashMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct JsonManifest {
+    fields: Vec<String>,
+    devices: HashMap<String, HashMap<String, serde_json::Value>>,
+}
+
+impl Manifest {
+    /// Load a manifest. Files with the `json` extension are read as JSON, all others as CSV.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the manifest {}", path.display()))?;
+
+        if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+            Self::parse_json(&contents)
+        } else {
+            Self::parse_csv(&contents)
+        }
+        .with_context(|| format!("Failed to parse the manifest {}", path.display()))
+    }
+
+    fn parse_csv(contents: &str) -> Result<Self> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        let Some((_, header)) = lines.next() else {
+            bail!("The manifest is empty");
+        };
+        let fields = header
+            .split(',')
+            .skip(1)
+            .map(Field::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut devices = HashMap::new();
+        for (index, line) in lines {
+            let mut columns = line.split(',').map(str::trim);
+            let unique_id = columns.next().unwrap_or_default().to_ascii_lowercase();
+            let values: Vec<String> = columns.map(str::to_string).collect();
+
+            if values.len() != fields.len() {
+                bail!(
+                    "Line {} has {} values, but there are {} fields",
+                    index + 1,
+                    values.len(),
+                    fields.len()
+                );
+            }
+            if devices.insert(unique_id.clone(), values).is_some() {
+                bail!("The device {unique_id} is listed more than once");
+            }
+        }
+
+        Ok(Manifest { fields, devices })
+    }
+
+    fn parse_json(contents: &str) -> Result<Self> {
+        let manifest: JsonManifest = serde_json::from_str(contents)?;
+        let fields = manifest
+            .fields
+            .iter()
+            .map(|field| Field::parse(field))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut devices = HashMap::new();
+        for (unique_id, mut device) in manifest.devices {
+            let values = fields
+                .iter()
+                .map(|field| match device.remove(&field.name) {
+                    Some(serde_json::Value::String(value)) => Ok(value),
+                    Some(serde_json::Value::Number(value)) => Ok(value.to_string()),
+                    Some(_) => bail!(
+                        "The field '{}' of the device {unique_id} is neither a string nor a number",
+                        field.name
+                    ),
+                    None => bail!(
+                        "The device {unique_id} has no value for the field '{}'",
+                        field.name
+                    ),
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            if let Some(name) = device.keys().next() {
+                bail!("The device {unique_id} has a value for the unknown field '{name}'");
+            }
+            devices.insert(unique_id.to_ascii_lowercase(), values);
+        }
+
+        Ok(Manifest { fields, devices })
+    }
+
+    /// The data of the device with the given unique ID, as (field, bytes) pairs.
+    fn device_data(&self, unique_id: &str) -> Result<Vec<(&Field, Vec<u8>)>> {
+        let values = self
+            .devices
+            .get(unique_id)
+            .ok_or_else(|| anyhow!("The device {unique_id} is not listed in the manifest"))?;
+
+        self.fields
+            .iter()
+            .zip(values)
+            .map(|(field, value)| Ok((field, field.encode(value)?)))
+            .collect()
+    }
+}
+
+/// A field written to a device.
+#[derive(Debug, Serialize)]
+pub struct WrittenField {
+    pub name: String,
+    pub address: u64,
+    /// The written bytes, as a hex string.
+    pub data: String,
+}
+
+/// What was written to a device, as stored in the record file.
+#[derive(Debug, Serialize)]
+pub struct ProvisioningRecord {
+    pub chip: String,
+    pub unique_id: String,
+    /// The time of provisioning, as a Unix timestamp.
+    pub timestamp: i64,
+    pub fields: Vec<WrittenField>,
+}
+
+/// Add the data of the connected device from the manifest to the loader.
+///
+/// The device has to be listed in the manifest, so that no device leaves production without its data.
+pub fn add_device_data(
+    session: &mut Session,
+    loader: &mut FlashLoader,
+    manifest_path: &Path,
+) -> Result<ProvisioningRecord> {
+    let manifest = Manifest::load(manifest_path)?;
+
+    let unique_id = session
+        .read_unique_id()?
+        .ok_or_else(|| {
+            anyhow!(
+                "The location of the unique ID of {} is not known, so the device cannot be looked up in the manifest",
+                session.target().name
+            )
+        })?
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    let mut fields = Vec::new();
+    for (field, bytes) in manifest.device_data(&unique_id)? {
+        tracing::info!(
+            "Provisioning {} ({} bytes) at {:#010x}",
+            field.name,
+            bytes.len(),
+            field.address
+        );
+        loader
+            .add_data(field.address, &bytes)
+            .with_context(|| format!("Failed to add the field '{}'", field.name))?;
+
+        fields.push(WrittenField {
+            name: field.name.clone(),
+            address: field.address,
+            data: bytes.iter().map(|byte| format!("{byte:02x}")).collect(),
+        });
+    }
+
+    Ok(ProvisioningRecord {
+        chip: session.target().name.clone(),
+        unique_id,
+        timestamp: time::OffsetDateTime::now_utc().unix_timestamp(),
+        fields,
+    })
+}
+
+/// The default record file of a manifest, `<manifest>.record.jsonl`.
+pub fn default_record_path(manifest_path: &Path) -> PathBuf {
+    let mut path = manifest_path.as_os_str().to_owned();
+    path.push(".record.jsonl");
+    path.into()
+}
+
+/// Append the record of a provisioned device to the record file.
+pub fn append_record(path: &Path, record: &ProvisioningRecord) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open the provisioning record {}", path.display()))?;
+
+    writeln!(file, "{}", serde_json::to_string(record)?)
+        .with_context(|| format!("Failed to write the provisioning record {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_and_json_manifests_are_equivalent() {
+        let csv = Manifest::parse_csv(
+            "unique_id,serial@0x10001080:u32,key@0x10001090\n\
+             0011223344556677,1001,0x000102\n\
+             8899AABBCCDDEEFF,0x1002,030405\n",
+        )
+        .unwrap();
+        let json = Manifest::parse_json(
+            r#"{
+                "fields": ["serial@0x10001080:u32", "key@0x10001090"],
+                "devices": {
+                    "0011223344556677": { "serial": 1001, "key": "000102" },
+                    "8899aabbccddeeff": { "serial": "0x1002", "key": "030405" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        for manifest in [csv, json] {
+            let data = manifest.device_data("8899aabbccddeeff").unwrap();
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].0.address, 0x10001080);
+            assert_eq!(data[0].1, vec![0x02, 0x10, 0x00, 0x00]);
+            assert_eq!(data[1].1, vec![0x03, 0x04, 0x05]);
+
+            let data = manifest.device_data("0011223344556677").unwrap();
+            assert_eq!(data[0].1, 1001u32.to_le_bytes().to_vec());
+            assert_eq!(data[1].1, vec![0x00, 0x01, 0x02]);
+
+            assert!(manifest.device_data("0000000000000000").is_err());
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let field = Field::parse("version@0x1000:u8").unwrap();
+        assert!(field.encode("256").is_err());
+
+        let field = Field::parse("key@0x1000").unwrap();
+        assert!(field.encode("123").is_err());
+        assert!(field.encode("zz").is_err());
+
+        assert!(Field::parse("serial").is_err());
+        assert!(Field::parse("serial@0x1000:i32").is_err());
+    }
+}
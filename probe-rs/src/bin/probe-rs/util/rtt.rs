@@ -1,3 +1,5 @@
+use crate::util::printf::{FormatTable, PrintfDecoder, FORMAT_SECTION};
+use crate::util::rtt_plot::{PlotDecoder, PlotFrame};
 use crate::*;
 use anyhow::{anyhow, Result};
 use defmt_decoder::DecodeError;
@@ -9,6 +11,8 @@ use probe_rs::Core;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs::File;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
 use std::{
     fmt,
     fmt::Write,
@@ -30,10 +34,10 @@ pub fn attach_to_rtt(
         if let Some(address) = RttActiveTarget::get_rtt_symbol(&mut file) {
             ScanRegion::Exact(address as u32)
         } else {
-            ScanRegion::Ram
+            rtt_config.scan_region().unwrap_or(ScanRegion::Ram)
         }
     } else {
-        ScanRegion::Ram
+        rtt_config.scan_region().unwrap_or(ScanRegion::Ram)
     };
 
     match Rtt::attach_region(core, memory_map, &rtt_header_address) {
@@ -63,6 +67,11 @@ pub enum DataFormat {
     String,
     BinaryLE,
     Defmt,
+    /// The plotting protocol of [`rtt_plot`](crate::util::rtt_plot).
+    Plot,
+    /// Format string IDs and arguments, which are expanded with the format strings of the ELF file, see
+    /// [`printf`](crate::util::printf).
+    Printf,
 }
 impl FromStr for DataFormat {
     type Err = String;
@@ -74,6 +83,8 @@ impl FromStr for DataFormat {
             "string" => Ok(Self::String),
             "binaryle" => Ok(Self::BinaryLE),
             "defmt" => Ok(Self::Defmt),
+            "plot" => Ok(Self::Plot),
+            "printf" => Ok(Self::Printf),
             _ => Err(format!("{src} is not a valid format")),
         }
     }
@@ -89,6 +100,19 @@ pub struct RttConfig {
     #[structopt(skip)]
     #[serde(default = "default_channel_formats", rename = "rttChannelFormats")]
     pub channels: Vec<RttChannelConfig>,
+    /// The RAM range which is scanned for the control block, if the ELF file has no `_SEGGER_RTT` symbol.
+    #[structopt(skip)]
+    #[serde(default, rename = "rttScanRegion")]
+    pub scan_region: Option<std::ops::Range<u64>>,
+}
+
+impl RttConfig {
+    /// The region which is scanned for the control block, if the ELF file does not tell its address.
+    pub fn scan_region(&self) -> Option<ScanRegion> {
+        self.scan_region
+            .as_ref()
+            .map(|range| ScanRegion::Range(range.start as u32..range.end as u32))
+    }
 }
 
 /// The User specified configuration for each active RTT Channel. The configuration is passed via a DAP Client configuration (`launch.json`). If no configuration is specified, the defaults will be `Dataformat::String` and `show_timestamps=false`.
@@ -107,6 +131,10 @@ pub struct RttChannelConfig {
     #[serde(default = "default_include_location")]
     // Control the inclusion of source location information for DataFormat::Defmt.
     pub show_location: bool,
+    #[structopt(skip)]
+    #[serde(default)]
+    // The local UDP address to which the decoded frames of DataFormat::Plot are sent as JSON, one per datagram.
+    pub plot_socket: Option<SocketAddr>,
 }
 
 /// This is the primary interface through which RTT channel data is read and written. Every actual RTT channel has a configuration and buffer that is used for this purpose.
@@ -127,6 +155,28 @@ pub struct RttActiveChannel {
     /// Getting the offset can fail in multi-threaded programs,
     /// so it needs to be stored.
     timestamp_offset: UtcOffset,
+
+    /// The decoded defmt frames, if they are recorded.
+    recorded_defmt_frames: Option<Vec<DefmtFrame>>,
+
+    plot_decoder: PlotDecoder,
+    /// The socket to which the decoded plot frames are sent, if one is configured.
+    plot_socket: Option<UdpSocket>,
+    /// The decoded plot frames, if they are recorded.
+    recorded_plot_frames: Option<Vec<PlotFrame>>,
+
+    printf_decoder: PrintfDecoder,
+    /// The format strings of the program, for [`DataFormat::Printf`].
+    printf_formats: Option<Arc<FormatTable>>,
+}
+
+/// A defmt log message, as it was decoded from an RTT channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefmtFrame {
+    /// The timestamp of the message, as it is displayed by defmt.
+    pub timestamp: Option<String>,
+    pub level: Option<&'static str>,
+    pub message: String,
 }
 
 /// A fully configured RttActiveChannel. The configuration will always try to 'default' based on information read from the RTT control block in the binary. Where insufficient information is available, it will use the supplied configuration, with final hardcoded defaults where no other information was available.
@@ -183,6 +233,16 @@ impl RttActiveChannel {
                     full_config.channel_number.unwrap_or(0)
                 )
             });
+        let plot_socket = full_config
+            .plot_socket
+            .filter(|_| data_format == DataFormat::Plot)
+            .and_then(|address| match connect_plot_socket(address) {
+                Ok(socket) => Some(socket),
+                Err(err) => {
+                    log::warn!("Failed to open the plot socket for {}: {}", address, err);
+                    None
+                }
+            });
         Self {
             up_channel,
             down_channel,
@@ -193,6 +253,12 @@ impl RttActiveChannel {
             show_timestamps: full_config.show_timestamps,
             show_location,
             timestamp_offset,
+            recorded_defmt_frames: None,
+            plot_decoder: PlotDecoder::default(),
+            plot_socket,
+            recorded_plot_frames: None,
+            printf_decoder: PrintfDecoder::default(),
+            printf_formats: None,
         }
     }
 
@@ -270,6 +336,13 @@ impl RttActiveChannel {
                                             match stream_decoder.decode() {
                                                 Ok(frame) => {
                                                     let loc = locs.as_ref().and_then(|locs| locs.get(&frame.index()) );
+                                                    if let Some(frames) = &mut self.recorded_defmt_frames {
+                                                        frames.push(DefmtFrame {
+                                                            timestamp: frame.display_timestamp().map(|timestamp| timestamp.to_string()),
+                                                            level: frame.level().map(|level| level.as_str()),
+                                                            message: frame.display_message().to_string(),
+                                                        });
+                                                    }
                                                     writeln!(formatted_data, "{}", frame.display(false)).map_or_else(|err| log::error!("Failed to format RTT data - {:?}", err), |r|r);
                                                     if self.show_location {
                                                         if let Some(loc) = loc {
@@ -311,6 +384,51 @@ impl RttActiveChannel {
                                     }
                                 }
                             }
+                            DataFormat::Printf => match &self.printf_formats {
+                                Some(formats) => {
+                                    self.printf_decoder.received(&self.rtt_buffer.0[..bytes_read]);
+                                    loop {
+                                        match self.printf_decoder.decode(formats) {
+                                            Ok(Some(message)) => {
+                                                if self.show_timestamps {
+                                                    write!(formatted_data, "{} :", OffsetDateTime::now_utc().to_offset(self.timestamp_offset))
+                                                        .map_or_else(|err| log::error!("Failed to format RTT data - {:?}", err), |r|r);
+                                                }
+                                                writeln!(formatted_data, "{message}").map_or_else(|err| log::error!("Failed to format RTT data - {:?}", err), |r|r);
+                                            }
+                                            Ok(None) => break,
+                                            // The decoder drops the malformed data, and continues with the next message.
+                                            Err(err) => log::warn!("Failed to decode RTT printf data - {:?}", err),
+                                        }
+                                    }
+                                }
+                                None => {
+                                    write!(formatted_data, "Running rtt in printf mode but the format strings could not be loaded.")
+                                        .map_or_else(|err| log::error!("Failed to format RTT data - {:?}", err), |r|r);
+                                }
+                            },
+                            DataFormat::Plot => {
+                                self.plot_decoder.received(&self.rtt_buffer.0[..bytes_read]);
+                                loop {
+                                    match self.plot_decoder.decode() {
+                                        Ok(Some(frame)) => {
+                                            self.write_plot_frame(&mut formatted_data, &frame);
+                                            if let Some(socket) = &self.plot_socket {
+                                                // The receiver may not be listening yet, so failed sends are not reported.
+                                                if let Ok(json) = serde_json::to_vec(&frame) {
+                                                    socket.send(&json).ok();
+                                                }
+                                            }
+                                            if let Some(frames) = &mut self.recorded_plot_frames {
+                                                frames.push(frame);
+                                            }
+                                        }
+                                        Ok(None) => break,
+                                        // The decoder drops the malformed data, and continues with the next declaration.
+                                        Err(err) => log::warn!("Failed to decode RTT plot data - {:?}", err),
+                                    }
+                                }
+                            }
                         };
                         formatted_data
                     }
@@ -318,6 +436,38 @@ impl RttActiveChannel {
             }).transpose()
     }
 
+    /// Write a decoded plot frame as a line of text, with the values labelled by the names of their signals.
+    fn write_plot_frame(&self, formatted_data: &mut String, frame: &PlotFrame) {
+        let line = match frame {
+            PlotFrame::Declaration { signals } => signals
+                .iter()
+                .map(|signal| format!("{} ({:?})", signal.name, signal.signal_type))
+                .collect::<Vec<_>>()
+                .join(", "),
+            PlotFrame::Sample { values, .. } => self
+                .plot_decoder
+                .signals()
+                .unwrap_or_default()
+                .iter()
+                .zip(values)
+                .map(|(signal, value)| format!("{}={}", signal.name, value))
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        writeln!(formatted_data, "{line}").map_or_else(
+            |err| log::error!("Failed to format RTT data - {:?}", err),
+            |r| r,
+        );
+    }
+
+    /// The plot frames which were decoded since the previous call, if they are recorded.
+    pub fn take_plot_frames(&mut self) -> Vec<PlotFrame> {
+        self.recorded_plot_frames
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
     pub fn _push_rtt(&mut self, core: &mut Core) {
         if let Some(down_channel) = self.down_channel.as_mut() {
             self._input_data += "\n";
@@ -420,6 +570,35 @@ impl RttActiveTarget {
             None
         };
 
+        if active_channels
+            .iter()
+            .any(|channel| channel.data_format == DataFormat::Printf)
+        {
+            let formats = fs::read(elf_file)
+                .map_err(|err| {
+                    anyhow!(
+                        "Error reading program binary while initalizing RTT: {}",
+                        err
+                    )
+                })
+                .and_then(|elf| FormatTable::parse(&elf));
+            match formats {
+                Ok(Some(formats)) => {
+                    let formats = Arc::new(formats);
+                    for channel in &mut active_channels {
+                        if channel.data_format == DataFormat::Printf {
+                            channel.printf_formats = Some(formats.clone());
+                        }
+                    }
+                }
+                Ok(None) => log::warn!(
+                    "The program has no {} section, so the printf messages can not be expanded.",
+                    FORMAT_SECTION
+                ),
+                Err(err) => log::warn!("Failed to read the printf format strings: {}", err),
+            }
+        }
+
         Ok(Self {
             active_channels,
             defmt_state,
@@ -444,6 +623,32 @@ impl RttActiveTarget {
         None
     }
 
+    /// Keep the decoded defmt frames of all channels, to be retrieved with [`RttActiveTarget::take_defmt_frames`].
+    pub fn record_defmt_frames(&mut self) {
+        for channel in &mut self.active_channels {
+            channel.recorded_defmt_frames.get_or_insert_with(Vec::new);
+        }
+    }
+
+    /// Keep the decoded frames of the channels with [`DataFormat::Plot`], to be retrieved with
+    /// [`RttActiveChannel::take_plot_frames`].
+    pub fn record_plot_frames(&mut self) {
+        for channel in &mut self.active_channels {
+            if channel.data_format == DataFormat::Plot {
+                channel.recorded_plot_frames.get_or_insert_with(Vec::new);
+            }
+        }
+    }
+
+    /// The defmt frames which were decoded since the previous call, if they are recorded.
+    pub fn take_defmt_frames(&mut self) -> Vec<DefmtFrame> {
+        self.active_channels
+            .iter_mut()
+            .filter_map(|channel| channel.recorded_defmt_frames.as_mut())
+            .flat_map(std::mem::take)
+            .collect()
+    }
+
     /// Polls the RTT target on all channels and returns available data.
     /// An error on any channel will return an error instead of incomplete data.
     pub fn poll_rtt_fallible(
@@ -465,6 +670,18 @@ impl RttActiveTarget {
     // }
 }
 
+/// Open a UDP socket which sends to the local `address`.
+fn connect_plot_socket(address: SocketAddr) -> std::io::Result<UdpSocket> {
+    let local: SocketAddr = if address.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    Ok(socket)
+}
+
 struct RttBuffer(Vec<u8>);
 impl RttBuffer {
     /// Initialize the buffer and ensure it has enough capacity to match the size of the RTT channel on the target at the time of instantiation. Doing this now prevents later performance impact if the buffer capacity has to be grown dynamically.
@@ -0,0 +1,266 @@
+//! Decode the plotting protocol, with which the firmware streams the values of signals over an RTT channel.
+//!
+//! The firmware first declares its signals, and then sends samples with one value of each signal. Every frame starts
+//! with a tag byte:
+//!
+//! - `0x01` declares the signals: the number of signals as `u8`, then for each signal its [`SignalType`] as `u8`, the
+//!   length of its name as `u8` and the name in UTF-8.
+//! - `0x02` is a sample: the value of every declared signal in the order of the declaration, little endian.
+//!
+//! A declaration replaces the previous one, so the firmware declares its signals again after a reset. The frames have
+//! no delimiter, so a malformed frame drops the received bytes, and samples are ignored until the next declaration.
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+
+const DECLARATION: u8 = 0x01;
+const SAMPLE: u8 = 0x02;
+
+/// The type of the values of a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SignalType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    F64,
+}
+
+impl SignalType {
+    fn from_tag(tag: u8) -> Option<Self> {
+        Some(match tag {
+            0 => Self::U8,
+            1 => Self::I8,
+            2 => Self::U16,
+            3 => Self::I16,
+            4 => Self::U32,
+            5 => Self::I32,
+            6 => Self::F32,
+            7 => Self::F64,
+            _ => return None,
+        })
+    }
+
+    /// The number of bytes of a value.
+    fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Decode a value from exactly [`SignalType::size`] bytes.
+    fn decode(self, bytes: &[u8]) -> f64 {
+        match self {
+            Self::U8 => bytes[0] as f64,
+            Self::I8 => bytes[0] as i8 as f64,
+            Self::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Self::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Self::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+}
+
+/// A signal which the firmware declared.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Signal {
+    pub name: String,
+    pub signal_type: SignalType,
+}
+
+/// A decoded frame of the plotting protocol.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "frame", rename_all = "camelCase")]
+pub enum PlotFrame {
+    /// The firmware declared its signals.
+    Declaration { signals: Vec<Signal> },
+    /// The values of the declared signals, in the order of the declaration.
+    Sample {
+        /// The number of the sample since the last declaration.
+        index: u64,
+        values: Vec<f64>,
+    },
+}
+
+/// Decodes the frames of the plotting protocol from the bytes of an RTT channel.
+#[derive(Debug, Default)]
+pub struct PlotDecoder {
+    /// The received bytes which are not decoded yet.
+    buffer: Vec<u8>,
+    /// The declared signals, `None` before the first declaration or after a malformed frame.
+    signals: Option<Vec<Signal>>,
+    samples: u64,
+}
+
+impl PlotDecoder {
+    /// Add bytes which were read from the channel.
+    pub fn received(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The declared signals, if they are known.
+    pub fn signals(&self) -> Option<&[Signal]> {
+        self.signals.as_deref()
+    }
+
+    /// Decode the next complete frame, `Ok(None)` if more bytes are needed.
+    pub fn decode(&mut self) -> Result<Option<PlotFrame>> {
+        let result = self.decode_frame();
+        if result.is_err() {
+            self.buffer.clear();
+            self.signals = None;
+        }
+        result
+    }
+
+    fn decode_frame(&mut self) -> Result<Option<PlotFrame>> {
+        let Some(&tag) = self.buffer.first() else {
+            return Ok(None);
+        };
+        match tag {
+            DECLARATION => {
+                let Some((signals, length)) = parse_declaration(&self.buffer[1..])? else {
+                    return Ok(None);
+                };
+                self.buffer.drain(..1 + length);
+                self.signals = Some(signals.clone());
+                self.samples = 0;
+                Ok(Some(PlotFrame::Declaration { signals }))
+            }
+            SAMPLE => {
+                let signals = self.signals.as_ref().ok_or_else(|| {
+                    anyhow!("Received a plot sample before the signals were declared")
+                })?;
+                let length = signals
+                    .iter()
+                    .map(|signal| signal.signal_type.size())
+                    .sum::<usize>();
+                if self.buffer.len() < 1 + length {
+                    return Ok(None);
+                }
+
+                let mut offset = 1;
+                let values = signals
+                    .iter()
+                    .map(|signal| {
+                        let size = signal.signal_type.size();
+                        let value = signal
+                            .signal_type
+                            .decode(&self.buffer[offset..offset + size]);
+                        offset += size;
+                        value
+                    })
+                    .collect();
+                self.buffer.drain(..offset);
+                let index = self.samples;
+                self.samples += 1;
+                Ok(Some(PlotFrame::Sample { index, values }))
+            }
+            tag => bail!("Received a plot frame with the unknown tag {tag:#04x}"),
+        }
+    }
+}
+
+/// Parse the signals of a declaration, and the number of bytes they take, `Ok(None)` if it is incomplete.
+fn parse_declaration(bytes: &[u8]) -> Result<Option<(Vec<Signal>, usize)>> {
+    let Some(&count) = bytes.first() else {
+        return Ok(None);
+    };
+    let mut signals = Vec::with_capacity(count as usize);
+    let mut offset = 1;
+    for _ in 0..count {
+        let Some(&[signal_type, length]) = bytes.get(offset..offset + 2) else {
+            return Ok(None);
+        };
+        let signal_type = SignalType::from_tag(signal_type)
+            .ok_or_else(|| anyhow!("Received a plot signal with the unknown type {signal_type}"))?;
+        offset += 2;
+        let Some(name) = bytes.get(offset..offset + length as usize) else {
+            return Ok(None);
+        };
+        let name = std::str::from_utf8(name)
+            .map_err(|_| anyhow!("Received a plot signal whose name is not UTF-8"))?;
+        offset += length as usize;
+        signals.push(Signal {
+            name: name.to_string(),
+            signal_type,
+        });
+    }
+    Ok(Some((signals, offset)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PlotDecoder, PlotFrame, Signal, SignalType};
+
+    #[test]
+    fn frames_are_decoded_across_reads() {
+        let mut decoder = PlotDecoder::default();
+        let mut bytes = vec![0x01, 2, 6, 5];
+        bytes.extend_from_slice(b"speed");
+        bytes.extend_from_slice(&[3, 7]);
+        bytes.extend_from_slice(b"current");
+        bytes.push(0x02);
+        bytes.extend_from_slice(&1.5f32.to_le_bytes());
+        bytes.extend_from_slice(&(-2i16).to_le_bytes());
+
+        // The sample is split between two reads.
+        decoder.received(&bytes[..bytes.len() - 1]);
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(PlotFrame::Declaration {
+                signals: vec![
+                    Signal {
+                        name: "speed".to_string(),
+                        signal_type: SignalType::F32,
+                    },
+                    Signal {
+                        name: "current".to_string(),
+                        signal_type: SignalType::I16,
+                    },
+                ]
+            })
+        );
+        assert_eq!(decoder.decode().unwrap(), None);
+
+        decoder.received(&bytes[bytes.len() - 1..]);
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(PlotFrame::Sample {
+                index: 0,
+                values: vec![1.5, -2.0],
+            })
+        );
+    }
+
+    #[test]
+    fn samples_are_dropped_until_the_next_declaration() {
+        let mut decoder = PlotDecoder::default();
+        decoder.received(&[0x02, 1, 2]);
+        assert!(decoder.decode().is_err());
+        assert_eq!(decoder.decode().unwrap(), None);
+
+        decoder.received(&[0x01, 1, 0, 1, b'x', 0x02, 42]);
+        assert!(matches!(
+            decoder.decode().unwrap(),
+            Some(PlotFrame::Declaration { .. })
+        ));
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(PlotFrame::Sample {
+                index: 0,
+                values: vec![42.0],
+            })
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Minimal support for ARM semihosting calls made by the firmware on the target.
+//!
+//! Only the operations needed to run tests and to report the exit status of the firmware are supported.
+
+use anyhow::{anyhow, Result};
+use probe_rs::{Architecture, Core, MemoryInterface, StopReason};
+
+/// Semihosting operation number for `SYS_OPEN`.
+pub const SYS_OPEN: u32 = 0x01;
+/// Semihosting operation number for `SYS_WRITEC`.
+pub const SYS_WRITEC: u32 = 0x03;
+/// Semihosting operation number for `SYS_WRITE0`.
+pub const SYS_WRITE0: u32 = 0x04;
+/// Semihosting operation number for `SYS_WRITE`.
+pub const SYS_WRITE: u32 = 0x05;
+/// Semihosting operation number for `SYS_GET_CMDLINE`.
+pub const SYS_GET_CMDLINE: u32 = 0x15;
+/// Semihosting operation number for `SYS_EXIT`.
+pub const SYS_EXIT: u32 = 0x18;
+/// Semihosting operation number for `SYS_EXIT_EXTENDED`.
+pub const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// Semihosting exception reason `ADP_Stopped_ApplicationExit`, which indicates a normal exit.
+pub const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+
+/// The semihosting file handle returned for `:tt`, i.e. stdout.
+pub const STDOUT_HANDLE: u32 = 1;
+/// The return value of a failed semihosting call.
+pub const SEMIHOSTING_ERROR: u32 = u32::MAX;
+
+/// The maximum length of a string that we read from the target.
+const MAX_STRING_LENGTH: usize = 4096;
+
+/// A semihosting call, which the core is currently halted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemihostingCall {
+    /// The requested operation, e.g. [`SYS_EXIT`].
+    pub operation: u32,
+    /// The parameter of the operation. Depending on the operation, this is either a value or a pointer to a parameter block.
+    pub parameter: u32,
+    program_counter: u64,
+}
+
+impl SemihostingCall {
+    /// Check if the (halted) core is stopped at a semihosting call.
+    pub fn pending(core: &mut Core) -> Result<Option<Self>> {
+        let Some(StopReason::Semihosting { operation }) = core.stop_reason()? else {
+            return Ok(None);
+        };
+
+        Ok(Some(SemihostingCall {
+            operation,
+            parameter: core.read_core_reg(core.registers().argument_register(1))?,
+            program_counter: core.read_core_reg(core.program_counter())?,
+        }))
+    }
+
+    /// Read the `index`th word of the parameter block.
+    pub fn parameter_word(&self, core: &mut Core, index: u32) -> Result<u32> {
+        Ok(core.read_word_32(self.parameter as u64 + 4 * index as u64)?)
+    }
+
+    /// Finish the semihosting call with the given return value, and step over the instruction which triggered it.
+    ///
+    /// The core is left halted, and has to be resumed by the caller.
+    pub fn complete(self, core: &mut Core, return_value: u32) -> Result<()> {
+        core.write_core_reg(core.registers().argument_register(0), return_value)?;
+
+        let instruction_size = match core.architecture() {
+            Architecture::Arm => 2,
+            Architecture::Riscv => 4,
+        };
+        core.write_core_reg(
+            core.program_counter(),
+            (self.program_counter + instruction_size) as u32,
+        )?;
+        Ok(())
+    }
+}
+
+/// Read a zero terminated string from the target.
+pub fn read_c_string(core: &mut Core, address: u32) -> Result<String> {
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 64];
+    let mut address = address as u64;
+
+    while bytes.len() < MAX_STRING_LENGTH {
+        core.read_8(address, &mut chunk)?;
+        if let Some(end) = chunk.iter().position(|byte| *byte == 0) {
+            bytes.extend_from_slice(&chunk[..end]);
+            return Ok(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        bytes.extend_from_slice(&chunk);
+        address += chunk.len() as u64;
+    }
+
+    Err(anyhow!(
+        "String at {:#010x} is longer than {} bytes.",
+        address,
+        MAX_STRING_LENGTH
+    ))
+}
+
+/// Read `length` bytes from the target, and interpret them as an UTF-8 string.
+pub fn read_string(core: &mut Core, address: u32, length: u32) -> Result<String> {
+    let mut bytes = vec![0u8; length as usize];
+    core.read_8(address as u64, &mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Handle the semihosting calls which write to the console of the host: opening `:tt`, `SYS_WRITEC`, `SYS_WRITE0`
+/// and `SYS_WRITE` to stdout. The written text is appended to `output`.
+///
+/// Returns the return value of the call, or `None` if the call is not a console call.
+pub fn handle_console(
+    core: &mut Core,
+    call: &SemihostingCall,
+    output: &mut String,
+) -> Result<Option<u32>> {
+    let return_value = match call.operation {
+        SYS_OPEN => {
+            let name = call.parameter_word(core, 0)?;
+            let name_length = call.parameter_word(core, 2)?;
+            if read_string(core, name, name_length)? == ":tt" {
+                STDOUT_HANDLE
+            } else {
+                SEMIHOSTING_ERROR
+            }
+        }
+        SYS_WRITEC => {
+            let mut character = [0u8];
+            core.read_8(call.parameter as u64, &mut character)?;
+            output.push(character[0] as char);
+            0
+        }
+        SYS_WRITE0 => {
+            output.push_str(&read_c_string(core, call.parameter)?);
+            0
+        }
+        SYS_WRITE => {
+            let handle = call.parameter_word(core, 0)?;
+            let data = call.parameter_word(core, 1)?;
+            let length = call.parameter_word(core, 2)?;
+            if handle == STDOUT_HANDLE {
+                output.push_str(&read_string(core, data, length)?);
+                // The return value is the number of bytes which were *not* written.
+                0
+            } else {
+                length
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(return_value))
+}
@@ -0,0 +1,156 @@
+//! Stack usage analysis.
+//!
+//! The unused part of a stack is painted with a known pattern while the core is halted, ideally right after a reset.
+//! Later, the stack is read back, and the lowest word which no longer contains the pattern shows how deep the stack
+//! grew. If even the last word of the stack was overwritten, the stack most likely overflowed.
+//!
+//! The main stack is found with the symbols of `cortex-m-rt`: it grows down from `_stack_start` to the end of the
+//! static data. Other stacks, e.g. the stacks of RTOS tasks, can be given as address ranges.
+
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use probe_rs::{Core, MemoryInterface};
+use serde::Serialize;
+
+/// The pattern which the unused part of the stack is painted with.
+pub const PAINT_PATTERN: u32 = 0xCCCC_CCCC;
+
+/// The symbols which mark the lowest address of the main stack, in the order they are tried.
+const STACK_END_SYMBOLS: &[&str] = &["_stack_end", "__euninit", "__ebss"];
+
+/// The measured usage of a stack.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackUsage {
+    /// The address range of the stack, which grows down from the end of the range.
+    pub stack: Range<u64>,
+    /// The maximum number of bytes which were used since the stack was painted.
+    pub used: u64,
+    /// The lowest word of the stack was overwritten, so the stack most likely overflowed.
+    pub overflowed: bool,
+}
+
+impl StackUsage {
+    /// The size of the stack in bytes.
+    pub fn size(&self) -> u64 {
+        self.stack.end - self.stack.start
+    }
+}
+
+/// Find the main stack of a `cortex-m-rt` program.
+pub fn main_stack(elf: &Path) -> Result<Range<u64>> {
+    let buffer =
+        std::fs::read(elf).with_context(|| format!("Failed to read ELF file {}", elf.display()))?;
+    let binary = goblin::elf::Elf::parse(buffer.as_slice())?;
+
+    let symbol = |name: &str| {
+        binary
+            .syms
+            .iter()
+            .find(|sym| binary.strtab.get_at(sym.st_name) == Some(name))
+            .map(|sym| sym.st_value)
+    };
+
+    let start = symbol("_stack_start").ok_or_else(|| {
+        anyhow!(
+            "The start of the stack (`_stack_start`) was not found in {}",
+            elf.display()
+        )
+    })?;
+    let end = STACK_END_SYMBOLS
+        .iter()
+        .find_map(|name| symbol(name))
+        .ok_or_else(|| anyhow!("The end of the stack was not found in {}", elf.display()))?;
+
+    if end >= start {
+        bail!(
+            "The stack {end:#010x}..{start:#010x} found in {} is empty",
+            elf.display()
+        );
+    }
+
+    Ok(word_aligned(end..start))
+}
+
+/// Shrink the range to whole words.
+fn word_aligned(range: Range<u64>) -> Range<u64> {
+    (range.start + 3) & !0x3..range.end & !0x3
+}
+
+/// Paint the unused part of the stack, below the current stack pointer, with the [`PAINT_PATTERN`].
+///
+/// If the stack pointer is not within the stack (e.g. for the stack of a task which was not started yet),
+/// the whole stack is painted. The core has to be halted.
+pub fn paint(core: &mut Core, stack: Range<u64>) -> Result<()> {
+    let stack = word_aligned(stack);
+    let stack_pointer: u64 = core.read_core_reg(core.stack_pointer())?;
+
+    let end = if stack.contains(&stack_pointer) {
+        stack_pointer & !0x3
+    } else {
+        stack.end
+    };
+
+    let words = vec![PAINT_PATTERN; ((end - stack.start) / 4) as usize];
+    core.write_32(stack.start, &words)
+        .with_context(|| format!("Failed to paint the stack at {:#010x}", stack.start))?;
+
+    Ok(())
+}
+
+/// Measure the usage of a stack which was painted with [`paint`].
+pub fn measure(core: &mut Core, stack: Range<u64>) -> Result<StackUsage> {
+    let stack = word_aligned(stack);
+
+    let mut words = vec![0; ((stack.end - stack.start) / 4) as usize];
+    core.read_32(stack.start, &mut words)
+        .with_context(|| format!("Failed to read the stack at {:#010x}", stack.start))?;
+
+    usage(stack, &words)
+}
+
+/// Find the usage of a stack from its contents.
+fn usage(stack: Range<u64>, words: &[u32]) -> Result<StackUsage> {
+    let unused_words = words
+        .iter()
+        .take_while(|&&word| word == PAINT_PATTERN)
+        .count();
+
+    if unused_words == 0 && !words.contains(&PAINT_PATTERN) {
+        bail!(
+            "The stack at {:#010x}..{:#010x} was not painted",
+            stack.start,
+            stack.end
+        );
+    }
+
+    Ok(StackUsage {
+        used: (words.len() - unused_words) as u64 * 4,
+        overflowed: unused_words == 0,
+        stack,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn usage_is_measured_from_the_lowest_overwritten_word() {
+        let p = PAINT_PATTERN;
+
+        // Words above the lowest overwritten word which still contain the pattern count as used.
+        let measured = usage(0x100..0x118, &[p, p, 0, p, 0, 0]).unwrap();
+        assert_eq!(measured.used, 16);
+        assert_eq!(measured.size(), 24);
+        assert!(!measured.overflowed);
+
+        let measured = usage(0x100..0x118, &[0, p, 0, 0, 0, 0]).unwrap();
+        assert_eq!(measured.used, 24);
+        assert!(measured.overflowed);
+
+        assert!(usage(0x100..0x108, &[0, 0]).is_err());
+    }
+}
@@ -0,0 +1,913 @@
+//! Decode the recordings of SEGGER SystemView, which the firmware sends over RTT, into a timeline of the tasks,
+//! interrupts and markers of an RTOS.
+//!
+//! The host starts a recording by writing [`COMMAND_START`] to the `SysView` down channel. The target answers on the
+//! `SysView` up channel with ten zero bytes, to synchronize the stream, followed by the packets of the events. A
+//! packet is the ID of its event, the payload, and the time since the previous packet in ticks of the timestamp
+//! clock, whose frequency is given by the init packet. Numbers are variable length integers with 7 bits per byte, LSB
+//! first, and strings are their length in a byte, or 255 and the length in 2 bytes, followed by the bytes.
+//!
+//! The events with IDs below 24 have a payload of a fixed layout. The others give the length of their payload after
+//! their ID, so the events which aren't decoded, e.g. the ones of middleware modules, are skipped.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::util::printf;
+
+/// The name of the RTT channels of SystemView, in both directions.
+pub const CHANNEL_NAME: &str = "SysView";
+/// Starts the recording, when written to the down channel.
+pub const COMMAND_START: u8 = 1;
+/// Stops the recording.
+pub const COMMAND_STOP: u8 = 2;
+
+/// The number of zero bytes which synchronize the stream.
+const SYNC_LENGTH: usize = 10;
+/// The length of a variable length integer of 32 bits.
+const MAX_VARINT_LENGTH: usize = 5;
+/// The events below this ID have no length.
+const FIRST_EVENT_WITH_LENGTH: u32 = 24;
+
+/// The IDs of the events.
+mod event_id {
+    pub const NOP: u32 = 0;
+    pub const OVERFLOW: u32 = 1;
+    pub const ISR_ENTER: u32 = 2;
+    pub const ISR_EXIT: u32 = 3;
+    pub const TASK_START_EXEC: u32 = 4;
+    pub const TASK_STOP_EXEC: u32 = 5;
+    pub const TASK_START_READY: u32 = 6;
+    pub const TASK_STOP_READY: u32 = 7;
+    pub const TASK_CREATE: u32 = 8;
+    pub const TASK_INFO: u32 = 9;
+    pub const TRACE_START: u32 = 10;
+    pub const TRACE_STOP: u32 = 11;
+    pub const SYSTIME_CYCLES: u32 = 12;
+    pub const SYSTIME_US: u32 = 13;
+    pub const SYSDESC: u32 = 14;
+    pub const MARK_START: u32 = 15;
+    pub const MARK_STOP: u32 = 16;
+    pub const IDLE: u32 = 17;
+    pub const ISR_TO_SCHEDULER: u32 = 18;
+    pub const TIMER_ENTER: u32 = 19;
+    pub const TIMER_EXIT: u32 = 20;
+    pub const STACK_INFO: u32 = 21;
+    pub const MODULEDESC: u32 = 22;
+    pub const INIT: u32 = 24;
+    pub const PRINT_FORMATTED: u32 = 26;
+    pub const TASK_TERMINATE: u32 = 29;
+    /// An extended event, whose ID is the first number of the payload.
+    pub const EX: u32 = 31;
+    pub const EX_MARK: u32 = 0;
+    pub const EX_NAME_MARKER: u32 = 1;
+}
+
+/// An event of a recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The frequency of the timestamps, and how the IDs of tasks are made short.
+    Init {
+        frequency: u32,
+        cpu_frequency: u32,
+        ram_base: u32,
+        id_shift: u32,
+    },
+    /// The description of the system, e.g. `N=App,O=FreeRTOS,I#15=SysTick`.
+    SystemDescription(String),
+    /// The target dropped events, because the up channel was full.
+    Overflow {
+        dropped: u32,
+    },
+    TraceStart,
+    TraceStop,
+    IsrEnter {
+        isr: u32,
+    },
+    IsrExit,
+    /// The interrupt exits to the scheduler, which decides what runs next.
+    IsrToScheduler,
+    TaskCreate {
+        task: u32,
+    },
+    TaskInfo {
+        task: u32,
+        priority: u32,
+        name: String,
+    },
+    TaskStartExec {
+        task: u32,
+    },
+    TaskStopExec,
+    TaskStartReady {
+        task: u32,
+    },
+    TaskStopReady {
+        task: u32,
+        cause: u32,
+    },
+    TaskTerminate {
+        task: u32,
+    },
+    /// The core is idle, no task runs.
+    Idle,
+    MarkStart {
+        marker: u32,
+    },
+    MarkStop {
+        marker: u32,
+    },
+    /// A point in time, without a duration.
+    Mark {
+        marker: u32,
+    },
+    NameMarker {
+        marker: u32,
+        name: String,
+    },
+    /// A log message, of the level 0 for information, 1 for warnings, and 2 for errors.
+    Print {
+        message: String,
+        level: u32,
+    },
+    /// An event which isn't decoded.
+    Other {
+        id: u32,
+    },
+}
+
+/// An event, and the time of its packet in ticks since the start of the recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub ticks: u64,
+    pub event: Event,
+}
+
+/// Why a packet couldn't be decoded.
+#[derive(Debug)]
+enum DecodeError {
+    /// The packet wasn't received completely yet.
+    Incomplete,
+    Malformed(anyhow::Error),
+}
+
+impl From<anyhow::Error> for DecodeError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Malformed(error)
+    }
+}
+
+/// Reads the fields of a packet.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or(DecodeError::Incomplete)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u32, DecodeError> {
+        let mut value = 0u64;
+        for index in 0..MAX_VARINT_LENGTH {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << (7 * index);
+            if byte & 0x80 == 0 {
+                return Ok(value as u32);
+            }
+        }
+        Err(anyhow!("A number is longer than {MAX_VARINT_LENGTH} bytes").into())
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        let bytes = self
+            .data
+            .get(self.position..)
+            .and_then(|data| data.get(..length))
+            .ok_or(DecodeError::Incomplete)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let length = match self.byte()? {
+            255 => u16::from_le_bytes([self.byte()?, self.byte()?]) as usize,
+            length => length as usize,
+        };
+        Ok(String::from_utf8_lossy(self.bytes(length)?).into())
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.position..];
+        self.position = self.data.len();
+        rest
+    }
+}
+
+/// Decodes the stream of the up channel, see the [module documentation](self).
+#[derive(Debug, Default)]
+pub struct SystemViewDecoder {
+    buffer: Vec<u8>,
+    synchronized: bool,
+    /// The time of the previous packet.
+    ticks: u64,
+}
+
+impl SystemViewDecoder {
+    /// Add received data, to be decoded by [`SystemViewDecoder::decode`].
+    pub fn received(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Decode the next event, `None` if it wasn't received completely yet. The data before the synchronization is
+    /// skipped. A malformed packet drops the received data, and the decoder waits for the next synchronization.
+    pub fn decode(&mut self) -> Result<Option<TimedEvent>> {
+        loop {
+            if !self.synchronized {
+                let sync = self
+                    .buffer
+                    .windows(SYNC_LENGTH)
+                    .position(|window| window.iter().all(|&byte| byte == 0));
+                match sync {
+                    Some(position) => {
+                        self.buffer.drain(..position + SYNC_LENGTH);
+                        self.synchronized = true;
+                    }
+                    None => {
+                        // The end of the buffer can be the start of the synchronization.
+                        let skipped = self.buffer.len().saturating_sub(SYNC_LENGTH - 1);
+                        self.buffer.drain(..skipped);
+                        return Ok(None);
+                    }
+                }
+            }
+
+            let mut reader = Reader::new(&self.buffer);
+            match read_packet(&mut reader) {
+                Ok(packet) => {
+                    let length = reader.position;
+                    self.buffer.drain(..length);
+                    if let Some((event, delta)) = packet {
+                        self.ticks += u64::from(delta);
+                        return Ok(Some(TimedEvent {
+                            ticks: self.ticks,
+                            event,
+                        }));
+                    }
+                }
+                Err(DecodeError::Incomplete) => return Ok(None),
+                Err(DecodeError::Malformed(error)) => {
+                    self.buffer.clear();
+                    self.synchronized = false;
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+/// Read the packet at the start of the `reader`, with the time since the previous packet. `None` for a NOP, which
+/// has no timestamp.
+fn read_packet(reader: &mut Reader) -> Result<Option<(Event, u32)>, DecodeError> {
+    let id = reader.varint()?;
+    if id == event_id::NOP {
+        return Ok(None);
+    }
+
+    let event = if id < FIRST_EVENT_WITH_LENGTH {
+        read_event(id, reader)?
+    } else {
+        let length = reader.varint()? as usize;
+        let mut payload = Reader::new(reader.bytes(length)?);
+        read_event(id, &mut payload).map_err(|error| match error {
+            DecodeError::Incomplete => anyhow!("The payload of the event {id} is too short").into(),
+            error => error,
+        })?
+    };
+    let delta = reader.varint()?;
+    Ok(Some((event, delta)))
+}
+
+/// Read the payload of an event.
+fn read_event(id: u32, reader: &mut Reader) -> Result<Event, DecodeError> {
+    use event_id::*;
+
+    let event = match id {
+        OVERFLOW => Event::Overflow {
+            dropped: reader.varint()?,
+        },
+        ISR_ENTER => Event::IsrEnter {
+            isr: reader.varint()?,
+        },
+        ISR_EXIT => Event::IsrExit,
+        TASK_START_EXEC => Event::TaskStartExec {
+            task: reader.varint()?,
+        },
+        TASK_STOP_EXEC => Event::TaskStopExec,
+        TASK_START_READY => Event::TaskStartReady {
+            task: reader.varint()?,
+        },
+        TASK_STOP_READY => Event::TaskStopReady {
+            task: reader.varint()?,
+            cause: reader.varint()?,
+        },
+        TASK_CREATE => Event::TaskCreate {
+            task: reader.varint()?,
+        },
+        TASK_INFO => Event::TaskInfo {
+            task: reader.varint()?,
+            priority: reader.varint()?,
+            name: reader.string()?,
+        },
+        TRACE_START => Event::TraceStart,
+        TRACE_STOP => Event::TraceStop,
+        SYSDESC => Event::SystemDescription(reader.string()?),
+        MARK_START => Event::MarkStart {
+            marker: reader.varint()?,
+        },
+        MARK_STOP => Event::MarkStop {
+            marker: reader.varint()?,
+        },
+        IDLE => Event::Idle,
+        ISR_TO_SCHEDULER => Event::IsrToScheduler,
+        INIT => Event::Init {
+            frequency: reader.varint()?,
+            cpu_frequency: reader.varint()?,
+            ram_base: reader.varint()?,
+            id_shift: reader.varint()?,
+        },
+        PRINT_FORMATTED => {
+            let format = reader.string()?;
+            let level = reader.varint()? & 0x3;
+            let _argument_count = reader.varint()?;
+            // A message whose arguments don't match its format string is shown as is.
+            let message = printf::format_arguments(&format, reader.rest()).unwrap_or(format);
+            Event::Print { message, level }
+        }
+        TASK_TERMINATE => Event::TaskTerminate {
+            task: reader.varint()?,
+        },
+        EX => match reader.varint()? {
+            EX_MARK => Event::Mark {
+                marker: reader.varint()?,
+            },
+            EX_NAME_MARKER => Event::NameMarker {
+                marker: reader.varint()?,
+                name: reader.string()?,
+            },
+            _ => Event::Other { id },
+        },
+        // The events without length which aren't decoded still have to be read.
+        SYSTIME_CYCLES | TIMER_ENTER => {
+            reader.varint()?;
+            Event::Other { id }
+        }
+        SYSTIME_US => {
+            reader.varint()?;
+            reader.varint()?;
+            Event::Other { id }
+        }
+        TIMER_EXIT => Event::Other { id },
+        STACK_INFO => {
+            for _ in 0..4 {
+                reader.varint()?;
+            }
+            Event::Other { id }
+        }
+        MODULEDESC => {
+            reader.varint()?;
+            reader.varint()?;
+            reader.string()?;
+            Event::Other { id }
+        }
+        id if id < FIRST_EVENT_WITH_LENGTH => {
+            return Err(anyhow!("The event {id} is unknown, so its length is unknown").into())
+        }
+        // The payload of other events is skipped.
+        _ => Event::Other { id },
+    };
+    Ok(event)
+}
+
+/// What runs on the core, besides interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Context {
+    Idle,
+    Task(u32),
+}
+
+/// A time span of a timeline, in ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span<T> {
+    pub what: T,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A task, from its task info packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TaskInfo {
+    name: String,
+    priority: u32,
+}
+
+/// The timeline of a recording: what ran when, the interrupts, the markers and the log messages.
+#[derive(Debug, Default)]
+pub struct Timeline {
+    /// The frequency of the timestamps in Hz.
+    frequency: u32,
+    ram_base: u32,
+    id_shift: u32,
+    /// The time of the first and the last event.
+    start: u64,
+    end: u64,
+    event_count: usize,
+    dropped: u64,
+    /// The tasks and the idle time, split by the interrupts.
+    pub contexts: Vec<Span<Context>>,
+    /// The interrupts, which can be nested.
+    pub interrupts: Vec<Span<u32>>,
+    /// The time between the start and the stop of each marker.
+    pub markers: Vec<Span<u32>>,
+    /// The time of each point marker.
+    pub marks: Vec<(u64, u32)>,
+    /// The time, the level and the text of each log message.
+    pub messages: Vec<(u64, u32, String)>,
+    tasks: HashMap<u32, TaskInfo>,
+    interrupt_names: HashMap<u32, String>,
+    marker_names: HashMap<u32, String>,
+}
+
+/// The state while the events are added to a timeline.
+#[derive(Debug, Default)]
+struct TimelineBuilder {
+    timeline: Timeline,
+    context: Option<Context>,
+    /// When the context started to run, or resumed after the interrupts.
+    context_start: u64,
+    /// The interrupts which run, the innermost last, with their start.
+    interrupts: Vec<(u32, u64)>,
+    /// The markers which were started, but not stopped yet.
+    markers: HashMap<u32, u64>,
+}
+
+impl TimelineBuilder {
+    fn end_context(&mut self, ticks: u64) {
+        if let (Some(context), true) = (self.context, self.interrupts.is_empty()) {
+            self.timeline.contexts.push(Span {
+                what: context,
+                start: self.context_start,
+                end: ticks,
+            });
+        }
+    }
+
+    fn switch_context(&mut self, context: Option<Context>, ticks: u64) {
+        self.end_context(ticks);
+        self.context = context;
+        self.context_start = ticks;
+    }
+
+    /// Exit the innermost interrupt, and resume the context it interrupted, unless the scheduler takes over.
+    fn exit_interrupt(&mut self, ticks: u64, to_scheduler: bool) {
+        if let Some((isr, start)) = self.interrupts.pop() {
+            self.timeline.interrupts.push(Span {
+                what: isr,
+                start,
+                end: ticks,
+            });
+        }
+        if self.interrupts.is_empty() {
+            if to_scheduler {
+                self.context = None;
+            }
+            self.context_start = ticks;
+        }
+    }
+
+    fn add(&mut self, TimedEvent { ticks, event }: &TimedEvent) {
+        let ticks = *ticks;
+        let timeline = &mut self.timeline;
+        if timeline.event_count == 0 {
+            timeline.start = ticks;
+            self.context_start = ticks;
+        }
+        timeline.event_count += 1;
+        timeline.end = ticks;
+
+        match event {
+            Event::Init {
+                frequency,
+                ram_base,
+                id_shift,
+                ..
+            } => {
+                timeline.frequency = *frequency;
+                timeline.ram_base = *ram_base;
+                timeline.id_shift = *id_shift;
+            }
+            Event::SystemDescription(description) => {
+                for item in description.split(',') {
+                    if let Some((isr, name)) = item
+                        .trim()
+                        .strip_prefix("I#")
+                        .and_then(|item| item.split_once('='))
+                    {
+                        if let Ok(isr) = isr.parse() {
+                            timeline.interrupt_names.insert(isr, name.to_string());
+                        }
+                    }
+                }
+            }
+            Event::Overflow { dropped } => timeline.dropped += u64::from(*dropped),
+            Event::TaskInfo {
+                task,
+                priority,
+                name,
+            } => {
+                timeline.tasks.insert(
+                    *task,
+                    TaskInfo {
+                        name: name.clone(),
+                        priority: *priority,
+                    },
+                );
+            }
+            Event::NameMarker { marker, name } => {
+                timeline.marker_names.insert(*marker, name.clone());
+            }
+            Event::Mark { marker } => timeline.marks.push((ticks, *marker)),
+            Event::Print { message, level } => {
+                timeline.messages.push((ticks, *level, message.clone()))
+            }
+            Event::MarkStart { marker } => {
+                self.markers.insert(*marker, ticks);
+            }
+            Event::MarkStop { marker } => {
+                if let Some(start) = self.markers.remove(marker) {
+                    timeline.markers.push(Span {
+                        what: *marker,
+                        start,
+                        end: ticks,
+                    });
+                }
+            }
+            Event::TaskStartExec { task } => self.switch_context(Some(Context::Task(*task)), ticks),
+            Event::TaskStopExec => self.switch_context(None, ticks),
+            Event::Idle => self.switch_context(Some(Context::Idle), ticks),
+            Event::IsrEnter { isr } => {
+                self.end_context(ticks);
+                self.interrupts.push((*isr, ticks));
+            }
+            Event::IsrExit => self.exit_interrupt(ticks, false),
+            Event::IsrToScheduler => self.exit_interrupt(ticks, true),
+            _ => {}
+        }
+    }
+
+    /// End what still runs at the end of the recording.
+    fn finish(mut self) -> Timeline {
+        let end = self.timeline.end;
+        while !self.interrupts.is_empty() {
+            self.exit_interrupt(end, false);
+        }
+        self.end_context(end);
+        for (marker, start) in std::mem::take(&mut self.markers) {
+            self.timeline.markers.push(Span {
+                what: marker,
+                start,
+                end,
+            });
+        }
+        self.timeline
+    }
+}
+
+impl Timeline {
+    /// The timeline of the `events` of a recording, which has to contain the init packet.
+    pub fn new(events: &[TimedEvent]) -> Result<Self> {
+        let mut builder = TimelineBuilder::default();
+        for event in events {
+            builder.add(event);
+        }
+        let timeline = builder.finish();
+
+        if timeline.frequency == 0 {
+            bail!(
+                "The recording has no init packet, so the frequency of its timestamps is unknown"
+            );
+        }
+        Ok(timeline)
+    }
+
+    /// The time since the start of the recording.
+    pub fn time(&self, ticks: u64) -> Duration {
+        Duration::from_secs_f64(ticks.saturating_sub(self.start) as f64 / f64::from(self.frequency))
+    }
+
+    /// The length of the recording.
+    pub fn duration(&self) -> Duration {
+        self.time(self.end)
+    }
+
+    /// The tasks which ran, and the idle time, in the order of their IDs.
+    pub fn context_list(&self) -> Vec<Context> {
+        let contexts = self
+            .contexts
+            .iter()
+            .map(|span| span.what)
+            .collect::<BTreeSet<_>>();
+        contexts.into_iter().collect()
+    }
+
+    pub fn context_name(&self, context: Context) -> String {
+        match context {
+            Context::Idle => "Idle".to_string(),
+            Context::Task(task) => match self.tasks.get(&task) {
+                Some(info) => info.name.clone(),
+                None => {
+                    // The ID of a task is made short from its address.
+                    let address = (task << self.id_shift).wrapping_add(self.ram_base);
+                    format!("Task {address:#010x}")
+                }
+            },
+        }
+    }
+
+    pub fn interrupt_name(&self, isr: u32) -> String {
+        match self.interrupt_names.get(&isr) {
+            Some(name) => format!("#{isr} {name}"),
+            None => format!("#{isr}"),
+        }
+    }
+
+    pub fn marker_name(&self, marker: u32) -> String {
+        match self.marker_names.get(&marker) {
+            Some(name) => name.clone(),
+            None => format!("Marker {marker}"),
+        }
+    }
+
+    /// A summary of the recording: the CPU load of every task, the time in every interrupt, and the durations of the
+    /// markers.
+    pub fn summary(&self) -> String {
+        let millis = |ticks: u64| ticks as f64 * 1000.0 / f64::from(self.frequency);
+        let total = self.end.saturating_sub(self.start).max(1);
+
+        let mut summary = format!(
+            "Recorded {:.3} s, {} events, {} dropped\n\n",
+            self.duration().as_secs_f64(),
+            self.event_count,
+            self.dropped
+        );
+
+        let _ = writeln!(
+            summary,
+            "{:<24} {:>8} {:>8} {:>14} {:>8}",
+            "Context", "Priority", "Runs", "CPU time", "Load"
+        );
+        for context in self.context_list() {
+            let spans = self.contexts.iter().filter(|span| span.what == context);
+            let time = spans.clone().map(|span| span.end - span.start).sum::<u64>();
+            let priority = match context {
+                Context::Task(task) => self.tasks.get(&task).map(|info| info.priority),
+                Context::Idle => None,
+            };
+            let _ = writeln!(
+                summary,
+                "{:<24} {:>8} {:>8} {:>11.3} ms {:>6.1} %",
+                self.context_name(context),
+                priority.map_or(String::new(), |priority| priority.to_string()),
+                spans.count(),
+                millis(time),
+                time as f64 * 100.0 / total as f64
+            );
+        }
+
+        let mut interrupts = self
+            .interrupts
+            .iter()
+            .map(|span| span.what)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .peekable();
+        if interrupts.peek().is_some() {
+            let _ = writeln!(
+                summary,
+                "\n{:<24} {:>8} {:>14} {:>14}",
+                "Interrupt", "Count", "Total time", "Longest"
+            );
+        }
+        for isr in interrupts {
+            let durations = self
+                .interrupts
+                .iter()
+                .filter(|span| span.what == isr)
+                .map(|span| span.end - span.start)
+                .collect::<Vec<_>>();
+            let _ = writeln!(
+                summary,
+                "{:<24} {:>8} {:>11.3} ms {:>11.3} ms",
+                self.interrupt_name(isr),
+                durations.len(),
+                millis(durations.iter().sum()),
+                millis(durations.iter().copied().max().unwrap_or(0))
+            );
+        }
+
+        let mut markers = self
+            .markers
+            .iter()
+            .map(|span| span.what)
+            .chain(self.marks.iter().map(|(_, marker)| *marker))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .peekable();
+        if markers.peek().is_some() {
+            let _ = writeln!(
+                summary,
+                "\n{:<24} {:>8} {:>14} {:>14} {:>14}",
+                "Marker", "Count", "Shortest", "Average", "Longest"
+            );
+        }
+        for marker in markers {
+            let durations = self
+                .markers
+                .iter()
+                .filter(|span| span.what == marker)
+                .map(|span| span.end - span.start)
+                .collect::<Vec<_>>();
+            if durations.is_empty() {
+                let count = self
+                    .marks
+                    .iter()
+                    .filter(|(_, mark)| *mark == marker)
+                    .count();
+                let _ = writeln!(summary, "{:<24} {count:>8}", self.marker_name(marker));
+                continue;
+            }
+            let _ = writeln!(
+                summary,
+                "{:<24} {:>8} {:>11.3} ms {:>11.3} ms {:>11.3} ms",
+                self.marker_name(marker),
+                durations.len(),
+                millis(durations.iter().copied().min().unwrap_or(0)),
+                millis(durations.iter().sum::<u64>()) / durations.len() as f64,
+                millis(durations.iter().copied().max().unwrap_or(0))
+            );
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A packet of an event without length, with the timestamp `delta`.
+    fn packet(id: u8, payload: &[u8], delta: u8) -> Vec<u8> {
+        [&[id], payload, &[delta]].concat()
+    }
+
+    #[test]
+    fn packets_are_decoded_after_the_synchronization() {
+        let mut stream = vec![b'x', 0, 0];
+        stream.extend([0; SYNC_LENGTH]);
+        // The init packet has a length: 72 MHz, the RAM at 0x2000_0000, and IDs shifted by 2.
+        stream.extend([
+            24, 11, 0x80, 0xc4, 0xaa, 0x22, 0, 0x80, 0x80, 0x80, 0x80, 2, 2, 2,
+        ]);
+        stream.extend(packet(9, &[0x81, 0x01, 3, 4, b'm', b'a', b'i', b'n'], 0));
+        stream.extend(packet(4, &[0x81, 0x01], 0x70));
+        stream.extend(packet(2, &[15], 1));
+
+        let mut decoder = SystemViewDecoder::default();
+        let mut events = Vec::new();
+        // The stream is received in pieces, which split the packets.
+        for chunk in stream.chunks(5) {
+            decoder.received(chunk);
+            while let Some(event) = decoder.decode().unwrap() {
+                events.push(event);
+            }
+        }
+
+        assert_eq!(
+            events,
+            [
+                TimedEvent {
+                    ticks: 2,
+                    event: Event::Init {
+                        frequency: 72_000_000,
+                        cpu_frequency: 0,
+                        ram_base: 0x2000_0000,
+                        id_shift: 2,
+                    },
+                },
+                TimedEvent {
+                    ticks: 2,
+                    event: Event::TaskInfo {
+                        task: 0x81,
+                        priority: 3,
+                        name: "main".to_string(),
+                    },
+                },
+                TimedEvent {
+                    ticks: 0x72,
+                    event: Event::TaskStartExec { task: 0x81 },
+                },
+                TimedEvent {
+                    ticks: 0x73,
+                    event: Event::IsrEnter { isr: 15 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn interrupts_split_the_time_of_tasks() {
+        let events = [
+            Event::Init {
+                frequency: 1000,
+                cpu_frequency: 1000,
+                ram_base: 0x2000_0000,
+                id_shift: 2,
+            },
+            Event::SystemDescription("N=App,I#15=SysTick".to_string()),
+            Event::TaskStartExec { task: 1 },
+            Event::IsrEnter { isr: 15 },
+            Event::IsrToScheduler,
+            Event::Idle,
+            Event::MarkStart { marker: 7 },
+            Event::IsrEnter { isr: 16 },
+            Event::IsrExit,
+            Event::MarkStop { marker: 7 },
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, event)| TimedEvent {
+            ticks: index as u64 * 10,
+            event,
+        })
+        .collect::<Vec<_>>();
+        let timeline = Timeline::new(&events).unwrap();
+
+        assert_eq!(
+            timeline.contexts,
+            [
+                Span {
+                    what: Context::Task(1),
+                    start: 20,
+                    end: 30
+                },
+                Span {
+                    what: Context::Idle,
+                    start: 50,
+                    end: 70
+                },
+                Span {
+                    what: Context::Idle,
+                    start: 80,
+                    end: 90
+                },
+            ]
+        );
+        assert_eq!(
+            timeline.interrupts,
+            [
+                Span {
+                    what: 15,
+                    start: 30,
+                    end: 40
+                },
+                Span {
+                    what: 16,
+                    start: 70,
+                    end: 80
+                },
+            ]
+        );
+        assert_eq!(timeline.duration(), Duration::from_millis(90));
+        assert_eq!(timeline.context_name(Context::Task(1)), "Task 0x20000004");
+        assert_eq!(timeline.interrupt_name(15), "#15 SysTick");
+
+        let summary = timeline.summary();
+        assert!(summary.contains("Idle"), "{summary}");
+        assert!(summary.contains("#16"), "{summary}");
+        assert!(summary.contains("Marker 7"), "{summary}");
+
+        assert!(Timeline::new(&events[1..]).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Sinks for sampled variable values, for plotting them in external tools.
+//!
+//! The samples are appended to a file as CSV or as NDJSON, with one JSON object per line. They can also be sent as
+//! one JSON object per UDP datagram, which e.g. the UDP server of PlotJuggler plots live.
+
+use std::io::Write;
+use std::net::{SocketAddr, UdpSocket};
+use std::str::FromStr;
+
+use serde_json::Value;
+
+/// The format of the samples in the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum TelemetryFormat {
+    #[default]
+    Csv,
+    /// Newline delimited JSON.
+    Ndjson,
+}
+
+impl FromStr for TelemetryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_ascii_lowercase()[..] {
+            "csv" => Ok(Self::Csv),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            _ => Err(format!("{s} is not a valid format, use csv or ndjson")),
+        }
+    }
+}
+
+/// A sample which can be written to a [`TelemetrySink`].
+pub(crate) trait TelemetryRecord {
+    /// The names of the columns of the CSV header, which are the keys of the JSON objects.
+    fn columns() -> &'static [&'static str];
+
+    fn to_csv(&self) -> String;
+
+    fn to_json(&self) -> Value;
+}
+
+/// Writes samples to a file or stdout, and optionally sends them over UDP.
+pub(crate) struct TelemetrySink {
+    format: TelemetryFormat,
+    output: Box<dyn Write>,
+    udp: Option<UdpSocket>,
+}
+
+impl TelemetrySink {
+    pub(crate) fn new(format: TelemetryFormat, output: Box<dyn Write>) -> Self {
+        Self {
+            format,
+            output,
+            udp: None,
+        }
+    }
+
+    /// Also send every sample as a JSON object in a UDP datagram to `addr`.
+    pub(crate) fn send_udp(&mut self, addr: SocketAddr) -> std::io::Result<()> {
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        self.udp = Some(socket);
+        Ok(())
+    }
+
+    /// Write the CSV header, before the first sample.
+    pub(crate) fn header<R: TelemetryRecord>(&mut self) -> std::io::Result<()> {
+        match self.format {
+            TelemetryFormat::Csv => writeln!(self.output, "{}", R::columns().join(",")),
+            TelemetryFormat::Ndjson => Ok(()),
+        }
+    }
+
+    pub(crate) fn record(&mut self, record: &impl TelemetryRecord) -> std::io::Result<()> {
+        let json = match (self.format, &self.udp) {
+            (TelemetryFormat::Csv, None) => None,
+            _ => Some(record.to_json().to_string()),
+        };
+
+        match (self.format, &json) {
+            (TelemetryFormat::Ndjson, Some(json)) => writeln!(self.output, "{json}")?,
+            _ => writeln!(self.output, "{}", record.to_csv())?,
+        }
+
+        if let (Some(udp), Some(json)) = (&self.udp, &json) {
+            // A lost datagram is not worth stopping the trace for, the sample is still in the file.
+            if let Err(error) = udp.send(json.as_bytes()) {
+                tracing::debug!("Failed to send a sample over UDP: {error}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    struct Sample(u32);
+
+    impl TelemetryRecord for Sample {
+        fn columns() -> &'static [&'static str] {
+            &["variable", "value"]
+        }
+
+        fn to_csv(&self) -> String {
+            format!("COUNTER,{}", self.0)
+        }
+
+        fn to_json(&self) -> Value {
+            json!({ "variable": "COUNTER", "value": self.0 })
+        }
+    }
+
+    /// A writer whose data can be inspected after it was moved into the sink.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn write_samples(format: TelemetryFormat, udp: Option<SocketAddr>) -> String {
+        let buffer = SharedBuffer::default();
+        let mut sink = TelemetrySink::new(format, Box::new(buffer.clone()));
+        if let Some(udp) = udp {
+            sink.send_udp(udp).unwrap();
+        }
+        sink.header::<Sample>().unwrap();
+        sink.record(&Sample(1)).unwrap();
+        sink.record(&Sample(2)).unwrap();
+
+        let output = buffer.0.borrow().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn samples_are_written_as_csv_and_ndjson() {
+        assert_eq!(
+            write_samples(TelemetryFormat::Csv, None),
+            "variable,value\nCOUNTER,1\nCOUNTER,2\n"
+        );
+        let ndjson = write_samples(TelemetryFormat::Ndjson, None);
+        let lines = ndjson
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                json!({ "variable": "COUNTER", "value": 1 }),
+                json!({ "variable": "COUNTER", "value": 2 })
+            ]
+        );
+    }
+
+    #[test]
+    fn samples_are_sent_over_udp() {
+        let receiver = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        write_samples(TelemetryFormat::Csv, Some(receiver.local_addr().unwrap()));
+
+        let mut datagram = [0; 64];
+        let length = receiver.recv(&mut datagram).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&datagram[..length]).unwrap(),
+            json!({ "variable": "COUNTER", "value": 1 })
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//! Deferred handling of Ctrl-C and termination signals.
+//!
+//! Killing the process while it accesses the target leaves the target halted, with stale breakpoints in the FPB and
+//! the debug logic enabled, and interrupting the flashing leaves a half-written image. So for the commands which run
+//! until they are interrupted, e.g. `run`, and for the ones which flash or erase the target, the first signal only
+//! sets a flag: they check it and return, and the flashing finishes first. Then the session is dropped, which removes
+//! the breakpoints, disables the debug logic and closes the probe. A second signal terminates the process
+//! immediately.
+//!
+//! Panics unwind, so they drop the session as well.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use signal_hook::consts::TERM_SIGNALS;
+
+/// The exit code after an interruption, as of a process which was terminated by SIGINT.
+pub(crate) const EXIT_CODE: i32 = 130;
+
+static REQUESTED: Lazy<Arc<AtomicBool>> = Lazy::new(|| Arc::new(AtomicBool::new(false)));
+
+/// Defer Ctrl-C and the termination signals until the command returns.
+pub(crate) fn install() -> std::io::Result<()> {
+    for &signal in TERM_SIGNALS {
+        // Registered before the flag is set, so only a second signal terminates the process.
+        signal_hook::flag::register_conditional_shutdown(signal, EXIT_CODE, REQUESTED.clone())?;
+        signal_hook::flag::register(signal, REQUESTED.clone())?;
+    }
+    Ok(())
+}
+
+/// Whether the user asked to terminate the process.
+pub(crate) fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Exit with [`EXIT_CODE`] if the user asked to terminate the process.
+///
+/// Call this after the command returned, so that it dropped its session, which cleaned up the target.
+pub(crate) fn exit_if_requested() {
+    if requested() {
+        eprintln!("Interrupted.");
+        std::process::exit(EXIT_CODE);
+    }
+}
@@ -0,0 +1,145 @@
+//! The USB timeouts and retries of the probe drivers, see [`probe_rs::usb_policy`].
+//!
+//! They are configured per driver in the `probes.toml` file in the probe-rs configuration directory, next to the probe
+//! aliases:
+//!
+//! ```toml
+//! [usb.cmsis-dap]
+//! timeout_ms = 2000
+//! retries = 3
+//! backoff_ms = 20
+//! ```
+//!
+//! The drivers are `cmsis-dap`, `stlink`, `esp-usb-jtag` and `ftdi`. The retry statistics are logged at the `info`
+//! level when a command finishes, e.g. with `RUST_LOG=info`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use figment::{
+    providers::{Format, Toml},
+    Figment,
+};
+use probe_rs::usb_policy::{self, UsbPolicy};
+use probe_rs::DebugProbeType;
+use serde::Deserialize;
+
+use super::probe_aliases::probes_file;
+
+/// The USB policies in the `probes.toml` file.
+#[derive(Debug, Default, Deserialize)]
+struct UsbPolicies {
+    #[serde(default)]
+    usb: HashMap<String, PolicyConfig>,
+}
+
+/// The policy of a driver, with the defaults of [`UsbPolicy`].
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyConfig {
+    timeout_ms: Option<u64>,
+    #[serde(default)]
+    retries: u32,
+    backoff_ms: Option<u64>,
+}
+
+impl PolicyConfig {
+    fn policy(&self) -> UsbPolicy {
+        let default = UsbPolicy::default();
+        UsbPolicy {
+            timeout: self.timeout_ms.map(Duration::from_millis),
+            retries: self.retries,
+            backoff: self
+                .backoff_ms
+                .map_or(default.backoff, Duration::from_millis),
+        }
+    }
+}
+
+fn probe_type(driver: &str) -> Result<DebugProbeType> {
+    Ok(match driver {
+        "cmsis-dap" => DebugProbeType::CmsisDap,
+        "stlink" => DebugProbeType::StLink,
+        "esp-usb-jtag" => DebugProbeType::EspJtag,
+        "ftdi" => DebugProbeType::Ftdi,
+        _ => bail!(
+            "The USB policy of the driver '{driver}' can't be configured. The drivers are cmsis-dap, stlink, \
+            esp-usb-jtag and ftdi"
+        ),
+    })
+}
+
+fn load_policies(figment: Figment) -> Result<Vec<(DebugProbeType, UsbPolicy)>> {
+    let policies: UsbPolicies = figment
+        .extract()
+        .context("Failed to parse the USB policies")?;
+    policies
+        .usb
+        .iter()
+        .map(|(driver, config)| Ok((probe_type(driver)?, config.policy())))
+        .collect()
+}
+
+/// Set the USB policies of the `probes.toml` file, if it exists.
+pub fn apply() -> Result<()> {
+    let Some(path) = probes_file().filter(|path| path.exists()) else {
+        return Ok(());
+    };
+    let policies = load_policies(Figment::from(Toml::file(&path)))
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    for (probe_type, policy) in policies {
+        usb_policy::set_policy(probe_type, policy);
+    }
+    Ok(())
+}
+
+/// Log the statistics of the USB transfers of each driver.
+pub fn log_statistics() {
+    for (probe_type, statistics) in usb_policy::statistics() {
+        tracing::info!(
+            "USB transfers of the {:?} driver: {}, with {} retries and {} timeouts",
+            probe_type,
+            statistics.transfers,
+            statistics.retries,
+            statistics.timeouts
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use probe_rs::DebugProbeType;
+
+    use super::load_policies;
+
+    #[test]
+    fn policies_are_parsed_per_driver() {
+        let policies = load_policies(Figment::from(Toml::string(
+            r#"
+            [aliases]
+            bench-3 = "0483:374b@1-4.2"
+
+            [usb.stlink]
+            timeout_ms = 3000
+            retries = 2
+            "#,
+        )))
+        .unwrap();
+
+        assert_eq!(policies.len(), 1);
+        let (probe_type, policy) = &policies[0];
+        assert_eq!(*probe_type, DebugProbeType::StLink);
+        assert_eq!(policy.timeout, Some(Duration::from_millis(3000)));
+        assert_eq!(policy.retries, 2);
+        assert_eq!(policy.backoff, Duration::from_millis(10));
+
+        assert!(load_policies(Figment::from(Toml::string("[usb.jlink]\nretries = 1"))).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Probes in WSL2, which are passed through from Windows with usbipd.
+//!
+//! WSL2 has no access to the USB devices of Windows. A probe has to be shared with `usbipd bind` on Windows, and
+//! then attached to WSL with `usbipd attach --wsl`, which the Windows tools can be started for from within WSL.
+//! When the probe resets or reenumerates, e.g. after a firmware update, Windows binds it again and it disappears from
+//! WSL until it is attached again. Also, udev doesn't run in WSL by default, so its rules for probes don't apply.
+
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use probe_rs::Probe;
+
+/// The usbipd tool of Windows, which is started through the interop of WSL.
+const USBIPD: &str = "usbipd.exe";
+
+/// The vendor IDs of the manufacturers of probes, whose devices are attached to WSL.
+const PROBE_VENDOR_IDS: &[u16] = &[
+    0x0483, // STMicroelectronics
+    0x1366, // SEGGER
+    0x0d28, // ARM (DAPLink)
+    0x303a, // Espressif
+    0x0403, // FTDI
+    0x1d50, // OpenMoko (Black Magic Probe)
+    0x2e8a, // Raspberry Pi (Debugprobe)
+    0xc251, // Keil
+];
+
+/// Returns true if probe-rs runs in WSL.
+pub fn is_wsl() -> bool {
+    std::env::var_os("WSL_DISTRO_NAME").is_some()
+        || std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .map(|release| release.to_ascii_lowercase().contains("microsoft"))
+            .unwrap_or(false)
+}
+
+/// Whether a USB device of Windows is shared with usbipd, and attached to WSL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsbipdState {
+    NotShared,
+    /// The device is shared, but not attached, e.g. because Windows bound it again.
+    Shared,
+    Attached,
+}
+
+/// A USB device of Windows, as listed by `usbipd list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbipdDevice {
+    pub bus_id: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub description: String,
+    pub state: UsbipdState,
+}
+
+impl UsbipdDevice {
+    /// Whether the device looks like a debug probe.
+    pub fn is_probe(&self) -> bool {
+        let description = self.description.to_ascii_lowercase();
+        PROBE_VENDOR_IDS.contains(&self.vendor_id)
+            || ["cmsis-dap", "daplink", "debug", "j-link", "st-link"]
+                .iter()
+                .any(|name| description.contains(name))
+    }
+}
+
+/// Parse the connected devices of the output of `usbipd list`.
+pub fn parse_usbipd_list(output: &str) -> Vec<UsbipdDevice> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Connected:"))
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty() && !line.starts_with("Persisted:"))
+        .filter_map(|line| {
+            let (bus_id, rest) = line.trim().split_once(char::is_whitespace)?;
+            let (ids, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+            let (vendor_id, product_id) = ids.split_once(':')?;
+            let vendor_id = u16::from_str_radix(vendor_id, 16).ok()?;
+            let product_id = u16::from_str_radix(product_id, 16).ok()?;
+
+            // The columns are separated by at least two spaces, and the state is the last one.
+            let rest = rest.trim();
+            let (description, state) = rest.rsplit_once("  ")?;
+            // Older versions of usbipd report "Not attached" for shared devices, and "Attached - <distribution>".
+            let state = match state.trim() {
+                "Not shared" => UsbipdState::NotShared,
+                "Shared" | "Not attached" => UsbipdState::Shared,
+                state if state.starts_with("Attached") => UsbipdState::Attached,
+                _ => return None,
+            };
+            Some(UsbipdDevice {
+                bus_id: bus_id.to_string(),
+                vendor_id,
+                product_id,
+                description: description.trim().to_string(),
+                state,
+            })
+        })
+        .collect()
+}
+
+/// List the USB devices of Windows with usbipd.
+pub fn list_devices() -> Result<Vec<UsbipdDevice>> {
+    let output = Command::new(USBIPD)
+        .arg("list")
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| {
+            format!("Failed to run {USBIPD}. Is usbipd-win installed on Windows, and WSL interop enabled?")
+        })?;
+    if !output.status.success() {
+        bail!(
+            "{USBIPD} list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse_usbipd_list(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Attach the shared device `bus_id` to WSL. With `auto_attach`, usbipd keeps running in the background and attaches
+/// the device again whenever Windows binds it again.
+pub fn attach(bus_id: &str, auto_attach: bool) -> Result<()> {
+    let mut command = Command::new(USBIPD);
+    command
+        .args(["attach", "--wsl", "--busid", bus_id])
+        .stdin(Stdio::null());
+    if auto_attach {
+        command
+            .arg("--auto-attach")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run {USBIPD}"))?;
+        return Ok(());
+    }
+
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run {USBIPD}"))?;
+    if !output.status.success() {
+        bail!(
+            "Attaching the USB device {bus_id} to WSL failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Attach all probes which are shared but not attached, e.g. because Windows bound them again after they
+/// reenumerated, and wait until they appear in WSL. Returns the number of attached probes.
+pub fn reattach_probes() -> Result<usize> {
+    let detached = list_devices()?
+        .into_iter()
+        .filter(|device| device.is_probe() && device.state == UsbipdState::Shared)
+        .collect::<Vec<_>>();
+    for device in &detached {
+        tracing::info!(
+            "Attaching the probe {} ({}) to WSL",
+            device.bus_id,
+            device.description
+        );
+        attach(&device.bus_id, false)?;
+    }
+    if !detached.is_empty() {
+        wait_for_probes(Duration::from_secs(5));
+    }
+    Ok(detached.len())
+}
+
+/// Rescan the probes until one is found, or the `timeout` expired. Attached devices take a moment to be enumerated by
+/// the kernel of WSL.
+pub fn wait_for_probes(timeout: Duration) {
+    let start = Instant::now();
+    while Probe::list_all().is_empty() && start.elapsed() < timeout {
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// The device files of the probes in WSL which can't be opened, with the vendor and product ID of the probe.
+pub fn inaccessible_probes() -> Vec<(String, u16, u16)> {
+    let Ok(devices) = std::fs::read_dir("/sys/bus/usb/devices") else {
+        return Vec::new();
+    };
+    devices
+        .flatten()
+        .filter_map(|device| {
+            let path = device.path();
+            let read = |name: &str| -> Option<String> {
+                Some(
+                    std::fs::read_to_string(path.join(name))
+                        .ok()?
+                        .trim()
+                        .to_string(),
+                )
+            };
+            let vendor_id = u16::from_str_radix(&read("idVendor")?, 16).ok()?;
+            let product_id = u16::from_str_radix(&read("idProduct")?, 16).ok()?;
+            if !PROBE_VENDOR_IDS.contains(&vendor_id) {
+                return None;
+            }
+            let bus: u32 = read("busnum")?.parse().ok()?;
+            let device: u32 = read("devnum")?.parse().ok()?;
+            let device_file = format!("/dev/bus/usb/{bus:03}/{device:03}");
+            let accessible = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&device_file)
+                .is_ok();
+            (!accessible).then_some((device_file, vendor_id, product_id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_usbipd_list, UsbipdState};
+
+    #[test]
+    fn usbipd_list_is_parsed() {
+        let output = "Connected:\n\
+            BUSID  VID:PID    DEVICE                                                        STATE\n\
+            1-3    0483:374b  ST-Link Debug, USB Mass Storage Device, STMicroelectronic...  Shared\n\
+            2-1    1366:1015  J-Link driver                                                 Attached\n\
+            2-4    046d:c52b  USB Input Device                                              Not shared\n\
+            \n\
+            Persisted:\n\
+            GUID                                  DEVICE\n";
+
+        let devices = parse_usbipd_list(output);
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].bus_id, "1-3");
+        assert_eq!(devices[0].vendor_id, 0x0483);
+        assert_eq!(devices[0].state, UsbipdState::Shared);
+        assert!(devices[0].is_probe());
+        assert_eq!(devices[1].state, UsbipdState::Attached);
+        assert_eq!(devices[2].state, UsbipdState::NotShared);
+        assert!(!devices[2].is_probe());
+
+        let old = "Connected:\nBUSID  VID:PID    DEVICE  STATE\n4-2    0d28:0204  CMSIS-DAP  Attached - Ubuntu\n";
+        assert_eq!(parse_usbipd_list(old)[0].state, UsbipdState::Attached);
+    }
+}
@@ -0,0 +1,208 @@
+//! Registry of boards
+//!
+//! A board maps a name, like `nucleo-h743zi`, to the chip on it, and to the options which are needed to connect to
+//! it, so that they do not have to be given every time.
+
+use super::RegistryError;
+use crate::WireProtocol;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::ops::Range;
+use std::sync::Mutex;
+
+static BOARDS: Lazy<Mutex<BoardRegistry>> =
+    Lazy::new(|| Mutex::new(BoardRegistry::from_builtin_boards()));
+
+/// A board with a chip, e.g. an evaluation board, and the options which are needed to connect to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Board {
+    /// The name of the board, e.g. `nucleo-h743zi`.
+    pub name: String,
+    /// Other names of the board, e.g. the part number of the manufacturer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// The name of the chip on the board, as it is known to the target registry.
+    pub chip: String,
+    /// The wire protocol which is used to connect to the chip.
+    #[serde(default)]
+    pub protocol: Option<WireProtocol>,
+    /// The speed of the wire protocol in kHz.
+    #[serde(default)]
+    pub speed: Option<u32>,
+    /// Connect under reset, e.g. because the debug port is not accessible while the chip sleeps.
+    #[serde(default)]
+    pub connect_under_reset: bool,
+    /// The RAM range which is scanned for the RTT control block, if the ELF file does not tell its address.
+    #[serde(default)]
+    pub rtt_scan_region: Option<Range<u64>>,
+    /// The debug probes which are part of the board.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub probes: Vec<BoardProbe>,
+}
+
+impl Board {
+    /// Returns true if the board is called `name`, ignoring the case.
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns true if the probe with the USB `vendor_id` and `product_id` is part of the board.
+    pub fn has_probe(&self, vendor_id: u16, product_id: u16) -> bool {
+        self.probes
+            .iter()
+            .any(|probe| probe.vendor_id == vendor_id && probe.product_id == product_id)
+    }
+}
+
+/// A debug probe which is part of a [`Board`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardProbe {
+    /// The USB vendor ID of the probe.
+    pub vendor_id: u16,
+    /// The USB product ID of the probe.
+    pub product_id: u16,
+}
+
+struct BoardRegistry {
+    boards: Vec<Board>,
+}
+
+impl BoardRegistry {
+    #[cfg(feature = "builtin-targets")]
+    fn from_builtin_boards() -> Self {
+        const BUILTIN_BOARDS: &str = include_str!("../../boards/boards.yaml");
+
+        let boards = match serde_yaml::from_str(BUILTIN_BOARDS) {
+            Ok(boards) => boards,
+            Err(err) => panic!("Failed to deserialize builtin boards. This is a bug : {err:?}"),
+        };
+
+        Self { boards }
+    }
+
+    #[cfg(not(feature = "builtin-targets"))]
+    fn from_builtin_boards() -> Self {
+        Self { boards: vec![] }
+    }
+
+    fn get_board_by_name(&self, name: &str) -> Result<Board, RegistryError> {
+        self.boards
+            .iter()
+            .find(|board| board.is_named(name))
+            .cloned()
+            .ok_or_else(|| RegistryError::BoardNotFound(name.to_owned()))
+    }
+
+    fn add_boards_from_yaml<R: Read>(&mut self, yaml_reader: R) -> Result<(), RegistryError> {
+        let boards: Vec<Board> = serde_yaml::from_reader(yaml_reader)?;
+
+        // Boards of the user replace the built-in boards with the same name.
+        for board in boards {
+            self.boards
+                .retain(|old_board| !old_board.is_named(&board.name));
+            self.boards.push(board);
+        }
+
+        Ok(())
+    }
+}
+
+/// Get a board from the registry of boards based on its name or one of its aliases.
+pub fn get_board_by_name(name: impl AsRef<str>) -> Result<Board, RegistryError> {
+    BOARDS.lock().unwrap().get_board_by_name(name.as_ref())
+}
+
+/// Parse a list of board descriptions and add the boards to the registry of boards.
+///
+/// A board replaces a board with the same name which was added before, e.g. a built-in board.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use std::fs::File;
+///
+/// let file = File::open(Path::new("/path/boards.yaml"))?;
+/// probe_rs::config::add_boards_from_yaml(file)?;
+///
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn add_boards_from_yaml<R: Read>(yaml_reader: R) -> Result<(), RegistryError> {
+    BOARDS.lock().unwrap().add_boards_from_yaml(yaml_reader)
+}
+
+/// Get a list of all boards which are contained in the registry of boards.
+pub fn boards() -> Vec<Board> {
+    BOARDS.lock().unwrap().boards.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_boards_have_known_chips() {
+        for board in BoardRegistry::from_builtin_boards().boards {
+            assert!(
+                crate::config::get_target_by_name(&board.chip).is_ok(),
+                "The chip {} of the board {} is unknown",
+                board.chip,
+                board.name
+            );
+        }
+    }
+
+    #[test]
+    fn board_by_alias() {
+        let mut registry = BoardRegistry { boards: vec![] };
+        registry
+            .add_boards_from_yaml(
+                r#"
+- name: nucleo-h743zi
+  aliases:
+    - nucleo-h743zi2
+  chip: STM32H743ZITx
+  protocol: Swd
+  connect_under_reset: true
+  probes:
+    - vendor_id: 0x0483
+      product_id: 0x374e
+"#
+                .as_bytes(),
+            )
+            .unwrap();
+
+        let board = registry.get_board_by_name("NUCLEO-H743ZI2").unwrap();
+        assert_eq!(board.chip, "STM32H743ZITx");
+        assert_eq!(board.protocol, Some(WireProtocol::Swd));
+        assert!(board.connect_under_reset);
+        assert!(board.has_probe(0x0483, 0x374e));
+        assert!(!board.has_probe(0x0483, 0x374b));
+
+        assert!(matches!(
+            registry.get_board_by_name("nucleo-f401re"),
+            Err(RegistryError::BoardNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn user_board_replaces_builtin_board() {
+        let mut registry = BoardRegistry { boards: vec![] };
+        registry
+            .add_boards_from_yaml("[{ name: custom, chip: nRF52840_xxAA }]".as_bytes())
+            .unwrap();
+        registry
+            .add_boards_from_yaml("[{ name: Custom, chip: nRF52833_xxAA, speed: 1000 }]".as_bytes())
+            .unwrap();
+
+        assert_eq!(registry.boards.len(), 1);
+        let board = registry.get_board_by_name("custom").unwrap();
+        assert_eq!(board.chip, "nRF52833_xxAA");
+        assert_eq!(board.speed, Some(1000));
+    }
+}
@@ -21,19 +21,27 @@
 //! To add a target at runtime, the [add_target_from_yaml] function can
 //! be used to read targets from a YAML file.
 //!
+//! ## Boards
+//!
+//! A [`Board`] maps the name of a board to its chip, and to the options which are needed to connect to it. The
+//! built-in boards are read from `boards/boards.yaml`, and more boards can be added with [add_boards_from_yaml].
+//!
 
+mod board;
 mod chip_info;
 mod registry;
 mod target;
 
 pub use probe_rs_target::{
-    Chip, ChipFamily, Core, CoreType, FlashProperties, GenericRegion, InstructionSet, MemoryRange,
-    MemoryRegion, NvmRegion, PageInfo, RamRegion, RawFlashAlgorithm, SectorDescription, SectorInfo,
+    write_protected_region, Chip, ChipFamily, Core, CoreType, FlashProperties, GenericRegion,
+    InstructionSet, MemoryAlias, MemoryAliasKind, MemoryRange, MemoryRegion, NvmRegion, PageInfo,
+    Partition, PartitionKind, RamRegion, RawFlashAlgorithm, SectorDescription, SectorInfo,
     TargetDescriptionSource,
 };
 
+pub use board::{add_boards_from_yaml, boards, get_board_by_name, Board, BoardProbe};
 pub use registry::{
-    add_target_from_yaml, families, get_target_by_name, search_chips, RegistryError,
+    add_target_from_yaml, families, get_target_by_name, search_chips, suggest_chips, RegistryError,
 };
 pub use target::{DebugSequence, Target, TargetParseError, TargetSelector};
 
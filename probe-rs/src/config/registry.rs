@@ -15,6 +15,8 @@ static REGISTRY: Lazy<Arc<Mutex<Registry>>> =
 #[derive(Debug, thiserror::Error)]
 pub enum RegistryError {
     /// The requested chip was not found in the registry.
+    ///
+    /// The closest matching chips can be found with [`suggest_chips`].
     #[error("The requested chip '{0}' was not found in the list of known targets.")]
     ChipNotFound(String),
     /// Multiple chips found which match the given string, unable to return a single chip.
@@ -37,6 +39,9 @@ pub enum RegistryError {
     /// An invalid [`ChipFamily`] was encountered.
     #[error("Invalid chip family definition ({})", .0.name)]
     InvalidChipFamilyDefinition(Box<ChipFamily>, String),
+    /// The requested board was not found in the registry of boards.
+    #[error("The requested board '{0}' was not found in the list of known boards.")]
+    BoardNotFound(String),
 }
 
 fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
@@ -105,6 +110,9 @@ fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
                 }],
                 memory_map: vec![],
                 flash_algorithms: vec![],
+                unique_id: None,
+                partitions: vec![],
+                aliases: vec![],
             }],
             flash_algorithms: vec![],
             source: TargetDescriptionSource::Generic,
@@ -230,6 +238,37 @@ impl Registry {
         targets
     }
 
+    /// Find the chips whose names are closest to `name`, ordered by similarity.
+    ///
+    /// This is used to give the user a hint when a chip name could not be found,
+    /// e.g. due to a typo.
+    fn suggest_chips(&self, name: &str) -> Vec<String> {
+        let name = name.to_ascii_lowercase();
+
+        // Allow roughly one typo for every four characters, but always allow at least two.
+        let max_distance = (name.len() / 4).max(2);
+
+        let mut candidates = Vec::new();
+
+        for family in &self.families {
+            for variant in family.variants.iter() {
+                let distance = chip_name_distance(&variant.name, &name);
+                if distance <= max_distance {
+                    candidates.push((distance, variant.name.clone()));
+                }
+            }
+        }
+
+        candidates.sort();
+        candidates.dedup_by(|(_, a), (_, b)| a == b);
+
+        candidates
+            .into_iter()
+            .take(MAX_CHIP_SUGGESTIONS)
+            .map(|(_, name)| name)
+            .collect()
+    }
+
     fn get_target_by_chip_info(&self, chip_info: ChipInfo) -> Result<Target, RegistryError> {
         let (family, chip) = {
             match chip_info {
@@ -305,6 +344,13 @@ pub fn get_target_by_name(name: impl AsRef<str>) -> Result<Target, RegistryError
     REGISTRY.lock().unwrap().get_target_by_name(name)
 }
 
+/// Get a list of chips from the internal registry whose names are close to `name`.
+///
+/// This can be used to suggest alternatives when a chip name is misspelled.
+pub fn suggest_chips(name: impl AsRef<str>) -> Vec<String> {
+    REGISTRY.lock().unwrap().suggest_chips(name.as_ref())
+}
+
 /// Get a target from the internal registry based on its name.
 pub fn search_chips(name: impl AsRef<str>) -> Result<Vec<String>, RegistryError> {
     Ok(REGISTRY.lock().unwrap().search_chips(name.as_ref()))
@@ -366,9 +412,50 @@ fn match_name_prefix(pattern: &str, name: &str) -> bool {
     true
 }
 
+/// Compute the edit distance between the chip name `pattern` and the (lower-case) user input `name`.
+///
+/// Any lower-case `x` character in `pattern` is treated as a wildcard, in the same way as in
+/// [`match_name_prefix`]. If `name` matches the beginning of `pattern`, the remaining
+/// characters of `pattern` are not counted, so that partial names are considered close.
+fn chip_name_distance(pattern: &str, name: &str) -> usize {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Classic Levenshtein distance, where `row[j]` is the distance between the
+    // current prefix of `pattern` and the first `j` characters of `name`.
+    let mut row: Vec<usize> = (0..=name.len()).collect();
+    // The best distance between `name` and any prefix of `pattern`.
+    let mut best = row[name.len()];
+
+    for p in pattern.iter() {
+        let mut previous_diagonal = row[0];
+        row[0] += 1;
+
+        for (j, n) in name.iter().enumerate() {
+            let cost = if p.to_ascii_lowercase() == *n || *p == 'x' {
+                0
+            } else {
+                1
+            };
+
+            let substitution = previous_diagonal + cost;
+            previous_diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+
+        best = best.min(row[name.len()]);
+    }
+
+    best
+}
+
+/// The maximum number of chip names which are returned by [`suggest_chips`].
+const MAX_CHIP_SUGGESTIONS: usize = 5;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::PartitionKind;
 
     #[test]
     fn try_fetch_not_unique() {
@@ -385,8 +472,38 @@ mod tests {
         let registry = Registry::from_builtin_families();
         assert!(matches!(
             registry.get_target_by_name("not_a_real_chip"),
+            Err(RegistryError::ChipNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn suggest_chip_with_typo() {
+        let registry = Registry::from_builtin_families();
+        // typo: 'nrf5284_xxAA' instead of 'nrf52840_xxAA'
+        assert!(matches!(
+            registry.get_target_by_name("nrf5284_xxAA"),
             Err(RegistryError::ChipNotFound(_))
         ));
+        assert_eq!(
+            registry
+                .suggest_chips("nrf5284_xxAA")
+                .first()
+                .map(String::as_str),
+            Some("nRF52840_xxAA")
+        );
+    }
+
+    #[test]
+    fn suggest_chip_without_match() {
+        let registry = Registry::from_builtin_families();
+        assert!(registry.suggest_chips("not_a_real_chip").is_empty());
+    }
+
+    #[test]
+    fn chip_name_distance_wildcard() {
+        assert_eq!(chip_name_distance("STM32F103C8Tx", "stm32f103c8t6"), 0);
+        assert_eq!(chip_name_distance("STM32F103C8Tx", "stm32f103c8"), 0);
+        assert_eq!(chip_name_distance("STM32F103C8Tx", "stm32f130c8"), 2);
     }
 
     #[test]
@@ -410,6 +527,92 @@ mod tests {
         assert!(registry.get_target_by_name("nrf51822_Xxaa").is_ok());
     }
 
+    const PARTITIONED_FAMILY: &str = r#"
+name: Partitioned Family
+manufacturer: null
+variants:
+  - name: PARTITIONED_CHIP
+    cores:
+      - name: main
+        type: armv7em
+        core_access_options:
+          !Arm
+            ap: 0x0
+            psel: 0x0
+    memory_map:
+      - !Nvm
+          range:
+            start: 0x0
+            end: 0x80000
+          is_boot_memory: true
+          cores:
+            - main
+    partitions:
+      - name: mcuboot
+        range:
+          start: 0x0
+          end: 0x10000
+        kind: bootloader
+      - name: slot0
+        range:
+          start: 0x10000
+          end: 0x40000
+        kind: slot
+      - name: slot1
+        range:
+          start: SLOT1_START
+          end: 0x70000
+        kind: slot
+      - name: storage
+        range:
+          start: 0x70000
+          end: 0x80000
+    flash_algorithms: []
+flash_algorithms: []
+"#;
+
+    #[test]
+    fn partitions_of_target() {
+        let mut registry = Registry::from_builtin_families();
+        registry
+            .add_target_from_yaml(
+                PARTITIONED_FAMILY
+                    .replace("SLOT1_START", "0x40000")
+                    .as_bytes(),
+            )
+            .unwrap();
+
+        let target = registry.get_target_by_name("PARTITIONED_CHIP").unwrap();
+        let slot1 = target.partition("slot1").unwrap();
+        assert_eq!(slot1.range, 0x40000..0x70000);
+        assert_eq!(slot1.kind, PartitionKind::Slot);
+        assert_eq!(
+            target.partition("storage").unwrap().kind,
+            PartitionKind::Other
+        );
+        assert!(target.partition("slot2").is_none());
+        assert_eq!(
+            target
+                .slots()
+                .map(|partition| partition.name.as_str())
+                .collect::<Vec<_>>(),
+            ["slot0", "slot1"]
+        );
+    }
+
+    #[test]
+    fn overlapping_partitions() {
+        let mut registry = Registry::from_builtin_families();
+        assert!(matches!(
+            registry.add_target_from_yaml(
+                PARTITIONED_FAMILY
+                    .replace("SLOT1_START", "0x3F000")
+                    .as_bytes()
+            ),
+            Err(RegistryError::InvalidChipFamilyDefinition(..))
+        ));
+    }
+
     #[test]
     fn validate_generic_targets() {
         let mut families = vec![];
@@ -432,4 +635,24 @@ mod tests {
             .collect::<Result<Vec<_>, _>>()
             .unwrap();
     }
+
+    #[test]
+    fn memory_map_of_each_core() {
+        let registry = Registry::from_builtin_families();
+        let target = registry.get_target_by_name("nRF5340_xxAA").unwrap();
+
+        let network_memory = target
+            .core_memory_map(1)
+            .map(|region| region.range().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            network_memory,
+            [0x2100_0000..0x2102_0000, 0x0100_0000..0x0104_0000]
+        );
+
+        assert!(target.core_can_access(0, 0x1000));
+        assert!(!target.core_can_access(1, 0x1000));
+        assert_eq!(target.core_index_for_address(0x0100_1000), Some(1));
+        assert_eq!(target.core_index_for_address(0x3000_0000), None);
+    }
 }
@@ -1,4 +1,7 @@
-use probe_rs_target::{Architecture, ChipFamily};
+use probe_rs_target::{
+    Architecture, ChipFamily, MemoryAlias, MemoryAliasKind, Partition, PartitionKind,
+    UniqueIdLocation,
+};
 
 use super::{Core, MemoryRegion, RawFlashAlgorithm, RegistryError, TargetDescriptionSource};
 use crate::architecture::arm::{
@@ -40,6 +43,12 @@ pub struct Target {
     pub(crate) source: TargetDescriptionSource,
     /// Debug sequences for the given target.
     pub debug_sequence: DebugSequence,
+    /// Where the unique device ID can be read from, if the target has one.
+    pub unique_id: Option<UniqueIdLocation>,
+    /// Named partitions of the memory map, like the bootloader and the slots of the firmware images.
+    pub partitions: Vec<Partition>,
+    /// Address ranges which mirror parts of the memory map, like flash which is also mapped at address 0.
+    pub aliases: Vec<MemoryAlias>,
 }
 
 impl std::fmt::Debug for Target {
@@ -178,6 +187,9 @@ impl Target {
             source: family.source.clone(),
             memory_map: chip.memory_map.clone(),
             debug_sequence,
+            unique_id: chip.unique_id,
+            partitions: chip.partitions.clone(),
+            aliases: chip.aliases.clone(),
         })
     }
 
@@ -214,7 +226,49 @@ impl Target {
     /// Create a [FlashLoader] for this target, which can be used
     /// to program its non-volatile memory.
     pub fn flash_loader(&self) -> FlashLoader {
-        FlashLoader::new(self.memory_map.clone(), self.source.clone())
+        let mut loader = FlashLoader::new(self.memory_map.clone(), self.source.clone());
+        for alias in &self.aliases {
+            loader.add_alias(alias.clone());
+        }
+        loader
+    }
+
+    /// Translate an address of a [`MemoryAlias`] to the address in the memory map which it mirrors. Other
+    /// addresses are returned as they are.
+    pub fn canonical_address(&self, address: u64) -> u64 {
+        self.aliases
+            .iter()
+            .find_map(|alias| alias.canonical_address(address))
+            .unwrap_or(address)
+    }
+
+    /// All addresses at which the byte at `address` can be accessed: its address in the memory map, followed by
+    /// the addresses of the aliases which mirror it. Bit-band aliases are left out, as they do not mirror bytes.
+    pub fn address_aliases(&self, address: u64) -> Vec<u64> {
+        let canonical = self.canonical_address(address);
+        let mut addresses = vec![canonical];
+        addresses.extend(
+            self.aliases
+                .iter()
+                .filter(|alias| alias.kind == MemoryAliasKind::Mirror)
+                .filter_map(|alias| alias.alias_address(canonical)),
+        );
+        addresses
+    }
+
+    /// Gets a [Partition] by name.
+    pub fn partition(&self, name: &str) -> Option<&Partition> {
+        self.partitions
+            .iter()
+            .find(|partition| partition.name == name)
+    }
+
+    /// The image slots of the target: the primary slot (A) first, followed by the secondary slot (B) if there is
+    /// one.
+    pub fn slots(&self) -> impl Iterator<Item = &Partition> {
+        self.partitions
+            .iter()
+            .filter(|partition| partition.kind == PartitionKind::Slot)
     }
 
     /// Gets a [RawFlashAlgorithm] by name.
@@ -222,11 +276,43 @@ impl Target {
         self.flash_algorithms.iter().find(|a| a.name == name)
     }
 
+    /// Returns true if the debug sequence of the target erases all flash memory, instead of a flash algorithm.
+    pub(crate) fn has_sequence_erase_all(&self) -> bool {
+        match &self.debug_sequence {
+            DebugSequence::Arm(seq) => seq.debug_erase_sequence().is_some(),
+            DebugSequence::Riscv(_) => false,
+        }
+    }
+
     /// Gets the core index from the core name
     pub(crate) fn core_index_by_name(&self, name: &str) -> Option<usize> {
         self.cores.iter().position(|c| c.name == name)
     }
 
+    /// The memory map of the core `core_index`: the regions which the core can access. Cores of a heterogeneous
+    /// multicore chip can see different memory at the same address.
+    pub fn core_memory_map(&self, core_index: usize) -> impl Iterator<Item = &MemoryRegion> {
+        let core_name = self.cores.get(core_index).map(|core| core.name.as_str());
+        self.memory_map.iter().filter(move |region| {
+            region
+                .cores()
+                .iter()
+                .any(|core| Some(core.as_str()) == core_name)
+        })
+    }
+
+    /// Returns true if the core `core_index` can access the memory at `address`.
+    pub fn core_can_access(&self, core_index: usize, address: u64) -> bool {
+        self.core_memory_map(core_index)
+            .any(|region| region.range().contains(&address))
+    }
+
+    /// The index of a core which can access the memory at `address`, preferring the default core, or `None` if the
+    /// address is not part of the memory map of any core.
+    pub fn core_index_for_address(&self, address: u64) -> Option<usize> {
+        (0..self.cores.len()).find(|&core_index| self.core_can_access(core_index, address))
+    }
+
     /// Gets the first found [MemoryRegion] that contains the given address
     pub(crate) fn get_memory_region_by_address(&self, address: u64) -> Option<&MemoryRegion> {
         self.memory_map.iter().find(|region| match region {
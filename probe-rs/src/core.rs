@@ -1,6 +1,10 @@
 use crate::{
-    architecture::arm::sequences::ArmDebugSequence, debug::DebugRegisters, error, CoreType, Error,
-    InstructionSet, MemoryInterface, Target,
+    architecture::arm::sequences::ArmDebugSequence,
+    config::{write_protected_region, MemoryRegion},
+    debug::DebugRegisters,
+    error, events,
+    session::{record_rtt_transfer, RttDirection, RttStatistics},
+    CoreType, Error, InstructionSet, MemoryInterface, Target,
 };
 use anyhow::{anyhow, Result};
 pub use probe_rs_target::{Architecture, CoreAccessOptions};
@@ -9,13 +13,18 @@ use std::{sync::Arc, time::Duration};
 
 pub mod core_state;
 pub mod core_status;
+pub mod function_call;
 pub mod memory_mapped_registers;
 pub mod registers;
+pub mod run_until;
+pub mod stop_reason;
 
 pub use core_state::*;
 pub use core_status::*;
 pub use memory_mapped_registers::MemoryMappedRegister;
 pub use registers::*;
+pub use run_until::RunUntilOutcome;
+pub use stop_reason::{StopReason, WatchpointAccess};
 
 /// An struct for storing the current state of a core.
 #[derive(Debug, Clone)]
@@ -24,6 +33,15 @@ pub struct CoreInformation {
     pub pc: u64,
 }
 
+/// The events which halt a core with a vector catch, see [`Core::enable_vector_catch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorCatchCondition {
+    /// A HardFault exception.
+    HardFault,
+    /// A local reset of the core.
+    CoreReset,
+}
+
 /// A generic interface to control a MCU core.
 pub trait CoreInterface: MemoryInterface + ExceptionInterface {
     /// Numerical ID of the core. Can be used as an argument to `Session::core()`.
@@ -132,6 +150,34 @@ pub trait CoreInterface: MemoryInterface + ExceptionInterface {
     /// decision for some core types.
     fn fpu_support(&mut self) -> Result<bool, error::Error>;
 
+    /// Determine the security state of the core, and if Secure code can be debugged.
+    ///
+    /// Returns `None` if the core does not implement a security extension, like TrustZone-M.
+    fn security_status(&mut self) -> Result<Option<SecurityStatus>, error::Error> {
+        Ok(None)
+    }
+
+    /// Determine the security attribution of a memory address.
+    ///
+    /// Returns `None` if the core does not implement a security extension,
+    /// or if the attribution cannot be read by the debugger.
+    fn security_attribution(
+        &mut self,
+        _address: u64,
+    ) -> Result<Option<SecurityAttribution>, error::Error> {
+        Ok(None)
+    }
+
+    /// Halt the core when the given condition occurs.
+    fn enable_vector_catch(&mut self, _condition: VectorCatchCondition) -> Result<(), Error> {
+        Err(Error::NotImplemented("vector catch"))
+    }
+
+    /// Don't halt the core anymore when the given condition occurs.
+    fn disable_vector_catch(&mut self, _condition: VectorCatchCondition) -> Result<(), Error> {
+        Err(Error::NotImplemented("vector catch"))
+    }
+
     /// Set the reset catch setting.
     ///
     /// This configures the core to halt after a reset.
@@ -182,30 +228,37 @@ impl<'probe> MemoryInterface for Core<'probe> {
     }
 
     fn write_word_64(&mut self, addr: u64, data: u64) -> Result<(), Error> {
+        self.check_write(addr, 8)?;
         self.inner.write_word_64(addr, data)
     }
 
     fn write_word_32(&mut self, addr: u64, data: u32) -> Result<(), Error> {
+        self.check_write(addr, 4)?;
         self.inner.write_word_32(addr, data)
     }
 
     fn write_word_8(&mut self, addr: u64, data: u8) -> Result<(), Error> {
+        self.check_write(addr, 1)?;
         self.inner.write_word_8(addr, data)
     }
 
     fn write_64(&mut self, addr: u64, data: &[u64]) -> Result<(), Error> {
+        self.check_write(addr, data.len() * 8)?;
         self.inner.write_64(addr, data)
     }
 
     fn write_32(&mut self, addr: u64, data: &[u32]) -> Result<(), Error> {
+        self.check_write(addr, data.len() * 4)?;
         self.inner.write_32(addr, data)
     }
 
     fn write_8(&mut self, addr: u64, data: &[u8]) -> Result<(), Error> {
+        self.check_write(addr, data.len())?;
         self.inner.write_8(addr, data)
     }
 
     fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), Error> {
+        self.check_write(addr, data.len())?;
         self.inner.write(addr, data)
     }
 
@@ -301,13 +354,21 @@ impl<'probe> ExceptionInterface for Core<'probe> {
 /// to allow potential other shareholders of the session struct to grab a core handle too.
 pub struct Core<'probe> {
     inner: Box<dyn CoreInterface + 'probe>,
+    rtt_statistics: &'probe mut RttStatistics,
+    protected_memory: &'probe [MemoryRegion],
 }
 
 impl<'probe> Core<'probe> {
     /// Create a new [`Core`].
-    pub(crate) fn new(core: impl CoreInterface + 'probe) -> Core<'probe> {
+    pub(crate) fn new(
+        core: impl CoreInterface + 'probe,
+        rtt_statistics: &'probe mut RttStatistics,
+        protected_memory: &'probe [MemoryRegion],
+    ) -> Core<'probe> {
         Self {
             inner: Box::new(core),
+            rtt_statistics,
+            protected_memory,
         }
     }
 
@@ -335,6 +396,8 @@ impl<'probe> Core<'probe> {
                     id,
                     core_state,
                     specific_state,
+                    rtt_statistics: RttStatistics::new(),
+                    protected_memory: Vec::new(),
                 }
             }
             CoreAccessOptions::Riscv(options) => {
@@ -343,6 +406,8 @@ impl<'probe> Core<'probe> {
                     id,
                     core_state,
                     specific_state,
+                    rtt_statistics: RttStatistics::new(),
+                    protected_memory: Vec::new(),
                 }
             }
         }
@@ -353,6 +418,39 @@ impl<'probe> Core<'probe> {
         self.inner.id()
     }
 
+    /// Count a transfer of `bytes` bytes over an RTT channel of this core.
+    #[cfg_attr(not(feature = "rtt"), allow(dead_code))]
+    pub(crate) fn record_rtt_transfer(
+        &mut self,
+        channel: usize,
+        direction: RttDirection,
+        bytes: usize,
+    ) {
+        let core = self.id();
+        record_rtt_transfer(self.rtt_statistics, core, channel, direction, bytes);
+    }
+
+    /// Forget the statistics of the RTT channels of this core, e.g. when RTT is attached again.
+    #[cfg_attr(not(feature = "rtt"), allow(dead_code))]
+    pub(crate) fn reset_rtt_statistics(&mut self) {
+        self.rtt_statistics.clear();
+    }
+
+    /// Refuse a write of `length` bytes at `address` if it touches a protected region of the memory map.
+    fn check_write(&self, address: u64, length: usize) -> Result<(), Error> {
+        if length == 0 {
+            return Ok(());
+        }
+        let range = address..address + length as u64;
+        match write_protected_region(self.protected_memory, &range) {
+            Some(region) => Err(Error::MemoryWriteProtected {
+                range,
+                region: Box::new(region.clone()),
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Wait until the core is halted. If the core does not halt on its own,
     /// a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) error will be returned.
     #[tracing::instrument(skip(self))]
@@ -370,13 +468,17 @@ impl<'probe> Core<'probe> {
     /// returns a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) otherwise.
     #[tracing::instrument(skip(self))]
     pub fn halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
-        self.inner.halt(timeout)
+        let info = self.inner.halt(timeout)?;
+        events::core_status(self.id(), CoreStatus::Halted(HaltReason::Request));
+        Ok(info)
     }
 
     /// Continue to execute instructions.
     #[tracing::instrument(skip(self))]
     pub fn run(&mut self) -> Result<(), error::Error> {
-        self.inner.run()
+        self.inner.run()?;
+        events::core_status(self.id(), CoreStatus::Running);
+        Ok(())
     }
 
     /// Reset the core, and then continue to execute instructions. If the core
@@ -394,19 +496,27 @@ impl<'probe> Core<'probe> {
     /// [`reset`]: Core::reset
     #[tracing::instrument(skip(self))]
     pub fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
-        self.inner.reset_and_halt(timeout)
+        let info = self.inner.reset_and_halt(timeout)?;
+        events::core_status(self.id(), CoreStatus::Halted(HaltReason::Request));
+        Ok(info)
     }
 
     /// Steps one instruction and then enters halted state again.
     #[tracing::instrument(skip(self))]
     pub fn step(&mut self) -> Result<CoreInformation, error::Error> {
-        self.inner.step()
+        // The core runs for the step, so the halt after it is a new halt.
+        events::core_status(self.id(), CoreStatus::Running);
+        let info = self.inner.step()?;
+        events::core_status(self.id(), CoreStatus::Halted(HaltReason::Step));
+        Ok(info)
     }
 
     /// Returns the current status of the core.
     #[tracing::instrument(skip(self))]
     pub fn status(&mut self) -> Result<CoreStatus, error::Error> {
-        self.inner.status()
+        let status = self.inner.status()?;
+        events::core_status(self.id(), status);
+        Ok(status)
     }
 
     /// Read the value of a core register.
@@ -516,6 +626,11 @@ impl<'probe> Core<'probe> {
         )))
     }
 
+    /// Returns the addresses of the hardware breakpoints which are set.
+    pub fn hw_breakpoints(&mut self) -> Result<Vec<u64>, error::Error> {
+        Ok(self.inner.hw_breakpoints()?.into_iter().flatten().collect())
+    }
+
     /// Set a hardware breakpoint
     ///
     /// This function will try to set a hardware breakpoint att `address`.
@@ -617,6 +732,38 @@ impl<'probe> Core<'probe> {
         self.inner.fpu_support()
     }
 
+    /// Determine the security state of the core, and if Secure code can be debugged.
+    ///
+    /// Returns `None` if the core does not implement a security extension, like TrustZone-M.
+    pub fn security_status(&mut self) -> Result<Option<SecurityStatus>, error::Error> {
+        self.inner.security_status()
+    }
+
+    /// Determine the security attribution of a memory address.
+    ///
+    /// Returns `None` if the core does not implement a security extension,
+    /// or if the attribution cannot be read by the debugger.
+    pub fn security_attribution(
+        &mut self,
+        address: u64,
+    ) -> Result<Option<SecurityAttribution>, error::Error> {
+        self.inner.security_attribution(address)
+    }
+
+    /// Halt the core when the given condition occurs, e.g. on a HardFault.
+    ///
+    /// The core reports the halt with [`HaltReason::Exception`].
+    #[tracing::instrument(skip(self))]
+    pub fn enable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.inner.enable_vector_catch(condition)
+    }
+
+    /// Don't halt the core anymore when the given condition occurs.
+    #[tracing::instrument(skip(self))]
+    pub fn disable_vector_catch(&mut self, condition: VectorCatchCondition) -> Result<(), Error> {
+        self.inner.disable_vector_catch(condition)
+    }
+
     pub(crate) fn reset_catch_clear(&mut self) -> Result<(), Error> {
         self.inner.reset_catch_clear()
     }
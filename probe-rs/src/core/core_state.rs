@@ -7,6 +7,8 @@ use crate::{
         },
         riscv::{communication_interface::RiscvCommunicationInterface, RiscVState},
     },
+    config::MemoryRegion,
+    session::RttStatistics,
     Core, CoreType, Error,
 };
 pub use probe_rs_target::{Architecture, CoreAccessOptions};
@@ -20,6 +22,13 @@ pub(crate) struct CombinedCoreState {
     pub(crate) specific_state: SpecificCoreState,
 
     pub(crate) id: usize,
+
+    /// The statistics of the RTT channels of the core, see [`crate::SessionState::rtt_channels`].
+    pub(crate) rtt_statistics: RttStatistics,
+
+    /// The regions of the memory map which writes of the core must not touch, see
+    /// [`crate::Session::protect_memory_writes`].
+    pub(crate) protected_memory: Vec<MemoryRegion>,
 }
 
 impl CombinedCoreState {
@@ -49,31 +58,41 @@ impl CombinedCoreState {
         Ok(match &mut self.specific_state {
             SpecificCoreState::Armv6m(s) => Core::new(
                 crate::architecture::arm::armv6m::Armv6m::new(memory, s, debug_sequence, self.id)?,
+                &mut self.rtt_statistics,
+                &self.protected_memory,
             ),
-            SpecificCoreState::Armv7a(s) => {
-                Core::new(crate::architecture::arm::armv7a::Armv7a::new(
+            SpecificCoreState::Armv7a(s) => Core::new(
+                crate::architecture::arm::armv7a::Armv7a::new(
                     memory,
                     s,
                     options.debug_base.expect("base_address not specified"),
                     debug_sequence,
                     self.id,
-                )?)
-            }
+                )?,
+                &mut self.rtt_statistics,
+                &self.protected_memory,
+            ),
             SpecificCoreState::Armv7m(s) | SpecificCoreState::Armv7em(s) => Core::new(
                 crate::architecture::arm::armv7m::Armv7m::new(memory, s, debug_sequence, self.id)?,
+                &mut self.rtt_statistics,
+                &self.protected_memory,
             ),
-            SpecificCoreState::Armv8a(s) => {
-                Core::new(crate::architecture::arm::armv8a::Armv8a::new(
+            SpecificCoreState::Armv8a(s) => Core::new(
+                crate::architecture::arm::armv8a::Armv8a::new(
                     memory,
                     s,
                     options.debug_base.expect("base_address not specified"),
                     options.cti_base.expect("cti_address not specified"),
                     debug_sequence,
                     self.id,
-                )?)
-            }
+                )?,
+                &mut self.rtt_statistics,
+                &self.protected_memory,
+            ),
             SpecificCoreState::Armv8m(s) => Core::new(
                 crate::architecture::arm::armv8m::Armv8m::new(memory, s, debug_sequence, self.id)?,
+                &mut self.rtt_statistics,
+                &self.protected_memory,
             ),
             _ => {
                 return Err(Error::UnableToOpenProbe(
@@ -140,9 +159,11 @@ impl CombinedCoreState {
         interface: &'probe mut RiscvCommunicationInterface,
     ) -> Result<Core<'probe>, Error> {
         Ok(match &mut self.specific_state {
-            SpecificCoreState::Riscv(s) => Core::new(crate::architecture::riscv::Riscv32::new(
-                interface, s, self.id,
-            )),
+            SpecificCoreState::Riscv(s) => Core::new(
+                crate::architecture::riscv::Riscv32::new(interface, s, self.id),
+                &mut self.rtt_statistics,
+                &self.protected_memory,
+            ),
             _ => {
                 return Err(Error::UnableToOpenProbe(
                     "Core architecture and Probe mismatch.",
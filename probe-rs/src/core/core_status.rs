@@ -63,3 +63,54 @@ pub enum HaltReason {
     /// This can happen for example when the core is already halted when we connect.
     Unknown,
 }
+
+/// The security state of a core which implements the ARMv8-M security extension (TrustZone-M).
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SecurityState {
+    /// The core executes in the Secure state.
+    Secure,
+    /// The core executes in the Non-secure state.
+    NonSecure,
+}
+
+impl std::fmt::Display for SecurityState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecurityState::Secure => write!(f, "Secure"),
+            SecurityState::NonSecure => write!(f, "Non-secure"),
+        }
+    }
+}
+
+/// The security state and the debug authorization of a core with the ARMv8-M security extension.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct SecurityStatus {
+    /// The security state the core is currently executing in.
+    pub state: SecurityState,
+    /// Indicates if the debugger is authorized to debug Secure code.
+    ///
+    /// If this is `false`, the core cannot be halted in the Secure state, and the Secure registers
+    /// and the Secure memory cannot be accessed.
+    pub secure_debug_enabled: bool,
+}
+
+/// The security attribution of a memory address, as configured in the Security Attribution Unit (SAU).
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SecurityAttribution {
+    /// The address is Secure.
+    Secure,
+    /// The address is Secure, and can be called from the Non-secure state (e.g. contains veneers with `SG` instructions).
+    NonSecureCallable,
+    /// The address is Non-secure.
+    NonSecure,
+}
+
+impl std::fmt::Display for SecurityAttribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecurityAttribution::Secure => write!(f, "Secure"),
+            SecurityAttribution::NonSecureCallable => write!(f, "Non-secure callable"),
+            SecurityAttribution::NonSecure => write!(f, "Non-secure"),
+        }
+    }
+}
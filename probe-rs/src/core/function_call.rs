@@ -0,0 +1,193 @@
+//! Call a function of the program on the halted core, see [`Core::call_function`].
+
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+
+use crate::{
+    Architecture, Core, CoreRegister, Error, MemoryInterface, RegisterValue, RunUntilOutcome,
+};
+
+/// Where the arguments of a call go, per the calling convention of the core.
+#[derive(Debug, PartialEq, Eq)]
+struct CallFrame {
+    /// The stack pointer at the call, below the arguments which are passed on the stack.
+    stack_pointer: u64,
+    /// The arguments which don't fit into the argument registers, as they are written to the stack.
+    stack_arguments: Vec<u8>,
+}
+
+impl CallFrame {
+    /// Lay out the `stack_arguments` of a call below the `stack_pointer` of the caller.
+    ///
+    /// The stack pointer is aligned to 8 bytes at a call per the AAPCS, and to 16 bytes per the RISC-V calling
+    /// convention. Each argument takes a word of `word_size` bytes.
+    fn new(
+        architecture: Architecture,
+        word_size: usize,
+        stack_pointer: u64,
+        stack_arguments: &[u64],
+    ) -> Self {
+        let alignment = match architecture {
+            Architecture::Arm => 8,
+            Architecture::Riscv => 16,
+        };
+        let stack_arguments = stack_arguments
+            .iter()
+            .flat_map(|argument| argument.to_le_bytes()[..word_size].to_vec())
+            .collect::<Vec<_>>();
+        let stack_pointer = (stack_pointer - stack_arguments.len() as u64) & !(alignment - 1);
+
+        Self {
+            stack_pointer,
+            stack_arguments,
+        }
+    }
+}
+
+impl<'probe> Core<'probe> {
+    /// Call the function at `address` of the program with `arguments`, and return its result, e.g. to run a helper
+    /// of the firmware from the debugger.
+    ///
+    /// The arguments are passed per the AAPCS on ARM cores, and per the RISC-V calling convention on RISC-V cores:
+    /// one argument per register, and the ones which don't fit into the argument registers on the stack. Arguments
+    /// which are wider than a register have to be split by the caller. The result is the first result register, so
+    /// the upper half of a result which is wider than a register is not returned.
+    ///
+    /// The core has to be halted. It runs from `address` until the function returns to the current program
+    /// counter, or `timeout` is used up, with a temporary breakpoint at the return address. The registers are
+    /// restored afterwards in any case, so the core is halted where it was, but the effects of the function on the
+    /// memory and the peripherals remain. Interrupts are not masked, so their handlers can run during the call.
+    #[tracing::instrument(skip(self))]
+    pub fn call_function(
+        &mut self,
+        address: u64,
+        arguments: &[u64],
+        timeout: Duration,
+    ) -> Result<u64, Error> {
+        if !self.core_halted()? {
+            return Err(Error::Other(anyhow!(
+                "The core has to be halted to call a function"
+            )));
+        }
+
+        // Registers which can't be read, e.g. of a disabled FPU, can't be changed by the call either.
+        let context = self
+            .registers()
+            .all_registers()
+            .filter_map(|register| {
+                self.read_core_reg::<RegisterValue>(register.id())
+                    .ok()
+                    .map(|value| (register.id(), value))
+            })
+            .collect::<Vec<_>>();
+
+        let result = self.call_with_arguments(address, arguments, timeout);
+
+        for (register, value) in context {
+            let restored = self.write_core_reg(register, value);
+            // The error of the call is more interesting than a failure to restore the context after it.
+            if result.is_ok() {
+                restored?;
+            }
+        }
+        result
+    }
+
+    fn call_with_arguments(
+        &mut self,
+        address: u64,
+        arguments: &[u64],
+        timeout: Duration,
+    ) -> Result<u64, Error> {
+        let architecture = self.architecture();
+        let argument_registers = (0..)
+            .map_while(|index| self.registers().get_argument_register(index))
+            .collect::<Vec<&CoreRegister>>();
+        let word_size = self.program_counter().size_in_bytes();
+
+        for (index, argument) in arguments.iter().enumerate() {
+            if word_size < 8 && *argument >> (8 * word_size) != 0 {
+                return Err(Error::Other(anyhow!(
+                    "The argument #{index} ({argument:#x}) is wider than a register"
+                )));
+            }
+        }
+
+        let split = arguments.len().min(argument_registers.len());
+        let (register_arguments, stack_arguments) = arguments.split_at(split);
+        for (register, argument) in argument_registers.iter().zip(register_arguments) {
+            self.write_core_reg(register.id(), *argument)?;
+        }
+
+        let stack_pointer: u64 = self.read_core_reg(self.stack_pointer())?;
+        let frame = CallFrame::new(architecture, word_size, stack_pointer, stack_arguments);
+        if !frame.stack_arguments.is_empty() {
+            self.write_8(frame.stack_pointer, &frame.stack_arguments)?;
+        }
+        self.write_core_reg(self.stack_pointer().id(), frame.stack_pointer)?;
+
+        // The function returns to the current program counter, where the core halts.
+        let return_address: u64 = self.read_core_reg(self.program_counter())?;
+        let (entry, link) = match architecture {
+            // The code of Cortex-M cores is always Thumb code, so the addresses which are branched to have bit 0 set.
+            Architecture::Arm => (address & !1, return_address | 1),
+            Architecture::Riscv => (address, return_address),
+        };
+        self.write_core_reg(self.return_address().id(), link)?;
+        self.write_core_reg(self.program_counter().id(), entry)?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.run_until(Some(return_address), remaining)? {
+                RunUntilOutcome::Reached { .. } => {
+                    // The return address is also reached when the function, or an interrupt during it, runs the code
+                    // at the return address itself. The function has only returned once its frame is gone.
+                    let stack_pointer: u64 = self.read_core_reg(self.stack_pointer())?;
+                    if stack_pointer == frame.stack_pointer {
+                        break;
+                    }
+                }
+                outcome => {
+                    return Err(Error::Other(anyhow!(
+                        "The function at {address:#010x} did not return: {outcome}"
+                    )))
+                }
+            }
+        }
+
+        self.read_core_reg(self.registers().result_register(0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CallFrame;
+    use crate::Architecture;
+
+    #[test]
+    fn stack_arguments_are_aligned_per_architecture() {
+        assert_eq!(
+            CallFrame::new(Architecture::Arm, 4, 0x2000_1000, &[5]),
+            CallFrame {
+                stack_pointer: 0x2000_0ff8,
+                stack_arguments: vec![5, 0, 0, 0],
+            }
+        );
+        assert_eq!(
+            CallFrame::new(Architecture::Riscv, 4, 0x8000_0ffc, &[]),
+            CallFrame {
+                stack_pointer: 0x8000_0ff0,
+                stack_arguments: Vec::new(),
+            }
+        );
+        assert_eq!(
+            CallFrame::new(Architecture::Riscv, 8, 0x8000_1000, &[0x1_0000_0002]),
+            CallFrame {
+                stack_pointer: 0x8000_0ff0,
+                stack_arguments: vec![2, 0, 0, 0, 1, 0, 0, 0],
+            }
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Run a core until it reaches an address, or a time budget is used up, see [`Core::run_until`].
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::{Core, CoreStatus, Error, HaltReason};
+
+/// The time to wait for the core to halt, when it is halted at the end of the run.
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+/// The time between two reads of the status of the running core.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How a [`Core::run_until`] ended. The core is halted in every case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunUntilOutcome {
+    /// The core halted at the requested address.
+    Reached {
+        /// The address at which the core halted.
+        address: u64,
+    },
+    /// The core halted for another reason first, e.g. on another breakpoint or an exception.
+    Halted {
+        /// Why the core halted.
+        reason: HaltReason,
+        /// The program counter at which the core halted.
+        address: u64,
+    },
+    /// The core locked up, and was halted.
+    LockedUp {
+        /// The program counter at which the core was halted.
+        address: u64,
+    },
+    /// The time budget was used up, so the core was halted.
+    Timeout {
+        /// The program counter at which the core was halted.
+        address: u64,
+    },
+}
+
+impl RunUntilOutcome {
+    /// The outcome of a run to `target`, after the core halted for `reason` at `address`.
+    fn from_halt(target: Option<u64>, reason: HaltReason, address: u64) -> Self {
+        match reason {
+            HaltReason::Breakpoint(_) | HaltReason::Multiple if target == Some(address) => {
+                Self::Reached { address }
+            }
+            reason => Self::Halted { reason, address },
+        }
+    }
+
+    /// The program counter at which the core is halted.
+    pub fn address(&self) -> u64 {
+        match *self {
+            Self::Reached { address }
+            | Self::Halted { address, .. }
+            | Self::LockedUp { address }
+            | Self::Timeout { address } => address,
+        }
+    }
+}
+
+impl fmt::Display for RunUntilOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reached { address } => write!(f, "Reached {address:#010x}"),
+            Self::Halted { reason, address } => {
+                write!(f, "Halted at {address:#010x} ({reason:?})")
+            }
+            Self::LockedUp { address } => write!(f, "Locked up at {address:#010x}"),
+            Self::Timeout { address } => write!(f, "Timed out at {address:#010x}"),
+        }
+    }
+}
+
+impl<'probe> Core<'probe> {
+    /// Run the core until it reaches `address`, halts for another reason, or `timeout` is used up, and halt it there.
+    ///
+    /// A running core is halted first. The run uses a temporary hardware breakpoint at `address`, which is cleared
+    /// again afterwards, unless a breakpoint was set there already. Without an `address`, the core runs until it
+    /// halts on its own, or for the whole `timeout`.
+    ///
+    /// The instruction at the current program counter is executed before the breakpoint can match, so a core which
+    /// is halted at `address` runs until it reaches `address` again.
+    #[tracing::instrument(skip(self))]
+    pub fn run_until(
+        &mut self,
+        address: Option<u64>,
+        timeout: Duration,
+    ) -> Result<RunUntilOutcome, Error> {
+        if !self.core_halted()? {
+            self.halt(HALT_TIMEOUT)?;
+        }
+
+        let temporary_breakpoint = match address {
+            Some(address) if !self.hw_breakpoints()?.contains(&address) => {
+                self.set_hw_breakpoint(address)?;
+                Some(address)
+            }
+            _ => None,
+        };
+
+        let outcome = self.run_until_halted(address, timeout);
+
+        if let Some(address) = temporary_breakpoint {
+            let cleared = self.clear_hw_breakpoint(address);
+            // The error of the run is more interesting than a failure to clean up after it.
+            if outcome.is_ok() {
+                cleared?;
+            }
+        }
+        outcome
+    }
+
+    fn run_until_halted(
+        &mut self,
+        address: Option<u64>,
+        timeout: Duration,
+    ) -> Result<RunUntilOutcome, Error> {
+        let start = Instant::now();
+        self.run()?;
+
+        loop {
+            match self.status()? {
+                CoreStatus::Halted(reason) => {
+                    let pc = self.read_core_reg(self.program_counter())?;
+                    return Ok(RunUntilOutcome::from_halt(address, reason, pc));
+                }
+                CoreStatus::LockedUp => {
+                    let info = self.halt(HALT_TIMEOUT)?;
+                    return Ok(RunUntilOutcome::LockedUp { address: info.pc });
+                }
+                _ if start.elapsed() >= timeout => {
+                    let info = self.halt(HALT_TIMEOUT)?;
+                    return Ok(RunUntilOutcome::Timeout { address: info.pc });
+                }
+                _ => std::thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RunUntilOutcome;
+    use crate::{BreakpointCause, HaltReason};
+
+    #[test]
+    fn only_a_breakpoint_at_the_target_reaches_it() {
+        let breakpoint = HaltReason::Breakpoint(BreakpointCause::Hardware);
+        assert_eq!(
+            RunUntilOutcome::from_halt(Some(0x100), breakpoint, 0x100),
+            RunUntilOutcome::Reached { address: 0x100 }
+        );
+        assert_eq!(
+            RunUntilOutcome::from_halt(Some(0x100), breakpoint, 0x200),
+            RunUntilOutcome::Halted {
+                reason: breakpoint,
+                address: 0x200
+            }
+        );
+        assert_eq!(
+            RunUntilOutcome::from_halt(Some(0x100), HaltReason::Exception, 0x100),
+            RunUntilOutcome::Halted {
+                reason: HaltReason::Exception,
+                address: 0x100
+            }
+        );
+        assert_eq!(
+            RunUntilOutcome::from_halt(None, breakpoint, 0x100).address(),
+            0x100
+        );
+    }
+}
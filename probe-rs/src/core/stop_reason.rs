@@ -0,0 +1,348 @@
+//! The detailed reason why a core halted, see [`Core::stop_reason`].
+
+use std::fmt;
+
+use crate::core::ExceptionInterface;
+use crate::debug::DebugRegisters;
+use crate::{Architecture, Core, CoreStatus, CoreType, Error, HaltReason, MemoryInterface};
+
+/// The control register of the DWT, which holds the number of comparators in bits 31:28.
+const DWT_CTRL: u64 = 0xE000_1000;
+/// The comparator register of the first DWT comparator, the registers of comparator `n` are `16 * n` bytes above.
+const DWT_COMP0: u64 = 0xE000_1020;
+/// The function register of the first DWT comparator.
+const DWT_FUNCTION0: u64 = 0xE000_1028;
+/// The `MATCHED` bit of a DWT function register, which is set when the comparator matched.
+const DWT_FUNCTION_MATCHED: u32 = 1 << 24;
+
+/// Thumb encoding of `BKPT 0xAB`, used for semihosting on ARMv6-M, ARMv7-M and ARMv8-M.
+const ARM_SEMIHOSTING_BKPT: u16 = 0xbeab;
+/// The upper byte of the Thumb encoding of `BKPT`.
+const ARM_BKPT: u8 = 0xbe;
+/// The RISC-V semihosting sequence is `slli zero, zero, 0x1f`, `ebreak`, `srai zero, zero, 7`.
+const RISCV_SEMIHOSTING_SEQUENCE: [u32; 3] = [0x01f0_1013, 0x0010_0073, 0x4070_5013];
+/// The encoding of `ebreak`.
+const RISCV_EBREAK: u32 = 0x0010_0073;
+/// The encoding of `c.ebreak`.
+const RISCV_C_EBREAK: u16 = 0x9002;
+
+/// The accesses which a watchpoint matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchpointAccess {
+    /// Reads of the watched address.
+    Read,
+    /// Writes of the watched address.
+    Write,
+    /// Reads and writes of the watched address.
+    ReadWrite,
+}
+
+impl WatchpointAccess {
+    /// Decode the match function of a DWT comparator, `None` if the comparator doesn't watch data accesses.
+    fn from_dwt_function(core_type: CoreType, function: u32) -> Option<Self> {
+        match (core_type, function & 0xf) {
+            (CoreType::Armv8m, 0b0100) => Some(Self::ReadWrite),
+            (CoreType::Armv8m, 0b0101) => Some(Self::Write),
+            (CoreType::Armv8m, 0b0110) => Some(Self::Read),
+            (CoreType::Armv8m, _) => None,
+            (_, 0b0101) => Some(Self::Read),
+            (_, 0b0110) => Some(Self::Write),
+            (_, 0b0111) => Some(Self::ReadWrite),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WatchpointAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchpointAccess::Read => write!(f, "read"),
+            WatchpointAccess::Write => write!(f, "write"),
+            WatchpointAccess::ReadWrite => write!(f, "read/write"),
+        }
+    }
+}
+
+/// Why a core halted, with the details which identify the cause, see [`Core::stop_reason`].
+///
+/// This refines the [`HaltReason`] of the core with what can be read from the target, e.g. which breakpoint was hit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StopReason {
+    /// The core hit a hardware breakpoint.
+    HardwareBreakpoint {
+        /// The index of the breakpoint comparator.
+        index: usize,
+        /// The address of the breakpoint.
+        address: u64,
+    },
+    /// The core executed a breakpoint instruction.
+    SoftwareBreakpoint {
+        /// The address of the instruction.
+        address: u64,
+    },
+    /// The core halted on a breakpoint, which is neither a set hardware breakpoint nor a breakpoint instruction.
+    Breakpoint {
+        /// The address at which the core halted.
+        address: u64,
+    },
+    /// The firmware made a semihosting call.
+    Semihosting {
+        /// The number of the requested operation.
+        operation: u32,
+    },
+    /// A data access matched a watchpoint.
+    Watchpoint {
+        /// The index of the comparator which matched, if it is known.
+        index: Option<usize>,
+        /// The watched address, if it is known.
+        address: Option<u64>,
+        /// The accesses which the watchpoint matches, if they are known.
+        access: Option<WatchpointAccess>,
+    },
+    /// A single step completed.
+    Step,
+    /// The debugger requested the halt.
+    Request,
+    /// An external debug request halted the core, e.g. another core or a cross trigger.
+    External,
+    /// The core halted on an exception or a fault.
+    Exception {
+        /// The decoded cause of the exception, if it can be decoded for the core.
+        description: Option<String>,
+    },
+    /// The core halted for more than one reason.
+    Multiple,
+    /// The reason can't be determined, e.g. because the core was halted before the debugger attached.
+    Unknown,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::HardwareBreakpoint { index, address } => {
+                write!(f, "Hit hardware breakpoint #{index} @{address:#010x}")
+            }
+            StopReason::SoftwareBreakpoint { address } => {
+                write!(f, "Hit breakpoint instruction @{address:#010x}")
+            }
+            StopReason::Breakpoint { address } => {
+                write!(f, "Halted on breakpoint @{address:#010x}")
+            }
+            StopReason::Semihosting { operation } => {
+                write!(f, "Halted on semihosting call {operation:#04x}")
+            }
+            StopReason::Watchpoint {
+                index,
+                address,
+                access,
+            } => {
+                write!(f, "Hit watchpoint")?;
+                if let Some(index) = index {
+                    write!(f, " #{index}")?;
+                }
+                if let Some(access) = access {
+                    write!(f, " on {access}")?;
+                }
+                if let Some(address) = address {
+                    write!(f, " of {address:#010x}")?;
+                }
+                Ok(())
+            }
+            StopReason::Step => write!(f, "Halted after a step"),
+            StopReason::Request => write!(f, "Halted by a debugger request"),
+            StopReason::External => write!(f, "Halted by an external debug request"),
+            StopReason::Exception {
+                description: Some(description),
+            } => write!(f, "Halted on exception: {description}"),
+            StopReason::Exception { description: None } => write!(f, "Halted on an exception"),
+            StopReason::Multiple => write!(f, "Halted for multiple reasons"),
+            StopReason::Unknown => write!(f, "Halted for an unknown reason"),
+        }
+    }
+}
+
+impl<'probe> Core<'probe> {
+    /// Determine in detail why the core halted, `None` if the core isn't halted.
+    ///
+    /// Breakpoints are matched against the hardware breakpoints and the instruction at the program counter, and
+    /// watchpoints against the comparators of the DWT on Cortex-M cores. Details which can't be read are left out,
+    /// rather than failing the whole classification.
+    pub fn stop_reason(&mut self) -> Result<Option<StopReason>, Error> {
+        let CoreStatus::Halted(reason) = self.status()? else {
+            return Ok(None);
+        };
+
+        let reason = match reason {
+            HaltReason::Breakpoint(_) => self.breakpoint_stop_reason()?,
+            HaltReason::Watchpoint => self.watchpoint_stop_reason(),
+            HaltReason::Exception => {
+                let registers = DebugRegisters::from_core(self);
+                StopReason::Exception {
+                    description: self.exception_description(&registers).ok(),
+                }
+            }
+            HaltReason::Step => StopReason::Step,
+            HaltReason::Request => StopReason::Request,
+            HaltReason::External => StopReason::External,
+            // Whether the core is halted on a semihosting call can be read from the target in any case.
+            HaltReason::Multiple => self
+                .pending_semihosting_operation()
+                .map_or(StopReason::Multiple, |operation| StopReason::Semihosting {
+                    operation,
+                }),
+            HaltReason::Unknown => self
+                .pending_semihosting_operation()
+                .map_or(StopReason::Unknown, |operation| StopReason::Semihosting {
+                    operation,
+                }),
+        };
+        Ok(Some(reason))
+    }
+
+    fn breakpoint_stop_reason(&mut self) -> Result<StopReason, Error> {
+        let address: u64 = self.read_core_reg(self.program_counter())?;
+
+        if let Some(operation) = self.semihosting_operation(address) {
+            return Ok(StopReason::Semihosting { operation });
+        }
+
+        let hw_breakpoints = self.inner.hw_breakpoints().unwrap_or_default();
+        if let Some(index) = hw_breakpoints
+            .iter()
+            .position(|breakpoint| *breakpoint == Some(address))
+        {
+            return Ok(StopReason::HardwareBreakpoint { index, address });
+        }
+
+        if self.is_breakpoint_instruction(address) {
+            Ok(StopReason::SoftwareBreakpoint { address })
+        } else {
+            Ok(StopReason::Breakpoint { address })
+        }
+    }
+
+    /// The operation of the semihosting call at the program counter, if there is one.
+    fn pending_semihosting_operation(&mut self) -> Option<u32> {
+        let address: u64 = self.read_core_reg(self.program_counter()).ok()?;
+        self.semihosting_operation(address)
+    }
+
+    /// The operation of the semihosting call at `address`, if there is one.
+    fn semihosting_operation(&mut self, address: u64) -> Option<u32> {
+        let is_semihosting = match self.architecture() {
+            Architecture::Arm => {
+                let mut instruction = [0u8; 2];
+                self.read_8(address, &mut instruction).ok()?;
+                u16::from_le_bytes(instruction) == ARM_SEMIHOSTING_BKPT
+            }
+            Architecture::Riscv => {
+                let mut sequence = [0u32; 3];
+                self.read_32(address.saturating_sub(4), &mut sequence)
+                    .ok()?;
+                sequence == RISCV_SEMIHOSTING_SEQUENCE
+            }
+        };
+
+        if !is_semihosting {
+            return None;
+        }
+        self.read_core_reg(self.registers().argument_register(0))
+            .ok()
+    }
+
+    fn is_breakpoint_instruction(&mut self, address: u64) -> bool {
+        let mut instruction = [0u8; 4];
+        if self.read_8(address, &mut instruction[..2]).is_err() {
+            return false;
+        }
+        match self.architecture() {
+            Architecture::Arm => instruction[1] == ARM_BKPT,
+            Architecture::Riscv => {
+                if u16::from_le_bytes([instruction[0], instruction[1]]) == RISCV_C_EBREAK {
+                    return true;
+                }
+                self.read_8(address + 2, &mut instruction[2..]).is_ok()
+                    && u32::from_le_bytes(instruction) == RISCV_EBREAK
+            }
+        }
+    }
+
+    fn watchpoint_stop_reason(&mut self) -> StopReason {
+        let unknown = StopReason::Watchpoint {
+            index: None,
+            address: None,
+            access: None,
+        };
+        let core_type = self.core_type();
+        if !core_type.is_cortex_m() {
+            return unknown;
+        }
+
+        let Ok(ctrl) = self.read_word_32(DWT_CTRL) else {
+            return unknown;
+        };
+        for index in 0..(ctrl >> 28) as u64 {
+            // Reading the function register clears the `MATCHED` bit.
+            let Ok(function) = self.read_word_32(DWT_FUNCTION0 + 16 * index) else {
+                return unknown;
+            };
+            if function & DWT_FUNCTION_MATCHED != 0 {
+                return StopReason::Watchpoint {
+                    index: Some(index as usize),
+                    address: self
+                        .read_word_32(DWT_COMP0 + 16 * index)
+                        .ok()
+                        .map(u64::from),
+                    access: WatchpointAccess::from_dwt_function(core_type, function),
+                };
+            }
+        }
+        unknown
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StopReason, WatchpointAccess};
+    use crate::CoreType;
+
+    #[test]
+    fn dwt_functions_are_decoded_per_architecture() {
+        let decode = WatchpointAccess::from_dwt_function;
+        assert_eq!(
+            decode(CoreType::Armv7em, 0x0100_0006),
+            Some(WatchpointAccess::Write)
+        );
+        assert_eq!(
+            decode(CoreType::Armv6m, 0b0111),
+            Some(WatchpointAccess::ReadWrite)
+        );
+        assert_eq!(
+            decode(CoreType::Armv8m, 0b0110),
+            Some(WatchpointAccess::Read)
+        );
+        assert_eq!(decode(CoreType::Armv8m, 0b0111), None);
+        assert_eq!(decode(CoreType::Armv7m, 0b0100), None);
+    }
+
+    #[test]
+    fn watchpoints_are_described_with_the_known_details() {
+        let reason = StopReason::Watchpoint {
+            index: Some(1),
+            address: Some(0x2000_0010),
+            access: Some(WatchpointAccess::Write),
+        };
+        assert_eq!(
+            reason.to_string(),
+            "Hit watchpoint #1 on write of 0x20000010"
+        );
+
+        let reason = StopReason::Watchpoint {
+            index: None,
+            address: None,
+            access: None,
+        };
+        assert_eq!(reason.to_string(), "Hit watchpoint");
+    }
+}
@@ -6,29 +6,33 @@ use crate::core::UnwindRule;
 use crate::{
     core::Core,
     core::{ExceptionInfo, ExceptionInterface, RegisterRole, RegisterValue},
-    debug::{registers, source_statement::SourceStatements},
+    debug::{
+        registers,
+        relocation::{RelocatedReader, Relocation},
+        source_statement::SourceStatements,
+    },
     MemoryInterface,
 };
 use ::gimli::{FileEntry, LineProgramHeader, UnwindContext};
 use gimli::{BaseAddresses, ColumnType, DebugFrame, UnwindSection};
 use object::read::{Object, ObjectSection};
-use probe_rs_target::InstructionSet;
+use probe_rs_target::{InstructionSet, MemoryAlias, MemoryAliasKind};
 use std::{
     borrow,
     cmp::Ordering,
     convert::TryInto,
     num::NonZeroU64,
-    ops::ControlFlow,
+    ops::{ControlFlow, Range},
     path::{Path, PathBuf},
     rc::Rc,
     str::from_utf8,
 };
 
-pub(crate) type GimliReader = gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>;
+pub(crate) type GimliReader = RelocatedReader;
 
 pub(crate) type GimliAttribute = gimli::Attribute<GimliReader>;
 
-pub(crate) type DwarfReader = gimli::read::EndianRcSlice<gimli::LittleEndian>;
+pub(crate) type DwarfReader = RelocatedReader;
 
 /// Capture the required information when a breakpoint is set based on a requested source location.
 /// It is possible that the requested source location cannot be resolved to a valid instruction address,
@@ -42,6 +46,17 @@ pub struct VerifiedBreakpoint {
     pub source_location: SourceLocation,
 }
 
+/// The function and the source location of an address, see [`DebugInfo::resolve_address`].
+#[derive(Clone, Debug)]
+pub struct ResolvedAddress {
+    /// The address which was resolved.
+    pub address: u64,
+    /// The name of the innermost function at the address, which can be an inlined function.
+    pub function: Option<String>,
+    /// The source location of the instruction at the address.
+    pub source_location: Option<SourceLocation>,
+}
+
 /// Debug information which is parsed from DWARF debugging information.
 pub struct DebugInfo {
     pub(crate) dwarf: gimli::Dwarf<DwarfReader>,
@@ -49,6 +64,12 @@ pub struct DebugInfo {
     pub(crate) locations_section: gimli::LocationLists<DwarfReader>,
     pub(crate) address_section: gimli::DebugAddr<DwarfReader>,
     pub(crate) debug_line_section: gimli::DebugLine<DwarfReader>,
+    /// How the addresses are moved, if the program does not run at the address it is linked at.
+    pub(crate) relocation: Option<Rc<Relocation>>,
+    /// The addresses of the code and the constants of the program, where it runs.
+    code: Option<Range<u64>>,
+    /// Address ranges which mirror the memory of the target, see [`DebugInfo::set_memory_aliases`].
+    memory_aliases: Vec<MemoryAlias>,
 }
 
 impl DebugInfo {
@@ -59,22 +80,69 @@ impl DebugInfo {
         DebugInfo::from_raw(&data)
     }
 
+    /// Read debug info directly from a ELF file, for a program which runs at `load_offset` from the address
+    /// it is linked at, e.g. because it was copied to RAM.
+    ///
+    /// The addresses of the code and the constants of the program are moved by the offset, but not the
+    /// addresses of variables in RAM.
+    pub fn from_file_with_load_offset<P: AsRef<Path>>(
+        path: P,
+        load_offset: i64,
+    ) -> Result<DebugInfo, DebugError> {
+        let data = std::fs::read(path)?;
+
+        DebugInfo::from_raw_with_load_offset(&data, load_offset)
+    }
+
     /// Parse debug information directly from a buffer containing an ELF file.
     pub fn from_raw(data: &[u8]) -> Result<Self, DebugError> {
+        DebugInfo::from_raw_with_load_offset(data, 0)
+    }
+
+    /// Parse debug information directly from a buffer containing an ELF file, for a program which runs at
+    /// `load_offset` from the address it is linked at. See [`DebugInfo::from_file_with_load_offset`].
+    pub fn from_raw_with_load_offset(data: &[u8], load_offset: i64) -> Result<Self, DebugError> {
         let object = object::File::parse(data)?;
 
+        let code = read_only_range(&object).map(|image| {
+            image.start.wrapping_add(load_offset as u64)..image.end.wrapping_add(load_offset as u64)
+        });
+        let relocation = if load_offset != 0 {
+            read_only_range(&object).map(|image| {
+                Rc::new(Relocation {
+                    image,
+                    offset: load_offset as u64,
+                })
+            })
+        } else {
+            None
+        };
+
         // Load a section and return as `Cow<[u8]>`.
-        let load_section = |id: gimli::SectionId| -> Result<DwarfReader, gimli::Error> {
+        let load_section_with = |id: gimli::SectionId,
+                                 relocation: Option<Rc<Relocation>>|
+         -> Result<DwarfReader, gimli::Error> {
             let data = object
                 .section_by_name(id.name())
                 .and_then(|section| section.uncompressed_data().ok())
                 .unwrap_or_else(|| borrow::Cow::Borrowed(&[][..]));
 
-            Ok(gimli::read::EndianRcSlice::new(
-                Rc::from(&*data),
-                gimli::LittleEndian,
+            Ok(RelocatedReader::new(
+                gimli::read::EndianRcSlice::new(Rc::from(&*data), gimli::LittleEndian),
+                relocation,
             ))
         };
+        // The addresses of the DWARF 4 range and location lists are relative to the base address of their
+        // compilation unit, which is moved already. The frame descriptions contain address ranges as lengths,
+        // so addresses are looked up at their link address instead.
+        let load_section = |id: gimli::SectionId| -> Result<DwarfReader, gimli::Error> {
+            match id {
+                gimli::SectionId::DebugRanges
+                | gimli::SectionId::DebugLoc
+                | gimli::SectionId::DebugFrame => load_section_with(id, None),
+                _ => load_section_with(id, relocation.clone()),
+            }
+        };
 
         // Load all of the sections.
         let dwarf_cow = gimli::Dwarf::load(&load_section)?;
@@ -93,9 +161,54 @@ impl DebugInfo {
             locations_section,
             address_section,
             debug_line_section,
+            relocation,
+            code,
+            memory_aliases: Vec::new(),
         })
     }
 
+    /// Set the address ranges which mirror the memory of the target, e.g. from [`Target::aliases`].
+    ///
+    /// Addresses at another alias of the memory than the one the program is linked at, like a program counter in
+    /// the flash alias at address 0, are then looked up at the address of the program.
+    ///
+    /// [`Target::aliases`]: crate::Target::aliases
+    pub fn set_memory_aliases(&mut self, aliases: Vec<MemoryAlias>) {
+        self.memory_aliases = aliases;
+    }
+
+    /// The address ranges which mirror the memory of the target, see [`DebugInfo::set_memory_aliases`].
+    pub fn memory_aliases(&self) -> &[MemoryAlias] {
+        &self.memory_aliases
+    }
+
+    /// The address in the program of `address`, if it is at another alias of the memory of the program. Other
+    /// addresses are returned as they are.
+    pub fn program_address(&self, address: u64) -> u64 {
+        let Some(code) = &self.code else {
+            return address;
+        };
+        if code.contains(&address) {
+            return address;
+        }
+
+        let canonical = self
+            .memory_aliases
+            .iter()
+            .find_map(|alias| alias.canonical_address(address))
+            .unwrap_or(address);
+        let mirrors = self
+            .memory_aliases
+            .iter()
+            .filter(|alias| alias.kind == MemoryAliasKind::Mirror)
+            .filter_map(|alias| alias.alias_address(canonical));
+
+        std::iter::once(canonical)
+            .chain(mirrors)
+            .find(|candidate| code.contains(candidate))
+            .unwrap_or(address)
+    }
+
     /// Get the name of the function at the given address.
     ///
     /// If no function is found, `None` will be returend.
@@ -108,6 +221,7 @@ impl DebugInfo {
         address: u64,
         find_inlined: bool,
     ) -> Result<Option<String>, DebugError> {
+        let address = self.program_address(address);
         let mut units = self.dwarf.units();
 
         while let Some(unit_info) = self.get_next_unit_info(&mut units) {
@@ -127,8 +241,33 @@ impl DebugInfo {
         Ok(None)
     }
 
+    /// Resolve an address to the function and the source location it belongs to.
+    pub fn resolve_address(&self, address: u64) -> Result<ResolvedAddress, DebugError> {
+        Ok(ResolvedAddress {
+            address,
+            function: self.function_name(address, true)?,
+            source_location: self.get_source_location(address),
+        })
+    }
+
+    /// Get the address ranges of the functions with the given name.
+    ///
+    /// The name is matched against the unqualified name of the function (e.g. `main` or `new`), so more than one
+    /// function can be found. Functions which are only inlined have no address of their own, and are not found.
+    pub fn function_ranges(&self, name: &str) -> Result<Vec<Range<u64>>, DebugError> {
+        let mut ranges = Vec::new();
+
+        let mut units = self.get_units();
+        while let Some(unit_info) = self.get_next_unit_info(&mut units) {
+            ranges.extend(unit_info.get_function_ranges(name)?);
+        }
+
+        Ok(ranges)
+    }
+
     /// Try get the [`SourceLocation`] for a given address.
     pub fn get_source_location(&self, address: u64) -> Option<SourceLocation> {
+        let address = self.program_address(address);
         let mut units = self.dwarf.units();
 
         while let Ok(Some(header)) = units.next() {
@@ -748,9 +887,10 @@ impl DebugInfo {
             };
 
             // PART 1: Construct the `StackFrame` for the current pc.
-            let frame_pc = frame_pc_register_value
+            let frame_pc: u64 = frame_pc_register_value
                 .try_into()
                 .map_err(|error| crate::Error::Register(format!("Cannot convert register value for program counter to a 64-bit integeer value: {:?}", error)))?;
+            let frame_pc = self.program_address(frame_pc);
             tracing::trace!(
                 "UNWIND: Will generate `StackFrame` for function at address (PC) {}",
                 frame_pc,
@@ -843,7 +983,11 @@ impl DebugInfo {
                 return_frame.source_location
             );
             // PART 2-a: get the `gimli::FrameDescriptorEntry` for this address and then the unwind info associated with this row.
-            match get_unwind_info(&mut unwind_context, &self.frame_section, frame_pc) {
+            let frame_link_address = self
+                .relocation
+                .as_ref()
+                .map_or(frame_pc, |relocation| relocation.link_address(frame_pc));
+            match get_unwind_info(&mut unwind_context, &self.frame_section, frame_link_address) {
                 Ok(unwind_info) => {
                     // Because we will be updating the `unwind_registers` with previous frame unwind info, we need to keep a copy of the current frame's registers that can be used to resolve [DWARF](https://dwarfstd.org) expressions.
                     let callee_frame_registers = unwind_registers.clone();
@@ -1205,6 +1349,23 @@ pub(crate) fn canonical_path_eq(primary_path: &Path, secondary_path: &Path) -> b
         })
 }
 
+/// The range of the link addresses of the allocated, read-only sections of a program.
+fn read_only_range(object: &object::File) -> Option<Range<u64>> {
+    object
+        .sections()
+        .filter(|section| match section.flags() {
+            object::SectionFlags::Elf { sh_flags } => {
+                let sh_flags = sh_flags as u32;
+                sh_flags & object::elf::SHF_ALLOC != 0
+                    && sh_flags & object::elf::SHF_WRITE == 0
+                    && section.size() > 0
+            }
+            _ => false,
+        })
+        .map(|section| section.address()..section.address() + section.size())
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+}
+
 /// Get a handle to the [`gimli::UnwindTableRow`] for this call frame, so that we can reference it to unwind register values.
 fn get_unwind_info<'a>(
     unwind_context: &'a mut Box<UnwindContext<DwarfReader>>,
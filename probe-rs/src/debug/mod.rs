@@ -16,6 +16,8 @@ pub mod debug_step;
 pub mod function_die;
 /// Target Register definitions, expanded from [`crate::core::registers::CoreRegister`] to include unwind specific information.
 pub mod registers;
+/// Moving the addresses of a program which does not run at the address it is linked at.
+pub(crate) mod relocation;
 /// The source statement information used while identifying haltpoints for debug stepping and breakpoints.
 pub(crate) mod source_statement;
 /// The stack frame information used while unwinding the stack from a specific program counter.
@@ -0,0 +1,197 @@
+use std::{borrow::Cow, ops::Deref, ops::Range, rc::Rc};
+
+use gimli::{EndianRcSlice, LittleEndian, Reader, ReaderOffsetId};
+
+/// Moves the addresses of a program which runs at an offset from the address it is linked at.
+///
+/// Only the addresses in the range of the read-only sections of the program (its code and constants) are moved,
+/// because these are the ones which are copied or run at another address. Variables in RAM stay where they
+/// are linked. An address of zero is moved as well, because it is the base address of compilation units whose
+/// code is given with address ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Relocation {
+    /// The link addresses of the read-only sections.
+    pub(crate) image: Range<u64>,
+    /// The offset which is added to the link addresses, as a wrapping `u64`.
+    pub(crate) offset: u64,
+}
+
+impl Relocation {
+    /// The address at which the program runs, for an address in the debug information.
+    pub(crate) fn load_address(&self, address: u64, address_size: u8) -> u64 {
+        if address == 0 || self.image.contains(&address) {
+            let moved = address.wrapping_add(self.offset);
+            match address_size {
+                1..=7 => moved & ((1 << (address_size as u64 * 8)) - 1),
+                _ => moved,
+            }
+        } else {
+            address
+        }
+    }
+
+    /// The address in the debug information, for an address at which the program runs.
+    pub(crate) fn link_address(&self, address: u64) -> u64 {
+        let link_address = address.wrapping_sub(self.offset) & address_mask(address);
+        if self.image.contains(&link_address) {
+            link_address
+        } else {
+            address
+        }
+    }
+}
+
+/// Keep a wrapped address in the 32 bit address space, if the address is part of it.
+fn address_mask(address: u64) -> u64 {
+    if address <= u32::MAX as u64 {
+        u32::MAX as u64
+    } else {
+        u64::MAX
+    }
+}
+
+/// A [`gimli::Reader`] which moves every address it reads with a [`Relocation`].
+#[derive(Debug, Clone)]
+pub(crate) struct RelocatedReader {
+    reader: EndianRcSlice<LittleEndian>,
+    relocation: Option<Rc<Relocation>>,
+}
+
+impl RelocatedReader {
+    pub(crate) fn new(
+        reader: EndianRcSlice<LittleEndian>,
+        relocation: Option<Rc<Relocation>>,
+    ) -> Self {
+        Self { reader, relocation }
+    }
+}
+
+impl Deref for RelocatedReader {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.reader
+    }
+}
+
+impl Reader for RelocatedReader {
+    type Endian = LittleEndian;
+    type Offset = usize;
+
+    fn read_address(&mut self, address_size: u8) -> gimli::Result<u64> {
+        let address = self.reader.read_address(address_size)?;
+        Ok(match &self.relocation {
+            Some(relocation) => relocation.load_address(address, address_size),
+            None => address,
+        })
+    }
+
+    fn endian(&self) -> Self::Endian {
+        self.reader.endian()
+    }
+
+    fn len(&self) -> Self::Offset {
+        self.reader.len()
+    }
+
+    fn empty(&mut self) {
+        self.reader.empty()
+    }
+
+    fn truncate(&mut self, len: Self::Offset) -> gimli::Result<()> {
+        self.reader.truncate(len)
+    }
+
+    fn offset_from(&self, base: &Self) -> Self::Offset {
+        self.reader.offset_from(&base.reader)
+    }
+
+    fn offset_id(&self) -> ReaderOffsetId {
+        self.reader.offset_id()
+    }
+
+    fn lookup_offset_id(&self, id: ReaderOffsetId) -> Option<Self::Offset> {
+        self.reader.lookup_offset_id(id)
+    }
+
+    fn find(&self, byte: u8) -> gimli::Result<Self::Offset> {
+        self.reader.find(byte)
+    }
+
+    fn skip(&mut self, len: Self::Offset) -> gimli::Result<()> {
+        self.reader.skip(len)
+    }
+
+    fn split(&mut self, len: Self::Offset) -> gimli::Result<Self> {
+        Ok(Self {
+            reader: self.reader.split(len)?,
+            relocation: self.relocation.clone(),
+        })
+    }
+
+    fn to_slice(&self) -> gimli::Result<Cow<[u8]>> {
+        self.reader.to_slice()
+    }
+
+    fn to_string(&self) -> gimli::Result<Cow<str>> {
+        self.reader.to_string()
+    }
+
+    fn to_string_lossy(&self) -> gimli::Result<Cow<str>> {
+        self.reader.to_string_lossy()
+    }
+
+    fn read_slice(&mut self, buf: &mut [u8]) -> gimli::Result<()> {
+        self.reader.read_slice(buf)
+    }
+
+    fn read_u8(&mut self) -> gimli::Result<u8> {
+        self.reader.read_u8()
+    }
+
+    fn read_u16(&mut self) -> gimli::Result<u16> {
+        self.reader.read_u16()
+    }
+
+    fn read_u32(&mut self) -> gimli::Result<u32> {
+        self.reader.read_u32()
+    }
+
+    fn read_u64(&mut self) -> gimli::Result<u64> {
+        self.reader.read_u64()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn relocation() -> Relocation {
+        Relocation {
+            image: 0x0800_0000..0x0801_0000,
+            offset: 0x8000,
+        }
+    }
+
+    #[test]
+    fn code_addresses_are_moved() {
+        let relocation = relocation();
+        assert_eq!(relocation.load_address(0x0800_0100, 4), 0x0800_8100);
+        assert_eq!(relocation.load_address(0, 4), 0x8000);
+        // Variables in RAM are not moved.
+        assert_eq!(relocation.load_address(0x2000_0000, 4), 0x2000_0000);
+
+        assert_eq!(relocation.link_address(0x0800_8100), 0x0800_0100);
+        assert_eq!(relocation.link_address(0x2000_0000), 0x2000_0000);
+    }
+
+    #[test]
+    fn negative_offsets_wrap_in_the_address_space() {
+        let relocation = Relocation {
+            image: 0x0001_0000..0x0002_0000,
+            offset: (-0x0001_0000i64) as u64,
+        };
+        assert_eq!(relocation.load_address(0x0001_0100, 4), 0x0100);
+        assert_eq!(relocation.link_address(0x0100), 0x0001_0100);
+    }
+}
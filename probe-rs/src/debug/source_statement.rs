@@ -1,4 +1,4 @@
-use super::{unit_info::UnitInfo, DebugError, DebugInfo};
+use super::{unit_info::UnitInfo, DebugError, DebugInfo, GimliReader};
 use gimli::{ColumnType, LineSequence};
 use std::{
     fmt::{Debug, Formatter},
@@ -234,11 +234,8 @@ fn get_program_info_at_pc(
     program_counter: u64,
 ) -> Result<
     (
-        gimli::CompleteLineProgram<
-            gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>,
-            usize,
-        >,
-        gimli::LineSequence<gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>>,
+        gimli::CompleteLineProgram<GimliReader, usize>,
+        gimli::LineSequence<GimliReader>,
     ),
     DebugError,
 > {
@@ -277,7 +274,7 @@ fn get_program_info_at_pc(
 
 /// Helper function to avoid code duplication when logging of information during row evaluation.
 fn log_row_eval(
-    active_sequence: &LineSequence<super::GimliReader>,
+    active_sequence: &LineSequence<GimliReader>,
     pc: u64,
     row: &gimli::LineRow,
     status: &str,
@@ -7,8 +7,7 @@ use crate::{core::Core, core::RegisterValue, MemoryInterface};
 use gimli::{AttributeValue::Language, Location, UnitOffset};
 use num_traits::Zero;
 
-pub(crate) type UnitIter =
-    gimli::DebugInfoUnitHeadersIter<gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>>;
+pub(crate) type UnitIter = gimli::DebugInfoUnitHeadersIter<GimliReader>;
 
 /// The result of `UnitInfo::evaluate_expression()` can be the value of a variable, or a memory location.
 pub(crate) enum ExpressionResult {
@@ -41,6 +40,37 @@ impl<'debuginfo> UnitInfo<'debuginfo> {
         }
     }
 
+    /// Get the address ranges of all functions with the given name in this unit.
+    ///
+    /// Functions which are only inlined have no address ranges of their own, and are not found.
+    pub(crate) fn get_function_ranges(
+        &self,
+        name: &str,
+    ) -> Result<Vec<std::ops::Range<u64>>, DebugError> {
+        let mut function_ranges = Vec::new();
+
+        let mut entries_cursor = self.unit.entries();
+        while let Ok(Some((_depth, current))) = entries_cursor.next_dfs() {
+            if current.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+
+            let Some(die) = FunctionDie::new(current.clone(), self) else {
+                continue;
+            };
+            if die.function_name().as_deref() != Some(name) {
+                continue;
+            }
+
+            let mut ranges = self.debug_info.dwarf.die_ranges(&self.unit, current)?;
+            while let Ok(Some(range)) = ranges.next() {
+                function_ranges.push(range.begin..range.end);
+            }
+        }
+
+        Ok(function_ranges)
+    }
+
     /// Get the DIE for the function containing the given address.
     ///
     /// If `stackframe_registers` is not `None`, then the function DIE's will have valid frame_base values calculated from the `DW_AT_frame_base` attribute.
@@ -1,9 +1,13 @@
 #![warn(missing_docs)]
 
-use crate::architecture::arm::ArmError;
+use crate::architecture::arm::{ArmError, DapError};
 use crate::architecture::riscv::communication_interface::RiscvError;
-use crate::config::RegistryError;
-use crate::DebugProbeError;
+use crate::config::{MemoryRegion, RegistryError};
+use crate::flashing::{FileDownloadError, FlashError};
+use crate::messages::{self, MessageId};
+use crate::probe::LOW_TARGET_VOLTAGE_WARNING_THRESHOLD;
+use crate::{DebugProbeError, ProbeCreationError};
+use std::ops::Range;
 
 /// The overarching error type which contains all possible errors as variants.
 #[derive(thiserror::Error, Debug)]
@@ -23,6 +27,25 @@ pub enum Error {
     /// The core with given ID does not exist.
     #[error("Core {0} does not exist")]
     CoreNotFound(usize),
+    /// No core of the target can access the memory at the address.
+    #[error("No core can access the memory at {0:#010x}")]
+    NoCoreForAddress(u64),
+    /// A memory write was refused, because it touches a protected region of the memory map. See
+    /// [`Session::protect_memory_writes`](crate::Session::protect_memory_writes).
+    #[error(
+        "Refusing to write to {:#010x}..{:#010x}, which is in the {} at {:#010x}..{:#010x}",
+        .range.start,
+        .range.end,
+        describe_region(.region),
+        .region.range().start,
+        .region.range().end
+    )]
+    MemoryWriteProtected {
+        /// The address range of the write.
+        range: Range<u64>,
+        /// The protected region which the write touches.
+        region: Box<MemoryRegion>,
+    },
     /// The given chip does not exist.
     #[error("Unable to load specification for chip")]
     ChipNotFound(#[from] RegistryError),
@@ -64,6 +87,19 @@ pub enum Error {
     },
 }
 
+/// The kind and the name of a memory region, e.g. "non-volatile memory 'FLASH'".
+fn describe_region(region: &MemoryRegion) -> String {
+    let (kind, name) = match region {
+        MemoryRegion::Nvm(region) => ("non-volatile memory", &region.name),
+        MemoryRegion::Generic(region) => ("read-only memory", &region.name),
+        MemoryRegion::Ram(region) => ("memory", &region.name),
+    };
+    match name {
+        Some(name) => format!("{kind} '{name}'"),
+        None => kind.to_string(),
+    }
+}
+
 impl From<ArmError> for Error {
     fn from(value: ArmError) -> Self {
         match value {
@@ -75,3 +111,334 @@ impl From<ArmError> for Error {
         }
     }
 }
+
+/// The area in which an error occurred, see [`ErrorCode::category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The debug probe could not be found, opened or used.
+    Probe,
+    /// The communication between the probe and the target failed.
+    Protocol,
+    /// The target is not powered.
+    TargetPower,
+    /// The operation is not permitted, by the operating system or by the [`Permissions`](crate::Permissions).
+    Permissions,
+    /// The chip is unknown, or its description is invalid.
+    Registry,
+    /// Flashing the image failed.
+    Flashing,
+    /// The error is not classified.
+    Other,
+}
+
+impl ErrorCategory {
+    /// The machine-readable name of the category.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::Probe => "probe",
+            ErrorCategory::Protocol => "protocol",
+            ErrorCategory::TargetPower => "target_power",
+            ErrorCategory::Permissions => "permissions",
+            ErrorCategory::Registry => "registry",
+            ErrorCategory::Flashing => "flashing",
+            ErrorCategory::Other => "other",
+        }
+    }
+}
+
+/// A stable, machine-readable classification of an error, with a hint how to recover from it.
+///
+/// The numeric value of a code never changes. The hundreds digit of the value is the [`ErrorCategory`] of the code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// The error is not classified.
+    Unclassified = 0,
+    /// No probe matches the selector.
+    ProbeNotFound = 100,
+    /// The probe is used by another program.
+    ProbeBusy = 101,
+    /// The USB communication with the probe failed.
+    ProbeUsb = 102,
+    /// The firmware of the probe is not supported.
+    ProbeFirmwareOutdated = 103,
+    /// The probe does not support the protocol, interface or speed.
+    ProbeUnsupported = 104,
+    /// The target did not acknowledge a request.
+    TargetNotResponding = 200,
+    /// The target answered a request with a FAULT response.
+    TargetFault = 201,
+    /// The target answered a request with a WAIT response.
+    TargetBusy = 202,
+    /// A transfer was corrupted.
+    TransmissionError = 203,
+    /// An operation timed out.
+    Timeout = 204,
+    /// The target did not respond, and the probe measured a low target voltage.
+    TargetNotPowered = 300,
+    /// The operating system denied the access to the USB device of the probe.
+    UsbAccessDenied = 400,
+    /// The operation was not allowed by the [`Permissions`](crate::Permissions).
+    MissingPermissions = 401,
+    /// The chip is not known.
+    ChipNotFound = 500,
+    /// A chip description could not be loaded.
+    InvalidChipDescription = 501,
+    /// The image contains data which is not in any flash memory of the target.
+    NoFlashForData = 600,
+    /// No flash algorithm is suitable for the flash memory.
+    FlashAlgorithm = 601,
+    /// Erasing or programming the flash failed.
+    Flashing = 602,
+    /// The image could not be read.
+    InvalidImage = 603,
+}
+
+impl ErrorCode {
+    /// Classify an error, by the most specific error in its chain of sources.
+    pub fn of(error: &(dyn std::error::Error + 'static)) -> Self {
+        let mut code = ErrorCode::Unclassified;
+        let mut current = Some(error);
+        while let Some(error) = current {
+            if let Some(specific) = classify(error) {
+                code = specific;
+            }
+            current = next_source(error);
+        }
+        code
+    }
+
+    /// Refine the code with the target voltage measured by the probe: a target which does not respond and has a
+    /// low voltage is most likely not powered.
+    pub fn with_target_voltage(self, voltage: Option<f32>) -> Self {
+        match (self, voltage) {
+            (ErrorCode::TargetNotResponding, Some(voltage))
+                if voltage < LOW_TARGET_VOLTAGE_WARNING_THRESHOLD =>
+            {
+                ErrorCode::TargetNotPowered
+            }
+            (code, _) => code,
+        }
+    }
+
+    /// The numeric value of the code.
+    pub fn value(self) -> u32 {
+        self as u32
+    }
+
+    /// The category of the code.
+    pub fn category(self) -> ErrorCategory {
+        match self.value() / 100 {
+            1 => ErrorCategory::Probe,
+            2 => ErrorCategory::Protocol,
+            3 => ErrorCategory::TargetPower,
+            4 => ErrorCategory::Permissions,
+            5 => ErrorCategory::Registry,
+            6 => ErrorCategory::Flashing,
+            _ => ErrorCategory::Other,
+        }
+    }
+
+    /// The machine-readable name of the code.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::Unclassified => "unclassified",
+            ErrorCode::ProbeNotFound => "probe_not_found",
+            ErrorCode::ProbeBusy => "probe_busy",
+            ErrorCode::ProbeUsb => "probe_usb",
+            ErrorCode::ProbeFirmwareOutdated => "probe_firmware_outdated",
+            ErrorCode::ProbeUnsupported => "probe_unsupported",
+            ErrorCode::TargetNotResponding => "target_not_responding",
+            ErrorCode::TargetFault => "target_fault",
+            ErrorCode::TargetBusy => "target_busy",
+            ErrorCode::TransmissionError => "transmission_error",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::TargetNotPowered => "target_not_powered",
+            ErrorCode::UsbAccessDenied => "usb_access_denied",
+            ErrorCode::MissingPermissions => "missing_permissions",
+            ErrorCode::ChipNotFound => "chip_not_found",
+            ErrorCode::InvalidChipDescription => "invalid_chip_description",
+            ErrorCode::NoFlashForData => "no_flash_for_data",
+            ErrorCode::FlashAlgorithm => "flash_algorithm",
+            ErrorCode::Flashing => "flashing",
+            ErrorCode::InvalidImage => "invalid_image",
+        }
+    }
+
+    /// What the user can do to recover from the error, in the active message [`Catalog`](crate::messages::Catalog).
+    pub fn hint(self) -> Option<String> {
+        self.hint_message().map(messages::text)
+    }
+
+    /// The message of [`Self::hint`].
+    pub fn hint_message(self) -> Option<MessageId> {
+        Some(match self {
+            ErrorCode::Unclassified => return None,
+            ErrorCode::ProbeNotFound => MessageId::HintProbeNotFound,
+            ErrorCode::ProbeBusy => MessageId::HintProbeBusy,
+            ErrorCode::ProbeUsb => MessageId::HintProbeUsb,
+            ErrorCode::ProbeFirmwareOutdated => MessageId::HintProbeFirmwareOutdated,
+            ErrorCode::ProbeUnsupported => MessageId::HintProbeUnsupported,
+            ErrorCode::TargetNotResponding => MessageId::HintTargetNotResponding,
+            ErrorCode::TargetFault => MessageId::HintTargetFault,
+            ErrorCode::TargetBusy => MessageId::HintTargetBusy,
+            ErrorCode::TransmissionError => MessageId::HintTransmissionError,
+            ErrorCode::Timeout => MessageId::HintTimeout,
+            ErrorCode::TargetNotPowered => MessageId::HintTargetNotPowered,
+            ErrorCode::UsbAccessDenied => MessageId::HintUsbAccessDenied,
+            ErrorCode::MissingPermissions => MessageId::HintMissingPermissions,
+            ErrorCode::ChipNotFound => MessageId::HintChipNotFound,
+            ErrorCode::InvalidChipDescription => MessageId::HintInvalidChipDescription,
+            ErrorCode::NoFlashForData => MessageId::HintNoFlashForData,
+            ErrorCode::FlashAlgorithm => MessageId::HintFlashAlgorithm,
+            ErrorCode::Flashing => MessageId::HintFlashing,
+            ErrorCode::InvalidImage => MessageId::HintInvalidImage,
+        })
+    }
+}
+
+/// The error which follows `error` in its chain. Transparent `anyhow::Error`s forward the sources of the error they
+/// contain, so the contained error itself is returned for them.
+fn next_source<'a>(
+    error: &'a (dyn std::error::Error + 'static),
+) -> Option<&'a (dyn std::error::Error + 'static)> {
+    if let Some(Error::Other(inner)) = error.downcast_ref::<Error>() {
+        return Some(inner.as_ref());
+    }
+    if let Some(DebugProbeError::Other(inner)) = error.downcast_ref::<DebugProbeError>() {
+        return Some(inner.as_ref());
+    }
+    if let Some(ArmError::Other(inner)) = error.downcast_ref::<ArmError>() {
+        return Some(inner.as_ref());
+    }
+    error.source()
+}
+
+/// Classify a single error, without its sources. Errors which only wrap their source are not classified.
+fn classify(error: &(dyn std::error::Error + 'static)) -> Option<ErrorCode> {
+    if let Some(error) = error.downcast_ref::<Error>() {
+        return match error {
+            Error::UnableToOpenProbe(_) => Some(ErrorCode::ProbeNotFound),
+            Error::MissingPermissions(_) => Some(ErrorCode::MissingPermissions),
+            Error::Timeout => Some(ErrorCode::Timeout),
+            _ => None,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<DebugProbeError>() {
+        return match error {
+            DebugProbeError::Usb(_) => Some(ErrorCode::ProbeUsb),
+            DebugProbeError::ProbeFirmwareOutdated => Some(ErrorCode::ProbeFirmwareOutdated),
+            DebugProbeError::UnsupportedProtocol(_)
+            | DebugProbeError::InterfaceNotAvailable(_)
+            | DebugProbeError::UnsupportedSpeed(_) => Some(ErrorCode::ProbeUnsupported),
+            DebugProbeError::TargetNotFound => Some(ErrorCode::TargetNotResponding),
+            DebugProbeError::Timeout => Some(ErrorCode::Timeout),
+            _ => None,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<ProbeCreationError>() {
+        return match error {
+            ProbeCreationError::NotFound => Some(ErrorCode::ProbeNotFound),
+            ProbeCreationError::CouldNotOpen => Some(ErrorCode::UsbAccessDenied),
+            _ => None,
+        };
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(error) = error.downcast_ref::<rusb::Error>() {
+        return match error {
+            rusb::Error::Access => Some(ErrorCode::UsbAccessDenied),
+            rusb::Error::Busy => Some(ErrorCode::ProbeBusy),
+            rusb::Error::NoDevice | rusb::Error::NotFound => Some(ErrorCode::ProbeNotFound),
+            _ => Some(ErrorCode::ProbeUsb),
+        };
+    }
+    if let Some(error) = error.downcast_ref::<ArmError>() {
+        return match error {
+            ArmError::Timeout => Some(ErrorCode::Timeout),
+            ArmError::MissingPermissions(_) => Some(ErrorCode::MissingPermissions),
+            _ => None,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<DapError>() {
+        return Some(match error {
+            DapError::NoAcknowledge => ErrorCode::TargetNotResponding,
+            DapError::FaultResponse => ErrorCode::TargetFault,
+            DapError::WaitResponse => ErrorCode::TargetBusy,
+            DapError::SwdProtocol | DapError::IncorrectParity => ErrorCode::TransmissionError,
+        });
+    }
+    if let Some(error) = error.downcast_ref::<RegistryError>() {
+        return Some(match error {
+            RegistryError::ChipNotFound(..)
+            | RegistryError::ChipNotUnique(_)
+            | RegistryError::ChipAutodetectFailed
+            | RegistryError::BoardNotFound(_) => ErrorCode::ChipNotFound,
+            _ => ErrorCode::InvalidChipDescription,
+        });
+    }
+    if let Some(error) = error.downcast_ref::<FlashError>() {
+        return match error {
+            FlashError::Core(_) => None,
+            FlashError::NoSuitableNvm { .. } => Some(ErrorCode::NoFlashForData),
+            FlashError::NoFlashLoaderAlgorithmAttached { .. }
+            | FlashError::MultipleDefaultFlashLoaderAlgorithms { .. }
+            | FlashError::MultipleFlashLoaderAlgorithmsNoDefault { .. }
+            | FlashError::FlashAlgorithmNotFound { .. } => Some(ErrorCode::FlashAlgorithm),
+            _ => Some(ErrorCode::Flashing),
+        };
+    }
+    if let Some(error) = error.downcast_ref::<FileDownloadError>() {
+        return match error {
+            FileDownloadError::Flash(_) => None,
+            _ => Some(ErrorCode::InvalidImage),
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ErrorCategory, ErrorCode};
+    use crate::architecture::arm::{ArmError, DapError};
+    use crate::{DebugProbeError, Error, ProbeCreationError};
+
+    #[test]
+    fn the_most_specific_error_is_classified() {
+        let error = Error::Probe(DebugProbeError::ProbeCouldNotBeCreated(
+            ProbeCreationError::Rusb(rusb::Error::Access),
+        ));
+        let code = ErrorCode::of(&error);
+        assert_eq!(code, ErrorCode::UsbAccessDenied);
+        assert_eq!(code.category(), ErrorCategory::Permissions);
+        assert!(code.hint().unwrap().contains("udev"));
+
+        let error = Error::from(ArmError::Dap(DapError::NoAcknowledge));
+        assert_eq!(ErrorCode::of(&error), ErrorCode::TargetNotResponding);
+    }
+
+    #[test]
+    fn errors_in_anyhow_chains_are_classified() {
+        let error =
+            Error::Other(anyhow::Error::new(DapError::FaultResponse).context("reading memory"));
+        assert_eq!(ErrorCode::of(&error), ErrorCode::TargetFault);
+
+        let error = Error::Other(anyhow::anyhow!("something else"));
+        assert_eq!(ErrorCode::of(&error), ErrorCode::Unclassified);
+        assert_eq!(ErrorCode::Unclassified.category(), ErrorCategory::Other);
+    }
+
+    #[test]
+    fn a_low_target_voltage_refines_a_missing_response() {
+        let code = ErrorCode::TargetNotResponding;
+        assert_eq!(
+            code.with_target_voltage(Some(0.1)),
+            ErrorCode::TargetNotPowered
+        );
+        assert_eq!(code.with_target_voltage(Some(3.3)), code);
+        assert_eq!(code.with_target_voltage(None), code);
+        assert_eq!(
+            ErrorCode::TargetNotPowered.category(),
+            ErrorCategory::TargetPower
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! A typed stream of the events of sessions, for applications which embed probe-rs.
+//!
+//! [`subscribe`] returns a receiver, which receives every [`SessionEvent`] which happens after it was created, e.g.
+//! to drive the state machine of a GUI or of a fleet manager. The events are independent of logging, and are only
+//! created while there is a subscriber. Dropping the receiver ends the subscription.
+//!
+//! ```no_run
+//! use probe_rs::events::{self, SessionEvent};
+//!
+//! let events = events::subscribe();
+//! std::thread::spawn(move || {
+//!     for event in events {
+//!         if let SessionEvent::CoreHalted { core, reason } = event {
+//!             println!("Core {core} halted: {reason:?}");
+//!         }
+//!     }
+//! });
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use crate::power::PowerMeasurement;
+use crate::{CoreStatus, ErrorCode, HaltReason};
+
+/// Whether there is at least one subscriber, so events are only created when they are received.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static SUBSCRIBERS: Lazy<Mutex<Vec<Sender<SessionEvent>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// The last status of each core, to report changes of the status.
+static CORE_STATUS: Lazy<Mutex<HashMap<usize, CoreStatus>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A phase of flashing, see [`SessionEvent::FlashPhase`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashPhase {
+    /// Reading the bytes of the flash which are restored.
+    Filling,
+    /// Erasing the sectors.
+    Erasing,
+    /// Programming the pages.
+    Programming,
+}
+
+/// The status of a [`FlashPhase`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseStatus {
+    /// The phase started.
+    Started,
+    /// The phase finished successfully.
+    Finished,
+    /// The phase failed.
+    Failed,
+}
+
+/// An event of a session, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SessionEvent {
+    /// A session attached to a target.
+    SessionStarted {
+        /// The name of the target.
+        target: String,
+    },
+    /// A session detached from its target.
+    SessionStopped {
+        /// The name of the target.
+        target: String,
+    },
+    /// A phase of flashing started, finished or failed.
+    FlashPhase {
+        /// The phase.
+        phase: FlashPhase,
+        /// What happened to the phase.
+        status: PhaseStatus,
+    },
+    /// A core was seen halted, after it was not halted before.
+    CoreHalted {
+        /// The index of the core.
+        core: usize,
+        /// Why the core halted.
+        reason: HaltReason,
+    },
+    /// A halted core was resumed.
+    CoreResumed {
+        /// The index of the core.
+        core: usize,
+    },
+    /// Bytes were read from an RTT up channel.
+    RttData {
+        /// The index of the core which read the channel.
+        core: usize,
+        /// The number of the channel.
+        channel: usize,
+        /// The bytes which were read.
+        data: Vec<u8>,
+    },
+    /// A power profiler measured the current of the target, see [`PowerCapture`](crate::power::PowerCapture).
+    PowerMeasured {
+        /// The measurement.
+        measurement: PowerMeasurement,
+    },
+    /// An operation failed.
+    Error {
+        /// The classification of the error.
+        code: ErrorCode,
+        /// The message of the error.
+        message: String,
+    },
+}
+
+/// Receive all events which happen from now on, until the receiver is dropped.
+pub fn subscribe() -> Receiver<SessionEvent> {
+    let (sender, receiver) = mpsc::channel();
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    ENABLED.store(true, Ordering::Relaxed);
+    receiver
+}
+
+/// Whether there is a subscriber to the events.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Send the event which `event` creates to all subscribers, if there are any.
+pub(crate) fn emit(event: impl FnOnce() -> SessionEvent) {
+    if !is_enabled() {
+        return;
+    }
+
+    let event = event();
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    if subscribers.is_empty() {
+        ENABLED.store(false, Ordering::Relaxed);
+        CORE_STATUS.lock().unwrap().clear();
+    }
+}
+
+/// Report an error as a [`SessionEvent::Error`].
+pub(crate) fn error(error: &(dyn std::error::Error + 'static)) {
+    emit(|| SessionEvent::Error {
+        code: ErrorCode::of(error),
+        message: error.to_string(),
+    });
+}
+
+/// Report the status of a core, which is sent as an event if the core halted or resumed since its last status.
+pub(crate) fn core_status(core: usize, status: CoreStatus) {
+    if !is_enabled() {
+        return;
+    }
+
+    let previous = CORE_STATUS.lock().unwrap().insert(core, status);
+    match status {
+        CoreStatus::Halted(reason) if !matches!(previous, Some(CoreStatus::Halted(_))) => {
+            emit(|| SessionEvent::CoreHalted { core, reason })
+        }
+        CoreStatus::Running if matches!(previous, Some(CoreStatus::Halted(_))) => {
+            emit(|| SessionEvent::CoreResumed { core })
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{core_status, emit, subscribe, SessionEvent};
+    use crate::{CoreStatus, HaltReason};
+
+    #[test]
+    fn core_status_changes_are_sent_once() {
+        let events = subscribe();
+        // Other tests may emit events concurrently, so only the events of this core are checked.
+        let core = 1000;
+
+        core_status(core, CoreStatus::Running);
+        core_status(core, CoreStatus::Halted(HaltReason::Step));
+        core_status(core, CoreStatus::Halted(HaltReason::Step));
+        core_status(core, CoreStatus::Running);
+        let end = SessionEvent::SessionStopped {
+            target: "end".to_owned(),
+        };
+        emit(|| end.clone());
+
+        let received = events
+            .iter()
+            .take_while(|event| *event != end)
+            .filter(|event| match event {
+                SessionEvent::CoreHalted { core, .. } | SessionEvent::CoreResumed { core } => {
+                    *core == 1000
+                }
+                _ => false,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            received,
+            [
+                SessionEvent::CoreHalted {
+                    core,
+                    reason: HaltReason::Step
+                },
+                SessionEvent::CoreResumed { core },
+            ]
+        );
+    }
+}
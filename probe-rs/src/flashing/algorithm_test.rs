@@ -0,0 +1,331 @@
+//! Test a flash algorithm of a target step by step on the hardware.
+//!
+//! [`test_flash_algorithm`] runs each function of a flash algorithm on one sector, and checks its effect by reading
+//! the flash back, so a new target description is known to work, instead of only seeming to work because flashing
+//! succeeded.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use probe_rs_target::{MemoryRegion, RawFlashAlgorithm, SectorInfo};
+
+use super::{FlashError, FlashLoader, Flasher};
+use crate::Session;
+
+/// A step of [`test_flash_algorithm`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgorithmTestStep {
+    /// Load the flash algorithm into the RAM of the target, and check that it was loaded.
+    Load,
+    /// Call the `Init` and `UnInit` functions of the flash algorithm.
+    Init,
+    /// Read the sector which is tested, to restore it at the end.
+    Backup,
+    /// Erase the sector.
+    EraseSector,
+    /// Check that the whole sector is erased.
+    BlankCheck,
+    /// Program a test pattern into all pages of the sector.
+    Program,
+    /// Check that the sector contains the test pattern.
+    Verify,
+    /// Erase the sector again, program its original contents, and check them.
+    Restore,
+}
+
+impl fmt::Display for AlgorithmTestStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AlgorithmTestStep::Load => "load",
+            AlgorithmTestStep::Init => "init",
+            AlgorithmTestStep::Backup => "backup",
+            AlgorithmTestStep::EraseSector => "erase sector",
+            AlgorithmTestStep::BlankCheck => "blank check",
+            AlgorithmTestStep::Program => "program",
+            AlgorithmTestStep::Verify => "verify",
+            AlgorithmTestStep::Restore => "restore",
+        })
+    }
+}
+
+/// The outcome of a step of [`test_flash_algorithm`].
+#[derive(Debug, Clone)]
+pub struct AlgorithmTestStepResult {
+    /// The step.
+    pub step: AlgorithmTestStep,
+    /// How long the step took.
+    pub duration: Duration,
+    /// What the step did, or why it failed.
+    pub message: String,
+    /// Whether the step succeeded.
+    pub passed: bool,
+}
+
+/// Options for [`test_flash_algorithm`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AlgorithmTestOptions {
+    /// The name of the flash algorithm. By default, the algorithm which flashes [`Self::address`] is tested.
+    pub algorithm: Option<String>,
+    /// An address in the sector which is tested. By default, the last sector of the flash algorithm is tested.
+    pub address: Option<u64>,
+    /// Program the original contents of the sector again at the end. This is also done if a step fails.
+    pub restore: bool,
+}
+
+impl Default for AlgorithmTestOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: None,
+            address: None,
+            restore: true,
+        }
+    }
+}
+
+/// Test a flash algorithm of the target on one sector: load it, call its `Init` function, erase the sector, check
+/// that it is erased, program a test pattern into all its pages, check the pattern, and restore the sector.
+///
+/// `on_step` is called after each step. The error of the first failed step is returned, after the sector was restored.
+pub fn test_flash_algorithm(
+    session: &mut Session,
+    options: &AlgorithmTestOptions,
+    mut on_step: impl FnMut(&AlgorithmTestStepResult),
+) -> Result<(), FlashError> {
+    let algorithm = select_algorithm(session, options)?;
+    let core_index = match algorithm.cores.first() {
+        Some(core) => session.target().core_index_by_name(core).unwrap_or(0),
+        None => 0,
+    };
+    let description_source = session.target().source().clone();
+
+    let start = Instant::now();
+    let loaded = Flasher::new(session, core_index, &algorithm, &[], None).map(|flasher| {
+        let message = format!(
+            "loaded '{}' at {:#010x}, with {} page buffer(s)",
+            algorithm.name,
+            flasher.flash_algorithm().load_address,
+            flasher.flash_algorithm().page_buffers.len()
+        );
+        (flasher, message)
+    });
+    let mut flasher = report(AlgorithmTestStep::Load, start, &mut on_step, loaded)?;
+
+    let address = options.address.unwrap_or_else(|| {
+        let end = algorithm.flash_properties.address_range.end;
+        end.saturating_sub(1)
+    });
+    let sector =
+        flasher
+            .flash_algorithm()
+            .sector_info(address)
+            .ok_or(FlashError::NoSuitableNvm {
+                start: address,
+                end: address + 1,
+                description_source,
+            })?;
+
+    run_step(AlgorithmTestStep::Init, &mut on_step, || {
+        flasher.run_erase(|_| Ok(()))?;
+        Ok(((), "Init and UnInit returned 0".to_owned()))
+    })?;
+
+    let backup = run_step(AlgorithmTestStep::Backup, &mut on_step, || {
+        let mut backup = vec![0; sector.size as usize];
+        flasher.read(sector.base_address, &mut backup)?;
+        let message = format!(
+            "read the sector at {:#010x} ({} bytes)",
+            sector.base_address, sector.size
+        );
+        Ok((backup, message))
+    })?;
+
+    let result = test_sector(&mut flasher, &sector, &mut on_step);
+
+    if options.restore {
+        let restored = run_step(AlgorithmTestStep::Restore, &mut on_step, || {
+            restore_sector(&mut flasher, &sector, &backup)?;
+            Ok((
+                (),
+                "programmed and verified the original contents".to_owned(),
+            ))
+        });
+        // The error of the test is more interesting than the one of the restore.
+        result.and(restored)
+    } else {
+        result
+    }
+}
+
+/// Run a step, and report its outcome.
+fn run_step<T>(
+    step: AlgorithmTestStep,
+    on_step: &mut impl FnMut(&AlgorithmTestStepResult),
+    f: impl FnOnce() -> Result<(T, String), FlashError>,
+) -> Result<T, FlashError> {
+    let start = Instant::now();
+    let result = f();
+    report(step, start, on_step, result)
+}
+
+/// Report the outcome of a step which was started at `start`.
+fn report<T>(
+    step: AlgorithmTestStep,
+    start: Instant,
+    on_step: &mut impl FnMut(&AlgorithmTestStepResult),
+    result: Result<(T, String), FlashError>,
+) -> Result<T, FlashError> {
+    let (message, passed) = match &result {
+        Ok((_, message)) => (message.clone(), true),
+        Err(error) => (error.to_string(), false),
+    };
+    on_step(&AlgorithmTestStepResult {
+        step,
+        duration: start.elapsed(),
+        message,
+        passed,
+    });
+    result.map(|(value, _)| value)
+}
+
+/// Select the flash algorithm which is named in the options, or the one which flashes the address of the options.
+fn select_algorithm(
+    session: &Session,
+    options: &AlgorithmTestOptions,
+) -> Result<RawFlashAlgorithm, FlashError> {
+    let target = session.target();
+
+    if let Some(name) = &options.algorithm {
+        return target
+            .flash_algorithm_by_name(name)
+            .cloned()
+            .ok_or_else(|| FlashError::FlashAlgorithmNotFound { name: name.clone() });
+    }
+
+    let region = target
+        .memory_map
+        .iter()
+        .filter_map(|region| match region {
+            MemoryRegion::Nvm(region) => Some(region),
+            _ => None,
+        })
+        .find(|region| match options.address {
+            Some(address) => region.range.contains(&address),
+            None => true,
+        })
+        .ok_or_else(|| {
+            let address = options.address.unwrap_or_default();
+            FlashError::NoSuitableNvm {
+                start: address,
+                end: address + 1,
+                description_source: target.source().clone(),
+            }
+        })?;
+
+    FlashLoader::get_flash_algorithm_for_region(region, target).cloned()
+}
+
+/// The test pattern for the byte at `address`. It differs between neighbouring bytes and pages, so swapped or
+/// repeated data is noticed.
+fn pattern(address: u64) -> u8 {
+    (address ^ (address >> 8) ^ (address >> 16)) as u8
+}
+
+/// Erase the sector, check that it is erased, program the test pattern into its pages, and check the pattern.
+fn test_sector(
+    flasher: &mut Flasher<'_>,
+    sector: &SectorInfo,
+    on_step: &mut impl FnMut(&AlgorithmTestStepResult),
+) -> Result<(), FlashError> {
+    let erased_byte_value = flasher.flash_algorithm().flash_properties.erased_byte_value;
+    let page_size = flasher.flash_algorithm().flash_properties.page_size as u64;
+    let sector_range = sector.base_address..sector.base_address + sector.size;
+
+    run_step(AlgorithmTestStep::EraseSector, on_step, || {
+        flasher.run_erase(|active| active.erase_sector(sector.base_address))?;
+        Ok((
+            (),
+            format!("erased the sector at {:#010x}", sector.base_address),
+        ))
+    })?;
+
+    run_step(AlgorithmTestStep::BlankCheck, on_step, || {
+        let mut data = vec![0; sector.size as usize];
+        flasher.read(sector.base_address, &mut data)?;
+        if let Some(offset) = data.iter().position(|&byte| byte != erased_byte_value) {
+            return Err(FlashError::NotErased {
+                address: sector.base_address + offset as u64,
+                value: data[offset],
+                erased_byte_value,
+            });
+        }
+        Ok(((), format!("all bytes are {erased_byte_value:#04x}")))
+    })?;
+
+    run_step(AlgorithmTestStep::Program, on_step, || {
+        let count = (sector.size + page_size - 1) / page_size;
+        flasher.run_program(|active| {
+            for page in sector_range.clone().step_by(page_size as usize) {
+                let data = (page..page + page_size).map(pattern).collect::<Vec<_>>();
+                active.program_page(page, &data)?;
+            }
+            Ok(())
+        })?;
+        Ok((
+            (),
+            format!("programmed {count} page(s) of {page_size} bytes"),
+        ))
+    })?;
+
+    run_step(AlgorithmTestStep::Verify, on_step, || {
+        let mut data = vec![0; sector.size as usize];
+        flasher.read(sector.base_address, &mut data)?;
+        let mismatch = sector_range
+            .clone()
+            .zip(&data)
+            .find(|&(address, &byte)| byte != pattern(address));
+        if let Some((address, &value)) = mismatch {
+            return Err(FlashError::UnexpectedData {
+                address,
+                value,
+                expected: pattern(address),
+            });
+        }
+        Ok(((), "the sector contains the test pattern".to_owned()))
+    })
+}
+
+/// Erase the sector, and program and check its original contents. Erased pages are not programmed.
+fn restore_sector(
+    flasher: &mut Flasher<'_>,
+    sector: &SectorInfo,
+    backup: &[u8],
+) -> Result<(), FlashError> {
+    let erased_byte_value = flasher.flash_algorithm().flash_properties.erased_byte_value;
+    let page_size = flasher.flash_algorithm().flash_properties.page_size as usize;
+
+    flasher.run_erase(|active| active.erase_sector(sector.base_address))?;
+    flasher.run_program(|active| {
+        for (index, page) in backup.chunks(page_size).enumerate() {
+            if page.iter().any(|&byte| byte != erased_byte_value) {
+                active.program_page(sector.base_address + (index * page_size) as u64, page)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    let mut data = vec![0; backup.len()];
+    flasher.read(sector.base_address, &mut data)?;
+    match data
+        .iter()
+        .zip(backup)
+        .position(|(read, original)| read != original)
+    {
+        Some(offset) => Err(FlashError::UnexpectedData {
+            address: sector.base_address + offset as u64,
+            value: data[offset],
+            expected: backup[offset],
+        }),
+        None => Ok(()),
+    }
+}
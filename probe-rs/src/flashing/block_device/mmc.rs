@@ -0,0 +1,266 @@
+//! eMMC and SD cards, in the transfer state.
+
+use super::BlockDevice;
+use crate::flashing::FlashError;
+use crate::Session;
+
+/// The size of the blocks of a card, with block addressing.
+const BLOCK_SIZE: u32 = 512;
+
+const SWITCH: u8 = 6;
+const SEND_EXT_CSD: u8 = 8;
+const READ_SINGLE_BLOCK: u8 = 17;
+const READ_MULTIPLE_BLOCK: u8 = 18;
+const WRITE_BLOCK: u8 = 24;
+const WRITE_MULTIPLE_BLOCK: u8 = 25;
+
+/// The bytes of the Extended CSD register of an eMMC.
+const EXT_CSD_PARTITION_CONFIG: usize = 179;
+const EXT_CSD_SEC_COUNT: usize = 212;
+const EXT_CSD_BOOT_SIZE_MULT: usize = 226;
+
+/// The error bits of a card status.
+const STATUS_ERRORS: u32 = 0xFFF8_0080;
+
+/// A SD/MMC host controller of the target, which sends commands to the card connected to it.
+///
+/// The card has to be initialized and selected already, e.g. by the boot ROM or by a debug sequence, so it is in
+/// the transfer state with 512 byte blocks.
+pub trait MmcController: Send {
+    /// The name of the controller, for the logs.
+    fn name(&self) -> &str;
+
+    /// Send a command without data, returning the card status of its R1 response. If `busy` is set, the response is
+    /// R1b, and the controller waits until the card is not busy anymore.
+    fn command(
+        &mut self,
+        session: &mut Session,
+        index: u8,
+        argument: u32,
+        busy: bool,
+    ) -> Result<u32, FlashError>;
+
+    /// Send a command which reads `data.len() / 512` blocks, returning the card status. Multiple blocks are stopped
+    /// with CMD12.
+    fn read(
+        &mut self,
+        session: &mut Session,
+        index: u8,
+        argument: u32,
+        data: &mut [u8],
+    ) -> Result<u32, FlashError>;
+
+    /// Send a command which writes `data.len() / 512` blocks, returning the card status. Multiple blocks are
+    /// stopped with CMD12.
+    fn write(
+        &mut self,
+        session: &mut Session,
+        index: u8,
+        argument: u32,
+        data: &[u8],
+    ) -> Result<u32, FlashError>;
+}
+
+/// The partitions of an eMMC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcPartition {
+    /// The user data area.
+    User,
+    /// The first boot partition.
+    Boot1,
+    /// The second boot partition.
+    Boot2,
+}
+
+/// A partition of an eMMC, or an SD card, with block addressing.
+pub struct Mmc<C> {
+    name: String,
+    controller: C,
+    block_count: u64,
+}
+
+impl<C: MmcController> Mmc<C> {
+    /// Select `partition` of an eMMC, whose size is read from its Extended CSD register.
+    ///
+    /// The boot configuration of the card is kept, so selecting a boot partition does not make the card boot from
+    /// it.
+    pub fn emmc(
+        session: &mut Session,
+        mut controller: C,
+        partition: MmcPartition,
+    ) -> Result<Self, FlashError> {
+        let mut ext_csd = [0; BLOCK_SIZE as usize];
+        let status = controller.read(session, SEND_EXT_CSD, 0, &mut ext_csd)?;
+        check_status(SEND_EXT_CSD, status)?;
+
+        let size = match partition {
+            MmcPartition::User => {
+                let sectors =
+                    u32::from_le_bytes(ext_csd[EXT_CSD_SEC_COUNT..][..4].try_into().unwrap());
+                sectors as u64 * BLOCK_SIZE as u64
+            }
+            MmcPartition::Boot1 | MmcPartition::Boot2 => {
+                ext_csd[EXT_CSD_BOOT_SIZE_MULT] as u64 * 128 * 1024
+            }
+        };
+        let access = match partition {
+            MmcPartition::User => 0,
+            MmcPartition::Boot1 => 1,
+            MmcPartition::Boot2 => 2,
+        };
+
+        // Write the PARTITION_ACCESS bits of the PARTITION_CONFIG byte.
+        let config = (ext_csd[EXT_CSD_PARTITION_CONFIG] & !0b111) | access;
+        let argument =
+            (0b11 << 24) | ((EXT_CSD_PARTITION_CONFIG as u32) << 16) | ((config as u32) << 8);
+        let status = controller.command(session, SWITCH, argument, true)?;
+        check_status(SWITCH, status)?;
+
+        Ok(Self {
+            name: format!("eMMC {partition:?} partition ({})", controller.name()),
+            controller,
+            block_count: size / BLOCK_SIZE as u64,
+        })
+    }
+
+    /// An SD card with `block_count` blocks of 512 bytes, e.g. from its CSD register.
+    pub fn sd(controller: C, block_count: u64) -> Self {
+        Self {
+            name: format!("SD card ({})", controller.name()),
+            controller,
+            block_count,
+        }
+    }
+}
+
+impl<C: MmcController> BlockDevice for Mmc<C> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> u32 {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(
+        &mut self,
+        session: &mut Session,
+        block: u64,
+        data: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let index = if data.len() > BLOCK_SIZE as usize {
+            READ_MULTIPLE_BLOCK
+        } else {
+            READ_SINGLE_BLOCK
+        };
+        let status = self.controller.read(session, index, block as u32, data)?;
+        check_status(index, status)
+    }
+
+    fn write_blocks(
+        &mut self,
+        session: &mut Session,
+        block: u64,
+        data: &[u8],
+    ) -> Result<(), FlashError> {
+        let index = if data.len() > BLOCK_SIZE as usize {
+            WRITE_MULTIPLE_BLOCK
+        } else {
+            WRITE_BLOCK
+        };
+        let status = self.controller.write(session, index, block as u32, data)?;
+        check_status(index, status)
+    }
+}
+
+fn check_status(index: u8, status: u32) -> Result<(), FlashError> {
+    if status & STATUS_ERRORS != 0 {
+        return Err(FlashError::MmcCommandFailed { index, status });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FakeProbe, Permissions, Probe};
+
+    /// An eMMC with 4 MiB boot partitions, which records its commands.
+    #[derive(Default)]
+    struct SimulatedEmmc {
+        commands: Vec<(u8, u32)>,
+    }
+
+    impl MmcController for SimulatedEmmc {
+        fn name(&self) -> &str {
+            "simulated"
+        }
+
+        fn command(
+            &mut self,
+            _session: &mut Session,
+            index: u8,
+            argument: u32,
+            _busy: bool,
+        ) -> Result<u32, FlashError> {
+            self.commands.push((index, argument));
+            Ok(0x900)
+        }
+
+        fn read(
+            &mut self,
+            _session: &mut Session,
+            index: u8,
+            argument: u32,
+            data: &mut [u8],
+        ) -> Result<u32, FlashError> {
+            self.commands.push((index, argument));
+            data.fill(0);
+            if index == SEND_EXT_CSD {
+                data[EXT_CSD_PARTITION_CONFIG] = 0x48;
+                data[EXT_CSD_BOOT_SIZE_MULT] = 32;
+            }
+            Ok(0x900)
+        }
+
+        fn write(
+            &mut self,
+            _session: &mut Session,
+            index: u8,
+            argument: u32,
+            _data: &[u8],
+        ) -> Result<u32, FlashError> {
+            self.commands.push((index, argument));
+            // An address out of range.
+            Ok(0x8000_0900)
+        }
+    }
+
+    #[test]
+    fn boot_partition_is_selected() {
+        let mut session = Probe::from_specific_probe(Box::new(FakeProbe::new()))
+            .attach("stm32wb55ccux", Permissions::default())
+            .unwrap();
+        let mut emmc =
+            Mmc::emmc(&mut session, SimulatedEmmc::default(), MmcPartition::Boot2).unwrap();
+
+        assert_eq!(emmc.block_count(), 4 * 1024 * 1024 / 512);
+        // The boot configuration 0x48 is kept.
+        assert_eq!(
+            emmc.controller.commands,
+            [(SEND_EXT_CSD, 0), (SWITCH, 0x03B3_4A00)]
+        );
+
+        assert!(matches!(
+            emmc.write_blocks(&mut session, 8, &[0; 1024]),
+            Err(FlashError::MmcCommandFailed {
+                index: WRITE_MULTIPLE_BLOCK,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,411 @@
+//! Programming of block devices, like the eMMC and raw NAND flash of SoCs which are brought up bare-metal.
+//!
+//! A [`BlockDevice`] is written in whole blocks, through a controller of the target. [`Nand`] is a raw NAND flash
+//! with its bad blocks and ECC layout, and [`Mmc`] is the user area or a boot partition of an eMMC, or an SD card.
+//! The [`BlockDeviceProgrammer`] maps an address range onto the good blocks of a device, so an image is programmed
+//! with [`DownloadOptions::programmers`](super::DownloadOptions::programmers), e.g. as a binary at the base address
+//! of the programmer.
+
+use std::ops::Range;
+
+use super::{FlashError, FlashProgrammer};
+use crate::Session;
+
+mod mmc;
+mod nand;
+mod sdhci;
+
+pub use mmc::{Mmc, MmcController, MmcPartition};
+pub use nand::{Nand, NandController, NandEcc, NandLayout};
+pub use sdhci::Sdhci;
+
+/// The largest number of bytes which are read or written with one call to the device, if its blocks are smaller.
+const MAX_TRANSFER_SIZE: u64 = 64 * 1024;
+
+/// A device which is read and written in blocks.
+pub trait BlockDevice: Send {
+    /// The name of the device, for the logs.
+    fn name(&self) -> &str;
+
+    /// The size of a block in bytes. Blocks are always written as a whole.
+    fn block_size(&self) -> u32;
+
+    /// The number of blocks, including the bad ones.
+    fn block_count(&self) -> u64;
+
+    /// Read the consecutive blocks from `block` into `data`, whose length is a multiple of the block size.
+    fn read_blocks(
+        &mut self,
+        session: &mut Session,
+        block: u64,
+        data: &mut [u8],
+    ) -> Result<(), FlashError>;
+
+    /// Write `data` to the consecutive blocks from `block`, erasing them first if the device needs it.
+    ///
+    /// Returns [`FlashError::BadBlock`] if a block failed, which is then marked as bad, so the data is written to
+    /// the next good block.
+    fn write_blocks(
+        &mut self,
+        session: &mut Session,
+        block: u64,
+        data: &[u8],
+    ) -> Result<(), FlashError>;
+
+    /// Whether the device has bad blocks which have to be skipped. Devices which manage their bad blocks themselves,
+    /// like an eMMC, have none.
+    fn has_bad_blocks(&self) -> bool {
+        false
+    }
+
+    /// Whether the block is marked as bad.
+    fn is_bad_block(&mut self, _session: &mut Session, _block: u64) -> Result<bool, FlashError> {
+        Ok(false)
+    }
+
+    /// Mark the block as bad.
+    fn mark_bad_block(&mut self, _session: &mut Session, _block: u64) -> Result<(), FlashError> {
+        Ok(())
+    }
+
+    /// Erase the good blocks of the device.
+    fn erase_all(&mut self, _session: &mut Session) -> Result<(), FlashError> {
+        Ok(())
+    }
+}
+
+/// A [`FlashProgrammer`] for a [`BlockDevice`], whose good blocks are mapped at `base_address`.
+///
+/// Bad blocks are skipped, so the data of a bad block is in the next good block, like the `nand write` of U-Boot.
+/// The parts of the first and the last block which are not programmed keep their contents.
+pub struct BlockDeviceProgrammer<D> {
+    name: String,
+    device: D,
+    base_address: u64,
+    /// The bad blocks in ascending order, once they were read from the device.
+    bad_blocks: Option<Vec<u64>>,
+}
+
+impl<D: BlockDevice> BlockDeviceProgrammer<D> {
+    /// Program `device`, with its first good block at `base_address`.
+    pub fn new(device: D, base_address: u64) -> Self {
+        Self {
+            name: device.name().to_owned(),
+            device,
+            base_address,
+            bad_blocks: None,
+        }
+    }
+
+    /// The device which is programmed.
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    fn bad_blocks(&mut self, session: &mut Session) -> Result<&mut Vec<u64>, FlashError> {
+        if self.bad_blocks.is_none() {
+            let mut bad_blocks = vec![];
+            if self.device.has_bad_blocks() {
+                for block in 0..self.device.block_count() {
+                    if self.device.is_bad_block(session, block)? {
+                        bad_blocks.push(block);
+                    }
+                }
+                tracing::info!("{} has {} bad blocks", self.name, bad_blocks.len());
+            }
+            self.bad_blocks = Some(bad_blocks);
+        }
+        Ok(self.bad_blocks.as_mut().unwrap())
+    }
+
+    /// The physical block of the logical `block`, and the number of good blocks which follow it, up to `count`.
+    fn run(
+        &mut self,
+        session: &mut Session,
+        block: u64,
+        count: u64,
+    ) -> Result<(u64, u64), FlashError> {
+        let block_count = self.device.block_count();
+        let bad_blocks = self.bad_blocks(session)?;
+
+        let mut physical = block;
+        for bad in bad_blocks.iter() {
+            if *bad <= physical {
+                physical += 1;
+            } else {
+                break;
+            }
+        }
+
+        let next_bad = bad_blocks
+            .iter()
+            .find(|bad| **bad > physical)
+            .copied()
+            .unwrap_or(block_count);
+        let count = count.min(next_bad - physical.min(next_bad));
+        if physical >= block_count || count == 0 {
+            return Err(FlashError::BlockDeviceFull {
+                name: self.name.clone(),
+            });
+        }
+        Ok((physical, count))
+    }
+
+    fn read_blocks(
+        &mut self,
+        session: &mut Session,
+        mut block: u64,
+        mut data: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let block_size = self.device.block_size() as usize;
+        while !data.is_empty() {
+            let (physical, count) = self.run(session, block, (data.len() / block_size) as u64)?;
+            let (run, rest) = data.split_at_mut(count as usize * block_size);
+            self.device.read_blocks(session, physical, run)?;
+            block += count;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(
+        &mut self,
+        session: &mut Session,
+        mut block: u64,
+        mut data: &[u8],
+    ) -> Result<(), FlashError> {
+        let block_size = self.device.block_size() as usize;
+        while !data.is_empty() {
+            let (physical, count) = self.run(session, block, (data.len() / block_size) as u64)?;
+            let (run, rest) = data.split_at(count as usize * block_size);
+            match self.device.write_blocks(session, physical, run) {
+                Ok(()) => {
+                    block += count;
+                    data = rest;
+                }
+                Err(FlashError::BadBlock { block: bad }) if self.device.has_bad_blocks() => {
+                    tracing::warn!("Block {} of {} went bad, it is skipped", bad, self.name);
+                    self.device.mark_bad_block(session, bad)?;
+                    let bad_blocks = self.bad_blocks(session)?;
+                    let index = bad_blocks.partition_point(|b| *b < bad);
+                    bad_blocks.insert(index, bad);
+
+                    // The blocks before the bad one were written.
+                    let written = bad.saturating_sub(physical);
+                    block += written;
+                    data = &data[written as usize * block_size..];
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// The offset into the device of `length` bytes at `address`.
+    fn offset(&self, address: u64, length: usize) -> Result<u64, FlashError> {
+        let range = self.range();
+        if address < range.start || address + length as u64 > range.end {
+            return Err(FlashError::BlockDeviceFull {
+                name: self.name.clone(),
+            });
+        }
+        Ok(address - self.base_address)
+    }
+}
+
+impl<D: BlockDevice> FlashProgrammer for BlockDeviceProgrammer<D> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> Range<u64> {
+        let size = self.device.block_count() * self.device.block_size() as u64;
+        self.base_address..self.base_address + size
+    }
+
+    fn erase_all(&mut self, session: &mut Session) -> Result<(), FlashError> {
+        self.device.erase_all(session)
+    }
+
+    fn program(
+        &mut self,
+        session: &mut Session,
+        address: u64,
+        data: &[u8],
+        _erase: bool,
+    ) -> Result<(), FlashError> {
+        let offset = self.offset(address, data.len())?;
+        let end = offset + data.len() as u64;
+        let block_size = self.device.block_size() as u64;
+        let blocks_per_transfer = (MAX_TRANSFER_SIZE / block_size).max(1);
+
+        let mut block = offset / block_size;
+        let last_block = (end + block_size - 1) / block_size;
+        while block < last_block {
+            let count = (last_block - block).min(blocks_per_transfer);
+            let start = block * block_size;
+            let stop = start + count * block_size;
+            let mut buffer = vec![0xFF; (count * block_size) as usize];
+
+            // Partial blocks are read first, so their other contents are kept.
+            if offset > start {
+                self.read_blocks(session, block, &mut buffer[..block_size as usize])?;
+            }
+            if end < stop && (count > 1 || offset <= start) {
+                let last = buffer.len() - block_size as usize;
+                self.read_blocks(session, block + count - 1, &mut buffer[last..])?;
+            }
+
+            let data_start = start.max(offset);
+            let data_end = stop.min(end);
+            buffer[(data_start - start) as usize..(data_end - start) as usize].copy_from_slice(
+                &data[(data_start - offset) as usize..(data_end - offset) as usize],
+            );
+
+            self.write_blocks(session, block, &buffer)?;
+            block += count;
+        }
+
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        session: &mut Session,
+        address: u64,
+        data: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let offset = self.offset(address, data.len())?;
+        let end = offset + data.len() as u64;
+        let block_size = self.device.block_size() as u64;
+        let blocks_per_transfer = (MAX_TRANSFER_SIZE / block_size).max(1);
+
+        let mut block = offset / block_size;
+        let last_block = (end + block_size - 1) / block_size;
+        while block < last_block {
+            let count = (last_block - block).min(blocks_per_transfer);
+            let start = block * block_size;
+            let stop = start + count * block_size;
+            let mut buffer = vec![0; (count * block_size) as usize];
+            self.read_blocks(session, block, &mut buffer)?;
+
+            let data_start = start.max(offset);
+            let data_end = stop.min(end);
+            data[(data_start - offset) as usize..(data_end - offset) as usize].copy_from_slice(
+                &buffer[(data_start - start) as usize..(data_end - start) as usize],
+            );
+            block += count;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FakeProbe, Permissions, Probe};
+
+    /// A NAND with 8 blocks of 16 bytes, of which block 1 is bad and block 3 goes bad when it is written.
+    struct SimulatedNand {
+        blocks: Vec<Option<[u8; 16]>>,
+        failing: Vec<u64>,
+    }
+
+    impl BlockDevice for SimulatedNand {
+        fn name(&self) -> &str {
+            "simulated"
+        }
+
+        fn block_size(&self) -> u32 {
+            16
+        }
+
+        fn block_count(&self) -> u64 {
+            self.blocks.len() as u64
+        }
+
+        fn read_blocks(
+            &mut self,
+            _session: &mut Session,
+            block: u64,
+            data: &mut [u8],
+        ) -> Result<(), FlashError> {
+            for (i, chunk) in data.chunks_mut(16).enumerate() {
+                chunk.copy_from_slice(&self.blocks[block as usize + i].unwrap());
+            }
+            Ok(())
+        }
+
+        fn write_blocks(
+            &mut self,
+            _session: &mut Session,
+            block: u64,
+            data: &[u8],
+        ) -> Result<(), FlashError> {
+            for (i, chunk) in data.chunks(16).enumerate() {
+                let block = block + i as u64;
+                if self.failing.contains(&block) {
+                    return Err(FlashError::BadBlock { block });
+                }
+                self.blocks[block as usize] = Some(chunk.try_into().unwrap());
+            }
+            Ok(())
+        }
+
+        fn has_bad_blocks(&self) -> bool {
+            true
+        }
+
+        fn is_bad_block(&mut self, _session: &mut Session, block: u64) -> Result<bool, FlashError> {
+            Ok(self.blocks[block as usize].is_none())
+        }
+
+        fn mark_bad_block(&mut self, _session: &mut Session, block: u64) -> Result<(), FlashError> {
+            self.blocks[block as usize] = None;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bad_blocks_are_skipped() {
+        let mut session = Probe::from_specific_probe(Box::new(FakeProbe::new()))
+            .attach("stm32wb55ccux", Permissions::default())
+            .unwrap();
+        let mut blocks = vec![Some([0xAA; 16]); 8];
+        blocks[1] = None;
+        let device = SimulatedNand {
+            blocks,
+            failing: vec![3],
+        };
+        let mut programmer = BlockDeviceProgrammer::new(device, 0x1000_0000);
+
+        // The data starts in the middle of logical block 0, and ends in the middle of logical block 3.
+        let data = (0..50).collect::<Vec<u8>>();
+        programmer
+            .program(&mut session, 0x1000_0008, &data, true)
+            .unwrap();
+
+        // Logical blocks 0 to 3 are in the physical blocks 0, 2, 4 and 5.
+        let blocks = &programmer.device().blocks;
+        assert_eq!(blocks[1], None);
+        assert_eq!(blocks[3], None);
+        assert_eq!(blocks[0].unwrap()[..8], [0xAA; 8]);
+        assert_eq!(blocks[0].unwrap()[8..], data[..8]);
+        assert_eq!(blocks[4].unwrap()[..], data[24..40]);
+        assert_eq!(blocks[5].unwrap()[..10], data[40..]);
+        assert_eq!(blocks[5].unwrap()[10..], [0xAA; 6]);
+
+        let mut read = vec![0; data.len()];
+        programmer
+            .read(&mut session, 0x1000_0008, &mut read)
+            .unwrap();
+        assert_eq!(read, data);
+
+        // Only 6 good blocks are left for the 8 blocks of the range.
+        assert!(matches!(
+            programmer.program(&mut session, 0x1000_0000, &[0; 128], true),
+            Err(FlashError::BlockDeviceFull { .. })
+        ));
+    }
+}
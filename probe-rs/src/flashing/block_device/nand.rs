@@ -0,0 +1,296 @@
+//! Raw NAND flash, with the ECC in the spare area of the pages.
+
+use super::BlockDevice;
+use crate::flashing::FlashError;
+use crate::Session;
+
+/// The number of bytes which are protected by 3 bytes of Hamming code.
+const HAMMING_STEP_SIZE: usize = 256;
+
+/// A NAND flash controller of the target, which reads and programs raw pages.
+pub trait NandController: Send {
+    /// The name of the controller, for the logs.
+    fn name(&self) -> &str;
+
+    /// Read the data and the spare area of `page`, without correcting them with the ECC.
+    fn read_page(
+        &mut self,
+        session: &mut Session,
+        page: u32,
+        data: &mut [u8],
+        spare: &mut [u8],
+    ) -> Result<(), FlashError>;
+
+    /// Program the data and the spare area of `page`, returning whether the flash reported success.
+    fn program_page(
+        &mut self,
+        session: &mut Session,
+        page: u32,
+        data: &[u8],
+        spare: &[u8],
+    ) -> Result<bool, FlashError>;
+
+    /// Erase `block`, returning whether the flash reported success.
+    fn erase_block(&mut self, session: &mut Session, block: u32) -> Result<bool, FlashError>;
+}
+
+/// The ECC of the pages of a [`Nand`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NandEcc {
+    /// The controller computes and checks the ECC, or there is none.
+    None,
+    /// 3 bytes of Hamming code in the SmartMedia byte order for every 256 bytes of a page, which corrects one bit
+    /// error and detects two. The code of every 256 bytes is at its offset into the spare area.
+    Hamming {
+        /// The offsets of the codes into the spare area.
+        offsets: Vec<u32>,
+    },
+}
+
+/// The layout of a raw NAND flash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NandLayout {
+    /// The size of the data of a page in bytes.
+    pub page_size: u32,
+    /// The size of the spare area of a page in bytes.
+    pub spare_size: u32,
+    /// The number of pages of an erase block.
+    pub pages_per_block: u32,
+    /// The number of erase blocks.
+    pub block_count: u32,
+    /// The offset into the spare area of the first page of a block of the bad block marker, which is not `0xFF` in
+    /// bad blocks.
+    pub bad_block_marker: u32,
+    /// The ECC of the pages.
+    pub ecc: NandEcc,
+}
+
+/// A raw NAND flash, whose blocks are its erase blocks.
+pub struct Nand<C> {
+    name: String,
+    controller: C,
+    layout: NandLayout,
+}
+
+impl<C: NandController> Nand<C> {
+    /// A flash with `layout`, which is connected to `controller`.
+    pub fn new(controller: C, layout: NandLayout) -> Self {
+        Self {
+            name: format!("NAND flash ({})", controller.name()),
+            controller,
+            layout,
+        }
+    }
+
+    fn read_page(
+        &mut self,
+        session: &mut Session,
+        page: u32,
+        data: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let mut spare = vec![0; self.layout.spare_size as usize];
+        self.controller.read_page(session, page, data, &mut spare)?;
+
+        if let NandEcc::Hamming { offsets } = &self.layout.ecc {
+            for (step, offset) in data.chunks_mut(HAMMING_STEP_SIZE).zip(offsets) {
+                let stored = &spare[*offset as usize..][..3];
+                hamming_correct(step, stored.try_into().unwrap())
+                    .map_err(|()| FlashError::NandEccUncorrectable { page })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn program_page(
+        &mut self,
+        session: &mut Session,
+        page: u32,
+        data: &[u8],
+    ) -> Result<bool, FlashError> {
+        let mut spare = vec![0xFF; self.layout.spare_size as usize];
+        if let NandEcc::Hamming { offsets } = &self.layout.ecc {
+            for (step, offset) in data.chunks(HAMMING_STEP_SIZE).zip(offsets) {
+                spare[*offset as usize..][..3].copy_from_slice(&hamming_code(step));
+            }
+        }
+        self.controller.program_page(session, page, data, &spare)
+    }
+}
+
+impl<C: NandController> BlockDevice for Nand<C> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> u32 {
+        self.layout.page_size * self.layout.pages_per_block
+    }
+
+    fn block_count(&self) -> u64 {
+        self.layout.block_count as u64
+    }
+
+    fn read_blocks(
+        &mut self,
+        session: &mut Session,
+        block: u64,
+        data: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let first_page = block as u32 * self.layout.pages_per_block;
+        for (i, page) in data.chunks_mut(self.layout.page_size as usize).enumerate() {
+            self.read_page(session, first_page + i as u32, page)?;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(
+        &mut self,
+        session: &mut Session,
+        block: u64,
+        data: &[u8],
+    ) -> Result<(), FlashError> {
+        for (i, block_data) in data.chunks(self.block_size() as usize).enumerate() {
+            let block = block + i as u64;
+            if !self.controller.erase_block(session, block as u32)? {
+                return Err(FlashError::BadBlock { block });
+            }
+
+            let first_page = block as u32 * self.layout.pages_per_block;
+            for (i, page) in block_data
+                .chunks(self.layout.page_size as usize)
+                .enumerate()
+            {
+                // Erased pages are not programmed, so they stay erased.
+                if page.iter().all(|byte| *byte == 0xFF) {
+                    continue;
+                }
+                if !self.program_page(session, first_page + i as u32, page)? {
+                    return Err(FlashError::BadBlock { block });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn has_bad_blocks(&self) -> bool {
+        true
+    }
+
+    fn is_bad_block(&mut self, session: &mut Session, block: u64) -> Result<bool, FlashError> {
+        let mut data = vec![0; self.layout.page_size as usize];
+        let mut spare = vec![0; self.layout.spare_size as usize];
+        let page = block as u32 * self.layout.pages_per_block;
+        self.controller
+            .read_page(session, page, &mut data, &mut spare)?;
+        Ok(spare[self.layout.bad_block_marker as usize] != 0xFF)
+    }
+
+    fn mark_bad_block(&mut self, session: &mut Session, block: u64) -> Result<(), FlashError> {
+        let data = vec![0xFF; self.layout.page_size as usize];
+        let mut spare = vec![0xFF; self.layout.spare_size as usize];
+        spare[self.layout.bad_block_marker as usize] = 0x00;
+        let page = block as u32 * self.layout.pages_per_block;
+        // The block failed already, so the result is ignored.
+        self.controller.erase_block(session, block as u32)?;
+        self.controller.program_page(session, page, &data, &spare)?;
+        Ok(())
+    }
+
+    fn erase_all(&mut self, session: &mut Session) -> Result<(), FlashError> {
+        for block in 0..self.block_count() {
+            if self.is_bad_block(session, block)? {
+                continue;
+            }
+            if !self.controller.erase_block(session, block as u32)? {
+                tracing::warn!("Erasing block {} of {} failed", block, self.name);
+                self.mark_bad_block(session, block)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The parities of the bytes and the bits of a step, which are the inverted Hamming code.
+///
+/// Bits 0 to 15 are the line parities, where bit `2n + 1` is the parity of the bytes with bit `n` set in their index,
+/// and bit `2n` of the other bytes. Bits 18 to 23 are the column parities, in the same way for the bits of the bytes.
+fn parities(data: &[u8]) -> u32 {
+    let mut lines = 0;
+    let mut columns = 0;
+    for (index, byte) in data.iter().enumerate() {
+        if byte.count_ones() % 2 == 1 {
+            for n in 0..8 {
+                lines ^= 1 << (2 * n + ((index >> n) & 1));
+            }
+        }
+        columns ^= *byte;
+    }
+
+    for n in 0..3 {
+        for bit in 0..8 {
+            if columns & (1 << bit) != 0 {
+                lines ^= 1 << (18 + 2 * n + ((bit >> n) & 1));
+            }
+        }
+    }
+    lines
+}
+
+/// The Hamming code of a step of 256 bytes, which is `0xFF` for erased data.
+fn hamming_code(data: &[u8]) -> [u8; 3] {
+    let parities = !parities(data);
+    [
+        parities as u8,
+        (parities >> 8) as u8,
+        (parities >> 16) as u8 | 0b11,
+    ]
+}
+
+/// Correct a single bit error of a step with the `stored` Hamming code.
+fn hamming_correct(data: &mut [u8], stored: [u8; 3]) -> Result<(), ()> {
+    let computed = hamming_code(data);
+    let syndrome = u32::from_le_bytes([
+        stored[0] ^ computed[0],
+        stored[1] ^ computed[1],
+        (stored[2] ^ computed[2]) & !0b11,
+        0,
+    ]);
+
+    match syndrome.count_ones() {
+        0 => Ok(()),
+        // A bit error of the code itself.
+        1 => Ok(()),
+        // A bit error of the data flips one parity of every pair.
+        11 if (0..12)
+            .filter(|pair| *pair != 8)
+            .all(|pair| (syndrome >> (2 * pair)) & 0b11 != 0b11) =>
+        {
+            let index = (0..8).fold(0, |index, n| index | ((syndrome >> (2 * n + 1)) & 1) << n);
+            let bit = (0..3).fold(0, |bit, n| bit | ((syndrome >> (18 + 2 * n + 1)) & 1) << n);
+            data[index as usize] ^= 1 << bit;
+            Ok(())
+        }
+        _ => Err(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn single_bit_errors_are_corrected() {
+        let data = (0..=255).collect::<Vec<u8>>();
+        let code = hamming_code(&data);
+        assert_eq!(hamming_code(&[0xFF; 256]), [0xFF; 3]);
+
+        let mut corrupted = data.clone();
+        corrupted[0x5A] ^= 1 << 3;
+        hamming_correct(&mut corrupted, code).unwrap();
+        assert_eq!(corrupted, data);
+
+        corrupted[0x10] ^= 1 << 1;
+        corrupted[0x20] ^= 1 << 6;
+        assert!(hamming_correct(&mut corrupted, code).is_err());
+    }
+}
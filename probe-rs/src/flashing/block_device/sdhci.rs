@@ -0,0 +1,230 @@
+//! A host controller with the registers of the SD Host Controller Standard, in PIO mode.
+
+use std::time::{Duration, Instant};
+
+use super::MmcController;
+use crate::flashing::FlashError;
+use crate::{Core, MemoryInterface, Session};
+
+const BLOCK_SIZE_AND_COUNT: u64 = 0x04;
+const ARGUMENT: u64 = 0x08;
+const TRANSFER_MODE_AND_COMMAND: u64 = 0x0C;
+const RESPONSE: u64 = 0x10;
+const BUFFER_DATA_PORT: u64 = 0x20;
+const PRESENT_STATE: u64 = 0x24;
+const INTERRUPT_STATUS: u64 = 0x30;
+const INTERRUPT_STATUS_ENABLE: u64 = 0x34;
+
+const PRESENT_COMMAND_INHIBIT: u32 = 1 << 0;
+const PRESENT_DATA_INHIBIT: u32 = 1 << 1;
+
+const INTERRUPT_COMMAND_COMPLETE: u32 = 1 << 0;
+const INTERRUPT_TRANSFER_COMPLETE: u32 = 1 << 1;
+const INTERRUPT_BUFFER_WRITE_READY: u32 = 1 << 4;
+const INTERRUPT_BUFFER_READ_READY: u32 = 1 << 5;
+const INTERRUPT_ERROR: u32 = 1 << 15;
+
+const TRANSFER_BLOCK_COUNT_ENABLE: u32 = 1 << 1;
+const TRANSFER_AUTO_CMD12: u32 = 0b01 << 2;
+const TRANSFER_READ: u32 = 1 << 4;
+const TRANSFER_MULTIPLE_BLOCKS: u32 = 1 << 5;
+
+/// A 48 bit response with CRC and index checks, and with the busy signal for R1b.
+const COMMAND_RESPONSE_R1: u32 = 0b1_1010;
+const COMMAND_RESPONSE_R1B: u32 = 0b1_1011;
+const COMMAND_DATA_PRESENT: u32 = 1 << 5;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Writing a block may take up to 250 ms, with some margin for slow cards.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The data of a command.
+enum Data<'a> {
+    None,
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+/// A SD/MMC host controller with the standard SDHCI registers at `base_address`, which transfers the data through
+/// its buffer data port.
+///
+/// The clock and the bus width of the controller have to be configured already, like the card.
+#[derive(Debug)]
+pub struct Sdhci {
+    core: usize,
+    base_address: u64,
+}
+
+impl Sdhci {
+    /// Operate the controller at `base_address` through the core with index `core`.
+    pub fn new(core: usize, base_address: u64) -> Self {
+        Self { core, base_address }
+    }
+
+    fn send(
+        &mut self,
+        session: &mut Session,
+        index: u8,
+        argument: u32,
+        busy: bool,
+        data: Data<'_>,
+    ) -> Result<u32, FlashError> {
+        let mut core = session.core(self.core)?;
+        let base = self.base_address;
+
+        let inhibit = match (&data, busy) {
+            (Data::None, false) => PRESENT_COMMAND_INHIBIT,
+            _ => PRESENT_COMMAND_INHIBIT | PRESENT_DATA_INHIBIT,
+        };
+        wait(&mut core, base + PRESENT_STATE, index, |state| {
+            state & inhibit == 0
+        })?;
+        core.write_word_32(base + INTERRUPT_STATUS_ENABLE, 0xFFFF_FFFF)?;
+        core.write_word_32(base + INTERRUPT_STATUS, 0xFFFF_FFFF)?;
+
+        let mut command = (index as u32) << 8;
+        let mut transfer_mode = 0;
+        let blocks = match &data {
+            Data::None => 0,
+            Data::Read(data) => data.len() / BLOCK_SIZE,
+            Data::Write(data) => data.len() / BLOCK_SIZE,
+        };
+        if blocks > 0 {
+            core.write_word_32(
+                base + BLOCK_SIZE_AND_COUNT,
+                ((blocks as u32) << 16) | BLOCK_SIZE as u32,
+            )?;
+            command |= COMMAND_DATA_PRESENT;
+            if matches!(data, Data::Read(_)) {
+                transfer_mode |= TRANSFER_READ;
+            }
+            if blocks > 1 {
+                transfer_mode |=
+                    TRANSFER_BLOCK_COUNT_ENABLE | TRANSFER_MULTIPLE_BLOCKS | TRANSFER_AUTO_CMD12;
+            }
+        }
+        command |= if busy {
+            COMMAND_RESPONSE_R1B
+        } else {
+            COMMAND_RESPONSE_R1
+        };
+
+        core.write_word_32(base + ARGUMENT, argument)?;
+        core.write_word_32(
+            base + TRANSFER_MODE_AND_COMMAND,
+            (command << 16) | transfer_mode,
+        )?;
+        wait_interrupt(&mut core, base, index, INTERRUPT_COMMAND_COMPLETE)?;
+        let status = core.read_word_32(base + RESPONSE)?;
+
+        match data {
+            Data::None => {}
+            Data::Read(data) => {
+                for block in data.chunks_mut(BLOCK_SIZE) {
+                    wait_interrupt(&mut core, base, index, INTERRUPT_BUFFER_READ_READY)?;
+                    for word in block.chunks_mut(4) {
+                        word.copy_from_slice(
+                            &core.read_word_32(base + BUFFER_DATA_PORT)?.to_le_bytes(),
+                        );
+                    }
+                }
+            }
+            Data::Write(data) => {
+                for block in data.chunks(BLOCK_SIZE) {
+                    wait_interrupt(&mut core, base, index, INTERRUPT_BUFFER_WRITE_READY)?;
+                    for word in block.chunks(4) {
+                        core.write_word_32(
+                            base + BUFFER_DATA_PORT,
+                            u32::from_le_bytes(word.try_into().unwrap()),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if blocks > 0 || busy {
+            wait_interrupt(&mut core, base, index, INTERRUPT_TRANSFER_COMPLETE)?;
+        }
+        Ok(status)
+    }
+}
+
+impl MmcController for Sdhci {
+    fn name(&self) -> &str {
+        "SDHCI"
+    }
+
+    fn command(
+        &mut self,
+        session: &mut Session,
+        index: u8,
+        argument: u32,
+        busy: bool,
+    ) -> Result<u32, FlashError> {
+        self.send(session, index, argument, busy, Data::None)
+    }
+
+    fn read(
+        &mut self,
+        session: &mut Session,
+        index: u8,
+        argument: u32,
+        data: &mut [u8],
+    ) -> Result<u32, FlashError> {
+        self.send(session, index, argument, false, Data::Read(data))
+    }
+
+    fn write(
+        &mut self,
+        session: &mut Session,
+        index: u8,
+        argument: u32,
+        data: &[u8],
+    ) -> Result<u32, FlashError> {
+        self.send(session, index, argument, false, Data::Write(data))
+    }
+}
+
+/// Wait for the `interrupt` status, and clear it.
+fn wait_interrupt(
+    core: &mut Core<'_>,
+    base: u64,
+    index: u8,
+    interrupt: u32,
+) -> Result<(), FlashError> {
+    let start = Instant::now();
+    loop {
+        let status = core.read_word_32(base + INTERRUPT_STATUS)?;
+        if status & INTERRUPT_ERROR != 0 {
+            core.write_word_32(base + INTERRUPT_STATUS, 0xFFFF_FFFF)?;
+            return Err(FlashError::MmcCommandFailed {
+                index,
+                status: status >> 16,
+            });
+        }
+        if status & interrupt != 0 {
+            core.write_word_32(base + INTERRUPT_STATUS, interrupt)?;
+            return Ok(());
+        }
+        if start.elapsed() > TIMEOUT {
+            return Err(FlashError::MmcTimeout { index });
+        }
+    }
+}
+
+/// Wait until the register at `address` is `done`.
+fn wait(
+    core: &mut Core<'_>,
+    address: u64,
+    index: u8,
+    done: impl Fn(u32) -> bool,
+) -> Result<(), FlashError> {
+    let start = Instant::now();
+    while !done(core.read_word_32(address)?) {
+        if start.elapsed() > TIMEOUT {
+            return Err(FlashError::MmcTimeout { index });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,437 @@
+//! The serial recovery of MCUboot, with the image upload of the SMP protocol of mcumgr.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+#[cfg(feature = "serial")]
+use serialport::SerialPort;
+
+use super::{communication_error, Bootloader, FlashSector};
+use crate::flashing::FlashError;
+
+const NAME: &str = "MCUboot serial recovery";
+
+/// The markers of the first and the following lines of a frame.
+const FRAME_START: [u8; 2] = [0x06, 0x09];
+const FRAME_CONTINUATION: [u8; 2] = [0x04, 0x14];
+/// The maximum number of base64 characters of a line, which has at most 127 bytes with its marker and newline.
+const LINE_LENGTH: usize = 124;
+
+const OP_WRITE: u8 = 2;
+const OP_WRITE_RESPONSE: u8 = 3;
+const GROUP_OS: u16 = 0;
+const GROUP_IMAGE: u16 = 1;
+const ID_RESET: u8 = 5;
+const ID_UPLOAD: u8 = 1;
+
+/// The size of the data of an upload request, which keeps the frames small enough for the default buffers of
+/// MCUboot.
+const CHUNK_SIZE: usize = 128;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// The time to wait for the first upload request, after which the slot is erased.
+const ERASE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// MCUboot in serial recovery mode, which uploads an image into its primary slot over the serial port.
+///
+/// The image has to be a signed MCUboot image, which is programmed at the start of the slot. MCUboot erases the slot
+/// itself, and can not read it back.
+pub struct McubootSerial<P> {
+    port: P,
+    slot: Range<u64>,
+    sequence: u8,
+}
+
+#[cfg(feature = "serial")]
+impl McubootSerial<Box<dyn SerialPort>> {
+    /// Connect to MCUboot on the serial port at `path`, with `baud_rate`, to program the slot at `slot`.
+    pub fn open_serial(path: &str, baud_rate: u32, slot: Range<u64>) -> Result<Self, FlashError> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(Duration::from_millis(100))
+            .open()
+            .map_err(|source| FlashError::BootloaderCommunication {
+                name: NAME.to_owned(),
+                source: Box::new(source),
+            })?;
+        Ok(Self::new(port, slot))
+    }
+}
+
+impl<P: Read + Write + Send> McubootSerial<P> {
+    /// Program the slot at `slot` with MCUboot over `port`.
+    pub fn new(port: P, slot: Range<u64>) -> Self {
+        Self {
+            port,
+            slot,
+            sequence: 0,
+        }
+    }
+
+    /// Send a write request, and return the values of its response.
+    fn request(
+        &mut self,
+        group: u16,
+        id: u8,
+        body: &[u8],
+        timeout: Duration,
+    ) -> Result<HashMap<String, i64>, FlashError> {
+        let mut packet = vec![OP_WRITE, 0];
+        packet.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&group.to_be_bytes());
+        packet.extend_from_slice(&[self.sequence, id]);
+        packet.extend_from_slice(body);
+        self.sequence = self.sequence.wrapping_add(1);
+
+        self.port
+            .write_all(&encode_frame(&packet))
+            .map_err(communication_error(NAME))?;
+
+        let response = self.read_frame(timeout)?;
+        if response.len() < 8 || response[0] != OP_WRITE_RESPONSE || response[7] != id {
+            return Err(FlashError::BootloaderCommunication {
+                name: NAME.to_owned(),
+                source: "The response does not belong to the request.".into(),
+            });
+        }
+        let values =
+            decode_map(&response[8..]).ok_or_else(|| FlashError::BootloaderCommunication {
+                name: NAME.to_owned(),
+                source: "The response is not a valid CBOR map.".into(),
+            })?;
+        match values.get("rc") {
+            Some(rc) if *rc != 0 => Err(FlashError::BootloaderRejected {
+                name: NAME.to_owned(),
+                operation: format!("the request {group}/{id} with the error {rc}"),
+            }),
+            _ => Ok(values),
+        }
+    }
+
+    /// Read the lines of a frame, and return its packet.
+    fn read_frame(&mut self, timeout: Duration) -> Result<Vec<u8>, FlashError> {
+        let start = Instant::now();
+        let mut line = vec![];
+        let mut text = vec![];
+        loop {
+            let mut byte = [0];
+            match self.port.read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(error) if error.kind() == ErrorKind::TimedOut && start.elapsed() < timeout => {
+                    continue
+                }
+                Err(error) => return Err(communication_error(NAME)(error)),
+            }
+            if byte[0] != b'\n' {
+                line.push(byte[0]);
+                continue;
+            }
+
+            // Other output of the bootloader is ignored.
+            match line.get(..2) {
+                Some(marker) if marker == FRAME_START => text = line[2..].to_vec(),
+                Some(marker) if marker == FRAME_CONTINUATION => text.extend_from_slice(&line[2..]),
+                _ => {}
+            }
+            line.clear();
+            if let Some(packet) = decode_frame(&text)? {
+                return Ok(packet);
+            }
+        }
+    }
+}
+
+impl<P: Read + Write + Send> Bootloader for McubootSerial<P> {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn erase_all(&mut self) -> Result<(), FlashError> {
+        // The slot is erased by the upload.
+        Ok(())
+    }
+
+    fn erase(&mut self, _sectors: &[FlashSector]) -> Result<(), FlashError> {
+        Ok(())
+    }
+
+    fn program(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<(), FlashError> {
+        if address != self.slot.start || address + data.len() as u64 > self.slot.end {
+            return Err(FlashError::BootloaderUnsupported {
+                name: NAME.to_owned(),
+                operation: "programming outside of the start of the slot",
+            });
+        }
+
+        let mut offset = 0;
+        while offset < data.len() {
+            let end = (offset + CHUNK_SIZE).min(data.len());
+            let mut body = Vec::new();
+            // The first request has the length of the image.
+            encode_header(&mut body, 5, if offset == 0 { 3 } else { 2 });
+            encode_text(&mut body, "data");
+            encode_header(&mut body, 2, (end - offset) as u64);
+            body.extend_from_slice(&data[offset..end]);
+            encode_text(&mut body, "off");
+            encode_header(&mut body, 0, offset as u64);
+            if offset == 0 {
+                encode_text(&mut body, "len");
+                encode_header(&mut body, 0, data.len() as u64);
+            }
+
+            let timeout = if offset == 0 { ERASE_TIMEOUT } else { TIMEOUT };
+            let response = self.request(GROUP_IMAGE, ID_UPLOAD, &body, timeout)?;
+            // The bootloader returns the offset at which it expects the next data.
+            offset = match response.get("off") {
+                Some(next) => usize::try_from(*next).unwrap_or(end).min(data.len()),
+                None => end,
+            };
+            progress(offset as u64);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, _address: u64, _data: &mut [u8]) -> Result<(), FlashError> {
+        Err(FlashError::BootloaderUnsupported {
+            name: NAME.to_owned(),
+            operation: "reading the flash",
+        })
+    }
+
+    fn finish(&mut self) -> Result<(), FlashError> {
+        // An empty map.
+        self.request(GROUP_OS, ID_RESET, &[0xA0], TIMEOUT)?;
+        Ok(())
+    }
+}
+
+/// The lines of the frame of `packet`, with its length and CRC.
+fn encode_frame(packet: &[u8]) -> Vec<u8> {
+    let mut data = ((packet.len() + 2) as u16).to_be_bytes().to_vec();
+    data.extend_from_slice(packet);
+    data.extend_from_slice(&crc16(packet).to_be_bytes());
+
+    let text = BASE64.encode(data);
+    let mut frame = vec![];
+    for (i, line) in text.as_bytes().chunks(LINE_LENGTH).enumerate() {
+        frame.extend_from_slice(if i == 0 {
+            &FRAME_START
+        } else {
+            &FRAME_CONTINUATION
+        });
+        frame.extend_from_slice(line);
+        frame.push(b'\n');
+    }
+    frame
+}
+
+/// The packet of the base64 `text` of a frame, if it is complete.
+fn decode_frame(text: &[u8]) -> Result<Option<Vec<u8>>, FlashError> {
+    let invalid = |message: &'static str| FlashError::BootloaderCommunication {
+        name: NAME.to_owned(),
+        source: message.into(),
+    };
+    let Ok(data) = BASE64.decode(text) else {
+        // The frame continues on the next line.
+        return Ok(None);
+    };
+    if data.len() < 2 {
+        return Ok(None);
+    }
+    let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+    if data.len() < length + 2 {
+        return Ok(None);
+    }
+    if length < 2 || crc16(&data[2..length + 2]) != 0 {
+        return Err(invalid("The CRC of a frame is invalid."));
+    }
+    Ok(Some(data[2..length].to_vec()))
+}
+
+/// The CRC-16 of XMODEM, which is 0 over data followed by its CRC.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |mut crc: u16, byte| {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Append the header of a CBOR item of `major` type with the argument `value`.
+fn encode_header(data: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => data.push(major | value as u8),
+        24..=0xFF => data.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xFFFF => {
+            data.push(major | 25);
+            data.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        _ => {
+            data.push(major | 26);
+            data.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+    }
+}
+
+fn encode_text(data: &mut Vec<u8>, text: &str) {
+    encode_header(data, 3, text.len() as u64);
+    data.extend_from_slice(text.as_bytes());
+}
+
+/// The integer values of a CBOR map with text keys. Other values are skipped.
+fn decode_map(data: &[u8]) -> Option<HashMap<String, i64>> {
+    fn header(data: &mut &[u8]) -> Option<(u8, u64)> {
+        let (first, rest) = data.split_first()?;
+        let length = match first & 0x1F {
+            value @ 0..=23 => {
+                *data = rest;
+                return Some((first >> 5, value as u64));
+            }
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return None,
+        };
+        let bytes = rest.get(..length)?;
+        *data = &rest[length..];
+        let value = bytes
+            .iter()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64);
+        Some((first >> 5, value))
+    }
+
+    let mut data = data;
+    let (major, entries) = header(&mut data)?;
+    if major != 5 {
+        return None;
+    }
+    let mut values = HashMap::new();
+    for _ in 0..entries {
+        let (3, length) = header(&mut data)? else {
+            return None;
+        };
+        let key = String::from_utf8_lossy(data.get(..length as usize)?).into_owned();
+        data = &data[length as usize..];
+        match header(&mut data)? {
+            (0, value) => {
+                values.insert(key, value as i64);
+            }
+            (1, value) => {
+                values.insert(key, -1 - value as i64);
+            }
+            (2 | 3, length) => data = data.get(length as usize..)?,
+            _ => return None,
+        }
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct ScriptedPort {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Read for ScriptedPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for ScriptedPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The frame of an upload response with the next `offset`.
+    fn upload_response(sequence: u8, offset: u64) -> Vec<u8> {
+        let mut body = vec![];
+        encode_header(&mut body, 5, 2);
+        encode_text(&mut body, "rc");
+        encode_header(&mut body, 0, 0);
+        encode_text(&mut body, "off");
+        encode_header(&mut body, 0, offset);
+        let mut packet = vec![OP_WRITE_RESPONSE, 0];
+        packet.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&GROUP_IMAGE.to_be_bytes());
+        packet.extend_from_slice(&[sequence, ID_UPLOAD]);
+        packet.extend_from_slice(&body);
+        encode_frame(&packet)
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn image_is_uploaded_in_chunks() {
+        let image = (0..300).map(|i| i as u8).collect::<Vec<_>>();
+        let mut port = ScriptedPort::default();
+        // Log output of the bootloader, and the responses.
+        port.input.extend(b"[INF] Starting serial recovery\n");
+        port.input.extend(upload_response(0, 128));
+        port.input.extend(upload_response(1, 256));
+        port.input.extend(upload_response(2, 300));
+
+        let mut mcuboot = McubootSerial::new(port, 0x0001_0000..0x0008_0000);
+        let mut progress = vec![];
+        mcuboot
+            .program(0x0001_0000, &image, &mut |count| progress.push(count))
+            .unwrap();
+        assert_eq!(progress, [128, 256, 300]);
+
+        // The requests are split into lines of at most 127 bytes.
+        let output = mcuboot.port.output.clone();
+        let lines = output.split(|byte| *byte == b'\n').collect::<Vec<_>>();
+        assert!(lines.iter().all(|line| line.len() <= 126));
+        assert_eq!(lines[0][..2], FRAME_START);
+        assert_eq!(lines[1][..2], FRAME_CONTINUATION);
+
+        let mut text = lines[0][2..].to_vec();
+        for line in lines[1..]
+            .iter()
+            .take_while(|line| line.starts_with(&FRAME_CONTINUATION))
+        {
+            text.extend_from_slice(&line[2..]);
+        }
+        let packet = decode_frame(&text).unwrap().unwrap();
+        assert_eq!(packet[..8], [OP_WRITE, 0, 0, 148, 0, 1, 0, ID_UPLOAD]);
+        let values = decode_map(&packet[8..]).unwrap();
+        assert_eq!(
+            values,
+            HashMap::from([("off".to_owned(), 0), ("len".to_owned(), 300)])
+        );
+
+        assert!(matches!(
+            mcuboot.program(0x0001_0200, &image, &mut |_| {}),
+            Err(FlashError::BootloaderUnsupported { .. })
+        ));
+    }
+}
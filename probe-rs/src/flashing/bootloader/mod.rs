@@ -0,0 +1,281 @@
+//! Flashing through the ROM or serial bootloader of a chip, without a debug probe.
+//!
+//! A [`Bootloader`] programs the flash with the protocol of a bootloader: the STM32 system bootloader over a UART
+//! ([`Stm32Bootloader`]) or over USB DFU ([`Stm32Dfu`]), the serial recovery of MCUboot ([`McubootSerial`]), or the
+//! mass storage of a UF2 bootloader ([`Uf2Drive`]). It has no access to the cores and the memory of the chip, so the
+//! image is loaded with the memory map of the [`Target`], and [`FlashLoader::commit_with_bootloader`] or
+//! [`download_file_with_bootloader`] hand its data to the bootloader.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use probe_rs_target::{MemoryRange, MemoryRegion, NvmRegion};
+
+use super::{DownloadOptions, FileDownloadError, FlashError, FlashLoader, FlashProgress, Format};
+use crate::Target;
+
+mod mcuboot;
+mod stm32;
+#[cfg(not(target_arch = "wasm32"))]
+mod stm32_dfu;
+mod uf2;
+
+pub use mcuboot::McubootSerial;
+pub use stm32::Stm32Bootloader;
+#[cfg(not(target_arch = "wasm32"))]
+pub use stm32_dfu::Stm32Dfu;
+pub use uf2::Uf2Drive;
+
+/// The size of the pieces which are read back to verify the programmed data.
+const VERIFY_SIZE: usize = 4096;
+
+/// A sector of the flash, as described by the flash algorithm of the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashSector {
+    /// The index of the sector from the start of the flash, which is the page number of many bootloaders.
+    pub index: u32,
+    /// The address range of the sector.
+    pub range: Range<u64>,
+}
+
+/// A ROM or serial bootloader of a chip, which programs its flash without a debug probe.
+pub trait Bootloader: Send {
+    /// The name of the bootloader, for the logs.
+    fn name(&self) -> &str;
+
+    /// Erase the whole flash, before anything is programmed. This is called instead of [`Self::erase`], if a chip
+    /// erase was requested.
+    fn erase_all(&mut self) -> Result<(), FlashError>;
+
+    /// Erase the `sectors` which are programmed.
+    fn erase(&mut self, sectors: &[FlashSector]) -> Result<(), FlashError>;
+
+    /// Program `data` at `address`, calling `progress` with the number of bytes of it which are programmed.
+    fn program(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<(), FlashError>;
+
+    /// Read the flash at `address` into `data`, to verify what was programmed.
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), FlashError>;
+
+    /// Leave the bootloader after flashing, and start the firmware if the bootloader can.
+    fn finish(&mut self) -> Result<(), FlashError>;
+}
+
+impl FlashLoader {
+    /// Program the loaded data with `bootloader`, instead of with the flash algorithms of `target`.
+    ///
+    /// All data has to be in NVM regions. Of the `options`, the progress, the erase options, `dry_run`, `verify` and
+    /// the OTP check are used.
+    pub fn commit_with_bootloader(
+        &self,
+        target: &Target,
+        bootloader: &mut dyn Bootloader,
+        options: DownloadOptions,
+    ) -> Result<(), FlashError> {
+        let name = bootloader.name().to_owned();
+        let progress = options
+            .progress
+            .unwrap_or_else(|| FlashProgress::new(|_| {}));
+
+        let mut sectors = BTreeMap::new();
+        for (address, data) in self.data() {
+            let range = address..address + data.len() as u64;
+            let region = self.nvm_region(target, &range)?;
+            if region.is_otp && !options.allow_otp_write {
+                return Err(FlashError::OtpWriteNotAllowed {
+                    range: region.range.clone(),
+                });
+            }
+            if !options.do_chip_erase && !options.skip_erase {
+                for sector in region_sectors(region, target)? {
+                    if sector.range.intersects_range(&range) {
+                        sectors.insert(sector.range.start, sector);
+                    }
+                }
+            }
+        }
+
+        let total = self.data().map(|(_, data)| data.len() as u64).sum::<u64>();
+        if total == 0 {
+            tracing::info!("Nothing to program with {}", name);
+            return Ok(());
+        }
+        if options.dry_run {
+            tracing::info!(
+                "Skipping programming {} bytes with {}, dry run",
+                total,
+                name
+            );
+            return Ok(());
+        }
+
+        if options.do_chip_erase {
+            tracing::debug!("Erasing the flash with {}", name);
+            bootloader.erase_all()?;
+        } else if !sectors.is_empty() {
+            tracing::debug!("Erasing {} sectors with {}", sectors.len(), name);
+            bootloader.erase(&sectors.into_values().collect::<Vec<_>>())?;
+        }
+
+        let mut programmed = 0;
+        progress.programmer_progress(&name, 0, total);
+        for (address, data) in self.data() {
+            bootloader.program(address, data, &mut |count| {
+                progress.programmer_progress(&name, programmed + count, total);
+            })?;
+            programmed += data.len() as u64;
+        }
+
+        if options.verify {
+            tracing::debug!("Verifying the data programmed with {}", name);
+            let mut read = vec![0; VERIFY_SIZE];
+            for (address, data) in self.data() {
+                for (i, chunk) in data.chunks(VERIFY_SIZE).enumerate() {
+                    let read = &mut read[..chunk.len()];
+                    bootloader.read(address + (i * VERIFY_SIZE) as u64, read)?;
+                    if read != chunk {
+                        return Err(FlashError::Verify);
+                    }
+                }
+            }
+        }
+
+        bootloader.finish()
+    }
+
+    /// The NVM region which contains `range`.
+    fn nvm_region<'t>(
+        &self,
+        target: &'t Target,
+        range: &Range<u64>,
+    ) -> Result<&'t NvmRegion, FlashError> {
+        target
+            .memory_map
+            .iter()
+            .find_map(|region| match region {
+                MemoryRegion::Nvm(region) if region.range.contains_range(range) => Some(region),
+                _ => None,
+            })
+            .ok_or_else(|| FlashError::NoSuitableNvm {
+                start: range.start,
+                end: range.end,
+                description_source: target.source().clone(),
+            })
+    }
+}
+
+/// The sectors of `region`, from the flash algorithm of `target` for it.
+fn region_sectors(region: &NvmRegion, target: &Target) -> Result<Vec<FlashSector>, FlashError> {
+    let algorithm = FlashLoader::get_flash_algorithm_for_region(region, target)?;
+    let properties = &algorithm.flash_properties;
+
+    let mut sectors = vec![];
+    let mut index = 0;
+    for (i, description) in properties.sectors.iter().enumerate() {
+        let end = properties
+            .sectors
+            .get(i + 1)
+            .map_or(properties.address_range.end, |next| {
+                properties.address_range.start + next.address
+            });
+        let mut address = properties.address_range.start + description.address;
+        while address < end {
+            if region.range.contains(&address) {
+                sectors.push(FlashSector {
+                    index,
+                    range: address..address + description.size,
+                });
+            }
+            address += description.size;
+            index += 1;
+        }
+    }
+    Ok(sectors)
+}
+
+/// Downloads a file of given `format` at `path` to the flash of `target`, with `bootloader`.
+///
+/// See [`FlashLoader::commit_with_bootloader`] for the `options` which are used. The esp-idf format is not supported,
+/// as it reads the chip.
+pub fn download_file_with_bootloader<P: AsRef<Path>>(
+    bootloader: &mut dyn Bootloader,
+    target: &Target,
+    path: P,
+    format: Format,
+    options: DownloadOptions,
+) -> Result<(), FileDownloadError> {
+    let mut loader = load_file(target, path.as_ref(), format)?;
+
+    loader.apply_patches(&options.patches)?;
+    for image in &options.additional_images {
+        let image_loader = load_file(target, &image.path, image.format.clone())?;
+        loader.add_image(&image.path, &image_loader)?;
+    }
+
+    loader
+        .commit_with_bootloader(target, bootloader, options)
+        .map_err(FileDownloadError::Flash)
+}
+
+/// Loads the file of given `format` at `path` into a new loader for `target`.
+fn load_file(
+    target: &Target,
+    path: &Path,
+    format: Format,
+) -> Result<FlashLoader, FileDownloadError> {
+    let mut file = File::open(path)?;
+
+    let mut loader = target.flash_loader();
+    match format {
+        Format::Bin(options) => loader.load_bin_data(&mut file, options),
+        Format::Elf(options) => loader.load_elf_data(&mut file, &options),
+        Format::Hex => loader.load_hex_data(&mut file),
+        Format::Idf(_) => Err(FileDownloadError::IdfUnsupported(target.name.clone())),
+    }?;
+    Ok(loader)
+}
+
+/// Map an I/O error of the connection to a bootloader.
+fn communication_error(name: &str) -> impl FnOnce(std::io::Error) -> FlashError + '_ {
+    move |source| FlashError::BootloaderCommunication {
+        name: name.to_owned(),
+        source: Box::new(source),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sectors_of_a_region() {
+        let target = crate::config::get_target_by_name("stm32f407vgtx").unwrap();
+        let region = target
+            .memory_map
+            .iter()
+            .find_map(|region| match region {
+                MemoryRegion::Nvm(region) if region.range.start == 0x0800_0000 => Some(region),
+                _ => None,
+            })
+            .unwrap();
+
+        let sectors = region_sectors(region, &target).unwrap();
+
+        // 4 sectors of 16 KiB, 1 of 64 KiB and 7 of 128 KiB.
+        assert_eq!(sectors.len(), 12);
+        assert_eq!(
+            sectors[4],
+            FlashSector {
+                index: 4,
+                range: 0x0801_0000..0x0802_0000
+            }
+        );
+        assert_eq!(sectors[11].range.end, 0x0810_0000);
+    }
+}
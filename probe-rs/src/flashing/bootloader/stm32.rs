@@ -0,0 +1,359 @@
+//! The system bootloader of the STM32 in ROM, over a USART, as described in ST AN3155.
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serial")]
+use serialport::{Parity, SerialPort};
+
+use super::{communication_error, Bootloader, FlashSector};
+use crate::flashing::FlashError;
+
+const NAME: &str = "STM32 system bootloader";
+
+const SYNC: u8 = 0x7F;
+const ACK: u8 = 0x79;
+const NACK: u8 = 0x1F;
+
+const GET: u8 = 0x00;
+const READ_MEMORY: u8 = 0x11;
+const GO: u8 = 0x21;
+const WRITE_MEMORY: u8 = 0x31;
+const ERASE: u8 = 0x43;
+const EXTENDED_ERASE: u8 = 0x44;
+
+/// The maximum length of the data of a read or write command.
+const MAX_TRANSFER: usize = 256;
+/// The number of pages which are erased by one command.
+const MAX_ERASE_PAGES: usize = 32;
+
+/// The time to wait for the response to a command.
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// The time to wait for an erase, which takes a few seconds for the large sectors of some chips.
+const ERASE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The STM32 system bootloader in ROM, over a serial port which is connected to one of its USARTs.
+///
+/// The chip has to be started in the bootloader already, e.g. with its BOOT0 pin high during reset.
+pub struct Stm32Bootloader<P> {
+    port: P,
+    extended_erase: bool,
+    /// The lowest address which was programmed, at which [`Bootloader::finish`] starts the firmware.
+    start: Option<u64>,
+}
+
+#[cfg(feature = "serial")]
+impl Stm32Bootloader<Box<dyn SerialPort>> {
+    /// Connect to the bootloader on the serial port at `path`, with `baud_rate`.
+    pub fn open_serial(path: &str, baud_rate: u32) -> Result<Self, FlashError> {
+        let port = serialport::new(path, baud_rate)
+            .parity(Parity::Even)
+            .timeout(Duration::from_millis(100))
+            .open()
+            .map_err(|source| FlashError::BootloaderCommunication {
+                name: NAME.to_owned(),
+                source: Box::new(source),
+            })?;
+        Self::new(port)
+    }
+}
+
+impl<P: Read + Write + Send> Stm32Bootloader<P> {
+    /// Connect to the bootloader over `port`, which has to use 8 data bits and even parity.
+    pub fn new(mut port: P) -> Result<Self, FlashError> {
+        port.write_all(&[SYNC]).map_err(communication_error(NAME))?;
+        // A bootloader which was synchronized already rejects the byte.
+        read_response(&mut port, TIMEOUT)?;
+
+        let mut bootloader = Self {
+            port,
+            extended_erase: false,
+            start: None,
+        };
+        bootloader.command(GET)?;
+        let mut header = [0; 2];
+        bootloader.read_exact(&mut header)?;
+        let mut commands = vec![0; header[0] as usize];
+        bootloader.read_exact(&mut commands)?;
+        bootloader.wait_ack(TIMEOUT, || "the Get command".to_owned())?;
+
+        tracing::debug!(
+            "Connected to the STM32 system bootloader version {}.{}",
+            header[1] >> 4,
+            header[1] & 0xF
+        );
+        bootloader.extended_erase = commands.contains(&EXTENDED_ERASE);
+        Ok(bootloader)
+    }
+
+    fn read_exact(&mut self, data: &mut [u8]) -> Result<(), FlashError> {
+        self.port
+            .read_exact(data)
+            .map_err(communication_error(NAME))
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), FlashError> {
+        self.port.write_all(data).map_err(communication_error(NAME))
+    }
+
+    fn wait_ack(
+        &mut self,
+        timeout: Duration,
+        operation: impl FnOnce() -> String,
+    ) -> Result<(), FlashError> {
+        if read_response(&mut self.port, timeout)? {
+            Ok(())
+        } else {
+            Err(FlashError::BootloaderRejected {
+                name: NAME.to_owned(),
+                operation: operation(),
+            })
+        }
+    }
+
+    /// Send `command` with its complement, and wait for it to be accepted.
+    fn command(&mut self, command: u8) -> Result<(), FlashError> {
+        self.write_all(&[command, !command])?;
+        self.wait_ack(TIMEOUT, || format!("the command {command:#04x}"))
+    }
+
+    /// Send the address of a command, with its checksum.
+    fn address(&mut self, address: u64) -> Result<(), FlashError> {
+        let address = u32::try_from(address).map_err(|_| FlashError::BootloaderUnsupported {
+            name: NAME.to_owned(),
+            operation: "addresses above 4 GiB",
+        })?;
+        let mut data = address.to_be_bytes().to_vec();
+        data.push(checksum(&data));
+        self.write_all(&data)?;
+        self.wait_ack(TIMEOUT, || format!("the address {address:#010x}"))
+    }
+
+    /// Erase `pages`, or the whole flash if it is `None`.
+    fn erase_pages(&mut self, pages: Option<&[u32]>) -> Result<(), FlashError> {
+        let mut data = vec![];
+        match (pages, self.extended_erase) {
+            (None, true) => data.extend_from_slice(&[0xFF, 0xFF]),
+            (None, false) => data.push(0xFF),
+            (Some(pages), true) => {
+                data.extend_from_slice(&(pages.len() as u16 - 1).to_be_bytes());
+                for page in pages {
+                    data.extend_from_slice(&(*page as u16).to_be_bytes());
+                }
+            }
+            (Some(pages), false) => {
+                data.push(pages.len() as u8 - 1);
+                data.extend(pages.iter().map(|page| *page as u8));
+            }
+        }
+        // The global erase of the legacy command is complemented, like a command.
+        data.push(match (pages, self.extended_erase) {
+            (None, false) => 0x00,
+            _ => checksum(&data),
+        });
+
+        self.command(if self.extended_erase {
+            EXTENDED_ERASE
+        } else {
+            ERASE
+        })?;
+        self.write_all(&data)?;
+        self.wait_ack(ERASE_TIMEOUT, || match pages {
+            Some(pages) => format!("erasing the pages {pages:?}"),
+            None => "the mass erase".to_owned(),
+        })
+    }
+}
+
+impl<P: Read + Write + Send> Bootloader for Stm32Bootloader<P> {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn erase_all(&mut self) -> Result<(), FlashError> {
+        self.erase_pages(None)
+    }
+
+    fn erase(&mut self, sectors: &[FlashSector]) -> Result<(), FlashError> {
+        let limit = if self.extended_erase { 0xFFF0 } else { 0xFF };
+        let pages = sectors
+            .iter()
+            .map(|sector| sector.index)
+            .collect::<Vec<_>>();
+        if pages.iter().any(|page| *page >= limit) {
+            return Err(FlashError::BootloaderUnsupported {
+                name: NAME.to_owned(),
+                operation: "erasing pages with this index",
+            });
+        }
+        for pages in pages.chunks(MAX_ERASE_PAGES) {
+            self.erase_pages(Some(pages))?;
+        }
+        Ok(())
+    }
+
+    fn program(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<(), FlashError> {
+        self.start = Some(self.start.map_or(address, |start| start.min(address)));
+
+        for (i, chunk) in data.chunks(MAX_TRANSFER).enumerate() {
+            let chunk_address = address + (i * MAX_TRANSFER) as u64;
+            self.command(WRITE_MEMORY)?;
+            self.address(chunk_address)?;
+
+            // The length has to be a multiple of 4 bytes.
+            let mut packet = vec![0xFF; 1 + (chunk.len() + 3) / 4 * 4];
+            packet[0] = (packet.len() - 2) as u8;
+            packet[1..1 + chunk.len()].copy_from_slice(chunk);
+            packet.push(checksum(&packet));
+            self.write_all(&packet)?;
+            self.wait_ack(TIMEOUT, || format!("writing {chunk_address:#010x}"))?;
+
+            progress((i * MAX_TRANSFER + chunk.len()) as u64);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), FlashError> {
+        for (i, chunk) in data.chunks_mut(MAX_TRANSFER).enumerate() {
+            let chunk_address = address + (i * MAX_TRANSFER) as u64;
+            self.command(READ_MEMORY)?;
+            self.address(chunk_address)?;
+            let length = (chunk.len() - 1) as u8;
+            self.write_all(&[length, !length])?;
+            self.wait_ack(TIMEOUT, || format!("reading {chunk_address:#010x}"))?;
+            self.read_exact(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), FlashError> {
+        let Some(start) = self.start else {
+            return Ok(());
+        };
+        // The firmware is started with the stack pointer and the reset vector of its vector table.
+        self.command(GO)?;
+        self.address(start)
+    }
+}
+
+/// Wait for the acknowledgement of a command, returning whether it was accepted.
+fn read_response(port: &mut impl Read, timeout: Duration) -> Result<bool, FlashError> {
+    let start = Instant::now();
+    let mut response = [0];
+    loop {
+        match port.read_exact(&mut response) {
+            Ok(()) => {}
+            Err(error) if error.kind() == ErrorKind::TimedOut && start.elapsed() < timeout => {
+                continue
+            }
+            Err(error) => return Err(communication_error(NAME)(error)),
+        }
+        match response[0] {
+            ACK => return Ok(true),
+            NACK => return Ok(false),
+            byte => tracing::debug!("Ignoring the byte {:#04x} from the bootloader", byte),
+        }
+    }
+}
+
+/// The XOR of all bytes.
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// A serial port which returns the bytes of `input`, and records what is written.
+    #[derive(Default)]
+    struct ScriptedPort {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Read for ScriptedPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for ScriptedPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn data_is_written_in_padded_packets() {
+        let mut port = ScriptedPort::default();
+        // The synchronization, and the response to Get with version 3.1 and the extended erase.
+        port.input
+            .extend([ACK, ACK, 3, 0x31, GET, WRITE_MEMORY, EXTENDED_ERASE, ACK]);
+        // The write command, its address and its data, and the erase of 2 pages.
+        port.input.extend([ACK; 3]);
+        port.input.extend([ACK; 2]);
+
+        let mut bootloader = Stm32Bootloader::new(port).unwrap();
+        assert!(bootloader.extended_erase);
+        bootloader
+            .program(0x0800_0000, &[1, 2, 3, 4, 5, 6], &mut |_| {})
+            .unwrap();
+        bootloader
+            .erase(&[
+                FlashSector {
+                    index: 1,
+                    range: 0x0800_4000..0x0800_8000,
+                },
+                FlashSector {
+                    index: 2,
+                    range: 0x0800_8000..0x0800_C000,
+                },
+            ])
+            .unwrap();
+
+        assert_eq!(
+            bootloader.port.output,
+            [
+                &[SYNC, GET, 0xFF][..],
+                &[WRITE_MEMORY, 0xCE, 0x08, 0x00, 0x00, 0x00, 0x08],
+                &[7, 1, 2, 3, 4, 5, 6, 0xFF, 0xFF, 7 ^ 1 ^ 2 ^ 3 ^ 4 ^ 5 ^ 6],
+                &[
+                    EXTENDED_ERASE,
+                    0xBB,
+                    0x00,
+                    0x01,
+                    0x00,
+                    0x01,
+                    0x00,
+                    0x02,
+                    0x02
+                ],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn rejected_commands_are_errors() {
+        let mut port = ScriptedPort::default();
+        port.input.extend([NACK, ACK, 1, 0x22, GET, ACK, NACK]);
+
+        let mut bootloader = Stm32Bootloader::new(port).unwrap();
+        assert!(matches!(
+            bootloader.read(0x0800_0000, &mut [0; 4]),
+            Err(FlashError::BootloaderRejected { .. })
+        ));
+    }
+}
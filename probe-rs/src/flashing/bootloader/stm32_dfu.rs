@@ -0,0 +1,293 @@
+//! The USB DFU mode of the STM32 system bootloader, with the DfuSe extensions of ST AN3156.
+
+use std::time::Duration;
+
+use rusb::{DeviceHandle, GlobalContext};
+
+use super::{Bootloader, FlashSector};
+use crate::flashing::FlashError;
+
+const NAME: &str = "STM32 DFU bootloader";
+
+const VENDOR_ID: u16 = 0x0483;
+const PRODUCT_ID: u16 = 0xDF11;
+
+const REQUEST_OUT: u8 = 0x21;
+const REQUEST_IN: u8 = 0xA1;
+
+const DFU_DNLOAD: u8 = 1;
+const DFU_UPLOAD: u8 = 2;
+const DFU_GETSTATUS: u8 = 3;
+const DFU_CLRSTATUS: u8 = 4;
+const DFU_ABORT: u8 = 6;
+
+const STATE_IDLE: u8 = 2;
+const STATE_DNBUSY: u8 = 4;
+const STATE_ERROR: u8 = 10;
+
+/// The DfuSe commands, which are downloaded to block 0.
+const SET_ADDRESS: u8 = 0x21;
+const ERASE: u8 = 0x41;
+/// The first block of data, which is written at the address that was set.
+const DATA_BLOCK: u16 = 2;
+
+/// The DFU functional descriptor, with the maximum transfer size.
+const DFU_FUNCTIONAL_DESCRIPTOR: u8 = 0x21;
+const DEFAULT_TRANSFER_SIZE: u16 = 2048;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The STM32 system bootloader in DFU mode, on the USB.
+///
+/// The chip has to be started in the bootloader already, e.g. with its BOOT0 pin high during reset.
+pub struct Stm32Dfu {
+    handle: DeviceHandle<GlobalContext>,
+    interface: u8,
+    transfer_size: u16,
+    /// The lowest address which was programmed, at which [`Bootloader::finish`] starts the firmware.
+    start: Option<u64>,
+}
+
+impl Stm32Dfu {
+    /// Open the first STM32 in DFU mode, and select its internal flash.
+    pub fn open() -> Result<Self, FlashError> {
+        let device = rusb::devices()
+            .map_err(usb_error)?
+            .iter()
+            .find(|device| {
+                device.device_descriptor().map_or(false, |descriptor| {
+                    descriptor.vendor_id() == VENDOR_ID && descriptor.product_id() == PRODUCT_ID
+                })
+            })
+            .ok_or_else(|| FlashError::BootloaderCommunication {
+                name: NAME.to_owned(),
+                source: "No STM32 in DFU mode was found on the USB.".into(),
+            })?;
+        let mut handle = device.open().map_err(usb_error)?;
+
+        // The alternate settings of the DFU interface are the memories, named like
+        // `@Internal Flash  /0x08000000/04*016Kg,01*064Kg,07*128Kg`.
+        let config = device.active_config_descriptor().map_err(usb_error)?;
+        let mut selected = None;
+        for descriptor in config
+            .interfaces()
+            .flat_map(|interface| interface.descriptors())
+            .filter(|descriptor| {
+                descriptor.class_code() == 0xFE && descriptor.sub_class_code() == 1
+            })
+        {
+            let name = descriptor
+                .description_string_index()
+                .and_then(|index| handle.read_string_descriptor_ascii(index).ok())
+                .unwrap_or_default();
+            let transfer_size = transfer_size(descriptor.extra());
+            let is_flash = name.starts_with("@Internal Flash");
+            if selected.is_none() || is_flash {
+                selected = Some((
+                    descriptor.interface_number(),
+                    descriptor.setting_number(),
+                    transfer_size,
+                ));
+            }
+            if is_flash {
+                break;
+            }
+        }
+        let (interface, setting, transfer_size) =
+            selected.ok_or_else(|| FlashError::BootloaderCommunication {
+                name: NAME.to_owned(),
+                source: "The device has no DFU interface.".into(),
+            })?;
+
+        handle.claim_interface(interface).map_err(usb_error)?;
+        handle
+            .set_alternate_setting(interface, setting)
+            .map_err(usb_error)?;
+
+        let dfu = Self {
+            handle,
+            interface,
+            transfer_size,
+            start: None,
+        };
+        dfu.reset_state()?;
+        Ok(dfu)
+    }
+
+    /// The status, the poll timeout and the state of the device.
+    fn status(&self) -> Result<(u8, Duration, u8), FlashError> {
+        let mut status = [0; 6];
+        self.handle
+            .read_control(
+                REQUEST_IN,
+                DFU_GETSTATUS,
+                0,
+                self.interface as u16,
+                &mut status,
+                TIMEOUT,
+            )
+            .map_err(usb_error)?;
+        let poll_timeout = u32::from_le_bytes([status[1], status[2], status[3], 0]);
+        Ok((
+            status[0],
+            Duration::from_millis(poll_timeout as u64),
+            status[4],
+        ))
+    }
+
+    /// Clear an error, and return to the idle state.
+    fn reset_state(&self) -> Result<(), FlashError> {
+        let (_, _, state) = self.status()?;
+        if state == STATE_ERROR {
+            self.request(DFU_CLRSTATUS, 0, &[])?;
+        } else if state != STATE_IDLE {
+            self.request(DFU_ABORT, 0, &[])?;
+        }
+        Ok(())
+    }
+
+    fn request(&self, request: u8, value: u16, data: &[u8]) -> Result<(), FlashError> {
+        self.handle
+            .write_control(
+                REQUEST_OUT,
+                request,
+                value,
+                self.interface as u16,
+                data,
+                TIMEOUT,
+            )
+            .map_err(usb_error)?;
+        Ok(())
+    }
+
+    /// Download `data` to `block`, and wait until the device executed it.
+    fn download(
+        &self,
+        block: u16,
+        data: &[u8],
+        operation: impl Fn() -> String,
+    ) -> Result<(), FlashError> {
+        self.request(DFU_DNLOAD, block, data)?;
+        loop {
+            let (status, poll_timeout, state) = self.status()?;
+            if status != 0 {
+                // Leave the error state for the next command.
+                self.request(DFU_CLRSTATUS, 0, &[])?;
+                return Err(FlashError::BootloaderRejected {
+                    name: NAME.to_owned(),
+                    operation: format!("{} with the status {status}", operation()),
+                });
+            }
+            if state != STATE_DNBUSY {
+                return Ok(());
+            }
+            std::thread::sleep(poll_timeout);
+        }
+    }
+
+    /// Download a DfuSe command with an address.
+    fn command(&self, command: u8, address: Option<u64>) -> Result<(), FlashError> {
+        let mut data = vec![command];
+        if let Some(address) = address {
+            let address =
+                u32::try_from(address).map_err(|_| FlashError::BootloaderUnsupported {
+                    name: NAME.to_owned(),
+                    operation: "addresses above 4 GiB",
+                })?;
+            data.extend_from_slice(&address.to_le_bytes());
+        }
+        self.download(0, &data, || match address {
+            Some(address) => format!("the command {command:#04x} at {address:#010x}"),
+            None => format!("the command {command:#04x}"),
+        })
+    }
+}
+
+impl Bootloader for Stm32Dfu {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn erase_all(&mut self) -> Result<(), FlashError> {
+        self.command(ERASE, None)
+    }
+
+    fn erase(&mut self, sectors: &[FlashSector]) -> Result<(), FlashError> {
+        for sector in sectors {
+            self.command(ERASE, Some(sector.range.start))?;
+        }
+        Ok(())
+    }
+
+    fn program(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<(), FlashError> {
+        self.start = Some(self.start.map_or(address, |start| start.min(address)));
+
+        let transfer_size = self.transfer_size as usize;
+        for (i, chunk) in data.chunks(transfer_size).enumerate() {
+            let chunk_address = address + (i * transfer_size) as u64;
+            self.command(SET_ADDRESS, Some(chunk_address))?;
+            self.download(DATA_BLOCK, chunk, || {
+                format!("writing {chunk_address:#010x}")
+            })?;
+            progress((i * transfer_size + chunk.len()) as u64);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), FlashError> {
+        let transfer_size = self.transfer_size as usize;
+        for (i, chunk) in data.chunks_mut(transfer_size).enumerate() {
+            self.command(SET_ADDRESS, Some(address + (i * transfer_size) as u64))?;
+            // Uploads start in the idle state.
+            self.request(DFU_ABORT, 0, &[])?;
+            self.handle
+                .read_control(
+                    REQUEST_IN,
+                    DFU_UPLOAD,
+                    DATA_BLOCK,
+                    self.interface as u16,
+                    chunk,
+                    TIMEOUT,
+                )
+                .map_err(usb_error)?;
+            self.request(DFU_ABORT, 0, &[])?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), FlashError> {
+        let Some(start) = self.start else {
+            return Ok(());
+        };
+        // A download of no data leaves the bootloader, and jumps to the address which was set.
+        self.command(SET_ADDRESS, Some(start))?;
+        self.request(DFU_DNLOAD, 0, &[])?;
+        // The device resets while answering, so the result does not matter.
+        let _ = self.status();
+        Ok(())
+    }
+}
+
+/// The maximum transfer size of the DFU functional descriptor in the `extra` descriptors of an interface.
+fn transfer_size(mut extra: &[u8]) -> u16 {
+    while extra.len() >= 2 {
+        let length = (extra[0] as usize).max(2).min(extra.len());
+        if extra[1] == DFU_FUNCTIONAL_DESCRIPTOR && length >= 7 {
+            return u16::from_le_bytes([extra[5], extra[6]]);
+        }
+        extra = &extra[length..];
+    }
+    DEFAULT_TRANSFER_SIZE
+}
+
+fn usb_error(error: rusb::Error) -> FlashError {
+    FlashError::BootloaderCommunication {
+        name: NAME.to_owned(),
+        source: Box::new(error),
+    }
+}
@@ -0,0 +1,215 @@
+//! The mass storage of UF2 bootloaders, which flash the UF2 files that are copied to them.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{communication_error, Bootloader, FlashSector};
+use crate::flashing::FlashError;
+
+const NAME: &str = "UF2 bootloader";
+
+const MAGIC_START_0: u32 = 0x0A32_4655;
+const MAGIC_START_1: u32 = 0x9E5D_5157;
+const MAGIC_END: u32 = 0x0AB1_6F30;
+const FLAG_FAMILY_ID: u32 = 0x0000_2000;
+
+const BLOCK_SIZE: usize = 512;
+/// The data of a block, which is aligned to its size.
+const PAYLOAD_SIZE: u64 = 256;
+
+/// The file of a UF2 drive, which describes the bootloader.
+const INFO_FILE: &str = "INFO_UF2.TXT";
+
+/// The mass storage drive of a UF2 bootloader, which is mounted at a directory of the host.
+///
+/// The data is written to the drive as one UF2 file by [`Bootloader::finish`], after which the bootloader programs
+/// it and starts the firmware. It can not be read back, and the bootloader erases the flash itself.
+pub struct Uf2Drive {
+    path: PathBuf,
+    family_id: Option<u32>,
+    /// The payloads of the blocks, by their address.
+    payloads: BTreeMap<u64, [u8; PAYLOAD_SIZE as usize]>,
+}
+
+impl Uf2Drive {
+    /// The drive mounted at `path`, whose bootloader only accepts blocks with `family_id` if it is set.
+    pub fn new(path: impl Into<PathBuf>, family_id: Option<u32>) -> Self {
+        Self {
+            path: path.into(),
+            family_id,
+            payloads: BTreeMap::new(),
+        }
+    }
+
+    /// The directories at which UF2 drives are mounted, which contain an `INFO_UF2.TXT`.
+    pub fn find_drives() -> Vec<PathBuf> {
+        let mut roots = vec![
+            PathBuf::from("/media"),
+            PathBuf::from("/run/media"),
+            PathBuf::from("/Volumes"),
+        ];
+        if let Some(user) = std::env::var_os("USER") {
+            roots.push(Path::new("/media").join(&user));
+            roots.push(Path::new("/run/media").join(&user));
+        }
+
+        let mut candidates = roots
+            .iter()
+            .filter_map(|root| std::fs::read_dir(root).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>();
+        if cfg!(windows) {
+            candidates.extend(
+                (b'D'..=b'Z').map(|letter| PathBuf::from(format!("{}:\\", letter as char))),
+            );
+        }
+
+        candidates
+            .into_iter()
+            .filter(|path| path.join(INFO_FILE).is_file())
+            .collect()
+    }
+
+    /// The blocks of the UF2 file.
+    fn file(&self) -> Vec<u8> {
+        let count = self.payloads.len() as u32;
+        let mut file = Vec::with_capacity(self.payloads.len() * BLOCK_SIZE);
+        for (index, (address, payload)) in self.payloads.iter().enumerate() {
+            let (flags, family_id) = match self.family_id {
+                Some(family_id) => (FLAG_FAMILY_ID, family_id),
+                None => (0, 0),
+            };
+            let mut block = [0; BLOCK_SIZE];
+            for (offset, value) in [
+                MAGIC_START_0,
+                MAGIC_START_1,
+                flags,
+                *address as u32,
+                PAYLOAD_SIZE as u32,
+                index as u32,
+                count,
+                family_id,
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                block[offset * 4..][..4].copy_from_slice(&value.to_le_bytes());
+            }
+            block[32..][..PAYLOAD_SIZE as usize].copy_from_slice(payload);
+            block[BLOCK_SIZE - 4..].copy_from_slice(&MAGIC_END.to_le_bytes());
+            file.extend_from_slice(&block);
+        }
+        file
+    }
+}
+
+impl Bootloader for Uf2Drive {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn erase_all(&mut self) -> Result<(), FlashError> {
+        Ok(())
+    }
+
+    fn erase(&mut self, _sectors: &[FlashSector]) -> Result<(), FlashError> {
+        Ok(())
+    }
+
+    fn program(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<(), FlashError> {
+        if address + data.len() as u64 > u32::MAX as u64 {
+            return Err(FlashError::BootloaderUnsupported {
+                name: NAME.to_owned(),
+                operation: "addresses above 4 GiB",
+            });
+        }
+
+        // The blocks are aligned, and the rest of them is erased.
+        let end = address + data.len() as u64;
+        let mut block = address / PAYLOAD_SIZE * PAYLOAD_SIZE;
+        while block < end {
+            let payload = self
+                .payloads
+                .entry(block)
+                .or_insert([0xFF; PAYLOAD_SIZE as usize]);
+            let start = block.max(address);
+            let stop = (block + PAYLOAD_SIZE).min(end);
+            payload[(start - block) as usize..(stop - block) as usize]
+                .copy_from_slice(&data[(start - address) as usize..(stop - address) as usize]);
+            block += PAYLOAD_SIZE;
+        }
+        // The data is only staged here, and written by `finish`.
+        progress(data.len() as u64);
+        Ok(())
+    }
+
+    fn read(&mut self, _address: u64, _data: &mut [u8]) -> Result<(), FlashError> {
+        Err(FlashError::BootloaderUnsupported {
+            name: NAME.to_owned(),
+            operation: "reading the flash",
+        })
+    }
+
+    fn finish(&mut self) -> Result<(), FlashError> {
+        if self.payloads.is_empty() {
+            return Ok(());
+        }
+        let path = self.path.join("firmware.uf2");
+        tracing::debug!(
+            "Writing {} UF2 blocks to {}",
+            self.payloads.len(),
+            path.display()
+        );
+
+        let mut file = std::fs::File::create(&path).map_err(communication_error(NAME))?;
+        file.write_all(&self.file())
+            .and_then(|()| file.sync_all())
+            .map_err(communication_error(NAME))?;
+        self.payloads.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unaligned_data_is_padded_to_blocks() {
+        let mut drive = Uf2Drive::new("/nonexistent", Some(0xE48B_FF56));
+        drive
+            .program(0x1000_0080, &[0xAB; 300], &mut |_| {})
+            .unwrap();
+
+        let file = drive.file();
+        assert_eq!(file.len(), 2 * BLOCK_SIZE);
+        let word = |block: usize, index: usize| {
+            u32::from_le_bytes(
+                file[block * BLOCK_SIZE + index * 4..][..4]
+                    .try_into()
+                    .unwrap(),
+            )
+        };
+        assert_eq!(word(0, 0), MAGIC_START_0);
+        assert_eq!(word(0, 2), FLAG_FAMILY_ID);
+        assert_eq!(word(0, 3), 0x1000_0000);
+        assert_eq!(word(1, 3), 0x1000_0100);
+        assert_eq!(word(1, 5), 1);
+        assert_eq!(word(1, 6), 2);
+        assert_eq!(word(1, 7), 0xE48B_FF56);
+        assert_eq!(word(1, 127), MAGIC_END);
+
+        // 128 bytes of the first block are erased, and 172 bytes of the last one are data.
+        assert_eq!(file[32..32 + 128], [0xFF; 128]);
+        assert_eq!(file[32 + 128..32 + 256], [0xAB; 128]);
+        assert_eq!(file[BLOCK_SIZE + 32..][..172], [0xAB; 172]);
+        assert_eq!(file[BLOCK_SIZE + 32 + 172..][..84], [0xFF; 84]);
+    }
+}
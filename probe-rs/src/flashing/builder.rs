@@ -213,6 +213,51 @@ impl FlashBuilder {
         Ok(())
     }
 
+    /// Returns the staged data in `range`, or `None` if not all of it is staged.
+    pub(super) fn get(&self, range: Range<u64>) -> Option<Vec<u8>> {
+        let mut result = Vec::with_capacity((range.end - range.start) as usize);
+
+        let mut address = range.start;
+        while address < range.end {
+            let (&start, data) = self.data.range(..=address).next_back()?;
+            let offset = (address - start) as usize;
+            if offset >= data.len() {
+                return None;
+            }
+
+            let len = (data.len() - offset).min((range.end - address) as usize);
+            result.extend_from_slice(&data[offset..offset + len]);
+            address += len as u64;
+        }
+
+        Some(result)
+    }
+
+    /// Overwrites already staged data, and returns `false` without changing anything if not all of it is staged.
+    pub(super) fn overwrite(&mut self, address: u64, bytes: &[u8]) -> bool {
+        let end = address + bytes.len() as u64;
+        if self.get(address..end).is_none() {
+            return false;
+        }
+
+        let mut written = 0;
+        for (&start, data) in self.data.range_mut(..end).rev() {
+            let chunk_end = start + data.len() as u64;
+            if chunk_end <= address {
+                break;
+            }
+
+            let from = start.max(address);
+            let to = chunk_end.min(end);
+            data[(from - start) as usize..(to - start) as usize]
+                .copy_from_slice(&bytes[(from - address) as usize..(to - address) as usize]);
+            written += to - from;
+        }
+
+        debug_assert_eq!(written, bytes.len() as u64);
+        true
+    }
+
     /// Check whether there is staged data for a given address range.
     pub(crate) fn has_data_in_range(&self, range: &Range<u64>) -> bool {
         self.data_in_range(range).next().is_some()
@@ -384,6 +429,7 @@ mod tests {
                 program_page_timeout: 200,
                 erase_sector_timeout: 200,
                 sectors: vec![sd],
+                program_granularity: None,
             },
             ..Default::default()
         };
@@ -393,6 +439,7 @@ mod tests {
             is_boot_memory: true,
             range: 0..1 << 16,
             cores: vec!["main".into()],
+            is_otp: false,
         };
 
         (region, flash_algorithm)
@@ -412,6 +459,7 @@ mod tests {
                 program_page_timeout: 200,
                 erase_sector_timeout: 200,
                 sectors: vec![sd],
+                program_granularity: None,
             },
             ..Default::default()
         };
@@ -421,6 +469,7 @@ mod tests {
             is_boot_memory: true,
             range: 0..1 << 16,
             cores: vec!["main".into()],
+            is_otp: false,
         };
 
         (region, flash_algorithm)
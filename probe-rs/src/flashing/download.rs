@@ -2,12 +2,18 @@ use object::{
     elf::FileHeader32, elf::PT_LOAD, read::elf::FileHeader, read::elf::ProgramHeader, Endianness,
     Object, ObjectSection,
 };
-use probe_rs_target::MemoryRange;
+use probe_rs_target::{MemoryRange, Partition};
 
-use std::{fs::File, path::Path, str::FromStr};
+use std::{
+    fs::File,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use super::*;
 use crate::session::Session;
+use crate::Target;
 
 /// Extended options for flashing a binary file.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -27,8 +33,65 @@ pub struct IdfOptions {
     pub partition_table: Option<esp_idf_part::PartitionTable>,
 }
 
+/// Extended options for flashing an ELF file.
+///
+/// The filters select which parts of the loadable segments are flashed, e.g. to skip a bootloader which is part of
+/// a combined ELF file, or to leave a SoftDevice untouched. Without any filters, all loadable segments are flashed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct ElfOptions {
+    /// The names of the sections to flash. If this is not empty, only these sections are flashed.
+    pub include_sections: Vec<String>,
+    /// The names of the sections which are not flashed.
+    pub exclude_sections: Vec<String>,
+    /// The address ranges to flash. If this is not empty, data outside of them is not flashed.
+    pub include_ranges: Vec<Range<u64>>,
+    /// The address ranges which are not flashed.
+    pub exclude_ranges: Vec<Range<u64>>,
+}
+
+impl ElfOptions {
+    /// Returns true if sections are selected by their names, instead of flashing whole segments.
+    fn filters_sections(&self) -> bool {
+        !self.include_sections.is_empty() || !self.exclude_sections.is_empty()
+    }
+
+    /// Returns true if the section with the name `name` is flashed.
+    fn section_selected(&self, name: &str) -> bool {
+        (self.include_sections.is_empty() || self.include_sections.iter().any(|s| s == name))
+            && !self.exclude_sections.iter().any(|s| s == name)
+    }
+
+    /// The parts of `range` which are flashed, according to the address ranges.
+    fn select_ranges(&self, range: Range<u64>) -> Vec<Range<u64>> {
+        let mut selected: Vec<_> = if self.include_ranges.is_empty() {
+            vec![range]
+        } else {
+            self.include_ranges
+                .iter()
+                .map(|include| range.start.max(include.start)..range.end.min(include.end))
+                .collect()
+        };
+        selected.retain(|selected| !selected.is_empty());
+
+        for exclude in &self.exclude_ranges {
+            selected = selected
+                .into_iter()
+                .flat_map(|selected| {
+                    [
+                        selected.start..selected.end.min(exclude.start),
+                        selected.start.max(exclude.end)..selected.end,
+                    ]
+                })
+                .filter(|selected| !selected.is_empty())
+                .collect();
+        }
+
+        selected
+    }
+}
+
 /// A finite list of all the available binary formats probe-rs understands.
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum Format {
     /// Marks a file in binary format. This means that the file contains the contents of the flash 1:1.
     /// [BinOptions] can be used to define the location in flash where the file contents should be put at.
@@ -37,13 +100,19 @@ pub enum Format {
     /// Marks a file in [Intel HEX](https://en.wikipedia.org/wiki/Intel_HEX) format.
     Hex,
     /// Marks a file in the [ELF](https://en.wikipedia.org/wiki/Executable_and_Linkable_Format) format.
-    #[default]
-    Elf,
+    /// Use [ElfOptions] to select the sections and address ranges which are flashed.
+    Elf(ElfOptions),
     /// Marks a file in the [ESP-IDF bootloader](https://docs.espressif.com/projects/esp-idf/en/latest/esp32/api-reference/system/app_image_format.html#app-image-structures) format.
     /// Use [IdfOptions] to configure flashing.
     Idf(IdfOptions),
 }
 
+impl Default for Format {
+    fn default() -> Self {
+        Format::Elf(ElfOptions::default())
+    }
+}
+
 impl FromStr for Format {
     type Err = String;
 
@@ -55,7 +124,7 @@ impl FromStr for Format {
             })),
             "idf" | "esp-idf" => Ok(Format::Idf(Default::default())),
             "hex" | "ihex" | "intelhex" => Ok(Format::Hex),
-            "elf" => Ok(Format::Elf),
+            "elf" => Ok(Format::Elf(Default::default())),
             _ => Err(format!("Format '{s}' is unknown.")),
         }
     }
@@ -95,6 +164,25 @@ pub enum FileDownloadError {
     /// This is most likely because of a bad linker script.
     #[error("No loadable ELF sections were found.")]
     NoLoadableSegments,
+    /// An image which is flashed together with other images contains data for the same addresses as one of them.
+    #[error("The image {image:?} contains data for the addresses {addresses:#010x?}, which overlap the data of another image at {existing:#010x?}.")]
+    ImageOverlaps {
+        /// The path of the image.
+        image: PathBuf,
+        /// The address range of the data of the image.
+        addresses: Range<u64>,
+        /// The address range of the data of the other images.
+        existing: Range<u64>,
+    },
+}
+
+/// An image which is flashed together with the main file, e.g. a Nordic SoftDevice next to the application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdditionalImage {
+    /// The path of the image.
+    pub path: PathBuf,
+    /// The format of the image.
+    pub format: Format,
 }
 
 /// Options for downloading a file onto a target chip.
@@ -135,6 +223,23 @@ pub struct DownloadOptions {
     pub verify: bool,
     /// Disable double buffering when loading flash.
     pub disable_double_buffering: bool,
+    /// Patches which are applied to the image before flashing it, e.g. to fill in a checksum or a serial number.
+    pub patches: Vec<ImagePatch>,
+    /// Allow writing to one-time programmable (OTP) regions of the memory map. If the image contains data
+    /// for such a region and this is not set, flashing fails before anything is written.
+    pub allow_otp_write: bool,
+    /// RAM ranges whose contents are kept, e.g. a region which is not initialized by the program and holds crash logs.
+    /// The flash algorithm is loaded outside of them, if it fits. Use a [`RamSnapshot`](super::RamSnapshot) to keep
+    /// them across a reset.
+    pub keep_ram: Vec<Range<u64>>,
+    /// Host-side loaders for address ranges which are not programmed by a flash algorithm, e.g. external flash.
+    pub programmers: Vec<Box<dyn FlashProgrammer>>,
+    /// The name of a partition of the target to flash into. A binary without a base address is placed at its start,
+    /// and flashing fails if the image contains data outside of it.
+    pub partition: Option<String>,
+    /// Images which are flashed together with the file, e.g. a Nordic SoftDevice next to the application. Their data
+    /// must not overlap, and they are erased and programmed in one go, with a combined progress.
+    pub additional_images: Vec<AdditionalImage>,
 }
 
 impl DownloadOptions {
@@ -165,26 +270,78 @@ pub fn download_file<P: AsRef<Path>>(
 pub fn download_file_with_options<P: AsRef<Path>>(
     session: &mut Session,
     path: P,
-    format: Format,
+    mut format: Format,
     options: DownloadOptions,
 ) -> Result<(), FileDownloadError> {
-    let mut file = match File::open(path.as_ref()) {
-        Ok(file) => file,
-        Err(e) => return Err(FileDownloadError::IO(e)),
+    let partition = match &options.partition {
+        Some(name) => Some(find_partition(session.target(), name)?.clone()),
+        None => None,
     };
+    if let (Some(partition), Format::Bin(bin_options)) = (&partition, &mut format) {
+        bin_options
+            .base_address
+            .get_or_insert(partition.range.start);
+    }
 
     let mut loader = session.target().flash_loader();
+    for programmer in &options.programmers {
+        loader.add_programmer_range(programmer.range());
+    }
+
+    load_file(session, &mut loader, path.as_ref(), format)?;
+
+    loader
+        .apply_patches(&options.patches)
+        .map_err(FileDownloadError::Flash)?;
+    if let Some(partition) = &partition {
+        loader.check_partition(partition)?;
+    }
+
+    for image in &options.additional_images {
+        let mut image_loader = session.target().flash_loader();
+        load_file(
+            session,
+            &mut image_loader,
+            &image.path,
+            image.format.clone(),
+        )?;
+        loader.add_image(&image.path, &image_loader)?;
+    }
+
+    loader
+        .commit(session, options)
+        .map_err(FileDownloadError::Flash)
+}
+
+/// Loads the file of given `format` at `path` into `loader`.
+fn load_file(
+    session: &mut Session,
+    loader: &mut FlashLoader,
+    path: &Path,
+    format: Format,
+) -> Result<(), FileDownloadError> {
+    let mut file = File::open(path)?;
 
     match format {
         Format::Bin(options) => loader.load_bin_data(&mut file, options),
-        Format::Elf => loader.load_elf_data(&mut file),
+        Format::Elf(options) => loader.load_elf_data(&mut file, &options),
         Format::Hex => loader.load_hex_data(&mut file),
         Format::Idf(options) => loader.load_idf_data(session, &mut file, options),
-    }?;
+    }
+}
 
-    loader
-        .commit(session, options)
-        .map_err(FileDownloadError::Flash)
+/// The partition of `target` with the name `name`.
+pub fn find_partition<'t>(target: &'t Target, name: &str) -> Result<&'t Partition, FlashError> {
+    target
+        .partition(name)
+        .ok_or_else(|| FlashError::PartitionNotFound {
+            name: name.to_owned(),
+            available: target
+                .partitions
+                .iter()
+                .map(|partition| partition.name.clone())
+                .collect(),
+        })
 }
 
 /// Flash data which was extraced from an ELF file.
@@ -212,9 +369,16 @@ impl std::fmt::Debug for ExtractedFlashData<'_> {
     }
 }
 
+/// The entry point of an ELF file.
+pub(super) fn elf_entry_point(elf_data: &[u8]) -> Result<u64, FileDownloadError> {
+    let elf_header = FileHeader32::<Endianness>::parse(elf_data)?;
+    Ok(elf_header.e_entry(elf_header.endian()?).into())
+}
+
 pub(super) fn extract_from_elf<'data>(
     extracted_data: &mut Vec<ExtractedFlashData<'data>>,
     elf_data: &'data [u8],
+    options: &ElfOptions,
 ) -> Result<usize, FileDownloadError> {
     let file_kind = object::FileKind::parse(elf_data)?;
 
@@ -244,6 +408,7 @@ pub(super) fn extract_from_elf<'data>(
             .map_err(|_| FileDownloadError::Object("Failed to access data for an ELF segment."))?;
 
         let mut elf_section = Vec::new();
+        let mut selected_sections = Vec::new();
 
         if !segment_data.is_empty() && segment.p_type(endian) == PT_LOAD {
             tracing::info!(
@@ -279,23 +444,48 @@ pub(super) fn extract_from_elf<'data>(
                         );
                     }
 
-                    elf_section.push(section.name()?.to_owned());
+                    let name = section.name()?;
+                    if options.section_selected(name) {
+                        selected_sections.push((
+                            vec![name.to_owned()],
+                            section_offset - segment_offset
+                                ..section_offset - segment_offset + section_filesize,
+                        ));
+                    } else {
+                        tracing::info!("Skipping section {:?}", name);
+                    }
+
+                    elf_section.push(name.to_owned());
                 }
             }
 
             if elf_section.is_empty() {
                 tracing::info!("Not adding segment, no matching sections found.");
             } else {
-                let section_data =
+                let segment_data =
                     &elf_data[segment_offset as usize..][..segment_filesize as usize];
 
-                extracted_data.push(ExtractedFlashData {
-                    section_names: elf_section,
-                    address: p_paddr as u32,
-                    data: section_data,
-                });
+                // The parts of the segment which are flashed, as offsets into the segment.
+                let parts = if options.filters_sections() {
+                    selected_sections
+                } else {
+                    vec![(elf_section, 0..segment_filesize)]
+                };
 
-                extracted_sections += 1;
+                for (section_names, part) in parts {
+                    for selected in options.select_ranges(p_paddr + part.start..p_paddr + part.end)
+                    {
+                        let offset = (selected.start - p_paddr) as usize;
+                        extracted_data.push(ExtractedFlashData {
+                            section_names: section_names.clone(),
+                            address: selected.start as u32,
+                            data: &segment_data[offset..]
+                                [..(selected.end - selected.start) as usize],
+                        });
+
+                        extracted_sections += 1;
+                    }
+                }
             }
         }
     }
@@ -307,7 +497,42 @@ pub(super) fn extract_from_elf<'data>(
 mod tests {
     use std::str::FromStr;
 
-    use super::{BinOptions, Format};
+    use super::{BinOptions, ElfOptions, Format};
+
+    #[test]
+    fn select_elf_ranges() {
+        let options = ElfOptions::default();
+        assert_eq!(options.select_ranges(0x1000..0x2000), [(0x1000..0x2000)]);
+
+        let options = ElfOptions {
+            include_ranges: vec![0x0..0x1800, 0x1C00..0x3000],
+            exclude_ranges: vec![(0x1100..0x1200)],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.select_ranges(0x1000..0x2000),
+            [0x1000..0x1100, 0x1200..0x1800, 0x1C00..0x2000]
+        );
+        assert!(options.select_ranges(0x1800..0x1C00).is_empty());
+    }
+
+    #[test]
+    fn select_elf_sections() {
+        let options = ElfOptions {
+            exclude_sections: vec![".bootloader".to_owned()],
+            ..Default::default()
+        };
+        assert!(options.filters_sections());
+        assert!(options.section_selected(".text"));
+        assert!(!options.section_selected(".bootloader"));
+
+        let options = ElfOptions {
+            include_sections: vec![".text".to_owned(), ".rodata".to_owned()],
+            ..Default::default()
+        };
+        assert!(options.section_selected(".rodata"));
+        assert!(!options.section_selected(".data"));
+    }
 
     #[test]
     fn parse_format() {
@@ -348,8 +573,14 @@ mod tests {
                 skip: 0
             }))
         );
-        assert_eq!(Format::from_str("Elf"), Ok(Format::Elf));
-        assert_eq!(Format::from_str("elf"), Ok(Format::Elf));
+        assert_eq!(
+            Format::from_str("Elf"),
+            Ok(Format::Elf(ElfOptions::default()))
+        );
+        assert_eq!(
+            Format::from_str("elf"),
+            Ok(Format::Elf(ElfOptions::default()))
+        );
         assert_eq!(
             Format::from_str("elfbin"),
             Err("Format 'elfbin' is unknown.".to_string())
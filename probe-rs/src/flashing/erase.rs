@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
-use probe_rs_target::{MemoryRange, MemoryRegion, NvmRegion};
+use probe_rs_target::{MemoryRange, MemoryRegion, NvmRegion, SectorInfo};
 
-use crate::flashing::{flasher::Flasher, FlashError, FlashLoader};
-use crate::Session;
+use crate::flashing::{
+    flasher::{assemble_algorithm, Flasher},
+    FlashError, FlashLoader,
+};
+use crate::{Session, Target};
 
 use super::FlashProgress;
 
@@ -50,7 +54,7 @@ pub fn erase_all(session: &mut Session, progress: Option<FlashProgress>) -> Resu
         let algo = algo.unwrap().clone();
 
         let core_index = session.target().core_index_by_name(&core_name).unwrap();
-        let mut flasher = Flasher::new(session, core_index, &algo, progress.clone())?;
+        let mut flasher = Flasher::new(session, core_index, &algo, &[], progress.clone())?;
 
         if flasher.is_chip_erase_supported() {
             tracing::debug!("     -- chip erase supported, doing it.");
@@ -136,7 +140,7 @@ pub fn erase_sectors(
         let algo = algo.unwrap().clone();
 
         let core_index = session.target().core_index_by_name(&core_name).unwrap();
-        let mut flasher = Flasher::new(session, core_index, &algo, progress.clone())?;
+        let mut flasher = Flasher::new(session, core_index, &algo, &[], progress.clone())?;
 
         let sectors = flasher
             .flash_algorithm()
@@ -166,3 +170,149 @@ pub fn erase_sectors(
 
     Ok(())
 }
+
+/// The sectors which are erased with one flash algorithm, see [`sectors_in_range`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EraseSectors {
+    /// The name of the flash algorithm which erases the sectors.
+    pub algorithm: String,
+    /// The name of the core which runs the flash algorithm.
+    pub core: String,
+    /// The sectors, ordered by their address.
+    pub sectors: Vec<SectorInfo>,
+}
+
+/// Find the sectors which have to be erased to erase `range`. The range is rounded out to the boundaries of the
+/// sectors it touches, so more than `range` is erased if it does not start and end at sector boundaries.
+///
+/// The whole range has to be flash which is not one-time programmable.
+pub fn sectors_in_range(
+    target: &Target,
+    range: Range<u64>,
+) -> Result<Vec<EraseSectors>, FlashError> {
+    let regions = target
+        .memory_map
+        .iter()
+        .filter_map(|region| match region {
+            MemoryRegion::Nvm(region)
+                if !region.is_otp && region.range.intersects_range(&range) =>
+            {
+                Some(region)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // The regions don't overlap, so they cover the range if their parts in it add up to its size.
+    let covered = regions
+        .iter()
+        .map(|region| region.range.end.min(range.end) - region.range.start.max(range.start))
+        .sum::<u64>();
+    if range.is_empty() || covered != range.end - range.start {
+        return Err(FlashError::NoSuitableNvm {
+            start: range.start,
+            end: range.end,
+            description_source: target.source().clone(),
+        });
+    }
+
+    let mut groups: Vec<EraseSectors> = Vec::new();
+    for region in regions {
+        let raw = FlashLoader::get_flash_algorithm_for_region(region, target)?;
+        let core = region
+            .cores
+            .first()
+            .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?;
+        let core_index = target.core_index_by_name(core).unwrap_or(0);
+        let algorithm = assemble_algorithm(target, core_index, raw, &[])?;
+
+        let sectors = algorithm.iter_sectors().filter(|sector| {
+            let sector_range = sector.base_address..sector.base_address + sector.size;
+            sector_range.intersects_range(&range) && sector_range.intersects_range(&region.range)
+        });
+
+        match groups
+            .iter_mut()
+            .find(|group| group.algorithm == raw.name && &group.core == core)
+        {
+            Some(group) => group.sectors.extend(sectors),
+            None => groups.push(EraseSectors {
+                algorithm: raw.name.clone(),
+                core: core.clone(),
+                sectors: sectors.collect(),
+            }),
+        }
+    }
+
+    for group in &mut groups {
+        group.sectors.sort_by_key(|sector| sector.base_address);
+        group.sectors.dedup();
+    }
+
+    Ok(groups)
+}
+
+/// Erase the sectors which contain `range`, see [`sectors_in_range`].
+pub fn erase_range(
+    session: &mut Session,
+    progress: Option<FlashProgress>,
+    range: Range<u64>,
+) -> Result<(), FlashError> {
+    tracing::debug!("Erasing {:08x}-{:08x}", range.start, range.end);
+
+    for group in sectors_in_range(session.target(), range)? {
+        tracing::debug!("Erasing with algorithm: {}", group.algorithm);
+
+        // This can't fail, the names come from the target.
+        let algo = session
+            .target()
+            .flash_algorithm_by_name(&group.algorithm)
+            .unwrap()
+            .clone();
+        let core_index = session.target().core_index_by_name(&group.core).unwrap();
+
+        let mut flasher = Flasher::new(session, core_index, &algo, &[], progress.clone())?;
+        flasher.run_erase(|active| {
+            for info in &group.sectors {
+                tracing::debug!(
+                    "    sector: {:08x}-{:08x} ({} bytes)",
+                    info.base_address,
+                    info.base_address + info.size,
+                    info.size
+                );
+
+                active.erase_sector(info.base_address)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::sectors_in_range;
+
+    #[test]
+    fn range_is_rounded_to_sectors() {
+        let target = crate::config::get_target_by_name("nRF52840_xxAA").unwrap();
+
+        let groups = sectors_in_range(&target, 0x1800..0x2100).unwrap();
+        assert_eq!(groups.len(), 1);
+        let sectors = groups[0]
+            .sectors
+            .iter()
+            .map(|sector| (sector.base_address, sector.size))
+            .collect::<Vec<_>>();
+        assert_eq!(sectors, [(0x1000, 0x1000), (0x2000, 0x1000)]);
+    }
+
+    #[test]
+    fn range_outside_of_flash() {
+        let target = crate::config::get_target_by_name("nRF52840_xxAA").unwrap();
+
+        assert!(sectors_in_range(&target, 0xF_F000..0x10_1000).is_err());
+        assert!(sectors_in_range(&target, 0x2000_0000..0x2000_1000).is_err());
+    }
+}
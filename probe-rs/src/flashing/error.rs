@@ -120,6 +120,32 @@ pub enum FlashError {
     /// Flash content verification failed.
     #[error("Flash content verification failed.")]
     Verify,
+    /// A flash algorithm which was selected by its name does not exist.
+    #[error("The target has no flash algorithm named '{name}'.")]
+    FlashAlgorithmNotFound {
+        /// The name of the flash algorithm.
+        name: String,
+    },
+    /// The flash is not erased after it was erased.
+    #[error("The byte at {address:#010x} is {value:#04x} after erasing, instead of {erased_byte_value:#04x}.")]
+    NotErased {
+        /// The address of the byte.
+        address: u64,
+        /// The value of the byte.
+        value: u8,
+        /// The value of erased bytes.
+        erased_byte_value: u8,
+    },
+    /// The flash does not contain the data which was programmed.
+    #[error("The byte at {address:#010x} is {value:#04x} after programming, instead of {expected:#04x}.")]
+    UnexpectedData {
+        /// The address of the byte.
+        address: u64,
+        /// The value of the byte.
+        value: u8,
+        /// The value which was programmed.
+        expected: u8,
+    },
     // TODO: 1 Add source of target definition
     // TOOD: 2 Do this at target load time.
     /// The given chip has no RAM defined.
@@ -154,7 +180,147 @@ pub enum FlashError {
     /// No core can access this RAM region.
     #[error("No core can access the ram region {0:?}.")]
     NoRamCoreAccess(RamRegion),
+    /// An image patch reads data which is not part of the image.
+    #[error("The address range {range:08X?} used by an image patch is not completely part of the image.")]
+    ImagePatchOutsideImage {
+        /// The address range which is read by the patch.
+        range: Range<u64>,
+    },
+    /// The image contains data for a one-time programmable region, but writing it was not allowed.
+    #[error("The image contains data for the one-time programmable region {range:08X?}, but writing OTP memory was not allowed.")]
+    OtpWriteNotAllowed {
+        /// The address range of the OTP region.
+        range: Range<u64>,
+    },
+    /// The target has no partition with the name.
+    #[error("The target has no partition named `{name}`. Its partitions are {available:?}.")]
+    PartitionNotFound {
+        /// The name of the partition.
+        name: String,
+        /// The names of the partitions of the target.
+        available: Vec<String>,
+    },
+    /// The image contains data outside of the partition it is flashed into.
+    #[error("The image contains data at {range:#010x?}, outside of the partition `{name}`.")]
+    OutsidePartition {
+        /// The name of the partition.
+        name: String,
+        /// The address range of the data.
+        range: Range<u64>,
+    },
+    /// An image patch is not valid.
+    #[error("Invalid image patch: {0}")]
+    InvalidImagePatch(String),
     /// The register value supplied for this flash algorithm is out of the supported range.
     #[error("The register value {0:08X?} is out of the supported range.")]
     RegisterValueNotSupported(u64),
+    /// The pages or sectors of the flash algorithm are not aligned to the program granularity of the flash.
+    #[error("The pages and sectors of the flash algorithm {name} are not aligned to the {granularity} byte words of the flash.")]
+    InvalidProgramGranularity {
+        /// The name of the flash algorithm.
+        name: String,
+        /// The size of the flash words in bytes.
+        granularity: u32,
+    },
+    /// A flash word which would be programmed without erasing it first is not erased.
+    #[error("The {granularity} byte flash word at {address:#010x} is not erased. A flash word can only be programmed once, so its sector has to be erased before it is programmed again.")]
+    FlashWordNotErased {
+        /// The address of the flash word.
+        address: u64,
+        /// The size of the flash words in bytes.
+        granularity: u32,
+    },
+    /// The range of a [`FlashProgrammer`](super::FlashProgrammer) contains only a part of an NVM region.
+    #[error("The range {range:#010x?} of the programmer {name} contains only a part of the flash region {region:#010x?}.")]
+    ProgrammerOverlapsRegion {
+        /// The name of the programmer.
+        name: String,
+        /// The range of the programmer.
+        range: Range<u64>,
+        /// The range of the NVM region.
+        region: Range<u64>,
+    },
+    /// The SPI NOR flash did not finish a command in time.
+    #[error("The SPI NOR flash did not finish the command {opcode:#04x} in time.")]
+    SpiNorTimeout {
+        /// The instruction of the command.
+        opcode: u8,
+    },
+    /// The SPI NOR flash has no SFDP, and its JEDEC ID is not known.
+    #[error("No SPI NOR flash was found, its JEDEC ID is {0:02x?}.")]
+    SpiNorNotFound([u8; 3]),
+    /// The data is not in the SPI NOR flash.
+    #[error("The range {range:#010x?} is outside of the SPI NOR flash at {flash:#010x?}.")]
+    OutsideOfSpiNorFlash {
+        /// The range of the data.
+        range: Range<u64>,
+        /// The range of the flash.
+        flash: Range<u64>,
+    },
+    /// Erasing or programming a block of a block device failed.
+    #[error("The block {block} of the block device went bad.")]
+    BadBlock {
+        /// The index of the block.
+        block: u64,
+    },
+    /// The data does not fit into the good blocks of a block device.
+    #[error("The data does not fit into the good blocks of {name}.")]
+    BlockDeviceFull {
+        /// The name of the block device.
+        name: String,
+    },
+    /// A page of a NAND flash has more bit errors than its ECC corrects.
+    #[error("The page {page} of the NAND flash has an uncorrectable ECC error.")]
+    NandEccUncorrectable {
+        /// The index of the page.
+        page: u32,
+    },
+    /// A command to an eMMC or SD card failed.
+    #[error("The command CMD{index} to the card failed with the status {status:#010x}.")]
+    MmcCommandFailed {
+        /// The index of the command.
+        index: u8,
+        /// The card status, or the error interrupt status of the controller.
+        status: u32,
+    },
+    /// An eMMC or SD card did not finish a command in time.
+    #[error("The card did not finish the command CMD{index} in time.")]
+    MmcTimeout {
+        /// The index of the command.
+        index: u8,
+    },
+    /// Communicating with a ROM or serial bootloader failed.
+    #[error("Communicating with the bootloader {name} failed.")]
+    BootloaderCommunication {
+        /// The name of the bootloader.
+        name: String,
+        /// The source error of this error.
+        #[source]
+        source: Box<dyn std::error::Error + 'static + Send + Sync>,
+    },
+    /// A bootloader rejected a command.
+    #[error("The bootloader {name} rejected {operation}.")]
+    BootloaderRejected {
+        /// The name of the bootloader.
+        name: String,
+        /// The rejected operation.
+        operation: String,
+    },
+    /// A bootloader does not support an operation.
+    #[error("The bootloader {name} does not support {operation}.")]
+    BootloaderUnsupported {
+        /// The name of the bootloader.
+        name: String,
+        /// The unsupported operation.
+        operation: &'static str,
+    },
+    /// Reading flash with ECC failed, which is usually an uncorrectable ECC error.
+    #[error("Reading the flash at {address:#010x} failed. This is usually an uncorrectable ECC error of a flash word which was programmed twice, or whose programming was interrupted. Erasing its sector clears the error.")]
+    EccError {
+        /// The address of the failed read.
+        address: u64,
+        /// The source error of this error.
+        #[source]
+        source: error::Error,
+    },
 }
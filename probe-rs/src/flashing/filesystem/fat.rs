@@ -0,0 +1,380 @@
+//! FAT12, FAT16 and FAT32 filesystems, with long file names.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::{FileEntry, FileSystem, FileSystemError, Storage};
+use crate::Session;
+
+/// The partition types of FAT volumes in a partition table.
+const FAT_PARTITION_TYPES: [u8; 6] = [0x01, 0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+/// A mounted FAT filesystem, at the start of a [`Storage`] or in its first partition.
+///
+/// Names are compared case-insensitively. The changes are only written to the flash by [`FileSystem::flush`] or
+/// [`Fat::unmount`].
+pub struct Fat<S> {
+    storage: S,
+    /// The offset of the volume in the storage.
+    volume: u64,
+}
+
+impl<S: Storage> Fat<S> {
+    /// Mount the filesystem in `storage`.
+    pub fn mount(session: &mut Session, mut storage: S) -> Result<Self, FileSystemError> {
+        let mut boot_sector = [0; 512];
+        storage.read(session, 0, &mut boot_sector)?;
+        if boot_sector[510..] != [0x55, 0xAA] {
+            return Err(FileSystemError::NoFileSystem);
+        }
+
+        // A boot sector starts with a jump instruction, a partition table with code or zeros.
+        let volume = if matches!(boot_sector[0], 0xEB | 0xE9) {
+            0
+        } else {
+            let partition = &boot_sector[0x1BE..0x1CE];
+            if !FAT_PARTITION_TYPES.contains(&partition[4]) {
+                return Err(FileSystemError::NoFileSystem);
+            }
+            u32::from_le_bytes([partition[8], partition[9], partition[10], partition[11]]) as u64
+                * 512
+        };
+
+        let mut fat = Self { storage, volume };
+        let fat_type = fat.with_fs(session, "/", |fs| Ok(fs.fat_type()))?;
+        tracing::debug!("Mounted {:?} at offset {:#x}", fat_type, volume);
+        Ok(fat)
+    }
+
+    /// Format the whole `storage` with a FAT filesystem, and mount it.
+    pub fn format(session: &mut Session, mut storage: S) -> Result<Self, FileSystemError> {
+        let disk = Disk {
+            session: &mut *session,
+            storage: &mut storage,
+            volume: 0,
+            position: 0,
+        };
+        fatfs::format_volume(disk, fatfs::FormatVolumeOptions::new())
+            .map_err(|error| fat_error(error, "/"))?;
+        Self::mount(session, storage)
+    }
+
+    /// Write the changes to the flash, and return the storage.
+    pub fn unmount(mut self, session: &mut Session) -> Result<S, FileSystemError> {
+        self.flush(session)?;
+        Ok(self.storage)
+    }
+
+    /// Run `f` on the filesystem, which is loaded from the storage for every operation. Errors which are not from
+    /// the storage refer to `path`.
+    fn with_fs<T>(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+        f: impl FnOnce(&fatfs::FileSystem<Disk<'_, S>>) -> io::Result<T>,
+    ) -> Result<T, FileSystemError> {
+        let disk = Disk {
+            session,
+            storage: &mut self.storage,
+            volume: self.volume,
+            position: 0,
+        };
+        let options = fatfs::FsOptions::new().time_provider(&UtcTimeProvider);
+        let fs = fatfs::FileSystem::new(disk, options).map_err(|error| fat_error(error, path))?;
+        let result = f(&fs);
+        // Updates the free cluster count of FAT32, and clears the dirty flag.
+        let unmounted = fs.unmount();
+        let value = result.map_err(|error| fat_error(error, path))?;
+        unmounted.map_err(|error| fat_error(error, path))?;
+        Ok(value)
+    }
+}
+
+impl<S: Storage> FileSystem for Fat<S> {
+    fn list(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+    ) -> Result<Vec<FileEntry>, FileSystemError> {
+        self.with_fs(session, path, |fs| {
+            let directory = match path.trim_matches('/') {
+                "" => fs.root_dir(),
+                path => fs.root_dir().open_dir(path)?,
+            };
+            let mut entries = Vec::new();
+            for entry in directory.iter() {
+                let entry = entry?;
+                let name = entry.file_name();
+                if name != "." && name != ".." {
+                    entries.push(FileEntry {
+                        name,
+                        is_directory: entry.is_dir(),
+                        size: entry.len(),
+                    });
+                }
+            }
+            Ok(entries)
+        })
+    }
+
+    fn read_file(&mut self, session: &mut Session, path: &str) -> Result<Vec<u8>, FileSystemError> {
+        self.with_fs(session, path, |fs| {
+            let mut data = Vec::new();
+            fs.root_dir().open_file(path)?.read_to_end(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    fn write_file(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), FileSystemError> {
+        self.with_fs(session, path, |fs| {
+            let mut file = fs.root_dir().create_file(path)?;
+            file.truncate()?;
+            file.write_all(data)?;
+            file.flush()
+        })
+    }
+
+    fn remove_file(&mut self, session: &mut Session, path: &str) -> Result<(), FileSystemError> {
+        self.with_fs(session, path, |fs| {
+            // Fails for directories.
+            fs.root_dir().open_file(path)?;
+            fs.root_dir().remove(path)
+        })
+    }
+
+    fn create_directory(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+    ) -> Result<(), FileSystemError> {
+        self.with_fs(session, path, |fs| {
+            // An existing directory would be opened.
+            if fs.root_dir().open_dir(path).is_ok() || fs.root_dir().open_file(path).is_ok() {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            fs.root_dir().create_dir(path)?;
+            Ok(())
+        })
+    }
+
+    fn flush(&mut self, session: &mut Session) -> Result<(), FileSystemError> {
+        self.storage.flush(session)
+    }
+}
+
+/// The error of the filesystem, or of the storage, which is passed through `fatfs` as the inner error.
+fn fat_error(error: io::Error, path: &str) -> FileSystemError {
+    match error.kind() {
+        io::ErrorKind::NotFound => FileSystemError::NotFound(path.to_owned()),
+        io::ErrorKind::AlreadyExists => FileSystemError::AlreadyExists(path.to_owned()),
+        kind => match error
+            .into_inner()
+            .map(|inner| inner.downcast::<FileSystemError>())
+        {
+            Some(Ok(error)) => *error,
+            Some(Err(inner)) => FileSystemError::Fat(io::Error::new(kind, inner)),
+            None => FileSystemError::Fat(kind.into()),
+        },
+    }
+}
+
+/// The volume in a [`Storage`], as the disk of `fatfs`.
+struct Disk<'a, S> {
+    session: &'a mut Session,
+    storage: &'a mut S,
+    volume: u64,
+    /// The position in the volume.
+    position: u64,
+}
+
+impl<S: Storage> Disk<'_, S> {
+    /// The number of bytes from the position to the end of the storage, up to `length`.
+    fn available(&self, length: usize) -> usize {
+        let end = self.storage.size().saturating_sub(self.volume);
+        end.saturating_sub(self.position).min(length as u64) as usize
+    }
+}
+
+impl<S: Storage> Read for Disk<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = self.available(buf.len());
+        self.storage
+            .read(
+                self.session,
+                self.volume + self.position,
+                &mut buf[..length],
+            )
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        self.position += length as u64;
+        Ok(length)
+    }
+}
+
+impl<S: Storage> Write for Disk<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = self.available(buf.len());
+        self.storage
+            .write(self.session, self.volume + self.position, &buf[..length])
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        self.position += length as u64;
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // The storage is flushed by the filesystem.
+        Ok(())
+    }
+}
+
+impl<S: Storage> Seek for Disk<'_, S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => {
+                let size = self.storage.size().saturating_sub(self.volume);
+                size.checked_add_signed(offset)
+            }
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative offset")
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Timestamps files in UTC, as the local time zone of the target is not known.
+#[derive(Debug)]
+struct UtcTimeProvider;
+
+impl fatfs::TimeProvider for UtcTimeProvider {
+    fn get_current_date(&self) -> fatfs::Date {
+        self.get_current_date_time().date
+    }
+
+    fn get_current_date_time(&self) -> fatfs::DateTime {
+        let now = time::OffsetDateTime::now_utc();
+        fatfs::DateTime {
+            date: fatfs::Date {
+                // The earliest date of FAT.
+                year: now.year().clamp(1980, 2107) as u16,
+                month: u8::from(now.month()) as u16,
+                day: now.day() as u16,
+            },
+            time: fatfs::Time {
+                hour: now.hour() as u16,
+                min: now.minute() as u16,
+                sec: now.second() as u16,
+                millis: now.millisecond(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::flashing::filesystem::test::{session, MemoryStorage};
+
+    /// A FAT12 volume of 128 sectors of 512 bytes, with 2 FATs of 1 sector and a root directory of 16 entries, which
+    /// contains `HELLO.TXT` in cluster 2.
+    fn fat12_image() -> MemoryStorage {
+        let mut image = vec![0; 128 * 512];
+        image[..62].copy_from_slice(&[
+            0xEB, 0x3C, 0x90, b'M', b'S', b'D', b'O', b'S', b'5', b'.', b'0', 0x00, 0x02, 0x01,
+            0x01, 0x00, 0x02, 0x10, 0x00, 0x80, 0x00, 0xF8, 0x01, 0x00, 0x20, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x29, 0x78, 0x56, 0x34,
+            0x12, b'N', b'O', b' ', b'N', b'A', b'M', b'E', b' ', b' ', b' ', b' ', b'F', b'A',
+            b'T', b'1', b'2', b' ', b' ', b' ',
+        ]);
+        image[510..512].copy_from_slice(&[0x55, 0xAA]);
+        for fat in [512, 1024] {
+            // The media descriptor, the reserved entry, and the end of the chain of cluster 2.
+            image[fat..fat + 5].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF, 0x0F]);
+        }
+        let root = 3 * 512;
+        image[root..root + 11].copy_from_slice(b"HELLO   TXT");
+        // The archive attribute.
+        image[root + 11] = 0x20;
+        image[root + 26] = 2;
+        image[root + 28] = 6;
+        let data = 4 * 512;
+        image[data..data + 6].copy_from_slice(b"hello\n");
+        MemoryStorage(image)
+    }
+
+    #[test]
+    fn files_of_an_existing_volume_are_read() {
+        let mut session = session();
+        let mut fat = Fat::mount(&mut session, fat12_image()).unwrap();
+
+        assert_eq!(
+            fat.list(&mut session, "/").unwrap(),
+            [FileEntry {
+                name: "HELLO.TXT".to_owned(),
+                is_directory: false,
+                size: 6,
+            }]
+        );
+        assert_eq!(
+            fat.read_file(&mut session, "hello.txt").unwrap(),
+            b"hello\n"
+        );
+        assert!(matches!(
+            fat.read_file(&mut session, "missing.txt"),
+            Err(FileSystemError::NotFound(_))
+        ));
+        assert!(matches!(
+            Fat::mount(&mut session, MemoryStorage(vec![0xFF; 512])),
+            Err(FileSystemError::NoFileSystem)
+        ));
+    }
+
+    #[test]
+    fn files_are_written_with_long_names() {
+        let mut session = session();
+        let mut fat = Fat::format(&mut session, MemoryStorage(vec![0xFF; 256 * 512])).unwrap();
+
+        fat.create_directory(&mut session, "assets").unwrap();
+        assert!(matches!(
+            fat.create_directory(&mut session, "assets"),
+            Err(FileSystemError::AlreadyExists(_))
+        ));
+        let large = (0..2000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        fat.write_file(&mut session, "assets/Background Image.png", &large)
+            .unwrap();
+        fat.write_file(&mut session, "assets/config.json", b"{}")
+            .unwrap();
+        fat.write_file(&mut session, "hello.txt", b"hello").unwrap();
+        fat.write_file(&mut session, "hello.txt", b"bye").unwrap();
+        fat.remove_file(&mut session, "assets/config.json").unwrap();
+        assert!(fat.remove_file(&mut session, "assets").is_err());
+
+        let storage = fat.unmount(&mut session).unwrap();
+        let mut fat = Fat::mount(&mut session, storage).unwrap();
+
+        let files = fat
+            .list(&mut session, "assets")
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.size))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            files,
+            HashMap::from([("Background Image.png".to_owned(), 2000)])
+        );
+        assert_eq!(
+            fat.read_file(&mut session, "/ASSETS/background image.png")
+                .unwrap(),
+            large
+        );
+        assert_eq!(fat.read_file(&mut session, "HELLO.TXT").unwrap(), b"bye");
+    }
+}
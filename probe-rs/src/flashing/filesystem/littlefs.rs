@@ -0,0 +1,485 @@
+//! LittleFS filesystems.
+//!
+//! The storage of `littlefs2` has a geometry which is known at compile time, but the block size and the size of a
+//! filesystem in the flash are only known at runtime. So the filesystem is accessed with the bindings of `littlefs2`
+//! and a configuration which is created at runtime, and the block count is read from the superblock when mounting.
+
+use std::ffi::{c_int, c_void, CString};
+
+use littlefs2::ll;
+
+use super::{FileEntry, FileSystem, FileSystemError, Storage};
+use crate::Session;
+
+/// The size of reads and writes, which the storage does not restrict.
+const IO_SIZE: u32 = 16;
+
+/// The size of the lookahead buffer, in 64-bit words, which tracks the free blocks.
+const LOOKAHEAD_WORDS: usize = 4;
+
+/// A mounted LittleFS filesystem in a [`Storage`].
+///
+/// The changes are only written to the flash by [`FileSystem::flush`] or [`LittleFs::unmount`].
+pub struct LittleFs<S> {
+    storage: S,
+    block_size: u32,
+}
+
+impl<S: Storage> LittleFs<S> {
+    /// Mount the filesystem in `storage`, which was formatted with blocks of `block_size` bytes, usually the erase
+    /// size of the flash.
+    pub fn mount(
+        session: &mut Session,
+        storage: S,
+        block_size: u32,
+    ) -> Result<Self, FileSystemError> {
+        let mut littlefs = Self::new(storage, block_size)?;
+        littlefs
+            .with_fs(session, "/", 0, |_| Ok(()))
+            .map_err(|error| match error {
+                FileSystemError::Corrupted(_) => FileSystemError::NoFileSystem,
+                error => error,
+            })?;
+        Ok(littlefs)
+    }
+
+    /// Format the whole `storage` with blocks of `block_size` bytes, and mount it.
+    pub fn format(
+        session: &mut Session,
+        storage: S,
+        block_size: u32,
+    ) -> Result<Self, FileSystemError> {
+        let mut littlefs = Self::new(storage, block_size)?;
+        let block_count = (littlefs.storage.size() / block_size as u64) as u32;
+        littlefs.with_fs(session, "/", block_count, |_| Ok(()))?;
+        Ok(littlefs)
+    }
+
+    /// Write the changes to the flash, and return the storage.
+    pub fn unmount(mut self, session: &mut Session) -> Result<S, FileSystemError> {
+        self.flush(session)?;
+        Ok(self.storage)
+    }
+
+    fn new(storage: S, block_size: u32) -> Result<Self, FileSystemError> {
+        // The caches have the size of a block, which has to be a multiple of the size of reads and writes.
+        if block_size < 128 || block_size % IO_SIZE != 0 {
+            return Err(FileSystemError::InvalidBlockSize(block_size));
+        }
+        Ok(Self {
+            storage,
+            block_size,
+        })
+    }
+
+    /// Mount the filesystem for the operation `f`. With a `block_count`, the storage is formatted first. Errors which
+    /// are not from the storage refer to `path`.
+    fn with_fs<T>(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+        block_count: u32,
+        f: impl FnOnce(&mut Mounted<'_>) -> Result<T, c_int>,
+    ) -> Result<T, FileSystemError> {
+        let mut context = Context {
+            session,
+            storage: &mut self.storage,
+            error: None,
+        };
+        let mut read_buffer = vec![0u8; self.block_size as usize];
+        let mut prog_buffer = vec![0u8; self.block_size as usize];
+        let mut lookahead_buffer = [0u64; LOOKAHEAD_WORDS];
+        let config = ll::lfs_config {
+            context: (&mut context as *mut Context<'_, S>).cast(),
+            read: Some(read::<S>),
+            prog: Some(prog::<S>),
+            erase: Some(erase::<S>),
+            sync: Some(sync),
+            read_size: IO_SIZE,
+            prog_size: IO_SIZE,
+            block_size: self.block_size,
+            // Zero when mounting, to read it from the superblock.
+            block_count,
+            block_cycles: -1,
+            cache_size: self.block_size,
+            lookahead_size: (LOOKAHEAD_WORDS * 8) as u32,
+            compact_thresh: 0,
+            read_buffer: read_buffer.as_mut_ptr().cast(),
+            prog_buffer: prog_buffer.as_mut_ptr().cast(),
+            lookahead_buffer: lookahead_buffer.as_mut_ptr().cast(),
+            // The defaults, or the values of the superblock.
+            name_max: 0,
+            file_max: 0,
+            attr_max: 0,
+            metadata_max: 0,
+            inline_max: 0,
+            disk_version: 0,
+        };
+
+        // The state refers to the configuration, so neither of them is moved until it is unmounted.
+        let mut state: ll::lfs_t = unsafe { std::mem::zeroed() };
+        let mut result = check(match block_count {
+            0 => 0,
+            _ => unsafe { ll::lfs_format(&mut state, &config) },
+        })
+        .and_then(|_| check(unsafe { ll::lfs_mount(&mut state, &config) }));
+        let value = match result {
+            Ok(_) => {
+                let value = f(&mut Mounted {
+                    state: &mut state,
+                    cache_size: self.block_size as usize,
+                });
+                result = check(unsafe { ll::lfs_unmount(&mut state) });
+                value
+            }
+            Err(code) => Err(code),
+        };
+
+        // An error of the storage is returned by LittleFS as an I/O error.
+        if let Some(error) = context.error.take() {
+            return Err(error);
+        }
+        let value = value.map_err(|code| littlefs_error(code, path))?;
+        result.map_err(|code| littlefs_error(code, path))?;
+        Ok(value)
+    }
+}
+
+impl<S: Storage> FileSystem for LittleFs<S> {
+    fn list(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+    ) -> Result<Vec<FileEntry>, FileSystemError> {
+        let c_path = c_path(path)?;
+        self.with_fs(session, path, 0, |fs| fs.list(&c_path))
+    }
+
+    fn read_file(&mut self, session: &mut Session, path: &str) -> Result<Vec<u8>, FileSystemError> {
+        let c_path = c_path(path)?;
+        self.with_fs(session, path, 0, |fs| {
+            let flags = ll::lfs_open_flags_LFS_O_RDONLY;
+            fs.with_file(&c_path, flags, |fs, file| {
+                let size = check(unsafe { ll::lfs_file_size(fs.state, file) })?;
+                let mut data = vec![0u8; size as usize];
+                let read = check(unsafe {
+                    ll::lfs_file_read(fs.state, file, data.as_mut_ptr().cast(), size as u32)
+                })?;
+                data.truncate(read as usize);
+                Ok(data)
+            })
+        })
+    }
+
+    fn write_file(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), FileSystemError> {
+        let c_path = c_path(path)?;
+        self.with_fs(session, path, 0, |fs| {
+            let flags = ll::lfs_open_flags_LFS_O_WRONLY
+                | ll::lfs_open_flags_LFS_O_CREAT
+                | ll::lfs_open_flags_LFS_O_TRUNC;
+            fs.with_file(&c_path, flags, |fs, file| {
+                check(unsafe {
+                    ll::lfs_file_write(fs.state, file, data.as_ptr().cast(), data.len() as u32)
+                })?;
+                Ok(())
+            })
+        })
+    }
+
+    fn remove_file(&mut self, session: &mut Session, path: &str) -> Result<(), FileSystemError> {
+        let c_path = c_path(path)?;
+        self.with_fs(session, path, 0, |fs| {
+            let mut info: ll::lfs_info = unsafe { std::mem::zeroed() };
+            check(unsafe { ll::lfs_stat(fs.state, c_path.as_ptr(), &mut info) })?;
+            if info.type_ as ll::lfs_type == ll::lfs_type_LFS_TYPE_DIR {
+                return Err(ll::lfs_error_LFS_ERR_ISDIR);
+            }
+            check(unsafe { ll::lfs_remove(fs.state, c_path.as_ptr()) })?;
+            Ok(())
+        })
+    }
+
+    fn create_directory(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+    ) -> Result<(), FileSystemError> {
+        let c_path = c_path(path)?;
+        self.with_fs(session, path, 0, |fs| {
+            check(unsafe { ll::lfs_mkdir(fs.state, c_path.as_ptr()) })?;
+            Ok(())
+        })
+    }
+
+    fn flush(&mut self, session: &mut Session) -> Result<(), FileSystemError> {
+        self.storage.flush(session)
+    }
+}
+
+/// A mounted filesystem, for the duration of an operation.
+struct Mounted<'a> {
+    state: &'a mut ll::lfs_t,
+    cache_size: usize,
+}
+
+impl Mounted<'_> {
+    fn list(&mut self, path: &CString) -> Result<Vec<FileEntry>, c_int> {
+        let mut directory: ll::lfs_dir_t = unsafe { std::mem::zeroed() };
+        check(unsafe { ll::lfs_dir_open(self.state, &mut directory, path.as_ptr()) })?;
+
+        let mut entries = Vec::new();
+        let result = loop {
+            let mut info: ll::lfs_info = unsafe { std::mem::zeroed() };
+            match check(unsafe { ll::lfs_dir_read(self.state, &mut directory, &mut info) }) {
+                Ok(0) => break Ok(entries),
+                Ok(_) => {
+                    let name = info
+                        .name
+                        .iter()
+                        .take_while(|c| **c != 0)
+                        .map(|c| *c as u8)
+                        .collect::<Vec<_>>();
+                    let name = String::from_utf8_lossy(&name).into_owned();
+                    if name != "." && name != ".." {
+                        entries.push(FileEntry {
+                            name,
+                            is_directory: info.type_ as ll::lfs_type == ll::lfs_type_LFS_TYPE_DIR,
+                            size: info.size as u64,
+                        });
+                    }
+                }
+                Err(code) => break Err(code),
+            }
+        };
+
+        let closed = check(unsafe { ll::lfs_dir_close(self.state, &mut directory) });
+        let entries = result?;
+        closed?;
+        Ok(entries)
+    }
+
+    /// Open the file at `path` for `f`, and close it.
+    fn with_file<T>(
+        &mut self,
+        path: &CString,
+        flags: ll::lfs_open_flags,
+        f: impl FnOnce(&mut Self, &mut ll::lfs_file_t) -> Result<T, c_int>,
+    ) -> Result<T, c_int> {
+        let mut buffer = vec![0u8; self.cache_size];
+        let config = ll::lfs_file_config {
+            buffer: buffer.as_mut_ptr().cast(),
+            attrs: std::ptr::null_mut(),
+            attr_count: 0,
+        };
+        let mut file: ll::lfs_file_t = unsafe { std::mem::zeroed() };
+        check(unsafe {
+            ll::lfs_file_opencfg(
+                self.state,
+                &mut file,
+                path.as_ptr(),
+                flags as c_int,
+                &config,
+            )
+        })?;
+
+        let result = f(self, &mut file);
+        // Writes the data of the file.
+        let closed = check(unsafe { ll::lfs_file_close(self.state, &mut file) });
+        let value = result?;
+        closed?;
+        Ok(value)
+    }
+}
+
+/// The session and the storage, for the callbacks of LittleFS.
+struct Context<'a, S> {
+    session: &'a mut Session,
+    storage: &'a mut S,
+    /// The first error of the storage.
+    error: Option<FileSystemError>,
+}
+
+impl<S: Storage> Context<'_, S> {
+    /// The context of `config`.
+    ///
+    /// # Safety
+    ///
+    /// `config` has to be the configuration of [`LittleFs::with_fs`], while it is running.
+    unsafe fn of<'a>(config: *const ll::lfs_config) -> (&'a mut Self, u64) {
+        let config = &*config;
+        (
+            &mut *config.context.cast::<Self>(),
+            config.block_size as u64,
+        )
+    }
+
+    /// Access the storage with `f`, and keep its error.
+    fn access(
+        &mut self,
+        f: impl FnOnce(&mut Session, &mut S) -> Result<(), FileSystemError>,
+    ) -> c_int {
+        match f(self.session, self.storage) {
+            Ok(()) => ll::lfs_error_LFS_ERR_OK,
+            Err(error) => {
+                self.error.get_or_insert(error);
+                ll::lfs_error_LFS_ERR_IO
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn read<S: Storage>(
+    config: *const ll::lfs_config,
+    block: ll::lfs_block_t,
+    offset: ll::lfs_off_t,
+    buffer: *mut c_void,
+    size: ll::lfs_size_t,
+) -> c_int {
+    let (context, block_size) = Context::<S>::of(config);
+    let data = std::slice::from_raw_parts_mut(buffer.cast::<u8>(), size as usize);
+    context.access(|session, storage| {
+        storage.read(session, block as u64 * block_size + offset as u64, data)
+    })
+}
+
+unsafe extern "C" fn prog<S: Storage>(
+    config: *const ll::lfs_config,
+    block: ll::lfs_block_t,
+    offset: ll::lfs_off_t,
+    buffer: *const c_void,
+    size: ll::lfs_size_t,
+) -> c_int {
+    let (context, block_size) = Context::<S>::of(config);
+    let data = std::slice::from_raw_parts(buffer.cast::<u8>(), size as usize);
+    context.access(|session, storage| {
+        storage.write(session, block as u64 * block_size + offset as u64, data)
+    })
+}
+
+unsafe extern "C" fn erase<S: Storage>(
+    config: *const ll::lfs_config,
+    block: ll::lfs_block_t,
+) -> c_int {
+    let (context, block_size) = Context::<S>::of(config);
+    context.access(|session, storage| {
+        // Like erased NOR flash. The storage erases the flash when it is written.
+        storage.write(
+            session,
+            block as u64 * block_size,
+            &vec![0xFF; block_size as usize],
+        )
+    })
+}
+
+unsafe extern "C" fn sync(_config: *const ll::lfs_config) -> c_int {
+    // The storage is flushed by the filesystem.
+    ll::lfs_error_LFS_ERR_OK
+}
+
+/// The result of a function of LittleFS, which returns negative error codes.
+fn check(code: c_int) -> Result<c_int, c_int> {
+    if code < 0 {
+        Err(code)
+    } else {
+        Ok(code)
+    }
+}
+
+/// The path as a C string.
+fn c_path(path: &str) -> Result<CString, FileSystemError> {
+    CString::new(path).map_err(|_| FileSystemError::InvalidName(path.to_owned()))
+}
+
+/// The error of a LittleFS error code.
+fn littlefs_error(code: c_int, path: &str) -> FileSystemError {
+    match code {
+        ll::lfs_error_LFS_ERR_NOENT => FileSystemError::NotFound(path.to_owned()),
+        ll::lfs_error_LFS_ERR_EXIST => FileSystemError::AlreadyExists(path.to_owned()),
+        ll::lfs_error_LFS_ERR_ISDIR => FileSystemError::IsADirectory(path.to_owned()),
+        ll::lfs_error_LFS_ERR_NOTDIR => FileSystemError::NotADirectory(path.to_owned()),
+        ll::lfs_error_LFS_ERR_NAMETOOLONG => FileSystemError::InvalidName(path.to_owned()),
+        ll::lfs_error_LFS_ERR_NOSPC => FileSystemError::Full,
+        ll::lfs_error_LFS_ERR_CORRUPT => {
+            FileSystemError::Corrupted("no valid metadata pair was found")
+        }
+        code => FileSystemError::LittleFs(code),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::flashing::filesystem::test::{session, MemoryStorage};
+
+    #[test]
+    fn files_are_written_and_read() {
+        let mut session = session();
+        assert!(matches!(
+            LittleFs::mount(&mut session, MemoryStorage(vec![0xFF; 32 * 512]), 512),
+            Err(FileSystemError::NoFileSystem)
+        ));
+
+        let storage = MemoryStorage(vec![0xFF; 32 * 512]);
+        let mut littlefs = LittleFs::format(&mut session, storage, 512).unwrap();
+        littlefs.create_directory(&mut session, "assets").unwrap();
+        let large = (0..2000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        littlefs
+            .write_file(&mut session, "assets/background.png", &large)
+            .unwrap();
+        littlefs
+            .write_file(&mut session, "/config.json", b"{}")
+            .unwrap();
+        littlefs
+            .write_file(&mut session, "/config.json", b"[]")
+            .unwrap();
+        littlefs
+            .write_file(&mut session, "hello.txt", b"hello")
+            .unwrap();
+        littlefs.remove_file(&mut session, "hello.txt").unwrap();
+        assert!(matches!(
+            littlefs.remove_file(&mut session, "assets"),
+            Err(FileSystemError::IsADirectory(_))
+        ));
+
+        let storage = littlefs.unmount(&mut session).unwrap();
+        let mut littlefs = LittleFs::mount(&mut session, storage, 512).unwrap();
+
+        let mut entries = littlefs.list(&mut session, "/").unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            entries,
+            [
+                FileEntry {
+                    name: "assets".to_owned(),
+                    is_directory: true,
+                    size: 0,
+                },
+                FileEntry {
+                    name: "config.json".to_owned(),
+                    is_directory: false,
+                    size: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            littlefs
+                .read_file(&mut session, "assets/background.png")
+                .unwrap(),
+            large
+        );
+        assert_eq!(
+            littlefs.read_file(&mut session, "config.json").unwrap(),
+            b"[]"
+        );
+        assert!(matches!(
+            littlefs.read_file(&mut session, "hello.txt"),
+            Err(FileSystemError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,361 @@
+//! Reading and writing the files of a filesystem in the flash of the target.
+//!
+//! A [`Fat`] or [`LittleFs`] filesystem is mounted on a [`Storage`], which is usually a [`FlashStorage`] of a flash
+//! region: the internal flash of the target, or an external flash which is programmed by a [`FlashProgrammer`].
+//! Single files are updated, so the rest of the filesystem is not flashed again. The storage caches what was written
+//! until it is flushed, so a file is written to the flash at once.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use super::{DownloadOptions, FlashError, FlashProgrammer};
+use crate::{MemoryInterface, Session};
+
+#[cfg(feature = "filesystem")]
+mod fat;
+#[cfg(feature = "filesystem")]
+mod littlefs;
+
+#[cfg(feature = "filesystem")]
+pub use fat::Fat;
+#[cfg(feature = "filesystem")]
+pub use littlefs::LittleFs;
+
+/// An error while accessing a filesystem.
+#[derive(Debug, thiserror::Error)]
+pub enum FileSystemError {
+    /// Writing the flash failed.
+    #[error("Writing the flash failed")]
+    Flash(#[from] FlashError),
+    /// Reading the flash failed.
+    #[error("Reading the flash failed")]
+    Core(#[from] crate::Error),
+    /// The storage does not contain a filesystem of the expected kind.
+    #[error("The storage does not contain a filesystem.")]
+    NoFileSystem,
+    /// The data structures of the filesystem are invalid.
+    #[error("The filesystem is corrupted: {0}.")]
+    Corrupted(&'static str),
+    /// The file or directory does not exist.
+    #[error("{0} does not exist.")]
+    NotFound(String),
+    /// The file or directory exists already.
+    #[error("{0} exists already.")]
+    AlreadyExists(String),
+    /// The path is a directory, not a file.
+    #[error("{0} is a directory.")]
+    IsADirectory(String),
+    /// The path is a file, not a directory.
+    #[error("{0} is not a directory.")]
+    NotADirectory(String),
+    /// The name can not be stored in the filesystem.
+    #[error("{0:?} is not a valid file name.")]
+    InvalidName(String),
+    /// There is no free space left for the data, or for the entry of a file.
+    #[error("The filesystem is full.")]
+    Full,
+    /// The block size of a LittleFS filesystem is not supported.
+    #[error("The block size {0} is not supported, it has to be a multiple of 16 bytes and at least 128 bytes.")]
+    InvalidBlockSize(u32),
+    /// Another error of the FAT filesystem.
+    #[error("Accessing the FAT filesystem failed")]
+    Fat(#[source] std::io::Error),
+    /// Another error of the LittleFS filesystem, with its error code.
+    #[error("Accessing the LittleFS filesystem failed with error code {0}.")]
+    LittleFs(i32),
+}
+
+/// A file or directory of a [`FileSystem`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    /// The name of the entry.
+    pub name: String,
+    /// Whether the entry is a directory.
+    pub is_directory: bool,
+    /// The size of a file in bytes.
+    pub size: u64,
+}
+
+/// A mounted filesystem. Paths are separated by `/`, and the changes are only written to the flash by
+/// [`FileSystem::flush`].
+pub trait FileSystem {
+    /// List the entries of the directory at `path`, without `.` and `..`.
+    fn list(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+    ) -> Result<Vec<FileEntry>, FileSystemError>;
+
+    /// Read the file at `path`.
+    fn read_file(&mut self, session: &mut Session, path: &str) -> Result<Vec<u8>, FileSystemError>;
+
+    /// Write the file at `path`, replacing it if it exists. Its directory has to exist.
+    fn write_file(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), FileSystemError>;
+
+    /// Remove the file at `path`.
+    fn remove_file(&mut self, session: &mut Session, path: &str) -> Result<(), FileSystemError>;
+
+    /// Create the directory at `path`. Its parent directory has to exist.
+    fn create_directory(
+        &mut self,
+        session: &mut Session,
+        path: &str,
+    ) -> Result<(), FileSystemError>;
+
+    /// Write the changes to the flash.
+    fn flush(&mut self, session: &mut Session) -> Result<(), FileSystemError>;
+}
+
+/// The memory which contains a filesystem.
+pub trait Storage {
+    /// The size of the storage in bytes.
+    fn size(&self) -> u64;
+
+    /// Read `data` at `offset` into the storage.
+    fn read(
+        &mut self,
+        session: &mut Session,
+        offset: u64,
+        data: &mut [u8],
+    ) -> Result<(), FileSystemError>;
+
+    /// Write `data` at `offset` into the storage. The data may be cached until [`Self::flush`].
+    fn write(
+        &mut self,
+        session: &mut Session,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), FileSystemError>;
+
+    /// Write the cached data to the memory.
+    fn flush(&mut self, session: &mut Session) -> Result<(), FileSystemError>;
+}
+
+enum Backend {
+    Nvm { core: usize },
+    Programmer(Box<dyn FlashProgrammer>),
+}
+
+/// A [`Storage`] in a flash region of the target, which is cached in units of the erase size of the flash.
+pub struct FlashStorage {
+    range: Range<u64>,
+    unit_size: u64,
+    backend: Backend,
+    /// The units which were read, and whether they were modified.
+    units: BTreeMap<u64, (Vec<u8>, bool)>,
+}
+
+impl FlashStorage {
+    /// The `range` of the internal flash, which is read through the core with index `core`, and written with the flash
+    /// algorithms of the target.
+    pub fn nvm(range: Range<u64>, core: usize, unit_size: u32) -> Self {
+        Self::new(range, unit_size, Backend::Nvm { core })
+    }
+
+    /// The `range` of the flash of `programmer`. With `unit_size` a multiple of the erase size of the flash, the
+    /// units are written as a whole, so no other data is erased.
+    pub fn programmer(
+        programmer: Box<dyn FlashProgrammer>,
+        range: Range<u64>,
+        unit_size: u32,
+    ) -> Self {
+        Self::new(range, unit_size, Backend::Programmer(programmer))
+    }
+
+    fn new(range: Range<u64>, unit_size: u32, backend: Backend) -> Self {
+        Self {
+            range,
+            unit_size: unit_size.max(1) as u64,
+            backend,
+            units: BTreeMap::new(),
+        }
+    }
+
+    /// The cached unit at `index`, which is read first.
+    fn unit(
+        &mut self,
+        session: &mut Session,
+        index: u64,
+    ) -> Result<&mut (Vec<u8>, bool), FileSystemError> {
+        match self.units.entry(index) {
+            Entry::Occupied(unit) => Ok(unit.into_mut()),
+            Entry::Vacant(unit) => {
+                let address = self.range.start + index * self.unit_size;
+                let length = self.unit_size.min(self.range.end - address);
+                let mut data = vec![0; length as usize];
+                match &mut self.backend {
+                    Backend::Nvm { core } => session.core(*core)?.read(address, &mut data)?,
+                    Backend::Programmer(programmer) => {
+                        programmer.read(session, address, &mut data)?
+                    }
+                }
+                Ok(unit.insert((data, false)))
+            }
+        }
+    }
+
+    /// Visit the parts of the units which contain `length` bytes at `offset`, with the offset into the unit and the
+    /// offset into the data.
+    fn for_each_unit(
+        &mut self,
+        session: &mut Session,
+        offset: u64,
+        length: usize,
+        mut f: impl FnMut(&mut (Vec<u8>, bool), Range<usize>, Range<usize>),
+    ) -> Result<(), FileSystemError> {
+        if offset + length as u64 > self.size() {
+            return Err(FileSystemError::Corrupted(
+                "an access is outside of the storage",
+            ));
+        }
+
+        let mut position = offset;
+        let end = offset + length as u64;
+        while position < end {
+            let index = position / self.unit_size;
+            let start = (position % self.unit_size) as usize;
+            let count = ((self.unit_size - start as u64).min(end - position)) as usize;
+            let data_start = (position - offset) as usize;
+            f(
+                self.unit(session, index)?,
+                start..start + count,
+                data_start..data_start + count,
+            );
+            position += count as u64;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FlashStorage {
+    fn size(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    fn read(
+        &mut self,
+        session: &mut Session,
+        offset: u64,
+        data: &mut [u8],
+    ) -> Result<(), FileSystemError> {
+        self.for_each_unit(
+            session,
+            offset,
+            data.len(),
+            |(unit, _), unit_range, data_range| {
+                data[data_range].copy_from_slice(&unit[unit_range]);
+            },
+        )
+    }
+
+    fn write(
+        &mut self,
+        session: &mut Session,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), FileSystemError> {
+        self.for_each_unit(
+            session,
+            offset,
+            data.len(),
+            |(unit, dirty), unit_range, data_range| {
+                if unit[unit_range.clone()] != data[data_range.clone()] {
+                    unit[unit_range].copy_from_slice(&data[data_range]);
+                    *dirty = true;
+                }
+            },
+        )
+    }
+
+    fn flush(&mut self, session: &mut Session) -> Result<(), FileSystemError> {
+        let dirty = self
+            .units
+            .iter()
+            .filter(|(_, (_, dirty))| *dirty)
+            .map(|(index, (data, _))| (self.range.start + index * self.unit_size, data))
+            .collect::<Vec<_>>();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        tracing::debug!(
+            "Writing {} units of the filesystem to the flash",
+            dirty.len()
+        );
+
+        match &mut self.backend {
+            Backend::Nvm { .. } => {
+                let mut loader = session.target().flash_loader();
+                for (address, data) in dirty {
+                    loader.add_data(address, data)?;
+                }
+                let options = DownloadOptions {
+                    keep_unwritten_bytes: true,
+                    ..Default::default()
+                };
+                loader.commit(session, options)?;
+            }
+            Backend::Programmer(programmer) => {
+                for (address, data) in dirty {
+                    programmer.program(session, address, data, true)?;
+                }
+            }
+        }
+
+        for (_, dirty) in self.units.values_mut() {
+            *dirty = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "filesystem"))]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::{FakeProbe, Permissions, Probe};
+
+    /// A [`Storage`] in memory, which is written immediately.
+    pub(super) struct MemoryStorage(pub(super) Vec<u8>);
+
+    impl Storage for MemoryStorage {
+        fn size(&self) -> u64 {
+            self.0.len() as u64
+        }
+
+        fn read(
+            &mut self,
+            _session: &mut Session,
+            offset: u64,
+            data: &mut [u8],
+        ) -> Result<(), FileSystemError> {
+            data.copy_from_slice(&self.0[offset as usize..][..data.len()]);
+            Ok(())
+        }
+
+        fn write(
+            &mut self,
+            _session: &mut Session,
+            offset: u64,
+            data: &[u8],
+        ) -> Result<(), FileSystemError> {
+            self.0[offset as usize..][..data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn flush(&mut self, _session: &mut Session) -> Result<(), FileSystemError> {
+            Ok(())
+        }
+    }
+
+    pub(super) fn session() -> Session {
+        Probe::from_specific_probe(Box::new(FakeProbe::new()))
+            .attach("stm32wb55ccux", Permissions::default())
+            .unwrap()
+    }
+}
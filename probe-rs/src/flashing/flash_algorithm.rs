@@ -165,6 +165,12 @@ impl FlashAlgorithm {
     // Header for RISCV Flash Algorithms
     const RISCV_FLASH_BLOB_HEADER: [u32; 2] = [riscv::assembly::EBREAK, riscv::assembly::EBREAK];
 
+    /// The offset of the CRC routine in the header of ARM flash algorithms, see [`Self::ARM_FLASH_BLOB_HEADER`].
+    pub(super) const ARM_CRC_ROUTINE_OFFSET: u64 = 2;
+
+    // Header for ARM Flash Algorithms: a breakpoint which the algorithm functions return to, followed by a
+    // CRC-32/MPEG-2 routine. The routine takes the initial CRC in r0, the address and length of the data in r1 and
+    // r2, the polynomial in r3, and returns the CRC in r0.
     const ARM_FLASH_BLOB_HEADER: [u32; 8] = [
         0xE00A_BE00,
         0x062D_780D,
@@ -173,7 +179,7 @@ impl FlashAlgorithm {
         0x1E64_4058,
         0x1C49_D1FA,
         0x2A00_1E52,
-        0x0477_0D1F,
+        0x4770_D1F2,
     ];
 
     fn get_algorithm_header(architecture: Architecture) -> &'static [u32] {
@@ -198,6 +204,23 @@ impl FlashAlgorithm {
             });
         }
 
+        if let Some(granularity) = raw.flash_properties.program_granularity {
+            let aligned = |value: u64| granularity != 0 && value % granularity as u64 == 0;
+            if !aligned(raw.flash_properties.address_range.start)
+                || !aligned(raw.flash_properties.page_size as u64)
+                || !raw
+                    .flash_properties
+                    .sectors
+                    .iter()
+                    .all(|sector| aligned(sector.address) && aligned(sector.size))
+            {
+                return Err(FlashError::InvalidProgramGranularity {
+                    name: raw.name.clone(),
+                    granularity,
+                });
+            }
+        }
+
         let assembled_instructions = raw.instructions.chunks_exact(size_of::<u32>());
 
         if !assembled_instructions.remainder().is_empty() {
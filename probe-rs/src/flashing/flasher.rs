@@ -1,12 +1,14 @@
 use probe_rs_target::{MemoryRegion, RawFlashAlgorithm};
 use tracing::Level;
 
+use super::kept_ram::ram_for_algorithm;
 use super::{
     FlashAlgorithm, FlashBuilder, FlashError, FlashFill, FlashLayout, FlashPage, FlashProgress,
 };
 use crate::config::NvmRegion;
 use crate::memory::MemoryInterface;
-use crate::{core::CoreRegisters, session::Session, Core, InstructionSet};
+use crate::{core::CoreRegisters, session::Session, Core, InstructionSet, Target};
+use std::ops::Range;
 use std::time::Instant;
 use std::{fmt::Debug, time::Duration};
 
@@ -61,38 +63,11 @@ impl<'session> Flasher<'session> {
         session: &'session mut Session,
         core_index: usize,
         raw_flash_algorithm: &RawFlashAlgorithm,
+        keep_ram: &[Range<u64>],
         progress: Option<FlashProgress>,
     ) -> Result<Self, FlashError> {
-        let target = session.target();
-
-        // Find a RAM region from which we can run the algo.
-        let mm = &target.memory_map;
-        let core_name = &target.cores[core_index].name;
-        let ram = mm
-            .iter()
-            .filter_map(|mm| match mm {
-                MemoryRegion::Ram(ram) => Some(ram),
-                _ => None,
-            })
-            .find(|ram| {
-                if let Some(load_addr) = raw_flash_algorithm.load_address {
-                    // The RAM must contain the forced load address _and_
-                    // be accessible from the core we're going to run the
-                    // algorithm on.
-                    ram.range.contains(&load_addr) && ram.cores.contains(core_name)
-                } else {
-                    // Any RAM is okay as long as it's accessible to the core;
-                    // the algorithm is presumably position-independent.
-                    ram.cores.contains(core_name)
-                }
-            })
-            .ok_or(FlashError::NoRamDefined {
-                name: session.target().name.clone(),
-            })?;
-
-        tracing::info!("Chosen RAM to run the algo: {:x?}", ram);
-
-        let flash_algorithm = FlashAlgorithm::assemble_from_raw(raw_flash_algorithm, ram, target)?;
+        let flash_algorithm =
+            assemble_algorithm(session.target(), core_index, raw_flash_algorithm, keep_ram)?;
 
         let mut this = Self {
             session,
@@ -321,6 +296,10 @@ impl<'session> Flasher<'session> {
         if !skip_erasing {
             // Erase all necessary sectors
             self.sector_erase(&flash_layout)?;
+        } else if let Some(granularity) = self.flash_algorithm.flash_properties.program_granularity
+        {
+            // Programming a flash word twice fails or corrupts its ECC, so make sure it was erased before.
+            self.check_pages_erased(&flash_layout, granularity)?;
         }
 
         // Flash all necessary pages.
@@ -345,14 +324,81 @@ impl<'session> Flasher<'session> {
     ) -> Result<(), FlashError> {
         let page_offset = (fill.address() - page.address()) as usize;
         let page_slice = &mut page.data_mut()[page_offset..page_offset + fill.size() as usize];
+        let program_granularity = self.flash_algorithm.flash_properties.program_granularity;
+        self.run_verify(|active| {
+            read_flash(
+                &mut active.core,
+                fill.address(),
+                page_slice,
+                program_granularity,
+            )
+        })
+    }
+
+    /// Read the flash at `address` into `data`, with the flash algorithm initialized for verifying.
+    pub(super) fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), FlashError> {
+        let program_granularity = self.flash_algorithm.flash_properties.program_granularity;
+        self.run_verify(|active| read_flash(&mut active.core, address, data, program_granularity))
+    }
+
+    /// Verify the data of `flash_builder` in `region` with CRCs which the target computes, which is much faster than
+    /// reading the flash back. Returns `false` if the target cannot compute the CRCs.
+    pub(super) fn verify_with_crc(
+        &mut self,
+        region: &NvmRegion,
+        flash_builder: &FlashBuilder,
+    ) -> Result<bool, FlashError> {
         self.run_verify(|active| {
-            active
-                .core
-                .read(fill.address(), page_slice)
-                .map_err(FlashError::Core)
+            // The CRC routine is part of the header of ARM flash algorithms.
+            if active.core.instruction_set()? != InstructionSet::Thumb2 {
+                return Ok(false);
+            }
+
+            for (address, data) in flash_builder.data_in_range(&region.range) {
+                let crc = active.crc32(address, data.len() as u32)?;
+                if crc != crc32_mpeg2(data) {
+                    tracing::debug!(
+                        "The CRC of {:#010x}..{:#010x} is {:#010x}, expected {:#010x}",
+                        address,
+                        address + data.len() as u64,
+                        crc,
+                        crc32_mpeg2(data)
+                    );
+                    return Err(FlashError::Verify);
+                }
+            }
+            Ok(true)
         })
     }
 
+    /// Check that all flash words of the pages in `flash_layout` are erased.
+    fn check_pages_erased(
+        &mut self,
+        flash_layout: &FlashLayout,
+        granularity: u32,
+    ) -> Result<(), FlashError> {
+        let erased_byte_value = self.flash_algorithm.flash_properties.erased_byte_value;
+        for page in flash_layout.pages() {
+            let mut contents = vec![0; page.size() as usize];
+            self.run_verify(|active| {
+                read_flash(
+                    &mut active.core,
+                    page.address(),
+                    &mut contents,
+                    Some(granularity),
+                )
+            })?;
+
+            if let Some(offset) = first_programmed_word(&contents, granularity, erased_byte_value) {
+                return Err(FlashError::FlashWordNotErased {
+                    address: page.address() + offset as u64,
+                    granularity,
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Programs the pages given in `flash_layout` into the flash.
     fn program_simple(&mut self, flash_layout: &FlashLayout) -> Result<(), FlashError> {
         self.progress.started_programming();
@@ -421,75 +467,76 @@ impl<'session> Flasher<'session> {
     /// This is only possible if the RAM is large enough to
     /// fit at least two page buffers. See [Flasher::double_buffering_supported].
     fn program_double_buffer(&mut self, flash_layout: &FlashLayout) -> Result<(), FlashError> {
-        let mut current_buf = 0;
+        let buffer_count = self.flash_algorithm.page_buffers.len();
         self.progress.started_programming();
 
-        let mut t = std::time::Instant::now();
+        let pages = flash_layout.pages();
         let result = self.run_program(|active| {
-            let mut last_page_address = 0;
-            for page in flash_layout.pages() {
-                // At the start of each loop cycle load the next page buffer into RAM.
-                active.load_page_buffer(page.address(), page.data(), current_buf)?;
-
-                // Then wait for the active RAM -> Flash copy process to finish.
-                // Also check if it finished properly. If it didn't, return an error.
-                let result =
-                    active
-                        .wait_for_completion(Duration::from_secs(2))
-                        .map_err(|error| FlashError::PageWrite {
-                            page_address: last_page_address,
-                            source: Box::new(error),
-                        })?;
-
-                last_page_address = page.address();
-                active.progress.page_programmed(page.size(), t.elapsed());
-
-                t = std::time::Instant::now();
-                if result != 0 {
-                    return Err(FlashError::RoutineCallFailed {
-                        name: "program_page",
-                        error_code: result,
-                    });
+            // When the page which is programmed at the moment was started.
+            let mut started = Instant::now();
+            pipeline_pages(pages.len(), buffer_count, |step| match step {
+                PageStep::Load { page, buffer } => {
+                    active.load_page_buffer(pages[page].address(), pages[page].data(), buffer)
                 }
-
-                // Start the next copy process.
-                active.start_program_page_with_buffer(page.address(), current_buf)?;
-
-                // Swap the buffers
-                if current_buf == 1 {
-                    current_buf = 0;
-                } else {
-                    current_buf = 1;
+                PageStep::Start { page, buffer } => {
+                    active.start_program_page_with_buffer(pages[page].address(), buffer)?;
+                    started = Instant::now();
+                    Ok(())
                 }
-            }
-
-            let result = active
-                .wait_for_completion(Duration::from_secs(2))
-                .map_err(|error| FlashError::PageWrite {
-                    page_address: last_page_address,
-                    source: Box::new(error),
-                })?;
-
-            if result != 0 {
-                Err(FlashError::RoutineCallFailed {
-                    name: "wait_for_completion",
-                    error_code: result,
-                })
-            } else {
-                Ok(0)
-            }
+                PageStep::Wait { page } => {
+                    active.wait_for_program_page(pages[page].address())?;
+                    active
+                        .progress
+                        .page_programmed(pages[page].size(), started.elapsed());
+                    Ok(())
+                }
+            })
         });
 
         if result.is_ok() {
             self.progress.finished_programming();
         } else {
             self.progress.failed_programming();
+        }
+
+        result
+    }
+}
+
+/// A step of programming pages from several page buffers, see [`pipeline_pages`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PageStep {
+    /// Download the page into the buffer.
+    Load { page: usize, buffer: usize },
+    /// Start to program the page from the buffer.
+    Start { page: usize, buffer: usize },
+    /// Wait until the page is programmed.
+    Wait { page: usize },
+}
 
-            result?;
+/// Program `page_count` pages from `buffer_count` page buffers, downloading each page while the flash algorithm
+/// programs the previous one. Stops at the first step which fails.
+fn pipeline_pages(
+    page_count: usize,
+    buffer_count: usize,
+    mut step: impl FnMut(PageStep) -> Result<(), FlashError>,
+) -> Result<(), FlashError> {
+    for page in 0..page_count {
+        let buffer = page % buffer_count;
+
+        step(PageStep::Load { page, buffer })?;
+        if page > 0 {
+            step(PageStep::Wait { page: page - 1 })?;
         }
+        step(PageStep::Start { page, buffer })?;
+    }
 
-        Ok(())
+    if page_count > 0 {
+        step(PageStep::Wait {
+            page: page_count - 1,
+        })?;
     }
+    Ok(())
 }
 
 struct Registers {
@@ -518,6 +565,93 @@ fn into_reg(val: u64) -> Result<u32, FlashError> {
     Ok(reg_value)
 }
 
+/// Assemble a flash algorithm to run on the core `core_index`, in a RAM region which the core can access, outside of
+/// the `keep_ram` ranges if it fits.
+pub(super) fn assemble_algorithm(
+    target: &Target,
+    core_index: usize,
+    raw_flash_algorithm: &RawFlashAlgorithm,
+    keep_ram: &[Range<u64>],
+) -> Result<FlashAlgorithm, FlashError> {
+    // Find a RAM region from which we can run the algo.
+    let mm = &target.memory_map;
+    let core_name = &target.cores[core_index].name;
+    let ram = mm
+        .iter()
+        .filter_map(|mm| match mm {
+            MemoryRegion::Ram(ram) => Some(ram),
+            _ => None,
+        })
+        .find(|ram| {
+            if let Some(load_addr) = raw_flash_algorithm.load_address {
+                // The RAM must contain the forced load address _and_
+                // be accessible from the core we're going to run the
+                // algorithm on.
+                ram.range.contains(&load_addr) && ram.cores.contains(core_name)
+            } else {
+                // Any RAM is okay as long as it's accessible to the core;
+                // the algorithm is presumably position-independent.
+                ram.cores.contains(core_name)
+            }
+        })
+        .ok_or(FlashError::NoRamDefined {
+            name: target.name.clone(),
+        })?;
+
+    let ram = ram_for_algorithm(ram, keep_ram, raw_flash_algorithm);
+    tracing::info!("Chosen RAM to run the algo: {:x?}", ram);
+
+    FlashAlgorithm::assemble_from_raw(raw_flash_algorithm, &ram, target)
+}
+
+/// Read flash. If the flash has a program granularity, it usually has ECC, so a failed read is reported as
+/// [`FlashError::EccError`].
+pub(super) fn read_flash(
+    core: &mut Core<'_>,
+    address: u64,
+    data: &mut [u8],
+    program_granularity: Option<u32>,
+) -> Result<(), FlashError> {
+    core.read(address, data)
+        .map_err(|source| match program_granularity {
+            Some(_) => FlashError::EccError { address, source },
+            None => FlashError::Core(source),
+        })
+}
+
+const CRC32_MPEG2_POLYNOMIAL: u32 = 0x04C1_1DB7;
+
+/// CRC-32/MPEG-2, which is the CRC-32 polynomial without reflection and without a final XOR.
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= u32::from(byte) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ CRC32_MPEG2_POLYNOMIAL
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// The offset of the first flash word in `contents` which is not erased.
+fn first_programmed_word(
+    contents: &[u8],
+    granularity: u32,
+    erased_byte_value: u8,
+) -> Option<usize> {
+    let granularity = granularity.max(1) as usize;
+    contents
+        .chunks(granularity)
+        .position(|word| word.iter().any(|&byte| byte != erased_byte_value))
+        .map(|word| word * granularity)
+}
+
 pub(super) struct ActiveFlasher<'probe, O: Operation> {
     core: Core<'probe>,
     #[cfg(feature = "rtt")]
@@ -596,6 +730,24 @@ impl<'probe, O: Operation> ActiveFlasher<'probe, O> {
         Ok(())
     }
 
+    /// Compute the CRC-32/MPEG-2 of `length` bytes at `address` on the target, with the routine in the header
+    /// of the flash algorithm.
+    fn crc32(&mut self, address: u64, length: u32) -> Result<u32, FlashError> {
+        let pc = self.flash_algorithm.load_address + FlashAlgorithm::ARM_CRC_ROUTINE_OFFSET;
+        self.call_function_and_wait(
+            &Registers {
+                pc: into_reg(pc)?,
+                r0: Some(0xFFFF_FFFF),
+                r1: Some(into_reg(address)?),
+                r2: Some(length),
+                r3: Some(CRC32_MPEG2_POLYNOMIAL),
+            },
+            false,
+            // The routine takes about 50 cycles per byte.
+            Duration::from_millis(1000 + length as u64 / 100),
+        )
+    }
+
     fn call_function_and_wait(
         &mut self,
         registers: &Registers,
@@ -918,6 +1070,32 @@ impl<'p> ActiveFlasher<'p, Program> {
         Ok(())
     }
 
+    /// Wait until the page at `address`, which was started with [`Self::start_program_page_with_buffer`], is
+    /// programmed.
+    pub(super) fn wait_for_program_page(&mut self, address: u64) -> Result<(), FlashError> {
+        let timeout = Duration::from_millis(
+            self.flash_algorithm.flash_properties.program_page_timeout as u64,
+        );
+        let result = self
+            .wait_for_completion(timeout)
+            .map_err(|error| FlashError::PageWrite {
+                page_address: address,
+                source: Box::new(error),
+            })?;
+
+        if result != 0 {
+            Err(FlashError::PageWrite {
+                page_address: address,
+                source: Box::new(FlashError::RoutineCallFailed {
+                    name: "program_page",
+                    error_code: result,
+                }),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
     pub(super) fn load_page_buffer(
         &mut self,
         _address: u64,
@@ -955,3 +1133,82 @@ impl<'p> ActiveFlasher<'p, Program> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{crc32_mpeg2, first_programmed_word, pipeline_pages, FlashError, PageStep};
+
+    #[test]
+    fn crc32_mpeg2_check_value() {
+        assert_eq!(crc32_mpeg2(b"123456789"), 0x0376_E6E7);
+    }
+
+    #[test]
+    fn programmed_flash_words_are_found() {
+        let mut contents = [0xff; 96];
+        assert_eq!(first_programmed_word(&contents, 32, 0xff), None);
+
+        // A single programmed byte makes the whole word programmed.
+        contents[70] = 0x00;
+        assert_eq!(first_programmed_word(&contents, 32, 0xff), Some(64));
+        assert_eq!(first_programmed_word(&contents, 8, 0xff), Some(64));
+    }
+
+    #[test]
+    fn pages_are_downloaded_while_the_previous_one_is_programmed() {
+        let mut steps = Vec::new();
+        pipeline_pages(3, 2, |step| {
+            steps.push(step);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            steps,
+            [
+                PageStep::Load { page: 0, buffer: 0 },
+                PageStep::Start { page: 0, buffer: 0 },
+                PageStep::Load { page: 1, buffer: 1 },
+                PageStep::Wait { page: 0 },
+                PageStep::Start { page: 1, buffer: 1 },
+                PageStep::Load { page: 2, buffer: 0 },
+                PageStep::Wait { page: 1 },
+                PageStep::Start { page: 2, buffer: 0 },
+                PageStep::Wait { page: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn programming_stops_when_a_page_times_out() {
+        let mut steps = Vec::new();
+        let result = pipeline_pages(3, 2, |step| {
+            steps.push(step);
+            match step {
+                PageStep::Wait { page: 0 } => Err(FlashError::Core(crate::Error::Timeout)),
+                _ => Ok(()),
+            }
+        });
+
+        assert!(matches!(
+            result,
+            Err(FlashError::Core(crate::Error::Timeout))
+        ));
+        // The next page was downloaded, but its programming was not started.
+        assert_eq!(steps.last(), Some(&PageStep::Wait { page: 0 }));
+        assert!(!steps.contains(&PageStep::Start { page: 1, buffer: 1 }));
+    }
+
+    #[test]
+    fn no_pages_leave_nothing_to_wait_for() {
+        let mut steps = Vec::new();
+        pipeline_pages(0, 2, |step| {
+            steps.push(step);
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(steps.is_empty());
+    }
+}
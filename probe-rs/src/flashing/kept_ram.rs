@@ -0,0 +1,170 @@
+//! Keeping the contents of RAM ranges across flashing and a reset.
+//!
+//! Programs often keep data in RAM which is not initialized at startup, e.g. crash logs or boot flags. Flashing runs
+//! the flash algorithm from RAM, so [`DownloadOptions::keep_ram`](super::DownloadOptions::keep_ram) places the
+//! algorithm outside of these ranges. A [`RamSnapshot`] keeps the contents when the algorithm does not fit next to
+//! them, or when the reset of the target clears the RAM.
+
+use std::ops::Range;
+
+use probe_rs_target::{RamRegion, RawFlashAlgorithm};
+
+use crate::{Core, Error, MemoryInterface};
+
+/// The size of the largest header which is loaded before the code of a flash algorithm.
+const ALGORITHM_HEADER_SIZE: u64 = 32;
+/// The smallest stack which a flash algorithm is given when the RAM is small.
+const MINIMAL_STACK_SIZE: u64 = 64;
+
+/// The contents of RAM ranges, which are read before flashing and restored afterwards.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RamSnapshot {
+    contents: Vec<(u64, Vec<u8>)>,
+}
+
+impl RamSnapshot {
+    /// Read the contents of `ranges`.
+    pub fn read(core: &mut Core<'_>, ranges: &[Range<u64>]) -> Result<Self, Error> {
+        let mut contents = Vec::with_capacity(ranges.len());
+        for range in ranges.iter().filter(|range| !range.is_empty()) {
+            let mut data = vec![0; (range.end - range.start) as usize];
+            core.read(range.start, &mut data)?;
+            tracing::debug!(
+                "Keeping {} bytes of RAM at {:#010x}",
+                data.len(),
+                range.start
+            );
+            contents.push((range.start, data));
+        }
+        Ok(Self { contents })
+    }
+
+    /// Write the contents back. The core should be halted, so nothing overwrites them before the program starts.
+    pub fn restore(&self, core: &mut Core<'_>) -> Result<(), Error> {
+        for (address, data) in &self.contents {
+            core.write_8(*address, data)?;
+        }
+        core.flush()
+    }
+
+    /// The number of bytes in the snapshot.
+    pub fn len(&self) -> usize {
+        self.contents.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    /// Whether the snapshot holds no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The part of `ram` which the flash algorithm is loaded into: the largest part outside of the `kept` ranges, which
+/// holds the load address of the algorithm, if it has one.
+///
+/// Returns `ram` itself if the algorithm does not fit outside of the kept ranges.
+pub(super) fn ram_for_algorithm(
+    ram: &RamRegion,
+    kept: &[Range<u64>],
+    algorithm: &RawFlashAlgorithm,
+) -> RamRegion {
+    if kept.is_empty() {
+        return ram.clone();
+    }
+
+    // The header and the code of the algorithm, a minimal stack and one page buffer.
+    let required = ALGORITHM_HEADER_SIZE
+        + algorithm.instructions.len() as u64
+        + MINIMAL_STACK_SIZE
+        + u64::from(algorithm.flash_properties.page_size);
+
+    let free = free_ranges(&ram.range, kept)
+        .into_iter()
+        .filter(|range| {
+            // The load address is the start of the code, after the header.
+            let start = match algorithm.load_address {
+                Some(load_address) => load_address.saturating_sub(ALGORITHM_HEADER_SIZE),
+                None => range.start,
+            };
+            range.start <= start && start + required <= range.end
+        })
+        .max_by_key(|range| range.end - range.start);
+
+    match free {
+        Some(range) => RamRegion {
+            range,
+            ..ram.clone()
+        },
+        None => {
+            tracing::warn!(
+                "The flash algorithm does not fit into {:#010x?} outside of the kept RAM ranges, it may overwrite them",
+                ram.range
+            );
+            ram.clone()
+        }
+    }
+}
+
+/// The parts of `range` which are outside of all `kept` ranges.
+fn free_ranges(range: &Range<u64>, kept: &[Range<u64>]) -> Vec<Range<u64>> {
+    let mut free = vec![range.clone()];
+    for kept in kept.iter().filter(|kept| !kept.is_empty()) {
+        free = free
+            .into_iter()
+            .flat_map(|range| {
+                [
+                    range.start..range.end.min(kept.start),
+                    range.start.max(kept.end)..range.end,
+                ]
+            })
+            .filter(|range| !range.is_empty())
+            .collect();
+    }
+    free
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn kept_ranges_are_cut_out_of_the_ram() {
+        let ram = 0x2000_0000..0x2001_0000;
+        assert_eq!(
+            free_ranges(&ram, &[0x2000_F000..0x2001_0000, 0x2000_1000..0x2000_2000]),
+            [0x2000_0000..0x2000_1000, 0x2000_2000..0x2000_F000]
+        );
+        assert_eq!(free_ranges(&ram, &[(0x1000_0000..0x3000_0000)]), []);
+        assert_eq!(free_ranges(&ram, &[(0x3000_0000..0x3000_1000)]), [ram]);
+    }
+
+    #[test]
+    fn the_algorithm_is_loaded_into_the_largest_free_part() {
+        let ram = RamRegion {
+            name: Some("RAM".into()),
+            range: 0x2000_0000..0x2001_0000,
+            is_boot_memory: false,
+            cores: vec!["main".into()],
+        };
+        let mut algorithm = RawFlashAlgorithm {
+            instructions: vec![0; 0x400],
+            ..Default::default()
+        };
+        algorithm.flash_properties.page_size = 0x400;
+
+        let kept = [(0x2000_1000..0x2000_2000)];
+        assert_eq!(
+            ram_for_algorithm(&ram, &kept, &algorithm).range,
+            0x2000_2000..0x2001_0000
+        );
+
+        algorithm.load_address = Some(0x2000_0100);
+        assert_eq!(
+            ram_for_algorithm(&ram, &kept, &algorithm).range,
+            0x2000_0000..0x2000_1000
+        );
+
+        // Without room next to the kept range, the whole RAM is used.
+        algorithm.flash_properties.page_size = 0x1000;
+        assert_eq!(ram_for_algorithm(&ram, &kept, &algorithm), ram);
+    }
+}
@@ -1,19 +1,22 @@
 use ihex::Record;
 use probe_rs_target::{
-    MemoryRange, MemoryRegion, NvmRegion, RawFlashAlgorithm, TargetDescriptionSource,
+    MemoryAlias, MemoryAliasKind, MemoryRange, MemoryRegion, NvmRegion, Partition,
+    RawFlashAlgorithm, TargetDescriptionSource,
 };
 use std::collections::HashMap;
 use std::io::{Read, Seek, SeekFrom};
 use std::ops::Range;
+use std::path::Path;
 use std::str::FromStr;
+use std::time::Instant;
 
 use super::builder::FlashBuilder;
 use super::{
-    extract_from_elf, BinOptions, DownloadOptions, FileDownloadError, FlashError, Flasher,
-    IdfOptions,
+    elf_entry_point, extract_from_elf, read_flash, BinOptions, DownloadOptions, ElfOptions,
+    FileDownloadError, FlashError, FlashProgrammer, Flasher, IdfOptions, ImagePatch, OtpWordChange,
 };
 use crate::memory::MemoryInterface;
-use crate::session::Session;
+use crate::session::{FlashResult, Session};
 use crate::Target;
 
 /// `FlashLoader` is a struct which manages the flashing of any chunks of data onto any sections of flash.
@@ -23,8 +26,14 @@ use crate::Target;
 /// The flash loader will make sure to select the appropriate flash region for the right data chunks.
 /// Region crossing data chunks are allowed as long as the regions are contiguous.
 pub struct FlashLoader {
-    memory_map: Vec<MemoryRegion>,
-    builder: FlashBuilder,
+    pub(super) memory_map: Vec<MemoryRegion>,
+    pub(super) builder: FlashBuilder,
+    /// The entry point of the loaded ELF file.
+    pub(super) entry_point: Option<u64>,
+    /// The ranges of the [`FlashProgrammer`](super::FlashProgrammer)s, whose data may be outside of the memory map.
+    programmer_ranges: Vec<Range<u64>>,
+    /// Address ranges which mirror the memory map. Data added at an alias is moved to the memory it mirrors.
+    aliases: Vec<MemoryAlias>,
 
     /// Source of the flash description,
     /// used for diagnostics.
@@ -37,6 +46,9 @@ impl FlashLoader {
         Self {
             memory_map,
             builder: FlashBuilder::new(),
+            entry_point: None,
+            programmer_ranges: Vec::new(),
+            aliases: Vec::new(),
             source,
         }
     }
@@ -46,6 +58,10 @@ impl FlashLoader {
     fn check_data_in_memory_map(&mut self, range: Range<u64>) -> Result<(), FlashError> {
         let mut address = range.start;
         while address < range.end {
+            if let Some(range) = self.programmer_ranges.iter().find(|r| r.contains(&address)) {
+                address = range.end;
+                continue;
+            }
             match Self::get_region_for_address(&self.memory_map, address) {
                 Some(MemoryRegion::Nvm(region)) => address = region.range.end,
                 Some(MemoryRegion::Ram(region)) => address = region.range.end,
@@ -71,10 +87,44 @@ impl FlashLoader {
             data.len()
         );
 
+        let address = self.canonical_address(address, data.len() as u64);
         self.check_data_in_memory_map(address..address + data.len() as u64)?;
         self.builder.add_data(address, data)
     }
 
+    /// Accept data at the addresses of `alias`, and program it into the memory which the alias mirrors, e.g. for
+    /// an image which is linked at the flash alias at address 0.
+    pub fn add_alias(&mut self, alias: MemoryAlias) {
+        self.aliases.push(alias);
+    }
+
+    /// The address in the memory map of `size` bytes of data at `address`, if all of them are part of a mirroring
+    /// alias. Bit-band aliases are not translated, as their data is not a copy of the memory.
+    fn canonical_address(&self, address: u64, size: u64) -> u64 {
+        let range = address..address + size;
+        if self.programmer_ranges.iter().any(|r| r.contains(&address)) {
+            return address;
+        }
+
+        self.aliases
+            .iter()
+            .filter(|alias| alias.kind == MemoryAliasKind::Mirror)
+            .find(|alias| alias.range.contains_range(&range))
+            .and_then(|alias| alias.canonical_address(address))
+            .map_or(address, |canonical| {
+                tracing::debug!(
+                    "Moving data at {address:#010x} to {canonical:#010x} which it mirrors"
+                );
+                canonical
+            })
+    }
+
+    /// Accept data in `range`, which is programmed by a [`FlashProgrammer`](super::FlashProgrammer) of the
+    /// [`DownloadOptions`], even if it is outside of the memory map.
+    pub fn add_programmer_range(&mut self, range: Range<u64>) {
+        self.programmer_ranges.push(range);
+    }
+
     pub(super) fn get_region_for_address(
         memory_map: &[MemoryRegion],
         address: u64,
@@ -188,13 +238,19 @@ impl FlashLoader {
 
     /// Prepares the data sections that have to be loaded into flash from an ELF file.
     /// This will validate the ELF file and transform all its data into sections but no flash loader commands yet.
-    pub fn load_elf_data<T: Read>(&mut self, file: &mut T) -> Result<(), FileDownloadError> {
+    ///
+    /// Only the sections and address ranges selected by `options` are loaded.
+    pub fn load_elf_data<T: Read>(
+        &mut self,
+        file: &mut T,
+        options: &ElfOptions,
+    ) -> Result<(), FileDownloadError> {
         let mut elf_buffer = Vec::new();
         file.read_to_end(&mut elf_buffer)?;
 
         let mut extracted_data = Vec::new();
 
-        let num_sections = extract_from_elf(&mut extracted_data, &elf_buffer)?;
+        let num_sections = extract_from_elf(&mut extracted_data, &elf_buffer, options)?;
 
         if num_sections == 0 {
             tracing::warn!("No loadable segments were found in the ELF file.");
@@ -225,6 +281,8 @@ impl FlashLoader {
             self.add_data(data.address.into(), data.data)?;
         }
 
+        self.entry_point = Some(elf_entry_point(&elf_buffer)?);
+
         Ok(())
     }
 
@@ -233,10 +291,23 @@ impl FlashLoader {
     /// Requires a session with an attached target that has a known flash algorithm.
     ///
     /// If `do_chip_erase` is `true` the entire flash will be erased.
+    ///
+    /// The result is recorded in the session, see [`SessionState::last_flash`](crate::SessionState::last_flash).
     pub fn commit(
         &self,
         session: &mut Session,
         options: DownloadOptions,
+    ) -> Result<(), FlashError> {
+        let started = Instant::now();
+        let result = self.commit_to_target(session, options);
+        session.set_last_flash(FlashResult::new(started, &result));
+        result
+    }
+
+    fn commit_to_target(
+        &self,
+        session: &mut Session,
+        mut options: DownloadOptions,
     ) -> Result<(), FlashError> {
         tracing::debug!("committing FlashLoader!");
 
@@ -269,49 +340,26 @@ impl FlashLoader {
             tracing::warn!("Memory map of flash loader does not match memory map of target!");
         }
 
-        let mut algos: HashMap<(String, String), Vec<NvmRegion>> = HashMap::new();
+        super::programmer::check_programmer_ranges(&options.programmers, &self.memory_map)?;
 
         // Commit NVM first
+        let algos = self.algorithm_regions(session.target(), &options.programmers)?;
 
-        // Iterate all NvmRegions and group them by flash algorithm.
-        // This avoids loading the same algorithm twice if it's used for two regions.
-        //
-        // This also ensures correct operation when chip erase is used. We assume doing a chip erase
-        // using a given algorithm erases all regions controlled by it. Therefore, we must do
-        // chip erase once per algorithm, not once per region. Otherwise subsequent chip erases will
-        // erase previous regions' flashed contents.
-        tracing::debug!("Regions:");
-        for region in &self.memory_map {
-            if let MemoryRegion::Nvm(region) = region {
-                tracing::debug!(
-                    "    region: {:08x}-{:08x} ({} bytes)",
-                    region.range.start,
-                    region.range.end,
-                    region.range.end - region.range.start
+        // OTP memory can only be programmed once, so it is never written by accident.
+        if options.dry_run {
+            for change in self.otp_changes(session)? {
+                tracing::info!(
+                    "The OTP word at {:#010x} would change from {:#010x} to {:#010x}",
+                    change.address,
+                    change.old,
+                    change.new
                 );
-
-                // If we have no data in this region, ignore it.
-                // This avoids uselessly initializing and deinitializing its flash algorithm.
-                if !self.builder.has_data_in_range(&region.range) {
-                    tracing::debug!("     -- empty, ignoring!");
-                    continue;
-                }
-
-                let algo = Self::get_flash_algorithm_for_region(region, session.target())?;
-
-                let entry = algos
-                    .entry((
-                        algo.name.clone(),
-                        region
-                            .cores
-                            .first()
-                            .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?
-                            .clone(),
-                    ))
-                    .or_default();
-                entry.push(region.clone());
-
-                tracing::debug!("     -- using algorithm: {}", algo.name);
+            }
+        } else if !options.allow_otp_write {
+            if let Some(region) = super::otp::regions_with_data(self).next() {
+                return Err(FlashError::OtpWriteNotAllowed {
+                    range: region.range.clone(),
+                });
             }
         }
 
@@ -327,6 +375,9 @@ impl FlashLoader {
             return Ok(());
         }
 
+        // The regions whose data was already verified by the target, or by a programmer.
+        let mut crc_verified = Vec::new();
+
         // Iterate all flash algorithms we need to use.
         for ((algo_name, core_name), regions) in algos {
             tracing::debug!("Flashing ranges for algo: {}", algo_name);
@@ -341,7 +392,13 @@ impl FlashLoader {
                 .iter()
                 .position(|c| c.name == core_name)
                 .unwrap();
-            let mut flasher = Flasher::new(session, core, &algo, options.progress.clone())?;
+            let mut flasher = Flasher::new(
+                session,
+                core,
+                &algo,
+                &options.keep_ram,
+                options.progress.clone(),
+            )?;
 
             let mut do_chip_erase = options.do_chip_erase;
 
@@ -377,8 +434,55 @@ impl FlashLoader {
                     &self.builder,
                     options.keep_unwritten_bytes,
                     do_use_double_buffering,
-                    options.skip_erase || do_chip_erase,
+                    // OTP memory can not be erased.
+                    options.skip_erase || do_chip_erase || region.is_otp,
                 )?;
+
+                if options.verify && flasher.verify_with_crc(&region, &self.builder)? {
+                    tracing::debug!("Verified {:08x?} with target-side CRCs", region.range);
+                    crc_verified.push(region.range.clone());
+                }
+            }
+        }
+
+        for programmer in &mut options.programmers {
+            let range = programmer.range();
+            if !self.builder.has_data_in_range(&range) {
+                continue;
+            }
+            tracing::debug!("Programming {:08x?} with {}", range, programmer.name());
+
+            if options.do_chip_erase {
+                programmer.erase_all(session)?;
+            }
+            let erase = !(options.skip_erase || options.do_chip_erase);
+            let total = self
+                .builder
+                .data_in_range(&range)
+                .map(|(_, data)| data.len() as u64)
+                .sum();
+            let mut programmed = 0;
+            for (address, data) in self.builder.data_in_range(&range) {
+                for (address, piece) in super::programmer::pieces(address, data) {
+                    programmer.program(session, address, piece, erase)?;
+                    programmed += piece.len() as u64;
+                    if let Some(progress) = &options.progress {
+                        progress.programmer_progress(programmer.name(), programmed, total);
+                    }
+                }
+            }
+
+            if options.verify {
+                for (address, data) in self.builder.data_in_range(&range) {
+                    for (address, piece) in super::programmer::pieces(address, data) {
+                        let mut written_data = vec![0; piece.len()];
+                        programmer.read(session, address, &mut written_data)?;
+                        if piece != written_data {
+                            return Err(FlashError::Verify);
+                        }
+                    }
+                }
+                crc_verified.push(range);
             }
         }
 
@@ -428,6 +532,14 @@ impl FlashLoader {
         if options.verify {
             tracing::debug!("Verifying!");
             for (&address, data) in &self.builder.data {
+                let range = address..address + data.len() as u64;
+                if crc_verified
+                    .iter()
+                    .any(|region: &Range<u64>| region.contains_range(&range))
+                {
+                    continue;
+                }
+
                 tracing::debug!(
                     "    data: {:08x}-{:08x} ({} bytes)",
                     address,
@@ -447,11 +559,18 @@ impl FlashLoader {
                 .first()
                 .unwrap();
                 let core_index = session.target().core_index_by_name(core_name).unwrap();
+                let program_granularity = match associated_region {
+                    MemoryRegion::Nvm(region) => {
+                        Self::get_flash_algorithm_for_region(region, session.target())
+                            .ok()
+                            .and_then(|algorithm| algorithm.flash_properties.program_granularity)
+                    }
+                    _ => None,
+                };
                 let mut core = session.core(core_index).map_err(FlashError::Core)?;
 
                 let mut written_data = vec![0; data.len()];
-                core.read(address, &mut written_data)
-                    .map_err(FlashError::Core)?;
+                read_flash(&mut core, address, &mut written_data, program_granularity)?;
 
                 if data != &written_data {
                     return Err(FlashError::Verify);
@@ -462,6 +581,68 @@ impl FlashLoader {
         Ok(())
     }
 
+    /// Group the NVM regions which contain data by the flash algorithm and the core which flash them. The regions in
+    /// the ranges of `programmers` are left out.
+    pub(super) fn algorithm_regions(
+        &self,
+        target: &Target,
+        programmers: &[Box<dyn FlashProgrammer>],
+    ) -> Result<HashMap<(String, String), Vec<NvmRegion>>, FlashError> {
+        let mut algos: HashMap<(String, String), Vec<NvmRegion>> = HashMap::new();
+
+        // Iterate all NvmRegions and group them by flash algorithm.
+        // This avoids loading the same algorithm twice if it's used for two regions.
+        //
+        // This also ensures correct operation when chip erase is used. We assume doing a chip erase
+        // using a given algorithm erases all regions controlled by it. Therefore, we must do
+        // chip erase once per algorithm, not once per region. Otherwise subsequent chip erases will
+        // erase previous regions' flashed contents.
+        tracing::debug!("Regions:");
+        for region in &self.memory_map {
+            if let MemoryRegion::Nvm(region) = region {
+                tracing::debug!(
+                    "    region: {:08x}-{:08x} ({} bytes)",
+                    region.range.start,
+                    region.range.end,
+                    region.range.end - region.range.start
+                );
+
+                // If we have no data in this region, ignore it.
+                // This avoids uselessly initializing and deinitializing its flash algorithm.
+                if !self.builder.has_data_in_range(&region.range) {
+                    tracing::debug!("     -- empty, ignoring!");
+                    continue;
+                }
+
+                if let Some(programmer) = programmers
+                    .iter()
+                    .find(|programmer| programmer.range().contains_range(&region.range))
+                {
+                    tracing::debug!("     -- programmed by {}", programmer.name());
+                    continue;
+                }
+
+                let algo = Self::get_flash_algorithm_for_region(region, target)?;
+
+                let entry = algos
+                    .entry((
+                        algo.name.clone(),
+                        region
+                            .cores
+                            .first()
+                            .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?
+                            .clone(),
+                    ))
+                    .or_default();
+                entry.push(region.clone());
+
+                tracing::debug!("     -- using algorithm: {}", algo.name);
+            }
+        }
+
+        Ok(algos)
+    }
+
     /// Try to find a flash algorithm for the given NvmRegion.
     /// Errors when:
     /// - there's no algo for the region.
@@ -507,6 +688,55 @@ impl FlashLoader {
         }
     }
 
+    /// Applies the given patches to the stored data, in order. See [`ImagePatch`].
+    pub fn apply_patches(&mut self, patches: &[ImagePatch]) -> Result<(), FlashError> {
+        super::patch::apply(self, patches)
+    }
+
+    /// Read the one-time programmable (OTP) memory which would be written by [`commit`](Self::commit),
+    /// and return the words which would change.
+    ///
+    /// Use this to show exactly what is going to be programmed, before setting
+    /// [`DownloadOptions::allow_otp_write`].
+    pub fn otp_changes(&self, session: &mut Session) -> Result<Vec<OtpWordChange>, FlashError> {
+        super::otp::changes(self, session)
+    }
+
+    /// Check that all data is inside of `partition`, before it is flashed into it.
+    pub fn check_partition(&self, partition: &Partition) -> Result<(), FlashError> {
+        for (address, data) in self.data() {
+            let range = address..address + data.len() as u64;
+            if !partition.range.contains_range(&range) {
+                return Err(FlashError::OutsidePartition {
+                    name: partition.name.clone(),
+                    range,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the data of `image`, the loader of the image at `path`, e.g. a SoftDevice which is flashed together with
+    /// the application.
+    ///
+    /// Fails with [`FileDownloadError::ImageOverlaps`] if its data overlaps the data which was added before.
+    pub fn add_image(&mut self, path: &Path, image: &FlashLoader) -> Result<(), FileDownloadError> {
+        for (address, data) in image.data() {
+            self.add_data(address, data).map_err(|error| match error {
+                FlashError::DataOverlaps {
+                    added_addresses,
+                    existing_addresses,
+                } => FileDownloadError::ImageOverlaps {
+                    image: path.to_owned(),
+                    addresses: added_addresses,
+                    existing: existing_addresses,
+                },
+                error => FileDownloadError::Flash(error),
+            })?;
+        }
+        Ok(())
+    }
+
     /// Return data chunks stored in the `FlashLoader` as pairs of address and bytes.
     pub fn data(&self) -> impl Iterator<Item = (u64, &[u8])> {
         self.builder
@@ -515,3 +745,77 @@ impl FlashLoader {
             .map(|(address, data)| (*address, data.as_slice()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use probe_rs_target::NvmRegion;
+
+    fn loader(data: &[(u64, &[u8])]) -> FlashLoader {
+        let region = NvmRegion {
+            name: Some("FLASH".into()),
+            is_boot_memory: true,
+            range: 0..1 << 16,
+            cores: vec!["main".into()],
+            is_otp: false,
+        };
+
+        let mut loader = FlashLoader::new(
+            vec![MemoryRegion::Nvm(region)],
+            TargetDescriptionSource::BuiltIn,
+        );
+        for (address, bytes) in data {
+            loader.add_data(*address, bytes).unwrap();
+        }
+        loader
+    }
+
+    #[test]
+    fn add_image() {
+        let mut application = loader(&[(0x1000, &[1, 2, 3, 4])]);
+        let softdevice = loader(&[(0x0, &[5, 6, 7, 8]), (0x800, &[9])]);
+
+        application
+            .add_image(Path::new("softdevice.hex"), &softdevice)
+            .unwrap();
+        assert_eq!(
+            application.data().collect::<Vec<_>>(),
+            [
+                (0x0, &[5, 6, 7, 8][..]),
+                (0x800, &[9][..]),
+                (0x1000, &[1, 2, 3, 4][..])
+            ]
+        );
+    }
+
+    #[test]
+    fn add_overlapping_image() {
+        let mut application = loader(&[(0x1000, &[1, 2, 3, 4])]);
+        let softdevice = loader(&[(0xFFE, &[5, 6, 7, 8])]);
+
+        assert!(matches!(
+            application.add_image(Path::new("softdevice.hex"), &softdevice),
+            Err(FileDownloadError::ImageOverlaps { addresses, existing, .. })
+                if addresses == (0xFFE..0x1002) && existing == (0x1000..0x1004)
+        ));
+    }
+
+    #[test]
+    fn data_at_an_alias_is_moved_to_the_memory() {
+        let mut loader = loader(&[]);
+        loader.add_alias(MemoryAlias {
+            name: None,
+            range: 0x0800_0000..0x0801_0000,
+            target: 0,
+            kind: MemoryAliasKind::Mirror,
+        });
+
+        loader.add_data(0x0800_1000, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            loader.data().collect::<Vec<_>>(),
+            [(0x1000, &[1, 2, 3, 4][..])]
+        );
+        // Data which leaves the alias is not moved.
+        assert!(loader.add_data(0x0800_FFFE, &[1, 2, 3, 4]).is_err());
+    }
+}
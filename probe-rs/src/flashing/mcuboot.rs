@@ -0,0 +1,916 @@
+//! Images of the MCUboot bootloader.
+//!
+//! MCUboot only boots images which start with its header, and are followed by TLVs (type-length-value records) with
+//! their hash and optionally a signature. [`create_mcuboot_image`] builds such an image from a binary like `imgtool sign`,
+//! and [`McubootImage::parse`] validates one. The trailer at the end of a slot tells MCUboot whether the image in the
+//! secondary slot is swapped in for a test or permanently, and whether the image in the primary slot was confirmed.
+//! An [`McubootSlot`] reads and writes it in a [`Storage`] of the slot.
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{
+    EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING,
+    RSA_PSS_SHA256,
+};
+
+use super::{FileSystemError, Storage};
+use crate::Session;
+
+const IMAGE_MAGIC: u32 = 0x96F3_B83D;
+const HEADER_SIZE: usize = 32;
+
+const TLV_INFO_MAGIC: u16 = 0x6907;
+const TLV_PROTECTED_INFO_MAGIC: u16 = 0x6908;
+const TLV_KEYHASH: u16 = 0x01;
+const TLV_SHA256: u16 = 0x10;
+const TLV_RSA2048_PSS: u16 = 0x20;
+const TLV_ECDSA_SIG: u16 = 0x22;
+const TLV_RSA3072_PSS: u16 = 0x23;
+const TLV_ED25519: u16 = 0x24;
+
+/// The magic at the end of a slot, for the default `BOOT_MAX_ALIGN` of 8 bytes.
+const TRAILER_MAGIC: [u8; 16] = [
+    0x77, 0xC2, 0x95, 0xF3, 0x60, 0xD2, 0xEF, 0x7F, 0x35, 0x52, 0x50, 0x0F, 0x2C, 0xB6, 0x79, 0x80,
+];
+/// The alignment of the fields of the trailer, the default `BOOT_MAX_ALIGN` of MCUboot.
+const TRAILER_ALIGN: u64 = 8;
+/// The offsets of the fields of the trailer from the end of the slot.
+const MAGIC_OFFSET: u64 = TRAILER_MAGIC.len() as u64;
+const IMAGE_OK_OFFSET: u64 = MAGIC_OFFSET + TRAILER_ALIGN;
+const COPY_DONE_OFFSET: u64 = IMAGE_OK_OFFSET + TRAILER_ALIGN;
+/// The size of the fields of the trailer: the magic, `image_ok`, `copy_done`, `swap_info` and `swap_size`.
+const TRAILER_FIELDS_SIZE: u64 = MAGIC_OFFSET + 4 * TRAILER_ALIGN;
+/// The value of a set flag of the trailer.
+const FLAG_SET: u8 = 0x01;
+
+/// The SubjectPublicKeyInfo of a key, without the key itself.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2A, 0x30, 0x05, 0x06, 0x03, 0x2B, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01, 0x06, 0x08, 0x2A,
+    0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+/// An error while creating, validating or marking an MCUboot image.
+#[derive(Debug, thiserror::Error)]
+pub enum McubootError {
+    /// Reading or writing the slot failed.
+    #[error("Accessing the slot failed")]
+    Storage(#[from] FileSystemError),
+    /// The data does not start with an MCUboot header.
+    #[error("The data is not an MCUboot image.")]
+    NoImage,
+    /// The header or the TLVs of the image are invalid.
+    #[error("The MCUboot image is invalid: {0}.")]
+    Invalid(&'static str),
+    /// The SHA-256 TLV of the image does not match its contents.
+    #[error("The hash of the MCUboot image does not match its contents.")]
+    HashMismatch,
+    /// The image overlaps the trailer of the slot.
+    #[error("The image of {size} bytes does not fit into the slot of {slot_size} bytes, together with its trailer of {trailer_size} bytes.")]
+    TooLarge {
+        /// The size of the image with its TLVs.
+        size: u64,
+        /// The size of the slot.
+        slot_size: u64,
+        /// The size of the trailer at the end of the slot.
+        trailer_size: u64,
+    },
+    /// The trailer of the slot contains an invalid value.
+    #[error("The trailer of the slot is corrupted, its {0} is invalid.")]
+    CorruptTrailer(&'static str),
+    /// The version is not of the form `major.minor.revision+build`.
+    #[error("{0:?} is not a valid image version of the form `major.minor.revision+build`.")]
+    InvalidVersion(String),
+    /// The signing key can not be used.
+    #[error("The signing key is not supported: {0}.")]
+    UnsupportedKey(&'static str),
+    /// Signing the image failed.
+    #[error("Signing the image failed.")]
+    Signing,
+}
+
+/// The version of an MCUboot image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ImageVersion {
+    /// The major version.
+    pub major: u8,
+    /// The minor version.
+    pub minor: u8,
+    /// The revision.
+    pub revision: u16,
+    /// The build number.
+    pub build: u32,
+}
+
+impl FromStr for ImageVersion {
+    type Err = McubootError;
+
+    /// Parse a version like imgtool: `major[.minor[.revision]][+build]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || McubootError::InvalidVersion(s.to_owned());
+        let (numbers, build) = match s.split_once('+') {
+            Some((numbers, build)) => (numbers, build.parse().map_err(|_| invalid())?),
+            None => (s, 0),
+        };
+
+        let mut parts = numbers.split('.');
+        let mut next = || parts.next().map(str::parse::<u16>).transpose();
+        let major = next().map_err(|_| invalid())?.ok_or_else(invalid)?;
+        let minor = next().map_err(|_| invalid())?.unwrap_or(0);
+        let revision = next().map_err(|_| invalid())?.unwrap_or(0);
+        if next().map_err(|_| invalid())?.is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            major: major.try_into().map_err(|_| invalid())?,
+            minor: minor.try_into().map_err(|_| invalid())?,
+            revision,
+            build,
+        })
+    }
+}
+
+impl fmt::Display for ImageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}+{}",
+            self.major, self.minor, self.revision, self.build
+        )
+    }
+}
+
+/// The algorithm of the signature of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureType {
+    /// RSA-2048 with PSS padding.
+    Rsa2048,
+    /// RSA-3072 with PSS padding.
+    Rsa3072,
+    /// ECDSA with SHA-256.
+    Ecdsa,
+    /// Ed25519 of the SHA-256 of the image.
+    Ed25519,
+}
+
+enum KeyKind {
+    Ed25519(Ed25519KeyPair),
+    EcdsaP256(EcdsaKeyPair),
+    Rsa(RsaKeyPair),
+}
+
+/// A private key which signs images, like the keys generated by `imgtool keygen`.
+///
+/// Ed25519, ECDSA P-256, RSA-2048 and RSA-3072 keys are supported.
+pub struct McubootKey {
+    kind: KeyKind,
+}
+
+impl McubootKey {
+    /// Load a key from a PEM file, with a PKCS#8 key or a PKCS#1 RSA key.
+    pub fn from_pem(pem: &str) -> Result<Self, McubootError> {
+        let (label, der) =
+            pem_block(pem).ok_or(McubootError::UnsupportedKey("no PEM block was found"))?;
+        match label {
+            "PRIVATE KEY" => Self::from_pkcs8(&der),
+            "RSA PRIVATE KEY" => Self::rsa(
+                RsaKeyPair::from_der(&der)
+                    .map_err(|_| McubootError::UnsupportedKey("the RSA key is invalid"))?,
+            ),
+            "EC PRIVATE KEY" => Err(McubootError::UnsupportedKey(
+                "EC keys have to be converted to PKCS#8",
+            )),
+            _ => Err(McubootError::UnsupportedKey(
+                "the PEM block is no private key",
+            )),
+        }
+    }
+
+    /// Load a key from its PKCS#8 DER encoding.
+    pub fn from_pkcs8(der: &[u8]) -> Result<Self, McubootError> {
+        if let Ok(key) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der) {
+            return Ok(Self {
+                kind: KeyKind::Ed25519(key),
+            });
+        }
+        if let Ok(key) = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, der) {
+            return Ok(Self {
+                kind: KeyKind::EcdsaP256(key),
+            });
+        }
+        match RsaKeyPair::from_pkcs8(der) {
+            Ok(key) => Self::rsa(key),
+            Err(_) => Err(McubootError::UnsupportedKey(
+                "only Ed25519, ECDSA P-256 and RSA keys are supported",
+            )),
+        }
+    }
+
+    fn rsa(key: RsaKeyPair) -> Result<Self, McubootError> {
+        match key.public_modulus_len() {
+            256 | 384 => Ok(Self {
+                kind: KeyKind::Rsa(key),
+            }),
+            _ => Err(McubootError::UnsupportedKey(
+                "only RSA keys of 2048 and 3072 bits are supported",
+            )),
+        }
+    }
+
+    /// The type of the signatures of this key.
+    pub fn signature_type(&self) -> SignatureType {
+        match &self.kind {
+            KeyKind::Ed25519(_) => SignatureType::Ed25519,
+            KeyKind::EcdsaP256(_) => SignatureType::Ecdsa,
+            KeyKind::Rsa(key) if key.public_modulus_len() == 256 => SignatureType::Rsa2048,
+            KeyKind::Rsa(_) => SignatureType::Rsa3072,
+        }
+    }
+
+    /// The SHA-256 of the public key, as stored in the image and compared by MCUboot with its built-in keys.
+    pub fn public_key_hash(&self) -> [u8; 32] {
+        let public_key = match &self.kind {
+            KeyKind::Ed25519(key) => [&ED25519_SPKI_PREFIX[..], key.public_key().as_ref()].concat(),
+            KeyKind::EcdsaP256(key) => [&P256_SPKI_PREFIX[..], key.public_key().as_ref()].concat(),
+            KeyKind::Rsa(key) => key.public_key().as_ref().to_vec(),
+        };
+        sha256(&public_key)
+    }
+
+    /// The TLV type and the signature of the image `payload` with the hash `hash`.
+    fn sign(&self, payload: &[u8], hash: &[u8; 32]) -> Result<(u16, Vec<u8>), McubootError> {
+        let rng = SystemRandom::new();
+        match &self.kind {
+            // MCUboot verifies Ed25519 signatures of the hash, not of the image.
+            KeyKind::Ed25519(key) => Ok((TLV_ED25519, key.sign(hash).as_ref().to_vec())),
+            KeyKind::EcdsaP256(key) => {
+                let signature = key.sign(&rng, payload).map_err(|_| McubootError::Signing)?;
+                Ok((TLV_ECDSA_SIG, signature.as_ref().to_vec()))
+            }
+            KeyKind::Rsa(key) => {
+                let mut signature = vec![0; key.public_modulus_len()];
+                key.sign(&RSA_PSS_SHA256, &rng, payload, &mut signature)
+                    .map_err(|_| McubootError::Signing)?;
+                let tlv = match self.signature_type() {
+                    SignatureType::Rsa2048 => TLV_RSA2048_PSS,
+                    _ => TLV_RSA3072_PSS,
+                };
+                Ok((tlv, signature))
+            }
+        }
+    }
+}
+
+/// Options for [`create_mcuboot_image`].
+///
+/// The defaults match the ones of `imgtool sign` with a header of 512 bytes.
+#[non_exhaustive]
+pub struct McubootImageOptions {
+    /// The version of the image.
+    pub version: ImageVersion,
+    /// The size of the header, which the binary has to be linked for: the vector table of the firmware starts after
+    /// it.
+    pub header_size: u16,
+    /// The address at which the image is loaded, for images which are copied to RAM.
+    pub load_address: u32,
+    /// The flags of the header.
+    pub flags: u32,
+    /// The key which signs the image. Without it, the image only carries its hash.
+    pub key: Option<McubootKey>,
+    /// The size of the slot, to which the image is padded with the trailer, so it is swapped in at the next reset.
+    /// Without it, the image is not padded.
+    pub slot_size: Option<u64>,
+    /// Mark the pending image as confirmed, so MCUboot does not revert it.
+    pub confirm: bool,
+    /// The value of erased flash, with which the image is padded.
+    pub erased_value: u8,
+    /// The maximum number of sectors of a slot, for whose swap status MCUboot reserves space in the trailer.
+    pub max_sectors: u64,
+    /// The write size of the flash, which is the size of an entry of the swap status.
+    pub write_size: u64,
+}
+
+impl Default for McubootImageOptions {
+    fn default() -> Self {
+        Self {
+            version: ImageVersion::default(),
+            header_size: 0x200,
+            load_address: 0,
+            flags: 0,
+            key: None,
+            slot_size: None,
+            confirm: false,
+            erased_value: 0xFF,
+            max_sectors: 128,
+            write_size: 8,
+        }
+    }
+}
+
+impl McubootImageOptions {
+    /// The size of the trailer at the end of the slot, which the image must not overlap.
+    pub fn trailer_size(&self) -> u64 {
+        self.max_sectors * 3 * self.write_size + TRAILER_FIELDS_SIZE
+    }
+
+    /// The trailer at the end of the slot. If the image is `pending`, it is swapped in at the next reset, for a test
+    /// or permanently if it is confirmed. Otherwise the trailer is erased.
+    pub fn trailer(&self, pending: bool) -> Vec<u8> {
+        let mut trailer = vec![self.erased_value; self.trailer_size() as usize];
+        let end = trailer.len();
+        if pending {
+            trailer[end - MAGIC_OFFSET as usize..].copy_from_slice(&TRAILER_MAGIC);
+            if self.confirm {
+                trailer[end - IMAGE_OK_OFFSET as usize] = FLAG_SET;
+            }
+        }
+        trailer
+    }
+}
+
+/// Create an MCUboot image from `binary`, a firmware linked to start after the header.
+pub fn create_mcuboot_image(
+    binary: &[u8],
+    options: &McubootImageOptions,
+) -> Result<Vec<u8>, McubootError> {
+    let header_size = options.header_size as usize;
+    if header_size < HEADER_SIZE {
+        return Err(McubootError::Invalid(
+            "the header size is smaller than the header",
+        ));
+    }
+    let image_size = u32::try_from(binary.len())
+        .map_err(|_| McubootError::Invalid("the binary is larger than 4 GiB"))?;
+
+    let mut image = vec![0; header_size];
+    let version = options.version;
+    let header = [
+        &IMAGE_MAGIC.to_le_bytes()[..],
+        &options.load_address.to_le_bytes(),
+        &options.header_size.to_le_bytes(),
+        // There are no protected TLVs.
+        &0u16.to_le_bytes(),
+        &image_size.to_le_bytes(),
+        &options.flags.to_le_bytes(),
+        &[version.major, version.minor],
+        &version.revision.to_le_bytes(),
+        &version.build.to_le_bytes(),
+    ]
+    .concat();
+    image[..header.len()].copy_from_slice(&header);
+    image.extend_from_slice(binary);
+
+    let hash = sha256(&image);
+    let mut tlvs = vec![];
+    push_tlv(&mut tlvs, TLV_SHA256, &hash);
+    if let Some(key) = &options.key {
+        push_tlv(&mut tlvs, TLV_KEYHASH, &key.public_key_hash());
+        let (tlv, signature) = key.sign(&image, &hash)?;
+        push_tlv(&mut tlvs, tlv, &signature);
+    }
+    image.extend_from_slice(&TLV_INFO_MAGIC.to_le_bytes());
+    image.extend_from_slice(&((tlvs.len() + 4) as u16).to_le_bytes());
+    image.extend_from_slice(&tlvs);
+
+    if let Some(slot_size) = options.slot_size {
+        let trailer_size = options.trailer_size();
+        if image.len() as u64 + trailer_size > slot_size {
+            return Err(McubootError::TooLarge {
+                size: image.len() as u64,
+                slot_size,
+                trailer_size,
+            });
+        }
+        image.resize((slot_size - trailer_size) as usize, options.erased_value);
+        image.extend_from_slice(&options.trailer(true));
+    }
+
+    Ok(image)
+}
+
+/// The header and the TLVs of a valid MCUboot image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McubootImage {
+    /// The version of the image.
+    pub version: ImageVersion,
+    /// The size of the header.
+    pub header_size: u16,
+    /// The size of the firmware after the header.
+    pub image_size: u32,
+    /// The address at which the image is loaded, for images which are copied to RAM.
+    pub load_address: u32,
+    /// The flags of the header.
+    pub flags: u32,
+    /// The SHA-256 of the image, which matches its contents.
+    pub hash: [u8; 32],
+    /// The SHA-256 of the public key which signed the image.
+    pub key_hash: Option<[u8; 32]>,
+    /// The algorithm of the signature of the image.
+    pub signature: Option<SignatureType>,
+    /// The size of the image with its TLVs.
+    pub size: u64,
+}
+
+impl McubootImage {
+    /// Parse and validate the image at the start of `data`, and check its hash.
+    ///
+    /// The signature is not verified, as it needs the public key which is built into MCUboot.
+    pub fn parse(data: &[u8]) -> Result<Self, McubootError> {
+        if data.len() < HEADER_SIZE || read_u32(data, 0) != IMAGE_MAGIC {
+            return Err(McubootError::NoImage);
+        }
+        let load_address = read_u32(data, 4);
+        let header_size = read_u16(data, 8);
+        let protected_tlv_size = read_u16(data, 10) as usize;
+        let image_size = read_u32(data, 12);
+        let flags = read_u32(data, 16);
+        let version = ImageVersion {
+            major: data[20],
+            minor: data[21],
+            revision: read_u16(data, 22),
+            build: read_u32(data, 24),
+        };
+        if (header_size as usize) < HEADER_SIZE {
+            return Err(McubootError::Invalid(
+                "the header size is smaller than the header",
+            ));
+        }
+
+        let tlv_start = header_size as usize + image_size as usize;
+        let hashed_end = tlv_start + protected_tlv_size;
+        if data.len() < hashed_end + 4 {
+            return Err(McubootError::Invalid("the image is truncated"));
+        }
+        if protected_tlv_size > 0
+            && (read_u16(data, tlv_start) != TLV_PROTECTED_INFO_MAGIC
+                || read_u16(data, tlv_start + 2) as usize != protected_tlv_size)
+        {
+            return Err(McubootError::Invalid("the protected TLVs are invalid"));
+        }
+        if read_u16(data, hashed_end) != TLV_INFO_MAGIC {
+            return Err(McubootError::Invalid("the TLVs are missing"));
+        }
+        let tlv_end = hashed_end + read_u16(data, hashed_end + 2) as usize;
+        if tlv_end < hashed_end + 4 || data.len() < tlv_end {
+            return Err(McubootError::Invalid("the TLVs are truncated"));
+        }
+
+        let mut hash = None;
+        let mut key_hash = None;
+        let mut signature = None;
+        let mut offset = hashed_end + 4;
+        while offset < tlv_end {
+            if offset + 4 > tlv_end {
+                return Err(McubootError::Invalid("the TLVs are truncated"));
+            }
+            let tlv = read_u16(data, offset);
+            let value = offset + 4..offset + 4 + read_u16(data, offset + 2) as usize;
+            if value.end > tlv_end {
+                return Err(McubootError::Invalid("the TLVs are truncated"));
+            }
+            let value = &data[value];
+            match tlv {
+                TLV_SHA256 => {
+                    hash = Some(
+                        value
+                            .try_into()
+                            .map_err(|_| McubootError::Invalid("the hash has a wrong size"))?,
+                    )
+                }
+                TLV_KEYHASH => key_hash = value.try_into().ok(),
+                TLV_RSA2048_PSS => signature = Some(SignatureType::Rsa2048),
+                TLV_RSA3072_PSS => signature = Some(SignatureType::Rsa3072),
+                TLV_ECDSA_SIG => signature = Some(SignatureType::Ecdsa),
+                TLV_ED25519 => signature = Some(SignatureType::Ed25519),
+                _ => {}
+            }
+            offset += 4 + value.len();
+        }
+
+        let hash: [u8; 32] = hash.ok_or(McubootError::Invalid("the hash is missing"))?;
+        if sha256(&data[..hashed_end]) != hash {
+            return Err(McubootError::HashMismatch);
+        }
+
+        Ok(Self {
+            version,
+            header_size,
+            image_size,
+            load_address,
+            flags,
+            hash,
+            key_hash,
+            signature,
+            size: tlv_end as u64,
+        })
+    }
+}
+
+/// The state of a field of the trailer of a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailerState {
+    /// The field is erased.
+    Unset,
+    /// The field is set: the magic is valid, or the flag is 1.
+    Set,
+    /// The field contains another value.
+    Bad,
+}
+
+/// The trailer at the end of a slot, through which the application and MCUboot agree on swapping images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotTrailer {
+    /// The magic, which is set once an image was marked for a swap.
+    pub magic: TrailerState,
+    /// Whether the image was confirmed, so it is not reverted at the next reset.
+    pub image_ok: TrailerState,
+    /// Whether MCUboot finished copying the image.
+    pub copy_done: TrailerState,
+}
+
+/// An MCUboot slot in a [`Storage`] of its range, e.g. a [`FlashStorage`](super::FlashStorage).
+pub struct McubootSlot<S> {
+    storage: S,
+    erased_value: u8,
+}
+
+impl<S: Storage> McubootSlot<S> {
+    /// The slot in `storage`, whose erased flash reads as `erased_value`.
+    pub fn new(storage: S, erased_value: u8) -> Self {
+        Self {
+            storage,
+            erased_value,
+        }
+    }
+
+    /// The storage of the slot.
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
+    /// The image at the start of the slot, if there is one.
+    pub fn image(&mut self, session: &mut Session) -> Result<Option<McubootImage>, McubootError> {
+        let mut header = [0; HEADER_SIZE];
+        self.storage.read(session, 0, &mut header)?;
+        if read_u32(&header, 0) != IMAGE_MAGIC {
+            return Ok(None);
+        }
+
+        // The size of the TLVs is read first, to read the whole image once.
+        let info = read_u16(&header, 8) as u64
+            + read_u32(&header, 12) as u64
+            + read_u16(&header, 10) as u64;
+        if info + 4 > self.storage.size() {
+            return Err(McubootError::Invalid("the image is larger than the slot"));
+        }
+        let mut tlv_info = [0; 4];
+        self.storage.read(session, info, &mut tlv_info)?;
+        let size = (info + read_u16(&tlv_info, 2) as u64).min(self.storage.size());
+
+        let mut data = vec![0; size as usize];
+        self.storage.read(session, 0, &mut data)?;
+        McubootImage::parse(&data).map(Some)
+    }
+
+    /// The trailer at the end of the slot.
+    pub fn trailer(&mut self, session: &mut Session) -> Result<SlotTrailer, McubootError> {
+        let mut trailer = [0; TRAILER_FIELDS_SIZE as usize];
+        let start = self.trailer_start()?;
+        self.storage.read(session, start, &mut trailer)?;
+
+        let end = trailer.len();
+        let magic = &trailer[end - MAGIC_OFFSET as usize..];
+        let magic = if magic == TRAILER_MAGIC {
+            TrailerState::Set
+        } else if magic.iter().all(|byte| *byte == self.erased_value) {
+            TrailerState::Unset
+        } else {
+            TrailerState::Bad
+        };
+
+        Ok(SlotTrailer {
+            magic,
+            image_ok: self.flag(trailer[end - IMAGE_OK_OFFSET as usize]),
+            copy_done: self.flag(trailer[end - COPY_DONE_OFFSET as usize]),
+        })
+    }
+
+    /// Mark the image in this secondary slot to be swapped in at the next reset, like `mcumgr image test`. Unless it
+    /// is `permanent`, MCUboot reverts it at the following reset if the firmware does not confirm itself.
+    pub fn mark_test(
+        &mut self,
+        session: &mut Session,
+        permanent: bool,
+    ) -> Result<(), McubootError> {
+        if self.image(session)?.is_none() {
+            return Err(McubootError::NoImage);
+        }
+        let trailer = self.trailer(session)?;
+        if trailer.magic == TrailerState::Bad {
+            return Err(McubootError::CorruptTrailer("magic"));
+        }
+
+        let end = self.storage.size();
+        if trailer.magic == TrailerState::Unset {
+            self.storage
+                .write(session, end - MAGIC_OFFSET, &TRAILER_MAGIC)?;
+        }
+        if permanent && trailer.image_ok != TrailerState::Set {
+            self.write_flag(session, end - IMAGE_OK_OFFSET)?;
+        }
+        self.storage.flush(session)?;
+        Ok(())
+    }
+
+    /// Confirm the image in this primary slot, which was swapped in for a test, so MCUboot keeps it. This is what the
+    /// firmware does with `boot_set_confirmed`.
+    pub fn mark_confirmed(&mut self, session: &mut Session) -> Result<(), McubootError> {
+        let trailer = self.trailer(session)?;
+        match trailer.magic {
+            // No swap happened, so there is nothing to revert.
+            TrailerState::Unset => return Ok(()),
+            TrailerState::Bad => return Err(McubootError::CorruptTrailer("magic")),
+            TrailerState::Set => {}
+        }
+        match trailer.image_ok {
+            TrailerState::Set => return Ok(()),
+            TrailerState::Bad => return Err(McubootError::CorruptTrailer("image_ok flag")),
+            TrailerState::Unset => {}
+        }
+
+        let end = self.storage.size();
+        self.write_flag(session, end - IMAGE_OK_OFFSET)?;
+        self.storage.flush(session)?;
+        Ok(())
+    }
+
+    fn trailer_start(&self) -> Result<u64, McubootError> {
+        self.storage
+            .size()
+            .checked_sub(TRAILER_FIELDS_SIZE)
+            .ok_or(McubootError::Invalid(
+                "the slot is smaller than its trailer",
+            ))
+    }
+
+    fn flag(&self, value: u8) -> TrailerState {
+        match value {
+            FLAG_SET => TrailerState::Set,
+            value if value == self.erased_value => TrailerState::Unset,
+            _ => TrailerState::Bad,
+        }
+    }
+
+    /// Set the flag at `offset`, which is written with the alignment of the trailer.
+    fn write_flag(&mut self, session: &mut Session, offset: u64) -> Result<(), McubootError> {
+        let mut field = [self.erased_value; TRAILER_ALIGN as usize];
+        field[0] = FLAG_SET;
+        self.storage.write(session, offset, &field)?;
+        Ok(())
+    }
+}
+
+fn push_tlv(tlvs: &mut Vec<u8>, tlv: u16, value: &[u8]) {
+    tlvs.extend_from_slice(&tlv.to_le_bytes());
+    tlvs.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    tlvs.extend_from_slice(value);
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(digest(&SHA256, data).as_ref());
+    hash
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// The label and the decoded contents of the first block of a PEM file.
+fn pem_block(pem: &str) -> Option<(&str, Vec<u8>)> {
+    let (_, rest) = pem.split_once("-----BEGIN ")?;
+    let (label, rest) = rest.split_once("-----")?;
+    let (body, _) = rest.split_once("-----END ")?;
+    let body = body.split_whitespace().collect::<String>();
+    Some((label, BASE64.decode(body).ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use ring::signature::{UnparsedPublicKey, ED25519};
+
+    use super::*;
+    use crate::{FakeProbe, Permissions, Probe};
+
+    struct MemoryStorage(Vec<u8>);
+
+    impl Storage for MemoryStorage {
+        fn size(&self) -> u64 {
+            self.0.len() as u64
+        }
+
+        fn read(
+            &mut self,
+            _session: &mut Session,
+            offset: u64,
+            data: &mut [u8],
+        ) -> Result<(), FileSystemError> {
+            data.copy_from_slice(&self.0[offset as usize..][..data.len()]);
+            Ok(())
+        }
+
+        fn write(
+            &mut self,
+            _session: &mut Session,
+            offset: u64,
+            data: &[u8],
+        ) -> Result<(), FileSystemError> {
+            self.0[offset as usize..][..data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn flush(&mut self, _session: &mut Session) -> Result<(), FileSystemError> {
+            Ok(())
+        }
+    }
+
+    fn session() -> Session {
+        Probe::from_specific_probe(Box::new(FakeProbe::new()))
+            .attach("stm32wb55ccux", Permissions::default())
+            .unwrap()
+    }
+
+    fn ed25519_key() -> McubootKey {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        McubootKey::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(
+            "1.2.3+4".parse::<ImageVersion>().unwrap(),
+            ImageVersion {
+                major: 1,
+                minor: 2,
+                revision: 3,
+                build: 4
+            }
+        );
+        assert_eq!(
+            "2.1".parse::<ImageVersion>().unwrap().to_string(),
+            "2.1.0+0"
+        );
+        assert!("1.2.3.4".parse::<ImageVersion>().is_err());
+        assert!("256.0".parse::<ImageVersion>().is_err());
+        assert!("".parse::<ImageVersion>().is_err());
+    }
+
+    #[test]
+    fn create_and_parse_image() {
+        let binary = vec![0xA5; 1000];
+        let options = McubootImageOptions {
+            version: "1.2.3+4".parse().unwrap(),
+            ..Default::default()
+        };
+
+        let image = create_mcuboot_image(&binary, &options).unwrap();
+        assert_eq!(image[..4], IMAGE_MAGIC.to_le_bytes());
+        assert_eq!(image[0x200..0x200 + 1000], binary[..]);
+
+        let parsed = McubootImage::parse(&image).unwrap();
+        assert_eq!(parsed.version, options.version);
+        assert_eq!(parsed.header_size, 0x200);
+        assert_eq!(parsed.image_size, 1000);
+        assert_eq!(parsed.signature, None);
+        // The info and the SHA-256 TLV.
+        assert_eq!(parsed.size, image.len() as u64);
+        assert_eq!(image.len(), 0x200 + 1000 + 4 + 4 + 32);
+    }
+
+    #[test]
+    fn modified_image_is_rejected() {
+        let mut image = create_mcuboot_image(&[0; 64], &McubootImageOptions::default()).unwrap();
+        image[0x200] = 1;
+
+        assert!(matches!(
+            McubootImage::parse(&image),
+            Err(McubootError::HashMismatch)
+        ));
+        assert!(matches!(
+            McubootImage::parse(&image[4..]),
+            Err(McubootError::NoImage)
+        ));
+    }
+
+    #[test]
+    fn signed_image() {
+        let key = ed25519_key();
+        let key_hash = key.public_key_hash();
+        let KeyKind::Ed25519(pair) = &key.kind else {
+            unreachable!()
+        };
+        let public_key = pair.public_key().as_ref().to_vec();
+
+        let image = create_mcuboot_image(
+            &[0x42; 300],
+            &McubootImageOptions {
+                key: Some(key),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let parsed = McubootImage::parse(&image).unwrap();
+        assert_eq!(parsed.signature, Some(SignatureType::Ed25519));
+        assert_eq!(parsed.key_hash, Some(key_hash));
+
+        // The signature is the last TLV, and signs the hash.
+        let signature = &image[image.len() - 64..];
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(&parsed.hash, signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn padded_and_confirmed_image() {
+        let options = McubootImageOptions {
+            slot_size: Some(0x1000),
+            confirm: true,
+            max_sectors: 4,
+            ..Default::default()
+        };
+        let image = create_mcuboot_image(&[0; 256], &options).unwrap();
+
+        assert_eq!(image.len(), 0x1000);
+        assert_eq!(image[0x1000 - 16..], TRAILER_MAGIC);
+        assert_eq!(
+            image[0x1000 - 24..0x1000 - 16],
+            [1, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+
+        let too_small = McubootImageOptions {
+            slot_size: Some(0x300),
+            ..Default::default()
+        };
+        assert!(matches!(
+            create_mcuboot_image(&[0; 256], &too_small),
+            Err(McubootError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_and_confirm_slots() {
+        let mut session = session();
+        let image = create_mcuboot_image(&[0x11; 512], &McubootImageOptions::default()).unwrap();
+        let mut data = vec![0xFF; 0x2000];
+        data[..image.len()].copy_from_slice(&image);
+
+        let mut secondary = McubootSlot::new(MemoryStorage(data), 0xFF);
+        assert_eq!(
+            secondary.image(&mut session).unwrap().unwrap().image_size,
+            512
+        );
+        assert_eq!(
+            secondary.trailer(&mut session).unwrap(),
+            SlotTrailer {
+                magic: TrailerState::Unset,
+                image_ok: TrailerState::Unset,
+                copy_done: TrailerState::Unset,
+            }
+        );
+
+        secondary.mark_test(&mut session, false).unwrap();
+        let trailer = secondary.trailer(&mut session).unwrap();
+        assert_eq!(trailer.magic, TrailerState::Set);
+        assert_eq!(trailer.image_ok, TrailerState::Unset);
+
+        // After the swap, the image is in the primary slot with the trailer of the test.
+        let mut primary = McubootSlot::new(secondary.into_storage(), 0xFF);
+        primary.mark_confirmed(&mut session).unwrap();
+        assert_eq!(
+            primary.trailer(&mut session).unwrap().image_ok,
+            TrailerState::Set
+        );
+
+        let mut empty = McubootSlot::new(MemoryStorage(vec![0xFF; 0x1000]), 0xFF);
+        assert!(matches!(
+            empty.mark_test(&mut session, true),
+            Err(McubootError::NoImage)
+        ));
+    }
+}
@@ -43,23 +43,67 @@
 //!
 //!
 
+mod algorithm_test;
+mod block_device;
+mod bootloader;
 mod builder;
 mod download;
 mod erase;
 mod error;
+mod filesystem;
 mod flash_algorithm;
 mod flasher;
+mod kept_ram;
 mod loader;
+#[cfg(feature = "mcuboot")]
+mod mcuboot;
+mod otp;
+mod patch;
+mod plan;
+mod programmer;
 mod progress;
+mod ram_image;
+mod spi_nor;
 mod visualizer;
 
 use builder::*;
 use flasher::*;
 
+pub use algorithm_test::{
+    test_flash_algorithm, AlgorithmTestOptions, AlgorithmTestStep, AlgorithmTestStepResult,
+};
+pub use block_device::{
+    BlockDevice, BlockDeviceProgrammer, Mmc, MmcController, MmcPartition, Nand, NandController,
+    NandEcc, NandLayout, Sdhci,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use bootloader::Stm32Dfu;
+pub use bootloader::{
+    download_file_with_bootloader, Bootloader, FlashSector, McubootSerial, Stm32Bootloader,
+    Uf2Drive,
+};
 pub use download::*;
 pub use erase::*;
 pub use error::*;
+#[cfg(feature = "filesystem")]
+pub use filesystem::{Fat, LittleFs};
+pub use filesystem::{FileEntry, FileSystem, FileSystemError, FlashStorage, Storage};
 pub use flash_algorithm::*;
+pub use kept_ram::RamSnapshot;
 pub use loader::*;
+#[cfg(feature = "mcuboot")]
+pub use mcuboot::{
+    create_mcuboot_image, ImageVersion, McubootError, McubootImage, McubootImageOptions,
+    McubootKey, McubootSlot, SignatureType, SlotTrailer, TrailerState,
+};
+pub use otp::OtpWordChange;
+pub use patch::ImagePatch;
+pub use plan::{FlashPlan, RegionPlan};
+pub use programmer::FlashProgrammer;
 pub use progress::*;
+pub use ram_image::*;
+pub use spi_nor::{
+    EraseType, SpiCommand, SpiController, SpiData, SpiNorGeometry, SpiNorProgrammer, Stm32Quadspi,
+    Stm32QuadspiFamily,
+};
 pub use visualizer::*;
@@ -0,0 +1,90 @@
+//! Safety checks for one-time programmable memory.
+//!
+//! Every bit of OTP memory can only be programmed once, so a mistake can not be undone. Data in regions marked
+//! with [`NvmRegion::is_otp`] is therefore only flashed if [`DownloadOptions::allow_otp_write`](super::DownloadOptions::allow_otp_write)
+//! is set, and [`FlashLoader::otp_changes`] shows which words would change before anything is written.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+use probe_rs_target::{MemoryRegion, NvmRegion};
+
+use super::{FlashError, FlashLoader};
+use crate::memory::MemoryInterface;
+use crate::session::Session;
+
+/// A 32-bit word of OTP memory which is changed by flashing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OtpWordChange {
+    /// The address of the word.
+    pub address: u64,
+    /// The current value of the word.
+    pub old: u32,
+    /// The value of the word after flashing.
+    pub new: u32,
+}
+
+/// The OTP regions of the loader which contain data.
+pub(super) fn regions_with_data(loader: &FlashLoader) -> impl Iterator<Item = &NvmRegion> {
+    loader
+        .memory_map
+        .iter()
+        .filter_map(move |region| match region {
+            MemoryRegion::Nvm(region)
+                if region.is_otp && loader.builder.has_data_in_range(&region.range) =>
+            {
+                Some(region)
+            }
+            _ => None,
+        })
+}
+
+/// Read the current contents of the OTP words which are written by the loader, and return the words which change.
+pub(super) fn changes(
+    loader: &FlashLoader,
+    session: &mut Session,
+) -> Result<Vec<OtpWordChange>, FlashError> {
+    let mut changes = Vec::new();
+
+    for region in regions_with_data(loader) {
+        let core_name = region
+            .cores
+            .first()
+            .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?;
+        let core_index = session
+            .target()
+            .core_index_by_name(core_name)
+            .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?;
+        let mut core = session.core(core_index).map_err(FlashError::Core)?;
+
+        // The old and the new value of every touched word.
+        let mut words: BTreeMap<u64, ([u8; 4], [u8; 4])> = BTreeMap::new();
+        for (address, data) in loader.builder.data_in_range(&region.range) {
+            for (offset, &byte) in data.iter().enumerate() {
+                let byte_address = address + offset as u64;
+                let word_address = byte_address & !0x3;
+
+                let word = match words.entry(word_address) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let mut old = [0; 4];
+                        core.read(word_address, &mut old)
+                            .map_err(FlashError::Core)?;
+                        entry.insert((old, old))
+                    }
+                };
+                word.1[(byte_address - word_address) as usize] = byte;
+            }
+        }
+
+        changes.extend(words.into_iter().filter(|(_, (old, new))| old != new).map(
+            |(address, (old, new))| OtpWordChange {
+                address,
+                old: u32::from_le_bytes(old),
+                new: u32::from_le_bytes(new),
+            },
+        ));
+    }
+
+    Ok(changes)
+}
@@ -0,0 +1,297 @@
+//! Patching of the image before it is flashed.
+//!
+//! Bootloaders often only start images which contain a valid checksum or CRC, and production images
+//! usually carry a serial number or version field which differs for every device. Instead of running external
+//! scripts on the image, these fields can be filled in by a list of [`ImagePatch`]es, which are applied to the
+//! loaded data in the given order, right before it is flashed.
+
+use serde::Deserialize;
+use std::ops::Range;
+
+use super::{FlashError, FlashLoader};
+
+/// A modification of the image, applied before flashing it.
+///
+/// All values are stored little-endian.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ImagePatch {
+    /// Store the two's complement of the sum of the 32-bit words in `start..end` at `address`,
+    /// so that all words in the range sum up to zero.
+    ///
+    /// The checksum may be part of the range (e.g. for the vector table checksum of NXP parts),
+    /// the old value at `address` is then ignored.
+    Checksum {
+        /// Start of the range, aligned to 4 bytes.
+        start: u64,
+        /// End of the range (exclusive), aligned to 4 bytes.
+        end: u64,
+        /// Where the 32-bit checksum is stored.
+        address: u64,
+    },
+    /// Store the CRC-32 (as used by Ethernet and zlib) of the bytes in `start..end` at `address`.
+    Crc32 {
+        /// Start of the range.
+        start: u64,
+        /// End of the range (exclusive).
+        end: u64,
+        /// Where the 32-bit CRC is stored, outside of the range.
+        address: u64,
+    },
+    /// Store a fixed value, e.g. a serial number or a version, at `address`.
+    Field {
+        /// Where the value is stored.
+        address: u64,
+        /// The value to store.
+        value: u64,
+        /// The size of the field in bytes, one of 1, 2, 4 or 8.
+        size: u8,
+    },
+}
+
+impl ImagePatch {
+    /// The bytes which are written by this patch, and where they are written.
+    fn evaluate(&self, loader: &FlashLoader) -> Result<(u64, Vec<u8>), FlashError> {
+        match *self {
+            ImagePatch::Checksum {
+                start,
+                end,
+                address,
+            } => {
+                if start % 4 != 0 || end % 4 != 0 {
+                    return Err(FlashError::InvalidImagePatch(format!(
+                        "The checksum range {start:#010x}..{end:#010x} is not aligned to 4 bytes"
+                    )));
+                }
+
+                let data = read(loader, start..end)?;
+                let sum = data
+                    .chunks_exact(4)
+                    .enumerate()
+                    .filter(|(index, _)| start + *index as u64 * 4 != address)
+                    .map(|(_, word)| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                    .fold(0u32, u32::wrapping_add);
+
+                Ok((address, sum.wrapping_neg().to_le_bytes().to_vec()))
+            }
+            ImagePatch::Crc32 {
+                start,
+                end,
+                address,
+            } => {
+                if address + 4 > start && address < end {
+                    return Err(FlashError::InvalidImagePatch(format!(
+                        "The CRC at {address:#010x} is part of the range {start:#010x}..{end:#010x} it is computed over"
+                    )));
+                }
+
+                let data = read(loader, start..end)?;
+                Ok((address, crc32(&data).to_le_bytes().to_vec()))
+            }
+            ImagePatch::Field {
+                address,
+                value,
+                size,
+            } => {
+                if !matches!(size, 1 | 2 | 4 | 8) {
+                    return Err(FlashError::InvalidImagePatch(format!(
+                        "The field size has to be 1, 2, 4 or 8 bytes, but is {size}"
+                    )));
+                }
+
+                if size < 8 && value >> (size * 8) != 0 {
+                    return Err(FlashError::InvalidImagePatch(format!(
+                        "The value {value:#x} does not fit into a field of {size} bytes"
+                    )));
+                }
+
+                Ok((address, value.to_le_bytes()[..size as usize].to_vec()))
+            }
+        }
+    }
+}
+
+fn read(loader: &FlashLoader, range: Range<u64>) -> Result<Vec<u8>, FlashError> {
+    loader
+        .builder
+        .get(range.clone())
+        .ok_or(FlashError::ImagePatchOutsideImage { range })
+}
+
+/// Apply the patches to the data in the loader, in order.
+pub(super) fn apply(loader: &mut FlashLoader, patches: &[ImagePatch]) -> Result<(), FlashError> {
+    for patch in patches {
+        let (address, bytes) = patch.evaluate(loader)?;
+        tracing::debug!(
+            "Patching {} bytes at {:#010x}: {:?}",
+            bytes.len(),
+            address,
+            patch
+        );
+
+        // Patches can also add data which is not part of the image, e.g. a serial number in a separate flash page.
+        if !loader.builder.overwrite(address, &bytes) {
+            loader.add_data(address, &bytes)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// CRC-32 with the polynomial 0x04C11DB7, reflected, as used by Ethernet and zlib.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MemoryRegion, NvmRegion, TargetDescriptionSource};
+
+    fn loader(data: &[(u64, &[u8])]) -> FlashLoader {
+        let region = NvmRegion {
+            name: Some("FLASH".into()),
+            is_boot_memory: true,
+            range: 0..1 << 16,
+            cores: vec!["main".into()],
+            is_otp: false,
+        };
+
+        let mut loader = FlashLoader::new(
+            vec![MemoryRegion::Nvm(region)],
+            TargetDescriptionSource::BuiltIn,
+        );
+        for (address, bytes) in data {
+            loader.add_data(*address, bytes).unwrap();
+        }
+        loader
+    }
+
+    fn contents(loader: &FlashLoader) -> Vec<(u64, Vec<u8>)> {
+        loader
+            .data()
+            .map(|(address, data)| (address, data.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn checksum_inside_range() {
+        let mut loader = loader(&[(0x0, &[1, 0, 0, 0, 2, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF])]);
+
+        apply(
+            &mut loader,
+            &[ImagePatch::Checksum {
+                start: 0,
+                end: 12,
+                address: 8,
+            }],
+        )
+        .unwrap();
+
+        assert_eq!(
+            contents(&loader),
+            vec![(0, vec![1, 0, 0, 0, 2, 0, 0, 0, 0xFD, 0xFF, 0xFF, 0xFF])]
+        );
+    }
+
+    #[test]
+    fn patches_are_applied_in_order() {
+        // Added in this order, the image consists of two chunks.
+        let mut loader = loader(&[(0x4, b"56789"), (0x0, b"x234")]);
+
+        apply(
+            &mut loader,
+            &[
+                ImagePatch::Field {
+                    address: 0x0,
+                    value: 0x31,
+                    size: 1,
+                },
+                ImagePatch::Crc32 {
+                    start: 0,
+                    end: 9,
+                    address: 0x100,
+                },
+                ImagePatch::Field {
+                    address: 0x200,
+                    value: 0x1234,
+                    size: 2,
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            contents(&loader),
+            vec![
+                (0, b"1234".to_vec()),
+                (4, b"56789".to_vec()),
+                (0x100, 0xCBF4_3926u32.to_le_bytes().to_vec()),
+                (0x200, vec![0x34, 0x12]),
+            ]
+        );
+    }
+
+    #[test]
+    fn range_outside_image_fails() {
+        let mut loader = loader(&[(0x0, &[0; 8]), (0x10, &[0; 8])]);
+
+        let result = apply(
+            &mut loader,
+            &[ImagePatch::Crc32 {
+                start: 0,
+                end: 0x18,
+                address: 0x20,
+            }],
+        );
+
+        assert!(matches!(
+            result,
+            Err(FlashError::ImagePatchOutsideImage { range }) if range == (0..0x18)
+        ));
+    }
+
+    #[test]
+    fn patches_are_deserialized() {
+        let patches: Vec<ImagePatch> = serde_yaml::from_str(
+            "
+            - type: checksum
+              start: 0
+              end: 28
+              address: 28
+            - type: field
+              address: 4096
+              value: 42
+              size: 4
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            patches[1],
+            ImagePatch::Field {
+                address: 4096,
+                value: 42,
+                size: 4
+            }
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! The plan of a flash download, which is computed without touching the target.
+
+use std::ops::Range;
+use std::time::Duration;
+
+use probe_rs_target::{MemoryRegion, NvmRegion};
+
+use super::flasher::assemble_algorithm;
+use super::{DownloadOptions, FlashError, FlashLayout, FlashLoader};
+use crate::Target;
+
+/// What [`FlashLoader::commit`] does with a set of [`DownloadOptions`], see [`FlashLoader::plan`].
+#[derive(Debug, Clone)]
+pub struct FlashPlan {
+    /// The NVM regions which are flashed with flash algorithms, ordered by their address.
+    pub regions: Vec<RegionPlan>,
+    /// The name, the range and the number of bytes of the programmers of the options which have data to program.
+    pub programmers: Vec<(String, Range<u64>, u64)>,
+    /// The ranges of RAM which are written after flashing.
+    pub ram: Vec<Range<u64>>,
+}
+
+impl FlashPlan {
+    /// The sum of the upper bounds of the time it takes to flash the regions.
+    pub fn max_duration(&self) -> Duration {
+        self.regions.iter().map(|region| region.max_duration).sum()
+    }
+}
+
+/// How an NVM region is flashed, see [`FlashPlan`].
+#[derive(Debug, Clone)]
+pub struct RegionPlan {
+    /// The region.
+    pub region: NvmRegion,
+    /// The name of the flash algorithm which flashes the region.
+    pub algorithm: String,
+    /// The name of the core which runs the flash algorithm.
+    pub core: String,
+    /// The address at which the flash algorithm is loaded into RAM.
+    pub algorithm_address: u64,
+    /// The whole flash of the algorithm is erased, before the region is programmed.
+    pub chip_erase: bool,
+    /// The sectors of the layout are erased, before the pages are programmed. Not set for a chip erase, if erasing
+    /// is skipped, and for OTP memory.
+    pub erase_sectors: bool,
+    /// The pages are programmed with double buffering.
+    pub double_buffering: bool,
+    /// The bytes of the programmed pages which are not part of the image are read from the flash and programmed
+    /// again, instead of being left erased.
+    pub restore_unwritten_bytes: bool,
+    /// The sectors which are erased, the pages which are programmed and the bytes which are restored.
+    pub layout: FlashLayout,
+    /// The upper bound of the time it takes to erase the sectors and program the pages, from the timeouts of the
+    /// flash algorithm.
+    pub max_duration: Duration,
+}
+
+impl RegionPlan {
+    /// The number of bytes which are read from the flash and programmed again.
+    pub fn restored_bytes(&self) -> u64 {
+        if self.restore_unwritten_bytes {
+            self.layout.fills().iter().map(|fill| fill.size()).sum()
+        } else {
+            0
+        }
+    }
+}
+
+impl FlashLoader {
+    /// Compute what [`Self::commit`] does with `options` on `target`, without touching the target: which sectors are
+    /// erased, which pages are programmed and which bytes are restored, with which flash algorithm.
+    pub fn plan(
+        &self,
+        target: &Target,
+        options: &DownloadOptions,
+    ) -> Result<FlashPlan, FlashError> {
+        super::programmer::check_programmer_ranges(&options.programmers, &self.memory_map)?;
+
+        let mut regions = Vec::new();
+        for ((algorithm_name, core_name), algorithm_regions) in
+            self.algorithm_regions(target, &options.programmers)?
+        {
+            // This can't fail, the names come from the target.
+            let raw = target.flash_algorithm_by_name(&algorithm_name).unwrap();
+            let core_index = target.core_index_by_name(&core_name).unwrap();
+            let algorithm = assemble_algorithm(target, core_index, raw, &options.keep_ram)?;
+
+            let chip_erase = options.do_chip_erase
+                && (target.has_sequence_erase_all() || algorithm.pc_erase_all.is_some());
+            let double_buffering =
+                algorithm.page_buffers.len() > 1 && !options.disable_double_buffering;
+            let properties = &algorithm.flash_properties;
+
+            for region in algorithm_regions {
+                let layout = self.builder.build_sectors_and_pages(
+                    &region,
+                    &algorithm,
+                    options.keep_unwritten_bytes,
+                )?;
+                let erase_sectors = !(options.skip_erase || chip_erase || region.is_otp);
+
+                let mut max_duration = Duration::from_millis(
+                    properties.program_page_timeout as u64 * layout.pages().len() as u64,
+                );
+                if erase_sectors {
+                    max_duration += Duration::from_millis(
+                        properties.erase_sector_timeout as u64 * layout.sectors().len() as u64,
+                    );
+                }
+
+                regions.push(RegionPlan {
+                    region,
+                    algorithm: algorithm_name.clone(),
+                    core: core_name.clone(),
+                    algorithm_address: algorithm.load_address,
+                    chip_erase,
+                    erase_sectors,
+                    double_buffering,
+                    restore_unwritten_bytes: options.keep_unwritten_bytes,
+                    layout,
+                    max_duration,
+                });
+            }
+        }
+        regions.sort_by_key(|plan| plan.region.range.start);
+
+        let programmers = options
+            .programmers
+            .iter()
+            .filter_map(|programmer| {
+                let range = programmer.range();
+                let bytes = self
+                    .builder
+                    .data_in_range(&range)
+                    .map(|(_, data)| data.len() as u64)
+                    .sum::<u64>();
+                (bytes > 0).then(|| (programmer.name().to_owned(), range, bytes))
+            })
+            .collect();
+
+        let ram = self
+            .memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Ram(region) => Some(region),
+                _ => None,
+            })
+            .flat_map(|region| self.builder.data_in_range(&region.range))
+            .map(|(address, data)| address..address + data.len() as u64)
+            .collect();
+
+        Ok(FlashPlan {
+            regions,
+            programmers,
+            ram,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::flashing::DownloadOptions;
+
+    #[test]
+    fn plan_erases_and_programs_the_touched_sectors() {
+        let target = crate::config::get_target_by_name("nRF52840_xxAA").unwrap();
+        let mut loader = target.flash_loader();
+        loader.add_data(0x1800, &[0x55; 0x1000]).unwrap();
+
+        let options = DownloadOptions {
+            keep_unwritten_bytes: true,
+            ..Default::default()
+        };
+        let plan = loader.plan(&target, &options).unwrap();
+
+        assert_eq!(plan.regions.len(), 1);
+        let region = &plan.regions[0];
+        assert!(region.erase_sectors);
+        let sectors = region
+            .layout
+            .sectors()
+            .iter()
+            .map(|sector| sector.address())
+            .collect::<Vec<_>>();
+        assert_eq!(sectors, [0x1000, 0x2000]);
+        assert_eq!(region.layout.pages().len(), 2);
+        assert_eq!(region.restored_bytes(), 0x1000);
+        // Two sectors of 3 s and two pages of 1 s.
+        assert_eq!(plan.max_duration(), Duration::from_secs(8));
+        assert!(plan.ram.is_empty());
+    }
+
+    #[test]
+    fn plan_without_erasing() {
+        let target = crate::config::get_target_by_name("nRF52840_xxAA").unwrap();
+        let mut loader = target.flash_loader();
+        loader.add_data(0x1000, &[0x55; 0x1000]).unwrap();
+        loader.add_data(0x2000_0000, &[1, 2, 3, 4]).unwrap();
+
+        let options = DownloadOptions {
+            skip_erase: true,
+            ..Default::default()
+        };
+        let plan = loader.plan(&target, &options).unwrap();
+
+        assert!(!plan.regions[0].erase_sectors);
+        assert_eq!(plan.regions[0].restored_bytes(), 0);
+        assert_eq!(plan.max_duration(), Duration::from_secs(1));
+        assert_eq!(plan.ram, [(0x2000_0000..0x2000_0004)]);
+    }
+}
@@ -0,0 +1,199 @@
+//! Host-side flash loaders, for memory which is not programmed by the CMSIS flash algorithms of the target.
+//!
+//! A [`FlashProgrammer`] programs an address range in its own way, e.g. through the ROM serial bootloader of a chip,
+//! or an external SPI NOR flash which is connected to GPIOs of the target. The programmers of
+//! [`DownloadOptions::programmers`](super::DownloadOptions::programmers) are used by
+//! [`download_file_with_options`](super::download_file_with_options) and [`FlashLoader::commit`](super::FlashLoader::commit):
+//! the data in their ranges is handed to them, and the rest of the image is flashed with the flash algorithms.
+
+use std::ops::Range;
+
+use probe_rs_target::{MemoryRange, MemoryRegion};
+
+use super::FlashError;
+use crate::Session;
+
+/// A flash loader which programs an address range on the host side, instead of with a flash algorithm that runs on
+/// the target.
+///
+/// The range may be outside of the memory map of the target, e.g. for external flash. If it overlaps an NVM region
+/// of the memory map, it has to contain the whole region, which is then not programmed by a flash algorithm.
+pub trait FlashProgrammer: Send {
+    /// The name of the programmer, for the logs.
+    fn name(&self) -> &str;
+
+    /// The address range which this programmer programs.
+    fn range(&self) -> Range<u64>;
+
+    /// Erase the whole range, before anything is programmed. This is called instead of erasing the sectors which are
+    /// programmed, if a chip erase was requested.
+    fn erase_all(&mut self, session: &mut Session) -> Result<(), FlashError>;
+
+    /// Program `data` at `address`, which is inside of [`Self::range`].
+    ///
+    /// If `erase` is set, the sectors which are programmed have to be erased first. It is not set after
+    /// [`Self::erase_all`], or if erasing was skipped.
+    fn program(
+        &mut self,
+        session: &mut Session,
+        address: u64,
+        data: &[u8],
+        erase: bool,
+    ) -> Result<(), FlashError>;
+
+    /// Read the memory at `address` into `data`, to verify what was programmed.
+    fn read(
+        &mut self,
+        session: &mut Session,
+        address: u64,
+        data: &mut [u8],
+    ) -> Result<(), FlashError>;
+}
+
+/// Check that the programmers only contain whole NVM regions, so no region is programmed twice.
+pub(super) fn check_programmer_ranges(
+    programmers: &[Box<dyn FlashProgrammer>],
+    memory_map: &[MemoryRegion],
+) -> Result<(), FlashError> {
+    for programmer in programmers {
+        let range = programmer.range();
+        for region in memory_map {
+            let MemoryRegion::Nvm(region) = region else {
+                continue;
+            };
+            if range.intersects_range(&region.range) && !range.contains_range(&region.range) {
+                return Err(FlashError::ProgrammerOverlapsRegion {
+                    name: programmer.name().to_owned(),
+                    range,
+                    region: region.range.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The size of the pieces in which the data is handed to a programmer, so large images are streamed with progress.
+const PIECE_SIZE: u64 = 1024 * 1024;
+
+/// Split `data` at `address` into pieces which do not cross a multiple of [`PIECE_SIZE`], so they are aligned to the
+/// blocks of a device.
+pub(super) fn pieces(mut address: u64, mut data: &[u8]) -> impl Iterator<Item = (u64, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+        let length = ((PIECE_SIZE - address % PIECE_SIZE) as usize).min(data.len());
+        let (piece, rest) = data.split_at(length);
+        let item = (address, piece);
+        address += length as u64;
+        data = rest;
+        Some(item)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::flashing::DownloadOptions;
+    use crate::{FakeProbe, Permissions, Probe};
+
+    /// The address, the data, and whether the sectors were erased, of the programmed chunks.
+    type Programmed = Arc<Mutex<Vec<(u64, Vec<u8>, bool)>>>;
+
+    /// An external flash at 0x9000_0000, outside of the memory map of the target.
+    #[derive(Default)]
+    struct ExternalFlash {
+        range: Option<Range<u64>>,
+        programmed: Programmed,
+    }
+
+    impl FlashProgrammer for ExternalFlash {
+        fn name(&self) -> &str {
+            "external"
+        }
+
+        fn range(&self) -> Range<u64> {
+            self.range.clone().unwrap_or(0x9000_0000..0x9100_0000)
+        }
+
+        fn erase_all(&mut self, _session: &mut Session) -> Result<(), FlashError> {
+            Ok(())
+        }
+
+        fn program(
+            &mut self,
+            _session: &mut Session,
+            address: u64,
+            data: &[u8],
+            erase: bool,
+        ) -> Result<(), FlashError> {
+            let mut programmed = self.programmed.lock().unwrap();
+            programmed.push((address, data.to_vec(), erase));
+            Ok(())
+        }
+
+        fn read(
+            &mut self,
+            _session: &mut Session,
+            address: u64,
+            data: &mut [u8],
+        ) -> Result<(), FlashError> {
+            let programmed = self.programmed.lock().unwrap();
+            let (_, written, _) = programmed.iter().find(|(a, _, _)| *a == address).unwrap();
+            data.copy_from_slice(written);
+            Ok(())
+        }
+    }
+
+    fn session() -> Session {
+        Probe::from_specific_probe(Box::new(FakeProbe::new()))
+            .attach("stm32wb55ccux", Permissions::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn data_in_the_range_of_a_programmer_is_programmed_by_it() {
+        let mut session = session();
+        let programmer = ExternalFlash::default();
+        let programmed = programmer.programmed.clone();
+
+        let mut loader = session.target().flash_loader();
+        assert!(loader.add_data(0x9000_0100, &[1, 2, 3, 4]).is_err());
+        loader.add_programmer_range(programmer.range());
+        loader.add_data(0x9000_0100, &[1, 2, 3, 4]).unwrap();
+
+        let options = DownloadOptions {
+            verify: true,
+            programmers: vec![Box::new(programmer)],
+            ..Default::default()
+        };
+        loader.commit(&mut session, options).unwrap();
+
+        assert_eq!(
+            *programmed.lock().unwrap(),
+            [(0x9000_0100, vec![1, 2, 3, 4], true)]
+        );
+    }
+
+    #[test]
+    fn programmers_contain_whole_flash_regions() {
+        let mut session = session();
+        let programmer = ExternalFlash {
+            range: Some(0x0800_0000..0x0800_1000),
+            ..Default::default()
+        };
+
+        let options = DownloadOptions {
+            programmers: vec![Box::new(programmer)],
+            ..Default::default()
+        };
+        let loader = session.target().flash_loader();
+        assert!(matches!(
+            loader.commit(&mut session, options),
+            Err(FlashError::ProgrammerOverlapsRegion { .. })
+        ));
+    }
+}
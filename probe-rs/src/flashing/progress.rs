@@ -1,4 +1,5 @@
 use super::FlashLayout;
+use crate::events::{self, FlashPhase, PhaseStatus, SessionEvent};
 use std::{sync::Arc, time::Duration};
 
 /// A structure to manage the flashing procedure progress reporting.
@@ -29,6 +30,9 @@ impl FlashProgress {
 
     /// Emit a flashing progress event.
     fn emit(&self, event: ProgressEvent) {
+        if let Some((phase, status)) = event.phase() {
+            events::emit(|| SessionEvent::FlashPhase { phase, status });
+        }
         (self.handler)(event);
     }
 
@@ -97,6 +101,15 @@ impl FlashProgress {
         self.emit(ProgressEvent::FinishedFilling);
     }
 
+    /// Signalize that a [`FlashProgrammer`](super::FlashProgrammer) programmed `programmed` of `total` bytes.
+    pub(super) fn programmer_progress(&self, name: &str, programmed: u64, total: u64) {
+        self.emit(ProgressEvent::ProgrammerProgress {
+            name: name.to_owned(),
+            programmed,
+            total,
+        });
+    }
+
     #[cfg(feature = "rtt")]
     pub(super) fn message(&self, message: String) {
         self.emit(ProgressEvent::DiagnosticMessage { message });
@@ -118,6 +131,7 @@ impl FlashProgress {
 /// * `StartedProgramming`
 /// * `PageProgrammed` for every page
 /// * `FinishedProgramming`
+/// * `ProgrammerProgress` for every piece of the data of a [`FlashProgrammer`](super::FlashProgrammer)
 ///
 /// If an erorr occurs in any stage, one of the `Failed*` event will be returned,
 /// and no further events will be returned.
@@ -170,9 +184,38 @@ pub enum ProgressEvent {
     FailedProgramming,
     /// Programming of the flash has finished successfully.
     FinishedProgramming,
+    /// A [`FlashProgrammer`](super::FlashProgrammer) programmed a part of its data.
+    ///
+    /// This is emitted after the flash algorithms finished, and is not part of the flash layout.
+    ProgrammerProgress {
+        /// The name of the programmer.
+        name: String,
+        /// The number of bytes which were programmed so far.
+        programmed: u64,
+        /// The number of bytes which the programmer programs.
+        total: u64,
+    },
     /// a message was received from the algo.
     DiagnosticMessage {
         /// The message that was emitted.
         message: String,
     },
 }
+
+impl ProgressEvent {
+    /// The phase which the event starts, finishes or fails, if any.
+    fn phase(&self) -> Option<(FlashPhase, PhaseStatus)> {
+        Some(match self {
+            ProgressEvent::StartedFilling => (FlashPhase::Filling, PhaseStatus::Started),
+            ProgressEvent::FinishedFilling => (FlashPhase::Filling, PhaseStatus::Finished),
+            ProgressEvent::FailedFilling => (FlashPhase::Filling, PhaseStatus::Failed),
+            ProgressEvent::StartedErasing => (FlashPhase::Erasing, PhaseStatus::Started),
+            ProgressEvent::FinishedErasing => (FlashPhase::Erasing, PhaseStatus::Finished),
+            ProgressEvent::FailedErasing => (FlashPhase::Erasing, PhaseStatus::Failed),
+            ProgressEvent::StartedProgramming => (FlashPhase::Programming, PhaseStatus::Started),
+            ProgressEvent::FinishedProgramming => (FlashPhase::Programming, PhaseStatus::Finished),
+            ProgressEvent::FailedProgramming => (FlashPhase::Programming, PhaseStatus::Failed),
+            _ => return None,
+        })
+    }
+}
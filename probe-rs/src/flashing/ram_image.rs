@@ -0,0 +1,201 @@
+//! Running images from RAM, for targets and experiments without a flash algorithm.
+//!
+//! An image which is linked to RAM is loaded by [`FlashLoader::commit`] without flashing anything. A reset would run
+//! the code in flash or in the boot ROM instead, so [`start_ram_image`] points the core to the image: on Cortex-M
+//! cores, the stack pointer and the reset handler are taken from the vector table at the start of the image. Other
+//! cores, and images without a vector table, start at the entry point of the ELF file.
+
+use std::time::Duration;
+
+use probe_rs_target::MemoryRegion;
+
+use super::FlashLoader;
+use crate::architecture::arm::core::registers::cortex_m::XPSR;
+use crate::{Core, CoreType, Error, MemoryInterface};
+
+/// Vector Table Offset Register of Cortex-M cores.
+const VTOR: u64 = 0xE000_ED08;
+
+/// How an image which was loaded into RAM is started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RamImageStart {
+    /// The address of the vector table at the start of the image, which VTOR is set to.
+    pub vector_table: Option<u64>,
+    /// The initial stack pointer from the vector table.
+    pub stack_pointer: Option<u32>,
+    /// The address of the first instruction.
+    pub entry_point: u64,
+}
+
+impl FlashLoader {
+    /// Whether all data of the image is in RAM, so that no flash algorithm is needed to load it.
+    pub fn is_ram_image(&self) -> bool {
+        !self.builder.data.is_empty()
+            && self.builder.data.iter().all(|(&address, data)| {
+                let last = address + (data.len() as u64).saturating_sub(1);
+                self.is_in_ram(address) && self.is_in_ram(last)
+            })
+    }
+
+    fn is_in_ram(&self, address: u64) -> bool {
+        matches!(
+            Self::get_region_for_address(&self.memory_map, address),
+            Some(MemoryRegion::Ram(_))
+        )
+    }
+
+    /// How to start the image on a core of type `core_type` after it was loaded into RAM.
+    ///
+    /// Returns `None` if the image is not completely in RAM, or if it has neither a vector table nor an entry point.
+    pub fn ram_image_start(&self, core_type: CoreType) -> Option<RamImageStart> {
+        if !self.is_ram_image() {
+            return None;
+        }
+
+        let vector_table = if core_type.is_cortex_m() {
+            self.vector_table()
+        } else {
+            None
+        };
+
+        vector_table.or_else(|| {
+            self.entry_point.map(|entry_point| RamImageStart {
+                vector_table: None,
+                stack_pointer: None,
+                entry_point,
+            })
+        })
+    }
+
+    /// The vector table at the start of the image, if its stack pointer is in RAM and its reset handler is Thumb
+    /// code of the image.
+    fn vector_table(&self) -> Option<RamImageStart> {
+        let (&address, data) = self.builder.data.iter().next()?;
+        let word = |index: usize| {
+            let bytes = data.get(index * 4..index * 4 + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().ok()?))
+        };
+        let stack_pointer = word(0)?;
+        let reset_handler = word(1)?;
+
+        let reset_address = u64::from(reset_handler & !1);
+        // VTOR can only point to a vector table which is aligned to 128 bytes.
+        let valid = address % 128 == 0
+            && stack_pointer != 0
+            && stack_pointer % 4 == 0
+            // The stack grows down, so the initial stack pointer may be the end of RAM.
+            && self.is_in_ram(u64::from(stack_pointer) - 1)
+            && reset_handler & 1 == 1
+            && self
+                .builder
+                .has_data_in_range(&(reset_address..reset_address + 2));
+
+        valid.then_some(RamImageStart {
+            vector_table: Some(address),
+            stack_pointer: Some(stack_pointer),
+            entry_point: reset_address,
+        })
+    }
+}
+
+/// Start an image which was loaded into RAM: the core is halted, VTOR, the stack pointer and the program counter are
+/// set, and the core is resumed.
+///
+/// The core is not reset, because that would run the code in flash or the boot ROM, which may overwrite the image.
+pub fn start_ram_image(core: &mut Core<'_>, start: &RamImageStart) -> Result<(), Error> {
+    tracing::info!("Starting the image in RAM at {:#010x}", start.entry_point);
+    core.halt(Duration::from_millis(100))?;
+
+    if core.core_type().is_cortex_m() {
+        if let Some(vector_table) = start.vector_table {
+            core.write_word_32(VTOR, vector_table as u32)?;
+        }
+        // Cortex-M cores only execute Thumb code.
+        core.write_core_reg(XPSR.id, 0x0100_0000u32)?;
+    }
+    if let Some(stack_pointer) = start.stack_pointer {
+        core.write_core_reg(core.stack_pointer().id, stack_pointer)?;
+    }
+    core.write_core_reg(core.program_counter().id, (start.entry_point & !1) as u32)?;
+
+    core.run()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{NvmRegion, RamRegion, TargetDescriptionSource};
+
+    fn loader(data: &[(u64, &[u8])]) -> FlashLoader {
+        let memory_map = vec![
+            MemoryRegion::Nvm(NvmRegion {
+                name: Some("FLASH".into()),
+                is_boot_memory: true,
+                range: 0x0800_0000..0x0801_0000,
+                cores: vec!["main".into()],
+                is_otp: false,
+            }),
+            MemoryRegion::Ram(RamRegion {
+                name: Some("RAM".into()),
+                is_boot_memory: false,
+                range: 0x2000_0000..0x2000_4000,
+                cores: vec!["main".into()],
+            }),
+        ];
+        let mut loader = FlashLoader::new(memory_map, TargetDescriptionSource::BuiltIn);
+        for (address, data) in data {
+            loader.add_data(*address, data).unwrap();
+        }
+        loader
+    }
+
+    fn vector_table(stack_pointer: u32, reset_handler: u32) -> Vec<u8> {
+        let mut data = [stack_pointer, reset_handler]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        data.resize(0x100, 0);
+        data
+    }
+
+    #[test]
+    fn cortex_m_images_start_at_the_reset_handler_of_their_vector_table() {
+        let loader = loader(&[(0x2000_0000, &vector_table(0x2000_4000, 0x2000_0081))]);
+
+        assert_eq!(
+            loader.ram_image_start(CoreType::Armv7em),
+            Some(RamImageStart {
+                vector_table: Some(0x2000_0000),
+                stack_pointer: Some(0x2000_4000),
+                entry_point: 0x2000_0080,
+            })
+        );
+    }
+
+    #[test]
+    fn images_without_vector_table_start_at_the_entry_point() {
+        let mut loader = loader(&[(0x2000_0000, &vector_table(0x1234_5678, 0x2000_0081))]);
+        assert_eq!(loader.ram_image_start(CoreType::Armv7em), None);
+
+        loader.entry_point = Some(0x2000_0010);
+        assert_eq!(
+            loader.ram_image_start(CoreType::Armv7em),
+            Some(RamImageStart {
+                vector_table: None,
+                stack_pointer: None,
+                entry_point: 0x2000_0010,
+            })
+        );
+    }
+
+    #[test]
+    fn images_with_data_in_flash_are_no_ram_images() {
+        let loader = loader(&[
+            (0x2000_0000, &vector_table(0x2000_4000, 0x2000_0081)),
+            (0x0800_0000, &[0; 4]),
+        ]);
+
+        assert!(!loader.is_ram_image());
+        assert_eq!(loader.ram_image_start(CoreType::Armv7em), None);
+    }
+}
@@ -0,0 +1,499 @@
+//! Programming of external SPI NOR flash, through a SPI or QSPI peripheral of the target.
+//!
+//! The flash is programmed with the standard command set of SPI NOR flash, which e.g. the W25Q and IS25 parts
+//! implement, so no flash algorithm is needed for it. Its size, page size and erase sizes are discovered from its SFDP,
+//! or from its JEDEC ID if it has none. The commands are sent by a [`SpiController`], which is a small shim for the
+//! registers of a peripheral, like [`Stm32Quadspi`].
+//!
+//! A [`SpiNorProgrammer`] is a [`FlashProgrammer`], so it is used with
+//! [`DownloadOptions::programmers`](super::DownloadOptions::programmers).
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use probe_rs_target::MemoryRange;
+
+use super::{FlashError, FlashProgrammer};
+use crate::Session;
+
+mod sfdp;
+mod stm32_quadspi;
+
+pub use stm32_quadspi::{Stm32Quadspi, Stm32QuadspiFamily};
+
+/// The largest flash which is addressed with 3 address bytes.
+const MAX_3_BYTE_SIZE: u64 = 1 << 24;
+
+const WRITE_ENABLE: u8 = 0x06;
+const READ_STATUS: u8 = 0x05;
+const PAGE_PROGRAM: u8 = 0x02;
+const READ: u8 = 0x03;
+const CHIP_ERASE: u8 = 0xC7;
+const READ_JEDEC_ID: u8 = 0x9F;
+const READ_SFDP: u8 = 0x5A;
+
+/// The Write In Progress bit of the status register.
+const STATUS_BUSY: u8 = 1 << 0;
+
+const PROGRAM_TIMEOUT: Duration = Duration::from_millis(100);
+const ERASE_TIMEOUT: Duration = Duration::from_secs(10);
+const CHIP_ERASE_TIMEOUT: Duration = Duration::from_secs(400);
+
+/// The data phase of a [`SpiCommand`].
+#[derive(Debug)]
+pub enum SpiData<'a> {
+    /// The command has no data.
+    None,
+    /// The data is written to the flash.
+    Write(&'a [u8]),
+    /// The data is read from the flash.
+    Read(&'a mut [u8]),
+}
+
+/// A command to a SPI NOR flash, which is sent on a single data line.
+#[derive(Debug)]
+pub struct SpiCommand<'a> {
+    /// The instruction.
+    pub opcode: u8,
+    /// The 3 byte address, if the command has one.
+    pub address: Option<u32>,
+    /// The number of clock cycles between the address and the data.
+    pub dummy_cycles: u8,
+    /// The data which is written or read.
+    pub data: SpiData<'a>,
+}
+
+/// A SPI or QSPI peripheral of the target, which sends commands to the flash connected to it.
+///
+/// The controller is operated through the memory interface of a core. The pins of the peripheral have to be
+/// configured already, e.g. by the firmware or by a debug sequence.
+pub trait SpiController: Send {
+    /// The name of the peripheral, for the logs.
+    fn name(&self) -> &str;
+
+    /// Send `command` to the flash, with the chip select asserted for the whole command.
+    fn execute(&mut self, session: &mut Session, command: SpiCommand<'_>)
+        -> Result<(), FlashError>;
+}
+
+/// An erase command of a SPI NOR flash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EraseType {
+    /// The size of the erased sector in bytes.
+    pub size: u32,
+    /// The instruction.
+    pub opcode: u8,
+}
+
+/// The geometry of a SPI NOR flash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpiNorGeometry {
+    /// The size of the flash in bytes.
+    pub size: u64,
+    /// The largest number of bytes which are programmed by one page program command.
+    pub page_size: u32,
+    /// The erase commands, from the smallest to the largest sector.
+    pub erase_types: Vec<EraseType>,
+}
+
+impl SpiNorGeometry {
+    /// The geometry of a flash with the standard 4 KiB sector and 64 KiB block erase commands, and 256 byte pages.
+    pub fn standard(size: u64) -> Self {
+        Self {
+            size,
+            page_size: 256,
+            erase_types: vec![
+                EraseType {
+                    size: 4 * 1024,
+                    opcode: 0x20,
+                },
+                EraseType {
+                    size: 64 * 1024,
+                    opcode: 0xD8,
+                },
+            ],
+        }
+    }
+}
+
+/// A [`FlashProgrammer`] for a SPI NOR flash, which is connected to a [`SpiController`].
+pub struct SpiNorProgrammer<C> {
+    name: String,
+    controller: C,
+    base_address: u64,
+    geometry: SpiNorGeometry,
+    /// The indices of the smallest sectors which were erased, so they are not erased again for the next chunk.
+    erased: BTreeSet<u64>,
+}
+
+impl<C: SpiController> SpiNorProgrammer<C> {
+    /// Discover the geometry of the flash, which is programmed at `base_address`, e.g. the address at which it is
+    /// mapped into the memory of the target.
+    pub fn new(
+        session: &mut Session,
+        mut controller: C,
+        base_address: u64,
+    ) -> Result<Self, FlashError> {
+        let sfdp = sfdp::read_geometry(|address, data| {
+            controller.execute(
+                session,
+                SpiCommand {
+                    opcode: READ_SFDP,
+                    address: Some(address),
+                    dummy_cycles: 8,
+                    data: SpiData::Read(data),
+                },
+            )
+        })?;
+
+        let mut geometry = match sfdp {
+            Some(geometry) => geometry,
+            None => {
+                let mut id = [0; 3];
+                controller.execute(
+                    session,
+                    SpiCommand {
+                        opcode: READ_JEDEC_ID,
+                        address: None,
+                        dummy_cycles: 0,
+                        data: SpiData::Read(&mut id),
+                    },
+                )?;
+                tracing::debug!("The SPI NOR flash has no SFDP, its JEDEC ID is {:02x?}", id);
+
+                // The last byte of the ID is the capacity, as a power of two, for most vendors.
+                if !(0x10..=0x20).contains(&id[2]) || id[0] == 0x00 || id[0] == 0xFF {
+                    return Err(FlashError::SpiNorNotFound(id));
+                }
+                SpiNorGeometry::standard(1 << id[2])
+            }
+        };
+
+        if geometry.size > MAX_3_BYTE_SIZE {
+            tracing::warn!(
+                "Only the first 16 MiB of the SPI NOR flash of {} bytes are programmed, with 3 byte addresses",
+                geometry.size
+            );
+            geometry.size = MAX_3_BYTE_SIZE;
+        }
+        if geometry.erase_types.is_empty() {
+            geometry.erase_types = SpiNorGeometry::standard(geometry.size).erase_types;
+        }
+
+        Ok(Self::with_geometry(controller, base_address, geometry))
+    }
+
+    /// Program a flash with a known geometry, at `base_address`.
+    pub fn with_geometry(controller: C, base_address: u64, geometry: SpiNorGeometry) -> Self {
+        Self {
+            name: format!("SPI NOR flash ({})", controller.name()),
+            controller,
+            base_address,
+            geometry,
+            erased: BTreeSet::new(),
+        }
+    }
+
+    /// The geometry of the flash.
+    pub fn geometry(&self) -> &SpiNorGeometry {
+        &self.geometry
+    }
+
+    fn command(
+        &mut self,
+        session: &mut Session,
+        opcode: u8,
+        address: Option<u32>,
+        data: SpiData<'_>,
+    ) -> Result<(), FlashError> {
+        self.controller.execute(
+            session,
+            SpiCommand {
+                opcode,
+                address,
+                dummy_cycles: 0,
+                data,
+            },
+        )
+    }
+
+    /// Run a command which modifies the flash, and wait until the flash finished it.
+    fn write_command(
+        &mut self,
+        session: &mut Session,
+        opcode: u8,
+        address: Option<u32>,
+        data: SpiData<'_>,
+        timeout: Duration,
+    ) -> Result<(), FlashError> {
+        self.command(session, WRITE_ENABLE, None, SpiData::None)?;
+        self.command(session, opcode, address, data)?;
+
+        let start = Instant::now();
+        loop {
+            let mut status = [0];
+            self.command(session, READ_STATUS, None, SpiData::Read(&mut status))?;
+            if status[0] & STATUS_BUSY == 0 {
+                return Ok(());
+            }
+            if start.elapsed() > timeout {
+                return Err(FlashError::SpiNorTimeout { opcode });
+            }
+        }
+    }
+
+    /// Erase the sectors which contain `range`, of offsets into the flash, which were not erased before. The
+    /// largest sectors are used which are fully contained in it.
+    fn erase(&mut self, session: &mut Session, range: Range<u64>) -> Result<(), FlashError> {
+        let smallest = self.geometry.erase_types[0];
+        let sector_size = smallest.size as u64;
+        let end = (range.end + sector_size - 1) / sector_size * sector_size;
+
+        let mut address = range.start / sector_size * sector_size;
+        while address < end {
+            if self.erased.contains(&(address / sector_size)) {
+                address += sector_size;
+                continue;
+            }
+
+            let erase_type = self
+                .geometry
+                .erase_types
+                .iter()
+                .rev()
+                .find(|erase_type| {
+                    let size = erase_type.size as u64;
+                    address % size == 0
+                        && address + size <= end
+                        && (address..address + size)
+                            .step_by(sector_size as usize)
+                            .all(|sector| !self.erased.contains(&(sector / sector_size)))
+                })
+                .copied()
+                .unwrap_or(smallest);
+
+            tracing::debug!(
+                "Erasing {} bytes at {:#x} of the SPI NOR flash",
+                erase_type.size,
+                address
+            );
+            self.write_command(
+                session,
+                erase_type.opcode,
+                Some(address as u32),
+                SpiData::None,
+                ERASE_TIMEOUT,
+            )?;
+
+            let erased_end = address + erase_type.size as u64;
+            self.erased.extend(
+                (address..erased_end)
+                    .step_by(sector_size as usize)
+                    .map(|sector| sector / sector_size),
+            );
+            address = erased_end;
+        }
+
+        Ok(())
+    }
+
+    /// The offset into the flash of `address`, checking that `length` bytes at it are in the flash.
+    fn offset(&self, address: u64, length: usize) -> Result<u64, FlashError> {
+        let flash = self.range();
+        let range = address..address + length as u64;
+        if !flash.contains_range(&range) {
+            return Err(FlashError::OutsideOfSpiNorFlash { range, flash });
+        }
+        Ok(address - self.base_address)
+    }
+}
+
+impl<C: SpiController> FlashProgrammer for SpiNorProgrammer<C> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> Range<u64> {
+        self.base_address..self.base_address + self.geometry.size
+    }
+
+    fn erase_all(&mut self, session: &mut Session) -> Result<(), FlashError> {
+        self.write_command(session, CHIP_ERASE, None, SpiData::None, CHIP_ERASE_TIMEOUT)
+    }
+
+    fn program(
+        &mut self,
+        session: &mut Session,
+        address: u64,
+        data: &[u8],
+        erase: bool,
+    ) -> Result<(), FlashError> {
+        let mut offset = self.offset(address, data.len())?;
+        if erase {
+            self.erase(session, offset..offset + data.len() as u64)?;
+        }
+
+        // A page program command wraps around at the end of the page, so it must not cross it.
+        let page_size = self.geometry.page_size as u64;
+        let mut data = data;
+        while !data.is_empty() {
+            let length = ((page_size - offset % page_size) as usize).min(data.len());
+            let (page, rest) = data.split_at(length);
+            self.write_command(
+                session,
+                PAGE_PROGRAM,
+                Some(offset as u32),
+                SpiData::Write(page),
+                PROGRAM_TIMEOUT,
+            )?;
+            offset += length as u64;
+            data = rest;
+        }
+
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        session: &mut Session,
+        address: u64,
+        data: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let mut offset = self.offset(address, data.len())?;
+        for chunk in data.chunks_mut(4096) {
+            let length = chunk.len() as u64;
+            self.command(session, READ, Some(offset as u32), SpiData::Read(chunk))?;
+            offset += length;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FakeProbe, Permissions, Probe};
+
+    /// A W25Q-like flash without SFDP, which only knows the commands which are used by the programmer.
+    struct SimulatedFlash {
+        memory: Vec<u8>,
+        write_enabled: bool,
+        /// The opcodes of the erase and program commands, and their addresses.
+        writes: Vec<(u8, u32)>,
+    }
+
+    impl SimulatedFlash {
+        fn new() -> Self {
+            Self {
+                memory: vec![0; 1 << 20],
+                write_enabled: false,
+                writes: vec![],
+            }
+        }
+    }
+
+    impl SpiController for SimulatedFlash {
+        fn name(&self) -> &str {
+            "simulated"
+        }
+
+        fn execute(
+            &mut self,
+            _session: &mut Session,
+            command: SpiCommand<'_>,
+        ) -> Result<(), FlashError> {
+            let address = command.address.unwrap_or(0) as usize;
+            match (command.opcode, command.data) {
+                (READ_SFDP, SpiData::Read(data)) => data.fill(0xFF),
+                (READ_JEDEC_ID, SpiData::Read(data)) => data.copy_from_slice(&[0xEF, 0x40, 0x14]),
+                (READ_STATUS, SpiData::Read(data)) => data[0] = 0,
+                (WRITE_ENABLE, _) => self.write_enabled = true,
+                (READ, SpiData::Read(data)) => {
+                    data.copy_from_slice(&self.memory[address..][..data.len()])
+                }
+                (opcode, data) => {
+                    assert!(self.write_enabled, "{opcode:#04x} without write enable");
+                    self.write_enabled = false;
+                    self.writes.push((opcode, address as u32));
+                    match (opcode, data) {
+                        (PAGE_PROGRAM, SpiData::Write(data)) => {
+                            assert!(address / 256 == (address + data.len() - 1) / 256);
+                            for (byte, new) in self.memory[address..].iter_mut().zip(data) {
+                                *byte &= new;
+                            }
+                        }
+                        (0x20, _) => self.memory[address..][..4096].fill(0xFF),
+                        (0xD8, _) => self.memory[address..][..0x1_0000].fill(0xFF),
+                        (CHIP_ERASE, _) => self.memory.fill(0xFF),
+                        (opcode, _) => panic!("Unknown command {opcode:#04x}"),
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn session() -> Session {
+        Probe::from_specific_probe(Box::new(FakeProbe::new()))
+            .attach("stm32wb55ccux", Permissions::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn geometry_from_the_jedec_id() {
+        let mut session = session();
+        let programmer =
+            SpiNorProgrammer::new(&mut session, SimulatedFlash::new(), 0x9000_0000).unwrap();
+
+        assert_eq!(*programmer.geometry(), SpiNorGeometry::standard(1 << 20));
+        assert_eq!(programmer.range(), 0x9000_0000..0x9010_0000);
+    }
+
+    #[test]
+    fn chunks_are_programmed_in_erased_sectors_and_pages() {
+        let mut session = session();
+        let mut programmer = SpiNorProgrammer::with_geometry(
+            SimulatedFlash::new(),
+            0x9000_0000,
+            SpiNorGeometry::standard(1 << 20),
+        );
+
+        // The chunk covers the last sector before the block, the whole block, and the first sector after it.
+        let data = (0..0x1_2000).map(|i| i as u8).collect::<Vec<_>>();
+        programmer
+            .program(&mut session, 0x9000_F800, &data, true)
+            .unwrap();
+        // A chunk in a sector which was erased for the previous chunk.
+        programmer
+            .program(&mut session, 0x9002_1800, &[1, 2, 3, 4], true)
+            .unwrap();
+
+        let erases = programmer
+            .controller
+            .writes
+            .iter()
+            .filter(|(opcode, _)| *opcode != PAGE_PROGRAM)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            erases,
+            [
+                (0x20, 0xF000),
+                (0xD8, 0x1_0000),
+                (0x20, 0x2_0000),
+                (0x20, 0x2_1000)
+            ]
+        );
+
+        let mut read = vec![0; data.len()];
+        programmer
+            .read(&mut session, 0x9000_F800, &mut read)
+            .unwrap();
+        assert_eq!(read, data);
+
+        assert!(programmer
+            .program(&mut session, 0x900F_FFFF, &[1, 2], true)
+            .is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Discovery of the geometry of a SPI NOR flash, from its Serial Flash Discoverable Parameters (JEDEC JESD216).
+
+use super::{EraseType, SpiNorGeometry};
+use crate::flashing::FlashError;
+
+/// The `SFDP` signature at the start of the parameters.
+const SIGNATURE: [u8; 4] = *b"SFDP";
+
+/// The ID of the Basic Flash Parameter Table, which every flash with SFDP has.
+const BASIC_PARAMETER_TABLE: u16 = 0xFF00;
+
+/// Read the geometry from the SFDP, with `read` reading the parameters at an address.
+///
+/// Returns `None` if the flash has no SFDP.
+pub(super) fn read_geometry(
+    mut read: impl FnMut(u32, &mut [u8]) -> Result<(), FlashError>,
+) -> Result<Option<SpiNorGeometry>, FlashError> {
+    // The header, followed by the header of the first parameter table, which is the basic table.
+    let mut header = [0; 16];
+    read(0, &mut header)?;
+    if header[..4] != SIGNATURE {
+        return Ok(None);
+    }
+
+    let id = u16::from_le_bytes([header[8], header[15]]);
+    if id != BASIC_PARAMETER_TABLE {
+        tracing::warn!(
+            "The first SFDP parameter table is {:#06x}, not the basic table",
+            id
+        );
+        return Ok(None);
+    }
+
+    // Only the first 11 dwords are used, which every revision of the table has except the first.
+    let length = header[11].min(11) as usize;
+    let pointer = u32::from_le_bytes([header[12], header[13], header[14], 0]);
+    let mut table = vec![0; length * 4];
+    read(pointer, &mut table)?;
+
+    Ok(parse_basic_table(&table))
+}
+
+/// Parse the Basic Flash Parameter Table.
+fn parse_basic_table(table: &[u8]) -> Option<SpiNorGeometry> {
+    let dwords = table
+        .chunks_exact(4)
+        .map(|d| u32::from_le_bytes(d.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    let first = *dwords.first()?;
+
+    // The density is in bits, either as the highest bit address, or as a power of two.
+    let density = *dwords.get(1)?;
+    let bits = if density & 0x8000_0000 == 0 {
+        density as u64 + 1
+    } else {
+        1u64.checked_shl(density & 0x7FFF_FFFF)?
+    };
+    let size = bits / 8;
+
+    // Erase types 1 to 4, as the size exponent and the opcode.
+    let mut erase_types = dwords
+        .get(7..9)
+        .into_iter()
+        .flatten()
+        .flat_map(|dword| [*dword as u16, (*dword >> 16) as u16])
+        .filter(|erase_type| erase_type & 0xFF != 0)
+        .map(|erase_type| EraseType {
+            size: 1 << (erase_type & 0x1F),
+            opcode: (erase_type >> 8) as u8,
+        })
+        .collect::<Vec<_>>();
+    if erase_types.is_empty() && first & 0b11 == 0b01 {
+        erase_types.push(EraseType {
+            size: 4096,
+            opcode: (first >> 8) as u8,
+        });
+    }
+    erase_types.sort_by_key(|erase_type| erase_type.size);
+
+    let page_size = dwords
+        .get(10)
+        .map_or(256, |dword| 1 << ((dword >> 4) & 0xF));
+
+    Some(SpiNorGeometry {
+        size,
+        page_size,
+        erase_types,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The SFDP of a W25Q128JV.
+    fn w25q128_sfdp() -> Vec<u8> {
+        let mut sfdp = vec![0xFF; 0x100];
+        sfdp[..16].copy_from_slice(&[
+            b'S', b'F', b'D', b'P', 0x05, 0x01, 0x00, 0xFF, 0x00, 0x05, 0x01, 0x10, 0x80, 0x00,
+            0x00, 0xFF,
+        ]);
+        let table: [u32; 16] = [
+            0xFFF9_20E5,
+            0x07FF_FFFF,
+            0x6B08_EB44,
+            0xBB42_3B08,
+            0xFFFF_FFFE,
+            0xFF00_FFFF,
+            0xEB40_FFFF,
+            0x520F_200C,
+            0xFF00_D810,
+            0x0060_2400,
+            0x0052_9282,
+            0xD5C0_6916,
+            0x1612_8D4C,
+            0x0000_0000,
+            0x0000_0000,
+            0x0000_0000,
+        ];
+        for (i, dword) in table.iter().enumerate() {
+            sfdp[0x80 + i * 4..][..4].copy_from_slice(&dword.to_le_bytes());
+        }
+        sfdp
+    }
+
+    #[test]
+    fn geometry_of_w25q128() {
+        let sfdp = w25q128_sfdp();
+        let geometry = read_geometry(|address, data| {
+            data.copy_from_slice(&sfdp[address as usize..][..data.len()]);
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            geometry,
+            SpiNorGeometry {
+                size: 16 * 1024 * 1024,
+                page_size: 256,
+                erase_types: vec![
+                    EraseType {
+                        size: 4096,
+                        opcode: 0x20
+                    },
+                    EraseType {
+                        size: 32 * 1024,
+                        opcode: 0x52
+                    },
+                    EraseType {
+                        size: 64 * 1024,
+                        opcode: 0xD8
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn flash_without_sfdp() {
+        let geometry = read_geometry(|_, data| {
+            data.fill(0xFF);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(geometry, None);
+    }
+}
@@ -0,0 +1,219 @@
+//! The QUADSPI peripheral of the STM32F7, STM32L4 and STM32H7, in indirect mode.
+
+use std::time::{Duration, Instant};
+
+use super::{SpiCommand, SpiController, SpiData};
+use crate::flashing::FlashError;
+use crate::{Core, MemoryInterface, Session};
+
+const CR: u64 = 0x00;
+const DCR: u64 = 0x04;
+const SR: u64 = 0x08;
+const FCR: u64 = 0x0C;
+const DLR: u64 = 0x10;
+const CCR: u64 = 0x14;
+const AR: u64 = 0x18;
+const DR: u64 = 0x20;
+
+const CR_EN: u32 = 1 << 0;
+const CR_ABORT: u32 = 1 << 1;
+
+const SR_TCF: u32 = 1 << 1;
+const SR_FTF: u32 = 1 << 2;
+const SR_BUSY: u32 = 1 << 5;
+
+/// Clears the transfer error, transfer complete, status match and timeout flags.
+const FCR_CLEAR_ALL: u32 = 0b1_1011;
+
+/// The FIFO threshold flag is set when 4 bytes can be written or read.
+const FIFO_THRESHOLD: u32 = 3;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The STM32 families with a QUADSPI peripheral.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stm32QuadspiFamily {
+    /// STM32F7
+    F7,
+    /// STM32L4 and STM32L4+
+    L4,
+    /// STM32H7, except for the H72x/H73x and H7Ax/H7Bx which have OCTOSPI.
+    H7,
+}
+
+impl Stm32QuadspiFamily {
+    fn base_address(self) -> u64 {
+        match self {
+            Self::F7 | Self::L4 => 0xA000_1000,
+            Self::H7 => 0x5200_5000,
+        }
+    }
+
+    /// The RCC register and bit which enable the clock of the peripheral.
+    fn clock_enable(self) -> (u64, u32) {
+        match self {
+            Self::F7 => (0x4002_3838, 1 << 1),
+            Self::L4 => (0x4002_1050, 1 << 8),
+            Self::H7 => (0x5802_44D4, 1 << 14),
+        }
+    }
+
+    /// The address at which the flash is mapped into memory, by memory-mapped mode.
+    pub fn memory_mapped_address(self) -> u64 {
+        0x9000_0000
+    }
+}
+
+/// The QUADSPI peripheral of an STM32, with the flash on bank 1.
+///
+/// The clock of the peripheral is enabled, but the pins have to be configured already. After programming, the
+/// peripheral stays in indirect mode, so the flash is not memory-mapped until the firmware configures it again.
+#[derive(Debug)]
+pub struct Stm32Quadspi {
+    family: Stm32QuadspiFamily,
+    core: usize,
+    prescaler: u8,
+    initialized: bool,
+}
+
+impl Stm32Quadspi {
+    /// Operate the peripheral of `family` through the core with index `core`.
+    pub fn new(family: Stm32QuadspiFamily, core: usize) -> Self {
+        Self {
+            family,
+            core,
+            prescaler: 3,
+            initialized: false,
+        }
+    }
+
+    /// Divide the clock of the peripheral by `prescaler + 1` for the flash. The default is 3, which is slow enough for
+    /// the usual kernel clocks, without a configuration of the delay of the sampling.
+    pub fn with_prescaler(mut self, prescaler: u8) -> Self {
+        self.prescaler = prescaler;
+        self
+    }
+
+    fn init(&self, core: &mut Core<'_>) -> Result<(), FlashError> {
+        let base = self.family.base_address();
+
+        let (rcc, enable) = self.family.clock_enable();
+        let value = core.read_word_32(rcc)?;
+        core.write_word_32(rcc, value | enable)?;
+
+        // The firmware may have left the peripheral in memory-mapped mode, which is busy until it is aborted.
+        let cr = core.read_word_32(base + CR)?;
+        if cr & CR_EN != 0 {
+            core.write_word_32(base + CR, cr | CR_ABORT)?;
+            wait(core, base + CR, 0, |cr| cr & CR_ABORT == 0)?;
+        }
+        core.write_word_32(base + CR, 0)?;
+
+        // A flash of 16 MiB, with the chip select high for 2 cycles between the commands.
+        core.write_word_32(base + DCR, (23 << 16) | (1 << 8))?;
+        core.write_word_32(
+            base + CR,
+            ((self.prescaler as u32) << 24) | (FIFO_THRESHOLD << 8) | CR_EN,
+        )?;
+        Ok(())
+    }
+}
+
+impl SpiController for Stm32Quadspi {
+    fn name(&self) -> &str {
+        "STM32 QUADSPI"
+    }
+
+    fn execute(
+        &mut self,
+        session: &mut Session,
+        command: SpiCommand<'_>,
+    ) -> Result<(), FlashError> {
+        let mut core = session.core(self.core)?;
+        if !self.initialized {
+            self.init(&mut core)?;
+            self.initialized = true;
+        }
+
+        let base = self.family.base_address();
+        let opcode = command.opcode;
+        wait(&mut core, base + SR, opcode, |sr| sr & SR_BUSY == 0)?;
+        core.write_word_32(base + FCR, FCR_CLEAR_ALL)?;
+
+        let (read, length) = match &command.data {
+            SpiData::None => (false, 0),
+            SpiData::Write(data) => (false, data.len()),
+            SpiData::Read(data) => (true, data.len()),
+        };
+
+        // The instruction on a single line, in indirect write or read mode.
+        let mut ccr = opcode as u32 | (0b01 << 8) | ((command.dummy_cycles as u32 & 0x1F) << 18);
+        if read {
+            ccr |= 0b01 << 26;
+        }
+        if command.address.is_some() {
+            // A 3 byte address on a single line.
+            ccr |= (0b01 << 10) | (0b10 << 12);
+        }
+        if length > 0 {
+            core.write_word_32(base + DLR, length as u32 - 1)?;
+            ccr |= 0b01 << 24;
+        }
+
+        // The command starts with the write of the last register which it needs.
+        core.write_word_32(base + CCR, ccr)?;
+        if let Some(address) = command.address {
+            core.write_word_32(base + AR, address)?;
+        }
+
+        match command.data {
+            SpiData::None => {}
+            SpiData::Write(data) => {
+                for chunk in data.chunks(4) {
+                    wait(&mut core, base + SR, opcode, |sr| sr & SR_FTF != 0)?;
+                    if let Ok(word) = <[u8; 4]>::try_from(chunk) {
+                        core.write_word_32(base + DR, u32::from_le_bytes(word))?;
+                    } else {
+                        for byte in chunk {
+                            core.write_word_8(base + DR, *byte)?;
+                        }
+                    }
+                }
+            }
+            SpiData::Read(data) => {
+                for chunk in data.chunks_mut(4) {
+                    wait(&mut core, base + SR, opcode, |sr| {
+                        sr & (SR_FTF | SR_TCF) != 0
+                    })?;
+                    if chunk.len() == 4 {
+                        chunk.copy_from_slice(&core.read_word_32(base + DR)?.to_le_bytes());
+                    } else {
+                        for byte in chunk {
+                            *byte = core.read_word_8(base + DR)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        wait(&mut core, base + SR, opcode, |sr| sr & SR_TCF != 0)?;
+        core.write_word_32(base + FCR, FCR_CLEAR_ALL)?;
+        Ok(())
+    }
+}
+
+/// Wait until the register at `address` is `done`.
+fn wait(
+    core: &mut Core<'_>,
+    address: u64,
+    opcode: u8,
+    done: impl Fn(u32) -> bool,
+) -> Result<(), FlashError> {
+    let start = Instant::now();
+    while !done(core.read_word_32(address)?) {
+        if start.elapsed() > TIMEOUT {
+            return Err(FlashError::SpiNorTimeout { opcode });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,507 @@
+//! Load the configuration of an FPGA through its JTAG port, with the [raw JTAG access](crate::RawJtagIo) of a probe.
+//!
+//! The FPGA can share the scan chain with the MCU, e.g. on a mixed MCU and FPGA board, or in a Zynq: the
+//! [`ChainPosition`] of the FPGA pads every shift with the bits of the other TAPs, which are kept in BYPASS. An
+//! [`Fpga`] checks the IDCODE of the FPGA against its [`FpgaFamily`], and [`Fpga::load`] writes a bitstream into the
+//! configuration memory of the FPGA, following the JTAG configuration sequence of its family. The configuration is
+//! volatile, so it is lost when the FPGA is powered off.
+//!
+//! The Lattice ECP5 and the Xilinx 7-series families are supported. Lattice iCE40 FPGAs have no JTAG port, they are
+//! configured over SPI only.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::{DebugProbeError, RawJtagIo};
+
+/// An error while loading an FPGA.
+#[derive(Debug, thiserror::Error)]
+pub enum FpgaError {
+    /// The raw JTAG access of the probe failed.
+    #[error("Raw JTAG access failed")]
+    Probe(#[from] DebugProbeError),
+    /// The IDCODE at the position of the FPGA is not one of an FPGA of the family.
+    #[error("The IDCODE {idcode:#010x} is not the one of a {family} FPGA. Check the position of the FPGA in the scan chain.")]
+    UnexpectedIdcode {
+        /// The IDCODE which was read.
+        idcode: u32,
+        /// The family of the FPGA.
+        family: FpgaFamily,
+    },
+    /// The bitstream is not a bitstream of the family.
+    #[error("The bitstream is invalid: {0}")]
+    InvalidBitstream(&'static str),
+    /// The FPGA didn't get ready in time.
+    #[error("The FPGA did not get ready for the configuration in time")]
+    Timeout,
+    /// The FPGA did not start after the bitstream was loaded, e.g. because its CRC is wrong, or it is meant for
+    /// another device.
+    #[error("The configuration of the FPGA failed, its status is {status:#x}")]
+    ConfigurationFailed {
+        /// The status register of an ECP5, or the captured IR of a Xilinx FPGA.
+        status: u32,
+    },
+}
+
+/// A family of FPGAs which can be configured through JTAG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpgaFamily {
+    /// Lattice ECP5 and ECP5-5G.
+    Ecp5,
+    /// Xilinx 7-series, i.e. Spartan-7, Artix-7, Kintex-7, Virtex-7 and the programmable logic of Zynq-7000,
+    /// except for the devices with multiple dies.
+    Xilinx7,
+}
+
+impl FpgaFamily {
+    /// The length of the instruction register of the TAP of the FPGA.
+    pub fn ir_len(&self) -> usize {
+        match self {
+            FpgaFamily::Ecp5 => 8,
+            FpgaFamily::Xilinx7 => 6,
+        }
+    }
+
+    /// Whether `idcode` is the IDCODE of an FPGA of the family. The version in the upper four bits is ignored.
+    pub fn matches_idcode(&self, idcode: u32) -> bool {
+        let manufacturer = idcode & 0xfff;
+        let part = (idcode >> 12) & 0xffff;
+        match self {
+            // Lattice, with the parts of the LFE5U and LFE5UM devices.
+            FpgaFamily::Ecp5 => manufacturer == 0x043 && (0x1111..=0x1113).contains(&part),
+            // Xilinx, with the family code of the 7-series.
+            FpgaFamily::Xilinx7 => manufacturer == 0x093 && part >> 9 == 0x1b,
+        }
+    }
+
+    fn idcode_instruction(&self) -> u8 {
+        match self {
+            FpgaFamily::Ecp5 => ecp5::IDCODE,
+            FpgaFamily::Xilinx7 => xilinx7::IDCODE,
+        }
+    }
+}
+
+impl fmt::Display for FpgaFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FpgaFamily::Ecp5 => write!(f, "ECP5"),
+            FpgaFamily::Xilinx7 => write!(f, "Xilinx 7-series"),
+        }
+    }
+}
+
+impl std::str::FromStr for FpgaFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_ascii_lowercase()[..] {
+            "ecp5" => Ok(FpgaFamily::Ecp5),
+            "xilinx7" => Ok(FpgaFamily::Xilinx7),
+            "ice40" => Err(
+                "iCE40 FPGAs have no JTAG port, they can only be configured over SPI.".to_string(),
+            ),
+            _ => Err(format!(
+                "'{s}' is not a supported FPGA family. Choose from [ecp5, xilinx7]."
+            )),
+        }
+    }
+}
+
+/// The position of the FPGA in the scan chain, given by the TAPs between the FPGA and TDO, which are shifted
+/// first, and the TAPs between TDI and the FPGA. The default is an FPGA which is alone on the chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChainPosition {
+    /// The sum of the IR lengths of the TAPs between the FPGA and TDO.
+    pub ir_before: usize,
+    /// The sum of the IR lengths of the TAPs between TDI and the FPGA.
+    pub ir_after: usize,
+    /// The number of TAPs between the FPGA and TDO.
+    pub taps_before: usize,
+    /// The number of TAPs between TDI and the FPGA.
+    pub taps_after: usize,
+}
+
+/// The instructions and status bits of the Lattice ECP5, see Lattice TN1260.
+mod ecp5 {
+    pub const IDCODE: u8 = 0xe0;
+    pub const ISC_ENABLE: u8 = 0xc6;
+    pub const ISC_ERASE: u8 = 0x0e;
+    pub const ISC_DISABLE: u8 = 0x26;
+    pub const ISC_NOOP: u8 = 0xff;
+    pub const LSC_INIT_ADDRESS: u8 = 0x46;
+    pub const LSC_BITSTREAM_BURST: u8 = 0x7a;
+    pub const LSC_READ_STATUS: u8 = 0x3c;
+
+    pub const STATUS_DONE: u32 = 1 << 8;
+    pub const STATUS_BUSY: u32 = 1 << 12;
+    pub const STATUS_FAIL: u32 = 1 << 13;
+
+    /// The preamble at the start of the configuration data.
+    pub const PREAMBLE: [u8; 4] = [0xff, 0xff, 0xbd, 0xb3];
+}
+
+/// The instructions and IR capture bits of the Xilinx 7-series, see Xilinx UG470.
+mod xilinx7 {
+    pub const IDCODE: u8 = 0x09;
+    pub const CFG_IN: u8 = 0x05;
+    pub const JPROGRAM: u8 = 0x0b;
+    pub const JSTART: u8 = 0x0c;
+    pub const ISC_NOOP: u8 = 0x14;
+    pub const BYPASS: u8 = 0x3f;
+
+    pub const IR_INIT_COMPLETE: u8 = 1 << 4;
+    /// The DONE bit, and the fixed `01` in the lowest bits of the IR capture.
+    pub const IR_DONE_MASK: u8 = 0x23;
+    pub const IR_DONE: u8 = 0x21;
+
+    /// The sync word in the configuration data.
+    pub const SYNC_WORD: [u8; 4] = [0xaa, 0x99, 0x55, 0x66];
+}
+
+/// An FPGA in the scan chain of a probe, see the [module documentation](self).
+pub struct Fpga<'probe> {
+    jtag: &'probe mut dyn RawJtagIo,
+    family: FpgaFamily,
+    position: ChainPosition,
+    idcode: u32,
+}
+
+impl<'probe> Fpga<'probe> {
+    /// Read the IDCODE of the FPGA at `position` in the scan chain, and check that it is one of `family`.
+    pub fn new(
+        jtag: &'probe mut dyn RawJtagIo,
+        family: FpgaFamily,
+        position: ChainPosition,
+    ) -> Result<Self, FpgaError> {
+        let mut fpga = Self {
+            jtag,
+            family,
+            position,
+            idcode: 0,
+        };
+        fpga.shift_ir(family.idcode_instruction())?;
+        fpga.idcode = le_u32(&fpga.shift_dr(&[0; 4], 32)?);
+        tracing::debug!("IDCODE of the FPGA: {:#010x}", fpga.idcode);
+
+        if !family.matches_idcode(fpga.idcode) {
+            return Err(FpgaError::UnexpectedIdcode {
+                idcode: fpga.idcode,
+                family,
+            });
+        }
+        Ok(fpga)
+    }
+
+    /// The IDCODE of the FPGA.
+    pub fn idcode(&self) -> u32 {
+        self.idcode
+    }
+
+    /// The family of the FPGA.
+    pub fn family(&self) -> FpgaFamily {
+        self.family
+    }
+
+    /// Load `bitstream` into the configuration memory of the FPGA, and start it. The bitstream is a `.bit` file of
+    /// the vendor tools of the family, or a `.bin` file of the Xilinx tools.
+    pub fn load(&mut self, bitstream: &[u8]) -> Result<(), FpgaError> {
+        match self.family {
+            FpgaFamily::Ecp5 => self.load_ecp5(ecp5_configuration(bitstream)?),
+            FpgaFamily::Xilinx7 => self.load_xilinx7(xilinx7_configuration(bitstream)?),
+        }
+    }
+
+    fn load_ecp5(&mut self, configuration: &[u8]) -> Result<(), FpgaError> {
+        self.shift_ir(ecp5::ISC_ENABLE)?;
+        self.shift_dr(&[0x00], 8)?;
+        self.wait(Duration::from_millis(10))?;
+
+        // Erase the SRAM, which stops the current configuration.
+        self.shift_ir(ecp5::ISC_ERASE)?;
+        self.shift_dr(&[0x01], 8)?;
+        self.wait(Duration::from_millis(10))?;
+        let start = Instant::now();
+        while self.ecp5_status()? & ecp5::STATUS_BUSY != 0 {
+            if start.elapsed() > Duration::from_secs(1) {
+                return Err(FpgaError::Timeout);
+            }
+            self.wait(Duration::from_millis(10))?;
+        }
+
+        self.shift_ir(ecp5::LSC_INIT_ADDRESS)?;
+        self.shift_dr(&[0x01], 8)?;
+        self.wait(Duration::from_millis(10))?;
+
+        self.shift_ir(ecp5::LSC_BITSTREAM_BURST)?;
+        self.jtag.run_test_idle(2)?;
+        self.shift_dr(&msb_first(configuration), configuration.len() * 8)?;
+
+        self.shift_ir(ecp5::ISC_DISABLE)?;
+        self.wait(Duration::from_millis(10))?;
+        self.shift_ir(ecp5::ISC_NOOP)?;
+        self.wait(Duration::from_millis(10))?;
+
+        let status = self.ecp5_status()?;
+        if status & ecp5::STATUS_DONE == 0 || status & ecp5::STATUS_FAIL != 0 {
+            return Err(FpgaError::ConfigurationFailed { status });
+        }
+        Ok(())
+    }
+
+    fn ecp5_status(&mut self) -> Result<u32, FpgaError> {
+        self.shift_ir(ecp5::LSC_READ_STATUS)?;
+        Ok(le_u32(&self.shift_dr(&[0; 4], 32)?))
+    }
+
+    fn load_xilinx7(&mut self, configuration: &[u8]) -> Result<(), FpgaError> {
+        // JPROGRAM clears the configuration memory, which is done when INIT_COMPLETE is set again.
+        self.shift_ir(xilinx7::JPROGRAM)?;
+        let start = Instant::now();
+        while self.shift_ir(xilinx7::ISC_NOOP)? & xilinx7::IR_INIT_COMPLETE == 0 {
+            if start.elapsed() > Duration::from_secs(1) {
+                return Err(FpgaError::Timeout);
+            }
+            self.wait(Duration::from_millis(1))?;
+        }
+        self.jtag.run_test_idle(10000)?;
+
+        self.shift_ir(xilinx7::CFG_IN)?;
+        self.shift_dr(&msb_first(configuration), configuration.len() * 8)?;
+
+        // The startup sequence is clocked by TCK.
+        self.shift_ir(xilinx7::JSTART)?;
+        self.jtag.run_test_idle(2000)?;
+
+        let status = self.shift_ir(xilinx7::BYPASS)?;
+        if status & xilinx7::IR_DONE_MASK != xilinx7::IR_DONE {
+            return Err(FpgaError::ConfigurationFailed {
+                status: status.into(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Wait in Run-Test/Idle for at least `duration`.
+    fn wait(&mut self, duration: Duration) -> Result<(), FpgaError> {
+        self.jtag.run_test_idle(100)?;
+        std::thread::sleep(duration);
+        Ok(())
+    }
+
+    /// Shift `instruction` into the IR of the FPGA, and BYPASS into the others. Returns the captured IR of the FPGA.
+    fn shift_ir(&mut self, instruction: u8) -> Result<u8, FpgaError> {
+        let ir_len = self.family.ir_len();
+        let bits = std::iter::repeat(true)
+            .take(self.position.ir_before)
+            .chain((0..ir_len).map(|bit| instruction & (1 << bit) != 0))
+            .chain(std::iter::repeat(true).take(self.position.ir_after));
+        let (data, len) = pack(bits);
+
+        let captured = self.jtag.shift_ir(&data, len)?;
+        let (captured, _) = pack(
+            (self.position.ir_before..self.position.ir_before + ir_len)
+                .map(|bit| get_bit(&captured, bit)),
+        );
+        Ok(captured[0])
+    }
+
+    /// Shift `bits` bits of `data` into the DR of the FPGA. Returns the bits which were shifted out of it.
+    fn shift_dr(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, FpgaError> {
+        let position = self.position;
+        if position.taps_before == 0 && position.taps_after == 0 {
+            return Ok(self.jtag.shift_dr(data, bits)?);
+        }
+
+        let padded = std::iter::repeat(false)
+            .take(position.taps_before)
+            .chain((0..bits).map(|bit| get_bit(data, bit)))
+            .chain(std::iter::repeat(false).take(position.taps_after));
+        let (padded, len) = pack(padded);
+
+        let shifted = self.jtag.shift_dr(&padded, len)?;
+        let (shifted, _) = pack(
+            (position.taps_before..position.taps_before + bits).map(|bit| get_bit(&shifted, bit)),
+        );
+        Ok(shifted)
+    }
+}
+
+/// The configuration data of the `.bit` file of an ECP5, without the comments in front of it.
+fn ecp5_configuration(bitstream: &[u8]) -> Result<&[u8], FpgaError> {
+    let start = bitstream
+        .windows(ecp5::PREAMBLE.len())
+        .position(|window| window == ecp5::PREAMBLE)
+        .ok_or(FpgaError::InvalidBitstream(
+            "The preamble of an ECP5 bitstream is missing",
+        ))?;
+    Ok(&bitstream[start..])
+}
+
+/// The configuration data of the `.bit` file of a Xilinx FPGA, which is the last field after a header with the name
+/// of the design, the part, and the date and time. A `.bin` file is the configuration data only.
+fn xilinx7_configuration(bitstream: &[u8]) -> Result<&[u8], FpgaError> {
+    const INVALID: FpgaError =
+        FpgaError::InvalidBitstream("The header of the .bit file is invalid");
+
+    let configuration = if bitstream.starts_with(&[0x00, 0x09]) {
+        // Skip the length and the 9 bytes of the first field, and the length of the second field.
+        let mut rest = bitstream.get(2 + 9 + 2..).ok_or(INVALID)?;
+        loop {
+            let (&key, tail) = rest.split_first().ok_or(INVALID)?;
+            match key {
+                b'a'..=b'd' => {
+                    let len = u16::from_be_bytes(tail.get(..2).ok_or(INVALID)?.try_into().unwrap());
+                    rest = tail.get(2 + len as usize..).ok_or(INVALID)?;
+                }
+                b'e' => {
+                    let len = u32::from_be_bytes(tail.get(..4).ok_or(INVALID)?.try_into().unwrap());
+                    break tail.get(4..4 + len as usize).ok_or(INVALID)?;
+                }
+                _ => return Err(INVALID),
+            }
+        }
+    } else {
+        bitstream
+    };
+
+    if !configuration
+        .windows(xilinx7::SYNC_WORD.len())
+        .any(|window| window == xilinx7::SYNC_WORD)
+    {
+        return Err(FpgaError::InvalidBitstream(
+            "The sync word of a Xilinx bitstream is missing",
+        ));
+    }
+    Ok(configuration)
+}
+
+/// The configuration data is shifted MSB first, but JTAG shifts the LSB first.
+fn msb_first(data: &[u8]) -> Vec<u8> {
+    data.iter().map(|byte| byte.reverse_bits()).collect()
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    (0..32).fold(0, |value, bit| value | (get_bit(data, bit) as u32) << bit)
+}
+
+fn get_bit(data: &[u8], bit: usize) -> bool {
+    match data.get(bit / 8) {
+        Some(byte) => byte & (1 << (bit % 8)) != 0,
+        None => false,
+    }
+}
+
+/// Pack `bits` into bytes, LSB first, and count them.
+fn pack(bits: impl IntoIterator<Item = bool>) -> (Vec<u8>, usize) {
+    let mut data = Vec::new();
+    let mut len = 0;
+    for bit in bits {
+        if len % 8 == 0 {
+            data.push(0);
+        }
+        if bit {
+            data[len / 8] |= 1 << (len % 8);
+        }
+        len += 1;
+    }
+    (data, len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A scan chain with a TAP with a 4 bit IR before the FPGA, and the IR and DR of each TAP.
+    struct Chain {
+        irs: [Vec<bool>; 2],
+        drs: [Vec<bool>; 2],
+    }
+
+    impl RawJtagIo for Chain {
+        fn shift_ir(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+            self.irs = [
+                (0..4).map(|bit| get_bit(data, bit)).collect(),
+                (4..bits).map(|bit| get_bit(data, bit)).collect(),
+            ];
+            // The captured IR of the FPGA is DONE, and the `01` of the other TAP.
+            Ok(pack([
+                true, false, false, false, true, false, false, false, false, true,
+            ])
+            .0)
+        }
+
+        fn shift_dr(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+            self.drs = [
+                vec![get_bit(data, 0)],
+                (1..bits).map(|bit| get_bit(data, bit)).collect(),
+            ];
+            // The bypass register of the other TAP, and the IDCODE of an XC7A35T.
+            let idcode = 0x0362_d093u32.to_le_bytes();
+            Ok(
+                pack(std::iter::once(false).chain((0..bits - 1).map(|bit| get_bit(&idcode, bit))))
+                    .0,
+            )
+        }
+
+        fn run_test_idle(&mut self, _cycles: usize) -> Result<(), DebugProbeError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shifts_are_padded_for_the_other_taps() {
+        let mut chain = Chain {
+            irs: Default::default(),
+            drs: Default::default(),
+        };
+        let position = ChainPosition {
+            ir_before: 4,
+            taps_before: 1,
+            ..Default::default()
+        };
+        let mut fpga = Fpga::new(&mut chain, FpgaFamily::Xilinx7, position).unwrap();
+        assert_eq!(fpga.idcode(), 0x0362_d093);
+
+        assert_eq!(fpga.shift_ir(xilinx7::BYPASS).unwrap(), xilinx7::IR_DONE);
+        assert_eq!(chain.irs[0], [true; 4]);
+        assert_eq!(chain.irs[1], [true; 6]);
+
+        assert!(Fpga::new(&mut chain, FpgaFamily::Ecp5, position).is_err());
+    }
+
+    #[test]
+    fn configuration_is_extracted_from_bit_files() {
+        let mut bit = vec![
+            0x00, 0x09, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x00, 0x00, 0x01,
+        ];
+        for (key, value) in [
+            (b'a', "top;UserID=0XFFFFFFFF"),
+            (b'b', "7a35tcpg236"),
+            (b'c', "2024/01/01"),
+            (b'd', "12:00:00"),
+        ] {
+            bit.push(key);
+            bit.extend_from_slice(&(value.len() as u16 + 1).to_be_bytes());
+            bit.extend_from_slice(value.as_bytes());
+            bit.push(0);
+        }
+        let configuration = [
+            0xff, 0xff, 0xff, 0xff, 0xaa, 0x99, 0x55, 0x66, 0x20, 0x00, 0x00, 0x00,
+        ];
+        bit.push(b'e');
+        bit.extend_from_slice(&(configuration.len() as u32).to_be_bytes());
+        bit.extend_from_slice(&configuration);
+
+        assert_eq!(xilinx7_configuration(&bit).unwrap(), configuration);
+        assert_eq!(
+            xilinx7_configuration(&configuration).unwrap(),
+            configuration
+        );
+        assert!(xilinx7_configuration(&bit[..40]).is_err());
+
+        let ecp5 = [0xff, 0x00, b'x', 0x00, 0xff, 0xff, 0xff, 0xbd, 0xb3, 0x01];
+        assert_eq!(ecp5_configuration(&ecp5).unwrap(), &ecp5[5..]);
+        assert_eq!(msb_first(&[0x01, 0xb3]), [0x80, 0xcd]);
+    }
+}
@@ -1,8 +1,8 @@
-use crate::{CoreType, Error, Session};
+use crate::{CoreType, Error, Session, SharedSession};
 use anyhow::Result;
 
 use std::net::{SocketAddr, ToSocketAddrs};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 use itertools::Itertools;
@@ -76,15 +76,29 @@ impl GdbInstanceConfiguration {
 ///
 /// # Arguments
 ///
-/// * session - The [Session] to use, protected by a [std::sync::Mutex]
+/// * session - The [Session] to use, shared as a [SharedSession]
 /// * instances - a list of [GdbInstanceConfiguration] objects used to configure the GDB session
 ///
 /// # Remarks
 ///
 /// A default configuration can be created by calling [GdbInstanceConfiguration::from_session()]
 pub fn run<'a>(
-    session: &Mutex<Session>,
+    session: &SharedSession,
     instances: impl Iterator<Item = &'a GdbInstanceConfiguration>,
+) -> Result<()> {
+    run_until(session, instances, &AtomicBool::new(false))
+}
+
+/// Run a new GDB session, until `shutdown` is set.
+///
+/// This allows running the GDB stub alongside other users of the same [SharedSession], e.g. a debug adapter,
+/// and stopping it when they are done with the session.
+///
+/// See [run] for a description of the arguments.
+pub fn run_until<'a>(
+    session: &SharedSession,
+    instances: impl Iterator<Item = &'a GdbInstanceConfiguration>,
+    shutdown: &AtomicBool,
 ) -> Result<()> {
     // Turn our group list into GDB targets
     let mut targets = instances
@@ -94,7 +108,7 @@ pub fn run<'a>(
         .collect::<Result<Vec<target::RuntimeTarget>, Error>>()?;
 
     // Process every target in a loop
-    loop {
+    while !shutdown.load(Ordering::Relaxed) {
         let mut wait_time = Duration::ZERO;
 
         for target in targets.iter_mut() {
@@ -104,6 +118,8 @@ pub fn run<'a>(
         // Wait until we were asked to check again
         std::thread::sleep(wait_time);
     }
+
+    Ok(())
 }
 
 /// Given a list of socket addresses, adjust the port by `offset` and return
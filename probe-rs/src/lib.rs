@@ -69,6 +69,9 @@ extern crate serde;
 
 /// All the interface bits for the different architectures.
 pub mod architecture;
+#[warn(missing_docs)]
+#[cfg(feature = "async")]
+mod async_session;
 pub mod config;
 
 #[warn(missing_docs)]
@@ -76,12 +79,20 @@ mod core;
 pub mod debug;
 mod error;
 #[warn(missing_docs)]
+pub mod events;
+#[warn(missing_docs)]
 pub mod flashing;
+#[warn(missing_docs)]
+pub mod fpga;
 #[cfg(feature = "gdb-server")]
 pub mod gdb_server;
 #[warn(missing_docs)]
 mod memory;
 #[warn(missing_docs)]
+pub mod messages;
+#[warn(missing_docs)]
+pub mod power;
+#[warn(missing_docs)]
 mod probe;
 #[warn(missing_docs)]
 #[cfg(feature = "rtt")]
@@ -89,19 +100,36 @@ pub mod rtt;
 #[warn(missing_docs)]
 mod session;
 
+#[cfg(feature = "async")]
+pub use crate::async_session::{AsyncSession, BlockingExecutor};
 pub use crate::config::{CoreType, InstructionSet, Target};
 pub use crate::core::{
     Architecture, BreakpointCause, Core, CoreInformation, CoreInterface, CoreRegister,
     CoreRegisters, CoreState, CoreStatus, HaltReason, MemoryMappedRegister, RegisterId,
-    RegisterRole, RegisterValue, SpecificCoreState,
+    RegisterRole, RegisterValue, RunUntilOutcome, SecurityAttribution, SecurityState,
+    SecurityStatus, SpecificCoreState, StopReason, VectorCatchCondition, WatchpointAccess,
 };
-pub use crate::error::Error;
+pub use crate::error::{Error, ErrorCategory, ErrorCode};
 pub use crate::memory::MemoryInterface;
+pub use crate::probe::cmsisdap::transport::{CmsisDapTransport, CmsisDapTransportError};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::probe::registry::{
+    register_probe_driver, unregister_probe_driver, ProbeDriver, ProbeFactory, UsbFilter,
+};
+pub use crate::probe::transaction_log;
+pub use crate::probe::usb_policy;
+pub use crate::probe::watcher::{ProbeEvent, ProbeWatcher, ProbeWatcherHandle};
 pub use crate::probe::{
     AttachMethod, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType,
-    Probe, ProbeCreationError, WireProtocol,
+    Probe, ProbeCreationError, RawJtagIo, WireProtocol,
+};
+pub use crate::session::{
+    AuthenticationChallenge, AuthenticationProtocol, CoreSnapshot, DebugAuthenticator,
+    DisconnectAction, FlashResult, Permissions, ProbeDetails, RttChannelStatistics, RttDirection,
+    Session, SessionGuard, SessionState, SharedSession,
 };
-pub use crate::session::{Permissions, Session};
 
 // TODO: Hide behind feature
 pub use crate::probe::fake_probe::FakeProbe;
+pub use crate::probe::gdb_remote::GdbRemote;
+pub use crate::probe::replay_probe::{ReplayError, ReplayProbe};
@@ -0,0 +1,282 @@
+//! The catalog of user-facing messages, like the recovery hints of [`ErrorCode`](crate::ErrorCode)s and the phases
+//! of flashing, which are shown by the CLI, the GDB stub and the DAP server.
+//!
+//! Every message has a [`MessageId`] with a stable name and an English default text, which may contain `{name}`
+//! placeholders for arguments. A translation is a [`Catalog`], which is loaded from a YAML file mapping the names of
+//! messages to their translated text, and activated with [`set_catalog`]. Messages which are not translated fall back
+//! to their default text.
+//!
+//! ```yaml
+//! progress.erasing_sectors: "Sektoren löschen"
+//! progress.percent: "{phase} ({percent} %)"
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
+static CATALOG: Lazy<RwLock<Catalog>> = Lazy::new(|| RwLock::new(Catalog::default()));
+
+macro_rules! messages {
+    ($($(#[$meta:meta])* $id:ident => $name:literal, $text:literal;)*) => {
+        /// A user-facing message, see the [module documentation](self).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        pub enum MessageId {
+            $($(#[$meta])* $id,)*
+        }
+
+        impl MessageId {
+            /// All messages of the catalog.
+            pub const ALL: &'static [MessageId] = &[$(MessageId::$id,)*];
+
+            /// The stable name of the message, which is its key in a translation.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(MessageId::$id => $name,)*
+                }
+            }
+
+            /// The English text of the message.
+            pub fn default_text(self) -> &'static str {
+                match self {
+                    $(MessageId::$id => $text,)*
+                }
+            }
+        }
+    };
+}
+
+messages! {
+    /// The recovery hint of [`ErrorCode::ProbeNotFound`](crate::ErrorCode::ProbeNotFound).
+    HintProbeNotFound => "hint.probe_not_found",
+        "Check that the probe is connected, and that the probe selector matches it. `probe-rs list` lists the connected probes.";
+    /// The recovery hint of [`ErrorCode::ProbeBusy`](crate::ErrorCode::ProbeBusy).
+    HintProbeBusy => "hint.probe_busy",
+        "Another program uses the probe. Close other debuggers, e.g. OpenOCD or a vendor IDE, and try again.";
+    /// The recovery hint of [`ErrorCode::ProbeUsb`](crate::ErrorCode::ProbeUsb).
+    HintProbeUsb => "hint.probe_usb",
+        "Reconnect the probe, and check its USB cable and hub.";
+    /// The recovery hint of [`ErrorCode::ProbeFirmwareOutdated`](crate::ErrorCode::ProbeFirmwareOutdated).
+    HintProbeFirmwareOutdated => "hint.probe_firmware_outdated",
+        "Update the firmware of the probe with the tool of its vendor.";
+    /// The recovery hint of [`ErrorCode::ProbeUnsupported`](crate::ErrorCode::ProbeUnsupported).
+    HintProbeUnsupported => "hint.probe_unsupported",
+        "Select another protocol or a lower speed, or use another probe.";
+    /// The recovery hint of [`ErrorCode::TargetNotResponding`](crate::ErrorCode::TargetNotResponding).
+    HintTargetNotResponding => "hint.target_not_responding",
+        "Check the wiring and the power of the target, and lower the speed. If the target sleeps or reuses its debug pins, connect under reset.";
+    /// The recovery hint of [`ErrorCode::TargetFault`](crate::ErrorCode::TargetFault).
+    HintTargetFault => "hint.target_fault",
+        "The target refused the access. The memory may not exist or be protected, or a sticky error of a previous access was not cleared.";
+    /// The recovery hint of [`ErrorCode::TargetBusy`](crate::ErrorCode::TargetBusy).
+    HintTargetBusy => "hint.target_busy",
+        "The target stayed busy. Lower the speed, or connect under reset.";
+    /// The recovery hint of [`ErrorCode::TransmissionError`](crate::ErrorCode::TransmissionError).
+    HintTransmissionError => "hint.transmission_error",
+        "Transfers were corrupted. Use shorter wires, connect ground close to the signals, and lower the speed.";
+    /// The recovery hint of [`ErrorCode::Timeout`](crate::ErrorCode::Timeout).
+    HintTimeout => "hint.timeout",
+        "The target did not finish the operation in time. Reset the target, and lower the speed.";
+    /// The recovery hint of [`ErrorCode::TargetNotPowered`](crate::ErrorCode::TargetNotPowered).
+    HintTargetNotPowered => "hint.target_not_powered",
+        "The probe measured a low target voltage. Power the target, and connect its supply to the VTref pin of the probe.";
+    /// The recovery hint of [`ErrorCode::UsbAccessDenied`](crate::ErrorCode::UsbAccessDenied).
+    HintUsbAccessDenied => "hint.usb_access_denied",
+        "The USB device of the probe can not be opened. On Linux, install the udev rules, see https://probe.rs/docs/getting-started/probe-setup/. On Windows, install the WinUSB driver for the probe.";
+    /// The recovery hint of [`ErrorCode::MissingPermissions`](crate::ErrorCode::MissingPermissions).
+    HintMissingPermissions => "hint.missing_permissions",
+        "The operation must be allowed explicitly, e.g. erasing a locked chip with `--allow-erase-all`.";
+    /// The recovery hint of [`ErrorCode::ChipNotFound`](crate::ErrorCode::ChipNotFound).
+    HintChipNotFound => "hint.chip_not_found",
+        "Check the name of the chip. `probe-rs chip list` lists the known chips, and a description of another chip can be loaded from a file.";
+    /// The recovery hint of [`ErrorCode::InvalidChipDescription`](crate::ErrorCode::InvalidChipDescription).
+    HintInvalidChipDescription => "hint.invalid_chip_description",
+        "Fix the chip description file, or regenerate it with target-gen.";
+    /// The recovery hint of [`ErrorCode::NoFlashForData`](crate::ErrorCode::NoFlashForData).
+    HintNoFlashForData => "hint.no_flash_for_data",
+        "The image contains data outside of the flash of the chip. Check the memory layout of the linker script, and the selected chip.";
+    /// The recovery hint of [`ErrorCode::FlashAlgorithm`](crate::ErrorCode::FlashAlgorithm).
+    HintFlashAlgorithm => "hint.flash_algorithm",
+        "Select a flash algorithm for the region in the chip description.";
+    /// The recovery hint of [`ErrorCode::Flashing`](crate::ErrorCode::Flashing).
+    HintFlashing => "hint.flashing",
+        "Check that the flash is not protected, and that the target runs at a supported clock. `probe-rs erase` erases the whole chip.";
+    /// The recovery hint of [`ErrorCode::InvalidImage`](crate::ErrorCode::InvalidImage).
+    HintInvalidImage => "hint.invalid_image",
+        "Check the format and the path of the image.";
+
+    /// The phase of flashing which reads the bytes of the flash which are restored.
+    ProgressReadingFlash => "progress.reading_flash", "Reading flash";
+    /// The phase of flashing which erases the sectors.
+    ProgressErasingSectors => "progress.erasing_sectors", "Erasing sectors";
+    /// The phase of flashing which programs the pages.
+    ProgressProgrammingPages => "progress.programming_pages", "Programming pages";
+    /// The phase of flashing which runs a programmer, e.g. for eFuses.
+    ProgressProgramming => "progress.programming", "Programming {name}";
+    /// Flashing through a bootloader of the chip.
+    ProgressProgrammingWith => "progress.programming_with", "Programming with {bootloader}";
+    /// The whole flash download.
+    ProgressFlashingDevice => "progress.flashing_device", "Flashing device";
+    /// A phase which started.
+    ProgressStarted => "progress.started", "{phase} ...";
+    /// The percentage of a phase which is done.
+    ProgressPercent => "progress.percent", "{phase} ({percent}%)";
+    /// A phase which failed.
+    ProgressFailed => "progress.failed", "{phase} failed!";
+    /// A phase which completed.
+    ProgressComplete => "progress.complete", "{phase} complete!";
+    /// The highlighted word of [`MessageId::ProgressFinishedAfter`].
+    ProgressFinished => "progress.finished", "Finished";
+    /// An operation which finished after some seconds.
+    ProgressFinishedAfter => "progress.finished_after", "{finished} in {seconds}s";
+
+    /// A GDB stub which is started.
+    GdbStubStarting => "gdb.stub_starting", "Firing up GDB stub for {core_type} cores at {addresses}";
+    /// A GDB stub which listens for connections.
+    GdbStubListening => "gdb.stub_listening", "{gdb_stub} listening at {address}";
+    /// A GDB stub which failed, followed by its error.
+    GdbStubFailed => "gdb.stub_failed", "During the execution of GDB an error was encountered:";
+
+    /// More than one probe was found to select one automatically.
+    ProbeMultipleFound => "probe.multiple_found", "Found multiple ({count}) probes";
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&text(*self))
+    }
+}
+
+/// An error when loading a [`Catalog`].
+#[derive(Debug, thiserror::Error)]
+pub enum CatalogError {
+    /// The catalog is not a YAML map from names of messages to their text.
+    #[error("The message catalog is not a valid YAML map of messages")]
+    Yaml(#[from] serde_yaml::Error),
+    /// The catalog contains a message which does not exist.
+    #[error("The message catalog contains the unknown message '{0}'")]
+    UnknownMessage(String),
+    /// A translation uses a placeholder which the message does not have.
+    #[error("The translation of '{message}' uses the unknown placeholder '{{{placeholder}}}'")]
+    UnknownPlaceholder {
+        /// The name of the message.
+        message: String,
+        /// The name of the placeholder.
+        placeholder: String,
+    },
+}
+
+/// Translations of messages, see the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    texts: HashMap<MessageId, String>,
+}
+
+impl Catalog {
+    /// Load a catalog from a YAML map of the names of messages to their translated text.
+    ///
+    /// The translations may only use the placeholders of the default text of their message.
+    pub fn from_yaml_reader(reader: impl Read) -> Result<Self, CatalogError> {
+        let raw: HashMap<String, String> = serde_yaml::from_reader(reader)?;
+
+        let mut texts = HashMap::new();
+        for (name, text) in raw {
+            let Some(id) = MessageId::ALL.iter().find(|id| id.name() == name) else {
+                return Err(CatalogError::UnknownMessage(name));
+            };
+            let allowed = placeholders(id.default_text()).collect::<Vec<_>>();
+            if let Some(placeholder) = placeholders(&text).find(|p| !allowed.contains(p)) {
+                return Err(CatalogError::UnknownPlaceholder {
+                    message: name,
+                    placeholder: placeholder.to_owned(),
+                });
+            }
+            texts.insert(*id, text);
+        }
+
+        Ok(Self { texts })
+    }
+
+    /// The text of a message, or its default text if it is not translated.
+    pub fn text(&self, id: MessageId) -> &str {
+        self.texts
+            .get(&id)
+            .map(String::as_str)
+            .unwrap_or_else(|| id.default_text())
+    }
+}
+
+/// The names of the `{name}` placeholders in a text.
+fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    text.split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+}
+
+/// Use `catalog` for all messages from now on.
+pub fn set_catalog(catalog: Catalog) {
+    *CATALOG.write().unwrap() = catalog;
+}
+
+/// The text of a message in the active catalog.
+pub fn text(id: MessageId) -> String {
+    CATALOG.read().unwrap().text(id).to_owned()
+}
+
+/// The text of a message in the active catalog, with its placeholders replaced by `args`.
+pub fn format(id: MessageId, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut text = text(id);
+    for (name, value) in args {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Catalog, CatalogError, MessageId};
+
+    #[test]
+    fn names_are_unique() {
+        for (index, id) in MessageId::ALL.iter().enumerate() {
+            assert!(
+                MessageId::ALL[index + 1..]
+                    .iter()
+                    .all(|other| other.name() != id.name()),
+                "{} is used twice",
+                id.name()
+            );
+        }
+    }
+
+    #[test]
+    fn translations_fall_back_to_the_default_text() {
+        let yaml = "progress.percent: \"{phase} ({percent} %)\"\n";
+        let catalog = Catalog::from_yaml_reader(yaml.as_bytes()).unwrap();
+
+        assert_eq!(
+            catalog.text(MessageId::ProgressPercent),
+            "{phase} ({percent} %)"
+        );
+        assert_eq!(catalog.text(MessageId::ProgressFailed), "{phase} failed!");
+    }
+
+    #[test]
+    fn invalid_translations_are_rejected() {
+        let unknown = Catalog::from_yaml_reader("progress.unknown: \"x\"".as_bytes());
+        assert!(
+            matches!(unknown, Err(CatalogError::UnknownMessage(name)) if name == "progress.unknown")
+        );
+
+        let placeholder =
+            Catalog::from_yaml_reader("progress.failed: \"{name} failed\"".as_bytes());
+        assert!(matches!(
+            placeholder,
+            Err(CatalogError::UnknownPlaceholder { placeholder, .. }) if placeholder == "name"
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+//! Measure the current consumption of the target with a power profiler, alongside a debug session.
+//!
+//! A [`PowerProfiler`] reads the samples of the profiler. A [`PowerCapture`] reads them in the background, averages
+//! every batch which is read into a [`PowerMeasurement`], timestamped on the same clock as the other
+//! [`events`](crate::events), and sends it as a [`SessionEvent::PowerMeasured`] and to its receiver. So the energy
+//! of an operation can be found by correlating the measurements with the halts of the cores and the RTT messages.
+//!
+//! The [`Ppk2`] is the supported profiler, the Nordic Power Profiler Kit II, with the `serial` feature.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::events::{self, SessionEvent};
+
+#[cfg(feature = "serial")]
+mod ppk2;
+
+#[cfg(feature = "serial")]
+pub use ppk2::{Ppk2, Ppk2Mode};
+
+/// An error of a power profiler.
+#[derive(Debug, thiserror::Error)]
+pub enum PowerProfilerError {
+    /// The serial port of the profiler could not be opened.
+    #[cfg(feature = "serial")]
+    #[error("Failed to open the serial port of the power profiler")]
+    Serial(#[from] serialport::Error),
+    /// Reading from or writing to the profiler failed.
+    #[error("Failed to communicate with the power profiler")]
+    Io(#[from] std::io::Error),
+    /// The profiler answered with data which could not be decoded.
+    #[error("The power profiler sent invalid data: {0}")]
+    InvalidResponse(String),
+}
+
+/// A source of current samples, see the [module documentation](self).
+pub trait PowerProfiler: Send {
+    /// Start sampling.
+    fn start(&mut self) -> Result<(), PowerProfilerError>;
+
+    /// Read the current of the samples which arrived since the previous call, in ampere. This waits a short time for
+    /// samples, and returns an empty list if there are none.
+    fn read(&mut self) -> Result<Vec<f64>, PowerProfilerError>;
+
+    /// Stop sampling.
+    fn stop(&mut self) -> Result<(), PowerProfilerError>;
+
+    /// The time between two samples.
+    fn sample_interval(&self) -> Duration;
+}
+
+/// The average current over a time span.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerMeasurement {
+    /// The start of the time span.
+    pub time: Instant,
+    /// The length of the time span.
+    pub duration: Duration,
+    /// The average current in ampere.
+    pub current: f64,
+}
+
+impl PowerMeasurement {
+    /// The charge which flowed during the measurement, in coulomb.
+    pub fn charge(&self) -> f64 {
+        self.current * self.duration.as_secs_f64()
+    }
+}
+
+/// Reads the samples of a [`PowerProfiler`] in the background, see the [module documentation](self).
+///
+/// Dropping the capture stops the profiler.
+pub struct PowerCapture {
+    measurements: Receiver<PowerMeasurement>,
+    stop: Arc<AtomicBool>,
+}
+
+impl PowerCapture {
+    /// Start the profiler, and read its samples until the capture is dropped or reading fails.
+    pub fn start(mut profiler: impl PowerProfiler + 'static) -> Result<Self, PowerProfilerError> {
+        profiler.start()?;
+
+        let (sender, measurements) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_reader = stop.clone();
+        std::thread::spawn(move || {
+            let interval = profiler.sample_interval();
+            while !stop_reader.load(Ordering::Relaxed) {
+                let samples = match profiler.read() {
+                    Ok(samples) => samples,
+                    Err(error) => {
+                        tracing::error!("Failed to read from the power profiler: {}", error);
+                        events::error(&error);
+                        break;
+                    }
+                };
+                if samples.is_empty() {
+                    continue;
+                }
+
+                // The samples of a batch were taken before it was received, one interval apart.
+                let duration = interval * samples.len() as u32;
+                let measurement = PowerMeasurement {
+                    time: Instant::now()
+                        .checked_sub(duration)
+                        .unwrap_or_else(Instant::now),
+                    duration,
+                    current: samples.iter().sum::<f64>() / samples.len() as f64,
+                };
+                events::emit(|| SessionEvent::PowerMeasured { measurement });
+                if sender.send(measurement).is_err() {
+                    break;
+                }
+            }
+            if let Err(error) = profiler.stop() {
+                tracing::warn!("Failed to stop the power profiler: {}", error);
+            }
+        });
+
+        Ok(Self { measurements, stop })
+    }
+
+    /// The measurements which were taken since the previous call.
+    pub fn measurements(&self) -> impl Iterator<Item = PowerMeasurement> + '_ {
+        self.measurements.try_iter()
+    }
+}
+
+impl Drop for PowerCapture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
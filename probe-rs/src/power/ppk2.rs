@@ -0,0 +1,247 @@
+//! The Nordic Power Profiler Kit II.
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use super::{PowerProfiler, PowerProfilerError};
+
+/// The commands of the PPK2.
+mod ppk2_command {
+    pub const AVERAGE_START: u8 = 0x06;
+    pub const AVERAGE_STOP: u8 = 0x07;
+    pub const DEVICE_RUNNING_SET: u8 = 0x0c;
+    pub const REGULATOR_SET: u8 = 0x0d;
+    pub const SET_POWER_MODE: u8 = 0x11;
+    pub const GET_META_DATA: u8 = 0x19;
+}
+
+/// The PPK2 samples with 100 kHz.
+const PPK2_SAMPLE_INTERVAL: Duration = Duration::from_micros(10);
+
+/// The voltage of one step of the ADC of the PPK2.
+const PPK2_ADC_MULT: f64 = 1.8 / 163840.0;
+
+/// How the PPK2 is connected to the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ppk2Mode {
+    /// The PPK2 measures the current of a target with its own supply. The voltage of the supply, in millivolts, is
+    /// used to correct the measurements.
+    Ammeter {
+        /// The voltage of the supply of the target.
+        millivolts: u16,
+    },
+    /// The PPK2 supplies the target with the given voltage, in millivolts.
+    SourceMeter {
+        /// The voltage of the supply.
+        millivolts: u16,
+    },
+}
+
+impl Ppk2Mode {
+    fn millivolts(self) -> u16 {
+        match self {
+            Ppk2Mode::Ammeter { millivolts } | Ppk2Mode::SourceMeter { millivolts } => millivolts,
+        }
+    }
+}
+
+/// The calibration of the five measurement ranges of a PPK2, as it is stored on the device.
+#[derive(Debug, Clone, PartialEq)]
+struct Ppk2Calibration {
+    r: [f64; 5],
+    gs: [f64; 5],
+    gi: [f64; 5],
+    o: [f64; 5],
+    s: [f64; 5],
+    i: [f64; 5],
+    ug: [f64; 5],
+}
+
+impl Default for Ppk2Calibration {
+    /// The nominal values, for devices which are not calibrated.
+    fn default() -> Self {
+        Self {
+            r: [1031.64, 101.65, 10.15, 0.94, 0.043],
+            gs: [1.0; 5],
+            gi: [1.0; 5],
+            o: [0.0; 5],
+            s: [0.0; 5],
+            i: [0.0; 5],
+            ug: [1.0; 5],
+        }
+    }
+}
+
+impl Ppk2Calibration {
+    /// Parse the metadata of the device, which has a `key: value` pair per line, e.g. `R0: 1003.3087`. Values which
+    /// are missing keep their nominal value.
+    fn parse(metadata: &str) -> Result<Self, PowerProfilerError> {
+        let mut calibration = Self::default();
+        for line in metadata.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let split = key.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+            let (name, range) = key.split_at(split);
+            let Ok(range @ 0..=4) = range.parse::<usize>() else {
+                continue;
+            };
+            let values = match name {
+                "R" => &mut calibration.r,
+                "GS" => &mut calibration.gs,
+                "GI" => &mut calibration.gi,
+                "O" => &mut calibration.o,
+                "S" => &mut calibration.s,
+                "I" => &mut calibration.i,
+                "UG" => &mut calibration.ug,
+                _ => continue,
+            };
+            values[range] = value.trim().parse().map_err(|_| {
+                PowerProfilerError::InvalidResponse(format!("The calibration value '{line}'"))
+            })?;
+        }
+        Ok(calibration)
+    }
+
+    /// Convert a raw sample into the current in ampere, for a supply with `millivolts`.
+    fn current(&self, sample: u32, millivolts: u16) -> f64 {
+        let adc = (sample & 0x3fff) as f64 * 4.0;
+        let range = (((sample >> 14) & 0x7) as usize).min(4);
+        let without_gain = (adc - self.o[range]) * (PPK2_ADC_MULT / self.r[range]);
+        self.ug[range]
+            * (without_gain * (self.gs[range] * without_gain + self.gi[range])
+                + (self.s[range] * (millivolts as f64 / 1000.0) + self.i[range]))
+    }
+}
+
+/// The Nordic Power Profiler Kit II, which is connected through its USB serial port.
+pub struct Ppk2 {
+    port: Box<dyn serialport::SerialPort>,
+    mode: Ppk2Mode,
+    calibration: Ppk2Calibration,
+    /// The bytes of a sample which was not received completely.
+    partial: Vec<u8>,
+}
+
+impl std::fmt::Debug for Ppk2 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ppk2")
+            .field("mode", &self.mode)
+            .field("calibration", &self.calibration)
+            .finish()
+    }
+}
+
+impl Ppk2 {
+    /// Open the PPK2 at the serial port `path`, read its calibration and configure it for `mode`.
+    pub fn open(path: &str, mode: Ppk2Mode) -> Result<Self, PowerProfilerError> {
+        let mut port = serialport::new(path, 115_200)
+            .timeout(Duration::from_millis(100))
+            .open()?;
+
+        port.write_all(&[ppk2_command::GET_META_DATA])?;
+        let metadata = read_metadata(port.as_mut())?;
+        let calibration = Ppk2Calibration::parse(&metadata)?;
+
+        let millivolts = mode.millivolts();
+        let power_mode = match mode {
+            Ppk2Mode::Ammeter { .. } => 1,
+            Ppk2Mode::SourceMeter { .. } => 2,
+        };
+        port.write_all(&[ppk2_command::SET_POWER_MODE, power_mode])?;
+        if let Ppk2Mode::SourceMeter { .. } = mode {
+            let [high, low] = millivolts.to_be_bytes();
+            port.write_all(&[ppk2_command::REGULATOR_SET, high, low])?;
+            port.write_all(&[ppk2_command::DEVICE_RUNNING_SET, 1])?;
+        }
+
+        Ok(Self {
+            port,
+            mode,
+            calibration,
+            partial: Vec::new(),
+        })
+    }
+}
+
+/// Read the metadata of a PPK2, which is terminated with `END`.
+fn read_metadata(port: &mut dyn serialport::SerialPort) -> Result<String, PowerProfilerError> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut metadata = Vec::new();
+    let mut buffer = [0; 256];
+    while !metadata.ends_with(b"END\n") && !metadata.ends_with(b"END") {
+        if Instant::now() > deadline {
+            return Err(PowerProfilerError::InvalidResponse(
+                "The metadata is not terminated".to_string(),
+            ));
+        }
+        match port.read(&mut buffer) {
+            Ok(count) => metadata.extend_from_slice(&buffer[..count]),
+            Err(error) if error.kind() == ErrorKind::TimedOut => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(String::from_utf8_lossy(&metadata).into_owned())
+}
+
+impl PowerProfiler for Ppk2 {
+    fn start(&mut self) -> Result<(), PowerProfilerError> {
+        self.port.write_all(&[ppk2_command::AVERAGE_START])?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<Vec<f64>, PowerProfilerError> {
+        let mut buffer = [0; 4096];
+        let count = match self.port.read(&mut buffer) {
+            Ok(count) => count,
+            Err(error) if error.kind() == ErrorKind::TimedOut => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+        self.partial.extend_from_slice(&buffer[..count]);
+
+        let millivolts = self.mode.millivolts();
+        let complete = self.partial.len() - self.partial.len() % 4;
+        let samples = self.partial[..complete]
+            .chunks_exact(4)
+            .map(|sample| {
+                let sample = u32::from_le_bytes(sample.try_into().unwrap());
+                self.calibration.current(sample, millivolts)
+            })
+            .collect();
+        self.partial.drain(..complete);
+        Ok(samples)
+    }
+
+    fn stop(&mut self) -> Result<(), PowerProfilerError> {
+        self.port.write_all(&[ppk2_command::AVERAGE_STOP])?;
+        if let Ppk2Mode::SourceMeter { .. } = self.mode {
+            self.port
+                .write_all(&[ppk2_command::DEVICE_RUNNING_SET, 0])?;
+        }
+        Ok(())
+    }
+
+    fn sample_interval(&self) -> Duration {
+        PPK2_SAMPLE_INTERVAL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ppk2Calibration;
+
+    #[test]
+    fn ppk2_samples_are_converted_with_the_calibration() {
+        let calibration = Ppk2Calibration::parse(
+            "Calibrated: 0\nR0: 1000.0\nR1: 100.0\nGS1: 0\nGI1: 1\nUG1: 1\nvdd: 3000\nHW: 9173\nEND\n",
+        )
+        .unwrap();
+        assert_eq!(calibration.r[0], 1000.0);
+        assert_eq!(calibration.r[2], 10.15);
+
+        // An ADC value of 0x1000 in range 1.
+        let current = calibration.current(0x1000 | (1 << 14), 3000);
+        let expected = 0x1000 as f64 * 4.0 * (1.8 / 163840.0) / 100.0;
+        assert!((current - expected).abs() < 1e-12);
+    }
+}
@@ -1,11 +1,23 @@
 pub(crate) mod cmsisdap;
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) mod espusbjtag;
 pub(crate) mod fake_probe;
-#[cfg(feature = "ftdi")]
+#[cfg(all(feature = "ftdi", not(target_arch = "wasm32")))]
 pub(crate) mod ftdi;
+pub(crate) mod gdb_remote;
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) mod jlink;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod registry;
+pub(crate) mod replay_probe;
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) mod stlink;
+pub mod transaction_log;
+pub mod usb_policy;
+pub(crate) mod watcher;
 
+use self::cmsisdap::transport::CmsisDapTransport;
+#[cfg(not(target_arch = "wasm32"))]
 use self::espusbjtag::list_espjtag_devices;
 use crate::architecture::arm::ArmError;
 use crate::architecture::riscv::communication_interface::RiscvError;
@@ -26,12 +38,13 @@ use crate::{
     },
     Permissions,
 };
+#[cfg(not(target_arch = "wasm32"))]
 use jlink::list_jlink_devices;
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, time::Duration};
 
 /// Used to log warnings when the measured target voltage is
 /// lower than 1.4V, if at all measureable.
-const LOW_TARGET_VOLTAGE_WARNING_THRESHOLD: f32 = 1.4;
+pub(crate) const LOW_TARGET_VOLTAGE_WARNING_THRESHOLD: f32 = 1.4;
 
 /// The protocol that is to be used by the probe when communicating with the target.
 ///
@@ -176,9 +189,11 @@ pub enum ProbeCreationError {
     #[error("USB device could not be opened. Please check the permissions.")]
     CouldNotOpen,
     /// Some error with HID API occurred.
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("{0}")]
     HidApi(#[from] hidapi::HidError),
     /// Some error with rusb occurred.
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("{0}")]
     Rusb(#[from] rusb::Error),
     /// An error specific with the selected probe occurred.
@@ -235,56 +250,95 @@ impl Probe {
         }
     }
 
+    /// Open a CMSIS-DAP probe over a transport which is not built into probe-rs, e.g. over WebUSB or WebHID.
+    pub fn from_cmsisdap_transport(
+        transport: Box<dyn CmsisDapTransport>,
+    ) -> Result<Self, DebugProbeError> {
+        let device = cmsisdap::commands::CmsisDapDevice::Transport {
+            transport,
+            // Start with the most common packet size, the actual size is requested from the probe.
+            packet_size: 64,
+        };
+        Ok(Probe::new(cmsisdap::CmsisDap::new_from_device(device)?))
+    }
+
     /// Get a list of all debug probes found.
     /// This can be used to select the debug probe which
     /// should be used.
     #[tracing::instrument]
     pub fn list_all() -> Vec<DebugProbeInfo> {
-        let mut list = cmsisdap::tools::list_cmsisdap_devices();
-        #[cfg(feature = "ftdi")]
+        // There are no built-in drivers on wasm32, its probes are opened with `Probe::from_cmsisdap_transport`.
+        #[cfg(target_arch = "wasm32")]
         {
-            list.extend(ftdi::list_ftdi_devices());
+            Vec::new()
         }
-        list.extend(stlink::tools::list_stlink_devices());
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut list = registry::list_registered_devices();
+            list.extend(cmsisdap::tools::list_cmsisdap_devices());
+            #[cfg(feature = "ftdi")]
+            {
+                list.extend(ftdi::list_ftdi_devices());
+            }
+            list.extend(stlink::tools::list_stlink_devices());
+
+            list.extend(list_jlink_devices());
 
-        list.extend(list_jlink_devices());
+            list.extend(list_espjtag_devices());
 
-        list.extend(list_espjtag_devices());
+            #[cfg(feature = "serial")]
+            list.extend(gdb_remote::black_magic_probe::list_black_magic_probes());
 
-        list
+            list
+        }
     }
 
     /// Create a [`Probe`] from [`DebugProbeInfo`]. Use the
     /// [`Probe::list_all()`] function to get the information
     /// about all probes available.
     #[tracing::instrument(skip_all)]
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
     pub fn open(selector: impl Into<DebugProbeSelector> + Clone) -> Result<Self, DebugProbeError> {
-        match cmsisdap::CmsisDap::new_from_selector(selector.clone()) {
-            Ok(link) => return Ok(Probe::from_specific_probe(link)),
-            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
-            Err(e) => return Err(e),
-        };
-        #[cfg(feature = "ftdi")]
-        match ftdi::FtdiProbe::new_from_selector(selector.clone()) {
-            Ok(link) => return Ok(Probe::from_specific_probe(link)),
-            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
-            Err(e) => return Err(e),
-        };
-        match stlink::StLink::new_from_selector(selector.clone()) {
-            Ok(link) => return Ok(Probe::from_specific_probe(link)),
-            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
-            Err(e) => return Err(e),
-        };
-        match jlink::JLink::new_from_selector(selector.clone()) {
-            Ok(link) => return Ok(Probe::from_specific_probe(link)),
-            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
-            Err(e) => return Err(e),
-        };
-        match espusbjtag::EspUsbJtag::new_from_selector(selector) {
-            Ok(link) => return Ok(Probe::from_specific_probe(link)),
-            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
-            Err(e) => return Err(e),
-        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            match registry::open_registered(&selector.clone().into()) {
+                Ok(link) => return Ok(Probe::from_specific_probe(link)),
+                Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+                Err(e) => return Err(e),
+            };
+            match cmsisdap::CmsisDap::new_from_selector(selector.clone()) {
+                Ok(link) => return Ok(Probe::from_specific_probe(link)),
+                Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+                Err(e) => return Err(e),
+            };
+            #[cfg(feature = "ftdi")]
+            match ftdi::FtdiProbe::new_from_selector(selector.clone()) {
+                Ok(link) => return Ok(Probe::from_specific_probe(link)),
+                Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+                Err(e) => return Err(e),
+            };
+            match stlink::StLink::new_from_selector(selector.clone()) {
+                Ok(link) => return Ok(Probe::from_specific_probe(link)),
+                Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+                Err(e) => return Err(e),
+            };
+            match jlink::JLink::new_from_selector(selector.clone()) {
+                Ok(link) => return Ok(Probe::from_specific_probe(link)),
+                Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+                Err(e) => return Err(e),
+            };
+            match espusbjtag::EspUsbJtag::new_from_selector(selector.clone()) {
+                Ok(link) => return Ok(Probe::from_specific_probe(link)),
+                Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+                Err(e) => return Err(e),
+            };
+            #[cfg(feature = "serial")]
+            match gdb_remote::GdbRemote::new_from_selector(selector) {
+                Ok(link) => return Ok(Probe::from_specific_probe(link)),
+                Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+                Err(e) => return Err(e),
+            };
+        }
 
         Err(DebugProbeError::ProbeCouldNotBeCreated(
             ProbeCreationError::NotFound,
@@ -408,6 +462,16 @@ impl Probe {
         self.inner.target_reset_deassert()
     }
 
+    /// Asserts the reset of the target for `duration`, and deasserts it again.
+    /// This is always the hard reset which means the reset wire has to be connected to work.
+    ///
+    /// This is not supported on all probes.
+    pub fn target_reset_pulse(&mut self, duration: Duration) -> Result<(), DebugProbeError> {
+        self.target_reset_assert()?;
+        std::thread::sleep(duration);
+        self.target_reset_deassert()
+    }
+
     /// Configure protocol speed to use in kHz
     pub fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
         if !self.attached {
@@ -492,12 +556,45 @@ impl Probe {
         self.inner.try_as_dap_probe()
     }
 
+    /// Gets raw access to the JTAG scan chain, see [`RawJtagIo`].
+    ///
+    /// This does not work on all probes.
+    pub fn try_as_raw_jtag(&mut self) -> Option<&mut dyn RawJtagIo> {
+        self.inner.try_as_raw_jtag()
+    }
+
     /// Try reading the target voltage of via the connected volgate pin.
     ///
     /// This does not work on all probes.
     pub fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
         self.inner.get_target_voltage()
     }
+
+    /// Switch the power supply of the target on or off.
+    ///
+    /// This does not work on all probes.
+    pub fn set_target_power(&mut self, enabled: bool) -> Result<(), DebugProbeError> {
+        self.inner.set_target_power(enabled)
+    }
+
+    /// Switch the power supply of the target off for `off_time`, and on again.
+    ///
+    /// This can be used to recover a target from a latch-up, or a state in which it cannot be reached
+    /// by the debugger anymore. After switching the power on, `settle_time` is waited for the target to start.
+    ///
+    /// This does not work on all probes.
+    pub fn power_cycle_target(
+        &mut self,
+        off_time: Duration,
+        settle_time: Duration,
+    ) -> Result<(), DebugProbeError> {
+        tracing::info!("Power cycling the target");
+        self.set_target_power(false)?;
+        std::thread::sleep(off_time);
+        self.set_target_power(true)?;
+        std::thread::sleep(settle_time);
+        Ok(())
+    }
 }
 
 /// An abstraction over general debug probe functionality.
@@ -622,15 +719,29 @@ pub trait DebugProbe: Send + fmt::Debug {
         None
     }
 
+    /// Get raw access to the JTAG scan chain.
+    ///
+    /// This is only available on JTAG probes which control the TAP state machine directly.
+    fn try_as_raw_jtag(&mut self) -> Option<&mut dyn RawJtagIo> {
+        None
+    }
+
     /// Reads the target voltage in Volts, if possible. Returns `Ok(None)`
     /// if the probe doesn’t support reading the target voltage.
     fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
         Ok(None)
     }
+
+    /// Switch the power supply of the target on or off, for probes which can power the target.
+    ///
+    /// Returns [`DebugProbeError::NotImplemented`] if the probe cannot control the target power.
+    fn set_target_power(&mut self, _enabled: bool) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotImplemented("target power control"))
+    }
 }
 
 /// Denotes the type of a given [`DebugProbe`].
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum DebugProbeType {
     /// CMSIS-DAP
     CmsisDap,
@@ -642,6 +753,11 @@ pub enum DebugProbeType {
     JLink,
     /// Built in RISC-V ESP JTAG debug probe
     EspJtag,
+    /// Black Magic Probe, through its GDB server
+    BlackMagicProbe,
+    /// A probe of a driver which was registered with [`register_probe_driver`](crate::register_probe_driver), with
+    /// the name of the driver
+    Custom(&'static str),
 }
 
 /// Gathers some information about a debug probe which was found during a scan.
@@ -661,19 +777,27 @@ pub struct DebugProbeInfo {
     /// The USB HID interface which should be used.
     /// This is necessary for composite HID devices.
     pub hid_interface: Option<u8>,
+
+    /// The physical USB path of the debug probe, in the form `<bus>-<port>[.<port>]*`, e.g. `1-4.2`.
+    ///
+    /// This is `None` if the probe driver cannot determine the path.
+    pub usb_path: Option<String>,
 }
 
 impl std::fmt::Debug for DebugProbeInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "{} (VID: {:04x}, PID: {:04x}, {}{:?})",
+            "{} (VID: {:04x}, PID: {:04x}, {}{}{:?})",
             self.identifier,
             self.vendor_id,
             self.product_id,
             self.serial_number
                 .clone()
                 .map_or("".to_owned(), |v| format!("Serial: {v}, ")),
+            self.usb_path
+                .clone()
+                .map_or("".to_owned(), |v| format!("Path: {v}, ")),
             self.probe_type
         )
     }
@@ -696,9 +820,16 @@ impl DebugProbeInfo {
             serial_number,
             probe_type,
             hid_interface: usb_hid_interface,
+            usb_path: None,
         }
     }
 
+    /// Set the physical USB path of the probe.
+    pub fn with_usb_path(mut self, usb_path: Option<String>) -> Self {
+        self.usb_path = usb_path;
+        self
+    }
+
     /// Open the probe described by this `DebugProbeInfo`.
     pub fn open(&self) -> Result<Probe, DebugProbeError> {
         Probe::open(self)
@@ -709,17 +840,22 @@ impl DebugProbeInfo {
 pub enum DebugProbeSelectorParseError {
     #[error("The VID or PID could not be parsed: {0}")]
     ParseInt(#[from] std::num::ParseIntError),
-    #[error("Please use a string in the form `VID:PID:<Serial>` where Serial is optional.")]
+    #[error("Please use a string in the form `VID:PID:<Serial>@<Path>` where Serial and Path are optional.")]
     Format,
 }
 
 /// A struct to describe the way a probe should be selected.
 ///
 /// Construct this from a set of info or from a string. The
-/// string has to be in the format "VID:PID:SERIALNUMBER",
-/// where the serialnumber is optional, and VID and PID are
+/// string has to be in the format "VID:PID:SERIALNUMBER@PATH",
+/// where the serialnumber and the USB path are optional, and VID and PID are
 /// parsed as hexadecimal numbers.
 ///
+/// The USB path selects the probe by the port it is plugged into, e.g. `1-4.2` for
+/// port 2 of the hub on port 4 of bus 1.
+/// This is useful if multiple identical probes are connected, which have no
+/// or no unique serial numbers.
+///
 /// ## Example:
 ///
 /// ```
@@ -728,6 +864,11 @@ pub enum DebugProbeSelectorParseError {
 ///
 /// assert_eq!(selector.vendor_id, 0x1942);
 /// assert_eq!(selector.product_id, 0x1337);
+///
+/// let selector: probe_rs::DebugProbeSelector = "1942:1337@1-4.2".try_into().unwrap();
+///
+/// assert_eq!(selector.serial_number, None);
+/// assert_eq!(selector.usb_path.as_deref(), Some("1-4.2"));
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 // We need this so that serde will first convert from the string `VID:PID:<Serial>` to a struct before deserializing.
@@ -739,17 +880,77 @@ pub struct DebugProbeSelector {
     pub product_id: u16,
     /// The the serial number of the debug probe to be used.
     pub serial_number: Option<String>,
+    /// The physical USB path of the debug probe to be used, in the form `<bus>-<port>[.<port>]*`.
+    pub usb_path: Option<String>,
+}
+
+impl DebugProbeSelector {
+    /// Check if the given probe matches this selector.
+    ///
+    /// If the selector has no serial number or USB path, all probes with the same VID and PID match.
+    pub fn matches(&self, probe_info: &DebugProbeInfo) -> bool {
+        self.vendor_id == probe_info.vendor_id
+            && self.product_id == probe_info.product_id
+            && (self.serial_number.is_none() || self.serial_number == probe_info.serial_number)
+            && self.matches_usb_path(probe_info.usb_path.as_deref())
+    }
+
+    /// Check if a device with the given USB path matches the path of this selector.
+    ///
+    /// If the selector has a USB path, devices with an unknown path never match.
+    pub(crate) fn matches_usb_path(&self, usb_path: Option<&str>) -> bool {
+        match &self.usb_path {
+            Some(selected) => usb_path == Some(selected.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// The physical USB path of a device, in the form `<bus>-<port>[.<port>]*`, e.g. `1-4.2`.
+///
+/// This is the same format which is used by Linux in `/sys/bus/usb/devices`. The path stays
+/// the same as long as the device is plugged into the same port, even if the device has
+/// no serial number.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn usb_path<T: rusb::UsbContext>(device: &rusb::Device<T>) -> Option<String> {
+    let ports = device.port_numbers().ok()?;
+    if ports.is_empty() {
+        return None;
+    }
+
+    let ports = ports
+        .iter()
+        .map(|port| port.to_string())
+        .collect::<Vec<_>>()
+        .join(".");
+    Some(format!("{}-{}", device.bus_number(), ports))
+}
+
+/// Whether `path` has the format of the USB paths of [`usb_path`], the bus number and the port numbers, e.g. `1-4.2`.
+fn is_usb_path(path: &str) -> bool {
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    match path.split_once('-') {
+        Some((bus, ports)) => is_number(bus) && ports.split('.').all(is_number),
+        None => false,
+    }
 }
 
 impl TryFrom<&str> for DebugProbeSelector {
     type Error = DebugProbeSelectorParseError;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
+        // Serial numbers can contain an `@` too, so only a suffix which is a USB path is split off.
+        let (value, usb_path) = match value.rsplit_once('@') {
+            Some((value, usb_path)) if is_usb_path(usb_path) => (value, Some(usb_path.to_string())),
+            _ => (value, None),
+        };
+
         let split = value.split(':').collect::<Vec<_>>();
         let mut selector = if split.len() > 1 {
             DebugProbeSelector {
                 vendor_id: u16::from_str_radix(split[0], 16)?,
                 product_id: u16::from_str_radix(split[1], 16)?,
                 serial_number: None,
+                usb_path,
             }
         } else {
             return Err(DebugProbeSelectorParseError::Format);
@@ -783,6 +984,7 @@ impl From<DebugProbeInfo> for DebugProbeSelector {
             vendor_id: selector.vendor_id,
             product_id: selector.product_id,
             serial_number: selector.serial_number,
+            usb_path: selector.usb_path,
         }
     }
 }
@@ -793,6 +995,7 @@ impl From<&DebugProbeInfo> for DebugProbeSelector {
             vendor_id: selector.vendor_id,
             product_id: selector.product_id,
             serial_number: selector.serial_number.clone(),
+            usb_path: selector.usb_path.clone(),
         }
     }
 }
@@ -803,6 +1006,9 @@ impl fmt::Display for DebugProbeSelector {
         if let Some(ref sn) = self.serial_number {
             write!(f, ":{sn}")?;
         }
+        if let Some(ref usb_path) = self.usb_path {
+            write!(f, "@{usb_path}")?;
+        }
         Ok(())
     }
 }
@@ -860,6 +1066,72 @@ pub trait JTAGAccess: DebugProbe {
     }
 }
 
+/// Raw access to the JTAG scan chain, to talk to TAPs which probe-rs doesn't know, e.g. an FPGA or a vendor specific
+/// TAP on the same chain as the MCU.
+///
+/// The registers are shifted through the whole chain: the TAPs which are not addressed have to be put into BYPASS by
+/// the caller. Each shift starts and ends in the Run-Test/Idle state.
+pub trait RawJtagIo {
+    /// Shift the lowest `bits` bits of `data` into the instruction registers of the chain, LSB first, and return the
+    /// bits which were shifted out.
+    fn shift_ir(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError>;
+
+    /// Shift the lowest `bits` bits of `data` into the data registers of the chain, LSB first, and return the bits
+    /// which were shifted out.
+    fn shift_dr(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError>;
+
+    /// Stay in the Run-Test/Idle state for `cycles` clock cycles.
+    fn run_test_idle(&mut self, cycles: usize) -> Result<(), DebugProbeError>;
+}
+
+/// The TMS and TDI bits of a raw shift of `bits` bits of `data` into the IR, or the DR, from Run-Test/Idle back to
+/// Run-Test/Idle, with the index of the clock cycle which shifts the first bit.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) fn raw_shift_sequence(
+    ir: bool,
+    data: &[u8],
+    bits: usize,
+) -> Result<(Vec<bool>, Vec<bool>, usize), DebugProbeError> {
+    if bits == 0 || data.len() * 8 < bits {
+        return Err(DebugProbeError::Other(anyhow::anyhow!(
+            "{bits} bits can't be shifted from {} bytes of data",
+            data.len()
+        )));
+    }
+
+    let tms_enter_shift: &[bool] = if ir {
+        &[true, true, false, false]
+    } else {
+        &[true, false, false]
+    };
+
+    // The last bit is shifted when leaving the Shift state, through Exit1 and Update.
+    let mut tms = tms_enter_shift.to_vec();
+    tms.extend(std::iter::repeat(false).take(bits - 1));
+    tms.extend_from_slice(&[true, true, false]);
+
+    let mut tdi = vec![false; tms_enter_shift.len()];
+    tdi.extend((0..bits).map(|bit| data[bit / 8] & (1 << (bit % 8)) != 0));
+    tdi.extend_from_slice(&[false, false]);
+
+    Ok((tms, tdi, tms_enter_shift.len()))
+}
+
+/// Pack the TDO `bits` which were captured during a raw shift into bytes, LSB first.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) fn raw_shift_response(bits: impl IntoIterator<Item = bool>) -> Vec<u8> {
+    let mut response = Vec::new();
+    for (index, bit) in bits.into_iter().enumerate() {
+        if index % 8 == 0 {
+            response.push(0);
+        }
+        if bit {
+            *response.last_mut().unwrap() |= 1 << (index % 8);
+        }
+    }
+    response
+}
+
 pub type DeferredResultIndex = usize;
 
 #[derive(Debug, Clone)]
@@ -914,3 +1186,80 @@ pub enum AttachMethod {
     /// This is required on targets that can remap SWD pins or disable the SWD interface in sleep.
     UnderReset,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn selector_with_usb_path() {
+        let selector: DebugProbeSelector = "0483:374b:SERIAL@1-4.2".parse().unwrap();
+
+        assert_eq!(selector.serial_number.as_deref(), Some("SERIAL"));
+        assert_eq!(selector.usb_path.as_deref(), Some("1-4.2"));
+        assert_eq!(selector.to_string(), "0483:374b:SERIAL@1-4.2");
+
+        assert!("0483:374b@".parse::<DebugProbeSelector>().is_err());
+    }
+
+    #[test]
+    fn selector_with_at_sign_in_serial_number() {
+        let selector: DebugProbeSelector = "0483:374b:user@host".parse().unwrap();
+        assert_eq!(selector.serial_number.as_deref(), Some("user@host"));
+        assert_eq!(selector.usb_path, None);
+
+        let selector: DebugProbeSelector = "0483:374b:user@host@1-4.2".parse().unwrap();
+        assert_eq!(selector.serial_number.as_deref(), Some("user@host"));
+        assert_eq!(selector.usb_path.as_deref(), Some("1-4.2"));
+    }
+
+    #[test]
+    fn selector_matches_usb_path() {
+        let probe = DebugProbeInfo::new(
+            "ST-Link",
+            0x0483,
+            0x374b,
+            None,
+            DebugProbeType::StLink,
+            None,
+        )
+        .with_usb_path(Some("1-4.2".to_string()));
+
+        assert!("0483:374b"
+            .parse::<DebugProbeSelector>()
+            .unwrap()
+            .matches(&probe));
+        assert!("0483:374b@1-4.2"
+            .parse::<DebugProbeSelector>()
+            .unwrap()
+            .matches(&probe));
+        assert!(!"0483:374b@1-4.3"
+            .parse::<DebugProbeSelector>()
+            .unwrap()
+            .matches(&probe));
+
+        let probe = probe.with_usb_path(None);
+        assert!(!"0483:374b@1-4.2"
+            .parse::<DebugProbeSelector>()
+            .unwrap()
+            .matches(&probe));
+    }
+
+    #[test]
+    fn raw_dr_shift_sequence() {
+        let (tms, tdi, start) = raw_shift_sequence(false, &[0b101], 3).unwrap();
+        assert_eq!(tms, [true, false, false, false, false, true, true, false]);
+        assert_eq!(tdi, [false, false, false, true, false, true, false, false]);
+        assert_eq!(start, 3);
+
+        let (tms, _, start) = raw_shift_sequence(true, &[0x1f, 0x01], 9).unwrap();
+        assert_eq!(tms.len(), 4 + 9 + 2);
+        assert_eq!(start, 4);
+
+        assert!(raw_shift_sequence(true, &[0xff], 9).is_err());
+        assert_eq!(
+            raw_shift_response([true, false, true, true, false, false, false, false, true]),
+            [0x0d, 0x01]
+        );
+    }
+}
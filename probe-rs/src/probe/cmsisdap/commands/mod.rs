@@ -5,7 +5,9 @@ pub mod swo;
 pub mod transfer;
 
 use crate::probe::cmsisdap::commands::general::info::PacketSizeCommand;
-use crate::DebugProbeError;
+use crate::probe::cmsisdap::transport::{CmsisDapTransport, CmsisDapTransportError};
+use crate::probe::usb_policy;
+use crate::{DebugProbeError, DebugProbeType};
 use std::str::Utf8Error;
 use std::time::Duration;
 
@@ -26,18 +28,25 @@ pub enum CmsisDapError {
     SwoTraceStreamError,
     #[error("Requested SWO mode is not available on this probe")]
     SwoModeNotAvailable,
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("USB Error reading SWO data.")]
     SwoReadError(#[source] rusb::Error),
+    #[error("Error reading SWO data.")]
+    SwoTransportError(#[source] CmsisDapTransportError),
     #[error("Could not determine a suitable packet size for this probe")]
     NoPacketSize,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum SendError {
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("Error in the USB HID access")]
     HidApi(#[from] hidapi::HidError),
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("Error in the USB access")]
     UsbError(rusb::Error),
+    #[error("Error in the transport to the probe")]
+    TransportError(#[source] CmsisDapTransportError),
     #[error("Not enough data in response from probe")]
     NotEnoughData,
     #[error("Status can only be 0x00 or 0xFF")]
@@ -58,6 +67,7 @@ pub enum SendError {
     Timeout,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl From<rusb::Error> for SendError {
     fn from(error: rusb::Error) -> Self {
         match error {
@@ -67,6 +77,15 @@ impl From<rusb::Error> for SendError {
     }
 }
 
+impl From<CmsisDapTransportError> for SendError {
+    fn from(error: CmsisDapTransportError) -> Self {
+        match error {
+            CmsisDapTransportError::Timeout => SendError::Timeout,
+            other => SendError::TransportError(other),
+        }
+    }
+}
+
 impl From<CmsisDapError> for DebugProbeError {
     fn from(error: CmsisDapError) -> Self {
         DebugProbeError::ProbeSpecific(Box::new(error))
@@ -76,6 +95,7 @@ impl From<CmsisDapError> for DebugProbeError {
 pub enum CmsisDapDevice {
     /// CMSIS-DAP v1 over HID.
     /// Stores a HID device handle and maximum HID report size.
+    #[cfg(not(target_arch = "wasm32"))]
     V1 {
         handle: hidapi::HidDevice,
         report_size: usize,
@@ -84,6 +104,7 @@ pub enum CmsisDapDevice {
     /// CMSIS-DAP v2 over WinUSB/Bulk.
     /// Stores an rusb device handle, out/in EP addresses, maximum DAP packet size,
     /// and an optional SWO streaming EP address and SWO maximum packet size.
+    #[cfg(not(target_arch = "wasm32"))]
     V2 {
         handle: rusb::DeviceHandle<rusb::Context>,
         out_ep: u8,
@@ -91,34 +112,65 @@ pub enum CmsisDapDevice {
         max_packet_size: usize,
         swo_ep: Option<(u8, usize)>,
     },
+
+    /// CMSIS-DAP over a transport which is implemented outside of probe-rs, e.g. over WebUSB.
+    /// Stores the transport and the maximum DAP packet size.
+    Transport {
+        transport: Box<dyn CmsisDapTransport>,
+        packet_size: usize,
+    },
 }
 
 impl CmsisDapDevice {
     /// Read from the probe into `buf`, returning the number of bytes read on success.
     fn read(&self, buf: &mut [u8]) -> Result<usize, SendError> {
-        match self {
-            CmsisDapDevice::V1 { handle, .. } => match handle.read_timeout(buf, 1000)? {
-                // Timeout is not indicated by error, but by returning 0 read bytes
-                0 => Err(SendError::Timeout),
-                n => Ok(n),
+        let default_timeout = match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            CmsisDapDevice::V2 { .. } => Duration::from_millis(100),
+            _ => Duration::from_millis(1000),
+        };
+        usb_policy::transfer(
+            &DebugProbeType::CmsisDap,
+            default_timeout,
+            |error| matches!(error, SendError::Timeout),
+            |timeout| match self {
+                #[cfg(not(target_arch = "wasm32"))]
+                CmsisDapDevice::V1 { handle, .. } => {
+                    let timeout = timeout.as_millis().try_into().unwrap_or(i32::MAX);
+                    match handle.read_timeout(buf, timeout)? {
+                        // Timeout is not indicated by error, but by returning 0 read bytes
+                        0 => Err(SendError::Timeout),
+                        n => Ok(n),
+                    }
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                CmsisDapDevice::V2 { handle, in_ep, .. } => {
+                    Ok(handle.read_bulk(*in_ep, buf, timeout)?)
+                }
+                CmsisDapDevice::Transport { transport, .. } => Ok(transport.read(buf, timeout)?),
             },
-            CmsisDapDevice::V2 { handle, in_ep, .. } => {
-                let timeout = Duration::from_millis(100);
-                Ok(handle.read_bulk(*in_ep, buf, timeout)?)
-            }
-        }
+        )
     }
 
     /// Write `buf` to the probe, returning the number of bytes written on success.
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
     fn write(&self, buf: &[u8]) -> Result<usize, SendError> {
-        match self {
-            CmsisDapDevice::V1 { handle, .. } => Ok(handle.write(buf)?),
-            CmsisDapDevice::V2 { handle, out_ep, .. } => {
-                let timeout = Duration::from_millis(100);
-                // Skip first byte as it's set to 0 for HID transfers
-                Ok(handle.write_bulk(*out_ep, &buf[1..], timeout)?)
-            }
-        }
+        usb_policy::transfer(
+            &DebugProbeType::CmsisDap,
+            Duration::from_millis(100),
+            |error| matches!(error, SendError::Timeout),
+            |timeout| match self {
+                #[cfg(not(target_arch = "wasm32"))]
+                CmsisDapDevice::V1 { handle, .. } => Ok(handle.write(buf)?),
+                #[cfg(not(target_arch = "wasm32"))]
+                CmsisDapDevice::V2 { handle, out_ep, .. } => {
+                    // Skip first byte as it's set to 0 for HID transfers
+                    Ok(handle.write_bulk(*out_ep, &buf[1..], timeout)?)
+                }
+                // Skip the HID report ID here as well.
+                CmsisDapDevice::Transport { transport, .. } => Ok(transport.write(&buf[1..])?),
+            },
+        )
     }
 
     /// Drain any pending data from the probe, ensuring future responses are
@@ -128,6 +180,7 @@ impl CmsisDapDevice {
         tracing::debug!("Draining probe of any pending data.");
 
         match self {
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V1 {
                 handle,
                 report_size,
@@ -140,6 +193,7 @@ impl CmsisDapDevice {
                 }
             },
 
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V2 {
                 handle,
                 in_ep,
@@ -155,28 +209,50 @@ impl CmsisDapDevice {
                     }
                 }
             }
+
+            CmsisDapDevice::Transport {
+                transport,
+                packet_size,
+            } => {
+                let timeout = Duration::from_millis(1);
+                let mut discard = vec![0u8; *packet_size];
+                loop {
+                    match transport.read(&mut discard, timeout) {
+                        Ok(n) if n != 0 => continue,
+                        _ => break,
+                    }
+                }
+            }
         }
     }
 
     /// Set the packet size to use for this device.
     ///
     /// Sets either the HID report size for V1 devices,
-    /// or the maximum bulk transfer size for V2 devices.
+    /// or the maximum bulk transfer size for V2 devices and other transports.
     pub(super) fn set_packet_size(&mut self, packet_size: usize) {
         tracing::debug!("Configuring probe to use packet size {}", packet_size);
         match self {
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V1 {
                 ref mut report_size,
                 ..
             } => {
                 *report_size = packet_size;
             }
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V2 {
                 ref mut max_packet_size,
                 ..
             } => {
                 *max_packet_size = packet_size;
             }
+            CmsisDapDevice::Transport {
+                packet_size: ref mut size,
+                ..
+            } => {
+                *size = packet_size;
+            }
         }
     }
 
@@ -216,8 +292,11 @@ impl CmsisDapDevice {
     /// Check if SWO streaming is supported by this device.
     pub(super) fn swo_streaming_supported(&self) -> bool {
         match self {
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V1 { .. } => false,
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V2 { swo_ep, .. } => swo_ep.is_some(),
+            CmsisDapDevice::Transport { transport, .. } => transport.swo_streaming_supported(),
         }
     }
 
@@ -228,7 +307,9 @@ impl CmsisDapDevice {
     /// On timeout, returns a zero-length buffer.
     pub(super) fn read_swo_stream(&self, timeout: Duration) -> Result<Vec<u8>, CmsisDapError> {
         match self {
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V1 { .. } => Err(CmsisDapError::SwoModeNotAvailable),
+            #[cfg(not(target_arch = "wasm32"))]
             CmsisDapDevice::V2 { handle, swo_ep, .. } => match swo_ep {
                 Some((ep, len)) => {
                     let mut buf = vec![0u8; *len];
@@ -246,6 +327,20 @@ impl CmsisDapDevice {
                 }
                 None => Err(CmsisDapError::SwoModeNotAvailable),
             },
+            CmsisDapDevice::Transport {
+                transport,
+                packet_size,
+            } => {
+                if !transport.swo_streaming_supported() {
+                    return Err(CmsisDapError::SwoModeNotAvailable);
+                }
+                let mut buf = vec![0u8; *packet_size];
+                let n = transport
+                    .read_swo(&mut buf, timeout)
+                    .map_err(CmsisDapError::SwoTransportError)?;
+                buf.truncate(n);
+                Ok(buf)
+            }
         }
     }
 }
@@ -339,10 +434,13 @@ fn send_command_inner<Req: Request>(
     // on v2 we can truncate to just the required data.
     // Add one byte for HID report ID.
     let buffer_len: usize = match device {
+        #[cfg(not(target_arch = "wasm32"))]
         CmsisDapDevice::V1 { report_size, .. } => *report_size + 1,
+        #[cfg(not(target_arch = "wasm32"))]
         CmsisDapDevice::V2 {
             max_packet_size, ..
         } => *max_packet_size + 1,
+        CmsisDapDevice::Transport { packet_size, .. } => *packet_size + 1,
     };
     let mut buffer = vec![0; buffer_len];
 
@@ -354,8 +452,14 @@ fn send_command_inner<Req: Request>(
     // so set the transfer size to the report size, plus one
     // byte for the HID report ID. On v2 devices, we just
     // write the exact required size every time.
-    if let CmsisDapDevice::V1 { report_size, .. } = device {
-        size = *report_size + 1;
+    match device {
+        #[cfg(not(target_arch = "wasm32"))]
+        CmsisDapDevice::V1 { report_size, .. } => size = *report_size + 1,
+        CmsisDapDevice::Transport {
+            transport,
+            packet_size,
+        } if transport.pads_packets() => size = *packet_size + 1,
+        _ => {}
     }
 
     // Send buffer to the device.
@@ -1,5 +1,7 @@
 pub mod commands;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod tools;
+pub mod transport;
 
 use crate::{
     architecture::arm::{
@@ -471,15 +473,23 @@ impl CmsisDap {
 }
 
 impl DebugProbe for CmsisDap {
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
     fn new_from_selector(
         selector: impl Into<DebugProbeSelector>,
     ) -> Result<Box<Self>, DebugProbeError>
     where
         Self: Sized,
     {
-        Ok(Box::new(Self::new_from_device(
+        #[cfg(not(target_arch = "wasm32"))]
+        return Ok(Box::new(Self::new_from_device(
             tools::open_device_from_selector(selector)?,
-        )?))
+        )?));
+
+        // Without the native drivers, the probe is opened with `Probe::from_cmsisdap_transport`.
+        #[cfg(target_arch = "wasm32")]
+        Err(DebugProbeError::ProbeCouldNotBeCreated(
+            crate::ProbeCreationError::NotFound,
+        ))
     }
 
     fn get_name(&self) -> &str {
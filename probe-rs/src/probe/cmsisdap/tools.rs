@@ -1,6 +1,6 @@
 use super::CmsisDapDevice;
 use crate::{
-    probe::{DebugProbeInfo, DebugProbeType, ProbeCreationError},
+    probe::{usb_path, DebugProbeInfo, DebugProbeType, ProbeCreationError},
     DebugProbeSelector,
 };
 use hidapi::HidApi;
@@ -117,6 +117,7 @@ fn get_cmsisdap_info(device: &Device<rusb::Context>) -> Option<DebugProbeInfo> {
             serial_number: sn_str,
             probe_type: DebugProbeType::CmsisDap,
             hid_interface,
+            usb_path: usb_path(device),
         })
     } else {
         None
@@ -142,6 +143,7 @@ fn get_cmsisdap_hid_info(device: &hidapi::DeviceInfo) -> Option<DebugProbeInfo>
             serial_number: device.serial_number().map(|s| s.to_owned()),
             probe_type: DebugProbeType::CmsisDap,
             hid_interface: Some(device.interface_number() as u8),
+            usb_path: None,
         })
     } else {
         None
@@ -236,9 +238,11 @@ fn device_matches(
     device_descriptor: DeviceDescriptor,
     selector: &DebugProbeSelector,
     serial_str: Option<String>,
+    usb_path: Option<String>,
 ) -> bool {
     if device_descriptor.vendor_id() == selector.vendor_id
         && device_descriptor.product_id() == selector.product_id
+        && selector.matches_usb_path(usb_path.as_deref())
     {
         if selector.serial_number.is_some() {
             serial_str == selector.serial_number
@@ -308,7 +312,7 @@ pub fn open_device_from_selector(
             // multiple open handles are not allowed on Windows.
             drop(handle);
 
-            if device_matches(d_desc, &selector, sn_str) {
+            if device_matches(d_desc, &selector, sn_str, usb_path(&device)) {
                 hid_device_info = get_cmsisdap_info(&device);
 
                 if hid_device_info.is_some() {
@@ -328,7 +332,17 @@ pub fn open_device_from_selector(
     // If rusb failed or the device didn't support v2, try using hidapi to open in v1 mode.
     let vid = selector.vendor_id;
     let pid = selector.product_id;
-    let sn = &selector.serial_number;
+    let mut sn = selector.serial_number.clone();
+
+    // hidapi does not report the USB path, so a probe selected by its path can only be
+    // opened in v1 mode if rusb found it above. In that case, its serial number is used
+    // to find the same device with hidapi.
+    if selector.usb_path.is_some() {
+        match &hid_device_info {
+            Some(info) => sn = sn.or_else(|| info.serial_number.clone()),
+            None => return Err(ProbeCreationError::NotFound),
+        }
+    }
 
     tracing::debug!(
         "Attempting to open {:04x}:{:04x} in CMSIS-DAP v1 mode",
@@ -349,7 +363,7 @@ pub fn open_device_from_selector(
         .find(|info| {
             let mut device_match = info.vendor_id() == vid && info.product_id() == pid;
 
-            if let Some(sn) = sn {
+            if let Some(sn) = &sn {
                 device_match &= Some(sn.as_ref()) == info.serial_number();
             }
 
@@ -0,0 +1,107 @@
+//! Transports for CMSIS-DAP probes which are not opened by probe-rs itself.
+//!
+//! probe-rs opens CMSIS-DAP probes over HID with hidapi, and over USB bulk endpoints with libusb. Other hosts, e.g. a
+//! browser-based flasher which reaches the probe over WebUSB or WebHID, implement [`CmsisDapTransport`] and open the
+//! probe with [`Probe::from_cmsisdap_transport`](crate::Probe::from_cmsisdap_transport). The commands are the same
+//! for every transport.
+//!
+//! On wasm32, the native probe drivers are not built, so a transport is the only way to open a probe.
+
+use std::{fmt, time::Duration};
+
+/// An error of a [`CmsisDapTransport`].
+#[derive(Debug, thiserror::Error)]
+pub enum CmsisDapTransportError {
+    /// No packet arrived before the timeout.
+    #[error("Timeout in the communication with the probe.")]
+    Timeout,
+    /// The transport failed.
+    #[error("Error in the communication with the probe")]
+    Other(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// The connection to a CMSIS-DAP probe.
+///
+/// A packet starts with the ID of the command or response, so it excludes the report ID of HID transports.
+pub trait CmsisDapTransport: Send + fmt::Debug {
+    /// Whether every packet which is written must have the full packet size, like the reports of HID transports.
+    /// Shorter packets are padded with zeros.
+    fn pads_packets(&self) -> bool;
+
+    /// Write a packet to the probe, returning the number of bytes written.
+    fn write(&self, packet: &[u8]) -> Result<usize, CmsisDapTransportError>;
+
+    /// Read a packet from the probe into `buffer`, returning the number of bytes read.
+    ///
+    /// Returns [`CmsisDapTransportError::Timeout`] if no packet arrives within `timeout`.
+    fn read(&self, buffer: &mut [u8], timeout: Duration) -> Result<usize, CmsisDapTransportError>;
+
+    /// Whether the probe streams SWO data to its own endpoint, which is read with [`Self::read_swo`].
+    fn swo_streaming_supported(&self) -> bool {
+        false
+    }
+
+    /// Read SWO data from the streaming endpoint into `buffer`, returning the number of bytes read, or zero on
+    /// timeout.
+    fn read_swo(
+        &self,
+        _buffer: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<usize, CmsisDapTransportError> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::probe::cmsisdap::commands::{
+        general::info::PacketSizeCommand, send_command, CmsisDapDevice,
+    };
+
+    /// A HID probe with 64 byte reports, which answers the request of its packet size.
+    #[derive(Debug, Default)]
+    struct HidProbe {
+        written: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl CmsisDapTransport for HidProbe {
+        fn pads_packets(&self) -> bool {
+            true
+        }
+
+        fn write(&self, packet: &[u8]) -> Result<usize, CmsisDapTransportError> {
+            self.written.lock().unwrap().push(packet.to_vec());
+            Ok(packet.len())
+        }
+
+        fn read(
+            &self,
+            buffer: &mut [u8],
+            _timeout: Duration,
+        ) -> Result<usize, CmsisDapTransportError> {
+            let response = [0x00, 0x02, 0x40, 0x00];
+            buffer[..response.len()].copy_from_slice(&response);
+            Ok(response.len())
+        }
+    }
+
+    #[test]
+    fn commands_are_sent_over_the_transport() {
+        let probe = HidProbe::default();
+        let written = probe.written.clone();
+        let mut device = CmsisDapDevice::Transport {
+            transport: Box::new(probe),
+            packet_size: 64,
+        };
+
+        assert_eq!(send_command(&mut device, PacketSizeCommand {}).unwrap(), 64);
+
+        // The packet is padded to the report size, without the report ID.
+        let mut packet = vec![0; 64];
+        packet[1] = 0xFF;
+        assert_eq!(*written.lock().unwrap(), [packet]);
+    }
+}
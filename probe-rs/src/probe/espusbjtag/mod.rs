@@ -20,7 +20,7 @@ use crate::{
 
 use self::protocol::{BitIter, ProtocolHandler};
 
-use super::JTAGAccess;
+use super::{raw_shift_response, raw_shift_sequence, JTAGAccess, RawJtagIo};
 
 pub use protocol::list_espjtag_devices;
 
@@ -429,6 +429,30 @@ impl JTAGAccess for EspUsbJtag {
     }
 }
 
+impl RawJtagIo for EspUsbJtag {
+    fn shift_ir(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+        let (tms, tdi, start) = raw_shift_sequence(true, data, bits)?;
+        let response = self.protocol.jtag_io(tms, tdi, true)?;
+
+        // The IR no longer holds the last register which was selected by `write_register`.
+        self.current_ir_reg = u32::MAX;
+
+        Ok(raw_shift_response(response.skip(start).take(bits)))
+    }
+
+    fn shift_dr(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+        let (tms, tdi, start) = raw_shift_sequence(false, data, bits)?;
+        let response = self.protocol.jtag_io(tms, tdi, true)?;
+        Ok(raw_shift_response(response.skip(start).take(bits)))
+    }
+
+    fn run_test_idle(&mut self, cycles: usize) -> Result<(), DebugProbeError> {
+        let idle = iter::repeat(false).take(cycles);
+        self.protocol.jtag_io(idle.clone(), idle, false)?;
+        Ok(())
+    }
+}
+
 impl DebugProbe for EspUsbJtag {
     fn new_from_selector(
         selector: impl Into<DebugProbeSelector>,
@@ -565,6 +589,10 @@ impl DebugProbe for EspUsbJtag {
         None
     }
 
+    fn try_as_raw_jtag(&mut self) -> Option<&mut dyn RawJtagIo> {
+        Some(self)
+    }
+
     fn try_get_arm_interface<'probe>(
         self: Box<Self>,
     ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
@@ -4,6 +4,7 @@ use std::{fmt::Debug, time::Duration};
 use rusb::{request_type, Context, Device, Direction, TransferType, UsbContext};
 
 use crate::{
+    probe::{usb_path, usb_policy},
     DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType, ProbeCreationError,
 };
 
@@ -81,9 +82,10 @@ impl ProtocolHandler {
             .filter(is_espjtag_device)
             .find_map(|device| {
                 let descriptor = device.device_descriptor().ok()?;
-                // First match the VID & PID.
+                // First match the VID & PID, and the USB path if one was given.
                 if selector.vendor_id == descriptor.vendor_id()
                     && selector.product_id == descriptor.product_id()
+                    && selector.matches_usb_path(usb_path(&device).as_deref())
                 {
                     // If the VID & PID match, match the serial if one was given.
                     if let Some(serial) = &selector.serial_number {
@@ -392,10 +394,16 @@ impl ProtocolHandler {
         let mut offset = 0;
         let mut total = 0;
         loop {
-            let bytes = self
-                .device_handle
-                .write_bulk(self.ep_out, &commands[offset..], USB_TIMEOUT)
-                .map_err(|e| DebugProbeError::Usb(Some(Box::new(e))))?;
+            let bytes = usb_policy::transfer(
+                &DebugProbeType::EspJtag,
+                USB_TIMEOUT,
+                |error| *error == rusb::Error::Timeout,
+                |timeout| {
+                    self.device_handle
+                        .write_bulk(self.ep_out, &commands[offset..], timeout)
+                },
+            )
+            .map_err(|e| DebugProbeError::Usb(Some(Box::new(e))))?;
             total += bytes;
             offset += bytes;
 
@@ -433,10 +441,16 @@ impl ProtocolHandler {
         let mut offset = 0;
         let mut total = 0;
         loop {
-            let read_bytes = self
-                .device_handle
-                .read_bulk(self.ep_in, &mut incoming[offset..], USB_TIMEOUT)
-                .map_err(|e| {
+            let read_bytes = usb_policy::transfer(
+                &DebugProbeType::EspJtag,
+                USB_TIMEOUT,
+                |error| *error == rusb::Error::Timeout,
+                |timeout| {
+                    self.device_handle
+                        .read_bulk(self.ep_in, &mut incoming[offset..], timeout)
+                },
+            )
+            .map_err(|e| {
                     tracing::warn!(
                         "Something went wrong in read_bulk {:?} when trying to read {}bytes - pending_in_bits: {}",
                         e,
@@ -740,14 +754,17 @@ pub fn list_espjtag_devices() -> Vec<DebugProbeInfo> {
                         }
                     };
 
-                    Some(DebugProbeInfo::new(
-                        "ESP JTAG".to_string(),
-                        descriptor.vendor_id(),
-                        descriptor.product_id(),
-                        sn_str,
-                        DebugProbeType::EspJtag,
-                        None,
-                    ))
+                    Some(
+                        DebugProbeInfo::new(
+                            "ESP JTAG".to_string(),
+                            descriptor.vendor_id(),
+                            descriptor.product_id(),
+                            sn_str,
+                            DebugProbeType::EspJtag,
+                            None,
+                        )
+                        .with_usb_path(usb_path(&device)),
+                    )
                 })
                 .collect::<Vec<_>>()
         })
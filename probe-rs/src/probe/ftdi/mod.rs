@@ -3,7 +3,7 @@ use crate::architecture::{
     arm::communication_interface::UninitializedArmProbe,
     riscv::communication_interface::RiscvCommunicationInterface,
 };
-use crate::probe::{JTAGAccess, ProbeCreationError};
+use crate::probe::{usb_path, usb_policy, JTAGAccess, ProbeCreationError, RawJtagIo};
 use crate::{
     DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType, WireProtocol,
 };
@@ -79,7 +79,8 @@ impl JtagAdapter {
     }
 
     fn read_response(&mut self, size: usize) -> io::Result<Vec<u8>> {
-        let timeout = Duration::from_millis(10);
+        let timeout =
+            usb_policy::policy(&DebugProbeType::Ftdi).timeout_or(Duration::from_millis(10));
         let mut result = Vec::new();
 
         let t0 = std::time::Instant::now();
@@ -155,6 +156,16 @@ impl JtagAdapter {
         self.device.write_all(&command)
     }
 
+    /// Shift whole bytes without leaving the SHIFT state
+    fn transfer_tdi_bytes(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let n: u16 = (data.len() - 1) as u16;
+        let mut command = vec![0x39];
+        command.extend_from_slice(&n.to_le_bytes());
+        command.extend_from_slice(data);
+        self.device.write_all(&command)?;
+        self.read_response(data.len())
+    }
+
     fn tranfer_tdi(&mut self, mut data: &[u8], mut bits: usize) -> io::Result<Vec<u8>> {
         assert!(bits > 0);
         assert!((bits + 7) / 8 <= data.len());
@@ -237,9 +248,22 @@ impl JtagAdapter {
     }
 
     /// Shift to DR and return to IDLE
+    ///
+    /// Long registers, e.g. the configuration of an FPGA, are shifted in chunks, so the replies fit
+    /// into the buffer of the adapter.
     pub fn transfer_dr(&mut self, data: &[u8], bits: usize) -> io::Result<Vec<u8>> {
+        const CHUNK_BYTES: usize = 256;
+
         self.shift_tms(&[0b001], 3)?;
-        let r = self.tranfer_tdi(data, bits)?;
+        let full_chunks = (bits - 1) / (CHUNK_BYTES * 8);
+        let mut r = Vec::with_capacity((bits + 7) / 8);
+        for chunk in data[..full_chunks * CHUNK_BYTES].chunks(CHUNK_BYTES) {
+            r.extend(self.transfer_tdi_bytes(chunk)?);
+        }
+        r.extend(self.tranfer_tdi(
+            &data[full_chunks * CHUNK_BYTES..],
+            bits - full_chunks * CHUNK_BYTES * 8,
+        )?);
         self.shift_tms(&[0b01], 2)?;
         Ok(r)
     }
@@ -555,6 +579,10 @@ impl DebugProbe for FtdiProbe {
         self
     }
 
+    fn try_as_raw_jtag(&mut self) -> Option<&mut dyn RawJtagIo> {
+        Some(self)
+    }
+
     fn try_get_arm_interface<'probe>(
         self: Box<Self>,
     ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
@@ -674,7 +702,8 @@ impl JTAGAccess for FtdiProbe {
                 }
             }
 
-            let timeout = Duration::from_millis(10);
+            let timeout =
+                usb_policy::policy(&DebugProbeType::Ftdi).timeout_or(Duration::from_millis(10));
             let mut result = Vec::new();
 
             let t0 = std::time::Instant::now();
@@ -752,6 +781,26 @@ impl JTAGAccess for FtdiProbe {
     }
 }
 
+impl RawJtagIo for FtdiProbe {
+    fn shift_ir(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+        self.adapter
+            .transfer_ir(data, bits)
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))
+    }
+
+    fn shift_dr(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+        self.adapter
+            .transfer_dr(data, bits)
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))
+    }
+
+    fn run_test_idle(&mut self, cycles: usize) -> Result<(), DebugProbeError> {
+        self.adapter
+            .idle(cycles)
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))
+    }
+}
+
 /// (VendorId, ProductId)
 static FTDI_COMPAT_DEVICE_IDS: &[(u16, u16)] = &[
     (0x0403, 0x6010), // FTDI Ltd. FT2232C/D/H Dual UART/FIFO IC
@@ -792,6 +841,7 @@ fn get_device_info(device: &rusb::Device<rusb::Context>) -> Option<DebugProbeInf
         serial_number: sn_str,
         probe_type: DebugProbeType::Ftdi,
         hid_interface: None,
+        usb_path: usb_path(device),
     })
 }
 
@@ -0,0 +1,99 @@
+//! The Black Magic Probe, whose GDB server is on the first of its USB serial ports.
+
+use std::collections::HashSet;
+
+use serialport::{SerialPortType, UsbPortInfo};
+
+use crate::{DebugProbeInfo, DebugProbeSelector, DebugProbeType};
+
+const VENDOR_ID: u16 = 0x1d50;
+const PRODUCT_ID: u16 = 0x6018;
+
+/// The USB interface of the serial port of the GDB server. The second serial port is the UART of the probe.
+const GDB_SERVER_INTERFACE: u8 = 0;
+
+pub(crate) fn list_black_magic_probes() -> Vec<DebugProbeInfo> {
+    gdb_server_ports()
+        .into_iter()
+        .map(|(_, probe_info)| probe_info)
+        .collect()
+}
+
+/// The serial port of the GDB server of the probe which matches the selector.
+pub(crate) fn find_gdb_server_port(selector: &DebugProbeSelector) -> Option<String> {
+    gdb_server_ports()
+        .into_iter()
+        .find(|(_, probe_info)| selector.matches(probe_info))
+        .map(|(port_name, _)| port_name)
+}
+
+fn gdb_server_ports() -> Vec<(String, DebugProbeInfo)> {
+    let mut ports = match serialport::available_ports() {
+        Ok(ports) => ports,
+        Err(e) => {
+            tracing::debug!("Failed to list the serial ports: {e}");
+            return vec![];
+        }
+    };
+
+    // The GDB server is on the port with the lower number.
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+
+    let mut serial_numbers = HashSet::new();
+    ports
+        .into_iter()
+        // macOS has a second device for every port, which waits for the carrier when it is opened.
+        .filter(|port| !port.port_name.starts_with("/dev/tty."))
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb_info) if is_gdb_server_port(&usb_info) => Some((
+                port.port_name,
+                DebugProbeInfo::new(
+                    "Black Magic Probe",
+                    usb_info.vid,
+                    usb_info.pid,
+                    usb_info.serial_number,
+                    DebugProbeType::BlackMagicProbe,
+                    None,
+                ),
+            )),
+            _ => None,
+        })
+        // Only the first port of every probe, if the interfaces are unknown.
+        .filter(|(_, probe_info)| serial_numbers.insert(probe_info.serial_number.clone()))
+        .collect()
+}
+
+fn is_gdb_server_port(usb_info: &UsbPortInfo) -> bool {
+    // The interface is unknown on some platforms.
+    usb_info.vid == VENDOR_ID
+        && usb_info.pid == PRODUCT_ID
+        && usb_info
+            .interface
+            .map_or(true, |interface| interface == GDB_SERVER_INTERFACE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn port(pid: u16, interface: Option<u8>) -> UsbPortInfo {
+        UsbPortInfo {
+            vid: VENDOR_ID,
+            pid,
+            serial_number: Some("7BB180B4".to_string()),
+            manufacturer: Some("Black Magic Debug".to_string()),
+            product: Some("Black Magic Probe".to_string()),
+            interface,
+        }
+    }
+
+    #[test]
+    fn only_the_gdb_server_port_is_used() {
+        assert!(is_gdb_server_port(&port(PRODUCT_ID, Some(0))));
+        assert!(is_gdb_server_port(&port(PRODUCT_ID, None)));
+        // The UART of the probe.
+        assert!(!is_gdb_server_port(&port(PRODUCT_ID, Some(2))));
+        // The DFU bootloader.
+        assert!(!is_gdb_server_port(&port(0x6017, Some(0))));
+    }
+}
@@ -0,0 +1,474 @@
+//! The packet layer of the GDB remote serial protocol.
+
+use std::fmt;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[cfg(feature = "serial")]
+use serialport::SerialPort;
+
+use super::GdbRemoteError;
+
+/// How long to wait for the response to a packet.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a packet is sent again, when the server reports a checksum error.
+const MAX_RETRANSMISSIONS: usize = 3;
+
+/// The packet size which is assumed when the server does not report one in `qSupported`.
+const DEFAULT_PACKET_SIZE: usize = 0x400;
+
+/// The stream which the packets are sent over.
+trait Transport: Read + Write + Send {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+
+    /// Whether data has been received, which can be read without blocking.
+    fn has_data(&mut self) -> io::Result<bool>;
+}
+
+impl Transport for TcpStream {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn has_data(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let received = self.peek(&mut [0]);
+        self.set_nonblocking(false)?;
+        match received {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Transport for Box<dyn SerialPort> {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(SerialPort::try_clone(self.as_ref())?))
+    }
+
+    fn has_data(&mut self) -> io::Result<bool> {
+        Ok(self.bytes_to_read()? > 0)
+    }
+}
+
+/// A connection to a GDB server.
+pub(crate) struct GdbConnection {
+    reader: BufReader<Box<dyn Transport>>,
+    writer: Box<dyn Transport>,
+    /// The largest packet the server accepts, including the framing.
+    packet_size: usize,
+}
+
+impl fmt::Debug for GdbConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GdbConnection")
+            .field("packet_size", &self.packet_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GdbConnection {
+    /// Connect to the server at a TCP address, and ask it for the features it supports.
+    pub(crate) fn connect(address: impl ToSocketAddrs) -> Result<Self, GdbRemoteError> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_nodelay(true)?;
+
+        Self::open(Box::new(stream))
+    }
+
+    /// Connect to the server on a serial port, e.g. the one of a Black Magic Probe.
+    #[cfg(feature = "serial")]
+    pub(crate) fn connect_serial(port: &str) -> Result<Self, GdbRemoteError> {
+        // The baud rate does not matter for USB CDC ports.
+        let port = serialport::new(port, 115_200)
+            .timeout(TIMEOUT)
+            .open()
+            .map_err(io::Error::from)?;
+
+        Self::open(Box::new(port))
+    }
+
+    fn open(transport: Box<dyn Transport>) -> Result<Self, GdbRemoteError> {
+        let mut connection = Self {
+            reader: BufReader::new(transport.try_clone()?),
+            writer: transport,
+            packet_size: DEFAULT_PACKET_SIZE,
+        };
+
+        let features = connection.command(b"qSupported")?;
+        if let Some(packet_size) = parse_packet_size(&features) {
+            connection.packet_size = packet_size;
+        }
+
+        // The stop reason is requested by GDB when it connects, some servers attach to the target with it.
+        connection.command(b"?")?;
+
+        Ok(connection)
+    }
+
+    /// The largest number of bytes which can be read or written with one packet.
+    pub(crate) fn max_transfer_size(&self) -> usize {
+        // Two hex digits per byte, and some space for the command and the address.
+        (self.packet_size.saturating_sub(32) / 2).max(4) & !3
+    }
+
+    /// Read memory with the `m` packet.
+    pub(crate) fn read_memory(
+        &mut self,
+        address: u64,
+        data: &mut [u8],
+    ) -> Result<(), GdbRemoteError> {
+        let chunk_size = self.max_transfer_size();
+        for (index, chunk) in data.chunks_mut(chunk_size).enumerate() {
+            let chunk_address = address + (index * chunk_size) as u64;
+            let response =
+                self.command(format!("m{chunk_address:x},{:x}", chunk.len()).as_bytes())?;
+            let bytes = decode_hex(&response)?;
+            if bytes.len() != chunk.len() {
+                return Err(GdbRemoteError::UnexpectedResponse(
+                    String::from_utf8_lossy(&response).into_owned(),
+                ));
+            }
+            chunk.copy_from_slice(&bytes);
+        }
+        Ok(())
+    }
+
+    /// Write memory with the `M` packet.
+    pub(crate) fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<(), GdbRemoteError> {
+        let chunk_size = self.max_transfer_size();
+        for (index, chunk) in data.chunks(chunk_size).enumerate() {
+            let chunk_address = address + (index * chunk_size) as u64;
+            let mut packet = format!("M{chunk_address:x},{:x}:", chunk.len());
+            packet.push_str(&encode_hex(chunk));
+            self.expect_ok(packet.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Send a command which is answered with `OK`.
+    pub(crate) fn expect_ok(&mut self, command: &[u8]) -> Result<(), GdbRemoteError> {
+        match self.command(command)?.as_slice() {
+            b"OK" => Ok(()),
+            response => Err(GdbRemoteError::UnexpectedResponse(
+                String::from_utf8_lossy(response).into_owned(),
+            )),
+        }
+    }
+
+    /// Send a command, and return the response of the server.
+    pub(crate) fn command(&mut self, command: &[u8]) -> Result<Vec<u8>, GdbRemoteError> {
+        self.send(command)?;
+        self.read_response()
+    }
+
+    /// Send a packet, without waiting for the response, e.g. to resume the target.
+    pub(crate) fn send(&mut self, command: &[u8]) -> Result<(), GdbRemoteError> {
+        tracing::trace!("Sending packet {}", String::from_utf8_lossy(command));
+        let packet = encode_packet(command);
+
+        let mut retransmissions = 0;
+        loop {
+            self.writer.write_all(&packet)?;
+            match self.read_byte()? {
+                b'+' => return Ok(()),
+                b'-' if retransmissions < MAX_RETRANSMISSIONS => retransmissions += 1,
+                _ => return Err(GdbRemoteError::NotAcknowledged),
+            }
+        }
+    }
+
+    /// Read the response to a packet.
+    pub(crate) fn read_response(&mut self) -> Result<Vec<u8>, GdbRemoteError> {
+        let response = self.read_packet()?;
+        tracing::trace!("Received packet {}", String::from_utf8_lossy(&response));
+
+        match response.as_slice() {
+            [b'E', high, low] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                let code = decode_hex(&response[1..])?[0];
+                Err(GdbRemoteError::ErrorResponse(code))
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Return the next packet if the server has sent one, e.g. the stop reply of a running target, without waiting
+    /// for it.
+    pub(crate) fn poll_response(&mut self) -> Result<Option<Vec<u8>>, GdbRemoteError> {
+        if self.reader.buffer().is_empty() && !self.reader.get_mut().has_data()? {
+            return Ok(None);
+        }
+        self.read_response().map(Some)
+    }
+
+    /// Interrupt the running target, and return its stop reply.
+    pub(crate) fn interrupt(&mut self) -> Result<Vec<u8>, GdbRemoteError> {
+        self.writer.write_all(&[0x03])?;
+        self.read_response()
+    }
+
+    /// Run a command of the server, e.g. `system_reset` for QEMU, with the `qRcmd` packet.
+    pub(crate) fn monitor(&mut self, command: &str) -> Result<(), GdbRemoteError> {
+        self.send(format!("qRcmd,{}", encode_hex(command.as_bytes())).as_bytes())?;
+        loop {
+            match self.read_response()?.as_slice() {
+                b"OK" => return Ok(()),
+                // Output of the command.
+                [b'O', output @ ..] if !output.is_empty() => {
+                    let output = decode_hex(output)?;
+                    tracing::info!("{}", String::from_utf8_lossy(&output).trim_end());
+                }
+                response => {
+                    return Err(GdbRemoteError::UnexpectedResponse(
+                        String::from_utf8_lossy(response).into_owned(),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Read a register with the `p` packet.
+    pub(crate) fn read_register(&mut self, number: u32) -> Result<u32, GdbRemoteError> {
+        let response = self.command(format!("p{number:x}").as_bytes())?;
+        let bytes = decode_hex(&response)?;
+        let mut value = [0; 4];
+        for (byte, register_byte) in value.iter_mut().zip(bytes) {
+            *byte = register_byte;
+        }
+        Ok(u32::from_le_bytes(value))
+    }
+
+    /// Write a register with the `P` packet.
+    pub(crate) fn write_register(&mut self, number: u32, value: u32) -> Result<(), GdbRemoteError> {
+        self.expect_ok(format!("P{number:x}={}", encode_hex(&value.to_le_bytes())).as_bytes())
+    }
+
+    /// Read a file of the target description, e.g. `target.xml`, with the `qXfer:features:read` packet.
+    pub(crate) fn read_target_description(
+        &mut self,
+        annex: &str,
+    ) -> Result<Vec<u8>, GdbRemoteError> {
+        let chunk_size = self.max_transfer_size();
+        let mut description = Vec::new();
+        loop {
+            let response = self.command(
+                format!(
+                    "qXfer:features:read:{annex}:{:x},{chunk_size:x}",
+                    description.len()
+                )
+                .as_bytes(),
+            )?;
+            match response.split_first() {
+                Some((b'm', data)) => description.extend(decode_binary(data)),
+                Some((b'l', data)) => {
+                    description.extend(decode_binary(data));
+                    return Ok(description);
+                }
+                _ => {
+                    return Err(GdbRemoteError::UnexpectedResponse(
+                        String::from_utf8_lossy(&response).into_owned(),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Read the next packet, and acknowledge it.
+    fn read_packet(&mut self) -> Result<Vec<u8>, GdbRemoteError> {
+        loop {
+            // Skip everything before the start of the packet, e.g. duplicate acknowledgements.
+            while self.read_byte()? != b'$' {}
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+
+            if decode_hex(&checksum)?.first() == Some(&packet_checksum(&data)) {
+                self.writer.write_all(b"+")?;
+                return decode_run_length(&data);
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, GdbRemoteError> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Frame a packet as `$<data>#<checksum>`.
+fn encode_packet(data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(data.len() + 4);
+    packet.push(b'$');
+    packet.extend_from_slice(data);
+    packet.push(b'#');
+    packet.extend_from_slice(format!("{:02x}", packet_checksum(data)).as_bytes());
+    packet
+}
+
+/// Expand the run-length encoding of a response, where `x*n` repeats `x` another `n - 29` times.
+fn decode_run_length(data: &[u8]) -> Result<Vec<u8>, GdbRemoteError> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte != b'*' {
+            decoded.push(byte);
+            continue;
+        }
+
+        let (Some(&repeated), Some(&count)) = (decoded.last(), bytes.next()) else {
+            return Err(GdbRemoteError::UnexpectedResponse(
+                String::from_utf8_lossy(data).into_owned(),
+            ));
+        };
+        let count = count.checked_sub(29).ok_or_else(|| {
+            GdbRemoteError::UnexpectedResponse(String::from_utf8_lossy(data).into_owned())
+        })?;
+        decoded.extend(std::iter::repeat(repeated).take(count as usize));
+    }
+    Ok(decoded)
+}
+
+/// The `PacketSize` feature of a `qSupported` response.
+fn parse_packet_size(features: &[u8]) -> Option<usize> {
+    String::from_utf8_lossy(features)
+        .split(';')
+        .find_map(|feature| feature.strip_prefix("PacketSize="))
+        .and_then(|size| usize::from_str_radix(size, 16).ok())
+}
+
+/// Undo the escaping of binary data, where `}` is followed by the escaped byte XOR 0x20.
+fn decode_binary(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => decoded.extend(bytes.next().map(|escaped| escaped ^ 0x20)),
+            byte => decoded.push(byte),
+        }
+    }
+    decoded
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &[u8]) -> Result<Vec<u8>, GdbRemoteError> {
+    if hex.len() % 2 != 0 {
+        return Err(GdbRemoteError::UnexpectedResponse(
+            String::from_utf8_lossy(hex).into_owned(),
+        ));
+    }
+    hex.chunks(2)
+        .map(|digits| {
+            std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| {
+                    GdbRemoteError::UnexpectedResponse(String::from_utf8_lossy(hex).into_owned())
+                })
+        })
+        .collect()
+}
+
+/// A server which expects the given packets of a session in order, and answers each with the given response.
+///
+/// The interrupt request is given as the packet `\x03`.
+#[cfg(test)]
+pub(crate) fn serve(script: &'static [(&'static [u8], &'static [u8])]) -> std::net::SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut connection = GdbConnection {
+            reader: BufReader::new(Transport::try_clone(&stream).unwrap()),
+            writer: Box::new(stream),
+            packet_size: DEFAULT_PACKET_SIZE,
+        };
+        for (request, response) in script {
+            if *request == b"\x03" {
+                assert_eq!(connection.read_byte().unwrap(), 0x03);
+            } else {
+                assert_eq!(
+                    String::from_utf8_lossy(&connection.read_packet().unwrap()),
+                    String::from_utf8_lossy(request)
+                );
+            }
+            connection
+                .writer
+                .write_all(&encode_packet(response))
+                .unwrap();
+            assert_eq!(connection.read_byte().unwrap(), b'+');
+        }
+    });
+    address
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn packets_are_framed_with_checksum() {
+        assert_eq!(encode_packet(b"m20000000,4"), b"$m20000000,4#4f");
+        assert_eq!(encode_packet(b""), b"$#00");
+    }
+
+    #[test]
+    fn run_length_is_expanded() {
+        assert_eq!(decode_run_length(b"0* ").unwrap(), b"0000");
+        assert_eq!(decode_run_length(b"12ab").unwrap(), b"12ab");
+        assert!(decode_run_length(b"*!").is_err());
+    }
+
+    #[test]
+    fn packet_size_is_parsed() {
+        assert_eq!(
+            parse_packet_size(b"PacketSize=3fff;qXfer:memory-map:read+"),
+            Some(0x3fff)
+        );
+        assert_eq!(parse_packet_size(b"swbreak+"), None);
+    }
+
+    #[test]
+    fn memory_is_read_and_written() {
+        let address = serve(&[
+            (b"qSupported", b"PacketSize=40"),
+            (b"?", b"S05"),
+            (b"m20000000,4", b"78563412"),
+            (b"M20000000,4:01020304", b"OK"),
+            (b"M20000000,4:01020304", b"E01"),
+        ]);
+        let mut connection = GdbConnection::connect(address).unwrap();
+        assert_eq!(connection.max_transfer_size(), 16);
+
+        let mut data = [0; 4];
+        connection.read_memory(0x2000_0000, &mut data).unwrap();
+        assert_eq!(u32::from_le_bytes(data), 0x1234_5678);
+
+        connection.write_memory(0x2000_0000, &[1, 2, 3, 4]).unwrap();
+        assert!(matches!(
+            connection.write_memory(0x2000_0000, &[1, 2, 3, 4]),
+            Err(GdbRemoteError::ErrorResponse(1))
+        ));
+    }
+}
@@ -0,0 +1,590 @@
+//! The debug registers of a Cortex-M core, emulated with the commands of a GDB stub.
+//!
+//! Emulators like QEMU do not implement the debug registers of the cores they emulate, but their GDB stub can halt,
+//! resume and step the core, and access its registers and breakpoints. The accesses of probe-rs to the debug registers
+//! are translated into these commands, so the emulated core is controlled like one which is attached to a debug
+//! probe:
+//!
+//! - DHCSR halts the core with an interrupt, and resumes or steps it with the `c` and `s` packets.
+//! - DCRSR and DCRDR read and write the core registers with the `p` and `P` packets.
+//! - The comparators of the FPB are set with the `Z1` and `z1` packets.
+//! - A system reset requested with AIRCR runs the `system_reset` monitor command of QEMU, and halts the core
+//!   afterwards if DEMCR catches the reset.
+//!
+//! In the all-stop mode of the GDB protocol, memory can only be accessed while the core is halted, so the core is
+//! halted for the accesses of a running core, and resumed afterwards. The FPU and the DWT are not emulated.
+
+use std::collections::HashMap;
+
+use super::connection::GdbConnection;
+use super::GdbRemoteError;
+
+const DHCSR: u64 = 0xE000_EDF0;
+const DCRSR: u64 = 0xE000_EDF4;
+const DCRDR: u64 = 0xE000_EDF8;
+const DEMCR: u64 = 0xE000_EDFC;
+const AIRCR: u64 = 0xE000_ED0C;
+const DFSR: u64 = 0xE000_ED30;
+const MVFR0: u64 = 0xE000_EF40;
+const DWT_CTRL: u64 = 0xE000_1000;
+const FP_CTRL: u64 = 0xE000_2000;
+const FP_REMAP: u64 = 0xE000_2004;
+const FP_COMP0: u64 = 0xE000_2008;
+
+const DHCSR_KEY: u32 = 0xA05F << 16;
+const DHCSR_CONTROL: u32 = 0b1111;
+const C_DEBUGEN: u32 = 1 << 0;
+const C_HALT: u32 = 1 << 1;
+const C_STEP: u32 = 1 << 2;
+const S_REGRDY: u32 = 1 << 16;
+const S_HALT: u32 = 1 << 17;
+const S_RESET_ST: u32 = 1 << 25;
+
+const DFSR_HALTED: u32 = 1 << 0;
+const DFSR_BKPT: u32 = 1 << 1;
+const DFSR_VCATCH: u32 = 1 << 3;
+
+const DEMCR_VC_CORERESET: u32 = 1 << 0;
+
+const AIRCR_VECTKEY: u32 = 0x05FA << 16;
+const AIRCR_SYSRESETREQ: u32 = 1 << 2;
+
+const DCRSR_REGWNR: u32 = 1 << 16;
+
+/// The number of emulated FPB comparators.
+const NUM_COMPARATORS: usize = 8;
+
+/// The special purpose registers, which share one register of DCRSR.
+const EXTRA_REGISTER: u32 = 0b10100;
+
+/// The names of the registers in the target description, by their number in DCRSR.
+const REGISTER_NAMES: &[(u32, &str)] = &[
+    (0, "r0"),
+    (1, "r1"),
+    (2, "r2"),
+    (3, "r3"),
+    (4, "r4"),
+    (5, "r5"),
+    (6, "r6"),
+    (7, "r7"),
+    (8, "r8"),
+    (9, "r9"),
+    (10, "r10"),
+    (11, "r11"),
+    (12, "r12"),
+    (13, "sp"),
+    (14, "lr"),
+    (15, "pc"),
+    (16, "xpsr"),
+    (17, "msp"),
+    (18, "psp"),
+];
+
+/// The special purpose registers in the extra register of DCRSR, and the bit they start at.
+const EXTRA_REGISTER_NAMES: &[(&str, u32)] = &[
+    ("control", 24),
+    ("faultmask", 16),
+    ("basepri", 8),
+    ("primask", 0),
+];
+
+/// The numbers of the core registers in the GDB stub.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RegisterMap {
+    numbers: HashMap<String, u32>,
+}
+
+impl RegisterMap {
+    /// Read the register numbers from the target description of the stub.
+    pub(crate) fn read(connection: &mut GdbConnection) -> Self {
+        match read_register_numbers(connection, "target.xml") {
+            Ok(numbers) if numbers.contains_key("pc") => Self { numbers },
+            result => {
+                if let Err(error) = result {
+                    tracing::debug!("Failed to read the target description: {error}");
+                }
+                Self::default()
+            }
+        }
+    }
+
+    fn number(&self, name: &str) -> Option<u32> {
+        self.numbers.get(name).copied()
+    }
+}
+
+impl Default for RegisterMap {
+    /// The registers of the `org.gnu.gdb.arm.m-profile` feature, for stubs without a target description.
+    fn default() -> Self {
+        let mut numbers = (0..16)
+            .map(|number| (REGISTER_NAMES[number].1.to_string(), number as u32))
+            .collect::<HashMap<_, _>>();
+        numbers.insert("xpsr".to_string(), 25);
+        Self { numbers }
+    }
+}
+
+fn read_register_numbers(
+    connection: &mut GdbConnection,
+    annex: &str,
+) -> Result<HashMap<String, u32>, GdbRemoteError> {
+    let mut numbers = HashMap::new();
+    let mut next_number = 0;
+    collect_register_numbers(connection, annex, &mut numbers, &mut next_number)?;
+    Ok(numbers)
+}
+
+/// Collect the `<reg>` elements of a target description file and the files it includes, in the order they are
+/// numbered in.
+fn collect_register_numbers(
+    connection: &mut GdbConnection,
+    annex: &str,
+    numbers: &mut HashMap<String, u32>,
+    next_number: &mut u32,
+) -> Result<(), GdbRemoteError> {
+    let description = connection.read_target_description(annex)?;
+    for element in parse_elements(&String::from_utf8_lossy(&description)) {
+        match element {
+            Element::Include(href) => {
+                collect_register_numbers(connection, &href, numbers, next_number)?
+            }
+            Element::Register { name, number } => {
+                let number = number.unwrap_or(*next_number);
+                numbers.insert(name.to_lowercase(), number);
+                *next_number = number + 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Element {
+    Include(String),
+    Register { name: String, number: Option<u32> },
+}
+
+/// The includes and registers of a target description, in document order.
+fn parse_elements(description: &str) -> Vec<Element> {
+    description
+        .split('<')
+        .filter_map(|tag| {
+            let (tag_name, attributes) = tag.split_once(char::is_whitespace)?;
+            match tag_name {
+                "xi:include" => attribute(attributes, "href").map(Element::Include),
+                "reg" => Some(Element::Register {
+                    name: attribute(attributes, "name")?,
+                    number: attribute(attributes, "regnum").and_then(|number| number.parse().ok()),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let start = attributes.find(&format!("{name}="))? + name.len() + 1;
+    let quote = attributes[start..].chars().next()?;
+    let value = &attributes[start + 1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+/// Why the core stopped, according to a stop reply.
+#[derive(Debug, PartialEq, Eq)]
+enum StopReason {
+    /// The core was interrupted, with `SIGINT`.
+    Interrupted,
+    /// The core stopped by itself, e.g. at a breakpoint or after a step.
+    Trap,
+}
+
+fn parse_stop_reply(reply: &[u8]) -> Result<StopReason, GdbRemoteError> {
+    match reply {
+        [b'S' | b'T', b'0', b'2', ..] => Ok(StopReason::Interrupted),
+        [b'S' | b'T', ..] => Ok(StopReason::Trap),
+        _ => Err(GdbRemoteError::UnexpectedResponse(
+            String::from_utf8_lossy(reply).into_owned(),
+        )),
+    }
+}
+
+/// The emulated debug registers of the core.
+#[derive(Debug)]
+pub(crate) struct EmulatedDebugRegisters {
+    registers: RegisterMap,
+    halted: bool,
+    /// The control bits of DHCSR.
+    control: u32,
+    /// The sticky reset status of DHCSR, which is cleared when it is read.
+    reset_status: bool,
+    dcrdr: u32,
+    demcr: u32,
+    dfsr: u32,
+    fpb_enabled: bool,
+    comparators: [u32; NUM_COMPARATORS],
+    /// The breakpoints which are set in the stub.
+    breakpoints: Vec<u32>,
+}
+
+impl EmulatedDebugRegisters {
+    /// The emulation for a stub which has just been connected to, and stopped the core.
+    pub(crate) fn new(registers: RegisterMap) -> Self {
+        Self {
+            registers,
+            halted: true,
+            // The core is halted, as if by the debugger.
+            control: C_DEBUGEN | C_HALT,
+            reset_status: false,
+            dcrdr: 0,
+            demcr: 0,
+            dfsr: DFSR_HALTED,
+            fpb_enabled: false,
+            comparators: [0; NUM_COMPARATORS],
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Read an emulated register, or return `None` for other addresses.
+    pub(crate) fn read(
+        &mut self,
+        connection: &mut GdbConnection,
+        address: u64,
+    ) -> Result<Option<u32>, GdbRemoteError> {
+        let value = match address {
+            DHCSR => {
+                self.poll_stop(connection)?;
+                let mut value = self.control | S_REGRDY;
+                if self.halted {
+                    value |= S_HALT;
+                }
+                if std::mem::take(&mut self.reset_status) {
+                    value |= S_RESET_ST;
+                }
+                value
+            }
+            DCRDR => self.dcrdr,
+            DEMCR => self.demcr,
+            DFSR => self.dfsr,
+            MVFR0 | DWT_CTRL | FP_REMAP => 0,
+            FP_CTRL => {
+                // Revision 2 of the FPB, with the number of comparators split into two fields.
+                let num_code = NUM_COMPARATORS as u32;
+                (1 << 28)
+                    | ((num_code >> 4) << 12)
+                    | ((num_code & 0xF) << 4)
+                    | self.fpb_enabled as u32
+            }
+            _ => match self.comparator_index(address) {
+                Some(index) => self.comparators[index],
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(value))
+    }
+
+    /// Write an emulated register. Returns `false` for other addresses.
+    pub(crate) fn write(
+        &mut self,
+        connection: &mut GdbConnection,
+        address: u64,
+        value: u32,
+    ) -> Result<bool, GdbRemoteError> {
+        match address {
+            DHCSR if value & 0xFFFF_0000 == DHCSR_KEY => {
+                self.control = value & DHCSR_CONTROL;
+                self.update_run_state(connection)?;
+            }
+            // Writes without the key are ignored.
+            DHCSR => {}
+            DCRSR => self.transfer_register(connection, value)?,
+            DCRDR => self.dcrdr = value,
+            DEMCR => self.demcr = value,
+            DFSR => self.dfsr &= !value,
+            AIRCR if value & 0xFFFF_0000 == AIRCR_VECTKEY && value & AIRCR_SYSRESETREQ != 0 => {
+                self.reset(connection)?
+            }
+            MVFR0 | DWT_CTRL | FP_REMAP => {}
+            FP_CTRL => {
+                // The enable bit is only written together with the key.
+                if value & 0b10 != 0 {
+                    self.fpb_enabled = value & 1 != 0;
+                    self.update_breakpoints(connection)?;
+                }
+            }
+            _ => match self.comparator_index(address) {
+                Some(index) => {
+                    self.comparators[index] = value;
+                    self.update_breakpoints(connection)?;
+                }
+                None => return Ok(false),
+            },
+        }
+        Ok(true)
+    }
+
+    /// Access memory, which needs a halted core.
+    ///
+    /// A running core is halted for the access, and resumed afterwards.
+    pub(crate) fn with_halted_core<R>(
+        &mut self,
+        connection: &mut GdbConnection,
+        access: impl FnOnce(&mut GdbConnection) -> Result<R, GdbRemoteError>,
+    ) -> Result<R, GdbRemoteError> {
+        if self.halted {
+            return access(connection);
+        }
+
+        if parse_stop_reply(&connection.interrupt()?)? == StopReason::Trap {
+            // The core stopped by itself before it was interrupted, so it stays halted.
+            self.halt(DFSR_BKPT);
+            return access(connection);
+        }
+
+        let result = access(connection);
+        connection.send(b"c")?;
+        result
+    }
+
+    /// Halt the core, if it is running.
+    pub(crate) fn halt_core(
+        &mut self,
+        connection: &mut GdbConnection,
+    ) -> Result<(), GdbRemoteError> {
+        if !self.halted {
+            let reason = match parse_stop_reply(&connection.interrupt()?)? {
+                StopReason::Interrupted => DFSR_HALTED,
+                StopReason::Trap => DFSR_BKPT,
+            };
+            self.halt(reason);
+        }
+        Ok(())
+    }
+
+    fn comparator_index(&self, address: u64) -> Option<usize> {
+        let offset = address.checked_sub(FP_COMP0)?;
+        let index = (offset / 4) as usize;
+        (offset % 4 == 0 && index < NUM_COMPARATORS).then_some(index)
+    }
+
+    fn halt(&mut self, reason: u32) {
+        self.halted = true;
+        self.dfsr |= reason;
+    }
+
+    /// Check if a running core has stopped, e.g. at a breakpoint.
+    fn poll_stop(&mut self, connection: &mut GdbConnection) -> Result<(), GdbRemoteError> {
+        if self.halted {
+            return Ok(());
+        }
+        if let Some(reply) = connection.poll_response()? {
+            parse_stop_reply(&reply)?;
+            self.halt(DFSR_BKPT);
+        }
+        Ok(())
+    }
+
+    /// Halt, resume or step the core, after the control bits of DHCSR changed.
+    fn update_run_state(&mut self, connection: &mut GdbConnection) -> Result<(), GdbRemoteError> {
+        self.poll_stop(connection)?;
+
+        if self.control & C_DEBUGEN != 0 && self.control & C_HALT != 0 {
+            self.halt_core(connection)?;
+        } else if self.halted {
+            if self.control & C_DEBUGEN != 0 && self.control & C_STEP != 0 {
+                parse_stop_reply(&connection.command(b"s")?)?;
+                self.dfsr |= DFSR_HALTED;
+            } else {
+                connection.send(b"c")?;
+                self.halted = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reset the system with the monitor command of QEMU.
+    fn reset(&mut self, connection: &mut GdbConnection) -> Result<(), GdbRemoteError> {
+        self.halt_core(connection)?;
+        connection.monitor("system_reset")?;
+        self.reset_status = true;
+
+        if self.demcr & DEMCR_VC_CORERESET != 0 {
+            self.halt(DFSR_VCATCH);
+        } else if self.control & C_DEBUGEN == 0 || self.control & C_HALT == 0 {
+            // DHCSR is not reset, the core only stays halted when the debugger halted it.
+            connection.send(b"c")?;
+            self.halted = false;
+        }
+        Ok(())
+    }
+
+    /// Read or write a core register, as requested with DCRSR.
+    fn transfer_register(
+        &mut self,
+        connection: &mut GdbConnection,
+        dcrsr: u32,
+    ) -> Result<(), GdbRemoteError> {
+        if !self.halted {
+            return Ok(());
+        }
+
+        let register = dcrsr & 0x7F;
+        let write = dcrsr & DCRSR_REGWNR != 0;
+
+        if register == EXTRA_REGISTER {
+            if write {
+                for (name, shift) in EXTRA_REGISTER_NAMES {
+                    if let Some(number) = self.registers.number(name) {
+                        connection.write_register(number, (self.dcrdr >> shift) & 0xFF)?;
+                    }
+                }
+            } else {
+                let mut value = 0;
+                for (name, shift) in EXTRA_REGISTER_NAMES {
+                    if let Some(number) = self.registers.number(name) {
+                        value |= (connection.read_register(number)? & 0xFF) << shift;
+                    }
+                }
+                self.dcrdr = value;
+            }
+            return Ok(());
+        }
+
+        let number = REGISTER_NAMES
+            .iter()
+            .find(|(dcrsr_number, _)| *dcrsr_number == register)
+            .and_then(|(_, name)| self.registers.number(name));
+        match (number, write) {
+            (Some(number), true) => connection.write_register(number, self.dcrdr)?,
+            (Some(number), false) => self.dcrdr = connection.read_register(number)?,
+            (None, true) => {
+                tracing::debug!("Register {register:#x} is not emulated, the write is ignored")
+            }
+            (None, false) => self.dcrdr = 0,
+        }
+        Ok(())
+    }
+
+    /// Set the breakpoints of the enabled comparators in the stub, and remove the others.
+    fn update_breakpoints(&mut self, connection: &mut GdbConnection) -> Result<(), GdbRemoteError> {
+        let wanted: Vec<u32> = if self.fpb_enabled {
+            self.comparators
+                .iter()
+                .filter(|comparator| *comparator & 1 != 0)
+                .map(|comparator| comparator & !1)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let removed = self
+            .breakpoints
+            .iter()
+            .filter(|address| !wanted.contains(*address))
+            .copied()
+            .collect::<Vec<_>>();
+        let added = wanted
+            .iter()
+            .filter(|address| !self.breakpoints.contains(*address))
+            .copied()
+            .collect::<Vec<_>>();
+        if removed.is_empty() && added.is_empty() {
+            return Ok(());
+        }
+
+        let mut breakpoints = std::mem::take(&mut self.breakpoints);
+        let result = self.with_halted_core(connection, |connection| {
+            for address in removed {
+                connection.expect_ok(format!("z1,{address:x},2").as_bytes())?;
+                breakpoints.retain(|breakpoint| *breakpoint != address);
+            }
+            for address in added {
+                connection.expect_ok(format!("Z1,{address:x},2").as_bytes())?;
+                breakpoints.push(address);
+            }
+            Ok(())
+        });
+        self.breakpoints = breakpoints;
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probe::gdb_remote::connection::serve;
+
+    #[test]
+    fn registers_are_numbered_in_document_order() {
+        let elements = parse_elements(
+            r#"<?xml version="1.0"?>
+            <feature name="org.gnu.gdb.arm.m-system">
+              <reg name="msp" bitsize="32" regnum="26"/>
+              <reg name='psp' bitsize="32"/>
+              <xi:include href="arm-m-profile.xml"/>
+            </feature>"#,
+        );
+        assert_eq!(
+            elements,
+            vec![
+                Element::Register {
+                    name: "msp".to_string(),
+                    number: Some(26)
+                },
+                Element::Register {
+                    name: "psp".to_string(),
+                    number: None
+                },
+                Element::Include("arm-m-profile.xml".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_replies_give_the_reason() {
+        assert_eq!(
+            parse_stop_reply(b"T02thread:p01.01;").unwrap(),
+            StopReason::Interrupted
+        );
+        assert_eq!(parse_stop_reply(b"S05").unwrap(), StopReason::Trap);
+        assert!(parse_stop_reply(b"W00").is_err());
+    }
+
+    #[test]
+    fn core_is_controlled_through_debug_registers() {
+        let address = serve(&[
+            (b"qSupported", b"PacketSize=1000"),
+            (b"?", b"T05thread:p01.01;"),
+            (b"pf", b"00010000"),
+            (b"s", b"T05thread:p01.01;"),
+            (b"c", b"T05thread:p01.01;"),
+        ]);
+        let mut connection = GdbConnection::connect(address).unwrap();
+        let mut registers = EmulatedDebugRegisters::new(RegisterMap::default());
+
+        // The core is halted when the stub is connected to.
+        let dhcsr = registers.read(&mut connection, DHCSR).unwrap().unwrap();
+        assert_eq!(dhcsr & (S_HALT | C_DEBUGEN), S_HALT | C_DEBUGEN);
+
+        // Read the program counter.
+        assert!(registers.write(&mut connection, DCRSR, 15).unwrap());
+        assert_eq!(registers.read(&mut connection, DCRDR).unwrap(), Some(0x100));
+
+        // Step, and resume until the core stops at a breakpoint.
+        registers
+            .write(&mut connection, DHCSR, DHCSR_KEY | C_DEBUGEN | C_STEP)
+            .unwrap();
+        registers
+            .write(&mut connection, DHCSR, DHCSR_KEY | C_DEBUGEN)
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let dhcsr = registers.read(&mut connection, DHCSR).unwrap().unwrap();
+        assert_ne!(dhcsr & S_HALT, 0);
+        assert_ne!(
+            registers.read(&mut connection, DFSR).unwrap().unwrap() & DFSR_BKPT,
+            0
+        );
+
+        // Other addresses are not emulated.
+        assert_eq!(registers.read(&mut connection, 0x2000_0000).unwrap(), None);
+    }
+}
@@ -0,0 +1,640 @@
+//! A target which is exposed by a GDB server, e.g. OpenOCD or a Black Magic Probe, and used like a debug probe.
+//!
+//! probe-rs connects to the server as a client of the GDB remote serial protocol. The server gives access to the
+//! memory of the target, which is presented as a single memory AP. The cores are controlled through their memory
+//! mapped debug registers, like with every other probe, so this only works for ARM targets, and for servers which pass
+//! accesses to the debug registers on to the target. Emulators like QEMU, which do not implement the debug registers,
+//! are opened with [`GdbRemote::connect_emulator`] instead, which emulates the debug registers of a Cortex-M core with
+//! the commands of the GDB stub.
+//!
+//! The Black Magic Probe runs a GDB server on its USB serial port, so it is listed and selected like other debug
+//! probes, and opened with [`DebugProbe::new_from_selector`]. It scans for the target when it is attached.
+
+#[cfg(feature = "serial")]
+pub(crate) mod black_magic_probe;
+mod connection;
+mod emulator;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use self::connection::GdbConnection;
+use self::emulator::{EmulatedDebugRegisters, RegisterMap};
+use super::{DebugProbe, DebugProbeError, ProbeCreationError, WireProtocol};
+use crate::architecture::arm::memory::adi_v5_memory_interface::ArmProbe;
+use crate::architecture::arm::{valid_32bit_arm_address, ArmError};
+use crate::{
+    architecture::arm::{
+        ap::{AccessPort, GenericAp, MemoryAp},
+        communication_interface::{
+            ArmProbeInterface, Initialized, SwdSequence, UninitializedArmProbe,
+        },
+        memory::Component,
+        sequences::ArmDebugSequence,
+        ApAddress, ApInformation, ArmChipInfo, DapAccess, DpAddress, MemoryApInformation,
+        SwoAccess, SwoConfig,
+    },
+    DebugProbeSelector, Error as ProbeRsError, Probe,
+};
+
+/// The address of the ROM table of Cortex-M cores, which is reported as the debug base address of the memory AP.
+const DEBUG_BASE_ADDRESS: u64 = 0xE00F_F000;
+
+/// The addresses of the debug registers of Cortex-M cores.
+const PRIVATE_PERIPHERAL_BUS: std::ops::Range<u64> = 0xE000_0000..0xE010_0000;
+
+/// An error in the communication with a GDB server.
+#[derive(thiserror::Error, Debug)]
+pub enum GdbRemoteError {
+    /// The connection to the server failed.
+    #[error("Failed to communicate with the GDB server")]
+    Io(#[from] std::io::Error),
+    /// The server did not acknowledge a packet, even after it was sent again.
+    #[error("The GDB server did not acknowledge a packet")]
+    NotAcknowledged,
+    /// The server answered a packet with an `Exx` error.
+    #[error("The GDB server answered with error {0:#04x}")]
+    ErrorResponse(u8),
+    /// The server answered a packet with something which is not a valid response to it.
+    #[error("The GDB server answered with the unexpected response `{0}`")]
+    UnexpectedResponse(String),
+}
+
+impl From<GdbRemoteError> for DebugProbeError {
+    fn from(e: GdbRemoteError) -> Self {
+        DebugProbeError::ProbeSpecific(Box::new(e))
+    }
+}
+
+impl From<GdbRemoteError> for ArmError {
+    fn from(e: GdbRemoteError) -> Self {
+        ArmError::Probe(e.into())
+    }
+}
+
+/// The kind of GDB server, which decides how it attaches to the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ServerKind {
+    /// The server is attached to the target already, e.g. OpenOCD.
+    Attached,
+    /// A Black Magic Probe, which scans for the target and attaches to it when it is asked to.
+    BlackMagicProbe,
+}
+
+/// A GDB server, which drives the target in place of a debug probe.
+#[derive(Debug)]
+pub struct GdbRemote {
+    name: String,
+    kind: ServerKind,
+    connection: GdbConnection,
+    protocol: Option<WireProtocol>,
+    speed_khz: u32,
+    /// The debug registers, for emulators which do not implement them.
+    emulator: Option<EmulatedDebugRegisters>,
+}
+
+impl GdbRemote {
+    /// Connect to the GDB server at `address`, e.g. `localhost:3333` for OpenOCD.
+    ///
+    /// The server has to be attached to the target already.
+    pub fn connect(address: &str) -> Result<Box<Self>, DebugProbeError> {
+        let connection = Self::open_connection(address)?;
+
+        Ok(Box::new(Self {
+            name: format!("GDB server at {address}"),
+            kind: ServerKind::Attached,
+            connection,
+            protocol: None,
+            speed_khz: 0,
+            emulator: None,
+        }))
+    }
+
+    /// Connect to the GDB stub of an emulator at `address`, e.g. `localhost:1234` for `qemu-system-arm -s -S`.
+    ///
+    /// The debug registers of the emulated Cortex-M core are emulated with the commands of the stub, which halt, resume
+    /// and step the core, and access its registers and breakpoints. The core has to be stopped, which it is when the stub has just been started.
+    pub fn connect_emulator(address: &str) -> Result<Box<Self>, DebugProbeError> {
+        let mut connection = Self::open_connection(address)?;
+        let registers = RegisterMap::read(&mut connection);
+
+        Ok(Box::new(Self {
+            name: format!("Emulator at {address}"),
+            kind: ServerKind::Attached,
+            connection,
+            protocol: None,
+            speed_khz: 0,
+            emulator: Some(EmulatedDebugRegisters::new(registers)),
+        }))
+    }
+
+    fn open_connection(address: &str) -> Result<GdbConnection, DebugProbeError> {
+        GdbConnection::connect(address).map_err(|e| {
+            DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::ProbeSpecific(Box::new(e)))
+        })
+    }
+}
+
+impl DebugProbe for GdbRemote {
+    /// Open the GDB server of a Black Magic Probe. Other GDB servers are opened with [`GdbRemote::connect`].
+    fn new_from_selector(
+        selector: impl Into<DebugProbeSelector>,
+    ) -> Result<Box<Self>, DebugProbeError> {
+        // The Black Magic Probe is only found on the serial ports with the `serial` feature.
+        #[cfg(not(feature = "serial"))]
+        {
+            let _ = selector;
+            Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::NotFound,
+            ))
+        }
+
+        #[cfg(feature = "serial")]
+        {
+            let selector = selector.into();
+            let port = black_magic_probe::find_gdb_server_port(&selector).ok_or(
+                DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound),
+            )?;
+            let connection = GdbConnection::connect_serial(&port).map_err(|e| {
+                DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::ProbeSpecific(
+                    Box::new(e),
+                ))
+            })?;
+
+            Ok(Box::new(Self {
+                name: "Black Magic Probe".to_string(),
+                kind: ServerKind::BlackMagicProbe,
+                connection,
+                protocol: None,
+                speed_khz: 0,
+                emulator: None,
+            }))
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.speed_khz
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        if self.kind != ServerKind::BlackMagicProbe {
+            // The speed is configured in the GDB server.
+            return Err(DebugProbeError::CommandNotSupportedByProbe("set_speed"));
+        }
+
+        self.connection
+            .monitor(&format!("frequency {speed_khz}k"))?;
+        self.speed_khz = speed_khz;
+        Ok(speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        match self.kind {
+            // The server attaches to the target when the connection is made.
+            ServerKind::Attached => Ok(()),
+            ServerKind::BlackMagicProbe => {
+                let scan = match self.protocol {
+                    Some(WireProtocol::Jtag) => "jtag_scan",
+                    _ => "swdp_scan",
+                };
+                self.connection.monitor(scan)?;
+
+                // Attach to the first target which was found, this stops it.
+                match self.connection.command(b"vAttach;1") {
+                    Ok(reply) if matches!(reply.first(), Some(b'S' | b'T')) => Ok(()),
+                    Ok(_) | Err(GdbRemoteError::ErrorResponse(_)) => {
+                        Err(DebugProbeError::TargetNotFound)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+        }
+    }
+
+    fn detach(&mut self) -> Result<(), crate::Error> {
+        if let Some(emulator) = &mut self.emulator {
+            // The stub only answers packets while the core is halted.
+            emulator
+                .halt_core(&mut self.connection)
+                .map_err(DebugProbeError::from)?;
+        }
+        self.connection
+            .expect_ok(b"D")
+            .map_err(DebugProbeError::from)?;
+        Ok(())
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotImplemented(
+            "hardware reset through a GDB server",
+        ))
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotImplemented(
+            "hardware reset through a GDB server",
+        ))
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotImplemented(
+            "hardware reset through a GDB server",
+        ))
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        // The protocol is configured in the GDB server, it is only recorded here.
+        self.protocol = Some(protocol);
+        Ok(())
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        self.protocol
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        true
+    }
+
+    fn try_get_arm_interface<'probe>(
+        self: Box<Self>,
+    ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
+    {
+        Ok(Box::new(UninitializedGdbRemote { probe: self }))
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct UninitializedGdbRemote {
+    probe: Box<GdbRemote>,
+}
+
+impl UninitializedArmProbe for UninitializedGdbRemote {
+    fn initialize(
+        self: Box<Self>,
+        _sequence: Arc<dyn ArmDebugSequence>,
+    ) -> Result<Box<dyn ArmProbeInterface>, (Box<dyn UninitializedArmProbe>, ProbeRsError)> {
+        let ap_information = ApInformation::MemoryAp(MemoryApInformation {
+            address: ApAddress {
+                dp: DpAddress::Default,
+                ap: 0,
+            },
+            supports_only_32bit_data_size: false,
+            debug_base_address: DEBUG_BASE_ADDRESS,
+            supports_hnonsec: false,
+            has_large_address_extension: false,
+            has_large_data_extension: false,
+            device_enabled: true,
+        });
+
+        Ok(Box::new(GdbRemoteArmDebug {
+            probe: self.probe,
+            ap_information,
+        }))
+    }
+
+    fn close(self: Box<Self>) -> Probe {
+        Probe::from_attached_probe(self.probe)
+    }
+}
+
+impl SwdSequence for UninitializedGdbRemote {
+    fn swj_sequence(&mut self, _bit_len: u8, _bits: u64) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("swj_sequence"))
+    }
+
+    fn swj_pins(
+        &mut self,
+        _pin_out: u32,
+        _pin_select: u32,
+        _pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("swj_pins"))
+    }
+}
+
+#[derive(Debug)]
+struct GdbRemoteArmDebug {
+    probe: Box<GdbRemote>,
+
+    /// The memory AP which gives access to the memory of the server.
+    ap_information: ApInformation,
+}
+
+impl GdbRemoteArmDebug {
+    fn check_access_port(&self, access_port: ApAddress) -> Result<(), ArmError> {
+        if access_port.dp != DpAddress::Default || access_port.ap != 0 {
+            return Err(ArmError::ApDoesNotExist(access_port));
+        }
+        Ok(())
+    }
+}
+
+impl DapAccess for GdbRemoteArmDebug {
+    fn read_raw_dp_register(&mut self, _dp: DpAddress, _address: u8) -> Result<u32, ArmError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("read_raw_dp_register").into())
+    }
+
+    fn write_raw_dp_register(
+        &mut self,
+        _dp: DpAddress,
+        _address: u8,
+        _value: u32,
+    ) -> Result<(), ArmError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("write_raw_dp_register").into())
+    }
+
+    fn read_raw_ap_register(&mut self, _ap: ApAddress, _address: u8) -> Result<u32, ArmError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("read_raw_ap_register").into())
+    }
+
+    fn write_raw_ap_register(
+        &mut self,
+        _ap: ApAddress,
+        _address: u8,
+        _value: u32,
+    ) -> Result<(), ArmError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("write_raw_ap_register").into())
+    }
+}
+
+impl ArmProbeInterface for GdbRemoteArmDebug {
+    fn memory_interface(
+        &mut self,
+        access_port: MemoryAp,
+    ) -> Result<Box<dyn ArmProbe + '_>, ArmError> {
+        self.check_access_port(access_port.ap_address())?;
+
+        let interface = GdbRemoteMemoryInterface {
+            probe: self,
+            current_ap: access_port,
+        };
+
+        Ok(Box::new(interface) as _)
+    }
+
+    fn ap_information(&mut self, access_port: GenericAp) -> Result<&ApInformation, ArmError> {
+        self.check_access_port(access_port.ap_address())?;
+
+        Ok(&self.ap_information)
+    }
+
+    fn read_chip_info_from_rom_table(
+        &mut self,
+        dp: DpAddress,
+    ) -> Result<Option<ArmChipInfo>, ArmError> {
+        if self.probe.emulator.is_some() {
+            // Emulators do not implement the ROM table.
+            return Ok(None);
+        }
+
+        let access_port = MemoryAp::new(ApAddress { dp, ap: 0 });
+        let mut memory = self.memory_interface(access_port)?;
+
+        let component = Component::try_parse(&mut *memory, DEBUG_BASE_ADDRESS)?;
+
+        if let Component::Class1RomTable(component_id, _)
+        | Component::Class9RomTable(component_id, _) = component
+        {
+            if let Some(jep106) = component_id.peripheral_id().jep106() {
+                return Ok(Some(ArmChipInfo {
+                    manufacturer: jep106,
+                    part: component_id.peripheral_id().part(),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn num_access_ports(&mut self, dp: DpAddress) -> Result<usize, ArmError> {
+        if dp != DpAddress::Default {
+            return Err(DebugProbeError::CommandNotSupportedByProbe("multidrop").into());
+        }
+
+        Ok(1)
+    }
+
+    fn close(self: Box<Self>) -> Probe {
+        Probe::from_attached_probe(self.probe)
+    }
+}
+
+impl SwdSequence for GdbRemoteArmDebug {
+    fn swj_sequence(&mut self, _bit_len: u8, _bits: u64) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("swj_sequence"))
+    }
+
+    fn swj_pins(
+        &mut self,
+        _pin_out: u32,
+        _pin_select: u32,
+        _pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("swj_pins"))
+    }
+}
+
+impl SwoAccess for GdbRemoteArmDebug {
+    fn enable_swo(&mut self, _config: &SwoConfig) -> Result<(), ArmError> {
+        Err(DebugProbeError::InterfaceNotAvailable("SWO").into())
+    }
+
+    fn disable_swo(&mut self) -> Result<(), ArmError> {
+        Err(DebugProbeError::InterfaceNotAvailable("SWO").into())
+    }
+
+    fn read_swo_timeout(&mut self, _timeout: Duration) -> Result<Vec<u8>, ArmError> {
+        Err(DebugProbeError::InterfaceNotAvailable("SWO").into())
+    }
+}
+
+#[derive(Debug)]
+struct GdbRemoteMemoryInterface<'probe> {
+    probe: &'probe mut GdbRemoteArmDebug,
+    current_ap: MemoryAp,
+}
+
+impl GdbRemoteMemoryInterface<'_> {
+    fn read_bytes(&mut self, address: u64, data: &mut [u8]) -> Result<(), ArmError> {
+        valid_32bit_arm_address(address)?;
+        let GdbRemote {
+            connection,
+            emulator,
+            ..
+        } = &mut *self.probe.probe;
+        match emulator {
+            Some(emulator) => emulator.with_halted_core(connection, |connection| {
+                connection.read_memory(address, data)
+            })?,
+            None => connection.read_memory(address, data)?,
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, address: u64, data: &[u8]) -> Result<(), ArmError> {
+        valid_32bit_arm_address(address)?;
+        let GdbRemote {
+            connection,
+            emulator,
+            ..
+        } = &mut *self.probe.probe;
+        match emulator {
+            Some(emulator) => emulator.with_halted_core(connection, |connection| {
+                connection.write_memory(address, data)
+            })?,
+            None => connection.write_memory(address, data)?,
+        }
+        Ok(())
+    }
+
+    /// Whether the words at `address` are accessed one by one, because they may be emulated debug registers.
+    fn is_emulated(&self, address: u64) -> bool {
+        self.probe.probe.emulator.is_some() && PRIVATE_PERIPHERAL_BUS.contains(&address)
+    }
+
+    fn read_emulated_word(&mut self, address: u64) -> Result<u32, ArmError> {
+        let GdbRemote {
+            connection,
+            emulator,
+            ..
+        } = &mut *self.probe.probe;
+        if let Some(value) = emulator
+            .as_mut()
+            .map(|emulator| emulator.read(connection, address))
+            .transpose()?
+            .flatten()
+        {
+            return Ok(value);
+        }
+
+        let mut bytes = [0; 4];
+        self.read_bytes(address, &mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn write_emulated_word(&mut self, address: u64, value: u32) -> Result<(), ArmError> {
+        let GdbRemote {
+            connection,
+            emulator,
+            ..
+        } = &mut *self.probe.probe;
+        if let Some(true) = emulator
+            .as_mut()
+            .map(|emulator| emulator.write(connection, address, value))
+            .transpose()?
+        {
+            return Ok(());
+        }
+
+        self.write_bytes(address, &value.to_le_bytes())
+    }
+}
+
+impl SwdSequence for GdbRemoteMemoryInterface<'_> {
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        self.probe.swj_sequence(bit_len, bits)
+    }
+
+    fn swj_pins(
+        &mut self,
+        pin_out: u32,
+        pin_select: u32,
+        pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        self.probe.swj_pins(pin_out, pin_select, pin_wait)
+    }
+}
+
+impl ArmProbe for GdbRemoteMemoryInterface<'_> {
+    fn supports_native_64bit_access(&mut self) -> bool {
+        false
+    }
+
+    fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), ArmError> {
+        let mut buffer = vec![0u8; data.len() * 8];
+        self.read_bytes(address, &mut buffer)?;
+        for (word, bytes) in data.iter_mut().zip(buffer.chunks_exact(8)) {
+            *word = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Ok(())
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), ArmError> {
+        if self.is_emulated(address) {
+            for (word_address, word) in (address..).step_by(4).zip(data.iter_mut()) {
+                *word = self.read_emulated_word(word_address)?;
+            }
+            return Ok(());
+        }
+
+        let mut buffer = vec![0u8; data.len() * 4];
+        self.read_bytes(address, &mut buffer)?;
+        for (word, bytes) in data.iter_mut().zip(buffer.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Ok(())
+    }
+
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), ArmError> {
+        self.read_bytes(address, data)
+    }
+
+    fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), ArmError> {
+        let buffer = data
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.write_bytes(address, &buffer)
+    }
+
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), ArmError> {
+        if self.is_emulated(address) {
+            for (word_address, word) in (address..).step_by(4).zip(data) {
+                self.write_emulated_word(word_address, *word)?;
+            }
+            return Ok(());
+        }
+
+        let buffer = data
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.write_bytes(address, &buffer)
+    }
+
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), ArmError> {
+        self.write_bytes(address, data)
+    }
+
+    fn flush(&mut self) -> Result<(), ArmError> {
+        Ok(())
+    }
+
+    fn supports_8bit_transfers(&self) -> Result<bool, ArmError> {
+        Ok(true)
+    }
+
+    fn get_arm_communication_interface(
+        &mut self,
+    ) -> Result<
+        &mut crate::architecture::arm::ArmCommunicationInterface<Initialized>,
+        DebugProbeError,
+    > {
+        Err(DebugProbeError::NotImplemented(
+            "GDB servers do not support raw SWD access.",
+        ))
+    }
+
+    fn ap(&mut self) -> MemoryAp {
+        self.current_ap
+    }
+}
@@ -847,6 +847,16 @@ pub trait RawProtocolIo {
     ///
     /// See section B4.3.3 in the ADIv5 Specification.
     fn line_reset(&mut self) -> Result<(), ArmError>;
+
+    /// Drive the nTRST pin of the probe to the given level.
+    fn set_ntrst(&mut self, _ntrst: bool) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("set_ntrst"))
+    }
+
+    /// Drive the TDI pin of the probe to the given level.
+    fn set_tdi(&mut self, _tdi: bool) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("set_tdi"))
+    }
 }
 
 impl RawProtocolIo for JLink {
@@ -928,6 +938,16 @@ impl RawProtocolIo for JLink {
     fn probe_statistics(&mut self) -> &mut ProbeStatistics {
         &mut self.probe_statistics
     }
+
+    fn set_ntrst(&mut self, ntrst: bool) -> Result<(), DebugProbeError> {
+        self.handle.set_trst(ntrst)?;
+        Ok(())
+    }
+
+    fn set_tdi(&mut self, tdi: bool) -> Result<(), DebugProbeError> {
+        self.handle.set_tdi(tdi)?;
+        Ok(())
+    }
 }
 
 impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for Probe {
@@ -1324,30 +1344,40 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
         pin_select: u32,
         pin_wait: u32,
     ) -> Result<u32, DebugProbeError> {
-        let mut nreset = Pins(0);
-        nreset.set_nreset(true);
-        let nreset_mask = nreset.0 as u32;
+        let mut supported = Pins(0);
+        supported.set_nreset(true);
+        supported.set_ntrst(true);
+        supported.set_tdi(true);
 
-        // If only the reset pin is selected we perform the reset.
+        // The J-Link can only drive the reset, nTRST and TDI pins individually.
         // If something else is selected return an error as this is not supported on J-Links.
-        if pin_select == nreset_mask {
-            if Pins(pin_out as u8).nreset() {
+        if pin_select == 0 || pin_select & !(supported.0 as u32) != 0 {
+            return Err(DebugProbeError::CommandNotSupportedByProbe("swj_pins"));
+        }
+
+        let select = Pins(pin_select as u8);
+        let out = Pins(pin_out as u8);
+        if select.nreset() {
+            if out.nreset() {
                 self.target_reset_deassert()?;
             } else {
                 self.target_reset_assert()?;
             }
+        }
+        if select.ntrst() {
+            self.set_ntrst(out.ntrst())?;
+        }
+        if select.tdi() {
+            self.set_tdi(out.tdi())?;
+        }
 
-            // Normally this would be the timeout we pass to the probe to settle the pins.
-            // The J-Link is not capable of this, so we just wait for this time on the host
-            // and assume it has settled until then.
-            std::thread::sleep(Duration::from_micros(pin_wait as u64));
+        // Normally this would be the timeout we pass to the probe to settle the pins.
+        // The J-Link is not capable of this, so we just wait for this time on the host
+        // and assume it has settled until then.
+        std::thread::sleep(Duration::from_micros(pin_wait as u64));
 
-            // We signal that we cannot read the pin state.
-            Ok(0xFFFF_FFFF)
-        } else {
-            // This is not supported for J-Links, unfortunately.
-            Err(DebugProbeError::CommandNotSupportedByProbe("swj_pins"))
-        }
+        // We signal that we cannot read the pin state.
+        Ok(0xFFFF_FFFF)
     }
 
     fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
@@ -17,7 +17,8 @@ use crate::{
         riscv::communication_interface::RiscvCommunicationInterface,
     },
     probe::{
-        DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeType, JTAGAccess, WireProtocol,
+        raw_shift_response, raw_shift_sequence, DebugProbe, DebugProbeError, DebugProbeInfo,
+        DebugProbeType, JTAGAccess, RawJtagIo, WireProtocol,
     },
     DebugProbeSelector,
 };
@@ -314,6 +315,29 @@ impl JLink {
 
         Ok(result)
     }
+
+    fn raw_shift(
+        &mut self,
+        ir: bool,
+        data: &[u8],
+        bits: usize,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        // A single JTAG operation of the J-Link transfers at most 65535 bits, but long registers, e.g. the
+        // configuration of an FPGA, can be split, because the TAP stays in its state between the operations.
+        const CHUNK_BITS: usize = 32768;
+
+        let (tms, tdi, start) = raw_shift_sequence(ir, data, bits)?;
+        let mut response = Vec::with_capacity(tms.len());
+        for (tms, tdi) in tms.chunks(CHUNK_BITS).zip(tdi.chunks(CHUNK_BITS)) {
+            response.extend(
+                self.handle
+                    .jtag_io(tms.iter().copied(), tdi.iter().copied())?,
+            );
+        }
+        Ok(raw_shift_response(
+            response.into_iter().skip(start).take(bits),
+        ))
+    }
 }
 
 impl DebugProbe for JLink {
@@ -321,6 +345,14 @@ impl DebugProbe for JLink {
         selector: impl Into<DebugProbeSelector>,
     ) -> Result<Box<Self>, DebugProbeError> {
         let selector = selector.into();
+
+        // jaylink does not expose the full USB port path, so J-Links cannot be selected by path.
+        if selector.usb_path.is_some() {
+            return Err(DebugProbeError::ProbeCouldNotBeCreated(
+                super::ProbeCreationError::NotFound,
+            ));
+        }
+
         let mut jlinks = jaylink::scan_usb()?
             .filter_map(|usb_info| {
                 if usb_info.vid() == selector.vendor_id && usb_info.pid() == selector.product_id {
@@ -605,6 +637,14 @@ impl DebugProbe for JLink {
         Some(self)
     }
 
+    fn try_as_raw_jtag(&mut self) -> Option<&mut dyn RawJtagIo> {
+        if self.protocol == Some(WireProtocol::Jtag) {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
     fn try_get_arm_interface<'probe>(
         self: Box<Self>,
     ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
@@ -618,6 +658,12 @@ impl DebugProbe for JLink {
         // Convert the integer millivolts value from self.handle to volts as an f32.
         Ok(Some((self.handle.read_target_voltage()? as f32) / 1000f32))
     }
+
+    fn set_target_power(&mut self, enabled: bool) -> Result<(), DebugProbeError> {
+        // The J-Link can supply 5V to the target on pin 19 of the 20-pin connector.
+        self.handle.set_kickstart_power(enabled)?;
+        Ok(())
+    }
 }
 
 impl JTAGAccess for JLink {
@@ -679,6 +725,30 @@ impl JTAGAccess for JLink {
     }
 }
 
+impl RawJtagIo for JLink {
+    fn shift_ir(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+        let response = self.raw_shift(true, data, bits)?;
+
+        // The IR no longer holds the last register which was selected by `write_register`.
+        self.current_ir_reg = u32::MAX;
+
+        Ok(response)
+    }
+
+    fn shift_dr(&mut self, data: &[u8], bits: usize) -> Result<Vec<u8>, DebugProbeError> {
+        self.raw_shift(false, data, bits)
+    }
+
+    fn run_test_idle(&mut self, cycles: usize) -> Result<(), DebugProbeError> {
+        if cycles == 0 {
+            return Ok(());
+        }
+        let idle = iter::repeat(false).take(cycles);
+        self.handle.jtag_io(idle.clone(), idle)?;
+        Ok(())
+    }
+}
+
 impl DapProbe for JLink {}
 
 impl SwoAccess for JLink {
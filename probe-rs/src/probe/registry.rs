@@ -0,0 +1,227 @@
+//! Debug probe drivers which are registered at runtime.
+//!
+//! Downstream crates implement [`DebugProbe`] for their probes, and register a [`ProbeDriver`] with
+//! [`register_probe_driver`]. [`Probe::list_all`](super::Probe::list_all) then lists the USB devices which match the
+//! filters of the driver, and [`Probe::open`](super::Probe::open) opens them with its factory function, before the
+//! built-in drivers are tried.
+
+use std::sync::RwLock;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use rusb::UsbContext;
+
+use super::{
+    usb_path, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType,
+    ProbeCreationError,
+};
+
+/// The USB vendor and product ID of the probes which a [`ProbeDriver`] handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsbFilter {
+    /// The USB vendor ID.
+    pub vendor_id: u16,
+    /// The USB product ID, `None` matches every product of the vendor.
+    pub product_id: Option<u16>,
+}
+
+impl UsbFilter {
+    fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        self.vendor_id == vendor_id && self.product_id.map_or(true, |id| id == product_id)
+    }
+}
+
+/// Opens the probe which the selector selects.
+///
+/// Returns [`ProbeCreationError::NotFound`] if the probe is not handled by the driver, so the other drivers are tried.
+pub type ProbeFactory = fn(&DebugProbeSelector) -> Result<Box<dyn DebugProbe>, DebugProbeError>;
+
+/// A driver for debug probes, which is implemented outside of probe-rs.
+#[derive(Debug, Clone)]
+pub struct ProbeDriver {
+    /// The name of the driver, which is the [`DebugProbeType::Custom`] of its probes.
+    pub name: &'static str,
+    /// The USB devices which are probes of this driver.
+    pub filters: Vec<UsbFilter>,
+    /// Opens a probe of this driver.
+    pub open: ProbeFactory,
+}
+
+impl ProbeDriver {
+    fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| filter.matches(vendor_id, product_id))
+    }
+}
+
+static DRIVERS: Lazy<RwLock<Vec<ProbeDriver>>> = Lazy::new(Default::default);
+
+/// Register a driver for debug probes. A driver with the same name is replaced.
+pub fn register_probe_driver(driver: ProbeDriver) {
+    let mut drivers = DRIVERS.write().unwrap();
+    drivers.retain(|registered| registered.name != driver.name);
+    drivers.push(driver);
+}
+
+/// Remove the driver with `name`, returning whether it was registered.
+pub fn unregister_probe_driver(name: &str) -> bool {
+    let mut drivers = DRIVERS.write().unwrap();
+    let count = drivers.len();
+    drivers.retain(|registered| registered.name != name);
+    drivers.len() != count
+}
+
+/// List the USB devices which are probes of a registered driver.
+#[tracing::instrument(skip_all)]
+pub(super) fn list_registered_devices() -> Vec<DebugProbeInfo> {
+    let drivers = DRIVERS.read().unwrap();
+    if drivers.is_empty() {
+        return vec![];
+    }
+
+    let Ok(devices) = rusb::Context::new().and_then(|context| context.devices()) else {
+        return vec![];
+    };
+
+    devices
+        .iter()
+        .filter_map(|device| {
+            let descriptor = device.device_descriptor().ok()?;
+            let driver = drivers
+                .iter()
+                .find(|driver| driver.matches(descriptor.vendor_id(), descriptor.product_id()))?;
+
+            let (product, serial_number) = read_strings(&device, &descriptor);
+            Some(
+                DebugProbeInfo::new(
+                    product.unwrap_or_else(|| driver.name.to_owned()),
+                    descriptor.vendor_id(),
+                    descriptor.product_id(),
+                    serial_number,
+                    DebugProbeType::Custom(driver.name),
+                    None,
+                )
+                .with_usb_path(usb_path(&device)),
+            )
+        })
+        .collect()
+}
+
+/// Read the product name and the serial number of a USB device. Either can fail, e.g. if the driver of the device
+/// is not installed.
+fn read_strings<T: UsbContext>(
+    device: &rusb::Device<T>,
+    descriptor: &rusb::DeviceDescriptor,
+) -> (Option<String>, Option<String>) {
+    let timeout = Duration::from_millis(100);
+    let Ok(handle) = device.open() else {
+        return (None, None);
+    };
+    let Some(language) = handle
+        .read_languages(timeout)
+        .ok()
+        .and_then(|l| l.first().copied())
+    else {
+        return (None, None);
+    };
+
+    (
+        handle
+            .read_product_string(language, descriptor, timeout)
+            .ok(),
+        handle
+            .read_serial_number_string(language, descriptor, timeout)
+            .ok(),
+    )
+}
+
+/// Open the probe with a registered driver whose filters match the selector.
+///
+/// Returns [`ProbeCreationError::NotFound`] if no driver opens it.
+pub(super) fn open_registered(
+    selector: &DebugProbeSelector,
+) -> Result<Box<dyn DebugProbe>, DebugProbeError> {
+    // The factories are copied, so a factory can register drivers itself.
+    let factories = DRIVERS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|driver| driver.matches(selector.vendor_id, selector.product_id))
+        .map(|driver| (driver.name, driver.open))
+        .collect::<Vec<_>>();
+
+    for (name, open) in factories {
+        match open(selector) {
+            Ok(probe) => {
+                tracing::debug!("Opened {} with the registered driver {}", selector, name);
+                return Ok(probe);
+            }
+            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(DebugProbeError::ProbeCouldNotBeCreated(
+        ProbeCreationError::NotFound,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FakeProbe, Probe};
+
+    fn open_fake(selector: &DebugProbeSelector) -> Result<Box<dyn DebugProbe>, DebugProbeError> {
+        match selector.serial_number.as_deref() {
+            Some("other") => Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::NotFound,
+            )),
+            _ => Ok(Box::new(FakeProbe::new())),
+        }
+    }
+
+    #[test]
+    fn registered_drivers_open_the_probes_of_their_filters() {
+        register_probe_driver(ProbeDriver {
+            name: "test",
+            filters: vec![UsbFilter {
+                vendor_id: 0xF00D,
+                product_id: None,
+            }],
+            open: open_fake,
+        });
+
+        let probe = Probe::open(DebugProbeSelector::try_from("f00d:0001").unwrap()).unwrap();
+        assert_eq!(probe.get_name(), "Mock probe for testing");
+
+        // The driver can refuse probes, which the other drivers try next.
+        assert!(matches!(
+            open_registered(&DebugProbeSelector::try_from("f00d:0001:other").unwrap()),
+            Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::NotFound
+            ))
+        ));
+
+        assert!(unregister_probe_driver("test"));
+        assert!(!unregister_probe_driver("test"));
+        assert!(open_registered(&DebugProbeSelector::try_from("f00d:0001").unwrap()).is_err());
+    }
+
+    #[test]
+    fn filters_without_product_match_every_product() {
+        let vendor = UsbFilter {
+            vendor_id: 0x1234,
+            product_id: None,
+        };
+        let product = UsbFilter {
+            product_id: Some(0x0002),
+            ..vendor
+        };
+
+        assert!(vendor.matches(0x1234, 0x0001));
+        assert!(!vendor.matches(0x4321, 0x0001));
+        assert!(!product.matches(0x1234, 0x0001));
+        assert!(product.matches(0x1234, 0x0002));
+    }
+}
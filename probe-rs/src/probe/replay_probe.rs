@@ -0,0 +1,439 @@
+//! A mock probe which replays a transaction log.
+//!
+//! The [`ReplayProbe`] answers the DAP transfers of probe-rs with the values and acknowledgements of a log which was
+//! recorded with [`transaction_log`](super::transaction_log), so a bug report or a regression test of the DAP state
+//! machine can run without the original probe and target. Every transfer has to match the next recorded one, so a
+//! change in the sequence of transfers is reported as a [`ReplayError::Mismatch`].
+//!
+//! Only ARM targets are replayed. JTAG scans of RISC-V targets are batched, and the log does not keep the bits which
+//! batched scans captured. The access ports of a DPv3 are read through their address space, which is not recorded.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use super::transaction_log::{self, Ack, DapPort, Transaction, TransactionLogError, Transfer};
+use crate::{
+    architecture::arm::{
+        communication_interface::{DapProbe, UninitializedArmProbe},
+        ArmCommunicationInterface, ArmError, DapError, DpAddress, PortType, RawDapAccess,
+    },
+    DebugProbe, DebugProbeError, DebugProbeSelector, Probe, WireProtocol,
+};
+
+/// An error of a [`ReplayProbe`].
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    /// All recorded transfers were replayed.
+    #[error("The transaction log ends before the transfer '{transfer}'.")]
+    Exhausted {
+        /// The transfer which was not recorded.
+        transfer: String,
+    },
+    /// A transfer is different from the recorded one.
+    #[error("Transfer #{index} is '{transfer}', but '{recorded}' was recorded.")]
+    Mismatch {
+        /// The index of the transfer in the log, starting at 0.
+        index: usize,
+        /// The transfer.
+        transfer: String,
+        /// The recorded transfer.
+        recorded: String,
+    },
+    /// The recorded transfer failed with an error of the probe.
+    #[error("The recorded transfer #{index} failed with an error of the probe.")]
+    RecordedError {
+        /// The index of the transfer in the log, starting at 0.
+        index: usize,
+    },
+}
+
+impl From<ReplayError> for ArmError {
+    fn from(error: ReplayError) -> Self {
+        ArmError::Probe(DebugProbeError::ProbeSpecific(Box::new(error)))
+    }
+}
+
+/// A DAP transfer of probe-rs, which is compared with the recorded one.
+struct DapTransfer<'a> {
+    port: PortType,
+    address: u8,
+    write: bool,
+    values: &'a [u32],
+}
+
+impl fmt::Display for DapTransfer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let port = match self.port {
+            PortType::DebugPort => "DP",
+            PortType::AccessPort => "AP",
+        };
+        let direction = if self.write { "write" } else { "read" };
+        write!(f, "{port} {direction} {:#04x}", self.address)?;
+        if self.write {
+            for value in self.values {
+                write!(f, " {value:#010x}")?;
+            }
+        } else if self.values.len() > 1 {
+            write!(f, " ({} values)", self.values.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// A mock probe which replays the DAP transfers of a transaction log, see the [module documentation](self).
+pub struct ReplayProbe {
+    protocol: WireProtocol,
+    speed: u32,
+    /// The recorded DAP transfers which were not replayed yet.
+    transactions: VecDeque<Transaction>,
+    /// The number of replayed transfers.
+    replayed: usize,
+}
+
+impl fmt::Debug for ReplayProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayProbe")
+            .field("protocol", &self.protocol)
+            .field("speed", &self.speed)
+            .field("replayed", &self.replayed)
+            .field("remaining", &self.transactions.len())
+            .finish()
+    }
+}
+
+impl ReplayProbe {
+    /// Create a probe which replays the DAP transfers of `transactions`. JTAG scans are skipped.
+    pub fn new(transactions: Vec<Transaction>) -> Self {
+        ReplayProbe {
+            protocol: WireProtocol::Swd,
+            speed: 1000,
+            transactions: transactions
+                .into_iter()
+                .filter(|transaction| matches!(transaction.transfer, Transfer::Dap { .. }))
+                .collect(),
+            replayed: 0,
+        }
+    }
+
+    /// Create a probe which replays the transaction log at `path`.
+    pub fn from_log(path: &Path) -> Result<Self, TransactionLogError> {
+        let file = File::open(path)?;
+        Ok(Self::new(transaction_log::read(BufReader::new(file))?))
+    }
+
+    /// The number of recorded transfers which were not replayed yet. A complete reproduction replays all of them.
+    pub fn remaining(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Makes a generic probe out of the [`ReplayProbe`].
+    pub fn into_probe(self) -> Probe {
+        Probe::from_specific_probe(Box::new(self))
+    }
+
+    /// Replay the next recorded transfer, which has to match `transfer`. The recorded values are returned.
+    fn replay(&mut self, transfer: DapTransfer<'_>) -> Result<Vec<u32>, ArmError> {
+        let index = self.replayed;
+        let Some(transaction) = self.transactions.pop_front() else {
+            return Err(ReplayError::Exhausted {
+                transfer: transfer.to_string(),
+            }
+            .into());
+        };
+        self.replayed += 1;
+
+        let Transfer::Dap {
+            port,
+            address,
+            write,
+            values,
+        } = &transaction.transfer
+        else {
+            unreachable!("only DAP transfers are replayed");
+        };
+        let port_type = match port {
+            DapPort::Dp(_) => PortType::DebugPort,
+            DapPort::Ap(_) => PortType::AccessPort,
+        };
+
+        // The recorded values of failed reads are not known.
+        let values_match = if transfer.write {
+            values[..] == *transfer.values
+        } else {
+            transaction.ack != Ack::Ok || values.len() == transfer.values.len()
+        };
+        if port_type != transfer.port
+            || *address != transfer.address
+            || *write != transfer.write
+            || !values_match
+        {
+            let recorded = DapTransfer {
+                port: port_type,
+                address: *address,
+                write: *write,
+                values,
+            };
+            return Err(ReplayError::Mismatch {
+                index,
+                transfer: transfer.to_string(),
+                recorded: recorded.to_string(),
+            }
+            .into());
+        }
+
+        match transaction.ack {
+            Ack::Ok => Ok(values.clone()),
+            Ack::Wait => Err(DapError::WaitResponse.into()),
+            Ack::Fault => Err(DapError::FaultResponse.into()),
+            Ack::NoAck => Err(DapError::NoAcknowledge.into()),
+            Ack::Error => Err(ReplayError::RecordedError { index }.into()),
+        }
+    }
+}
+
+impl DebugProbe for ReplayProbe {
+    fn new_from_selector(
+        _selector: impl Into<DebugProbeSelector>,
+    ) -> Result<Box<Self>, DebugProbeError>
+    where
+        Self: Sized,
+    {
+        Err(DebugProbeError::NotImplemented(
+            "opening a replay probe with a selector",
+        ))
+    }
+
+    fn get_name(&self) -> &str {
+        "Replay of a transaction log"
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.speed
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        self.speed = speed_khz;
+
+        Ok(speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        self.protocol = protocol;
+
+        Ok(())
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        Some(self.protocol)
+    }
+
+    fn detach(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        Some(self)
+    }
+
+    fn try_get_arm_interface<'probe>(
+        self: Box<Self>,
+    ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
+    {
+        Ok(Box::new(ArmCommunicationInterface::new(self, false)))
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        true
+    }
+}
+
+impl RawDapAccess for ReplayProbe {
+    /// The transfers which select a DP on a multidrop bus are not recorded.
+    fn select_dp(&mut self, _dp: DpAddress) -> Result<(), ArmError> {
+        Ok(())
+    }
+
+    fn raw_read_register(&mut self, port: PortType, address: u8) -> Result<u32, ArmError> {
+        let values = self.replay(DapTransfer {
+            port,
+            address,
+            write: false,
+            values: &[0],
+        })?;
+        Ok(values[0])
+    }
+
+    fn raw_read_block(
+        &mut self,
+        port: PortType,
+        address: u8,
+        values: &mut [u32],
+    ) -> Result<(), ArmError> {
+        let recorded = self.replay(DapTransfer {
+            port,
+            address,
+            write: false,
+            values,
+        })?;
+        values.copy_from_slice(&recorded);
+        Ok(())
+    }
+
+    fn raw_write_register(
+        &mut self,
+        port: PortType,
+        address: u8,
+        value: u32,
+    ) -> Result<(), ArmError> {
+        self.replay(DapTransfer {
+            port,
+            address,
+            write: true,
+            values: &[value],
+        })
+        .map(|_| ())
+    }
+
+    fn raw_write_block(
+        &mut self,
+        port: PortType,
+        address: u8,
+        values: &[u32],
+    ) -> Result<(), ArmError> {
+        self.replay(DapTransfer {
+            port,
+            address,
+            write: true,
+            values,
+        })
+        .map(|_| ())
+    }
+
+    /// Line sequences are not recorded.
+    fn swj_sequence(&mut self, _bit_len: u8, _bits: u64) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    /// Pin changes are not recorded, all pins read high.
+    fn swj_pins(
+        &mut self,
+        _pin_out: u32,
+        _pin_select: u32,
+        _pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        Ok(u32::MAX)
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn core_status_notification(&mut self, _: crate::CoreStatus) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+}
+
+impl DapProbe for ReplayProbe {}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::ReplayProbe;
+    use crate::architecture::arm::{
+        ApAddress, ArmError, DapError, DpAddress, PortType, RawDapAccess,
+    };
+    use crate::probe::transaction_log::{Ack, DapPort, Transaction, Transfer};
+
+    fn dap(port: DapPort, address: u8, write: bool, values: &[u32], ack: Ack) -> Transaction {
+        Transaction {
+            start: Duration::ZERO,
+            duration: Duration::ZERO,
+            transfer: Transfer::Dap {
+                port,
+                address,
+                write,
+                values: values.to_vec(),
+            },
+            ack,
+        }
+    }
+
+    #[test]
+    fn recorded_transfers_are_replayed() {
+        let dp = DapPort::Dp(DpAddress::Default);
+        let ap = DapPort::Ap(ApAddress {
+            dp: DpAddress::Default,
+            ap: 0,
+        });
+        let mut probe = ReplayProbe::new(vec![
+            dap(dp, 0x00, false, &[0x2ba0_1477], Ack::Ok),
+            dap(dp, 0x04, true, &[0x5000_0000], Ack::Ok),
+            dap(ap, 0x0C, false, &[1, 2, 3], Ack::Ok),
+            dap(ap, 0x0C, false, &[], Ack::Wait),
+        ]);
+
+        assert_eq!(
+            probe.raw_read_register(PortType::DebugPort, 0x00).unwrap(),
+            0x2ba0_1477
+        );
+        probe
+            .raw_write_register(PortType::DebugPort, 0x04, 0x5000_0000)
+            .unwrap();
+        let mut values = [0; 3];
+        probe
+            .raw_read_block(PortType::AccessPort, 0x0C, &mut values)
+            .unwrap();
+        assert_eq!(values, [1, 2, 3]);
+        assert!(matches!(
+            probe.raw_read_register(PortType::AccessPort, 0x0C),
+            Err(ArmError::Dap(DapError::WaitResponse))
+        ));
+        assert_eq!(probe.remaining(), 0);
+    }
+
+    #[test]
+    fn different_transfer_is_reported() {
+        let mut probe = ReplayProbe::new(vec![dap(
+            DapPort::Dp(DpAddress::Default),
+            0x04,
+            true,
+            &[0x5000_0000],
+            Ack::Ok,
+        )]);
+
+        let error = probe
+            .raw_write_register(PortType::DebugPort, 0x04, 0x1000_0000)
+            .unwrap_err();
+        let ArmError::Probe(crate::DebugProbeError::ProbeSpecific(error)) = error else {
+            panic!("the error is not an error of the probe");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Transfer #0 is 'DP write 0x04 0x10000000', but 'DP write 0x04 0x50000000' was recorded."
+        );
+    }
+}
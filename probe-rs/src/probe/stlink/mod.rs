@@ -1350,7 +1350,9 @@ impl ArmProbeInterface for StlinkArmDebug {
 
                 let component = Component::try_parse(&mut *memory, baseaddr)?;
 
-                if let Component::Class1RomTable(component_id, _) = component {
+                if let Component::Class1RomTable(component_id, _)
+                | Component::Class9RomTable(component_id, _) = component
+                {
                     if let Some(jep106) = component_id.peripheral_id().jep106() {
                         return Ok(Some(ArmChipInfo {
                             manufacturer: jep106,
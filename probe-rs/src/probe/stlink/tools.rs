@@ -1,7 +1,7 @@
 use rusb::Device;
 use rusb::UsbContext;
 
-use crate::probe::{DebugProbeInfo, DebugProbeType};
+use crate::probe::{usb_path, DebugProbeInfo, DebugProbeType};
 
 use super::usb_interface::USB_PID_EP_MAP;
 use super::usb_interface::USB_VID;
@@ -45,17 +45,20 @@ pub fn list_stlink_devices() -> Vec<DebugProbeInfo> {
                         }
                     };
 
-                    Some(DebugProbeInfo::new(
-                        format!(
-                            "STLink {}",
-                            &USB_PID_EP_MAP[&descriptor.product_id()].version_name
-                        ),
-                        descriptor.vendor_id(),
-                        descriptor.product_id(),
-                        sn_str,
-                        DebugProbeType::StLink,
-                        None,
-                    ))
+                    Some(
+                        DebugProbeInfo::new(
+                            format!(
+                                "STLink {}",
+                                &USB_PID_EP_MAP[&descriptor.product_id()].version_name
+                            ),
+                            descriptor.vendor_id(),
+                            descriptor.product_id(),
+                            sn_str,
+                            DebugProbeType::StLink,
+                            None,
+                        )
+                        .with_usb_path(usb_path(&device)),
+                    )
                 })
                 .collect::<Vec<_>>()
         })
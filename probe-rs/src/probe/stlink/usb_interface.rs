@@ -8,8 +8,8 @@ use std::collections::HashMap;
 
 use super::tools::{is_stlink_device, read_serial_number};
 use crate::{
-    probe::{DebugProbeError, ProbeCreationError},
-    DebugProbeSelector,
+    probe::{usb_path, usb_policy, DebugProbeError, ProbeCreationError},
+    DebugProbeSelector, DebugProbeType,
 };
 
 /// The USB Command packet size.
@@ -114,9 +114,10 @@ impl StLinkUsbDevice {
             .filter(is_stlink_device)
             .find_map(|device| {
                 let descriptor = device.device_descriptor().ok()?;
-                // First match the VID & PID.
+                // First match the VID & PID, and the USB path if one was given.
                 if selector.vendor_id == descriptor.vendor_id()
                     && selector.product_id == descriptor.product_id()
+                    && selector.matches_usb_path(usb_path(&device).as_deref())
                 {
                     // If the VID & PID match, match the serial if one was given.
                     if let Some(serial) = &selector.serial_number {
@@ -199,6 +200,26 @@ impl StLinkUsbDevice {
     fn close(&mut self) -> Result<(), Error> {
         self.device_handle.release_interface(0)
     }
+
+    /// A bulk write with the USB policy of the ST-Link driver, where `timeout` is the default.
+    fn write_bulk(&self, endpoint: u8, data: &[u8], timeout: Duration) -> Result<usize, Error> {
+        usb_policy::transfer(
+            &DebugProbeType::StLink,
+            timeout,
+            |error| *error == Error::Timeout,
+            |timeout| self.device_handle.write_bulk(endpoint, data, timeout),
+        )
+    }
+
+    /// A bulk read with the USB policy of the ST-Link driver, where `timeout` is the default.
+    fn read_bulk(&self, endpoint: u8, data: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+        usb_policy::transfer(
+            &DebugProbeType::StLink,
+            timeout,
+            |error| *error == Error::Timeout,
+            |timeout| self.device_handle.read_bulk(endpoint, data, timeout),
+        )
+    }
 }
 
 impl StLinkUsb for StLinkUsbDevice {
@@ -227,9 +248,7 @@ impl StLinkUsb for StLinkUsbDevice {
         let ep_out = self.info.ep_out;
         let ep_in = self.info.ep_in;
 
-        let written_bytes = self
-            .device_handle
-            .write_bulk(ep_out, &padded_cmd, timeout)?;
+        let written_bytes = self.write_bulk(ep_out, &padded_cmd, timeout)?;
 
         if written_bytes != CMD_LEN {
             return Err(StlinkError::NotEnoughBytesWritten {
@@ -245,9 +264,7 @@ impl StLinkUsb for StLinkUsbDevice {
             let mut write_index = 0;
 
             while remaining_bytes > 0 {
-                let written_bytes =
-                    self.device_handle
-                        .write_bulk(ep_out, &write_data[write_index..], timeout)?;
+                let written_bytes = self.write_bulk(ep_out, &write_data[write_index..], timeout)?;
 
                 remaining_bytes -= written_bytes;
                 write_index += written_bytes;
@@ -268,9 +285,7 @@ impl StLinkUsb for StLinkUsbDevice {
             let mut read_index = 0;
 
             while remaining_bytes > 0 {
-                let read_bytes =
-                    self.device_handle
-                        .read_bulk(ep_in, &mut read_data[read_index..], timeout)?;
+                let read_bytes = self.read_bulk(ep_in, &mut read_data[read_index..], timeout)?;
 
                 read_index += read_bytes;
                 remaining_bytes -= read_bytes;
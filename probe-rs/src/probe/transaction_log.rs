@@ -0,0 +1,645 @@
+//! Record the transfers on the wire between the probe and the target.
+//!
+//! The log is opt-in, see [`start`]. Every DAP register access and every JTAG scan of the RISC-V debug transport
+//! module is written to a CSV file, with its timing and acknowledgement, so a protocol error which only happens with
+//! a particular probe and chip can be captured and attached to an issue report. [`read`] parses a log again,
+//! [`Transaction`] formats its entries with the names of the registers, and [`ReplayProbe`](crate::ReplayProbe)
+//! replays it.
+//!
+//! Probes which batch transfers report the error of a queued write with a later transfer, so the acknowledgement of a
+//! write is only reliable for the last transfer of a batch.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+use crate::architecture::arm::{ApAddress, ArmError, DapError, DpAddress};
+
+/// The header line of a log file.
+const HEADER: &str = "start_us,duration_us,transfer,port,register,bits,data,captured,ack";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOG: Lazy<Mutex<Option<TransactionLog>>> = Lazy::new(|| Mutex::new(None));
+
+struct TransactionLog {
+    writer: LineWriter<File>,
+    started: Instant,
+}
+
+/// Start to record all transfers to a CSV file at `path`, which is overwritten. A log which was started before is
+/// closed.
+pub fn start(path: &Path) -> std::io::Result<()> {
+    let mut writer = LineWriter::new(File::create(path)?);
+    writeln!(writer, "{HEADER}")?;
+
+    let mut log = LOG.lock().unwrap();
+    *log = Some(TransactionLog {
+        writer,
+        started: Instant::now(),
+    });
+    ENABLED.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Stop recording transfers, and close the log file.
+pub fn stop() {
+    ENABLED.store(false, Ordering::Relaxed);
+    LOG.lock().unwrap().take();
+}
+
+/// Returns true if transfers are recorded.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The acknowledgement of the target for a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ack {
+    /// The transfer succeeded.
+    Ok,
+    /// The target was busy, and the transfer has to be repeated. For RISC-V, the debug module was busy.
+    Wait,
+    /// The target refused the transfer, e.g. because of a sticky error. For RISC-V, the operation of the debug
+    /// module failed.
+    Fault,
+    /// The target did not respond.
+    NoAck,
+    /// The probe reported another error, e.g. a parity or USB error.
+    Error,
+}
+
+impl Ack {
+    fn from_arm_error(error: Option<&ArmError>) -> Self {
+        match error {
+            None => Ack::Ok,
+            Some(ArmError::Dap(DapError::WaitResponse)) => Ack::Wait,
+            Some(ArmError::Dap(DapError::FaultResponse)) => Ack::Fault,
+            Some(ArmError::Dap(DapError::NoAcknowledge)) => Ack::NoAck,
+            Some(_) => Ack::Error,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Ack::Ok => "ok",
+            Ack::Wait => "wait",
+            Ack::Fault => "fault",
+            Ack::NoAck => "no_ack",
+            Ack::Error => "error",
+        }
+    }
+}
+
+/// The port of a DAP transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DapPort {
+    /// A register of a debug port.
+    Dp(DpAddress),
+    /// A register of an access port.
+    Ap(ApAddress),
+}
+
+/// A transfer between the probe and the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transfer {
+    /// A read or write of a DAP register, with SWD or JTAG.
+    Dap {
+        /// The port of the register.
+        port: DapPort,
+        /// The address of the register, including its bank.
+        address: u8,
+        /// The register was written.
+        write: bool,
+        /// The values which were written or read. Repeated accesses of a register, e.g. of `DRW`, are one transfer.
+        values: Vec<u32>,
+    },
+    /// A scan of a JTAG data register.
+    Jtag {
+        /// The instruction which selects the data register.
+        instruction: u32,
+        /// The length of the data register in bits.
+        length: u32,
+        /// The bits which were shifted into the data register, least significant bit first.
+        data: Vec<u8>,
+        /// The bits which were shifted out of the data register. Empty for batched scans, whose captured bits are
+        /// not kept.
+        captured: Vec<u8>,
+    },
+}
+
+/// An entry of the transaction log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// When the transfer started, relative to the start of the log.
+    pub start: Duration,
+    /// How long the transfer took.
+    pub duration: Duration,
+    /// The transfer.
+    pub transfer: Transfer,
+    /// The acknowledgement of the target.
+    pub ack: Ack,
+}
+
+/// An error while parsing a transaction log.
+#[derive(Debug, thiserror::Error)]
+pub enum TransactionLogError {
+    /// The log could not be read.
+    #[error("Failed to read the transaction log.")]
+    Io(#[from] std::io::Error),
+    /// A line of the log is malformed.
+    #[error("Line {line} of the transaction log is malformed: {message}")]
+    Malformed {
+        /// The number of the line, starting at 1.
+        line: usize,
+        /// What is wrong with the line.
+        message: String,
+    },
+}
+
+/// Record a DAP transfer, which started at `start`. For reads, `values` are the values which were read.
+pub(crate) fn record_dap(
+    start: Instant,
+    port: DapPort,
+    address: u8,
+    write: bool,
+    values: &[u32],
+    error: Option<&ArmError>,
+) {
+    if !is_enabled() {
+        return;
+    }
+
+    // The values of failed reads are undefined.
+    let values = if error.is_some() && !write {
+        Vec::new()
+    } else {
+        values.to_vec()
+    };
+
+    record(
+        start,
+        Transfer::Dap {
+            port,
+            address,
+            write,
+            values,
+        },
+        Ack::from_arm_error(error),
+    );
+}
+
+/// Record a JTAG scan, which started at `start`.
+pub(crate) fn record_jtag(
+    start: Instant,
+    instruction: u32,
+    length: u32,
+    data: &[u8],
+    captured: &[u8],
+    ack: Ack,
+) {
+    if !is_enabled() {
+        return;
+    }
+
+    let bytes = ((length + 7) / 8) as usize;
+    let truncate = |bits: &[u8]| bits[..bits.len().min(bytes)].to_vec();
+    record(
+        start,
+        Transfer::Jtag {
+            instruction,
+            length,
+            data: truncate(data),
+            captured: truncate(captured),
+        },
+        ack,
+    );
+}
+
+fn record(start: Instant, transfer: Transfer, ack: Ack) {
+    let duration = start.elapsed();
+
+    let mut log = LOG.lock().unwrap();
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+
+    let transaction = Transaction {
+        start: start.saturating_duration_since(log.started),
+        duration,
+        transfer,
+        ack,
+    };
+    if let Err(error) = writeln!(log.writer, "{}", transaction.to_csv()) {
+        tracing::warn!("Failed to write to the transaction log: {error}");
+    }
+}
+
+/// Parse the transactions of a log file.
+pub fn read(input: impl BufRead) -> Result<Vec<Transaction>, TransactionLogError> {
+    let mut transactions = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || (index == 0 && line == HEADER) {
+            continue;
+        }
+
+        let transaction =
+            Transaction::from_csv(&line).map_err(|message| TransactionLogError::Malformed {
+                line: index + 1,
+                message,
+            })?;
+        transactions.push(transaction);
+    }
+    Ok(transactions)
+}
+
+impl Transaction {
+    /// Format the transaction as a line of the CSV file.
+    fn to_csv(&self) -> String {
+        let (transfer, port, register, bits, data, captured) = match &self.transfer {
+            Transfer::Dap {
+                port,
+                address,
+                write,
+                values,
+            } => {
+                let (kind, port) = match port {
+                    DapPort::Dp(dp) => ("dp", format_dp(*dp)),
+                    DapPort::Ap(ap) => ("ap", format!("{}@{}", ap.ap, format_dp(ap.dp))),
+                };
+                let direction = if *write { "write" } else { "read" };
+                let values = values
+                    .iter()
+                    .map(|value| format!("{value:#010x}"))
+                    .collect::<Vec<_>>();
+                (
+                    format!("{kind}_{direction}"),
+                    port,
+                    format!("{address:#04x}"),
+                    String::new(),
+                    values.join(" "),
+                    String::new(),
+                )
+            }
+            Transfer::Jtag {
+                instruction,
+                length,
+                data,
+                captured,
+            } => (
+                "jtag".to_owned(),
+                String::new(),
+                format!("{instruction:#x}"),
+                length.to_string(),
+                format_bits(data),
+                format_bits(captured),
+            ),
+        };
+
+        format!(
+            "{},{},{transfer},{port},{register},{bits},{data},{captured},{}",
+            self.start.as_micros(),
+            self.duration.as_micros(),
+            self.ack.name()
+        )
+    }
+
+    /// Parse a line of the CSV file.
+    fn from_csv(line: &str) -> Result<Self, String> {
+        let fields = line.split(',').collect::<Vec<_>>();
+        let [start, duration, transfer, port, register, bits, data, captured, ack] = fields[..]
+        else {
+            return Err(format!("expected 9 fields, found {}", fields.len()));
+        };
+
+        let micros = |field: &str| {
+            field
+                .parse::<u64>()
+                .map(Duration::from_micros)
+                .map_err(|_| format!("'{field}' is not a number of microseconds"))
+        };
+        let register = parse_hex(register)?;
+
+        let transfer = match transfer {
+            "jtag" => Transfer::Jtag {
+                instruction: register,
+                length: bits
+                    .parse()
+                    .map_err(|_| format!("'{bits}' is not a number of bits"))?,
+                data: parse_bits(data)?,
+                captured: parse_bits(captured)?,
+            },
+            _ => {
+                let (kind, direction) = transfer
+                    .split_once('_')
+                    .ok_or_else(|| format!("unknown transfer '{transfer}'"))?;
+                let port = match kind {
+                    "dp" => DapPort::Dp(parse_dp(port)?),
+                    "ap" => {
+                        let (ap, dp) = port
+                            .split_once('@')
+                            .ok_or_else(|| format!("'{port}' is not an access port"))?;
+                        DapPort::Ap(ApAddress {
+                            ap: ap
+                                .parse()
+                                .map_err(|_| format!("'{ap}' is not an access port"))?,
+                            dp: parse_dp(dp)?,
+                        })
+                    }
+                    _ => return Err(format!("unknown transfer '{transfer}'")),
+                };
+                let write = match direction {
+                    "read" => false,
+                    "write" => true,
+                    _ => return Err(format!("unknown transfer '{transfer}'")),
+                };
+                Transfer::Dap {
+                    port,
+                    address: register as u8,
+                    write,
+                    values: data
+                        .split_whitespace()
+                        .map(parse_hex)
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+        };
+
+        let ack = match ack {
+            "ok" => Ack::Ok,
+            "wait" => Ack::Wait,
+            "fault" => Ack::Fault,
+            "no_ack" => Ack::NoAck,
+            "error" => Ack::Error,
+            _ => return Err(format!("unknown acknowledgement '{ack}'")),
+        };
+
+        Ok(Transaction {
+            start: micros(start)?,
+            duration: micros(duration)?,
+            transfer,
+            ack,
+        })
+    }
+}
+
+impl fmt::Display for Transaction {
+    /// Format the transaction for humans, with the names of the registers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>12.3} ms {:>7} us  ",
+            self.start.as_secs_f64() * 1000.0,
+            self.duration.as_micros()
+        )?;
+
+        match &self.transfer {
+            Transfer::Dap {
+                port,
+                address,
+                write,
+                values,
+            } => {
+                let (port, name) = match port {
+                    DapPort::Dp(DpAddress::Default) => {
+                        ("DP".to_owned(), dp_register_name(*address, *write))
+                    }
+                    DapPort::Dp(dp) => (
+                        format!("DP {}", format_dp(*dp)),
+                        dp_register_name(*address, *write),
+                    ),
+                    DapPort::Ap(ap) => {
+                        let port = match ap.dp {
+                            DpAddress::Default => format!("AP {}", ap.ap),
+                            dp => format!("AP {} of DP {}", ap.ap, format_dp(dp)),
+                        };
+                        (port, ap_register_name(*address))
+                    }
+                };
+                let register = match name {
+                    Some(name) => name.to_owned(),
+                    None => format!("{address:#04x}"),
+                };
+                let direction = if *write { "write" } else { "read " };
+                let values = values
+                    .iter()
+                    .map(|value| format!("{value:#010x}"))
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "{port:<6} {direction} {register:<10} {}",
+                    values.join(" ")
+                )?;
+            }
+            Transfer::Jtag {
+                instruction,
+                length,
+                data,
+                captured,
+            } => {
+                write!(
+                    f,
+                    "JTAG   IR {instruction:#04x}, {length} bits, in {}",
+                    format_bits(data)
+                )?;
+                if !captured.is_empty() {
+                    write!(f, ", out {}", format_bits(captured))?;
+                }
+            }
+        }
+
+        if self.ack != Ack::Ok {
+            write!(f, "  {}", self.ack.name().to_uppercase())?;
+        }
+        Ok(())
+    }
+}
+
+fn format_dp(dp: DpAddress) -> String {
+    match dp {
+        DpAddress::Default => "default".to_owned(),
+        DpAddress::Multidrop(targetsel) => format!("{targetsel:#010x}"),
+    }
+}
+
+fn parse_dp(dp: &str) -> Result<DpAddress, String> {
+    match dp {
+        "default" => Ok(DpAddress::Default),
+        targetsel => parse_hex(targetsel).map(DpAddress::Multidrop),
+    }
+}
+
+fn parse_hex(value: &str) -> Result<u32, String> {
+    value
+        .strip_prefix("0x")
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+        .ok_or_else(|| format!("'{value}' is not a hexadecimal number"))
+}
+
+/// Format bits, least significant bit first, as a hexadecimal number.
+fn format_bits(bits: &[u8]) -> String {
+    if bits.is_empty() {
+        return String::new();
+    }
+    let digits = bits
+        .iter()
+        .rev()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("0x{digits}")
+}
+
+fn parse_bits(bits: &str) -> Result<Vec<u8>, String> {
+    if bits.is_empty() {
+        return Ok(Vec::new());
+    }
+    let digits = bits
+        .strip_prefix("0x")
+        .filter(|digits| digits.len() % 2 == 0)
+        .ok_or_else(|| format!("'{bits}' is not a hexadecimal number of bytes"))?;
+    (0..digits.len())
+        .step_by(2)
+        .rev()
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("'{bits}' is not a hexadecimal number of bytes"))
+        })
+        .collect()
+}
+
+/// The name of a DP register. Some addresses are different registers for reads and writes.
+fn dp_register_name(address: u8, write: bool) -> Option<&'static str> {
+    Some(match (address, write) {
+        (0x00, false) => "DPIDR",
+        (0x00, true) => "ABORT",
+        (0x04, _) => "CTRL/STAT",
+        (0x08, _) => "SELECT",
+        (0x0C, false) => "RDBUFF",
+        (0x0C, true) => "TARGETSEL",
+        (0x14, _) => "DLCR",
+        (0x20, _) => "BASEPTR0",
+        (0x24, _) => "TARGETID",
+        (0x30, _) => "BASEPTR1",
+        (0x34, _) => "DLPIDR",
+        (0x44, _) => "EVENTSTAT",
+        (0x54, _) => "SELECT1",
+        _ => return None,
+    })
+}
+
+/// The name of a MEM-AP register.
+fn ap_register_name(address: u8) -> Option<&'static str> {
+    Some(match address {
+        0x00 => "CSW",
+        0x04 => "TAR",
+        0x08 => "TAR[63:32]",
+        0x0C => "DRW",
+        0x10 => "BD0",
+        0x14 => "BD1",
+        0x18 => "BD2",
+        0x1C => "BD3",
+        0x20 => "MBT",
+        0xF0 => "BASE2",
+        0xF4 => "CFG",
+        0xF8 => "BASE",
+        0xFC => "IDR",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{read, Ack, DapPort, Transaction, Transfer};
+    use crate::architecture::arm::{ApAddress, DpAddress};
+
+    fn transactions() -> Vec<Transaction> {
+        vec![
+            Transaction {
+                start: Duration::from_micros(1500),
+                duration: Duration::from_micros(12),
+                transfer: Transfer::Dap {
+                    port: DapPort::Dp(DpAddress::Default),
+                    address: 0x04,
+                    write: true,
+                    values: vec![0x5000_0000],
+                },
+                ack: Ack::Ok,
+            },
+            Transaction {
+                start: Duration::from_micros(1600),
+                duration: Duration::from_micros(40),
+                transfer: Transfer::Dap {
+                    port: DapPort::Ap(ApAddress {
+                        dp: DpAddress::Multidrop(0x0100_2927),
+                        ap: 1,
+                    }),
+                    address: 0x0C,
+                    write: false,
+                    values: vec![],
+                },
+                ack: Ack::Wait,
+            },
+            Transaction {
+                start: Duration::from_micros(2000),
+                duration: Duration::from_micros(8),
+                transfer: Transfer::Jtag {
+                    instruction: 0x11,
+                    length: 41,
+                    data: vec![0x01, 0x00, 0x00, 0x00, 0x40, 0x01],
+                    captured: vec![0x03, 0x00, 0x00, 0x00, 0x00, 0x00],
+                },
+                ack: Ack::Wait,
+            },
+        ]
+    }
+
+    #[test]
+    fn log_is_read_back() {
+        let mut log = format!("{}\n", super::HEADER);
+        for transaction in transactions() {
+            log.push_str(&transaction.to_csv());
+            log.push('\n');
+        }
+
+        assert_eq!(read(log.as_bytes()).unwrap(), transactions());
+    }
+
+    #[test]
+    fn transactions_are_printed_with_register_names() {
+        let printed = transactions()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            printed[0],
+            "       1.500 ms      12 us  DP     write CTRL/STAT  0x50000000"
+        );
+        assert_eq!(
+            printed[1],
+            "       1.600 ms      40 us  AP 1 of DP 0x01002927 read  DRW          WAIT"
+        );
+        assert_eq!(
+            printed[2],
+            "       2.000 ms       8 us  JTAG   IR 0x11, 41 bits, in 0x014000000001, out 0x000000000003  WAIT"
+        );
+    }
+
+    #[test]
+    fn malformed_line_is_reported() {
+        let error = read("1,2,dp_read,default,0x00,,,,maybe\n".as_bytes()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 1 of the transaction log is malformed: unknown acknowledgement 'maybe'"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! The timeouts and retries of the USB transfers of the probe drivers.
+//!
+//! Each driver has its own [`UsbPolicy`], see [`set_policy`]. By default, the drivers use their built-in timeouts
+//! and don't retry, which fails fast. Hubs, virtual machines and USB/IP add latency, so transfers may need a longer
+//! timeout, or a retry after a short wait. The policy is used by the CMSIS-DAP, ST-Link and ESP USB JTAG drivers,
+//! and FTDI probes use its timeout. J-Links are accessed through their own library, which has fixed timeouts.
+//!
+//! Only transfers which timed out are retried: a command whose response timed out is not sent again, but the
+//! response is waited for again, so a command is never executed twice.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use crate::DebugProbeType;
+
+static POLICIES: Lazy<Mutex<HashMap<DebugProbeType, UsbPolicy>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static STATISTICS: Lazy<Mutex<HashMap<DebugProbeType, UsbStatistics>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// How a probe driver times out and retries its USB transfers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsbPolicy {
+    /// The timeout of a single transfer, or `None` for the built-in timeout of the driver.
+    pub timeout: Option<Duration>,
+    /// How often a transfer which timed out is retried.
+    pub retries: u32,
+    /// The wait before the first retry, which doubles with each further retry.
+    pub backoff: Duration,
+}
+
+impl Default for UsbPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retries: 0,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl UsbPolicy {
+    /// The timeout of a transfer whose built-in timeout is `default`.
+    pub fn timeout_or(&self, default: Duration) -> Duration {
+        self.timeout.unwrap_or(default)
+    }
+
+    /// The wait before the retry `attempt`, starting at 1.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(Duration::from_secs(5))
+    }
+}
+
+/// The counts of the USB transfers of a probe driver since the start of the program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsbStatistics {
+    /// The number of transfers, not counting the retries.
+    pub transfers: u64,
+    /// The number of retries after a timeout.
+    pub retries: u64,
+    /// The number of transfers which still timed out after all retries.
+    pub timeouts: u64,
+}
+
+/// Set the policy of the driver of `probe_type`. It applies to the probes which are opened afterwards.
+pub fn set_policy(probe_type: DebugProbeType, policy: UsbPolicy) {
+    POLICIES.lock().unwrap().insert(probe_type, policy);
+}
+
+/// The policy of the driver of `probe_type`.
+pub fn policy(probe_type: &DebugProbeType) -> UsbPolicy {
+    POLICIES
+        .lock()
+        .unwrap()
+        .get(probe_type)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// The statistics of all drivers which transferred anything.
+pub fn statistics() -> Vec<(DebugProbeType, UsbStatistics)> {
+    let statistics = STATISTICS.lock().unwrap();
+    statistics
+        .iter()
+        .map(|(probe_type, statistics)| (probe_type.clone(), *statistics))
+        .collect()
+}
+
+fn count(probe_type: &DebugProbeType, update: impl FnOnce(&mut UsbStatistics)) {
+    update(
+        STATISTICS
+            .lock()
+            .unwrap()
+            .entry(probe_type.clone())
+            .or_default(),
+    );
+}
+
+/// Run a USB `transfer` with the timeout of the policy of `probe_type`, or `default_timeout`, and retry it while
+/// `is_timeout` is true for its error.
+pub(crate) fn transfer<T, E>(
+    probe_type: &DebugProbeType,
+    default_timeout: Duration,
+    is_timeout: impl Fn(&E) -> bool,
+    mut transfer: impl FnMut(Duration) -> Result<T, E>,
+) -> Result<T, E> {
+    let policy = policy(probe_type);
+    let timeout = policy.timeout_or(default_timeout);
+    count(probe_type, |statistics| statistics.transfers += 1);
+
+    let mut attempt = 0;
+    loop {
+        match transfer(timeout) {
+            Err(error) if is_timeout(&error) => {
+                if attempt == policy.retries {
+                    count(probe_type, |statistics| statistics.timeouts += 1);
+                    return Err(error);
+                }
+                attempt += 1;
+                count(probe_type, |statistics| statistics.retries += 1);
+                tracing::debug!(
+                    "USB transfer of the {probe_type:?} driver timed out after {timeout:?}, retry {attempt} of {}",
+                    policy.retries
+                );
+                std::thread::sleep(policy.backoff(attempt));
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{policy, set_policy, statistics, transfer, UsbPolicy};
+    use crate::DebugProbeType;
+
+    #[test]
+    fn timed_out_transfers_are_retried() {
+        let probe_type = DebugProbeType::Custom("usb-policy-test");
+        set_policy(
+            probe_type.clone(),
+            UsbPolicy {
+                timeout: Some(Duration::from_millis(250)),
+                retries: 2,
+                backoff: Duration::ZERO,
+            },
+        );
+        assert_eq!(policy(&probe_type).retries, 2);
+
+        let mut attempts = 0;
+        let result = transfer(
+            &probe_type,
+            Duration::from_millis(100),
+            |error: &&str| *error == "timeout",
+            |timeout| {
+                assert_eq!(timeout, Duration::from_millis(250));
+                attempts += 1;
+                if attempts < 3 {
+                    Err("timeout")
+                } else {
+                    Ok(attempts)
+                }
+            },
+        );
+        assert_eq!(result, Ok(3));
+
+        let result: Result<(), _> = transfer(
+            &probe_type,
+            Duration::from_millis(100),
+            |error: &&str| *error == "timeout",
+            |_| Err("timeout"),
+        );
+        assert_eq!(result, Err("timeout"));
+
+        let (_, statistics) = statistics()
+            .into_iter()
+            .find(|(driver, _)| *driver == probe_type)
+            .unwrap();
+        assert_eq!(statistics.transfers, 2);
+        assert_eq!(statistics.retries, 4);
+        assert_eq!(statistics.timeouts, 1);
+    }
+}
@@ -0,0 +1,232 @@
+//! Detection of debug probes which are plugged in or unplugged.
+//!
+//! There is no portable way to get notified of USB hot-plug events for all the supported probe types,
+//! so the [`ProbeWatcher`] periodically lists the connected probes and reports the differences.
+
+use super::{DebugProbeError, DebugProbeInfo, DebugProbeSelector, Probe, ProbeCreationError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// A change in the set of connected debug probes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeEvent {
+    /// A probe was plugged in.
+    Attached(DebugProbeInfo),
+    /// A probe was unplugged.
+    Detached(DebugProbeInfo),
+}
+
+type ProbeLister = Box<dyn FnMut() -> Vec<DebugProbeInfo> + Send>;
+
+/// Keeps track of the connected debug probes, and reports when probes are plugged in or unplugged.
+pub struct ProbeWatcher {
+    known_probes: Vec<DebugProbeInfo>,
+    list_probes: ProbeLister,
+}
+
+impl ProbeWatcher {
+    /// Create a new watcher. The probes which are currently connected are not reported as events.
+    pub fn new() -> Self {
+        Self::with_lister(Box::new(Probe::list_all))
+    }
+
+    fn with_lister(mut list_probes: ProbeLister) -> Self {
+        Self {
+            known_probes: list_probes(),
+            list_probes,
+        }
+    }
+
+    /// The probes which were connected when the watcher was last polled.
+    pub fn probes(&self) -> &[DebugProbeInfo] {
+        &self.known_probes
+    }
+
+    /// List the connected probes, and return the changes since the last poll.
+    pub fn poll(&mut self) -> Vec<ProbeEvent> {
+        let current_probes = (self.list_probes)();
+
+        let detached = self
+            .known_probes
+            .iter()
+            .filter(|probe| !current_probes.contains(probe))
+            .cloned()
+            .map(ProbeEvent::Detached);
+        let attached = current_probes
+            .iter()
+            .filter(|probe| !self.known_probes.contains(probe))
+            .cloned()
+            .map(ProbeEvent::Attached);
+        let events = detached.chain(attached).collect();
+
+        self.known_probes = current_probes;
+        events
+    }
+
+    /// Wait until a probe matching the `selector` is connected, and return it.
+    ///
+    /// `on_event` is called for every probe which is plugged in or unplugged while waiting.
+    /// If no matching probe appears within `timeout`, [`ProbeCreationError::NotFound`] is returned.
+    pub fn wait_for(
+        &mut self,
+        selector: &DebugProbeSelector,
+        timeout: Duration,
+        poll_interval: Duration,
+        mut on_event: impl FnMut(&ProbeEvent),
+    ) -> Result<DebugProbeInfo, DebugProbeError> {
+        let start = Instant::now();
+
+        loop {
+            if let Some(probe) = self
+                .known_probes
+                .iter()
+                .find(|probe| selector.matches(probe))
+            {
+                return Ok(probe.clone());
+            }
+
+            if start.elapsed() >= timeout {
+                return Err(DebugProbeError::ProbeCouldNotBeCreated(
+                    ProbeCreationError::NotFound,
+                ));
+            }
+
+            std::thread::sleep(poll_interval);
+            for event in self.poll() {
+                on_event(&event);
+            }
+        }
+    }
+
+    /// Keep polling in a background thread, and call `callback` for every event.
+    ///
+    /// The thread is stopped when the returned [`ProbeWatcherHandle`] is dropped.
+    pub fn spawn(
+        mut self,
+        poll_interval: Duration,
+        mut callback: impl FnMut(ProbeEvent) + Send + 'static,
+    ) -> ProbeWatcherHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(poll_interval);
+                for event in self.poll() {
+                    callback(event);
+                }
+            }
+        });
+
+        ProbeWatcherHandle {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Default for ProbeWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`ProbeWatcher`] running in a background thread, see [`ProbeWatcher::spawn`].
+pub struct ProbeWatcherHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for ProbeWatcherHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DebugProbeType;
+    use std::collections::VecDeque;
+
+    fn probe(serial_number: &str) -> DebugProbeInfo {
+        DebugProbeInfo {
+            identifier: "Test probe".to_string(),
+            vendor_id: 0x1234,
+            product_id: 0x5678,
+            serial_number: Some(serial_number.to_string()),
+            probe_type: DebugProbeType::CmsisDap,
+            hid_interface: None,
+            usb_path: None,
+        }
+    }
+
+    /// A watcher which returns the given probe lists, one per poll, and then keeps returning the last one.
+    fn scripted_watcher(lists: Vec<Vec<DebugProbeInfo>>) -> ProbeWatcher {
+        let mut lists = VecDeque::from(lists);
+        ProbeWatcher::with_lister(Box::new(move || {
+            if lists.len() > 1 {
+                lists.pop_front().unwrap()
+            } else {
+                lists.front().cloned().unwrap_or_default()
+            }
+        }))
+    }
+
+    #[test]
+    fn poll_reports_changes() {
+        let mut watcher = scripted_watcher(vec![
+            vec![probe("A")],
+            vec![probe("A"), probe("B")],
+            vec![probe("B")],
+        ]);
+
+        assert_eq!(watcher.probes(), &[probe("A")]);
+        assert_eq!(watcher.poll(), vec![ProbeEvent::Attached(probe("B"))]);
+        assert_eq!(watcher.poll(), vec![ProbeEvent::Detached(probe("A"))]);
+        assert_eq!(watcher.poll(), vec![]);
+    }
+
+    #[test]
+    fn wait_for_probe_to_appear() {
+        let mut watcher = scripted_watcher(vec![vec![], vec![probe("A")], vec![probe("B")]]);
+        let selector: DebugProbeSelector = "1234:5678:B".parse().unwrap();
+
+        let mut events = Vec::new();
+        let found = watcher
+            .wait_for(&selector, Duration::from_secs(1), Duration::ZERO, |event| {
+                events.push(event.clone())
+            })
+            .unwrap();
+
+        assert_eq!(found, probe("B"));
+        assert_eq!(
+            events,
+            vec![
+                ProbeEvent::Attached(probe("A")),
+                ProbeEvent::Detached(probe("A")),
+                ProbeEvent::Attached(probe("B")),
+            ]
+        );
+    }
+
+    #[test]
+    fn wait_for_probe_times_out() {
+        let mut watcher = scripted_watcher(vec![vec![probe("A")]]);
+        let selector: DebugProbeSelector = "1234:5678:B".parse().unwrap();
+
+        let result = watcher.wait_for(&selector, Duration::ZERO, Duration::ZERO, |_| {});
+
+        assert!(matches!(
+            result,
+            Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::NotFound
+            ))
+        ));
+    }
+}
@@ -199,6 +199,9 @@ impl Rtt {
             }
         }
 
+        // The statistics of the channels of a previous control block don't apply to this one.
+        core.reset_rtt_statistics();
+
         Ok(Some(Rtt {
             ptr,
             up_channels: Channels(up_channels),
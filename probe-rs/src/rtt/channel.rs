@@ -1,4 +1,6 @@
+use crate::events::{self, SessionEvent};
 use crate::rtt::Error;
+use crate::session::RttDirection;
 use crate::{config::MemoryRegion, Core, MemoryInterface};
 use scroll::{Pread, LE};
 use std::cmp::min;
@@ -230,6 +232,13 @@ impl UpChannel {
         if total > 0 {
             // Write read pointer back to target if something was read
             core.write_word_32((self.0.ptr + Channel::O_READ as u32).into(), read)?;
+
+            core.record_rtt_transfer(self.number(), RttDirection::Up, total);
+            events::emit(|| SessionEvent::RttData {
+                core: core.id(),
+                channel: self.number(),
+                data: buf[..total].to_vec(),
+            });
         }
 
         Ok(total)
@@ -327,6 +336,7 @@ impl DownChannel {
 
         // Write write pointer back to target
         core.write_word_32((self.0.ptr + Channel::O_WRITE as u32).into(), write)?;
+        core.record_rtt_transfer(self.number(), RttDirection::Down, total);
 
         Ok(total)
     }
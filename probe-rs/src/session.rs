@@ -1,9 +1,10 @@
 use crate::architecture::arm::component::get_arm_components;
 use crate::architecture::arm::sequences::{ArmDebugSequence, DefaultArmSequence};
-use crate::architecture::arm::{ArmError, DpAddress};
+use crate::architecture::arm::{ArmError, DpAddress, Pins};
 use crate::architecture::riscv::communication_interface::RiscvError;
 use crate::config::{ChipInfo, CoreExt, RegistryError, Target, TargetSelector};
 use crate::core::{Architecture, CombinedCoreState};
+use crate::events::{self, SessionEvent};
 use crate::{
     architecture::{
         arm::{
@@ -18,6 +19,17 @@ use crate::{AttachMethod, Core, CoreType, Error, FakeProbe, Probe};
 use std::ops::DerefMut;
 use std::{fmt, sync::Arc, time::Duration};
 
+mod authentication;
+mod shared;
+mod state;
+
+pub use authentication::{AuthenticationChallenge, AuthenticationProtocol, DebugAuthenticator};
+pub use shared::{SessionGuard, SharedSession};
+pub(crate) use state::{record_rtt_transfer, RttStatistics};
+pub use state::{
+    CoreSnapshot, FlashResult, ProbeDetails, RttChannelStatistics, RttDirection, SessionState,
+};
+
 /// The `Session` struct represents an active debug session.
 ///
 /// ## Creating a session
@@ -29,9 +41,10 @@ use std::{fmt, sync::Arc, time::Duration};
 ///
 /// # Usage
 /// The Session is the common handle that gives a user exclusive access to an active probe.
-/// You can create and share a session between threads to enable multiple stakeholders (e.g. GDB and RTT) to access the target taking turns, by using  `Arc<Mutex<Session>>.`
+/// You can create and share a session between threads to enable multiple stakeholders (e.g. GDB and RTT) to access the target taking turns, by using  `Arc<SharedSession>`.
 ///
-/// If you do so, make sure that both threads sleep in between tasks such that other stakeholders may take their turn.
+/// The [SharedSession] hands out access in the order it was requested, so keep it locked only for short operations,
+/// such that other stakeholders may take their turn.
 ///
 /// To get access to a single [Core] from the `Session`, the [Session::core()] method can be used.
 /// Please see the [Session::core()] method for more usage guidelines.
@@ -42,6 +55,28 @@ pub struct Session {
     interface: ArchitectureInterface,
     cores: Vec<CombinedCoreState>,
     configured_trace_sink: Option<TraceSink>,
+    disconnect_action: DisconnectAction,
+    probe: ProbeDetails,
+    last_flash: Option<FlashResult>,
+}
+
+/// What happens to the cores when a [`Session`] is dropped.
+///
+/// The hardware breakpoints are always removed. Unless the cores are left halted, the debug logic of the cores is also
+/// disabled, e.g. on Cortex-M cores the halting debug in DHCSR, the vector catches and the DWT and ITM in DEMCR.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisconnectAction {
+    /// Leave the cores as they are, and only disable the debug logic.
+    #[default]
+    Detach,
+    /// Resume halted cores, and leave running cores running.
+    Run,
+    /// Halt the cores. The debug logic stays enabled, because disabling it would resume the cores.
+    Halt,
+    /// Reset the cores and let them run.
+    ResetAndRun,
+    /// Reset the cores and halt them at the first instruction.
+    ResetAndHalt,
 }
 
 pub(crate) enum ArchitectureInterface {
@@ -91,6 +126,26 @@ impl Session {
         target: TargetSelector,
         attach_method: AttachMethod,
         permissions: Permissions,
+    ) -> Result<Self, Error> {
+        match Self::attach_to_target(probe, target, attach_method, permissions) {
+            Ok(session) => {
+                events::emit(|| SessionEvent::SessionStarted {
+                    target: session.target.name.clone(),
+                });
+                Ok(session)
+            }
+            Err(error) => {
+                events::error(&error);
+                Err(error)
+            }
+        }
+    }
+
+    fn attach_to_target(
+        probe: Probe,
+        target: TargetSelector,
+        attach_method: AttachMethod,
+        permissions: Permissions,
     ) -> Result<Self, Error> {
         let (probe, target) = get_target_from_selector(target, attach_method, probe)?;
 
@@ -161,6 +216,7 @@ impl Session {
         }
 
         probe.inner_attach()?;
+        let probe_details = ProbeDetails::new(&probe);
 
         let interface = probe.try_into_arm_interface().map_err(|(_, err)| err)?;
 
@@ -168,6 +224,22 @@ impl Session {
             .initialize(sequence_handle.clone())
             .map_err(|(_interface, e)| e)?;
 
+        if let Some(authenticator) = permissions.debug_authenticator() {
+            let _span = tracing::debug_span!("debug_authenticate").entered();
+
+            match sequence_handle.debug_authenticate(
+                &mut *interface,
+                default_memory_ap,
+                authenticator,
+            ) {
+                Ok(()) => (),
+                Err(ArmError::ReAttachRequired) => {
+                    Self::reattach_arm_interface(&mut interface, &sequence_handle)?;
+                }
+                Err(e) => return Err(Error::Arm(e)),
+            }
+        }
+
         let unlock_span = tracing::debug_span!("debug_device_unlock").entered();
 
         // Enable debug mode
@@ -217,6 +289,9 @@ impl Session {
                 interface: ArchitectureInterface::Arm(interface),
                 cores,
                 configured_trace_sink: None,
+                disconnect_action: DisconnectAction::default(),
+                probe: probe_details,
+                last_flash: None,
             };
 
             {
@@ -240,6 +315,9 @@ impl Session {
                 interface: ArchitectureInterface::Arm(interface),
                 cores,
                 configured_trace_sink: None,
+                disconnect_action: DisconnectAction::default(),
+                probe: probe_details,
+                last_flash: None,
             })
         }
     }
@@ -261,6 +339,7 @@ impl Session {
         };
 
         probe.inner_attach()?;
+        let probe_details = ProbeDetails::new(&probe);
 
         let interface = probe
             .try_into_riscv_interface()
@@ -271,6 +350,9 @@ impl Session {
             interface: ArchitectureInterface::Riscv(Box::new(interface)),
             cores,
             configured_trace_sink: None,
+            disconnect_action: DisconnectAction::default(),
+            probe: probe_details,
+            last_flash: None,
         };
 
         {
@@ -333,6 +415,38 @@ impl Session {
         self.interface.attach(combined_state)
     }
 
+    /// Refuse memory writes of the core `core_index` to non-volatile memory and to the read-only regions of its
+    /// memory map, to protect against a mistyped address, e.g. in a debugger. The writes fail with
+    /// [`Error::MemoryWriteProtected`]. Writes are not protected by default.
+    pub fn protect_memory_writes(&mut self, core_index: usize, protect: bool) -> Result<(), Error> {
+        let protected_memory = if protect {
+            self.target
+                .core_memory_map(core_index)
+                .filter(|region| region.is_write_protected())
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.cores
+            .get_mut(core_index)
+            .ok_or(Error::CoreNotFound(core_index))?
+            .protected_memory = protected_memory;
+        Ok(())
+    }
+
+    /// Attach to a core which can access the memory at `address`, so the memory is accessed through the access
+    /// port of that core. The default core is used if it can access the address.
+    ///
+    /// This matters for heterogeneous multicore chips, whose cores have different memory maps.
+    pub fn core_for_address(&mut self, address: u64) -> Result<Core<'_>, Error> {
+        let core_index = self
+            .target
+            .core_index_for_address(address)
+            .ok_or(Error::NoCoreForAddress(address))?;
+        self.core(core_index)
+    }
+
     /// Read available trace data from the specified data sink.
     ///
     /// This method is only supported for ARM-based targets, and will
@@ -431,10 +545,7 @@ impl Session {
 
     /// Check if the connected device has a debug erase sequence defined
     pub fn has_sequence_erase_all(&self) -> bool {
-        match &self.target.debug_sequence {
-            DebugSequence::Arm(seq) => seq.debug_erase_sequence().is_some(),
-            DebugSequence::Riscv(_) => false,
-        }
+        self.target.has_sequence_erase_all()
     }
 
     /// Erase all flash memory using the Device's Debug Erase Sequence if any
@@ -499,11 +610,35 @@ impl Session {
         get_arm_components(interface, dp)
     }
 
+    /// Set what happens to the cores when the session is dropped, e.g. because the debugger disconnects.
+    pub fn set_disconnect_action(&mut self, action: DisconnectAction) {
+        self.disconnect_action = action;
+    }
+
     /// Get the target description of the connected target.
     pub fn target(&self) -> &Target {
         &self.target
     }
 
+    /// Read the unique device ID of the target, or return `None` if the location of the ID is not known for this target.
+    pub fn read_unique_id(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        use crate::MemoryInterface;
+
+        let Some(location) = self.target.unique_id else {
+            return Ok(None);
+        };
+
+        let mut id = vec![0; location.size as usize];
+        // The ID is often outside of the memory map, in the registers of a peripheral.
+        let core_index = self
+            .target
+            .core_index_for_address(location.address)
+            .unwrap_or(0);
+        self.core(core_index)?.read(location.address, &mut id)?;
+
+        Ok(Some(id))
+    }
+
     /// Configure the target and probe for serial wire view (SWV) tracing.
     pub fn setup_tracing(
         &mut self,
@@ -526,14 +661,14 @@ impl Session {
         let components = self.get_arm_components(DpAddress::Default)?;
         let interface = self.get_arm_interface()?;
 
-        // Configure SWO on the probe when the trace sink is configured for a serial output. Note
-        // that on some architectures, the TPIU is configured to drive SWO.
+        // Configure SWO on the probe when the trace sink is configured for a serial output, unless
+        // it is received by an external serial adapter. Note that on some architectures, the TPIU
+        // is configured to drive SWO.
         match destination {
-            TraceSink::Swo(ref config) => {
-                interface.enable_swo(config)?;
-            }
-            TraceSink::Tpiu(ref config) => {
-                interface.enable_swo(config)?;
+            TraceSink::Swo(ref config) | TraceSink::Tpiu(ref config) => {
+                if !config.external_capture() {
+                    interface.enable_swo(config)?;
+                }
             }
             TraceSink::TraceMemory => {}
         }
@@ -586,6 +721,56 @@ impl Session {
                 .and_then(|mut core| core.clear_all_hw_breakpoints())
         })
     }
+
+    /// Assert the hardware reset line (nRESET) of the target, and keep it asserted until
+    /// [`Session::target_reset_deassert`] is called.
+    ///
+    /// Unlike [`Core::reset`], this does not use the reset sequence of the target, and does not
+    /// touch the state of the debug connection. This is not supported on all probes.
+    pub fn target_reset_assert(&mut self) -> Result<(), Error> {
+        tracing::debug!("Asserting the target reset line");
+        self.set_reset_line(false)
+    }
+
+    /// Release the hardware reset line (nRESET) of the target.
+    ///
+    /// This is not supported on all probes.
+    pub fn target_reset_deassert(&mut self) -> Result<(), Error> {
+        tracing::debug!("Releasing the target reset line");
+        self.set_reset_line(true)
+    }
+
+    /// Assert the hardware reset line (nRESET) of the target for `duration`, and release it again.
+    ///
+    /// This is not supported on all probes.
+    pub fn target_reset_pulse(&mut self, duration: Duration) -> Result<(), Error> {
+        self.target_reset_assert()?;
+        std::thread::sleep(duration);
+        self.target_reset_deassert()
+    }
+
+    /// Drive the nRESET line high (`true`, released) or low (`false`, asserted).
+    fn set_reset_line(&mut self, high: bool) -> Result<(), Error> {
+        match &mut self.interface {
+            ArchitectureInterface::Arm(interface) => {
+                let mut n_reset = Pins(0);
+                n_reset.set_nreset(true);
+                let n_reset = n_reset.0 as u32;
+
+                let pin_out = if high { n_reset } else { 0 };
+                interface.swj_pins(pin_out, n_reset, 0)?;
+            }
+            ArchitectureInterface::Riscv(interface) => {
+                if high {
+                    interface.target_reset_deassert()?;
+                } else {
+                    interface.target_reset_assert()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // This test ensures that [Session] is fully [Send] + [Sync].
@@ -602,6 +787,32 @@ impl Drop for Session {
             tracing::warn!("Could not clear all hardware breakpoints: {:?}", err);
         }
 
+        let action = self.disconnect_action;
+        if let Err(err) = { 0..self.cores.len() }.try_for_each(|i| {
+            self.core(i).and_then(|mut core| match action {
+                DisconnectAction::Run if core.core_halted()? => core.run(),
+                DisconnectAction::Detach | DisconnectAction::Run => Ok(()),
+                DisconnectAction::Halt => core.halt(Duration::from_millis(100)).map(|_| ()),
+                DisconnectAction::ResetAndRun => core.reset(),
+                DisconnectAction::ResetAndHalt => {
+                    core.reset_and_halt(Duration::from_millis(100)).map(|_| ())
+                }
+            })
+        }) {
+            tracing::warn!("Failed to apply {action:?} to the cores during shutdown: {err:?}");
+        }
+        events::emit(|| SessionEvent::SessionStopped {
+            target: self.target.name.clone(),
+        });
+
+        // Disabling the debug logic would resume halted cores.
+        if matches!(
+            action,
+            DisconnectAction::Halt | DisconnectAction::ResetAndHalt
+        ) {
+            return;
+        }
+
         // Call any necessary deconfiguration/shutdown hooks.
         if let Err(err) = { 0..self.cores.len() }
             .try_for_each(|i| self.core(i).and_then(|mut core| core.debug_core_stop()))
@@ -714,6 +925,8 @@ fn get_target_from_selector(
 pub struct Permissions {
     /// When set to true, all memory of the chip may be erased or reset to factory default
     erase_all: bool,
+    /// Answers the challenge of devices which require debug authentication.
+    debug_authenticator: Option<Arc<dyn DebugAuthenticator>>,
 }
 
 impl Permissions {
@@ -735,6 +948,19 @@ impl Permissions {
         }
     }
 
+    /// Authenticate to devices which only allow debug access after answering a challenge, see [`DebugAuthenticator`].
+    #[must_use]
+    pub fn with_debug_authenticator(self, authenticator: Arc<dyn DebugAuthenticator>) -> Self {
+        Self {
+            debug_authenticator: Some(authenticator),
+            ..self
+        }
+    }
+
+    pub(crate) fn debug_authenticator(&self) -> Option<&dyn DebugAuthenticator> {
+        self.debug_authenticator.as_deref()
+    }
+
     pub(crate) fn erase_all(&self) -> Result<(), MissingPermissions> {
         if self.erase_all {
             Ok(())
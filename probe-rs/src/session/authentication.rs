@@ -0,0 +1,50 @@
+//! Debug authentication of locked devices.
+//!
+//! Some devices disable debug access in the field, and only re-enable it after the debugger proved that
+//! it is allowed to do so, usually by signing a challenge issued by the device with a key whose certificate
+//! is stored on the device. This is used to open locked production parts for failure analysis, without
+//! erasing them.
+//!
+//! probe-rs does not handle keys or certificates itself. Instead, the user supplies a [`DebugAuthenticator`]
+//! through [`Permissions::with_debug_authenticator`](crate::Permissions::with_debug_authenticator), which is
+//! asked to answer the challenge of the device before the cores are attached.
+
+use std::fmt;
+
+/// The protocol a device uses for debug authentication.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthenticationProtocol {
+    /// The debug authentication of the NXP debug mailbox, as used by the LPC55Sxx family.
+    ///
+    /// The challenge is the Debug Credential Challenge (DAC) returned by the `DBG_AUTH_START` command,
+    /// and the response is the Debug Authentication Response (DAR) sent with the `DBG_AUTH_RESP` command.
+    /// Both are sent as little-endian 32-bit words.
+    NxpDebugMailbox,
+}
+
+impl fmt::Display for AuthenticationProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthenticationProtocol::NxpDebugMailbox => write!(f, "NXP debug mailbox"),
+        }
+    }
+}
+
+/// A challenge issued by a device which requires debug authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticationChallenge {
+    /// The protocol, which determines the format of [`data`](Self::data) and of the expected response.
+    pub protocol: AuthenticationProtocol,
+    /// The raw challenge, as read from the device.
+    pub data: Vec<u8>,
+}
+
+/// Answers the debug authentication challenges of locked devices.
+///
+/// Implement this trait to supply the certificates and the signatures, e.g. by calling into a
+/// signing service or a hardware security module.
+pub trait DebugAuthenticator: fmt::Debug + Send + Sync {
+    /// Create the response to the given challenge, which is sent back to the device unmodified.
+    fn respond(&self, challenge: &AuthenticationChallenge) -> Result<Vec<u8>, anyhow::Error>;
+}
@@ -0,0 +1,216 @@
+//! Sharing a [`Session`] between multiple threads.
+
+use super::Session;
+use crate::{Core, Error};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// A [`Session`] which can be shared between multiple threads, e.g. an RTT poller, a DAP handler and a GDB stub.
+///
+/// Access to the session is granted in the order in which it was requested. This ensures that a thread which
+/// locks the session in a tight loop, like an RTT poller, cannot starve the other users of the session.
+///
+/// Keep the session locked for as short as possible, ideally only for a single operation,
+/// so that the other users can interleave their accesses.
+#[derive(Debug)]
+pub struct SharedSession {
+    session: Mutex<Session>,
+    queue: Mutex<Queue>,
+    turn: Condvar,
+}
+
+/// A FIFO ticket queue.
+#[derive(Debug, Default)]
+struct Queue {
+    /// The ticket which will be handed out next.
+    next_ticket: u64,
+    /// The ticket which currently holds the lock.
+    now_serving: u64,
+}
+
+impl SharedSession {
+    /// Wrap a session, so that it can be shared.
+    pub fn new(session: Session) -> Self {
+        Self {
+            session: Mutex::new(session),
+            queue: Mutex::new(Queue::default()),
+            turn: Condvar::new(),
+        }
+    }
+
+    /// Lock the session, blocking until all users who requested access earlier are done.
+    ///
+    /// If a previous user panicked while holding the lock, the session is handed out anyway.
+    pub fn lock(&self) -> SessionGuard<'_> {
+        let mut queue = self.lock_queue();
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+
+        while queue.now_serving != ticket {
+            queue = self
+                .turn
+                .wait(queue)
+                .unwrap_or_else(|error| error.into_inner());
+        }
+        drop(queue);
+
+        self.guard()
+    }
+
+    /// Lock the session, if nobody else holds or waits for the lock.
+    pub fn try_lock(&self) -> Option<SessionGuard<'_>> {
+        let mut queue = self.lock_queue();
+        if queue.now_serving != queue.next_ticket {
+            return None;
+        }
+        queue.next_ticket += 1;
+        drop(queue);
+
+        Some(self.guard())
+    }
+
+    /// Lock the session, and run a function with the core with the given index.
+    pub fn with_core<R>(
+        &self,
+        core_index: usize,
+        function: impl FnOnce(&mut Core) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let mut session = self.lock();
+        let mut core = session.core(core_index)?;
+        function(&mut core)
+    }
+
+    /// Take back the session.
+    pub fn into_inner(self) -> Session {
+        self.session
+            .into_inner()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn lock_queue(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn guard(&self) -> SessionGuard<'_> {
+        // Only the holder of the current ticket gets here, so this never blocks.
+        let session = self
+            .session
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        SessionGuard {
+            session: Some(session),
+            shared: self,
+        }
+    }
+}
+
+impl From<Session> for SharedSession {
+    fn from(session: Session) -> Self {
+        Self::new(session)
+    }
+}
+
+/// Exclusive access to a [`SharedSession`]. The next user gets access once this guard is dropped.
+pub struct SessionGuard<'a> {
+    session: Option<MutexGuard<'a, Session>>,
+    shared: &'a SharedSession,
+}
+
+impl Deref for SessionGuard<'_> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        self.session
+            .as_ref()
+            .expect("session guard already released")
+    }
+}
+
+impl DerefMut for SessionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Session {
+        self.session
+            .as_mut()
+            .expect("session guard already released")
+    }
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        // Release the session before passing on the turn, so that the next user never blocks on it.
+        self.session.take();
+
+        let mut queue = self.shared.lock_queue();
+        queue.now_serving += 1;
+        drop(queue);
+        self.shared.turn.notify_all();
+    }
+}
+
+// The whole point of the shared session is to be used from multiple threads.
+static_assertions::assert_impl_all!(SharedSession: Send, Sync);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FakeProbe, Permissions, Probe};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn shared_session() -> SharedSession {
+        let probe = Probe::from_specific_probe(Box::new(FakeProbe::new()));
+        let session = probe
+            .attach("stm32wb55ccux", Permissions::default())
+            .expect("Failed to attach with 'fake' probe.");
+        SharedSession::new(session)
+    }
+
+    /// Wait until `count` tickets were handed out, i.e. `count` users hold or wait for the lock.
+    fn wait_for_tickets(shared: &SharedSession, count: u64) {
+        while shared.lock_queue().next_ticket < count {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn try_lock_fails_while_locked() {
+        let shared = shared_session();
+
+        let guard = shared.lock();
+        assert!(shared.try_lock().is_none());
+        drop(guard);
+
+        assert!(shared.try_lock().is_some());
+        assert_eq!(shared.lock().list_cores().len(), 1);
+    }
+
+    #[test]
+    fn lock_is_granted_in_order() {
+        let shared = Arc::new(shared_session());
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let guard = shared.lock();
+
+        let handles: Vec<_> = (0..3)
+            .map(|index| {
+                let thread_shared = shared.clone();
+                let order = order.clone();
+                let handle = thread::spawn(move || {
+                    let _guard = thread_shared.lock();
+                    order.lock().unwrap().push(index);
+                });
+                // The thread queued up once it took its ticket, the one after the tickets of the guard and of the
+                // threads before it.
+                wait_for_tickets(&shared, index + 2);
+                handle
+            })
+            .collect();
+
+        drop(guard);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+    }
+}
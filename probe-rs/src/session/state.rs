@@ -0,0 +1,211 @@
+//! A snapshot of the state of a [`Session`], see [`Session::state`].
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::Session;
+use crate::{CoreStatus, CoreType, ErrorCode, Probe, WireProtocol};
+
+/// The statistics of the RTT channels of a core, by channel and direction.
+pub(crate) type RttStatistics = BTreeMap<(usize, RttDirection), RttChannelStatistics>;
+
+/// The state of a [`Session`] at one point in time, see [`Session::state`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionState {
+    /// The name of the target.
+    pub target: String,
+    /// The probe which the session attached with.
+    pub probe: ProbeDetails,
+    /// The state of each core, ordered by the index of the core.
+    pub cores: Vec<CoreSnapshot>,
+    /// The statistics of the RTT channels which transferred data.
+    pub rtt_channels: Vec<RttChannelStatistics>,
+    /// The result of the last flash download of the session, if there was one.
+    pub last_flash: Option<FlashResult>,
+}
+
+/// The details of the probe of a session, as they were when the session attached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeDetails {
+    /// The name of the probe.
+    pub name: String,
+    /// The wire protocol which is used to talk to the target.
+    pub protocol: Option<WireProtocol>,
+    /// The speed of the wire protocol in kHz.
+    pub speed_khz: u32,
+}
+
+impl ProbeDetails {
+    pub(crate) fn new(probe: &Probe) -> Self {
+        Self {
+            name: probe.get_name(),
+            protocol: probe.protocol(),
+            speed_khz: probe.speed_khz(),
+        }
+    }
+}
+
+/// The state of a core, see [`SessionState`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreSnapshot {
+    /// The index of the core.
+    pub index: usize,
+    /// The name of the core in the target description.
+    pub name: String,
+    /// The type of the core.
+    pub core_type: CoreType,
+    /// The status of the core, [`CoreStatus::Unknown`] if it could not be read.
+    pub status: CoreStatus,
+    /// The addresses of the set hardware breakpoints, empty if they could not be read.
+    pub breakpoints: Vec<u64>,
+}
+
+/// The direction of an RTT channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RttDirection {
+    /// Target to host.
+    Up,
+    /// Host to target.
+    Down,
+}
+
+/// How much data an RTT channel transferred, see [`SessionState`].
+///
+/// The statistics are counted per session, from the last time RTT was attached to the core on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttChannelStatistics {
+    /// The index of the core which transferred the data.
+    pub core: usize,
+    /// The number of the channel.
+    pub channel: usize,
+    /// The direction of the channel.
+    pub direction: RttDirection,
+    /// The number of bytes which were transferred.
+    pub bytes: u64,
+    /// The number of reads or writes which transferred at least one byte.
+    pub transfers: u64,
+    /// When the last byte was transferred.
+    pub last_transfer: SystemTime,
+}
+
+/// The result of a flash download, see [`SessionState`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashResult {
+    /// When the download finished.
+    pub finished: SystemTime,
+    /// How long the download took.
+    pub duration: Duration,
+    /// The classification and the message of the error, if the download failed.
+    pub error: Option<(ErrorCode, String)>,
+}
+
+impl FlashResult {
+    pub(crate) fn new(
+        started: Instant,
+        result: &Result<(), impl std::error::Error + 'static>,
+    ) -> Self {
+        Self {
+            finished: SystemTime::now(),
+            duration: started.elapsed(),
+            error: result
+                .as_ref()
+                .err()
+                .map(|error| (ErrorCode::of(error), error.to_string())),
+        }
+    }
+
+    /// Whether the download succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Count a transfer of `bytes` bytes over an RTT channel of `core` in its `statistics`.
+#[cfg_attr(not(feature = "rtt"), allow(dead_code))]
+pub(crate) fn record_rtt_transfer(
+    statistics: &mut RttStatistics,
+    core: usize,
+    channel: usize,
+    direction: RttDirection,
+    bytes: usize,
+) {
+    let entry = statistics
+        .entry((channel, direction))
+        .or_insert(RttChannelStatistics {
+            core,
+            channel,
+            direction,
+            bytes: 0,
+            transfers: 0,
+            last_transfer: SystemTime::now(),
+        });
+    entry.bytes += bytes as u64;
+    entry.transfers += 1;
+    entry.last_transfer = SystemTime::now();
+}
+
+impl Session {
+    /// Take a snapshot of the state of the session: the status and the breakpoints of the cores, the probe, the
+    /// statistics of the RTT channels and the result of the last flash download.
+    ///
+    /// This reads the status and the breakpoints of every core from the target. A core which can't be read is
+    /// reported with [`CoreStatus::Unknown`], so that one unresponsive core doesn't hide the state of the others.
+    pub fn state(&mut self) -> SessionState {
+        let cores = (0..self.cores.len())
+            .map(|index| {
+                let core_info = &self.target.cores[index];
+                let name = core_info.name.clone();
+                let core_type = core_info.core_type;
+                let (status, breakpoints) = match self.core(index) {
+                    Ok(mut core) => (
+                        core.status().unwrap_or(CoreStatus::Unknown),
+                        core.hw_breakpoints().unwrap_or_default(),
+                    ),
+                    Err(_) => (CoreStatus::Unknown, Vec::new()),
+                };
+                CoreSnapshot {
+                    index,
+                    name,
+                    core_type,
+                    status,
+                    breakpoints,
+                }
+            })
+            .collect();
+
+        SessionState {
+            target: self.target.name.clone(),
+            probe: self.probe.clone(),
+            cores,
+            rtt_channels: self
+                .cores
+                .iter()
+                .flat_map(|core| core.rtt_statistics.values().copied())
+                .collect(),
+            last_flash: self.last_flash.clone(),
+        }
+    }
+
+    /// Record the result of a flash download, see [`SessionState::last_flash`].
+    pub(crate) fn set_last_flash(&mut self, result: FlashResult) {
+        self.last_flash = Some(result);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{record_rtt_transfer, RttDirection, RttStatistics};
+
+    #[test]
+    fn rtt_transfers_are_counted_per_channel_and_direction() {
+        let mut statistics = RttStatistics::new();
+        record_rtt_transfer(&mut statistics, 1, 0, RttDirection::Up, 10);
+        record_rtt_transfer(&mut statistics, 1, 0, RttDirection::Up, 5);
+        record_rtt_transfer(&mut statistics, 1, 0, RttDirection::Down, 3);
+
+        let up = statistics[&(0, RttDirection::Up)];
+        assert_eq!((up.core, up.bytes, up.transfers), (1, 15, 2));
+        let down = statistics[&(0, RttDirection::Down)];
+        assert_eq!((down.core, down.bytes, down.transfers), (1, 3, 1));
+    }
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "async")]
+
+use futures_executor::block_on;
+use probe_rs::{AsyncSession, BlockingExecutor, FakeProbe, Permissions, Probe};
+
+#[test]
+fn async_session_with_fake_probe() {
+    let executor = BlockingExecutor::new(2);
+    let probe = Probe::from_specific_probe(Box::new(FakeProbe::new()));
+
+    let session = block_on(AsyncSession::attach(
+        probe,
+        "stm32wb55ccux",
+        Permissions::default(),
+        executor,
+    ))
+    .expect("Failed to attach with 'fake' probe.");
+
+    let cores = block_on(session.with_session(|session| Ok(session.list_cores().len())))
+        .expect("Failed to access the session.");
+    assert_eq!(cores, 1);
+
+    // A second handle keeps the session alive.
+    let handle = session.clone();
+    let session = session
+        .into_inner()
+        .expect_err("The session is still shared.");
+    drop(handle);
+
+    let session = session
+        .into_inner()
+        .ok()
+        .expect("The session is no longer shared.");
+    assert_eq!(session.list_cores().len(), 1);
+}
+
+#[test]
+fn executor_runs_jobs_on_worker_threads() {
+    let executor = BlockingExecutor::new(2);
+
+    let first = executor.spawn(|| std::thread::current().name().map(str::to_string));
+    let second = executor.spawn(|| 40 + 2);
+
+    let name = block_on(first).unwrap().unwrap();
+    assert!(name.starts_with("probe-rs-executor-"));
+    assert_eq!(block_on(second).unwrap(), 42);
+}
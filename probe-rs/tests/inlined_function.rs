@@ -80,3 +80,18 @@ fn function_name_of_non_inlined_function() -> TestResult {
 
     Ok(())
 }
+
+#[test]
+fn function_ranges_contain_inlined_function() -> TestResult {
+    let di = DebugInfo::from_file("tests/inlined-function").unwrap();
+
+    let ranges = di.function_ranges("__cortex_m_rt_main")?;
+
+    assert!(ranges.iter().any(|range| range.contains(&0x15e)));
+
+    let resolved = di.resolve_address(0x15e)?;
+
+    assert_eq!(resolved.function.as_deref(), Some("blink_on"));
+
+    Ok(())
+}
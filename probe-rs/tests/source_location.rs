@@ -105,3 +105,29 @@ fn find_non_existing_unit_by_path() {
         .get_breakpoint_location(unit_path, 14, None)
         .is_err());
 }
+
+#[test]
+fn source_location_with_load_offset() {
+    let di = DebugInfo::from_file_with_load_offset("tests/probe-rs-debugger-test", 0x1000).unwrap();
+
+    let path = Path::new("/Users/jacknoppe/dev/probe-rs-debugger-test/src/main.rs");
+
+    for (addr, line, col) in TEST_DATA.iter() {
+        let location = di
+            .get_source_location(*addr + 0x1000)
+            .expect("Failed to find the source location at the moved address.");
+        assert_eq!((location.line, location.column), (Some(*line), Some(*col)));
+
+        let column = if let ColumnType::Column(c) = col {
+            Some(*c)
+        } else {
+            None
+        };
+        assert_eq!(
+            *addr + 0x1000,
+            di.get_breakpoint_location(path, *line, column)
+                .expect("Failed to find breakpoint location.")
+                .address
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Python bindings for probe-rs, for hardware test scripts.
+//!
+//! A [`Session`] is opened on a target, and its cores are controlled through [`Core`] objects, which borrow the
+//! session for every call. [`Rtt`] reads the up channels and writes the down channels of a program which uses RTT.
+//! Every failure is raised as `ProbeRsError`.
+//!
+//! ```python
+//! import pyprobe_rs
+//!
+//! with pyprobe_rs.Session("nRF52840_xxAA") as session:
+//!     session.download("firmware.elf")
+//!     core = session.core(0)
+//!     core.reset()
+//!     rtt = session.attach_rtt()
+//!     print(rtt.read(0))
+//! ```
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use probe_rs::flashing::{self, BinOptions, Format};
+use probe_rs::{DebugProbeSelector, MemoryInterface, Permissions, Probe};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+create_exception!(pyprobe_rs, ProbeRsError, PyException);
+
+fn error(error: impl std::fmt::Display) -> PyErr {
+    ProbeRsError::new_err(error.to_string())
+}
+
+/// A connected debug probe.
+#[pyclass(get_all)]
+#[derive(Debug, Clone)]
+struct ProbeInfo {
+    identifier: String,
+    vendor_id: u16,
+    product_id: u16,
+    serial_number: Option<String>,
+    /// The selector which [`Session`] opens the probe with, `VID:PID[:SERIAL]`.
+    selector: String,
+}
+
+#[pymethods]
+impl ProbeInfo {
+    fn __repr__(&self) -> String {
+        format!("ProbeInfo({:?}, {:?})", self.identifier, self.selector)
+    }
+}
+
+/// List the connected debug probes.
+#[pyfunction]
+fn list_probes() -> Vec<ProbeInfo> {
+    Probe::list_all()
+        .iter()
+        .map(|info| ProbeInfo {
+            identifier: info.identifier.clone(),
+            vendor_id: info.vendor_id,
+            product_id: info.product_id,
+            serial_number: info.serial_number.clone(),
+            selector: DebugProbeSelector::from(info).to_string(),
+        })
+        .collect()
+}
+
+/// A session on a target, which is closed by `close` or at the end of a `with` block.
+#[pyclass]
+struct Session {
+    session: Option<probe_rs::Session>,
+}
+
+impl Session {
+    fn get(&mut self) -> PyResult<&mut probe_rs::Session> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| error("The session is closed"))
+    }
+}
+
+#[pymethods]
+impl Session {
+    /// Open the probe with the selector `probe`, or the first probe, and attach to the target `chip`.
+    #[new]
+    #[pyo3(signature = (chip, probe = None, speed_khz = None))]
+    fn new(
+        py: Python<'_>,
+        chip: String,
+        probe: Option<String>,
+        speed_khz: Option<u32>,
+    ) -> PyResult<Self> {
+        let session = py.allow_threads(|| -> Result<_, String> {
+            let mut probe = match probe {
+                Some(selector) => {
+                    let selector =
+                        DebugProbeSelector::try_from(&selector[..]).map_err(|e| e.to_string())?;
+                    Probe::open(selector).map_err(|e| e.to_string())?
+                }
+                None => Probe::list_all()
+                    .first()
+                    .ok_or("No probe was found")?
+                    .open()
+                    .map_err(|e| e.to_string())?,
+            };
+            if let Some(speed_khz) = speed_khz {
+                probe.set_speed(speed_khz).map_err(|e| e.to_string())?;
+            }
+            probe
+                .attach(chip, Permissions::default())
+                .map_err(|e| e.to_string())
+        });
+
+        Ok(Self {
+            session: Some(session.map_err(error)?),
+        })
+    }
+
+    /// Detach from the target and close the probe.
+    fn close(&mut self) {
+        self.session = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, _exc_type: &PyAny, _exc_value: &PyAny, _traceback: &PyAny) -> bool {
+        self.close();
+        false
+    }
+
+    /// The indices of the cores of the target.
+    fn cores(&mut self) -> PyResult<Vec<usize>> {
+        let cores = self.get()?.list_cores();
+        Ok(cores.into_iter().map(|(index, _)| index).collect())
+    }
+
+    /// The core with `index`.
+    #[pyo3(signature = (index = 0))]
+    fn core(slf: PyRef<'_, Self>, index: usize) -> Core {
+        Core {
+            session: slf.into(),
+            index,
+        }
+    }
+
+    /// Flash the file at `path`, in the format `elf`, `hex` or `bin`. A binary is flashed to `base_address`.
+    #[pyo3(signature = (path, format = "elf", base_address = None))]
+    fn download(
+        &mut self,
+        py: Python<'_>,
+        path: PathBuf,
+        format: &str,
+        base_address: Option<u64>,
+    ) -> PyResult<()> {
+        let format = match format {
+            "elf" => Format::default(),
+            "hex" => Format::Hex,
+            "bin" => Format::Bin(BinOptions {
+                base_address,
+                skip: 0,
+            }),
+            other => {
+                return Err(error(format!(
+                    "{other} is not a format, use elf, hex or bin"
+                )))
+            }
+        };
+        let session = self.get()?;
+        py.allow_threads(|| flashing::download_file(session, path, format))
+            .map_err(error)
+    }
+
+    /// Attach to the RTT control block of the program running on core `core`.
+    #[pyo3(signature = (core = 0))]
+    fn attach_rtt(mut slf: PyRefMut<'_, Self>, core: usize) -> PyResult<Rtt> {
+        let rtt = {
+            let session = slf.get()?;
+            let memory_map = session.target().memory_map.clone();
+            let mut target_core = session.core(core).map_err(error)?;
+            probe_rs::rtt::Rtt::attach(&mut target_core, &memory_map).map_err(error)?
+        };
+
+        Ok(Rtt {
+            session: slf.into(),
+            core,
+            rtt,
+        })
+    }
+}
+
+/// A core of the target of a [`Session`].
+#[pyclass]
+struct Core {
+    session: Py<Session>,
+    index: usize,
+}
+
+impl Core {
+    fn with<T>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&mut probe_rs::Core<'_>) -> Result<T, probe_rs::Error>,
+    ) -> PyResult<T> {
+        let mut session = self.session.borrow_mut(py);
+        let mut core = session.get()?.core(self.index).map_err(error)?;
+        f(&mut core).map_err(error)
+    }
+}
+
+#[pymethods]
+impl Core {
+    /// Halt the core, waiting up to `timeout_ms` for it to halt.
+    #[pyo3(signature = (timeout_ms = 500))]
+    fn halt(&self, py: Python<'_>, timeout_ms: u64) -> PyResult<()> {
+        self.with(py, |core| {
+            core.halt(Duration::from_millis(timeout_ms))?;
+            Ok(())
+        })
+    }
+
+    /// Resume the core.
+    fn run(&self, py: Python<'_>) -> PyResult<()> {
+        self.with(py, |core| core.run())
+    }
+
+    /// Reset the core, and let it run.
+    fn reset(&self, py: Python<'_>) -> PyResult<()> {
+        self.with(py, |core| core.reset())
+    }
+
+    /// Reset the core, and halt it before it runs the first instruction.
+    #[pyo3(signature = (timeout_ms = 500))]
+    fn reset_and_halt(&self, py: Python<'_>, timeout_ms: u64) -> PyResult<()> {
+        self.with(py, |core| {
+            core.reset_and_halt(Duration::from_millis(timeout_ms))?;
+            Ok(())
+        })
+    }
+
+    fn is_halted(&self, py: Python<'_>) -> PyResult<bool> {
+        self.with(py, |core| core.core_halted())
+    }
+
+    /// Read `length` bytes at `address`.
+    fn read(&self, py: Python<'_>, address: u64, length: usize) -> PyResult<PyObject> {
+        let mut data = vec![0; length];
+        self.with(py, |core| core.read(address, &mut data))?;
+        Ok(PyBytes::new(py, &data).into())
+    }
+
+    /// Write `data` to `address`.
+    fn write(&self, py: Python<'_>, address: u64, data: &[u8]) -> PyResult<()> {
+        self.with(py, |core| {
+            core.write_8(address, data)?;
+            core.flush()
+        })
+    }
+
+    fn read_32(&self, py: Python<'_>, address: u64) -> PyResult<u32> {
+        self.with(py, |core| core.read_word_32(address))
+    }
+
+    fn write_32(&self, py: Python<'_>, address: u64, value: u32) -> PyResult<()> {
+        self.with(py, |core| core.write_word_32(address, value))
+    }
+}
+
+/// The RTT channels of the program running on a core of a [`Session`].
+#[pyclass]
+struct Rtt {
+    session: Py<Session>,
+    core: usize,
+    rtt: probe_rs::rtt::Rtt,
+}
+
+#[pymethods]
+impl Rtt {
+    /// The numbers and names of the up channels, which the target writes to.
+    fn up_channels(&mut self) -> Vec<(usize, Option<String>)> {
+        self.rtt
+            .up_channels()
+            .iter()
+            .map(|channel| (channel.number(), channel.name().map(str::to_owned)))
+            .collect()
+    }
+
+    /// The numbers and names of the down channels, which the target reads.
+    fn down_channels(&mut self) -> Vec<(usize, Option<String>)> {
+        self.rtt
+            .down_channels()
+            .iter()
+            .map(|channel| (channel.number(), channel.name().map(str::to_owned)))
+            .collect()
+    }
+
+    /// Read the data which the target wrote to up channel `channel` since the last read.
+    #[pyo3(signature = (channel = 0))]
+    fn read(&mut self, py: Python<'_>, channel: usize) -> PyResult<PyObject> {
+        let Some(up_channel) = self.rtt.up_channels().get(channel) else {
+            return Err(error(format!("There is no up channel {channel}")));
+        };
+        let mut session = self.session.borrow_mut(py);
+        let mut core = session.get()?.core(self.core).map_err(error)?;
+
+        let mut data = Vec::new();
+        let mut buffer = [0; 1024];
+        loop {
+            let count = up_channel.read(&mut core, &mut buffer).map_err(error)?;
+            data.extend_from_slice(&buffer[..count]);
+            if count < buffer.len() {
+                break;
+            }
+        }
+        Ok(PyBytes::new(py, &data).into())
+    }
+
+    /// Write `data` to down channel `channel`, returning the number of bytes which fit into its buffer.
+    fn write(&mut self, py: Python<'_>, channel: usize, data: &[u8]) -> PyResult<usize> {
+        let Some(down_channel) = self.rtt.down_channels().get(channel) else {
+            return Err(error(format!("There is no down channel {channel}")));
+        };
+        let mut session = self.session.borrow_mut(py);
+        let mut core = session.get()?.core(self.core).map_err(error)?;
+        down_channel.write(&mut core, data).map_err(error)
+    }
+}
+
+#[pymodule]
+fn pyprobe_rs(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("ProbeRsError", py.get_type::<ProbeRsError>())?;
+    m.add_function(wrap_pyfunction!(list_probes, m)?)?;
+    m.add_class::<ProbeInfo>()?;
+    m.add_class::<Session>()?;
+    m.add_class::<Core>()?;
+    m.add_class::<Rtt>()?;
+    Ok(())
+}
@@ -94,6 +94,7 @@ pub fn cmd_elf(
                         range: 0..0x2000,
                         cores: vec!["main".to_owned()],
                         name: None,
+                        is_otp: false,
                     }),
                     MemoryRegion::Ram(RamRegion {
                         is_boot_memory: true,
@@ -103,6 +104,9 @@ pub fn cmd_elf(
                     }),
                 ],
                 flash_algorithms: vec![algorithm_name],
+                unique_id: None,
+                partitions: vec![],
+                aliases: vec![],
             }],
             flash_algorithms: vec![algorithm],
             source: BuiltIn,
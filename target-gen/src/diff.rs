@@ -0,0 +1,107 @@
+//! Compare a regenerated target description with the previous one.
+
+use std::fmt;
+
+use probe_rs::config::ChipFamily;
+
+/// The differences between two versions of a chip family.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct FamilyDiff {
+    pub(crate) added_chips: Vec<String>,
+    pub(crate) removed_chips: Vec<String>,
+    pub(crate) changed_chips: Vec<String>,
+    pub(crate) added_algorithms: Vec<String>,
+    pub(crate) removed_algorithms: Vec<String>,
+    pub(crate) changed_algorithms: Vec<String>,
+    /// The pack file release changed, from the old to the new one.
+    pub(crate) pack_file_release: Option<(Option<String>, Option<String>)>,
+}
+
+impl FamilyDiff {
+    /// Compare the chips and flash algorithms of two versions of a family.
+    pub(crate) fn new(old: &ChipFamily, new: &ChipFamily) -> Self {
+        let mut diff = Self::default();
+
+        for chip in &new.variants {
+            match old.variants.iter().find(|old| old.name == chip.name) {
+                None => diff.added_chips.push(chip.name.clone()),
+                Some(old) if old != chip => diff.changed_chips.push(chip.name.clone()),
+                Some(_) => {}
+            }
+        }
+        for chip in &old.variants {
+            if !new.variants.iter().any(|new| new.name == chip.name) {
+                diff.removed_chips.push(chip.name.clone());
+            }
+        }
+
+        for algorithm in &new.flash_algorithms {
+            match old
+                .flash_algorithms
+                .iter()
+                .find(|old| old.name == algorithm.name)
+            {
+                None => diff.added_algorithms.push(algorithm.name.clone()),
+                Some(old) if old != algorithm => {
+                    diff.changed_algorithms.push(algorithm.name.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        for algorithm in &old.flash_algorithms {
+            if !new
+                .flash_algorithms
+                .iter()
+                .any(|new| new.name == algorithm.name)
+            {
+                diff.removed_algorithms.push(algorithm.name.clone());
+            }
+        }
+
+        if old.pack_file_release != new.pack_file_release {
+            diff.pack_file_release =
+                Some((old.pack_file_release.clone(), new.pack_file_release.clone()));
+        }
+
+        diff
+    }
+
+    /// Returns true if the chips and flash algorithms of both versions are the same.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.added_chips.is_empty()
+            && self.removed_chips.is_empty()
+            && self.changed_chips.is_empty()
+            && self.added_algorithms.is_empty()
+            && self.removed_algorithms.is_empty()
+            && self.changed_algorithms.is_empty()
+    }
+}
+
+impl fmt::Display for FamilyDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((old, new)) = &self.pack_file_release {
+            writeln!(
+                f,
+                "pack file release: {} -> {}",
+                old.as_deref().unwrap_or("none"),
+                new.as_deref().unwrap_or("none")
+            )?;
+        }
+
+        let lists = [
+            ("added chips", &self.added_chips),
+            ("removed chips", &self.removed_chips),
+            ("changed chips", &self.changed_chips),
+            ("added algorithms", &self.added_algorithms),
+            ("removed algorithms", &self.removed_algorithms),
+            ("changed algorithms", &self.changed_algorithms),
+        ];
+        for (title, names) in lists {
+            if !names.is_empty() {
+                writeln!(f, "{title}: {}", names.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -145,6 +145,9 @@ where
             cores,
             memory_map: get_mem_map(&device),
             flash_algorithms: flash_algorithm_names,
+            unique_id: None,
+            partitions: vec![],
+            aliases: vec![],
         });
     }
 
@@ -441,6 +444,7 @@ struct DeviceMemory {
     memory_type: MemoryType,
     p_name: Option<String>,
     is_boot_memory: bool,
+    read_only: bool,
     memory_start: u64,
     memory_end: u64,
     name: String,
@@ -475,6 +479,7 @@ pub(crate) fn get_mem_map(device: &Device) -> Vec<MemoryRegion> {
             memory_start: memory.start,
             memory_end: memory.start + memory.size,
             is_boot_memory: memory.startup,
+            read_only: !memory.access.write,
         })
         .collect();
 
@@ -486,7 +491,9 @@ pub(crate) fn get_mem_map(device: &Device) -> Vec<MemoryRegion> {
         let mut merged: Vec<DeviceMemory> = Vec::new();
         let mut cur = device_memories.first().cloned().unwrap();
         for region in device_memories.iter().skip(1) {
-            if region.is_boot_memory == cur.is_boot_memory && region.memory_start == cur.memory_end
+            if region.is_boot_memory == cur.is_boot_memory
+                && region.read_only == cur.read_only
+                && region.memory_start == cur.memory_end
             {
                 // Merge with previous region.
                 cur.memory_end = region.memory_end;
@@ -540,6 +547,7 @@ pub(crate) fn get_mem_map(device: &Device) -> Vec<MemoryRegion> {
                     range: region.memory_start..region.memory_end,
                     is_boot_memory: region.is_boot_memory,
                     cores: vec![current_core],
+                    is_otp: false,
                     }));
                 },
             MemoryType::Generic => if let Some(MemoryRegion::Generic(existing_region)) = mem_map.iter_mut().find(|existing_region|{
@@ -551,6 +559,7 @@ pub(crate) fn get_mem_map(device: &Device) -> Vec<MemoryRegion> {
                     name: Some(region.name.clone()),
                     range: region.memory_start..region.memory_end,
                     cores: vec![current_core],
+                    read_only: region.read_only,
                     }));
                 },
         };
@@ -1,11 +1,14 @@
 pub mod algorithm_binary;
 pub mod commands;
+pub mod diff;
 pub mod fetch;
 pub mod flash_device;
 pub mod generate;
 pub mod parser;
+pub mod stack_usage;
+pub mod validate;
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use clap::Parser;
 use probe_rs::config::ChipFamily;
 use std::{
@@ -19,6 +22,8 @@ use crate::commands::{
     elf::{cmd_elf, serialize_to_yaml_file},
     test::cmd_test,
 };
+use crate::diff::FamilyDiff;
+use crate::validate::{validate_family, Finding, Severity};
 
 #[derive(clap::Parser)]
 enum TargetGen {
@@ -36,6 +41,10 @@ enum TargetGen {
             help = "An output directory where all the generated .yaml files are put in."
         )]
         output_dir: PathBuf,
+        /// Write a report of the changes to existing target descriptions and of the problems found in the generated
+        /// ones to this file.
+        #[clap(long)]
+        report: Option<PathBuf>,
     },
     /// Generates from the entries listed in the ARM root VIDX/PIDX at <https://www.keil.com/pack/Keil.pidx>.
     /// This will only download and generate target descriptions for chip families that are already supported by probe-rs, to avoid generating a lot of unsupportable chip families.
@@ -61,6 +70,10 @@ enum TargetGen {
             help = "An output directory where all the generated .yaml files are put in."
         )]
         output_dir: Option<PathBuf>,
+        /// Write a report of the changes to existing target descriptions and of the problems found in the generated
+        /// ones to this file.
+        #[clap(long)]
+        report: Option<PathBuf>,
     },
     /// Extract a flash algorithm from an ELF file
     Elf {
@@ -99,6 +112,16 @@ enum TargetGen {
         /// The path to the ELF.
         target_artifact: PathBuf,
     },
+    /// Check target descriptions for problems which only show up when flashing, like flash algorithms which do not
+    /// fit into the RAM of a chip, or suspicious sector maps.
+    Validate {
+        /// The target description files, or directories containing them.
+        #[clap(required = true)]
+        targets: Vec<PathBuf>,
+        /// Fail on warnings, not only on errors.
+        #[clap(long)]
+        deny_warnings: bool,
+    },
 }
 
 fn main() -> Result<()> {
@@ -112,7 +135,11 @@ fn main() -> Result<()> {
     let t = std::time::Instant::now();
 
     match options {
-        TargetGen::Pack { input, output_dir } => cmd_pack(&input, &output_dir)?,
+        TargetGen::Pack {
+            input,
+            output_dir,
+            report,
+        } => cmd_pack(&input, &output_dir, report.as_deref())?,
         TargetGen::Elf {
             elf,
             output,
@@ -130,7 +157,8 @@ fn main() -> Result<()> {
             output_dir,
             pack_filter: chip_family,
             list,
-        } => cmd_arm(output_dir, chip_family, list)?,
+            report,
+        } => cmd_arm(output_dir, chip_family, list, report.as_deref())?,
         TargetGen::Test {
             target_artifact,
             template_path,
@@ -140,6 +168,10 @@ fn main() -> Result<()> {
             template_path.as_path(),
             definition_export_path.as_path(),
         )?,
+        TargetGen::Validate {
+            targets,
+            deny_warnings,
+        } => cmd_validate(&targets, deny_warnings)?,
     }
 
     println!("Finished in {:?}", t.elapsed());
@@ -151,7 +183,7 @@ fn main() -> Result<()> {
 /// to a CMSIS-Pack file, or a directory containing at least one .pdsc file.
 ///
 /// The generated target description will be placed in `out_dir`.
-fn cmd_pack(input: &Path, out_dir: &Path) -> Result<()> {
+fn cmd_pack(input: &Path, out_dir: &Path, report: Option<&Path>) -> Result<()> {
     ensure!(
         input.exists(),
         "No such file or directory: {}",
@@ -182,30 +214,17 @@ fn cmd_pack(input: &Path, out_dir: &Path) -> Result<()> {
         );
     }
 
-    let mut generated_files = Vec::with_capacity(families.len());
-
-    for family in &families {
-        let path = out_dir.join(family.name.clone().replace(' ', "_") + ".yaml");
-        let file = std::fs::File::create(&path)
-            .context(format!("Failed to create file '{}'.", path.display()))?;
-
-        serialize_to_yaml_file(family, &file)?;
-
-        generated_files.push(path);
-    }
-
-    println!("Generated {} target definition(s):", generated_files.len());
-
-    for file in generated_files {
-        println!("\t{}", file.display());
-    }
-
-    Ok(())
+    write_families(&families, out_dir, report)
 }
 
 /// Handle the arm subcommand.
 /// Generated target descriptions will be placed in `out_dir`.
-fn cmd_arm(out_dir: Option<PathBuf>, chip_family: Option<String>, list: bool) -> Result<()> {
+fn cmd_arm(
+    out_dir: Option<PathBuf>,
+    chip_family: Option<String>,
+    list: bool,
+    report: Option<&Path>,
+) -> Result<()> {
     if list {
         let mut packs = crate::fetch::get_vidx()?;
         println!("Available ARM CMSIS Pack files:");
@@ -234,14 +253,44 @@ fn cmd_arm(out_dir: Option<PathBuf>, chip_family: Option<String>, list: bool) ->
 
     generate::visit_arm_files(&mut families, chip_family)?;
 
+    write_families(&families, &out_dir, report)
+}
+
+/// Write the target descriptions of `families` to `out_dir`, replacing the existing ones.
+///
+/// The changes to the existing target descriptions, and the problems found in the new ones are printed, and written
+/// to `report` if it is given.
+fn write_families(families: &[ChipFamily], out_dir: &Path, report: Option<&Path>) -> Result<()> {
     let mut generated_files = Vec::with_capacity(families.len());
+    let mut report_text = String::new();
 
-    for family in &families {
+    for family in families {
         let path = out_dir.join(family.name.clone().replace(' ', "_") + ".yaml");
+
+        let status = match std::fs::File::open(&path) {
+            Ok(file) => match serde_yaml::from_reader::<_, ChipFamily>(file) {
+                Ok(old) => {
+                    let diff = FamilyDiff::new(&old, family);
+                    if diff.is_empty() {
+                        "unchanged".to_owned()
+                    } else {
+                        format!("changed\n{diff}")
+                    }
+                }
+                Err(error) => format!("replaced an unreadable file: {error}"),
+            },
+            Err(_) => "new".to_owned(),
+        };
+
         let file = std::fs::File::create(&path)
             .context(format!("Failed to create file '{}'.", path.display()))?;
         serialize_to_yaml_file(family, &file)?;
 
+        report_text += &format!("{}: {status}\n", path.display());
+        for finding in validate_family(family) {
+            report_text += &format!("{finding}\n");
+        }
+
         generated_files.push(path);
     }
 
@@ -251,5 +300,67 @@ fn cmd_arm(out_dir: Option<PathBuf>, chip_family: Option<String>, list: bool) ->
         println!("\t{}", file.display());
     }
 
+    println!();
+    print!("{report_text}");
+
+    if let Some(report) = report {
+        std::fs::write(report, report_text)
+            .context(format!("Failed to write report '{}'.", report.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Handle the validate subcommand.
+fn cmd_validate(targets: &[PathBuf], deny_warnings: bool) -> Result<()> {
+    let mut files = Vec::new();
+    for target in targets {
+        if target.is_dir() {
+            for entry in std::fs::read_dir(target)? {
+                let path = entry?.path();
+                if path
+                    .extension()
+                    .map_or(false, |extension| extension == "yaml")
+                {
+                    files.push(path);
+                }
+            }
+        } else {
+            files.push(target.clone());
+        }
+    }
+    files.sort();
+
+    let mut findings = Vec::<Finding>::new();
+    for path in &files {
+        let file = std::fs::File::open(path)
+            .context(format!("Failed to open file '{}'.", path.display()))?;
+        let family: ChipFamily = serde_yaml::from_reader(file)
+            .context(format!("Failed to parse file '{}'.", path.display()))?;
+
+        let family_findings = validate_family(&family);
+        if !family_findings.is_empty() {
+            println!("{}:", path.display());
+            for finding in &family_findings {
+                println!("\t{finding}");
+            }
+        }
+        findings.extend(family_findings);
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+    println!(
+        "Validated {} target definition(s): {errors} error(s), {warnings} warning(s)",
+        files.len()
+    );
+
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        bail!("The target definitions have problems.");
+    }
+
     Ok(())
 }
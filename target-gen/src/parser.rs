@@ -1,4 +1,7 @@
 use crate::flash_device::FlashDevice;
+use crate::stack_usage::{
+    estimate_stack_usage, StackUsage, DEFAULT_STACK_SIZE, STACK_SIZE_GRANULARITY,
+};
 use probe_rs::config::{FlashProperties, RawFlashAlgorithm, SectorDescription};
 
 use anyhow::{anyhow, Context, Result};
@@ -102,6 +105,33 @@ pub fn extract_flash_algo(
         }
     }
 
+    // Give the algorithm a larger stack if its functions need more than the default stack.
+    let entries = ["Init", "UnInit", "EraseChip", "EraseSector", "ProgramPage"];
+    match estimate_stack_usage(&elf, &buffer, &entries) {
+        Some(StackUsage::Bounded(usage)) if usage > DEFAULT_STACK_SIZE => {
+            let stack_size = (usage + STACK_SIZE_GRANULARITY - 1) / STACK_SIZE_GRANULARITY
+                * STACK_SIZE_GRANULARITY;
+            log::warn!(
+                "The flash algorithm '{}' uses up to {} bytes of stack. Setting its stack size to {} bytes.",
+                file_name.display(),
+                usage,
+                stack_size
+            );
+            algo.stack_size = Some(stack_size);
+        }
+        Some(StackUsage::Bounded(usage)) => {
+            log::debug!("The flash algorithm uses up to {} bytes of stack", usage);
+        }
+        Some(StackUsage::Unknown(reason)) => {
+            log::warn!(
+                "The stack usage of the flash algorithm '{}' can not be estimated: {}. Please check that its stack is large enough.",
+                file_name.display(),
+                reason
+            );
+        }
+        None => {}
+    }
+
     if fixed_load_address {
         log::debug!(
             "Flash algorithm will be loaded at fixed address {:#010x}",
@@ -127,11 +157,23 @@ pub fn extract_flash_algo(
     algo.default = default;
     algo.data_section_offset = algorithm_binary.data_section.start as u64;
 
+    // probe-rs expects the offsets of the sectors from the start of the flash, so the first sector is at offset 0. Some
+    // algorithms, e.g. the ones of the network core of the nRF53, give the sectors relative to another address.
+    let sector_base = flash_device
+        .sectors
+        .first()
+        .map_or(0, |first| first.address);
+    if sector_base != 0 {
+        log::debug!(
+            "The first sector of the flash algorithm is at {:#010x}, converting the sector addresses to offsets",
+            sector_base
+        );
+    }
     let sectors = flash_device
         .sectors
         .iter()
         .map(|si| SectorDescription {
-            address: si.address.into(),
+            address: (si.address - sector_base).into(),
             size: si.size.into(),
         })
         .collect();
@@ -147,6 +189,8 @@ pub fn extract_flash_algo(
         erase_sector_timeout: flash_device.erase_sector_timeout,
 
         sectors,
+        // CMSIS packs do not describe the program granularity.
+        program_granularity: None,
     };
 
     algo.flash_properties = properties;
@@ -0,0 +1,238 @@
+//! Estimate the stack usage of the functions of a flash algorithm.
+//!
+//! The functions of a CMSIS flash algorithm run with the small stack which probe-rs reserves behind the code of the
+//! algorithm, so an algorithm which needs more than that silently corrupts its page buffer while flashing. The stack
+//! usage is estimated from the Thumb code of the ELF file: the frame of a function is the sum of all instructions in
+//! it which grow the stack, and the stack usage of a function is its frame plus the largest stack usage of the
+//! functions it calls. This overestimates the stack usage a bit, but never underestimates it, unless the algorithm
+//! calls functions through pointers or recursively, in which case no estimate is given.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use goblin::elf::header::EM_ARM;
+use goblin::elf::sym::STT_FUNC;
+
+use crate::parser::read_elf_bin_data;
+
+/// The stack size which probe-rs uses when a flash algorithm does not set one.
+pub(crate) const DEFAULT_STACK_SIZE: u32 = 512;
+
+/// The granularity in which probe-rs reduces the stack of a flash algorithm to make room for its page buffer.
+pub(crate) const STACK_SIZE_GRANULARITY: u32 = 64;
+
+/// The estimated stack usage of a function of a flash algorithm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StackUsage {
+    /// The function uses at most this many bytes of stack.
+    Bounded(u32),
+    /// The stack usage can not be estimated.
+    Unknown(String),
+}
+
+struct Function {
+    name: String,
+    range: Range<u32>,
+    frame: u32,
+    calls: Vec<u32>,
+    indirect_calls: bool,
+}
+
+/// Estimate the stack usage of the functions called `entries` in the ELF file of a flash algorithm.
+///
+/// Returns `None` if the ELF file does not contain Thumb code.
+pub(crate) fn estimate_stack_usage(
+    elf: &goblin::elf::Elf<'_>,
+    buffer: &[u8],
+    entries: &[&str],
+) -> Option<StackUsage> {
+    if elf.header.e_machine != EM_ARM {
+        return None;
+    }
+
+    let mut functions = Vec::new();
+    for sym in elf.syms.iter() {
+        if sym.st_type() != STT_FUNC || sym.st_size == 0 {
+            continue;
+        }
+
+        // The lowest bit of the address of a Thumb function is set.
+        let start = sym.st_value as u32 & !1;
+        let size = sym.st_size as u32;
+        let Some(code) = read_elf_bin_data(elf, buffer, start, size) else {
+            continue;
+        };
+
+        let mut function = Function {
+            name: elf.strtab[sym.st_name].to_owned(),
+            range: start..start + size,
+            frame: 0,
+            calls: Vec::new(),
+            indirect_calls: false,
+        };
+        decode_function(&mut function, code);
+        functions.push(function);
+    }
+
+    let mut usage = 0;
+    let mut cache = HashMap::new();
+    for entry in entries {
+        let Some(index) = functions
+            .iter()
+            .position(|function| function.name == *entry)
+        else {
+            continue;
+        };
+        match function_usage(&functions, index, &mut cache, &mut Vec::new()) {
+            StackUsage::Bounded(bytes) => usage = usage.max(bytes),
+            unknown => return Some(unknown),
+        }
+    }
+
+    Some(StackUsage::Bounded(usage))
+}
+
+fn function_usage(
+    functions: &[Function],
+    index: usize,
+    cache: &mut HashMap<usize, StackUsage>,
+    stack: &mut Vec<usize>,
+) -> StackUsage {
+    if let Some(usage) = cache.get(&index) {
+        return usage.clone();
+    }
+
+    let function = &functions[index];
+    if stack.contains(&index) {
+        return StackUsage::Unknown(format!("'{}' is called recursively", function.name));
+    }
+    if function.indirect_calls {
+        return StackUsage::Unknown(format!(
+            "'{}' calls a function through a pointer",
+            function.name
+        ));
+    }
+
+    stack.push(index);
+    let mut callees = 0;
+    let mut usage = None;
+    for &target in &function.calls {
+        // Branches within the function are not calls.
+        if function.range.contains(&target) {
+            continue;
+        }
+
+        let Some(callee) = functions
+            .iter()
+            .position(|function| function.range.contains(&target))
+        else {
+            usage = Some(StackUsage::Unknown(format!(
+                "'{}' calls the unknown function at {target:#010x}",
+                function.name
+            )));
+            break;
+        };
+
+        match function_usage(functions, callee, cache, stack) {
+            StackUsage::Bounded(bytes) => callees = callees.max(bytes),
+            unknown => {
+                usage = Some(unknown);
+                break;
+            }
+        }
+    }
+    stack.pop();
+
+    let usage = usage.unwrap_or(StackUsage::Bounded(function.frame + callees));
+    cache.insert(index, usage.clone());
+    usage
+}
+
+/// Add the stack growing instructions and the calls of the Thumb code of a function to it.
+fn decode_function(function: &mut Function, code: &[u8]) {
+    let halfword = |offset: usize| {
+        code.get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+
+    let mut offset = 0;
+    while let Some(hw1) = halfword(offset) {
+        let address = function.range.start + offset as u32;
+
+        // Instructions starting with 0b11101, 0b11110 or 0b11111 are 32 bits wide.
+        if hw1 >> 11 < 0b11101 {
+            if hw1 & 0xFE00 == 0xB400 {
+                // PUSH {registers}, with LR in bit 8
+                function.frame += 4 * (hw1 & 0x01FF).count_ones();
+            } else if hw1 & 0xFF80 == 0xB080 {
+                // SUB SP, SP, #imm7 * 4
+                function.frame += 4 * (hw1 & 0x007F) as u32;
+            } else if hw1 & 0xFF87 == 0x4780 {
+                // BLX register
+                function.indirect_calls = true;
+            }
+            offset += 2;
+            continue;
+        }
+
+        let Some(hw2) = halfword(offset + 2) else {
+            break;
+        };
+
+        if hw1 == 0xE92D {
+            // PUSH.W {registers}
+            function.frame += 4 * hw2.count_ones();
+        } else if hw1 == 0xF84D && hw2 & 0x0FFF == 0x0D04 {
+            // PUSH.W {register}, encoded as STR Rt, [SP, #-4]!
+            function.frame += 4;
+        } else if hw1 & 0xFBEF == 0xF1AD && hw2 & 0x8F00 == 0x0D00 {
+            // SUB.W SP, SP, #const
+            let imm12 =
+                ((hw1 as u32 & 0x0400) << 1) | ((hw2 as u32 & 0x7000) >> 4) | (hw2 as u32 & 0xFF);
+            function.frame += thumb_expand_imm(imm12);
+        } else if hw1 & 0xFBFF == 0xF2AD && hw2 & 0x8F00 == 0x0D00 {
+            // SUBW SP, SP, #imm12
+            function.frame +=
+                ((hw1 as u32 & 0x0400) << 1) | ((hw2 as u32 & 0x7000) >> 4) | (hw2 as u32 & 0xFF);
+        } else if hw1 & 0xFFBF == 0xED2D && hw2 & 0x0E00 == 0x0A00 {
+            // VPUSH {registers}, with the number of words in imm8
+            function.frame += 4 * (hw2 & 0x00FF) as u32;
+        } else if hw1 & 0xF800 == 0xF000 && hw2 & 0x9000 == 0x9000 {
+            // BL and B.W, which is a call if it leaves the function
+            function.calls.push(branch_target(address, hw1, hw2));
+        }
+        offset += 4;
+    }
+}
+
+/// Decode the target of a BL or B.W instruction (encoding T4) at `address`.
+fn branch_target(address: u32, hw1: u16, hw2: u16) -> u32 {
+    let s = (hw1 as u32 >> 10) & 1;
+    let j1 = (hw2 as u32 >> 13) & 1;
+    let j2 = (hw2 as u32 >> 11) & 1;
+    let i1 = !(j1 ^ s) & 1;
+    let i2 = !(j2 ^ s) & 1;
+    let imm10 = hw1 as u32 & 0x03FF;
+    let imm11 = hw2 as u32 & 0x07FF;
+
+    let offset = (s << 24) | (i1 << 23) | (i2 << 22) | (imm10 << 12) | (imm11 << 1);
+    // Sign extend the 25 bit offset.
+    let offset = ((offset << 7) as i32) >> 7;
+
+    address.wrapping_add(4).wrapping_add(offset as u32)
+}
+
+/// Expand a modified immediate constant of a 32-bit Thumb instruction.
+fn thumb_expand_imm(imm12: u32) -> u32 {
+    let imm8 = imm12 & 0xFF;
+    if imm12 >> 10 == 0 {
+        match (imm12 >> 8) & 0b11 {
+            0b00 => imm8,
+            0b01 => imm8 << 16 | imm8,
+            0b10 => imm8 << 24 | imm8 << 8,
+            _ => imm8 << 24 | imm8 << 16 | imm8 << 8 | imm8,
+        }
+    } else {
+        (0x80 | (imm12 & 0x7F)).rotate_right(imm12 >> 7)
+    }
+}
@@ -0,0 +1,330 @@
+//! Check generated target descriptions for mistakes which only show up when flashing.
+
+use std::fmt;
+
+use probe_rs::config::{
+    Chip, ChipFamily, MemoryRange, MemoryRegion, RawFlashAlgorithm, SectorDescription,
+};
+use probe_rs::Architecture;
+
+use crate::stack_usage::{DEFAULT_STACK_SIZE, STACK_SIZE_GRANULARITY};
+
+/// How bad a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    /// The target description looks suspicious, but might work.
+    Warning,
+    /// Flashing with the target description fails, or writes to the wrong places.
+    Error,
+}
+
+/// A problem found in a target description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Finding {
+    pub(crate) severity: Severity,
+    /// The chip or flash algorithm which has the problem.
+    pub(crate) location: String,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}: {}", self.location, self.message)
+    }
+}
+
+/// Check all chips and flash algorithms of a family.
+pub(crate) fn validate_family(family: &ChipFamily) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if let Err(error) = family.validate() {
+        findings.push(Finding {
+            severity: Severity::Error,
+            location: family.name.clone(),
+            message: error,
+        });
+    }
+
+    for algorithm in &family.flash_algorithms {
+        validate_algorithm(algorithm, &mut findings);
+
+        if !family
+            .variants
+            .iter()
+            .any(|chip| chip.flash_algorithms.contains(&algorithm.name))
+        {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                location: format!("algorithm {}", algorithm.name),
+                message: "the algorithm is not used by any chip".to_owned(),
+            });
+        }
+    }
+
+    for chip in &family.variants {
+        for algorithm in &chip.flash_algorithms {
+            if let Some(algorithm) = family
+                .flash_algorithms
+                .iter()
+                .find(|a| &a.name == algorithm)
+            {
+                validate_chip_algorithm(chip, algorithm, &mut findings);
+            }
+        }
+    }
+
+    findings
+}
+
+/// Check the code and the sector map of a flash algorithm.
+fn validate_algorithm(algorithm: &RawFlashAlgorithm, findings: &mut Vec<Finding>) {
+    let location = format!("algorithm {}", algorithm.name);
+    let mut report = |severity, message: String| {
+        findings.push(Finding {
+            severity,
+            location: location.clone(),
+            message,
+        })
+    };
+
+    let code_size = algorithm.instructions.len() as u64;
+    if code_size % 4 != 0 {
+        report(
+            Severity::Error,
+            format!("the code is {code_size} bytes long, which is not a multiple of 4 bytes"),
+        );
+    }
+
+    let entries = [
+        ("Init", algorithm.pc_init),
+        ("UnInit", algorithm.pc_uninit),
+        ("ProgramPage", Some(algorithm.pc_program_page)),
+        ("EraseSector", Some(algorithm.pc_erase_sector)),
+        ("EraseChip", algorithm.pc_erase_all),
+    ];
+    for (name, entry) in entries {
+        if let Some(entry) = entry.filter(|&entry| entry >= code_size) {
+            report(
+                Severity::Error,
+                format!("the entry point of {name} at {entry:#x} is outside of the code"),
+            );
+        }
+    }
+    if algorithm.data_section_offset > code_size {
+        report(
+            Severity::Error,
+            format!(
+                "the data section at {:#x} is outside of the code",
+                algorithm.data_section_offset
+            ),
+        );
+    }
+
+    if let Some(stack_size) = algorithm.stack_size {
+        if stack_size < STACK_SIZE_GRANULARITY {
+            report(
+                Severity::Warning,
+                format!("the stack of {stack_size} bytes is too small, probe-rs uses {STACK_SIZE_GRANULARITY} bytes instead"),
+            );
+        }
+    }
+
+    let properties = &algorithm.flash_properties;
+    let flash_size = properties
+        .address_range
+        .end
+        .saturating_sub(properties.address_range.start);
+    if flash_size == 0 {
+        report(Severity::Error, "the flash is empty".to_owned());
+    }
+
+    let page_size = properties.page_size as u64;
+    if page_size == 0 || page_size % 4 != 0 {
+        report(
+            Severity::Error,
+            format!("the page size of {page_size} bytes is not a multiple of 4 bytes"),
+        );
+    }
+
+    if properties.program_page_timeout == 0 || properties.erase_sector_timeout == 0 {
+        report(Severity::Warning, "a timeout is zero".to_owned());
+    }
+
+    // The sectors are checked relative to the first one, so that a sector map with another base, e.g. with the
+    // absolute addresses of the sectors, is only reported once.
+    let base = properties.sectors.first().map_or(0, |first| first.address);
+    let sectors = properties
+        .sectors
+        .iter()
+        .map(|sector| SectorDescription {
+            address: sector.address.wrapping_sub(base),
+            size: sector.size,
+        })
+        .collect::<Vec<_>>();
+
+    if sectors.is_empty() {
+        report(Severity::Error, "the sector map is empty".to_owned());
+    } else if base != 0 {
+        report(
+            Severity::Error,
+            format!(
+                "the first sector starts at offset {base:#x} instead of at the start of the flash"
+            ),
+        );
+    }
+
+    for (i, sector) in sectors.iter().enumerate() {
+        if sector.size == 0 {
+            report(
+                Severity::Error,
+                format!("the sectors at offset {:#x} are empty", sector.address),
+            );
+            continue;
+        }
+        if i > 0 && sector.address <= sectors[i - 1].address {
+            report(
+                Severity::Error,
+                format!(
+                    "the sectors at offset {:#x} do not follow the sectors at offset {:#x}",
+                    sector.address,
+                    sectors[i - 1].address
+                ),
+            );
+        }
+        if sector.address >= flash_size {
+            report(
+                Severity::Error,
+                format!(
+                    "the sectors at offset {:#x} are outside of the flash of {flash_size:#x} bytes",
+                    sector.address
+                ),
+            );
+        }
+        if page_size != 0 && sector.size % page_size != 0 {
+            report(
+                Severity::Warning,
+                format!(
+                    "the sectors of {:#x} bytes at offset {:#x} do not consist of whole pages of {page_size:#x} bytes",
+                    sector.size, sector.address
+                ),
+            );
+        }
+        if !sector.size.is_power_of_two() {
+            report(
+                Severity::Warning,
+                format!(
+                    "the size of the sectors at offset {:#x} is {:#x} bytes, which is not a power of two",
+                    sector.address, sector.size
+                ),
+            );
+        }
+        if sector.address % sector.size != 0 {
+            report(
+                Severity::Warning,
+                format!(
+                    "the sectors of {:#x} bytes at offset {:#x} are not aligned to their size",
+                    sector.size, sector.address
+                ),
+            );
+        }
+
+        // Every sector description repeats until the next one, or the end of the flash.
+        let end = sectors.get(i + 1).map_or(flash_size, |next| next.address);
+        if end > sector.address && (end - sector.address) % sector.size != 0 {
+            report(
+                Severity::Warning,
+                format!(
+                    "the sectors of {:#x} bytes at offset {:#x} do not fill the space up to offset {end:#x}",
+                    sector.size, sector.address
+                ),
+            );
+        }
+    }
+}
+
+/// Check that a flash algorithm fits into the RAM of a chip, and flashes memory of the chip.
+fn validate_chip_algorithm(
+    chip: &Chip,
+    algorithm: &RawFlashAlgorithm,
+    findings: &mut Vec<Finding>,
+) {
+    let location = format!("chip {}, algorithm {}", chip.name, algorithm.name);
+
+    let flash_range = &algorithm.flash_properties.address_range;
+    if !chip
+        .memory_map
+        .iter()
+        .any(|region| region.range().contains_range(flash_range))
+    {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            location: location.clone(),
+            message: format!(
+                "the flash at {:#010x}..{:#010x} is not part of the memory map",
+                flash_range.start, flash_range.end
+            ),
+        });
+    }
+
+    // The RAM of the algorithm holds a header, which probe-rs puts in front of the code, the code, the stack and at
+    // least one page buffer.
+    let header_size = match chip.cores.first().map(|core| core.core_type.architecture()) {
+        Some(Architecture::Riscv) => 8,
+        _ => 32,
+    };
+    let stack_size = algorithm
+        .stack_size
+        .unwrap_or(DEFAULT_STACK_SIZE)
+        .max(STACK_SIZE_GRANULARITY) as u64;
+    let code_size = header_size + algorithm.instructions.len() as u64;
+    let page_size = algorithm.flash_properties.page_size as u64;
+
+    let available = chip
+        .memory_map
+        .iter()
+        .filter_map(|region| {
+            let MemoryRegion::Ram(ram) = region else {
+                return None;
+            };
+            if !algorithm.cores.is_empty()
+                && !ram.cores.iter().any(|core| algorithm.cores.contains(core))
+            {
+                return None;
+            }
+            let start = match algorithm.load_address {
+                Some(load_address) => load_address
+                    .checked_sub(header_size)
+                    .filter(|start| ram.range.contains(start))?,
+                None => ram.range.start,
+            };
+            Some(ram.range.end - start)
+        })
+        .max()
+        .unwrap_or(0);
+
+    if available < code_size + STACK_SIZE_GRANULARITY as u64 + page_size {
+        findings.push(Finding {
+            severity: Severity::Error,
+            location,
+            message: format!(
+                "the algorithm needs at least {} bytes of RAM for its code, stack and page buffer, but the chip only has {available} bytes for it",
+                code_size + STACK_SIZE_GRANULARITY as u64 + page_size
+            ),
+        });
+    } else if available < code_size + stack_size + page_size {
+        // probe-rs shrinks the stack until a page buffer fits.
+        let reduced = (available - code_size - page_size) / STACK_SIZE_GRANULARITY as u64
+            * STACK_SIZE_GRANULARITY as u64;
+        findings.push(Finding {
+            severity: Severity::Warning,
+            location,
+            message: format!(
+                "the RAM of the chip only fits a stack of {reduced} bytes instead of {stack_size} bytes next to the code and page buffer"
+            ),
+        });
+    }
+}
@@ -29,3 +29,45 @@ fn extract_target_specs() {
         "Generated 4 target definition(s):",
     ));
 }
+
+#[test]
+fn regenerate_target_specs() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let report = temp.path().join("report.txt");
+
+    let mut cmd = Command::cargo_bin("target-gen").unwrap();
+    cmd.arg("pack").arg(NORDIC_SAMPLE_PACK).arg(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains(": new"));
+
+    // Generating the same targets again changes nothing.
+    let mut cmd = Command::cargo_bin("target-gen").unwrap();
+    cmd.arg("pack")
+        .arg(NORDIC_SAMPLE_PACK)
+        .arg(temp.path())
+        .arg("--report")
+        .arg(&report);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains(": unchanged"));
+
+    let report = std::fs::read_to_string(report).unwrap();
+    assert!(report.contains(": unchanged"));
+    assert!(!report.contains(": changed"));
+}
+
+#[test]
+fn validate_target_specs() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("target-gen").unwrap();
+    cmd.arg("pack").arg(NORDIC_SAMPLE_PACK).arg(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("target-gen").unwrap();
+    cmd.arg("validate").arg(temp.path());
+    cmd.assert().success().stdout(predicates::str::contains(
+        "Validated 4 target definition(s): 0 error(s)",
+    ));
+}